@@ -0,0 +1,250 @@
+/**
+ * The OS's reduced-motion and high-contrast settings, watched while the app
+ * runs so the reader can follow them
+ */
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+use crate::logging;
+
+/// How often the OS settings are looked at again; none of the platforms
+/// offers one change notification for both
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The settings last seen, so changes can be told apart
+static CURRENT: Mutex<Option<AccessibilityPreferences>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessibilityPreferences {
+    #[serde(rename = "reducedMotion")]
+    pub reduced_motion: bool,
+    /// High contrast on Windows and Linux, increased contrast on macOS
+    #[serde(rename = "highContrast")]
+    pub high_contrast: bool,
+}
+
+/// The OS settings, or none of them on when they can't be read
+fn detect() -> AccessibilityPreferences {
+    platform::detect().unwrap_or_else(|e| {
+        logging::debug(&format!("Failed to read accessibility settings: {}", e));
+        AccessibilityPreferences::default()
+    })
+}
+
+/// The settings as last seen by the watcher, read now if it hasn't run yet
+pub fn current() -> AccessibilityPreferences {
+    let Ok(mut current) = CURRENT.lock() else {
+        return detect();
+    };
+    *current.get_or_insert_with(detect)
+}
+
+/// Look at the OS settings every few seconds and emit
+/// "accessibility-changed" when they change
+pub fn watch(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(POLL_INTERVAL);
+
+        let detected = detect();
+        let changed = CURRENT
+            .lock()
+            .map(|mut current| current.replace(detected) != Some(detected))
+            .unwrap_or(false);
+        if changed {
+            logging::info(&format!(
+                "Accessibility settings changed: reduced motion {}, high contrast {}",
+                detected.reduced_motion, detected.high_contrast
+            ));
+            let _ = app.emit("accessibility-changed", detected);
+        }
+    });
+}
+
+/// Whether the OS asks for reduced motion and high contrast
+#[tauri::command]
+pub fn detect_accessibility_preferences() -> Result<AccessibilityPreferences, String> {
+    let detected = platform::detect()?;
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = Some(detected);
+    }
+    Ok(detected)
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::ffi::c_void;
+    use windows_sys::Win32::UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETCLIENTAREAANIMATION, SPI_GETHIGHCONTRAST,
+    };
+
+    use super::AccessibilityPreferences;
+
+    pub fn detect() -> Result<AccessibilityPreferences, String> {
+        // "Show animations in Windows" in the accessibility settings
+        let mut animations: i32 = 1;
+        let ok = unsafe {
+            SystemParametersInfoW(
+                SPI_GETCLIENTAREAANIMATION,
+                0,
+                &mut animations as *mut i32 as *mut c_void,
+                0,
+            )
+        };
+        if ok == 0 {
+            return Err("Failed to read the animation setting".to_string());
+        }
+
+        let mut contrast = HIGHCONTRASTW {
+            cbSize: std::mem::size_of::<HIGHCONTRASTW>() as u32,
+            dwFlags: 0,
+            lpszDefaultScheme: std::ptr::null_mut(),
+        };
+        let ok = unsafe {
+            SystemParametersInfoW(
+                SPI_GETHIGHCONTRAST,
+                contrast.cbSize,
+                &mut contrast as *mut HIGHCONTRASTW as *mut c_void,
+                0,
+            )
+        };
+        if ok == 0 {
+            return Err("Failed to read the high contrast setting".to_string());
+        }
+
+        Ok(AccessibilityPreferences {
+            reduced_motion: animations == 0,
+            high_contrast: contrast.dwFlags & HCF_HIGHCONTRASTON != 0,
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void};
+
+    use super::AccessibilityPreferences;
+
+    #[link(name = "AppKit", kind = "framework")]
+    extern "C" {}
+
+    #[link(name = "objc")]
+    extern "C" {
+        fn objc_getClass(name: *const c_char) -> *mut c_void;
+        fn sel_registerName(name: *const c_char) -> *mut c_void;
+        fn objc_msgSend();
+    }
+
+    /// Send a message without arguments to `receiver`
+    unsafe fn send<R>(receiver: *mut c_void, selector: &[u8]) -> R {
+        let send: unsafe extern "C" fn(*mut c_void, *mut c_void) -> R =
+            std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+        send(
+            receiver,
+            sel_registerName(selector.as_ptr() as *const c_char),
+        )
+    }
+
+    pub fn detect() -> Result<AccessibilityPreferences, String> {
+        unsafe {
+            let class = objc_getClass(b"NSWorkspace\0".as_ptr() as *const c_char);
+            if class.is_null() {
+                return Err("NSWorkspace is not available".to_string());
+            }
+            let workspace: *mut c_void = send(class, b"sharedWorkspace\0");
+            if workspace.is_null() {
+                return Err("No shared workspace".to_string());
+            }
+            let reduced_motion: i8 = send(workspace, b"accessibilityDisplayShouldReduceMotion\0");
+            let high_contrast: i8 =
+                send(workspace, b"accessibilityDisplayShouldIncreaseContrast\0");
+            Ok(AccessibilityPreferences {
+                reduced_motion: reduced_motion != 0,
+                high_contrast: high_contrast != 0,
+            })
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use zbus::blocking::Connection;
+    use zbus::zvariant::{OwnedValue, Value};
+
+    use super::AccessibilityPreferences;
+
+    const SERVICE: &str = "org.freedesktop.portal.Desktop";
+    const PATH: &str = "/org/freedesktop/portal/desktop";
+    const INTERFACE: &str = "org.freedesktop.portal.Settings";
+
+    /// A setting from the desktop portal, None when it doesn't have it
+    fn read(connection: &Connection, namespace: &str, key: &str) -> Option<OwnedValue> {
+        connection
+            .call_method(
+                Some(SERVICE),
+                PATH,
+                Some(INTERFACE),
+                "Read",
+                &(namespace, key),
+            )
+            .ok()?
+            .body()
+            .deserialize()
+            .ok()
+    }
+
+    /// Older portals wrap the value in a second variant
+    fn unwrap<'a>(mut value: &'a Value<'a>) -> &'a Value<'a> {
+        while let Value::Value(inner) = value {
+            value = inner;
+        }
+        value
+    }
+
+    fn read_bool(connection: &Connection, namespace: &str, key: &str) -> Option<bool> {
+        let value = read(connection, namespace, key)?;
+        match unwrap(&value) {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn detect() -> Result<AccessibilityPreferences, String> {
+        let connection = Connection::session()
+            .map_err(|e| format!("Failed to connect to the session bus: {}", e))?;
+
+        // 1 asks for higher contrast; GNOME's own switch for older portals
+        let contrast = read(&connection, "org.freedesktop.appearance", "contrast")
+            .is_some_and(|value| matches!(unwrap(&value), Value::U32(1)));
+        let high_contrast = contrast
+            || read_bool(
+                &connection,
+                "org.gnome.desktop.a11y.interface",
+                "high-contrast",
+            )
+            .unwrap_or(false);
+        let animations = read_bool(
+            &connection,
+            "org.gnome.desktop.interface",
+            "enable-animations",
+        )
+        .unwrap_or(true);
+
+        Ok(AccessibilityPreferences {
+            reduced_motion: !animations,
+            high_contrast,
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    use super::AccessibilityPreferences;
+
+    pub fn detect() -> Result<AccessibilityPreferences, String> {
+        Err("Accessibility settings are not supported on this platform".to_string())
+    }
+}
@@ -0,0 +1,197 @@
+/**
+ * Per-day reading activity for the yearly heatmap
+ */
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tauri::State;
+
+use crate::sessions::{self, ReadingSession, SessionManager};
+use crate::state::AppState;
+
+/// Pages are counted as this many words
+pub const WORDS_PER_PAGE: f64 = 275.0;
+
+/// Size and modification time of the sessions log; any change to it makes
+/// cached heatmaps stale
+type DataVersion = (u64, Option<SystemTime>);
+type HeatmapCache = HashMap<(i32, i32), (DataVersion, Accumulator)>;
+
+static HEATMAP_CACHE: Mutex<Option<HeatmapCache>> = Mutex::new(None);
+
+/// One entry per day of the year in each array, January 1st first
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ActivityHeatmap {
+    pub year: i32,
+    pub minutes: Vec<u32>,
+    pub words: Vec<u32>,
+    pub pages: Vec<u32>,
+    /// Distinct books read that day
+    pub books: Vec<u16>,
+}
+
+#[derive(Clone, Default)]
+struct Accumulator {
+    minutes: Vec<f64>,
+    words: Vec<f64>,
+    books: HashSet<(usize, String)>,
+}
+
+impl Accumulator {
+    fn new(days: usize) -> Self {
+        Self {
+            minutes: vec![0.0; days],
+            words: vec![0.0; days],
+            books: HashSet::new(),
+        }
+    }
+
+    /// Spread a session over the local days it spans, in proportion to the
+    /// time spent in each
+    fn add(&mut self, session: &ReadingSession, year: i32, offset: &FixedOffset) {
+        let start = session.start.with_timezone(offset);
+        let end = session
+            .end
+            .unwrap_or(session.last_activity)
+            .max(session.start)
+            .with_timezone(offset);
+        let total = (end - start).num_milliseconds().max(1) as f64;
+        let minutes = session.active_seconds as f64 / 60.0;
+        let words = session.words.unwrap_or(0) as f64;
+
+        let mut day_start = start;
+        while day_start <= end {
+            let date = day_start.date_naive();
+            let next_day = date
+                .succ_opt()
+                .and_then(|d| {
+                    offset
+                        .from_local_datetime(&d.and_hms_opt(0, 0, 0)?)
+                        .single()
+                })
+                .unwrap_or(end + Duration::milliseconds(1));
+            let day_end = next_day.min(end);
+            let share = if start == end {
+                1.0
+            } else {
+                (day_end - day_start).num_milliseconds() as f64 / total
+            };
+
+            if date.year() == year {
+                let index = date.ordinal0() as usize;
+                self.minutes[index] += minutes * share;
+                self.words[index] += words * share;
+                if share > 0.0 {
+                    self.books.insert((index, session.book_id.clone()));
+                }
+            }
+            if next_day > end {
+                break;
+            }
+            day_start = next_day;
+        }
+    }
+
+    fn finish(self, year: i32) -> ActivityHeatmap {
+        let mut books = vec![0u16; self.minutes.len()];
+        for (index, _) in &self.books {
+            books[*index] = books[*index].saturating_add(1);
+        }
+        ActivityHeatmap {
+            year,
+            minutes: self.minutes.iter().map(|m| m.round() as u32).collect(),
+            words: self.words.iter().map(|w| w.round() as u32).collect(),
+            pages: self
+                .words
+                .iter()
+                .map(|w| (w / WORDS_PER_PAGE).round() as u32)
+                .collect(),
+            books,
+        }
+    }
+}
+
+fn days_in_year(year: i32) -> Option<usize> {
+    let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+    let next = NaiveDate::from_ymd_opt(year + 1, 1, 1)?;
+    Some((next - first).num_days() as usize)
+}
+
+fn accumulate(
+    sessions: &[ReadingSession],
+    year: i32,
+    offset: &FixedOffset,
+    accumulator: &mut Accumulator,
+) {
+    // A day either side, as local days straddle UTC ones
+    let from = Utc
+        .with_ymd_and_hms(year - 1, 12, 31, 0, 0, 0)
+        .single()
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let to = Utc
+        .with_ymd_and_hms(year + 1, 1, 2, 0, 0, 0)
+        .single()
+        .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    for session in sessions {
+        let end = session.end.unwrap_or(session.last_activity);
+        if end >= from && session.start < to {
+            accumulator.add(session, year, offset);
+        }
+    }
+}
+
+/// Minutes read, words and pages advanced and books touched on each day of
+/// `year`. `tz_offset` is the user's offset from UTC in minutes, east
+/// positive (the negation of JavaScript's getTimezoneOffset()).
+#[tauri::command]
+pub fn get_activity_heatmap(
+    state: State<'_, AppState>,
+    session_manager: State<'_, SessionManager>,
+    year: i32,
+    tz_offset: i32,
+) -> Result<ActivityHeatmap, String> {
+    let offset = FixedOffset::east_opt(tz_offset * 60)
+        .ok_or_else(|| format!("Invalid timezone offset: {}", tz_offset))?;
+    let days = days_in_year(year).ok_or_else(|| format!("Invalid year: {}", year))?;
+
+    let path = state.paths()?.sessions.clone();
+    let version: DataVersion = fs::metadata(&path)
+        .map(|m| (m.len(), m.modified().ok()))
+        .unwrap_or((0, None));
+    let key = (year, tz_offset);
+
+    let cached = HEATMAP_CACHE.lock().ok().and_then(|cache| {
+        cache
+            .as_ref()?
+            .get(&key)
+            .filter(|(v, _)| *v == version)
+            .map(|(_, accumulator)| accumulator.clone())
+    });
+    let mut accumulator = match cached {
+        Some(accumulator) => accumulator,
+        None => {
+            let mut accumulator = Accumulator::new(days);
+            accumulate(
+                &sessions::load_sessions(&path),
+                year,
+                &offset,
+                &mut accumulator,
+            );
+            if let Ok(mut cache) = HEATMAP_CACHE.lock() {
+                cache
+                    .get_or_insert_with(HashMap::new)
+                    .insert(key, (version, accumulator.clone()));
+            }
+            accumulator
+        }
+    };
+
+    // The open session isn't in the log yet, but today's reading counts
+    if let Some(open) = sessions::open_session(&session_manager) {
+        accumulate(&[open], year, &offset, &mut accumulator);
+    }
+    Ok(accumulator.finish(year))
+}
@@ -0,0 +1,219 @@
+/**
+ * Power-user settings from advanced.toml (read once at startup)
+ */
+use serde::Serialize;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tauri::State;
+
+use crate::config;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AdvancedConfig {
+    /// Maximum number of entries read from a zip (EPUB, backup or
+    /// annotations archive)
+    #[serde(rename = "zipMaxEntries")]
+    pub zip_max_entries: u64,
+    /// Maximum total uncompressed size of a zip, in megabytes
+    #[serde(rename = "zipMaxUncompressedMb")]
+    pub zip_max_uncompressed_mb: u64,
+    /// Delay before pending progress is written to disk
+    #[serde(rename = "autosaveDebounceMs")]
+    pub autosave_debounce_ms: u64,
+    /// Gaps between reading activity longer than this count as idle time
+    #[serde(rename = "idleThresholdMinutes")]
+    pub idle_threshold_minutes: u64,
+    /// Size cap of the covers cache, in megabytes. Grid thumbnails of the
+    /// books opened longest ago are dropped past it; they are made again
+    /// when next shown.
+    #[serde(rename = "coverCacheMaxMb")]
+    pub cover_cache_max_mb: u64,
+    /// "error", "warn", "info" or "debug"
+    #[serde(rename = "logLevel")]
+    pub log_level: String,
+    /// Modification time of advanced.toml when it was loaded
+    #[serde(skip)]
+    pub loaded_mtime: Option<SystemTime>,
+}
+
+impl Default for AdvancedConfig {
+    fn default() -> Self {
+        Self {
+            zip_max_entries: 10_000,
+            zip_max_uncompressed_mb: 2048,
+            autosave_debounce_ms: 1000,
+            idle_threshold_minutes: 5,
+            cover_cache_max_mb: 512,
+            log_level: "info".to_string(),
+            loaded_mtime: None,
+        }
+    }
+}
+
+pub fn advanced_config_path() -> Result<PathBuf, String> {
+    Ok(config::get_app_dir_path()?.join("advanced.toml"))
+}
+
+/// Current modification time of advanced.toml, if it exists
+pub fn current_mtime() -> Option<SystemTime> {
+    let path = advanced_config_path().ok()?;
+    fs::metadata(path).ok()?.modified().ok()
+}
+
+/// Load advanced.toml. Invalid or unknown keys keep their defaults and are
+/// returned as warnings instead of failing.
+pub fn load_advanced_config() -> (AdvancedConfig, Vec<String>) {
+    let mut config = AdvancedConfig::default();
+    let mut warnings = Vec::new();
+
+    let Ok(path) = advanced_config_path() else {
+        return (config, warnings);
+    };
+    let Ok(content) = fs::read_to_string(&path) else {
+        return (config, warnings); // Absent is the normal case
+    };
+    config.loaded_mtime = current_mtime();
+
+    let table: toml::Table = match content.parse() {
+        Ok(table) => table,
+        Err(e) => {
+            warnings.push(format!("advanced.toml is not valid TOML, using defaults: {}", e));
+            return (config, warnings);
+        }
+    };
+
+    for (key, value) in table {
+        let applied = match key.as_str() {
+            "zip_max_entries" => positive(&value).map(|v| config.zip_max_entries = v),
+            "zip_max_uncompressed_mb" => {
+                positive(&value).map(|v| config.zip_max_uncompressed_mb = v)
+            }
+            "autosave_debounce_ms" => value
+                .as_integer()
+                .filter(|v| (0..=60_000).contains(v))
+                .map(|v| config.autosave_debounce_ms = v as u64),
+            "idle_threshold_minutes" => value
+                .as_integer()
+                .filter(|v| (1..=120).contains(v))
+                .map(|v| config.idle_threshold_minutes = v as u64),
+            "cover_cache_max_mb" => positive(&value).map(|v| config.cover_cache_max_mb = v),
+            "log_level" => value
+                .as_str()
+                .filter(|v| ["error", "warn", "info", "debug"].contains(v))
+                .map(|v| config.log_level = v.to_string()),
+            // epilogue:// links are always handled now
+            "experimental_protocol_handler" => {
+                warnings.push(format!("advanced.toml: '{}' is no longer used", key));
+                continue;
+            }
+            _ => {
+                warnings.push(format!("advanced.toml: unknown key '{}' ignored", key));
+                continue;
+            }
+        };
+
+        if applied.is_none() {
+            warnings.push(format!(
+                "advanced.toml: invalid value for '{}' ({}), using default",
+                key, value
+            ));
+        }
+    }
+
+    (config, warnings)
+}
+
+impl AdvancedConfig {
+    pub fn zip_max_uncompressed_bytes(&self) -> u64 {
+        self.zip_max_uncompressed_mb.saturating_mul(1024 * 1024)
+    }
+
+    /// Refuse a zip with more entries, or more data once expanded, than the
+    /// limits allow, before anything in it is read. `what` names the
+    /// archive in the errors.
+    pub fn check_zip<R: Read + Seek>(
+        &self,
+        archive: &mut zip::ZipArchive<R>,
+        what: &str,
+    ) -> Result<(), String> {
+        if archive.len() as u64 > self.zip_max_entries {
+            return Err(format!(
+                "{} has {} files, more than the limit of {}",
+                what,
+                archive.len(),
+                self.zip_max_entries
+            ));
+        }
+        let mut uncompressed = 0u64;
+        for idx in 0..archive.len() {
+            let entry = archive
+                .by_index_raw(idx)
+                .map_err(|e| format!("{} is not a valid archive: {}", what, e))?;
+            uncompressed = uncompressed.saturating_add(entry.size());
+            if uncompressed > self.zip_max_uncompressed_bytes() {
+                return Err(format!(
+                    "{} expands to more than {} MB",
+                    what, self.zip_max_uncompressed_mb
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn positive(value: &toml::Value) -> Option<u64> {
+    value.as_integer().filter(|v| *v > 0).map(|v| v as u64)
+}
+
+/// Get the advanced settings in effect (read-only; edit advanced.toml and restart)
+#[tauri::command]
+pub fn get_advanced_config(state: State<'_, AdvancedConfig>) -> Result<AdvancedConfig, String> {
+    Ok(state.inner().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    /// A zip of `count` entries of `size` zero bytes each
+    fn zip_of(count: usize, size: usize) -> zip::ZipArchive<Cursor<Vec<u8>>> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for i in 0..count {
+            zip.start_file(format!("{}.txt", i), SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(&vec![0; size]).unwrap();
+        }
+        zip::ZipArchive::new(zip.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn zips_within_the_limits_pass() {
+        let limits = AdvancedConfig {
+            zip_max_entries: 3,
+            zip_max_uncompressed_mb: 1,
+            ..AdvancedConfig::default()
+        };
+        assert!(limits.check_zip(&mut zip_of(3, 1024), "EPUB").is_ok());
+    }
+
+    #[test]
+    fn zips_over_the_limits_are_refused() {
+        let limits = AdvancedConfig {
+            zip_max_entries: 3,
+            zip_max_uncompressed_mb: 1,
+            ..AdvancedConfig::default()
+        };
+        let too_many = limits.check_zip(&mut zip_of(4, 1), "EPUB").unwrap_err();
+        assert_eq!(too_many, "EPUB has 4 files, more than the limit of 3");
+
+        // Compresses to almost nothing, expands past the limit
+        let too_large = limits
+            .check_zip(&mut zip_of(2, 600 * 1024), "Backup")
+            .unwrap_err();
+        assert_eq!(too_large, "Backup expands to more than 1 MB");
+    }
+}
@@ -0,0 +1,380 @@
+/**
+ * Highlights and notes, stored per book in annotations/<book_id>.json
+ */
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tauri::State;
+
+use crate::config;
+use crate::logging;
+use crate::preflight::{self, PathKind};
+use crate::state::AppState;
+
+/// Most categories in the highlight palette
+const MAX_CATEGORIES: usize = 20;
+const MAX_CATEGORY_LABEL_CHARS: usize = 40;
+
+/// A highlight color and what it means to the user. Highlights refer to
+/// the id, so the color and label can change under them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HighlightCategory {
+    pub id: String,
+    pub label: String,
+    /// "#rgb" or "#rrggbb"
+    pub color: String,
+    /// Left out of exports
+    #[serde(default)]
+    pub private: bool,
+}
+
+/// The palette a fresh install starts with; the first is the default
+pub fn default_categories() -> Vec<HighlightCategory> {
+    [
+        ("yellow", "Yellow", "#ffd54f"),
+        ("green", "Green", "#81c784"),
+        ("blue", "Blue", "#64b5f6"),
+        ("pink", "Pink", "#f48fb1"),
+    ]
+    .into_iter()
+    .map(|(id, label, color)| HighlightCategory {
+        id: id.to_string(),
+        label: label.to_string(),
+        color: color.to_string(),
+        private: false,
+    })
+    .collect()
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+/// Check a palette before it is saved
+pub fn validate_categories(categories: &[HighlightCategory]) -> Result<(), String> {
+    if categories.is_empty() {
+        return Err("The highlight palette needs at least one category".to_string());
+    }
+    if categories.len() > MAX_CATEGORIES {
+        return Err(format!(
+            "The highlight palette has more than {} categories",
+            MAX_CATEGORIES
+        ));
+    }
+    for (i, category) in categories.iter().enumerate() {
+        let valid_id = !category.id.is_empty()
+            && category
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_id {
+            return Err(format!("Invalid highlight category id: '{}'", category.id));
+        }
+        if categories[..i].iter().any(|c| c.id == category.id) {
+            return Err(format!(
+                "Duplicate highlight category id: '{}'",
+                category.id
+            ));
+        }
+        let label = category.label.trim();
+        if label.is_empty() || label.chars().count() > MAX_CATEGORY_LABEL_CHARS {
+            return Err(format!(
+                "Label of highlight category '{}' must be 1 to {} characters",
+                category.id, MAX_CATEGORY_LABEL_CHARS
+            ));
+        }
+        if !is_hex_color(&category.color) {
+            return Err(format!(
+                "Color of highlight category '{}' must be like #ffd54f, got '{}'",
+                category.id, category.color
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The category a highlight is shown in: its own, or the palette's first
+/// when it has none or its category is gone
+pub fn category_of<'a>(
+    highlight: &Highlight,
+    categories: &'a [HighlightCategory],
+) -> Option<&'a HighlightCategory> {
+    highlight
+        .category
+        .as_ref()
+        .and_then(|id| categories.iter().find(|c| c.id == *id))
+        .or_else(|| categories.first())
+}
+
+/// Whether a highlight may go into exports
+pub fn is_exported(highlight: &Highlight, categories: &[HighlightCategory]) -> bool {
+    category_of(highlight, categories).is_none_or(|c| !c.private)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Highlight {
+    pub id: String,
+    pub cfi: String,
+    pub text: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub chapter: Option<String>,
+    /// Copied as a quote rather than highlighted in the reader
+    #[serde(default)]
+    pub quoted: bool,
+    /// Where an imported highlight came from ("kobo", "kindle")
+    #[serde(default)]
+    pub origin: Option<String>,
+    /// The highlight's id at its origin, so re-imports don't duplicate it
+    #[serde(rename = "sourceId", default)]
+    pub source_id: Option<String>,
+    /// Id of its highlight category; none for the palette's default
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl Highlight {
+    pub fn new(cfi: String, text: String, chapter: Option<String>) -> Self {
+        let created_at = Utc::now();
+        let id = format!(
+            "{:x}",
+            md5::compute(format!("{}|{}", cfi, created_at.to_rfc3339()).as_bytes())
+        );
+        Self {
+            id,
+            cfi,
+            text,
+            note: None,
+            chapter,
+            quoted: false,
+            origin: None,
+            source_id: None,
+            category: None,
+            created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct BookAnnotations {
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    #[serde(default)]
+    pub highlights: Vec<Highlight>,
+}
+
+/// Spine index a CFI points into: "epubcfi(/6/4!/...)" is the second
+/// spine item, as spine children are numbered 2, 4, 6...
+pub fn spine_index(cfi: &str) -> Option<usize> {
+    let inner = cfi.strip_prefix("epubcfi(")?.trim_end_matches(')');
+    let step = inner.split('!').next()?.split('/').nth(2)?;
+    let number: usize = step.split(['[', ':']).next()?.parse().ok()?;
+    (number / 2).checked_sub(1)
+}
+
+/// Character offset at the end of a CFI ("...:120"), 0 when there is none.
+/// Range CFIs use the offset of their start.
+pub fn char_offset(cfi: &str) -> usize {
+    let inner = cfi
+        .strip_prefix("epubcfi(")
+        .unwrap_or(cfi)
+        .trim_end_matches(')');
+    let start = inner.split(',').take(2).collect::<Vec<_>>().join("");
+    start
+        .rsplit_once(':')
+        .and_then(|(_, offset)| {
+            offset
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>()
+                .parse()
+                .ok()
+        })
+        .unwrap_or(0)
+}
+
+fn annotations_path(state: &AppState, book_id: &str) -> Result<PathBuf, String> {
+    // Book ids are hex digests, anything else must not reach the filesystem
+    if book_id.is_empty() || !book_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid book id: {}", book_id));
+    }
+    Ok(state.paths()?.annotations.join(format!("{}.json", book_id)))
+}
+
+/// Annotations of a book; a book without any gets an empty set
+pub fn load_annotations(state: &AppState, book_id: &str) -> Result<BookAnnotations, String> {
+    let path = annotations_path(state, book_id)?;
+    if !path.exists() {
+        return Ok(BookAnnotations {
+            book_id: book_id.to_string(),
+            highlights: Vec::new(),
+        });
+    }
+
+    let content =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read annotations: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse annotations: {}", e))
+}
+
+pub fn save_annotations(state: &AppState, annotations: &BookAnnotations) -> Result<(), String> {
+    // Annotations are read-only in safe mode, like the library
+    if config::is_safe_mode() {
+        return Err(config::safe_mode_error().into());
+    }
+
+    let path = annotations_path(state, &annotations.book_id)?;
+    preflight::check_writable(PathKind::Annotations)?;
+
+    let json = serde_json::to_string_pretty(annotations)
+        .map_err(|e| format!("Failed to serialize annotations: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write annotations: {}", e))
+}
+
+/// Add a highlight to a book's annotations
+pub fn add_highlight(state: &AppState, book_id: &str, highlight: Highlight) -> Result<(), String> {
+    let mut annotations = load_annotations(state, book_id)?;
+    annotations.highlights.push(highlight);
+    save_annotations(state, &annotations)
+}
+
+/// A highlight by id, searched for across the books on the shelf. Returns
+/// the book id with it.
+pub fn find_highlight(
+    state: &AppState,
+    highlight_id: &str,
+) -> Result<Option<(String, Highlight)>, String> {
+    let book_ids: Vec<String> =
+        state.with_library(|library| library.books.iter().map(|b| b.id.clone()).collect())?;
+    for book_id in book_ids {
+        // A damaged file of one book shouldn't hide the others' highlights
+        let Ok(annotations) = load_annotations(state, &book_id) else {
+            continue;
+        };
+        if let Some(highlight) = annotations
+            .highlights
+            .into_iter()
+            .find(|h| h.id == highlight_id)
+        {
+            return Ok(Some((book_id, highlight)));
+        }
+    }
+    Ok(None)
+}
+
+/// Add highlights only `incoming` has, by id. Returns how many were added.
+pub fn merge_annotations(local: &mut BookAnnotations, incoming: BookAnnotations) -> usize {
+    let before = local.highlights.len();
+    for highlight in incoming.highlights {
+        if !local.highlights.iter().any(|h| h.id == highlight.id) {
+            local.highlights.push(highlight);
+        }
+    }
+    local
+        .highlights
+        .sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    local.highlights.len() - before
+}
+
+/// Check that a category id given for a highlight is in the palette
+pub fn check_category(state: &AppState, category: Option<&str>) -> Result<(), String> {
+    let Some(category) = category else {
+        return Ok(());
+    };
+    let categories = state.preferences()?.highlight_categories;
+    if !categories.iter().any(|c| c.id == category) {
+        return Err(format!("Unknown highlight category: '{}'", category));
+    }
+    Ok(())
+}
+
+/// Give highlights of categories no longer in the palette the default
+/// category. Returns how many were moved.
+pub fn reassign_removed_categories(
+    state: &AppState,
+    categories: &[HighlightCategory],
+) -> Result<usize, String> {
+    let mut moved = 0;
+    let Ok(entries) = fs::read_dir(&state.paths()?.annotations) else {
+        return Ok(0);
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_file() || path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        let Some(book_id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let mut annotations = match load_annotations(state, book_id) {
+            Ok(annotations) => annotations,
+            Err(e) => {
+                logging::warn(&format!("Skipping annotations of {}: {}", book_id, e));
+                continue;
+            }
+        };
+        let mut changed = 0;
+        for highlight in annotations.highlights.iter_mut() {
+            let removed = highlight
+                .category
+                .as_ref()
+                .is_some_and(|id| !categories.iter().any(|c| c.id == *id));
+            if removed {
+                highlight.category = None;
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            save_annotations(state, &annotations)?;
+            moved += changed;
+        }
+    }
+    Ok(moved)
+}
+
+/// Highlights of a book, in the order they were made; with `category`, only
+/// those shown in that category
+#[tauri::command]
+pub fn get_highlights(
+    state: State<'_, AppState>,
+    book_id: String,
+    category: Option<String>,
+) -> Result<Vec<Highlight>, String> {
+    let book_id = state.resolve_book_id(&book_id);
+    let highlights = load_annotations(&state, &book_id)?.highlights;
+    let Some(category) = category else {
+        return Ok(highlights);
+    };
+    let categories = state.preferences()?.highlight_categories;
+    Ok(highlights
+        .into_iter()
+        .filter(|h| category_of(h, &categories).is_some_and(|c| c.id == category))
+        .collect())
+}
+
+/// Move a highlight to another category; none puts it in the default
+#[tauri::command]
+pub fn set_highlight_category(
+    state: State<'_, AppState>,
+    book_id: String,
+    highlight_id: String,
+    category: Option<String>,
+) -> Result<Highlight, String> {
+    let book_id = state.resolve_book_id(&book_id);
+    check_category(&state, category.as_deref())?;
+    let mut annotations = load_annotations(&state, &book_id)?;
+    let highlight = annotations
+        .highlights
+        .iter_mut()
+        .find(|h| h.id == highlight_id)
+        .ok_or_else(|| format!("Highlight not found: {}", highlight_id))?;
+    highlight.category = category;
+    let highlight = highlight.clone();
+    save_annotations(&state, &annotations)?;
+    Ok(highlight)
+}
@@ -0,0 +1,421 @@
+/**
+ * The whole annotations store as a single archive, for moving highlights
+ * to another machine. Books are matched by content hash, then by title and
+ * author; highlights of books not in the library wait in
+ * annotations/pending/ until the book is imported.
+ */
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+use zip::write::SimpleFileOptions;
+
+use crate::advanced::AdvancedConfig;
+use crate::annotations::{self, BookAnnotations};
+use crate::authors;
+use crate::config;
+use crate::file_access;
+use crate::library::Book;
+use crate::logging;
+use crate::state::AppState;
+
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArchiveBook {
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(rename = "contentHash", default)]
+    pub content_hash: Option<String>,
+    #[serde(default)]
+    pub highlights: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArchiveManifest {
+    #[serde(rename = "formatVersion")]
+    pub format_version: u32,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    pub books: Vec<ArchiveBook>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportStrategy {
+    /// Add the archive's highlights to those already there
+    Merge,
+    /// A book's highlights become the archive's
+    ReplacePerBook,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ArchiveExport {
+    #[serde(rename = "archivePath")]
+    pub archive_path: String,
+    pub books: usize,
+    pub highlights: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ArchiveBookResult {
+    /// The book's id in the archive
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    pub title: Option<String>,
+    /// The library book the highlights went to
+    #[serde(rename = "matchedBookId")]
+    pub matched_book_id: Option<String>,
+    /// "hash" or "title"
+    #[serde(rename = "matchedBy")]
+    pub matched_by: Option<String>,
+    pub imported: usize,
+    #[serde(rename = "skippedDuplicates")]
+    pub skipped_duplicates: usize,
+    /// Not in the library; kept until the book is imported
+    pub pending: bool,
+}
+
+/// Highlights waiting for their book, in annotations/pending/<book_id>.json
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PendingAnnotations {
+    book: ArchiveBook,
+    annotations: BookAnnotations,
+}
+
+fn pending_dir(state: &AppState) -> Result<PathBuf, String> {
+    Ok(state.paths()?.annotations.join("pending"))
+}
+
+/// Title and author compared without case, and authors however written
+fn same_book(book: &Book, title: Option<&str>, author: Option<&str>) -> bool {
+    let (Some(title), Some(author)) = (title, author) else {
+        return false;
+    };
+    book.title.trim().eq_ignore_ascii_case(title.trim())
+        && authors::author_key(&book.author) == authors::author_key(author)
+}
+
+/// The library book an archived book is, and how it was recognized
+fn match_book<'a>(books: &'a [Book], archived: &ArchiveBook) -> Option<(&'a Book, &'static str)> {
+    let by_hash = archived
+        .content_hash
+        .as_ref()
+        .and_then(|hash| books.iter().find(|b| b.content_hash.as_ref() == Some(hash)));
+    if let Some(book) = by_hash {
+        return Some((book, "hash"));
+    }
+    books
+        .iter()
+        .find(|b| same_book(b, archived.title.as_deref(), archived.author.as_deref()))
+        .map(|book| (book, "title"))
+}
+
+/// Add `incoming` to a book's highlights, or replace them. Returns how many
+/// were added and how many were already there, by id or by position and
+/// text.
+fn apply(
+    state: &AppState,
+    book_id: &str,
+    incoming: BookAnnotations,
+    strategy: ImportStrategy,
+) -> Result<(usize, usize), String> {
+    let mut local = annotations::load_annotations(state, book_id)?;
+    if strategy == ImportStrategy::ReplacePerBook {
+        local.highlights.clear();
+    }
+    let mut added = 0;
+    let mut skipped = 0;
+    for highlight in incoming.highlights {
+        let known = local
+            .highlights
+            .iter()
+            .any(|h| h.id == highlight.id || (h.cfi == highlight.cfi && h.text == highlight.text));
+        if known {
+            skipped += 1;
+        } else {
+            local.highlights.push(highlight);
+            added += 1;
+        }
+    }
+    local
+        .highlights
+        .sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    annotations::save_annotations(state, &local)?;
+    Ok((added, skipped))
+}
+
+/// Keep highlights of a book not in the library, merged with any already
+/// waiting for it
+fn stage(state: &AppState, book: ArchiveBook, incoming: BookAnnotations) -> Result<(), String> {
+    let dir = pending_dir(state)?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create pending annotations directory: {}", e))?;
+    let path = dir.join(format!("{}.json", book.book_id));
+    let mut pending = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<PendingAnnotations>(&content).ok())
+        .unwrap_or_else(|| PendingAnnotations {
+            book: book.clone(),
+            annotations: BookAnnotations {
+                book_id: book.book_id.clone(),
+                highlights: Vec::new(),
+            },
+        });
+    annotations::merge_annotations(&mut pending.annotations, incoming);
+    pending.book = book;
+    let json = serde_json::to_string_pretty(&pending)
+        .map_err(|e| format!("Failed to serialize pending annotations: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write pending annotations: {}", e))
+}
+
+/// Give a newly imported book the highlights waiting for it, if any
+pub fn attach_pending(state: &AppState, book: &Book) {
+    let Ok(dir) = pending_dir(state) else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(pending) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<PendingAnnotations>(&content).ok())
+        else {
+            continue;
+        };
+        if match_book(std::slice::from_ref(book), &pending.book).is_none() {
+            continue;
+        }
+        let mut incoming = pending.annotations;
+        incoming.book_id = book.id.clone();
+        match apply(state, &book.id, incoming, ImportStrategy::Merge) {
+            Ok((added, _)) => {
+                logging::info(&format!(
+                    "Attached {} pending highlights to '{}'",
+                    added, book.title
+                ));
+                if let Err(e) = fs::remove_file(&path) {
+                    logging::warn(&format!("Failed to remove pending annotations: {}", e));
+                }
+            }
+            Err(e) => logging::error(&format!(
+                "Failed to attach pending highlights to '{}': {}",
+                book.title, e
+            )),
+        }
+    }
+}
+
+fn create_archive(state: &AppState, dest: &Path) -> Result<ArchiveExport, String> {
+    // A destination ending in .zip is used verbatim, anything else is a folder
+    let archive_path = if dest.extension().and_then(|s| s.to_str()) == Some("zip") {
+        dest.to_path_buf()
+    } else {
+        fs::create_dir_all(dest)
+            .map_err(|e| format!("Failed to create export directory: {}", e))?;
+        dest.join(format!(
+            "epilogue-annotations-{}.zip",
+            Utc::now().format("%Y%m%d-%H%M%S")
+        ))
+    };
+
+    let books = state.with_library(|library| library.books.clone())?;
+    let mut sets = Vec::new();
+    if let Ok(entries) = fs::read_dir(&state.paths()?.annotations) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_file() || path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            let Some(book_id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            match annotations::load_annotations(state, book_id) {
+                Ok(set) if !set.highlights.is_empty() => sets.push(set),
+                Ok(_) => {}
+                Err(e) => logging::warn(&format!("Skipping annotations of {}: {}", book_id, e)),
+            }
+        }
+    }
+
+    let manifest = ArchiveManifest {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now(),
+        books: sets
+            .iter()
+            .map(|set| {
+                let book = books.iter().find(|b| b.id == set.book_id);
+                ArchiveBook {
+                    book_id: set.book_id.clone(),
+                    title: book.map(|b| b.title.clone()),
+                    author: book.map(|b| b.author.clone()),
+                    content_hash: book.and_then(|b| b.content_hash.clone()),
+                    highlights: set.highlights.len(),
+                }
+            })
+            .collect(),
+    };
+
+    let file = File::create(&archive_path)
+        .map_err(|e| format!("Failed to create annotations archive: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+    let mut write = |name: &str, json: String| -> Result<(), String> {
+        zip.start_file(name, options)
+            .map_err(|e| format!("Failed to add {} to archive: {}", name, e))?;
+        zip.write_all(json.as_bytes())
+            .map_err(|e| format!("Failed to add {} to archive: {}", name, e))
+    };
+    write(
+        MANIFEST_NAME,
+        serde_json::to_string_pretty(&manifest)
+            .map_err(|e| format!("Failed to serialize archive manifest: {}", e))?,
+    )?;
+    for set in &sets {
+        write(
+            &format!("annotations/{}.json", set.book_id),
+            serde_json::to_string_pretty(set)
+                .map_err(|e| format!("Failed to serialize annotations: {}", e))?,
+        )?;
+    }
+    zip.finish()
+        .map_err(|e| format!("Failed to finalize annotations archive: {}", e))?;
+
+    Ok(ArchiveExport {
+        archive_path: archive_path.to_string_lossy().to_string(),
+        books: sets.len(),
+        highlights: sets.iter().map(|s| s.highlights.len()).sum(),
+    })
+}
+
+/// A JSON entry of the archive, read no further than `max_bytes` in case
+/// its recorded size lies
+fn read_json<T: serde::de::DeserializeOwned>(
+    archive: &mut zip::ZipArchive<File>,
+    name: &str,
+    max_bytes: u64,
+) -> Result<T, String> {
+    let entry = archive
+        .by_name(name)
+        .map_err(|e| format!("Archive has no {}: {}", name, e))?;
+    let mut data = Vec::new();
+    entry
+        .take(max_bytes)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    serde_json::from_slice(&data).map_err(|e| format!("Failed to parse {}: {}", name, e))
+}
+
+fn import_archive(
+    state: &AppState,
+    limits: &AdvancedConfig,
+    path: &Path,
+    strategy: ImportStrategy,
+) -> Result<Vec<ArchiveBookResult>, String> {
+    if config::is_safe_mode() {
+        return Err(config::safe_mode_error().into());
+    }
+    let file = File::open(path).map_err(|e| format!("Failed to open archive: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Invalid annotations archive: {}", e))?;
+    limits.check_zip(&mut archive, "Annotations archive")?;
+    let max_bytes = limits.zip_max_uncompressed_bytes();
+    let manifest: ArchiveManifest = read_json(&mut archive, MANIFEST_NAME, max_bytes)?;
+    if manifest.format_version > ARCHIVE_FORMAT_VERSION {
+        return Err(format!(
+            "The archive was made by a newer version of Epilogue ({})",
+            manifest.app_version
+        ));
+    }
+
+    let books = state.with_library(|library| library.books.clone())?;
+    let mut results = Vec::new();
+    for archived in manifest.books {
+        // Ids become file names
+        if archived.book_id.is_empty()
+            || !archived.book_id.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            logging::warn(&format!("Skipping invalid book id {}", archived.book_id));
+            continue;
+        }
+        let mut incoming: BookAnnotations = read_json(
+            &mut archive,
+            &format!("annotations/{}.json", archived.book_id),
+            max_bytes,
+        )?;
+        let mut result = ArchiveBookResult {
+            book_id: archived.book_id.clone(),
+            title: archived.title.clone(),
+            matched_book_id: None,
+            matched_by: None,
+            imported: 0,
+            skipped_duplicates: 0,
+            pending: false,
+        };
+
+        match match_book(&books, &archived) {
+            Some((book, matched_by)) => {
+                incoming.book_id = book.id.clone();
+                let (imported, skipped) = apply(state, &book.id, incoming, strategy)?;
+                result.matched_book_id = Some(book.id.clone());
+                result.matched_by = Some(matched_by.to_string());
+                result.imported = imported;
+                result.skipped_duplicates = skipped;
+            }
+            None => {
+                result.imported = incoming.highlights.len();
+                stage(state, archived, incoming)?;
+                result.pending = true;
+            }
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// Write every book's highlights and notes into one archive, with a
+/// manifest naming the books so they can be found on another machine
+#[tauri::command]
+pub async fn export_annotations_archive(
+    app: AppHandle,
+    dest: String,
+) -> Result<ArchiveExport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        create_archive(&app.state::<AppState>(), Path::new(&dest))
+    })
+    .await
+    .map_err(|e| format!("Annotations export task failed: {}", e))?
+}
+
+/// Bring in an archive from export_annotations_archive. `strategy` is
+/// "merge" (the default) or "replace-per-book".
+#[tauri::command]
+pub async fn import_annotations_archive(
+    app: AppHandle,
+    path: String,
+    strategy: Option<ImportStrategy>,
+) -> Result<Vec<ArchiveBookResult>, String> {
+    let path = file_access::check_read_access(&app.state::<AppState>(), &path)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        import_archive(
+            &app.state::<AppState>(),
+            &app.state::<AdvancedConfig>(),
+            &path,
+            strategy.unwrap_or(ImportStrategy::Merge),
+        )
+    })
+    .await
+    .map_err(|e| format!("Annotations import task failed: {}", e))?
+}
@@ -0,0 +1,296 @@
+/**
+ * Native application menu: File, Edit, View and Help
+ */
+use tauri::menu::{IsMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::{AppHandle, Emitter, Listener, Manager, WebviewWindow};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::fullscreen;
+use crate::library;
+use crate::logging;
+use crate::preferences::{self, MAX_WINDOW_ZOOM, MIN_WINDOW_ZOOM};
+use crate::state::AppState;
+use crate::tray;
+use crate::update;
+
+/// Menu ids are prefixed so they never collide with the tray menu's
+const ID_PREFIX: &str = "app-";
+const RECENT_LIMIT: usize = 10;
+const ZOOM_STEP: f64 = 0.1;
+
+/// Install the menu and keep Open Recent in step with the library
+pub fn init(app: &AppHandle) {
+    refresh(app);
+    apply_zoom(app);
+
+    let handle = app.clone();
+    app.listen_any("library-changed", move |_| refresh(&handle));
+    app.on_menu_event(|app, event| {
+        if let Some(id) = event.id().as_ref().strip_prefix(ID_PREFIX) {
+            handle_menu_event(app, id);
+        }
+    });
+}
+
+/// Rebuild the whole menu; called when recents or shortcuts change
+pub fn refresh(app: &AppHandle) {
+    match build_menu(app).and_then(|menu| app.set_menu(menu)) {
+        Ok(_) => {}
+        Err(e) => logging::warn(&format!("Failed to build application menu: {}", e)),
+    }
+}
+
+/// Our default accelerator, unless the user bound the same keys to one of
+/// their own shortcuts (theirs win)
+fn accelerator(shortcuts: &[String], default: &'static str) -> Option<&'static str> {
+    let wanted = normalize_accelerator(default);
+    if shortcuts.iter().any(|s| normalize_accelerator(s) == wanted) {
+        None
+    } else {
+        Some(default)
+    }
+}
+
+/// "Ctrl + O", "CommandOrControl+o" and "CmdOrCtrl+O" are the same keys
+fn normalize_accelerator(accelerator: &str) -> String {
+    let primary: &[&str] = if cfg!(target_os = "macos") {
+        &["cmd", "command", "super", "meta"]
+    } else {
+        &["ctrl", "control"]
+    };
+
+    let mut parts: Vec<String> = accelerator
+        .split('+')
+        .map(|part| part.trim().to_lowercase())
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.as_str() {
+            "cmdorctrl" | "commandorcontrol" => "cmdorctrl".to_string(),
+            p if primary.contains(&p) => "cmdorctrl".to_string(),
+            _ => part,
+        })
+        .collect();
+    parts.sort();
+    parts.join("+")
+}
+
+fn item(
+    app: &AppHandle,
+    id: &str,
+    text: &str,
+    accelerator: Option<&str>,
+) -> tauri::Result<MenuItem<tauri::Wry>> {
+    MenuItem::with_id(app, format!("{}{}", ID_PREFIX, id), text, true, accelerator)
+}
+
+fn build_menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
+    let shortcuts: Vec<String> = app
+        .state::<AppState>()
+        .preferences()
+        .map(|p| p.shortcuts.into_values().collect())
+        .unwrap_or_default();
+    let keys = |default| accelerator(&shortcuts, default);
+
+    let recent = Submenu::new(app, "Open Recent", true)?;
+    let books =
+        library::recent_books(&app.state::<AppState>(), RECENT_LIMIT, false).unwrap_or_default();
+    if books.is_empty() {
+        recent.append(&MenuItem::with_id(
+            app,
+            format!("{}recent-none", ID_PREFIX),
+            "No recent books",
+            false,
+            None::<&str>,
+        )?)?;
+    }
+    for book in books {
+        recent.append(&item(
+            app,
+            &format!("recent:{}", book.id),
+            &tray::truncate_title(&book.title),
+            None,
+        )?)?;
+    }
+
+    let file = Submenu::with_items(
+        app,
+        "File",
+        true,
+        &[
+            &item(app, "open", "Open…", keys("CmdOrCtrl+O"))?,
+            &recent,
+            &item(
+                app,
+                "import-folder",
+                "Import Folder…",
+                keys("CmdOrCtrl+Shift+O"),
+            )?,
+            &PredefinedMenuItem::separator(app)?,
+            &item(app, "close-window", "Close Window", keys("CmdOrCtrl+W"))?,
+        ],
+    )?;
+
+    let edit = Submenu::with_items(
+        app,
+        "Edit",
+        true,
+        &[
+            &PredefinedMenuItem::undo(app, None)?,
+            &PredefinedMenuItem::redo(app, None)?,
+            &PredefinedMenuItem::separator(app)?,
+            &PredefinedMenuItem::cut(app, None)?,
+            &PredefinedMenuItem::copy(app, None)?,
+            &PredefinedMenuItem::paste(app, None)?,
+            &PredefinedMenuItem::select_all(app, None)?,
+        ],
+    )?;
+
+    let fullscreen_keys = if cfg!(target_os = "macos") {
+        "Ctrl+Cmd+F"
+    } else {
+        "F11"
+    };
+    let view = Submenu::with_items(
+        app,
+        "View",
+        true,
+        &[
+            &item(
+                app,
+                "fullscreen",
+                "Toggle Fullscreen",
+                keys(fullscreen_keys),
+            )?,
+            &PredefinedMenuItem::separator(app)?,
+            &item(app, "zoom-in", "Zoom In", keys("CmdOrCtrl+="))?,
+            &item(app, "zoom-out", "Zoom Out", keys("CmdOrCtrl+-"))?,
+            &item(app, "zoom-reset", "Actual Size", keys("CmdOrCtrl+0"))?,
+        ],
+    )?;
+
+    let help = Submenu::with_items(
+        app,
+        "Help",
+        true,
+        &[
+            &item(app, "open-logs", "Open Logs Folder", None)?,
+            &item(app, "check-updates", "Check for Updates…", None)?,
+        ],
+    )?;
+
+    // macOS puts the first submenu under the app's name
+    #[cfg(target_os = "macos")]
+    let app_submenu = Submenu::with_items(
+        app,
+        "Epilogue",
+        true,
+        &[
+            &PredefinedMenuItem::about(app, None, None)?,
+            &PredefinedMenuItem::separator(app)?,
+            &PredefinedMenuItem::hide(app, None)?,
+            &PredefinedMenuItem::hide_others(app, None)?,
+            &PredefinedMenuItem::show_all(app, None)?,
+            &PredefinedMenuItem::separator(app)?,
+            &PredefinedMenuItem::quit(app, None)?,
+        ],
+    )?;
+
+    let mut submenus: Vec<&dyn IsMenuItem<tauri::Wry>> = Vec::new();
+    #[cfg(target_os = "macos")]
+    submenus.push(&app_submenu);
+    submenus.extend([&file as &dyn IsMenuItem<tauri::Wry>, &edit, &view, &help]);
+
+    Menu::with_items(app, &submenus)
+}
+
+/// The window menu actions apply to
+fn focused_window(app: &AppHandle) -> Option<WebviewWindow> {
+    app.webview_windows()
+        .into_values()
+        .find(|w| w.is_focused().unwrap_or(false))
+        .or_else(|| app.get_webview_window("main"))
+}
+
+fn handle_menu_event(app: &AppHandle, id: &str) {
+    match id {
+        "open" => emit_to_focused(app, "menu-open"),
+        "import-folder" => emit_to_focused(app, "menu-import-folder"),
+        "close-window" => {
+            if let Some(window) = focused_window(app) {
+                let _ = window.close();
+            }
+        }
+        "fullscreen" => {
+            if let Some(window) = focused_window(app) {
+                if let Err(e) = fullscreen::toggle_fullscreen(app.clone(), window.label().into()) {
+                    logging::warn(&e);
+                }
+            }
+        }
+        "zoom-in" => change_zoom(app, |zoom| zoom + ZOOM_STEP),
+        "zoom-out" => change_zoom(app, |zoom| zoom - ZOOM_STEP),
+        "zoom-reset" => change_zoom(app, |_| 1.0),
+        "open-logs" => open_logs_folder(app),
+        "check-updates" => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Ok(info) = update::check_for_updates().await {
+                    let _ = app.emit("update-check-result", info);
+                }
+            });
+        }
+        _ => {
+            if let Some(book_id) = id.strip_prefix("recent:") {
+                tray::open_recent(app, book_id);
+            }
+        }
+    }
+}
+
+fn emit_to_focused(app: &AppHandle, event: &str) {
+    if let Some(window) = focused_window(app) {
+        let _ = app.emit_to(window.label(), event, ());
+    }
+}
+
+/// Apply the saved zoom to every window
+pub fn apply_zoom(app: &AppHandle) {
+    let zoom = app
+        .state::<AppState>()
+        .preferences()
+        .map(|p| p.window_zoom)
+        .unwrap_or(1.0);
+    for window in app.webview_windows().values() {
+        let _ = window.set_zoom(zoom);
+    }
+}
+
+fn change_zoom(app: &AppHandle, f: impl FnOnce(f64) -> f64) {
+    let state = app.state::<AppState>();
+    let Ok(mut prefs) = state.preferences() else {
+        return;
+    };
+
+    // Round to whole steps so repeated zooming doesn't drift
+    let zoom = (f(prefs.window_zoom) * 10.0).round() / 10.0;
+    prefs.window_zoom = zoom.clamp(MIN_WINDOW_ZOOM, MAX_WINDOW_ZOOM);
+
+    match preferences::save_preferences(&state, prefs.clone()) {
+        Ok(()) => {
+            apply_zoom(app);
+            let _ = app.emit("preferences-changed", prefs);
+        }
+        Err(e) => logging::warn(&format!("Failed to save zoom: {}", e)),
+    }
+}
+
+fn open_logs_folder(app: &AppHandle) {
+    let result = logging::logs_dir().and_then(|dir| {
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        app.opener()
+            .open_path(dir.to_string_lossy(), None::<&str>)
+            .map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        logging::warn(&format!("Failed to open logs folder: {}", e));
+    }
+}
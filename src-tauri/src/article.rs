@@ -0,0 +1,722 @@
+/**
+ * Read later: web articles saved as single-chapter EPUBs in the library
+ */
+use chrono::Utc;
+use ego_tree::NodeRef;
+use scraper::{ElementRef, Html, Node, Selector};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use zip::write::SimpleFileOptions;
+
+use crate::advanced::AdvancedConfig;
+use crate::epub;
+use crate::library::{self, Book};
+use crate::logging;
+use crate::state::AppState;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// Most the page and its images may add up to
+const MAX_DOWNLOAD_BYTES: usize = 20 * 1024 * 1024;
+/// Less extracted text than this isn't an article
+const MIN_ARTICLE_CHARS: usize = 500;
+/// Tag added to every saved article
+const ARTICLE_TAG: &str = "article";
+
+/// Elements dropped with everything inside them
+const DROPPED_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "iframe", "object", "embed", "form", "button",
+    "input", "select", "textarea", "nav", "aside", "footer", "header", "svg", "canvas", "video",
+    "audio", "source",
+];
+/// Elements kept in the chapter; anything else is replaced by its content
+const KEPT_TAGS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "code",
+    "em",
+    "strong",
+    "i",
+    "b",
+    "u",
+    "s",
+    "sub",
+    "sup",
+    "small",
+    "mark",
+    "q",
+    "cite",
+    "abbr",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "a",
+    "br",
+    "hr",
+    "img",
+    "figure",
+    "figcaption",
+    "table",
+    "thead",
+    "tbody",
+    "tfoot",
+    "tr",
+    "th",
+    "td",
+    "caption",
+];
+const VOID_TAGS: &[&str] = &["br", "hr", "img"];
+/// class/id words of content containers
+const POSITIVE_HINTS: &[&str] = &[
+    "article", "body", "content", "entry", "main", "page", "post", "story", "text",
+];
+/// class/id words of page furniture
+const NEGATIVE_HINTS: &[&str] = &[
+    "ad",
+    "banner",
+    "comment",
+    "footer",
+    "menu",
+    "meta",
+    "nav",
+    "newsletter",
+    "popup",
+    "promo",
+    "related",
+    "share",
+    "sidebar",
+    "social",
+    "sponsor",
+    "subscribe",
+    "widget",
+];
+/// Markers publishers put on pages whose text is held back
+const PAYWALL_HINTS: &[&str] = &[
+    "\"isaccessibleforfree\":false",
+    "\"isaccessibleforfree\":\"false\"",
+    "paywall",
+    "subscriber-only",
+    "subscribe to continue",
+];
+
+/// An image the article refers to, downloaded once the text is extracted
+struct ArticleImage {
+    url: String,
+    alt: String,
+}
+
+struct Article {
+    title: String,
+    site_name: String,
+    language: String,
+    /// XHTML body of the chapter, with each image as a placeholder comment
+    content: String,
+    images: Vec<ArticleImage>,
+}
+
+struct EpubImage {
+    file_name: String,
+    media_type: String,
+    data: Vec<u8>,
+}
+
+fn client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(concat!("Epilogue/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("invalid built-in selector")
+}
+
+/// Download a response body, counting it against `budget`
+async fn read_capped(
+    mut response: reqwest::Response,
+    budget: &mut usize,
+) -> Result<Vec<u8>, String> {
+    if response
+        .content_length()
+        .is_some_and(|len| len as usize > *budget)
+    {
+        return Err("Download is too large".to_string());
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Download failed: {}", e))?
+    {
+        if data.len() + chunk.len() > *budget {
+            return Err("Download is too large".to_string());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    *budget -= data.len();
+    Ok(data)
+}
+
+fn content_type(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+async fn fetch_page(url: &reqwest::Url, budget: &mut usize) -> Result<String, String> {
+    let response = client()?
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch the page: {}", e))?;
+    let status = response.status();
+    if matches!(status.as_u16(), 401..=403) {
+        return Err(format!(
+            "The page requires a subscription or sign-in (HTTP {})",
+            status
+        ));
+    }
+    if !status.is_success() {
+        return Err(format!("Failed to fetch the page: HTTP {}", status));
+    }
+    let mime = content_type(&response);
+    if mime != "text/html" && mime != "application/xhtml+xml" {
+        return Err(format!(
+            "Not a web page ({})",
+            if mime.is_empty() {
+                "unknown type"
+            } else {
+                &mime
+            }
+        ));
+    }
+
+    let data = read_capped(response, budget)
+        .await
+        .map_err(|e| format!("Failed to fetch the page: {}", e))?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.chars()
+        // Control characters aren't allowed in XML at all
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .fold(String::with_capacity(text.len()), |mut out, c| {
+            match c {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' => out.push_str("&gt;"),
+                '"' => out.push_str("&quot;"),
+                _ => out.push(c),
+            }
+            out
+        })
+}
+
+fn meta_content(document: &Html, css: &str) -> Option<String> {
+    document
+        .select(&selector(css))
+        .filter_map(|el| el.value().attr("content"))
+        .map(|c| c.trim().to_string())
+        .find(|c| !c.is_empty())
+}
+
+fn element_text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Positive for content-like class names and ids, negative for furniture
+fn class_weight(element: ElementRef) -> f64 {
+    let names = format!(
+        "{} {}",
+        element.value().attr("class").unwrap_or_default(),
+        element.value().attr("id").unwrap_or_default()
+    )
+    .to_lowercase();
+    let words: Vec<&str> = names
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut weight = 0.0;
+    if words.iter().any(|w| POSITIVE_HINTS.contains(w)) {
+        weight += 25.0;
+    }
+    if words.iter().any(|w| NEGATIVE_HINTS.contains(w)) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+/// Share of an element's text that is link text
+fn link_density(element: ElementRef) -> f64 {
+    let text = element_text(element).len();
+    if text == 0 {
+        return 0.0;
+    }
+    let links: usize = element
+        .select(&selector("a"))
+        .map(|a| element_text(a).len())
+        .sum();
+    links as f64 / text as f64
+}
+
+/// The element holding the article text, after Readability: paragraphs
+/// score their parent, and half as much their grandparent
+fn find_content(document: &Html) -> Option<ElementRef<'_>> {
+    let mut scores: HashMap<ego_tree::NodeId, (ElementRef, f64)> = HashMap::new();
+    for paragraph in document.select(&selector("p, pre, td")) {
+        let text = element_text(paragraph);
+        if text.len() < 25 {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (text.len() / 100).min(3) as f64;
+
+        let parent = paragraph.parent().and_then(ElementRef::wrap);
+        let grandparent = parent.and_then(|p| p.parent()).and_then(ElementRef::wrap);
+        for (ancestor, share) in [(parent, 1.0), (grandparent, 0.5)] {
+            let Some(ancestor) = ancestor else { continue };
+            scores
+                .entry(ancestor.id())
+                .or_insert_with(|| (ancestor, class_weight(ancestor)))
+                .1 += score * share;
+        }
+    }
+
+    scores
+        .into_values()
+        .map(|(element, score)| (element, score * (1.0 - link_density(element))))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(element, _)| element)
+}
+
+/// Serialize `node` as XHTML, keeping only simple formatting. Images are
+/// written as placeholders and collected into `images`.
+fn write_node(
+    node: NodeRef<Node>,
+    base: &reqwest::Url,
+    title: &str,
+    out: &mut String,
+    images: &mut Vec<ArticleImage>,
+) {
+    let element = match node.value() {
+        Node::Text(text) => {
+            out.push_str(&escape_xml(text));
+            return;
+        }
+        Node::Element(element) => element,
+        _ => return,
+    };
+    let name = element.name();
+    if DROPPED_TAGS.contains(&name) {
+        return;
+    }
+    if let Some(element_ref) = ElementRef::wrap(node) {
+        // Page furniture inside the article, unless it is mostly text
+        if class_weight(element_ref) < 0.0 && link_density(element_ref) > 0.25 {
+            return;
+        }
+        // The title is written as the chapter heading already
+        if name == "h1" && element_text(element_ref) == title {
+            return;
+        }
+    }
+
+    if name == "img" {
+        let src = element
+            .attr("data-src")
+            .or_else(|| element.attr("src"))
+            .filter(|src| !src.starts_with("data:"));
+        if let Some(url) = src.and_then(|src| base.join(src).ok()) {
+            out.push_str(&format!("<!--epilogue-image-{}-->", images.len()));
+            images.push(ArticleImage {
+                url: url.to_string(),
+                alt: element.attr("alt").unwrap_or_default().to_string(),
+            });
+        }
+        return;
+    }
+
+    let kept = KEPT_TAGS.contains(&name);
+    if kept {
+        out.push('<');
+        out.push_str(name);
+        if name == "a" {
+            if let Some(href) = element.attr("href").and_then(|h| base.join(h).ok()) {
+                out.push_str(&format!(" href=\"{}\"", escape_xml(href.as_str())));
+            }
+        }
+        if VOID_TAGS.contains(&name) {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+    }
+    for child in node.children() {
+        write_node(child, base, title, out, images);
+    }
+    if kept {
+        out.push_str(&format!("</{}>", name));
+    } else if matches!(name, "div" | "section" | "article") {
+        out.push('\n');
+    }
+}
+
+fn extract_article(html: &str, url: &reqwest::Url) -> Result<Article, String> {
+    let document = Html::parse_document(html);
+
+    let title = meta_content(&document, "meta[property='og:title']")
+        .or_else(|| {
+            document
+                .select(&selector("title"))
+                .map(element_text)
+                .find(|t| !t.is_empty())
+        })
+        .unwrap_or_else(|| url.to_string());
+    let site_name = meta_content(&document, "meta[property='og:site_name']")
+        .or_else(|| {
+            url.host_str()
+                .map(|h| h.trim_start_matches("www.").to_string())
+        })
+        .unwrap_or_else(|| "Unknown".to_string());
+    let language = document
+        .select(&selector("html"))
+        .find_map(|el| el.value().attr("lang"))
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .unwrap_or_else(|| "en".to_string());
+
+    let content = find_content(&document);
+    let text_len = content.map(|c| element_text(c).len()).unwrap_or(0);
+    if text_len < MIN_ARTICLE_CHARS {
+        let page = html.to_lowercase().replace(' ', "");
+        if PAYWALL_HINTS
+            .iter()
+            .any(|hint| page.contains(&hint.replace(' ', "")))
+        {
+            return Err("This article appears to be paywalled".to_string());
+        }
+        return Err("Couldn't find an article on this page".to_string());
+    }
+
+    let mut body = String::new();
+    let mut images = Vec::new();
+    if let Some(content) = content {
+        for child in content.children() {
+            write_node(child, url, &title, &mut body, &mut images);
+        }
+    }
+
+    Ok(Article {
+        title,
+        site_name,
+        language,
+        content: body,
+        images,
+    })
+}
+
+fn image_extension(media_type: &str) -> Option<&'static str> {
+    match media_type {
+        "image/jpeg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "image/svg+xml" => Some("svg"),
+        _ => None,
+    }
+}
+
+async fn download_image(url: &str, budget: &mut usize) -> Result<(Vec<u8>, String), String> {
+    let response = client()?
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Download failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Download failed: HTTP {}", response.status()));
+    }
+    let media_type = content_type(&response);
+    if image_extension(&media_type).is_none() {
+        return Err(format!("Unsupported image type {}", media_type));
+    }
+    Ok((read_capped(response, budget).await?, media_type))
+}
+
+/// Download the article's images and put them in place of their
+/// placeholders. Images that fail or don't fit the size cap are left out.
+async fn download_images(article: &mut Article, budget: &mut usize) -> Vec<EpubImage> {
+    let mut downloaded = Vec::new();
+    for (index, image) in article.images.iter().enumerate() {
+        let placeholder = format!("<!--epilogue-image-{}-->", index);
+        let tag = match download_image(&image.url, budget).await {
+            Ok((data, media_type)) => {
+                let file_name = format!(
+                    "images/image-{}.{}",
+                    index,
+                    image_extension(&media_type).unwrap_or("img")
+                );
+                let tag = format!(
+                    "<img src=\"{}\" alt=\"{}\"/>",
+                    file_name,
+                    escape_xml(&image.alt)
+                );
+                downloaded.push(EpubImage {
+                    file_name,
+                    media_type,
+                    data,
+                });
+                tag
+            }
+            Err(e) => {
+                logging::debug(&format!("Skipping article image {}: {}", image.url, e));
+                String::new()
+            }
+        };
+        article.content = article.content.replace(&placeholder, &tag);
+    }
+    downloaded
+}
+
+fn chapter_xhtml(article: &Article, url: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{lang}" lang="{lang}">
+<head>
+<title>{title}</title>
+</head>
+<body>
+<h1>{title}</h1>
+<p><a href="{url}">{site}</a></p>
+{content}
+</body>
+</html>
+"#,
+        lang = escape_xml(&article.language),
+        title = escape_xml(&article.title),
+        url = escape_xml(url),
+        site = escape_xml(&article.site_name),
+        content = article.content,
+    )
+}
+
+fn nav_xhtml(article: &Article) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+<title>{title}</title>
+</head>
+<body>
+<nav epub:type="toc" id="toc">
+<ol>
+<li><a href="article.xhtml">{title}</a></li>
+</ol>
+</nav>
+</body>
+</html>
+"#,
+        title = escape_xml(&article.title),
+    )
+}
+
+/// EPUB 2 table of contents, for readers that don't use the nav document
+fn toc_ncx(article: &Article, identifier: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+<head>
+<meta name="dtb:uid" content="{identifier}"/>
+</head>
+<docTitle><text>{title}</text></docTitle>
+<navMap>
+<navPoint id="article" playOrder="1">
+<navLabel><text>{title}</text></navLabel>
+<content src="article.xhtml"/>
+</navPoint>
+</navMap>
+</ncx>
+"#,
+        identifier = escape_xml(identifier),
+        title = escape_xml(&article.title),
+    )
+}
+
+fn content_opf(article: &Article, url: &str, identifier: &str, images: &[EpubImage]) -> String {
+    let image_items: String = images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| {
+            format!(
+                "<item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                index, image.file_name, image.media_type
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="book-id">{identifier}</dc:identifier>
+<dc:title>{title}</dc:title>
+<dc:creator>{author}</dc:creator>
+<dc:language>{lang}</dc:language>
+<dc:source>{url}</dc:source>
+<meta property="dcterms:modified">{modified}</meta>
+</metadata>
+<manifest>
+<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+<item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+<item id="article" href="article.xhtml" media-type="application/xhtml+xml"/>
+{image_items}</manifest>
+<spine toc="ncx">
+<itemref idref="article"/>
+</spine>
+</package>
+"#,
+        identifier = escape_xml(identifier),
+        title = escape_xml(&article.title),
+        author = escape_xml(&article.site_name),
+        lang = escape_xml(&article.language),
+        url = escape_xml(url),
+        modified = Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+    )
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+fn write_epub(
+    path: &Path,
+    article: &Article,
+    url: &str,
+    images: &[EpubImage],
+) -> Result<(), String> {
+    let identifier = format!("urn:epilogue:article:{:x}", md5::compute(url.as_bytes()));
+    let file = fs::File::create(path).map_err(|e| format!("Failed to create EPUB: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let stored = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default();
+
+    // The mimetype must come first, uncompressed
+    let entries: Vec<(String, Vec<u8>, SimpleFileOptions)> = [
+        (
+            "mimetype".to_string(),
+            b"application/epub+zip".to_vec(),
+            stored,
+        ),
+        (
+            "META-INF/container.xml".to_string(),
+            CONTAINER_XML.as_bytes().to_vec(),
+            deflated,
+        ),
+        (
+            "OEBPS/content.opf".to_string(),
+            content_opf(article, url, &identifier, images).into_bytes(),
+            deflated,
+        ),
+        (
+            "OEBPS/nav.xhtml".to_string(),
+            nav_xhtml(article).into_bytes(),
+            deflated,
+        ),
+        (
+            "OEBPS/toc.ncx".to_string(),
+            toc_ncx(article, &identifier).into_bytes(),
+            deflated,
+        ),
+        (
+            "OEBPS/article.xhtml".to_string(),
+            chapter_xhtml(article, url).into_bytes(),
+            deflated,
+        ),
+    ]
+    .into_iter()
+    .chain(images.iter().map(|image| {
+        (
+            format!("OEBPS/{}", image.file_name),
+            image.data.clone(),
+            stored,
+        )
+    }))
+    .collect();
+
+    for (name, data, options) in entries {
+        zip.start_file(name.as_str(), options)
+            .map_err(|e| format!("Failed to write {} to EPUB: {}", name, e))?;
+        zip.write_all(&data)
+            .map_err(|e| format!("Failed to write {} to EPUB: {}", name, e))?;
+    }
+    zip.finish()
+        .map_err(|e| format!("Failed to finish EPUB: {}", e))?;
+    Ok(())
+}
+
+/// Fetch a web article, extract its text and images, and add it to the
+/// library as an EPUB with the site as author
+#[tauri::command]
+pub async fn save_article_as_epub(app: AppHandle, url: String) -> Result<Book, String> {
+    let url = reqwest::Url::parse(url.trim()).map_err(|e| format!("Invalid URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("Only web pages can be saved".to_string());
+    }
+
+    let mut budget = MAX_DOWNLOAD_BYTES;
+    let html = fetch_page(&url, &mut budget).await?;
+    let mut article = extract_article(&html, &url)?;
+    let images = download_images(&mut article, &mut budget).await;
+
+    let state = app.state::<AppState>();
+    let books_dir = state.paths()?.books.clone();
+    fs::create_dir_all(&books_dir)
+        .map_err(|e| format!("Failed to create books directory: {}", e))?;
+    let path = books_dir.join(format!(
+        "article-{:x}.epub",
+        md5::compute(url.as_str().as_bytes())
+    ));
+    write_epub(&path, &article, url.as_str(), &images)?;
+    if let Err(e) = epub::validate_epub(&path, &app.state::<AdvancedConfig>()) {
+        let _ = fs::remove_file(&path);
+        return Err(format!("Failed to build a valid EPUB: {}", e));
+    }
+
+    library::import_tagged_book(
+        &app,
+        &state,
+        article.title,
+        article.site_name,
+        path.to_string_lossy().to_string(),
+        vec![ARTICLE_TAG.to_string()],
+    )
+}
@@ -0,0 +1,367 @@
+/**
+ * Audiobooks on the shelf: duration, chapters, cover and tags read from
+ * m4b/m4a/mp3 files
+ */
+use chrono::Utc;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+use tauri::{AppHandle, State};
+
+use crate::book_ids;
+use crate::covers;
+use crate::crash;
+use crate::file_access;
+use crate::library::{
+    self, AudioChapter, AudioInfo, Book, CoverSource, LibraryEvent, MediaType, ReadingState,
+};
+use crate::logging;
+use crate::state::AppState;
+
+pub const AUDIOBOOK_EXTENSIONS: &[&str] = &["m4b", "m4a", "mp3"];
+/// Largest moov box read when looking for chapters
+const MAX_MOOV_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+struct AudioMetadata {
+    title: Option<String>,
+    author: Option<String>,
+    duration_secs: f64,
+    cover: Option<(Vec<u8>, String)>,
+}
+
+fn tag_value(revision: &MetadataRevision, keys: &[StandardTagKey]) -> Option<String> {
+    keys.iter().find_map(|key| {
+        revision
+            .tags()
+            .iter()
+            .find(|tag| tag.std_key == Some(*key))
+            .map(|tag| tag.value.to_string().trim().to_string())
+            .filter(|value| !value.is_empty())
+    })
+}
+
+/// Title, author and cover from the file's tags, and the duration of its
+/// first audio track. Files without a frame count in their headers (VBR
+/// mp3 without a Xing header) are measured by walking their packets.
+fn read_metadata(path: &Path) -> Result<AudioMetadata, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open audiobook: {}", e))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| format!("Unsupported audio file: {}", e))?;
+
+    let mut metadata = AudioMetadata::default();
+    // ID3 tags sit in front of the container; MP4 tags inside it
+    let mut revisions = Vec::new();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        revisions.push(revision.clone());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        revisions.push(revision.clone());
+    }
+    for revision in &revisions {
+        metadata.title = metadata.title.or_else(|| {
+            tag_value(
+                revision,
+                &[StandardTagKey::Album, StandardTagKey::TrackTitle],
+            )
+        });
+        metadata.author = metadata.author.or_else(|| {
+            tag_value(
+                revision,
+                &[
+                    StandardTagKey::Artist,
+                    StandardTagKey::AlbumArtist,
+                    StandardTagKey::Composer,
+                ],
+            )
+        });
+        metadata.cover = metadata.cover.or_else(|| {
+            revision
+                .visuals()
+                .first()
+                .map(|visual| (visual.data.to_vec(), visual.media_type.clone()))
+        });
+    }
+
+    let track = probed
+        .format
+        .default_track()
+        .ok_or("The file has no audio track")?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let time_base = params
+        .time_base
+        .or_else(|| params.sample_rate.map(|rate| TimeBase::new(1, rate)))
+        .ok_or("The audio track has no time base")?;
+    let frames = match params.n_frames {
+        Some(frames) => frames,
+        None => {
+            let mut frames = 0;
+            while let Ok(packet) = probed.format.next_packet() {
+                if packet.track_id() == track_id {
+                    frames = packet.ts() + packet.dur();
+                }
+            }
+            frames
+        }
+    };
+    let time = time_base.calc_time(frames);
+    metadata.duration_secs = time.seconds as f64 + time.frac;
+
+    Ok(metadata)
+}
+
+fn read_box_header(file: &mut File) -> Option<(u64, [u8; 4], u64)> {
+    let mut header = [0u8; 8];
+    file.read_exact(&mut header).ok()?;
+    let size = u32::from_be_bytes(header[..4].try_into().ok()?) as u64;
+    let kind: [u8; 4] = header[4..].try_into().ok()?;
+    if size == 1 {
+        let mut large = [0u8; 8];
+        file.read_exact(&mut large).ok()?;
+        return Some((u64::from_be_bytes(large), kind, 16));
+    }
+    Some((size, kind, 8))
+}
+
+/// Find a child box by type in a box's payload
+fn find_child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[offset..offset + 4].try_into().ok()?) as usize;
+        if size < 8 || offset + size > data.len() {
+            return None;
+        }
+        if &data[offset + 4..offset + 8] == kind {
+            return Some(&data[offset + 8..offset + size]);
+        }
+        offset += size;
+    }
+    None
+}
+
+/// Chapters from the Nero chapter list (moov/udta/chpl) most m4b encoders
+/// write. Start times are in units of 100 ns.
+fn read_mp4_chapters(path: &Path) -> Option<Vec<AudioChapter>> {
+    let mut file = File::open(path).ok()?;
+    let moov = loop {
+        let (size, kind, header_len) = read_box_header(&mut file)?;
+        if size < header_len {
+            return None;
+        }
+        if &kind == b"moov" {
+            if size > MAX_MOOV_BYTES {
+                return None;
+            }
+            let mut moov = vec![0u8; (size - header_len) as usize];
+            file.read_exact(&mut moov).ok()?;
+            break moov;
+        }
+        file.seek(SeekFrom::Current((size - header_len) as i64))
+            .ok()?;
+    };
+
+    let chpl = find_child(find_child(&moov, b"udta")?, b"chpl")?;
+    let version = *chpl.first()?;
+    // version, flags, and a reserved word in version 1
+    let mut offset = if version == 1 { 8 } else { 4 };
+    let count = *chpl.get(offset)? as usize;
+    offset += 1;
+
+    let mut chapters = Vec::with_capacity(count);
+    for _ in 0..count {
+        let start = u64::from_be_bytes(chpl.get(offset..offset + 8)?.try_into().ok()?);
+        let title_len = *chpl.get(offset + 8)? as usize;
+        let title = chpl.get(offset + 9..offset + 9 + title_len)?;
+        chapters.push(AudioChapter {
+            title: String::from_utf8_lossy(title).trim().to_string(),
+            start_secs: start as f64 / 10_000_000.0,
+        });
+        offset += 9 + title_len;
+    }
+    Some(chapters)
+}
+
+/// Chapters from ID3v2 CHAP frames
+fn read_mp3_chapters(path: &Path) -> Option<Vec<AudioChapter>> {
+    let tag = id3::Tag::read_from_path(path).ok()?;
+    let mut chapters: Vec<AudioChapter> = tag
+        .chapters()
+        .map(|chapter| AudioChapter {
+            title: chapter
+                .frames
+                .iter()
+                .find(|frame| frame.id() == "TIT2")
+                .and_then(|frame| frame.content().text())
+                .unwrap_or(&chapter.element_id)
+                .to_string(),
+            start_secs: chapter.start_time as f64 / 1000.0,
+        })
+        .collect();
+    chapters.sort_by(|a, b| a.start_secs.total_cmp(&b.start_secs));
+    Some(chapters)
+}
+
+fn read_chapters(path: &Path) -> Vec<AudioChapter> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let chapters = if extension == "mp3" {
+        read_mp3_chapters(path)
+    } else {
+        read_mp4_chapters(path)
+    };
+    let mut chapters = chapters.unwrap_or_default();
+    for (index, chapter) in chapters.iter_mut().enumerate() {
+        if chapter.title.is_empty() {
+            chapter.title = format!("Chapter {}", index + 1);
+        }
+    }
+    chapters
+}
+
+/// Save the cover embedded in an audiobook into the covers directory
+pub fn extract_cover(path: &str, id: &str, covers_dir: &Path) -> Option<String> {
+    let metadata = read_metadata(Path::new(path))
+        .map_err(|e| logging::warn(&e))
+        .ok()?;
+    let (data, media_type) = metadata.cover?;
+    library::save_cover(covers_dir, id, &data, &media_type)
+}
+
+/// Add an audiobook to the library, or refresh its details if it is there
+/// already. Listening progress is kept.
+#[tauri::command]
+pub fn add_audiobook(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<Book, String> {
+    let file_path = file_access::check_read_access(&state, &path)?;
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    if !AUDIOBOOK_EXTENSIONS.contains(&extension.as_str()) {
+        return Err(format!("Unsupported audiobook format: .{}", extension));
+    }
+
+    // Malformed files must not take the app down with them
+    let metadata = crash::catch_panic("reading the audiobook", || read_metadata(&file_path))??;
+    let chapters = crash::catch_panic("reading the chapters", || read_chapters(&file_path))
+        .unwrap_or_else(|e| {
+            logging::error(&format!("{}: {}", e, path));
+            Vec::new()
+        });
+
+    let id = book_ids::path_id(&path);
+    let covers_dir = state.paths()?.covers.clone();
+    let cover_path = metadata
+        .cover
+        .as_ref()
+        .and_then(|(data, media_type)| library::save_cover(&covers_dir, &id, data, media_type));
+    let thumbnail_path = cover_path.as_deref().and_then(covers::thumbnail_of);
+    let title = metadata.title.unwrap_or_else(|| {
+        file_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Untitled".to_string())
+    });
+    let author = metadata.author.unwrap_or_else(|| "Unknown".to_string());
+    let audio = AudioInfo {
+        duration_secs: metadata.duration_secs,
+        position_secs: 0.0,
+        chapters,
+    };
+
+    let event = state.update_library(|library| {
+        if let Some(existing) = library.books.iter_mut().find(|b| b.id == id) {
+            let position = existing.audio.as_ref().map_or(0.0, |a| a.position_secs);
+            existing.title = title;
+            existing.author = author;
+            existing.media_type = MediaType::Audio;
+            existing.missing = false;
+            existing.audio = Some(AudioInfo {
+                position_secs: position.min(audio.duration_secs),
+                ..audio
+            });
+            existing.last_opened = Utc::now();
+            if cover_path.is_some() {
+                existing.thumbnail_path = thumbnail_path;
+                existing.cover_path = cover_path;
+                existing.cover_source = CoverSource::Extracted;
+            }
+            return Ok(LibraryEvent::Updated(existing.clone()));
+        }
+
+        let book = Book {
+            id: id.clone(),
+            title,
+            author,
+            file_path: path.clone(),
+            cover_path,
+            cover_source: CoverSource::Extracted,
+            thumbnail_path,
+            last_opened: Utc::now(),
+            progress: 0.0,
+            cfi: None,
+            positions: Default::default(),
+            reading_location: None,
+            isbn: None,
+            word_count: None,
+            rating: None,
+            review: None,
+            reading_state: ReadingState::ToRead,
+            finished_at: None,
+            completions: Vec::new(),
+            first_opened: Some(Utc::now()),
+            started_at: None,
+            tags: Vec::new(),
+            media_type: MediaType::Audio,
+            audio: Some(audio),
+            description: None,
+            publish_year: None,
+            series: None,
+            series_index: None,
+            series_end: None,
+            language: None,
+            subjects: Vec::new(),
+            open_library_key: None,
+            imported_position: None,
+            overrides: Default::default(),
+            content_hash: None,
+            chapters: None,
+            missing: false,
+            favorite: false,
+            archived: false,
+        };
+        library.books.push(book.clone());
+        Ok(LibraryEvent::Added(book))
+    })?;
+
+    let book = match &event {
+        LibraryEvent::Added(b) | LibraryEvent::Updated(b) => b.clone(),
+        _ => return Err("Unexpected library change".to_string()),
+    };
+    library::emit_library_event(&app, event);
+    Ok(book)
+}
@@ -0,0 +1,296 @@
+/**
+ * Authors across the library: every book by someone, whichever way their
+ * name was written on each book
+ */
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::State;
+
+use crate::library::{Book, ReadingState};
+use crate::sessions;
+use crate::state::AppState;
+
+/// Name parts that stay with the name before them: "King, Jr."
+const NAME_SUFFIXES: &[&str] = &["jr", "jr.", "sr", "sr.", "ii", "iii", "iv", "phd", "md"];
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthorSort {
+    /// A–Z by last name
+    #[default]
+    Name,
+    /// Most books first
+    Count,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AuthorSummary {
+    /// The name as written on most of their books, "First Last"
+    pub name: String,
+    /// "Last, First", for an A–Z index
+    #[serde(rename = "sortName")]
+    pub sort_name: String,
+    #[serde(rename = "bookCount")]
+    pub book_count: usize,
+    pub finished: usize,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct AuthorStats {
+    #[serde(rename = "booksFinished")]
+    pub books_finished: usize,
+    /// Active reading time from sessions
+    #[serde(rename = "totalHours")]
+    pub total_hours: f64,
+    /// Of the books with a rating
+    #[serde(rename = "averageRating")]
+    pub average_rating: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AuthorPage {
+    pub name: String,
+    #[serde(rename = "sortName")]
+    pub sort_name: String,
+    /// Every way the name is written across the books
+    pub variants: Vec<String>,
+    pub books: Vec<Book>,
+    pub stats: AuthorStats,
+    pub series: Vec<String>,
+}
+
+/// A letter without its accent, or the letter itself
+fn fold_char(c: char) -> char {
+    match c {
+        'à'..='å' | 'ā' | 'ă' | 'ą' => 'a',
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => 'c',
+        'ď' | 'đ' => 'd',
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => 'e',
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => 'g',
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => 'i',
+        'ķ' => 'k',
+        'ĺ' | 'ļ' | 'ľ' | 'ł' => 'l',
+        'ñ' | 'ń' | 'ņ' | 'ň' => 'n',
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => 'o',
+        'ŕ' | 'ř' => 'r',
+        'ś' | 'ŝ' | 'ş' | 'š' => 's',
+        'ţ' | 'ť' => 't',
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => 'u',
+        'ý' | 'ÿ' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        _ => c,
+    }
+}
+
+/// Text lowercased and without accents, for matching what was typed
+/// without them: "Brontë" is "bronte"
+pub fn fold(text: &str) -> String {
+    text.to_lowercase().chars().map(fold_char).collect()
+}
+
+fn is_suffix(piece: &str) -> bool {
+    NAME_SUFFIXES.contains(&piece.trim().to_lowercase().as_str())
+}
+
+/// "Last, First" turned around; anything else as it is
+fn first_last(name: &str) -> String {
+    match name.split_once(',') {
+        Some((last, first)) if !first.trim().is_empty() && !is_suffix(first) => {
+            format!("{} {}", first.trim(), last.trim())
+        }
+        _ => name.trim().to_string(),
+    }
+}
+
+/// The people in a book's author field. Authors are separated by ";", "&",
+/// " and " or commas; a single comma after a one-word surname, or before
+/// names with initials, is "Last, First".
+pub fn split_authors(field: &str) -> Vec<String> {
+    let mut authors = Vec::new();
+    let field = field.replace(" and ", ";").replace('&', ";");
+    for part in field.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+        let pieces: Vec<&str> = part.split(',').map(str::trim).collect();
+        let initials = |piece: &str| piece.split_whitespace().any(|w| w.ends_with('.'));
+        match pieces.as_slice() {
+            [last, first] if (!last.contains(' ') || initials(first)) && !is_suffix(first) => {
+                authors.push(first_last(part))
+            }
+            _ => {
+                for piece in pieces.iter().filter(|p| !p.is_empty()) {
+                    match authors.last_mut() {
+                        Some(previous) if is_suffix(piece) => {
+                            *previous = format!("{}, {}", previous, piece)
+                        }
+                        _ => authors.push(piece.to_string()),
+                    }
+                }
+            }
+        }
+    }
+    authors
+}
+
+/// Comparison key of a name: no case, accents or periods, so "J.R.R.
+/// Tolkien", "J. R. R. Tolkien" and "j r r tolkien" are the same person
+pub fn author_key(name: &str) -> String {
+    first_last(name)
+        .to_lowercase()
+        .chars()
+        .map(fold_char)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// "Tolkien, J. R. R." for "J. R. R. Tolkien"
+pub fn sort_name(name: &str) -> String {
+    let (name, suffix) = match name.rsplit_once(',') {
+        Some((name, suffix)) if is_suffix(suffix) => (name, Some(suffix.trim())),
+        _ => (name, None),
+    };
+    let sorted = match name.trim().rsplit_once(' ') {
+        Some((first, last)) => format!("{}, {}", last, first),
+        None => name.trim().to_string(),
+    };
+    match suffix {
+        Some(suffix) => format!("{}, {}", sorted, suffix),
+        None => sorted,
+    }
+}
+
+struct AuthorGroup {
+    /// Raw spellings, in the order first seen, with how many books use each
+    variants: Vec<(String, usize)>,
+    books: Vec<Book>,
+}
+
+impl AuthorGroup {
+    /// The spelling most of their books use
+    fn name(&self) -> String {
+        let mut best: Option<&(String, usize)> = None;
+        for variant in &self.variants {
+            if best.is_none_or(|b| variant.1 > b.1) {
+                best = Some(variant);
+            }
+        }
+        best.map(|(name, _)| name.clone()).unwrap_or_default()
+    }
+}
+
+/// Books grouped by author key; a book with several authors is in each
+fn group_by_author(books: &[Book]) -> HashMap<String, AuthorGroup> {
+    let mut groups: HashMap<String, AuthorGroup> = HashMap::new();
+    for book in books {
+        for author in split_authors(&book.author) {
+            let key = author_key(&author);
+            if key.is_empty() {
+                continue;
+            }
+            let group = groups.entry(key).or_insert_with(|| AuthorGroup {
+                variants: Vec::new(),
+                books: Vec::new(),
+            });
+            match group.variants.iter_mut().find(|(name, _)| *name == author) {
+                Some((_, count)) => *count += 1,
+                None => group.variants.push((author, 1)),
+            }
+            if !group.books.iter().any(|b| b.id == book.id) {
+                group.books.push(book.clone());
+            }
+        }
+    }
+    groups
+}
+
+fn finished_count(books: &[Book]) -> usize {
+    books
+        .iter()
+        .filter(|b| b.reading_state == ReadingState::Finished)
+        .count()
+}
+
+/// Every author in the library with how many books they have
+#[tauri::command]
+pub fn list_authors(
+    state: State<'_, AppState>,
+    sort: Option<AuthorSort>,
+) -> Result<Vec<AuthorSummary>, String> {
+    let books = state.with_library(|library| library.books.clone())?;
+    let mut authors: Vec<AuthorSummary> = group_by_author(&books)
+        .into_values()
+        .map(|group| {
+            let name = group.name();
+            AuthorSummary {
+                sort_name: sort_name(&name),
+                name,
+                book_count: group.books.len(),
+                finished: finished_count(&group.books),
+            }
+        })
+        .collect();
+
+    authors.sort_by(|a, b| a.sort_name.to_lowercase().cmp(&b.sort_name.to_lowercase()));
+    if sort.unwrap_or_default() == AuthorSort::Count {
+        authors.sort_by(|a, b| b.book_count.cmp(&a.book_count));
+    }
+    Ok(authors)
+}
+
+/// An author's books, series and reading stats, by any spelling of their
+/// name
+#[tauri::command]
+pub fn get_author(state: State<'_, AppState>, author_name: String) -> Result<AuthorPage, String> {
+    let key = author_key(&author_name);
+    let books = state.with_library(|library| library.books.clone())?;
+    let mut group = group_by_author(&books)
+        .remove(&key)
+        .ok_or_else(|| format!("No books by '{}' in the library", author_name))?;
+    group.books.sort_by(|a, b| {
+        (
+            a.series.as_deref(),
+            a.series_index.unwrap_or(0.0),
+            a.title.to_lowercase(),
+        )
+            .partial_cmp(&(
+                b.series.as_deref(),
+                b.series_index.unwrap_or(0.0),
+                b.title.to_lowercase(),
+            ))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let active_seconds: i64 = match state.paths() {
+        Ok(paths) => sessions::load_sessions(&paths.sessions)
+            .iter()
+            .filter(|s| group.books.iter().any(|b| b.id == s.book_id))
+            .map(|s| s.active_seconds)
+            .sum(),
+        Err(_) => 0,
+    };
+    let ratings: Vec<u8> = group.books.iter().filter_map(|b| b.rating).collect();
+    let stats = AuthorStats {
+        books_finished: finished_count(&group.books),
+        total_hours: active_seconds as f64 / 3600.0,
+        average_rating: (!ratings.is_empty())
+            .then(|| ratings.iter().map(|r| *r as f32).sum::<f32>() / ratings.len() as f32),
+    };
+
+    let mut series: Vec<String> = Vec::new();
+    for name in group.books.iter().filter_map(|b| b.series.clone()) {
+        if !series.contains(&name) {
+            series.push(name);
+        }
+    }
+
+    let name = group.name();
+    Ok(AuthorPage {
+        sort_name: sort_name(&name),
+        name,
+        variants: group.variants.into_iter().map(|(name, _)| name).collect(),
+        books: group.books,
+        stats,
+        series,
+    })
+}
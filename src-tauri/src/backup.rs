@@ -0,0 +1,923 @@
+/**
+ * Application data backup and restore
+ */
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager, State};
+use zip::write::SimpleFileOptions;
+
+use crate::advanced::AdvancedConfig;
+use crate::config;
+use crate::crash;
+use crate::file_access;
+use crate::library;
+use crate::library_db;
+use crate::logging;
+use crate::preferences;
+use crate::state::AppState;
+use crate::vocabulary;
+
+/// Version of the archive layout itself (manifest + entry naming)
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// Schema versions of the individual data stores written into the manifest
+pub const LIBRARY_SCHEMA_VERSION: u32 = 1;
+pub const PREFERENCES_SCHEMA_VERSION: u32 = 1;
+pub const PRESETS_SCHEMA_VERSION: u32 = 2;
+pub const ANNOTATIONS_SCHEMA_VERSION: u32 = 1;
+pub const STATS_SCHEMA_VERSION: u32 = 1;
+
+pub const MANIFEST_NAME: &str = "manifest.json";
+/// Progress history and the other library tables that aren't books
+const LIBRARY_EXTRAS_NAME: &str = "library_extras.json";
+
+/// Archives with at least this many files report progress to the frontend
+const PROGRESS_THRESHOLD: usize = 50;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupManifest {
+    #[serde(rename = "formatVersion")]
+    pub format_version: u32,
+    #[serde(rename = "appVersion")]
+    pub app_version: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "schemaVersions")]
+    pub schema_versions: BTreeMap<String, u32>,
+    pub categories: Vec<String>,
+    #[serde(rename = "fileCount")]
+    pub file_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupOptions {
+    /// Covers can be regenerated from the EPUBs, so they are optional
+    #[serde(rename = "includeCovers", default = "default_true")]
+    pub include_covers: bool,
+    #[serde(rename = "excludeBooks", default)]
+    pub exclude_books: bool,
+    #[serde(rename = "excludeMedia", default)]
+    pub exclude_media: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            include_covers: true,
+            exclude_books: false,
+            exclude_media: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BackupSummary {
+    #[serde(rename = "archivePath")]
+    pub archive_path: String,
+    pub categories: Vec<String>,
+    #[serde(rename = "fileCount")]
+    pub file_count: usize,
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CategoryResult {
+    pub category: String,
+    /// "restored" or "merged"
+    pub status: String,
+    pub files: usize,
+    /// Files already here that a merge left alone
+    pub skipped: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merge: Option<library::MergeSummary>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RestoreSummary {
+    pub mode: String,
+    #[serde(rename = "snapshotPath")]
+    pub snapshot_path: String,
+    pub categories: Vec<CategoryResult>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct BackupProgress {
+    current: usize,
+    total: usize,
+    file: String,
+}
+
+/// A named group of files inside the app directory
+pub struct Category {
+    pub name: &'static str,
+    /// Paths relative to the app directory; directories are walked recursively
+    pub paths: &'static [&'static str],
+}
+
+/// Every category a backup can contain, in the order they are written
+pub const CATEGORIES: &[Category] = &[
+    // The library lives in library.db; backups carry it as library.json
+    // and library_extras.json
+    Category { name: "library", paths: &["library.json", "library_extras.json"] },
+    Category { name: "preferences", paths: &["preferences.json"] },
+    Category { name: "presets", paths: &["presets"] },
+    Category { name: "annotations", paths: &["annotations"] },
+    Category { name: "stats", paths: &["stats"] },
+    Category { name: "view_state", paths: &["view_state"] },
+    Category { name: "vocabulary", paths: &["vocabulary.json"] },
+    Category { name: "manifests", paths: &["fonts/fonts.json", "media/music/music.json"] },
+    Category { name: "covers", paths: &["cache/covers", "covers"] },
+    Category { name: "books", paths: &["books"] },
+    Category { name: "media", paths: &["media"] },
+];
+
+/// Current schema version of every versioned category
+pub fn schema_versions() -> BTreeMap<String, u32> {
+    let mut versions = BTreeMap::new();
+    versions.insert("library".to_string(), LIBRARY_SCHEMA_VERSION);
+    versions.insert("preferences".to_string(), PREFERENCES_SCHEMA_VERSION);
+    versions.insert("presets".to_string(), PRESETS_SCHEMA_VERSION);
+    versions.insert("annotations".to_string(), ANNOTATIONS_SCHEMA_VERSION);
+    versions.insert("stats".to_string(), STATS_SCHEMA_VERSION);
+    versions
+}
+
+/// Back up all application data into a single timestamped zip archive
+#[tauri::command]
+pub async fn backup_app_data(
+    app: AppHandle,
+    dest: String,
+    options: Option<BackupOptions>,
+) -> Result<BackupSummary, String> {
+    let options = options.unwrap_or_default();
+
+    tauri::async_runtime::spawn_blocking(move || create_backup(&app, Path::new(&dest), &options))
+        .await
+        .map_err(|e| format!("Backup task failed: {}", e))?
+}
+
+pub fn create_backup(
+    app: &AppHandle,
+    dest: &Path,
+    options: &BackupOptions,
+) -> Result<BackupSummary, String> {
+    let app_dir = config::get_app_dir_path()?;
+
+    // A destination ending in .zip is used verbatim, anything else is a folder
+    let archive_path = if dest.extension().and_then(|s| s.to_str()) == Some("zip") {
+        dest.to_path_buf()
+    } else {
+        fs::create_dir_all(dest)
+            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+        dest.join(format!(
+            "epilogue-backup-{}.zip",
+            Utc::now().format("%Y%m%d-%H%M%S")
+        ))
+    };
+
+    // A snapshot of the library as it is in memory, unsaved progress included
+    let state = app.state::<AppState>();
+    let library_json = state
+        .with_library(serde_json::to_vec_pretty)?
+        .map_err(|e| format!("Failed to serialize library: {}", e))?;
+    // The progress history of the page being read is written with the books
+    if let Err(e) = state.flush_library() {
+        logging::warn(&format!(
+            "Failed to save the library before a backup: {}",
+            e
+        ));
+    }
+    let extras_json = serde_json::to_vec_pretty(&state.with_db(library_db::export_extras)?)
+        .map_err(|e| format!("Failed to serialize library extras: {}", e))?;
+
+    // Gather files per category before writing so progress has a total
+    let mut entries: Vec<(String, PathBuf)> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut categories = vec!["library".to_string()];
+
+    for category in CATEGORIES.iter().filter(|c| c.name != "library") {
+        let skip = match category.name {
+            "covers" => !options.include_covers,
+            "books" => options.exclude_books,
+            "media" => options.exclude_media,
+            _ => false,
+        };
+        if skip {
+            continue;
+        }
+
+        let mut found = false;
+        for rel in category.paths {
+            for file in collect_files(&app_dir.join(rel)) {
+                let name = entry_name(&app_dir, &file)?;
+                if seen.insert(name.clone()) {
+                    entries.push((name, file));
+                    found = true;
+                }
+            }
+        }
+        if found {
+            categories.push(category.name.to_string());
+        }
+    }
+
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now(),
+        schema_versions: schema_versions(),
+        categories: categories.clone(),
+        file_count: entries.len() + 2,
+    };
+
+    let file = File::create(&archive_path)
+        .map_err(|e| format!("Failed to create backup archive: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let zip_options = SimpleFileOptions::default().large_file(true);
+
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize backup manifest: {}", e))?;
+    zip.start_file(MANIFEST_NAME, zip_options)
+        .map_err(|e| format!("Failed to write backup manifest: {}", e))?;
+    zip.write_all(manifest_json.as_bytes())
+        .map_err(|e| format!("Failed to write backup manifest: {}", e))?;
+
+    for (name, data) in [
+        ("library.json", &library_json),
+        (LIBRARY_EXTRAS_NAME, &extras_json),
+    ] {
+        zip.start_file(name, zip_options)
+            .map_err(|e| format!("Failed to add {} to backup: {}", name, e))?;
+        zip.write_all(data)
+            .map_err(|e| format!("Failed to add {} to backup: {}", name, e))?;
+    }
+
+    let total = entries.len();
+    let report_every = (total / 100).max(1);
+    let mut total_bytes = (library_json.len() + extras_json.len()) as u64;
+
+    for (idx, (name, path)) in entries.iter().enumerate() {
+        zip.start_file(name.as_str(), zip_options)
+            .map_err(|e| format!("Failed to add {} to backup: {}", name, e))?;
+        let mut source =
+            File::open(path).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        total_bytes += io::copy(&mut source, &mut zip)
+            .map_err(|e| format!("Failed to add {} to backup: {}", name, e))?;
+
+        if total >= PROGRESS_THRESHOLD && (idx % report_every == 0 || idx + 1 == total) {
+            let _ = app.emit(
+                "backup-progress",
+                BackupProgress {
+                    current: idx + 1,
+                    total,
+                    file: name.clone(),
+                },
+            );
+        }
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finalize backup archive: {}", e))?;
+
+    Ok(BackupSummary {
+        archive_path: archive_path.to_string_lossy().to_string(),
+        categories,
+        file_count: total + 2,
+        total_bytes,
+    })
+}
+
+/// Recursively list the files under `path` (or `path` itself if it is a file)
+pub fn collect_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    if path.is_file() {
+        files.push(path.to_path_buf());
+    } else if let Ok(entries) = fs::read_dir(path) {
+        for entry in entries.flatten() {
+            files.extend(collect_files(&entry.path()));
+        }
+    }
+
+    files.sort();
+    files
+}
+
+/// Archive entry name for a file: its path relative to the app dir, using `/`
+fn entry_name(app_dir: &Path, file: &Path) -> Result<String, String> {
+    let rel = file
+        .strip_prefix(app_dir)
+        .map_err(|_| format!("File outside app directory: {}", file.display()))?;
+
+    let parts: Vec<String> = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+
+    Ok(parts.join("/"))
+}
+
+/// Restore application data from a backup archive.
+/// `mode` is "merge" (library and preferences are merged, other files only
+/// added), the default, or "replace" (category contents are swapped out
+/// wholesale). A merge keeps presets of the same name unless `force` is set.
+#[tauri::command]
+pub async fn restore_app_data(
+    app: AppHandle,
+    advanced: State<'_, AdvancedConfig>,
+    path: String,
+    mode: Option<String>,
+    force: Option<bool>,
+) -> Result<RestoreSummary, String> {
+    let path = file_access::check_read_access(&app.state::<AppState>(), &path)?;
+    let limits = advanced.inner().clone();
+    let mode = mode.unwrap_or_else(|| "merge".to_string());
+    let force = force.unwrap_or(false);
+    let app_dir = config::get_app_dir_path()?;
+
+    // Pending progress and book edits go into the database first, so the
+    // snapshot holds them and the reload afterwards doesn't drop them
+    app.state::<AppState>().flush_library()?;
+
+    let summary = tauri::async_runtime::spawn_blocking(move || {
+        restore_backup(&app_dir, &path, &mode, force, &limits)
+    })
+    .await
+    .map_err(|e| format!("Restore task failed: {}", e))?;
+
+    // Everything cached was just replaced on disk, or put back by a
+    // rollback, library database included
+    app.state::<AppState>().invalidate();
+    let summary = summary?;
+    library::emit_library_event(&app, library::LibraryEvent::Reloaded);
+    Ok(summary)
+}
+
+/// Archive entries (index, name) by category name
+type EntryIndex = BTreeMap<&'static str, Vec<(usize, String)>>;
+
+/// Check every entry's path and group them by category
+fn index_entries<R: Read + io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> Result<EntryIndex, String> {
+    let mut by_category = EntryIndex::new();
+    for idx in 0..archive.len() {
+        let entry = archive
+            .by_index(idx)
+            .map_err(|e| format!("Invalid backup archive: {}", e))?;
+        if entry.is_dir() || entry.name() == MANIFEST_NAME {
+            continue;
+        }
+        if entry.enclosed_name().is_none() {
+            return Err(format!("Backup contains an unsafe path: {}", entry.name()));
+        }
+        let name = entry.name().to_string();
+        let category = category_for(&name)
+            .ok_or_else(|| format!("Backup contains an unexpected file: {}", name))?;
+        by_category.entry(category.name).or_default().push((idx, name));
+    }
+
+    Ok(by_category)
+}
+
+/// Parse the data files that get merged, so a damaged library is caught
+/// before anything is moved aside
+fn validate_data<R: Read + io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    by_category: &EntryIndex,
+) -> Result<(), String> {
+    for (category, entries) in by_category {
+        for (idx, name) in entries {
+            let parsed = match *category {
+                "library" if name == LIBRARY_EXTRAS_NAME => {
+                    serde_json::from_slice::<library_db::LibraryExtras>(&read_entry(archive, *idx)?)
+                        .map(|_| ())
+                }
+                "library" => {
+                    serde_json::from_slice::<library::Library>(&read_entry(archive, *idx)?)
+                        .map(|_| ())
+                }
+                "preferences" => {
+                    serde_json::from_slice::<serde_json::Value>(&read_entry(archive, *idx)?)
+                        .map(|_| ())
+                }
+                "vocabulary" => serde_json::from_slice::<Vec<vocabulary::VocabularyEntry>>(
+                    &read_entry(archive, *idx)?,
+                )
+                .map(|_| ()),
+                _ => continue,
+            };
+            parsed.map_err(|e| format!("Backup has an invalid {}: {}", name, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Step to undo when a restore has to be rolled back
+enum Undo {
+    /// Move a snapshotted path back to its original location
+    MoveBack { from: PathBuf, to: PathBuf },
+    /// Remove a path created by the restore
+    Remove(PathBuf),
+}
+
+fn restore_backup(
+    app_dir: &Path,
+    archive_path: &Path,
+    mode: &str,
+    force: bool,
+    limits: &AdvancedConfig,
+) -> Result<RestoreSummary, String> {
+    if mode != "replace" && mode != "merge" {
+        return Err(format!("Invalid restore mode: {}", mode));
+    }
+
+    let file =
+        File::open(archive_path).map_err(|e| format!("Failed to open backup archive: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Invalid backup archive: {}", e))?;
+
+    let manifest = read_manifest(&mut archive)?;
+    validate_manifest(&manifest)?;
+
+    // Validate every entry before touching anything on disk. A corrupt
+    // archive that trips up the zip reader is reported, not a crash.
+    let by_category = crash::catch_panic("reading the backup archive", || {
+        limits.check_zip(&mut archive, "Backup")?;
+        let by_category = index_entries(&mut archive)?;
+        validate_data(&mut archive, &by_category)?;
+        Ok::<_, String>(by_category)
+    })??;
+
+    let snapshot_dir = app_dir
+        .join("snapshots")
+        .join(format!("restore-{}", Utc::now().format("%Y%m%d-%H%M%S")));
+    fs::create_dir_all(&snapshot_dir)
+        .map_err(|e| format!("Failed to create restore snapshot: {}", e))?;
+
+    let mut undo: Vec<Undo> = Vec::new();
+    let mut results: Vec<CategoryResult> = Vec::new();
+
+    for category in CATEGORIES {
+        let Some(entries) = by_category.get(category.name) else {
+            continue;
+        };
+
+        let outcome = if category.name == "library" {
+            restore_library(
+                &mut archive,
+                app_dir,
+                &snapshot_dir,
+                entries,
+                mode == "replace",
+                &mut undo,
+            )
+        } else if mode == "replace" {
+            replace_category(&mut archive, app_dir, &snapshot_dir, category, entries, &mut undo)
+        } else {
+            merge_category(
+                &mut archive,
+                app_dir,
+                &snapshot_dir,
+                category,
+                entries,
+                force,
+                &mut undo,
+            )
+        };
+
+        match outcome {
+            Ok(result) => results.push(result),
+            Err(e) => {
+                rollback(undo);
+                return Err(format!(
+                    "Restore of {} failed, previous data was restored: {}",
+                    category.name, e
+                ));
+            }
+        }
+    }
+
+    Ok(RestoreSummary {
+        mode: mode.to_string(),
+        snapshot_path: snapshot_dir.to_string_lossy().to_string(),
+        categories: results,
+    })
+}
+
+/// Read the manifest of a backup archive
+pub fn read_manifest<R: Read + io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> Result<BackupManifest, String> {
+    let mut entry = archive
+        .by_name(MANIFEST_NAME)
+        .map_err(|_| "Backup archive has no manifest".to_string())?;
+    let mut content = String::new();
+    entry
+        .read_to_string(&mut content)
+        .map_err(|e| format!("Failed to read backup manifest: {}", e))?;
+
+    serde_json::from_str(&content).map_err(|e| format!("Invalid backup manifest: {}", e))
+}
+
+/// Refuse archives written by a newer Epilogue than this one
+pub fn validate_manifest(manifest: &BackupManifest) -> Result<(), String> {
+    let update_hint = format!(
+        "This backup was created by Epilogue {}. Please update the app to restore it.",
+        manifest.app_version
+    );
+
+    if manifest.format_version > BACKUP_FORMAT_VERSION {
+        return Err(update_hint);
+    }
+
+    let current = schema_versions();
+    for (name, version) in &manifest.schema_versions {
+        if let Some(supported) = current.get(name) {
+            if version > supported {
+                return Err(update_hint);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the category an archive entry belongs to
+fn category_for(name: &str) -> Option<&'static Category> {
+    CATEGORIES.iter().find(|category| {
+        category
+            .paths
+            .iter()
+            .any(|p| name == *p || name.starts_with(&format!("{}/", p)))
+    })
+}
+
+/// Restore a backed up library.json into the library database, replacing
+/// the library or merged into it by book id, then library_extras.json. The
+/// database is copied into the snapshot first.
+fn restore_library<R: Read + io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    app_dir: &Path,
+    snapshot_dir: &Path,
+    entries: &[(usize, String)],
+    replace: bool,
+    undo: &mut Vec<Undo>,
+) -> Result<CategoryResult, String> {
+    let mut result = CategoryResult {
+        category: "library".to_string(),
+        status: (if replace { "restored" } else { "merged" }).to_string(),
+        files: 0,
+        skipped: 0,
+        merge: None,
+    };
+    let db_path = app_dir.join("library.db");
+    if db_path.exists() {
+        let saved = snapshot_dir.join("library.db");
+        fs::copy(&db_path, &saved).map_err(|e| format!("Failed to snapshot library.db: {}", e))?;
+        undo.push(Undo::MoveBack {
+            from: saved,
+            to: db_path.clone(),
+        });
+    } else {
+        undo.push(Undo::Remove(db_path.clone()));
+    }
+    let mut conn = library_db::open(&db_path, &app_dir.join("library.json"))?;
+
+    // The books first, as only the extras of books in the library are kept
+    let mut entries = entries.to_vec();
+    entries.sort_by_key(|(_, name)| name == LIBRARY_EXTRAS_NAME);
+    for (idx, name) in &entries {
+        if name == LIBRARY_EXTRAS_NAME {
+            let extras: library_db::LibraryExtras =
+                serde_json::from_slice(&read_entry(archive, *idx)?)
+                    .map_err(|e| format!("Invalid {} in backup: {}", name, e))?;
+            library_db::import_extras(&mut conn, &extras, replace)?;
+            result.files += 1;
+            continue;
+        }
+
+        let incoming: library::Library = serde_json::from_slice(&read_entry(archive, *idx)?)
+            .map_err(|e| format!("Invalid library in backup: {}", e))?;
+        if replace {
+            library_db::replace(&mut conn, &incoming)?;
+        } else {
+            let before = library_db::load(&conn)?;
+            let mut local = before.clone();
+            result.merge = Some(library::merge_libraries(&mut local, incoming));
+            library_db::save_changes(&mut conn, &before, &local, &HashSet::new())?;
+        }
+        result.files += 1;
+    }
+
+    Ok(result)
+}
+
+fn replace_category<R: Read + io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    app_dir: &Path,
+    snapshot_dir: &Path,
+    category: &Category,
+    entries: &[(usize, String)],
+    undo: &mut Vec<Undo>,
+) -> Result<CategoryResult, String> {
+    // Move the current contents aside, then extract the archived ones
+    for rel in category.paths {
+        let original = app_dir.join(rel);
+        if !original.exists() {
+            continue;
+        }
+        let saved = snapshot_dir.join(rel);
+        if let Some(parent) = saved.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to snapshot {}: {}", rel, e))?;
+        }
+        fs::rename(&original, &saved).map_err(|e| format!("Failed to snapshot {}: {}", rel, e))?;
+        undo.push(Undo::MoveBack {
+            from: saved,
+            to: original,
+        });
+    }
+
+    for (idx, name) in entries {
+        let target = app_dir.join(name);
+        extract_entry(archive, *idx, &target)?;
+        undo.push(Undo::Remove(target));
+    }
+
+    Ok(CategoryResult {
+        category: category.name.to_string(),
+        status: "restored".to_string(),
+        files: entries.len(),
+        skipped: 0,
+        merge: None,
+    })
+}
+
+fn merge_category<R: Read + io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    app_dir: &Path,
+    snapshot_dir: &Path,
+    category: &Category,
+    entries: &[(usize, String)],
+    force: bool,
+    undo: &mut Vec<Undo>,
+) -> Result<CategoryResult, String> {
+    let mut result = CategoryResult {
+        category: category.name.to_string(),
+        status: "merged".to_string(),
+        files: 0,
+        skipped: 0,
+        merge: None,
+    };
+
+    for (idx, name) in entries {
+        let target = app_dir.join(name);
+
+        // Files we don't have yet are simply added
+        if !target.exists() {
+            extract_entry(archive, *idx, &target)?;
+            undo.push(Undo::Remove(target));
+            result.files += 1;
+            continue;
+        }
+
+        let merged = match category.name {
+            "preferences" => {
+                let incoming: serde_json::Value =
+                    serde_json::from_slice(&read_entry(archive, *idx)?)
+                        .map_err(|e| format!("Invalid preferences in backup: {}", e))?;
+                let local: serde_json::Value = fs::read(&target)
+                    .ok()
+                    .and_then(|data| serde_json::from_slice(&data).ok())
+                    .unwrap_or(serde_json::Value::Null);
+                serde_json::to_vec_pretty(&preferences::merge_preferences(local, incoming))
+                    .map_err(|e| format!("Failed to serialize preferences: {}", e))?
+            }
+            "vocabulary" => {
+                let incoming: Vec<vocabulary::VocabularyEntry> =
+                    serde_json::from_slice(&read_entry(archive, *idx)?)
+                        .map_err(|e| format!("Invalid vocabulary in backup: {}", e))?;
+                let mut local = vocabulary::load_vocabulary(&target);
+                for entry in incoming {
+                    vocabulary::merge_entry(&mut local, entry);
+                }
+                serde_json::to_vec_pretty(&local)
+                    .map_err(|e| format!("Failed to serialize vocabulary: {}", e))?
+            }
+            // Presets of the same name are only replaced when asked to
+            "presets" if force => read_entry(archive, *idx)?,
+            // Existing files in other categories are left untouched
+            _ => {
+                result.skipped += 1;
+                continue;
+            }
+        };
+
+        let saved = snapshot_dir.join(name);
+        if let Some(parent) = saved.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to snapshot {}: {}", name, e))?;
+        }
+        fs::copy(&target, &saved).map_err(|e| format!("Failed to snapshot {}: {}", name, e))?;
+        undo.push(Undo::MoveBack {
+            from: saved,
+            to: target.clone(),
+        });
+
+        fs::write(&target, merged).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        result.files += 1;
+    }
+
+    Ok(result)
+}
+
+fn read_entry<R: Read + io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    idx: usize,
+) -> Result<Vec<u8>, String> {
+    let mut entry = archive
+        .by_index(idx)
+        .map_err(|e| format!("Failed to read backup entry: {}", e))?;
+    let mut data = Vec::new();
+    entry
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read {}: {}", entry.name(), e))?;
+    Ok(data)
+}
+
+fn extract_entry<R: Read + io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    idx: usize,
+    target: &Path,
+) -> Result<(), String> {
+    let mut entry = archive
+        .by_index(idx)
+        .map_err(|e| format!("Failed to read backup entry: {}", e))?;
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let mut out = File::create(target)
+        .map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    io::copy(&mut entry, &mut out)
+        .map_err(|e| format!("Failed to extract {}: {}", target.display(), e))?;
+
+    Ok(())
+}
+
+/// Undo a partially applied restore, newest step first
+fn rollback(undo: Vec<Undo>) {
+    for step in undo.into_iter().rev() {
+        let result = match &step {
+            Undo::Remove(path) => remove_path(path),
+            Undo::MoveBack { from, to } => remove_path(to).and_then(|_| fs::rename(from, to)),
+        };
+        if let Err(e) = result {
+            logging::warn(&format!("Rollback step failed: {}", e));
+        }
+    }
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if path.exists() {
+        fs::remove_file(path)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::{book, temp_dir};
+
+    /// Write a backup holding `files` to `path`
+    fn archive(path: &Path, files: &[(&str, Vec<u8>)]) {
+        let manifest = BackupManifest {
+            format_version: BACKUP_FORMAT_VERSION,
+            app_version: "test".to_string(),
+            created_at: Utc::now(),
+            schema_versions: schema_versions(),
+            categories: Vec::new(),
+            file_count: files.len(),
+        };
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        zip.start_file(MANIFEST_NAME, SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&serde_json::to_vec(&manifest).unwrap())
+            .unwrap();
+        for (name, data) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn library_json(ids: &[&str]) -> Vec<u8> {
+        serde_json::to_vec(&library::Library {
+            books: ids.iter().map(|id| book(id)).collect(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// An app directory whose library holds `ids`
+    fn app_dir_with(name: &str, ids: &[&str]) -> PathBuf {
+        let dir = temp_dir(name);
+        let app_dir = dir.join("app");
+        fs::create_dir_all(&app_dir).unwrap();
+        let mut conn =
+            library_db::open(&app_dir.join("library.db"), &app_dir.join("library.json")).unwrap();
+        library_db::replace(
+            &mut conn,
+            &serde_json::from_slice(&library_json(ids)).unwrap(),
+        )
+        .unwrap();
+        app_dir
+    }
+
+    fn book_ids(app_dir: &Path) -> Vec<String> {
+        let conn =
+            library_db::open(&app_dir.join("library.db"), &app_dir.join("library.json")).unwrap();
+        let mut ids: Vec<String> = library_db::load(&conn)
+            .unwrap()
+            .books
+            .into_iter()
+            .map(|b| b.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn a_merge_restore_adds_the_backed_up_books_and_files() {
+        let app_dir = app_dir_with("backup_merge", &["a"]);
+        let backup = app_dir.with_file_name("backup.zip");
+        archive(
+            &backup,
+            &[
+                ("library.json", library_json(&["b"])),
+                ("stats/2026.json", b"{}".to_vec()),
+            ],
+        );
+
+        let summary = restore_backup(
+            &app_dir,
+            &backup,
+            "merge",
+            false,
+            &AdvancedConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(book_ids(&app_dir), vec!["a", "b"]);
+        assert!(app_dir.join("stats/2026.json").exists());
+        assert_eq!(summary.categories.len(), 2);
+    }
+
+    #[test]
+    fn a_failed_restore_puts_the_library_back() {
+        let app_dir = app_dir_with("backup_rollback", &["a"]);
+        // A file where the backup has a directory, so the annotations
+        // can't be extracted after the library was already merged
+        fs::write(app_dir.join("annotations"), "not a directory").unwrap();
+        let backup = app_dir.with_file_name("backup.zip");
+        archive(
+            &backup,
+            &[
+                ("library.json", library_json(&["b"])),
+                ("annotations/b.json", b"[]".to_vec()),
+            ],
+        );
+
+        let err = restore_backup(
+            &app_dir,
+            &backup,
+            "merge",
+            false,
+            &AdvancedConfig::default(),
+        )
+        .unwrap_err();
+
+        assert!(err.contains("previous data was restored"), "{}", err);
+        assert_eq!(book_ids(&app_dir), vec!["a"]);
+        assert_eq!(
+            fs::read_to_string(app_dir.join("annotations")).unwrap(),
+            "not a directory"
+        );
+    }
+}
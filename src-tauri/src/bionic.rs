@@ -0,0 +1,152 @@
+/**
+ * Bionic reading: the first part of each word in bold, to guide the eye
+ */
+/// Share of a word's letters set in bold
+const BOLD_SHARE: f32 = 0.45;
+/// Longest entity name looked for, "&CounterClockwiseContourIntegral;"
+const MAX_ENTITY_LEN: usize = 33;
+/// Text inside these elements is left alone: code, markup that isn't
+/// prose, and text that is bold already
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "title", "code", "pre", "kbd", "samp", "var", "math", "svg",
+    "textarea", "b", "strong",
+];
+
+/// Latin letters, the only script the transform applies to
+fn is_latin_letter(c: char) -> bool {
+    c.is_ascii_alphabetic()
+        || (c.is_alphabetic()
+            && matches!(
+                c as u32,
+                0x00C0..=0x024F | 0x1E00..=0x1EFF | 0x2C60..=0x2C7F | 0xA720..=0xA7FF
+            ))
+}
+
+/// Letters of a word to set in bold, at least one
+fn bold_len(letters: usize) -> usize {
+    ((letters as f32 * BOLD_SHARE).round() as usize).clamp(1, letters)
+}
+
+/// Bold the start of each word in a run of text. Entities, numbers and
+/// words with letters of other scripts are copied as they are.
+fn transform_text(text: &str, out: &mut String) {
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        // An entity is copied whole, so it is never split by a tag
+        if c == '&' {
+            let end = rest
+                .char_indices()
+                .skip(1)
+                .take(MAX_ENTITY_LEN)
+                .find(|(_, c)| !(c.is_ascii_alphanumeric() || *c == '#'))
+                .filter(|(_, c)| *c == ';')
+                .map_or(1, |(end, _)| end + 1);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        if !c.is_alphanumeric() {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        rest = &rest[end..];
+        if !word.chars().all(is_latin_letter) {
+            out.push_str(word);
+            continue;
+        }
+        let split = word
+            .char_indices()
+            .nth(bold_len(word.chars().count()))
+            .map_or(word.len(), |(i, _)| i);
+        out.push_str("<b>");
+        out.push_str(&word[..split]);
+        out.push_str("</b>");
+        out.push_str(&word[split..]);
+    }
+}
+
+/// Name of the element a tag opens or closes, lowercased, and whether it
+/// closes it
+fn tag_name(tag: &str) -> (String, bool) {
+    let inner = tag.trim_start_matches('<');
+    let closing = inner.starts_with('/');
+    let name = inner
+        .trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or_default();
+    // Namespaced names ("svg:svg", "m:math") count by their local name
+    let local = name.rsplit(':').next().unwrap_or(name);
+    (local.to_ascii_lowercase(), closing)
+}
+
+/// End of a tag starting at `start`, past its closing '>'. Quoted
+/// attribute values may contain '>'.
+fn tag_end(html: &str, start: usize) -> usize {
+    let mut quote = None;
+    for (i, c) in html[start..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return start + i + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Apply bionic reading to a chapter's (X)HTML. Markup is copied untouched;
+/// only text between tags changes, so a tag is never split.
+pub fn transform(html: &str) -> String {
+    let mut out = String::with_capacity(html.len() + html.len() / 4);
+    let mut skipped = 0usize;
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let Some(offset) = html[pos..].find('<') else {
+            if skipped == 0 {
+                transform_text(&html[pos..], &mut out);
+            } else {
+                out.push_str(&html[pos..]);
+            }
+            break;
+        };
+        let start = pos + offset;
+        if skipped == 0 {
+            transform_text(&html[pos..start], &mut out);
+        } else {
+            out.push_str(&html[pos..start]);
+        }
+
+        let rest = &html[start..];
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->").map_or(html.len(), |e| start + e + 3)
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>").map_or(html.len(), |e| start + e + 3)
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest.find('>').map_or(html.len(), |e| start + e + 1)
+        } else {
+            let end = tag_end(html, start);
+            let tag = &html[start..end];
+            let (name, closing) = tag_name(tag);
+            if SKIPPED_ELEMENTS.contains(&name.as_str()) {
+                if closing {
+                    skipped = skipped.saturating_sub(1);
+                } else if !tag.ends_with("/>") {
+                    skipped += 1;
+                }
+            }
+            end
+        };
+        out.push_str(&html[start..end]);
+        pos = end;
+    }
+    out
+}
@@ -0,0 +1,277 @@
+/**
+ * Book ids from the contents of the book file, so a book moved to another
+ * folder is still the same book. Books added when ids came from the file's
+ * path are moved to content ids once, at startup; their old ids are kept
+ * as aliases, so links and notes that name them still find the book.
+ */
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+use crate::backup::{self, BackupOptions};
+use crate::config;
+use crate::library::{self, Book, MediaType};
+use crate::library_db;
+use crate::logging;
+use crate::meta;
+use crate::state::{AppPaths, AppState};
+
+/// Recorded in the library database once the books have content ids
+const MIGRATION: &str = "content_ids";
+
+/// The id books had before, the MD5 of the file's path. Audiobooks keep
+/// it, their files being too large to hash on every import.
+pub fn path_id(path: &str) -> String {
+    format!("{:x}", md5::compute(path.as_bytes()))
+}
+
+/// The id for a book added from `path`: its content hash, unless the file
+/// couldn't be read or a book already has that id
+pub fn new_book_id(
+    state: &AppState,
+    path: &str,
+    content_hash: Option<&str>,
+) -> Result<String, String> {
+    let Some(hash) = content_hash else {
+        return Ok(path_id(path));
+    };
+    let taken = state.with_library(|library| library.books.iter().any(|b| b.id == hash))?;
+    Ok(if taken {
+        path_id(path)
+    } else {
+        hash.to_string()
+    })
+}
+
+/// Replace old ids in JSON, as values and as object keys
+fn rename_in_json(value: &mut Value, renames: &HashMap<String, String>) {
+    match value {
+        Value::String(s) => {
+            if let Some(new_id) = renames.get(s.as_str()) {
+                *s = new_id.clone();
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rename_in_json(item, renames);
+            }
+        }
+        Value::Object(map) => {
+            for (key, mut item) in std::mem::take(map) {
+                rename_in_json(&mut item, renames);
+                map.insert(renames.get(&key).cloned().unwrap_or(key), item);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Rewrite the ids in a JSON file, or in every line of a JSON lines file.
+/// Files that aren't there are left alone.
+fn rewrite_json(path: &Path, renames: &HashMap<String, String>, lines: bool) -> Result<(), String> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let rename = |text: &str| -> Result<String, String> {
+        let mut value: Value = serde_json::from_str(text)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        rename_in_json(&mut value, renames);
+        serde_json::to_string(&value)
+            .map_err(|e| format!("Failed to serialize {}: {}", path.display(), e))
+    };
+    let rewritten = if lines {
+        let mut rewritten = String::new();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            // An unreadable line stays as it was, as load_sessions skips it
+            rewritten.push_str(&rename(line).unwrap_or_else(|_| line.to_string()));
+            rewritten.push('\n');
+        }
+        rewritten
+    } else {
+        rename(&content)?
+    };
+    fs::write(path, rewritten).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Move the `{id}.json` file of a book in `dir` to its new id, with the ids
+/// inside renamed too
+fn move_book_file(
+    dir: &Path,
+    old_id: &str,
+    new_id: &str,
+    renames: &HashMap<String, String>,
+) -> Result<(), String> {
+    let old = dir.join(format!("{}.json", old_id));
+    if !old.exists() {
+        return Ok(());
+    }
+    let new = dir.join(format!("{}.json", new_id));
+    rewrite_json(&old, renames, false)?;
+    fs::rename(&old, &new).map_err(|e| format!("Failed to move {}: {}", old.display(), e))
+}
+
+/// Where a cover named after `old_id` goes under `new_id`; other covers
+/// stay where they are
+fn moved_cover(cover: &str, old_id: &str, new_id: &str) -> Option<PathBuf> {
+    let cover = Path::new(cover);
+    let name = cover.file_name()?.to_string_lossy();
+    let rest = name.strip_prefix(old_id)?.strip_prefix('.')?;
+    Some(cover.with_file_name(format!("{}.{}", new_id, rest)))
+}
+
+/// Move a cover file to its new name; the new path once it is there
+fn move_cover(cover: Option<&str>, old_id: &str, new_id: &str) -> Option<String> {
+    let cover = cover?;
+    let target = moved_cover(cover, old_id, new_id)?;
+    // Moved already by a migration that stopped before saving the library
+    if !target.exists() {
+        if let Err(e) = fs::rename(cover, &target) {
+            logging::warn(&format!("Failed to rename cover {}: {}", cover, e));
+            return None;
+        }
+    }
+    Some(target.to_string_lossy().to_string())
+}
+
+/// The new id of every book that gets one: its content hash. Audiobooks,
+/// books whose file is gone and books whose contents another book has
+/// already keep theirs.
+fn plan_renames(books: &[Book]) -> (HashMap<String, String>, HashMap<String, String>) {
+    let mut taken: HashSet<String> = books.iter().map(|b| b.id.clone()).collect();
+    let mut renames = HashMap::new();
+    let mut hashed = HashMap::new();
+    for book in books {
+        if book.media_type == MediaType::Audio {
+            continue;
+        }
+        let hash = match &book.content_hash {
+            Some(hash) => hash.clone(),
+            None => match library::file_hash(Path::new(&book.file_path)) {
+                Some(hash) => {
+                    hashed.insert(book.id.clone(), hash.clone());
+                    hash
+                }
+                None => continue,
+            },
+        };
+        if hash == book.id || !taken.insert(hash.clone()) {
+            continue;
+        }
+        renames.insert(book.id.clone(), hash);
+    }
+    (renames, hashed)
+}
+
+/// Move the files named after books, and rewrite the ids in the stats and
+/// vocabulary. Each step can run again after an interrupted migration.
+fn move_files(paths: &AppPaths, renames: &HashMap<String, String>) -> Result<(), String> {
+    for (old_id, new_id) in renames {
+        move_book_file(&paths.annotations, old_id, new_id, renames)?;
+        move_book_file(&paths.view_state, old_id, new_id, renames)?;
+    }
+    rewrite_json(&paths.sessions, renames, true)?;
+    rewrite_json(&paths.open_session, renames, false)?;
+    rewrite_json(&paths.reading_speed, renames, false)?;
+    rewrite_json(&paths.vocabulary, renames, false)?;
+    // What was last exported to each Obsidian vault, by book
+    meta::update_meta(|m| {
+        for exported in m.obsidian_exports.values_mut() {
+            rename_keys(exported, renames);
+        }
+    })?;
+    Ok(())
+}
+
+/// Move the entries of a map by book id to the books' new ids
+fn rename_keys<T>(map: &mut BTreeMap<String, T>, renames: &HashMap<String, String>) {
+    for (key, value) in std::mem::take(map) {
+        map.insert(renames.get(&key).cloned().unwrap_or(key), value);
+    }
+}
+
+/// Give books added with path ids their content ids, once. The data
+/// directory is backed up to snapshots/ first. Running again after an
+/// interruption finishes the job.
+pub fn migrate_to_content_ids(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    if config::is_safe_mode() || state.with_db(|conn| library_db::has_migrated(conn, MIGRATION))? {
+        return Ok(());
+    }
+
+    let books = state.with_library(|library| library.books.clone())?;
+    let (renames, hashed) = plan_renames(&books);
+    if !renames.is_empty() {
+        // Unsaved progress goes into the backup and the renamed rows
+        state.flush_library()?;
+        let paths = state.paths()?;
+        let backup = backup::create_backup(
+            app,
+            &paths.app_dir.join("snapshots"),
+            &BackupOptions {
+                include_covers: true,
+                exclude_books: true,
+                exclude_media: true,
+            },
+        )?;
+        logging::info(&format!(
+            "Backed up the data folder to {} before giving books content ids",
+            backup.archive_path
+        ));
+        move_files(paths, &renames)?;
+
+        state.rename_books(&renames, |library| {
+            for book in library.books.iter_mut() {
+                let Some(new_id) = renames.get(&book.id) else {
+                    continue;
+                };
+                if let Some(cover) = move_cover(book.cover_path.as_deref(), &book.id, new_id) {
+                    book.cover_path = Some(cover);
+                }
+                if let Some(thumbnail) =
+                    move_cover(book.thumbnail_path.as_deref(), &book.id, new_id)
+                {
+                    book.thumbnail_path = Some(thumbnail);
+                }
+                if let Some(hash) = hashed.get(&book.id) {
+                    book.content_hash = Some(hash.clone());
+                }
+                book.id = new_id.clone();
+            }
+            Ok(())
+        })?;
+        if let Ok(mut covers) = state.covers.lock() {
+            covers.clear();
+        }
+        logging::info(&format!("Gave {} books content ids", renames.len()));
+    }
+    state.with_db(|conn| library_db::record_migration(conn, MIGRATION))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_move_to_the_new_ids() {
+        let renames = HashMap::from([("old".to_string(), "new".to_string())]);
+        let mut exported = BTreeMap::from([
+            ("old".to_string(), "hash-1".to_string()),
+            ("other".to_string(), "hash-2".to_string()),
+        ]);
+        rename_keys(&mut exported, &renames);
+        assert_eq!(
+            exported,
+            BTreeMap::from([
+                ("new".to_string(), "hash-1".to_string()),
+                ("other".to_string(), "hash-2".to_string()),
+            ])
+        );
+
+        // Running again after an interrupted migration changes nothing
+        let again = exported.clone();
+        rename_keys(&mut exported, &renames);
+        assert_eq!(exported, again);
+    }
+}
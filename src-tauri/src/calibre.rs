@@ -0,0 +1,258 @@
+/**
+ * Importing a Calibre library: the EPUB of every book folder, with the
+ * title, authors, series and tags Calibre keeps in its metadata.opf and
+ * its cover.jpg. The Calibre library is only read.
+ */
+use scraper::{ElementRef, Html};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::advanced::AdvancedConfig;
+use crate::covers;
+use crate::crash;
+use crate::epub;
+use crate::file_access;
+use crate::folder_import::{self, FailedImport, ImportProgress};
+use crate::library::{self, BookNames, LibraryEvent, LibraryEventBatch};
+use crate::logging;
+use crate::series;
+use crate::state::AppState;
+
+const METADATA_FILE: &str = "metadata.opf";
+const COVER_FILE: &str = "cover.jpg";
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct CalibreImportSummary {
+    pub imported: usize,
+    /// Books already in the library, under their path or their contents
+    pub skipped: usize,
+    /// Book folders with only other formats
+    #[serde(rename = "withoutEpub")]
+    pub without_epub: usize,
+    pub failed: Vec<FailedImport>,
+}
+
+/// What Calibre knows of a book
+#[derive(Debug, Default)]
+struct CalibreMetadata {
+    title: Option<String>,
+    authors: Vec<String>,
+    series: Option<String>,
+    series_index: Option<series::SeriesIndex>,
+    tags: Vec<String>,
+}
+
+/// Folders holding a metadata.opf, in name order. Symlinked folders are
+/// not followed.
+fn find_book_folders(dir: &Path, found: &mut Vec<PathBuf>) {
+    if dir.join(METADATA_FILE).is_file() {
+        found.push(dir.to_path_buf());
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        logging::warn(&format!("Failed to read folder {}", dir.display()));
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            find_book_folders(&entry.path(), found);
+        }
+    }
+}
+
+fn text_of(element: ElementRef) -> Option<String> {
+    let text = element.text().collect::<String>().trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Read a metadata.opf. The HTML parser keeps prefixed names such as
+/// "dc:title" whole, so elements are matched on the part after the colon.
+fn read_metadata(path: &Path) -> Result<CalibreMetadata, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let document = Html::parse_document(&content);
+    let mut metadata = CalibreMetadata::default();
+    let mut series_index = None;
+    for element in document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+    {
+        let value = element.value();
+        let name = value.name();
+        let local = name.rsplit(':').next().unwrap_or(name);
+        match local {
+            "title" if metadata.title.is_none() => {
+                metadata.title = text_of(element);
+            }
+            "creator" => {
+                let role = value.attr("opf:role").or_else(|| value.attr("role"));
+                if role.is_none_or(|r| r == "aut") {
+                    metadata.authors.extend(text_of(element));
+                }
+            }
+            "subject" => metadata.tags.extend(text_of(element)),
+            "meta" => match (value.attr("name"), value.attr("content")) {
+                (Some("calibre:series"), Some(content)) if !content.trim().is_empty() => {
+                    metadata.series = Some(content.trim().to_string());
+                }
+                (Some("calibre:series_index"), Some(content)) => {
+                    series_index = series::parse_index(content);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    metadata.series_index = metadata.series.as_ref().and(series_index);
+    Ok(metadata)
+}
+
+/// Import the EPUB of a book folder and give it Calibre's metadata and
+/// cover
+fn import_book_folder(
+    state: &AppState,
+    limits: &AdvancedConfig,
+    folder: &Path,
+    path: &Path,
+) -> Result<LibraryEvent, String> {
+    let metadata = read_metadata(&folder.join(METADATA_FILE))?;
+    epub::validate_epub(path, limits)?;
+
+    let path = path.to_string_lossy().to_string();
+    // calibre's title and authors, the EPUB's for any it has not
+    let names = BookNames::Given {
+        title: metadata.title.clone(),
+        author: Some(metadata.authors.join(", ")).filter(|a| !a.is_empty()),
+    };
+    let event = crash::catch_panic("importing the book", || {
+        library::import_book(state, names, path)
+    })??;
+    let LibraryEvent::Added(book) = event else {
+        // The same file was already on the shelf; its details are the user's
+        return Ok(event);
+    };
+
+    let cover = match fs::read(folder.join(COVER_FILE)) {
+        Ok(data) => library::save_cover(&state.paths()?.covers, &book.id, &data, "image/jpeg"),
+        Err(_) => None,
+    };
+    let book = state.update_library(|library| {
+        let entry = library
+            .books
+            .iter_mut()
+            .find(|b| b.id == book.id)
+            .ok_or_else(|| format!("Book with id '{}' not found", book.id))?;
+        if let Some(name) = metadata.series {
+            entry.series = Some(name);
+            entry.series_index = metadata.series_index.map(|(start, _)| start);
+            entry.series_end = metadata.series_index.and_then(|(_, end)| end);
+        }
+        library::add_tags(entry, metadata.tags);
+        if let Some(cover) = cover {
+            // An extracted cover under another extension is replaced
+            if let Some(old) = entry.cover_path.take().filter(|old| *old != cover) {
+                let _ = fs::remove_file(old);
+            }
+            entry.thumbnail_path = covers::thumbnail_of(&cover);
+            entry.cover_path = Some(cover);
+        }
+        Ok(entry.clone())
+    })?;
+    if let (Some(cover), Ok(mut covers)) = (&book.cover_path, state.covers.lock()) {
+        covers.insert(book.id.clone(), cover.clone());
+    }
+    Ok(LibraryEvent::Added(book))
+}
+
+fn import_all(app: &AppHandle, library_dir: &Path) -> CalibreImportSummary {
+    let state = app.state::<AppState>();
+    let limits = app.state::<AdvancedConfig>();
+    let mut folders = Vec::new();
+    find_book_folders(library_dir, &mut folders);
+    let known: HashSet<String> = state
+        .with_library(|library| library.books.iter().map(|b| b.file_path.clone()).collect())
+        .unwrap_or_default();
+
+    let mut summary = CalibreImportSummary::default();
+    let mut events = LibraryEventBatch::new(app);
+    let total = folders.len();
+    for (index, folder) in folders.iter().enumerate() {
+        let _ = app.emit(
+            "import-progress",
+            ImportProgress {
+                current: index + 1,
+                total,
+                file_name: folder
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            },
+        );
+        let mut epubs = Vec::new();
+        folder_import::find_epubs(folder, false, &mut epubs);
+        let Some(path) = epubs.into_iter().next() else {
+            summary.without_epub += 1;
+            continue;
+        };
+        if known.contains(path.to_string_lossy().as_ref()) {
+            summary.skipped += 1;
+            continue;
+        }
+
+        match import_book_folder(&state, &limits, folder, &path) {
+            Ok(event @ LibraryEvent::Added(_)) => {
+                summary.imported += 1;
+                events.push(event);
+            }
+            Ok(event) => {
+                summary.skipped += 1;
+                events.push(event);
+            }
+            Err(reason) => {
+                logging::warn(&format!(
+                    "Failed to import {}: {}",
+                    folder.display(),
+                    reason
+                ));
+                summary.failed.push(FailedImport {
+                    path: folder.to_string_lossy().to_string(),
+                    reason,
+                });
+            }
+        }
+    }
+
+    logging::info(&format!(
+        "Imported {} books from the Calibre library {}, skipped {}, {} without an EPUB, {} failed",
+        summary.imported,
+        library_dir.display(),
+        summary.skipped,
+        summary.without_epub,
+        summary.failed.len()
+    ));
+    summary
+}
+
+/// Import the EPUBs of a Calibre library folder picked with
+/// open_folder_dialog, with Calibre's metadata and covers.
+/// "import-progress" is emitted before each book folder.
+#[tauri::command]
+pub async fn import_calibre(
+    app: AppHandle,
+    library_dir: String,
+) -> Result<CalibreImportSummary, String> {
+    let dir = file_access::check_read_access(&app.state::<AppState>(), &library_dir)?;
+    // Every Calibre library has its database at the top
+    if !dir.join("metadata.db").is_file() {
+        return Err(format!("Not a Calibre library: {}", library_dir));
+    }
+    tauri::async_runtime::spawn_blocking(move || import_all(&app, &dir))
+        .await
+        .map_err(|e| format!("Calibre import task failed: {}", e))
+}
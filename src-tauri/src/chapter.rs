@@ -0,0 +1,125 @@
+/**
+ * Chapters served one at a time, with the reading transforms applied
+ */
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+use tauri::State;
+
+use crate::bionic;
+use crate::crash;
+use crate::error::AppError;
+use crate::file_access;
+use crate::state::AppState;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Chapter {
+    /// Path of the chapter inside the EPUB
+    pub href: String,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub content: String,
+    /// Bionic reading was applied
+    pub bionic: bool,
+}
+
+/// `href` as a path inside the EPUB. Links are relative to the chapter they
+/// are in (`from`); a bare "#fragment" points into that chapter.
+pub fn resolve_href(href: &str, from: Option<&str>) -> String {
+    let Some(from) = from.map(|f| f.split('#').next().unwrap_or_default()) else {
+        return href.to_string();
+    };
+    if href.starts_with('#') {
+        return format!("{}{}", from, href);
+    }
+    if href.starts_with('/') || href.contains("://") {
+        return href.to_string();
+    }
+    let mut path = PathBuf::new();
+    let joined = Path::new(from).parent().unwrap_or(Path::new("")).join(href);
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::Normal(part) => path.push(part),
+            _ => {}
+        }
+    }
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Resolve `href` (with or without a fragment) against the EPUB's files:
+/// as a path inside the archive, or relative to the package document
+pub fn resource_path<R: std::io::Read + std::io::Seek>(
+    doc: &epub::doc::EpubDoc<R>,
+    href: &str,
+) -> Option<(PathBuf, String)> {
+    let file = href.split('#').next().unwrap_or_default();
+    let file = file.trim_start_matches('/');
+    let candidates = [PathBuf::from(file), doc.root_base.join(file)];
+    candidates.into_iter().find_map(|candidate| {
+        doc.resources
+            .values()
+            .find(|(path, _)| path == &candidate)
+            .map(|(path, mime)| (path.clone(), mime.clone()))
+    })
+}
+
+/// Text of one file of an EPUB, with its media type
+pub fn read_resource(path: &Path, href: &str) -> Result<(PathBuf, String, String), String> {
+    let mut doc =
+        epub::doc::EpubDoc::new(path).map_err(|e| format!("Failed to open EPUB: {:?}", e))?;
+    let (resource, media_type) =
+        resource_path(&doc, href).ok_or_else(|| format!("No file '{}' in this book", href))?;
+    let content = doc
+        .get_resource_str_by_path(&resource)
+        .ok_or_else(|| format!("Failed to read '{}'", resource.display()))?;
+    Ok((resource, media_type, content))
+}
+
+/// Whether bionic reading applies to the book at `path`: the book's own
+/// setting if it has one, else the preference
+fn bionic_enabled(state: &AppState, path: &Path) -> Result<bool, String> {
+    let book_setting = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .find(|b| {
+                Path::new(&b.file_path)
+                    .canonicalize()
+                    .is_ok_and(|p| p == path)
+            })
+            .and_then(|b| b.overrides.bionic_reading)
+    })?;
+    Ok(match book_setting {
+        Some(enabled) => enabled,
+        None => state.preferences()?.bionic_reading,
+    })
+}
+
+/// A chapter of an EPUB, by its path in the book. Bionic reading is applied
+/// to (X)HTML when enabled for the book.
+#[tauri::command]
+pub fn get_chapter(
+    state: State<'_, AppState>,
+    path: String,
+    href: String,
+) -> Result<Chapter, AppError> {
+    let path = file_access::check_read_access(&state, &path)?;
+    // A malformed EPUB must not take the app down with it
+    let (resource, media_type, content) =
+        crash::catch_panic("reading the chapter", || read_resource(&path, &href))??;
+
+    let is_html = media_type.contains("html");
+    let bionic = is_html && bionic_enabled(&state, &path)?;
+    Ok(Chapter {
+        href: resource.to_string_lossy().replace('\\', "/"),
+        content: if bionic {
+            bionic::transform(&content)
+        } else {
+            content
+        },
+        media_type,
+        bionic,
+    })
+}
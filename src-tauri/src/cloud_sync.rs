@@ -0,0 +1,260 @@
+/**
+ * Data directories inside Dropbox, OneDrive, Google Drive or iCloud, and
+ * the conflicted copies their sync clients leave next to our files
+ */
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::config;
+use crate::error::AppError;
+use crate::file_access;
+use crate::library::{self, LibraryEvent, MergeSummary};
+use crate::logging;
+use crate::meta;
+use crate::state::AppState;
+
+/// Conflicted copies are looked for of the library only; it is the one
+/// file that can be merged back
+const LIBRARY_STEM: &str = "library";
+/// Suffix a conflicted copy gets once merged, so it isn't offered again
+const MERGED_SUFFIX: &str = ".merged";
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CloudSyncStatus {
+    /// Sync service the data directory is inside of, if any
+    pub provider: Option<String>,
+    #[serde(rename = "dataDir")]
+    pub data_dir: String,
+    /// Conflicted copies of the library in the data directory
+    pub conflicts: Vec<String>,
+}
+
+/// Dropbox lists its folders in info.json, in a place that depends on the
+/// platform
+fn dropbox_roots() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        files.push(home.join(".dropbox").join("info.json"));
+    }
+    for var in ["APPDATA", "LOCALAPPDATA"] {
+        if let Some(dir) = std::env::var_os(var) {
+            files.push(PathBuf::from(dir).join("Dropbox").join("info.json"));
+        }
+    }
+
+    let mut roots = Vec::new();
+    for file in files {
+        let Some(info) = fs::read(&file)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Value>(&data).ok())
+        else {
+            continue;
+        };
+        // {"personal": {"path": …}, "business": {"path": …}}
+        if let Some(accounts) = info.as_object() {
+            roots.extend(
+                accounts
+                    .values()
+                    .filter_map(|account| account.get("path")?.as_str())
+                    .map(PathBuf::from),
+            );
+        }
+    }
+    roots
+}
+
+/// Folders the sync clients installed here keep in sync, with their names
+fn synced_roots() -> Vec<(&'static str, PathBuf)> {
+    let mut roots: Vec<(&'static str, PathBuf)> = dropbox_roots()
+        .into_iter()
+        .map(|root| ("Dropbox", root))
+        .collect();
+
+    // OneDrive on Windows
+    for var in ["OneDrive", "OneDriveConsumer", "OneDriveCommercial"] {
+        if let Some(dir) = std::env::var_os(var) {
+            roots.push(("OneDrive", PathBuf::from(dir)));
+        }
+    }
+
+    if let Some(home) = dirs::home_dir() {
+        // iCloud Drive on macOS and Windows
+        roots.push((
+            "iCloud Drive",
+            home.join("Library").join("Mobile Documents"),
+        ));
+        roots.push(("iCloud Drive", home.join("iCloudDrive")));
+
+        // macOS File Provider folders: OneDrive-Personal, GoogleDrive-me@…
+        let cloud_storage = home.join("Library").join("CloudStorage");
+        for entry in fs::read_dir(cloud_storage).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let provider = if name.starts_with("OneDrive") {
+                "OneDrive"
+            } else if name.starts_with("GoogleDrive") {
+                "Google Drive"
+            } else if name.starts_with("Dropbox") {
+                "Dropbox"
+            } else {
+                continue;
+            };
+            roots.push((provider, entry.path()));
+        }
+    }
+    roots
+}
+
+/// A sync service recognized by the files it keeps at the top of its folder
+fn marked_root(dir: &Path) -> Option<&'static str> {
+    if dir.join(".dropbox").is_file() || dir.join(".dropbox.cache").is_dir() {
+        return Some("Dropbox");
+    }
+    if dir.join(".tmp.drivedownload").is_dir() || dir.join(".tmp.driveupload").is_dir() {
+        return Some("Google Drive");
+    }
+    // Google Drive for desktop mounts "My Drive" at the root of a drive
+    let is_drive_root = dir.parent().is_some_and(|p| p.parent().is_none());
+    if is_drive_root && dir.file_name().is_some_and(|n| n == "My Drive") {
+        return Some("Google Drive");
+    }
+    None
+}
+
+/// The sync service `dir` is inside of. Only the services' own folders and
+/// marker files count, never a folder that happens to be called "Dropbox".
+pub fn cloud_provider(dir: &Path) -> Option<&'static str> {
+    // A directory that doesn't exist yet is judged by its nearest ancestor
+    let existing = dir.ancestors().find(|a| a.exists())?;
+    let dir = existing
+        .canonicalize()
+        .unwrap_or_else(|_| existing.to_path_buf());
+
+    let in_root = synced_roots().into_iter().find(|(_, root)| {
+        let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+        dir.starts_with(root)
+    });
+    if let Some((provider, _)) = in_root {
+        return Some(provider);
+    }
+    dir.ancestors().find_map(marked_root)
+}
+
+/// Whether a file name is a copy of `stem`.json a sync client made on a
+/// conflict: "library (Jo's conflicted copy 2024-05-01).json" from Dropbox,
+/// "library.sync-conflict-20240501-101500-ABCDEFG.json" from Syncthing
+fn is_conflict_copy(name: &str, stem: &str) -> bool {
+    let Some(rest) = name
+        .strip_suffix(".json")
+        .and_then(|base| base.strip_prefix(stem))
+    else {
+        return false;
+    };
+    let dropbox =
+        rest.starts_with(" (") && rest.ends_with(')') && rest.contains("conflicted copy");
+    let syncthing = rest.starts_with(".sync-conflict-");
+    dropbox || syncthing
+}
+
+/// Conflicted copies of the library next to it
+pub fn library_conflicts(app_dir: &Path) -> Vec<PathBuf> {
+    let mut conflicts: Vec<PathBuf> = fs::read_dir(app_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| is_conflict_copy(&entry.file_name().to_string_lossy(), LIBRARY_STEM))
+        .map(|entry| entry.path())
+        .collect();
+    conflicts.sort();
+    conflicts
+}
+
+fn status(app_dir: &Path) -> CloudSyncStatus {
+    CloudSyncStatus {
+        provider: cloud_provider(app_dir).map(str::to_string),
+        data_dir: app_dir.to_string_lossy().to_string(),
+        conflicts: library_conflicts(app_dir)
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect(),
+    }
+}
+
+/// Startup check: warn once per data directory when it is in a synced
+/// folder, and on every launch while conflicted copies are around
+pub fn check(app: &AppHandle) {
+    if config::is_safe_mode() {
+        return;
+    }
+    let Ok(app_dir) = config::get_app_dir_path() else {
+        return;
+    };
+    let status = status(&app_dir);
+
+    if let Some(provider) = &status.provider {
+        logging::warn(&format!(
+            "The data folder {} is synced by {}",
+            status.data_dir, provider
+        ));
+        if meta::load_meta().cloud_sync_warned.as_ref() != Some(&status.data_dir) {
+            let _ = app.emit("cloud-sync-warning", &status);
+            let warned = status.data_dir.clone();
+            if let Err(e) = meta::update_meta(|m| m.cloud_sync_warned = Some(warned)) {
+                logging::error(&e);
+            }
+        }
+    }
+    if !status.conflicts.is_empty() {
+        logging::warn(&format!(
+            "Found {} conflicted copies of the library",
+            status.conflicts.len()
+        ));
+        let _ = app.emit("sync-conflicts-found", &status.conflicts);
+    }
+}
+
+/// Whether the data directory is in a synced folder, and any conflicted
+/// copies of the library it holds
+#[tauri::command]
+pub fn get_cloud_sync_status(state: State<'_, AppState>) -> Result<CloudSyncStatus, String> {
+    Ok(status(&state.paths()?.app_dir))
+}
+
+/// Merge another copy of library.json, such as a sync client's conflicted
+/// copy, into the library. Conflicted copies in the data directory are
+/// renamed afterwards so they aren't offered again.
+#[tauri::command]
+pub fn merge_external_library(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<MergeSummary, AppError> {
+    let path = file_access::check_read_access(&state, &path)?;
+    let incoming = library::load_library(&path)?;
+
+    state.flush_library()?;
+    let summary =
+        state.update_library(|library| Ok(library::merge_libraries(library, incoming)))?;
+    if summary.added + summary.updated > 0 {
+        library::emit_library_event(&app, LibraryEvent::Reloaded);
+    }
+
+    let app_dir = state.paths()?.app_dir.canonicalize().ok();
+    let is_conflict = path
+        .file_name()
+        .is_some_and(|name| is_conflict_copy(&name.to_string_lossy(), LIBRARY_STEM));
+    if is_conflict && path.parent() == app_dir.as_deref() && !config::is_safe_mode() {
+        let mut merged = path.clone().into_os_string();
+        merged.push(MERGED_SUFFIX);
+        if let Err(e) = fs::rename(&path, &merged) {
+            logging::warn(&format!(
+                "Failed to rename merged copy {}: {}",
+                path.display(),
+                e
+            ));
+        }
+    }
+    Ok(summary)
+}
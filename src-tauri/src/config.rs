@@ -1,105 +1,494 @@
 /**
  * Configuration and directory management
  */
+use serde::Serialize;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::advanced::{self, AdvancedConfig};
+use crate::cloud_sync;
+use crate::error::AppError;
+use crate::launch;
+use crate::library;
+use crate::meta;
+use crate::sleep_inhibit::{SleepInhibit, SleepInhibitStatus};
+use crate::state::{AppPaths, AppState};
+use crate::view_state;
+
+/// Outcome of the startup check of the data directory
+#[derive(Debug, Serialize, Clone)]
+pub struct AppDirStatus {
+    /// Directory actually in use
+    pub path: PathBuf,
+    /// True when running from a temporary directory because the real one is unusable
+    #[serde(rename = "safeMode")]
+    pub safe_mode: bool,
+    /// Where the data directory should have been
+    #[serde(rename = "preferredPath")]
+    pub preferred_path: Option<PathBuf>,
+    pub reason: Option<String>,
+}
+
+static APP_DIR: OnceLock<Result<AppDirStatus, AppError>> = OnceLock::new();
+
+/// File outside the data directory remembering where it was moved to
+fn location_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("epilogue").join("data-location"))
+}
+
+/// The data directory chosen with set_data_directory, if any
+fn chosen_app_dir() -> Option<PathBuf> {
+    let location = fs::read_to_string(location_file()?).ok()?;
+    let location = location.trim();
+    (!location.is_empty()).then(|| PathBuf::from(location))
+}
+
+/// Resolve the data directory once, verifying that it is creatable and
+/// writable, and falling back to a temp-dir safe mode otherwise
+pub fn resolve_app_dir() -> Result<&'static AppDirStatus, AppError> {
+    APP_DIR
+        .get_or_init(|| {
+            let preferred = chosen_app_dir()
+                .or_else(|| dirs::home_dir().map(|home| home.join(".epub-reader")));
+
+            let reason = match &preferred {
+                Some(path) => match probe_writable(path) {
+                    Ok(()) => {
+                        return Ok(AppDirStatus {
+                            path: path.clone(),
+                            safe_mode: false,
+                            preferred_path: None,
+                            reason: None,
+                        })
+                    }
+                    Err(e) => e,
+                },
+                None => "Could not determine home directory".to_string(),
+            };
+
+            eprintln!("App directory unusable, starting in safe mode: {}", reason);
+
+            let fallback = std::env::temp_dir().join("epilogue-safe-mode");
+            probe_writable(&fallback).map_err(|e| AppError::AppDirUnavailable {
+                path: fallback.to_string_lossy().to_string(),
+                reason: e,
+            })?;
+
+            Ok(AppDirStatus {
+                path: fallback,
+                safe_mode: true,
+                preferred_path: preferred,
+                reason: Some(reason),
+            })
+        })
+        .as_ref()
+        .map_err(|e| e.clone())
+}
+
+/// Create `dir` if needed and check that a file can be written into it
+fn probe_writable(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+
+    let probe = dir.join(".write-test");
+    fs::write(&probe, b"ok").map_err(|e| format!("Cannot write to {}: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+
+    Ok(())
+}
+
+/// True when the app is running from the temporary safe-mode directory
+pub fn is_safe_mode() -> bool {
+    resolve_app_dir().map(|s| s.safe_mode).unwrap_or(true)
+}
+
+/// Error returned by writes that are disabled in safe mode
+pub fn safe_mode_error() -> AppError {
+    match resolve_app_dir() {
+        Ok(status) => AppError::AppDirUnavailable {
+            path: status
+                .preferred_path
+                .as_ref()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            reason: status.reason.clone().unwrap_or_default(),
+        },
+        Err(e) => e,
+    }
+}
 
 /// Get the app data directory path
 #[tauri::command]
-pub fn get_app_dir() -> Result<String, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+pub fn get_app_dir(state: State<'_, AppState>) -> Result<String, String> {
+    Ok(state.paths()?.app_dir.to_string_lossy().to_string())
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AppInfo {
+    pub version: String,
+    #[serde(rename = "appDir")]
+    pub app_dir: String,
+    #[serde(rename = "safeMode")]
+    pub safe_mode: bool,
+    #[serde(rename = "advancedConfigPath")]
+    pub advanced_config_path: String,
+    /// advanced.toml changed since startup; a restart is needed to apply it
+    #[serde(rename = "restartRequired")]
+    pub restart_required: bool,
+    #[serde(rename = "sleepInhibit")]
+    pub sleep_inhibit: SleepInhibitStatus,
+}
+
+/// General information about the running app
+#[tauri::command]
+pub fn get_app_info(
+    advanced: State<'_, AdvancedConfig>,
+    sleep_inhibit: State<'_, SleepInhibit>,
+) -> Result<AppInfo, String> {
+    let status = resolve_app_dir()?;
 
-    let app_dir = home_dir.join(".epub-reader");
+    Ok(AppInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        app_dir: status.path.to_string_lossy().to_string(),
+        safe_mode: status.safe_mode,
+        advanced_config_path: advanced::advanced_config_path()?
+            .to_string_lossy()
+            .to_string(),
+        restart_required: advanced::current_mtime() != advanced.loaded_mtime,
+        sleep_inhibit: sleep_inhibit.status(),
+    })
+}
 
-    Ok(app_dir.to_string_lossy().to_string())
+/// Report whether the data directory is usable, for the safe-mode banner
+#[tauri::command]
+pub fn get_app_dir_status() -> Result<AppDirStatus, AppError> {
+    resolve_app_dir().cloned()
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+    let entries =
+        fs::read_dir(from).map_err(|e| format!("Failed to read {}: {}", from.display(), e))?;
+    for entry in entries.flatten() {
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .map_err(|e| format!("Failed to copy {}: {}", entry.path().display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Move the data directory to `path`, which must be empty or not exist yet.
+/// Everything is copied over and used from the next launch; the old
+/// directory is left in place for the user to remove.
+#[tauri::command]
+pub fn set_data_directory(app: AppHandle, path: String) -> Result<String, AppError> {
+    if is_safe_mode() {
+        return Err(safe_mode_error());
+    }
+    let target = PathBuf::from(path.trim());
+    if !target.is_absolute() {
+        return Err(format!("{} is not an absolute path", target.display()).into());
+    }
+    let current = get_app_dir_path()?;
+    if target.starts_with(&current) || current.starts_with(&target) {
+        return Err("The new data folder can't contain or be inside the current one"
+            .to_string()
+            .into());
+    }
+    if let Some(provider) = cloud_sync::cloud_provider(&target) {
+        return Err(format!(
+            "{} is synced by {}, which can corrupt the library",
+            target.display(),
+            provider
+        )
+        .into());
+    }
+    if fs::read_dir(&target).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(format!("{} is not empty", target.display()).into());
+    }
+    probe_writable(&target).map_err(|reason| AppError::AppDirUnavailable {
+        path: target.to_string_lossy().to_string(),
+        reason,
+    })?;
+
+    // Pending library changes go along with the move
+    library::flush_pending(&app);
+    view_state::flush_pending(&app);
+    copy_dir(&current, &target)?;
+
+    let location = location_file()
+        .ok_or_else(|| "Could not determine the config directory".to_string())?;
+    if let Some(parent) = location.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(&location, target.to_string_lossy().as_bytes())
+        .map_err(|e| format!("Failed to save the data folder location: {}", e))?;
+
+    let path = target.to_string_lossy().to_string();
+    let _ = app.emit("data-directory-changed", &path);
+    Ok(path)
 }
 
 /// Initialize the library directory structure
 #[tauri::command]
-pub fn init_library() -> Result<(), String> {
-    let app_dir = get_app_dir_path()?;
+pub fn init_library(state: State<'_, AppState>) -> Result<(), String> {
+    create_app_dirs(state.paths()?)?;
+
+    // Create the library database, moving library.json into it, and load
+    // the library once for every command to share
+    state.with_library(|_| ())?;
+
+    // Record when Epilogue was first launched
+    if meta::load_meta().first_launch_at.is_none() {
+        meta::update_meta(|m| m.first_launch_at = Some(chrono::Utc::now()))?;
+    }
+
+    Ok(())
+}
 
+/// Create the data directory and the folders the app writes into
+pub fn create_app_dirs(paths: &AppPaths) -> Result<(), String> {
     // Create main directory
-    fs::create_dir_all(&app_dir).map_err(|e| format!("Failed to create app directory: {}", e))?;
+    fs::create_dir_all(&paths.app_dir)
+        .map_err(|e| format!("Failed to create app directory: {}", e))?;
 
     // Create subdirectories
-    let media_dir = app_dir.join("media");
-    let backgrounds_dir = media_dir.join("backgrounds");
-    let presets_dir = app_dir.join("presets");
-    let cache_dir = app_dir.join("cache");
-    let covers_dir = cache_dir.join("covers");
-
-    fs::create_dir_all(&backgrounds_dir)
+    fs::create_dir_all(&paths.backgrounds)
         .map_err(|e| format!("Failed to create backgrounds directory: {}", e))?;
-    fs::create_dir_all(&presets_dir)
+    fs::create_dir_all(&paths.music)
+        .map_err(|e| format!("Failed to create music directory: {}", e))?;
+    fs::create_dir_all(&paths.presets)
         .map_err(|e| format!("Failed to create presets directory: {}", e))?;
-    fs::create_dir_all(&covers_dir)
+    fs::create_dir_all(&paths.covers)
         .map_err(|e| format!("Failed to create covers directory: {}", e))?;
+    Ok(())
+}
+
+/// Built-in presets and backgrounds embedded in the binary, keyed by their
+/// path relative to the app directory
+const BUILTIN_ASSETS: &[(&str, &str)] = &[
+    (
+        "presets/cozy-reading.json",
+        include_str!("../assets/presets/cozy-reading.json"),
+    ),
+    (
+        "presets/focus-mode.json",
+        include_str!("../assets/presets/focus-mode.json"),
+    ),
+    (
+        "presets/night-reading.json",
+        include_str!("../assets/presets/night-reading.json"),
+    ),
+    (
+        "media/backgrounds/fireplace.svg",
+        include_str!("../assets/presets/backgrounds/fireplace.svg"),
+    ),
+    (
+        "media/backgrounds/gradient.svg",
+        include_str!("../assets/presets/backgrounds/gradient.svg"),
+    ),
+    (
+        "media/backgrounds/starry-night.svg",
+        include_str!("../assets/presets/backgrounds/starry-night.svg"),
+    ),
+];
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct BuiltinAssetChanges {
+    /// Built-ins written for the first time
+    pub added: Vec<String>,
+    /// Unmodified copies replaced with the new version
+    pub updated: Vec<String>,
+    /// User-modified copies kept, new version written alongside
+    pub preserved: Vec<String>,
+}
 
-    // Create library.json if it doesn't exist
-    let library_path = app_dir.join("library.json");
-    if !library_path.exists() {
-        let default_library = r#"{
-  "books": [],
-  "collections": [],
-  "tags": []
-}"#;
-        fs::write(&library_path, default_library)
-            .map_err(|e| format!("Failed to create library.json: {}", e))?;
+impl BuiltinAssetChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.preserved.is_empty()
     }
+}
 
-    Ok(())
+fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", md5::compute(data))
 }
 
-/// Copy built-in presets and backgrounds on first run
+/// Hash of the whole embedded asset set, used to detect app updates
+fn builtin_assets_hash() -> String {
+    let mut all = Vec::new();
+    for (rel, content) in BUILTIN_ASSETS {
+        all.extend_from_slice(rel.as_bytes());
+        all.extend_from_slice(content.as_bytes());
+    }
+    content_hash(&all)
+}
+
+/// Copy built-in presets and backgrounds, refreshing them when the embedded
+/// set changed since the last install
 #[tauri::command]
-pub fn copy_builtin_presets() -> Result<(), String> {
+pub fn copy_builtin_presets(app: AppHandle) -> Result<BuiltinAssetChanges, String> {
+    let changes = install_builtin_assets()?;
+
+    if !changes.is_empty() {
+        app.state::<AppState>().invalidate_preset_list();
+        // Runs during setup, before the frontend listens
+        launch::emit_when_ready(&app, "builtin-assets-updated", changes.clone());
+    }
+
+    Ok(changes)
+}
+
+fn install_builtin_assets() -> Result<BuiltinAssetChanges, String> {
     let app_dir = get_app_dir_path()?;
     let presets_dir = app_dir.join("presets");
-    let backgrounds_dir = app_dir.join("media").join("backgrounds");
 
-    // Check if presets already exist (marker file)
+    // Older versions used a marker file instead of meta.json
     let marker_path = presets_dir.join(".initialized");
     if marker_path.exists() {
-        return Ok(()); // Already initialized
-    }
-
-    // Embedded preset JSONs
-    let cozy_reading_json = include_str!("../assets/presets/cozy-reading.json");
-    let focus_mode_json = include_str!("../assets/presets/focus-mode.json");
-    let night_reading_json = include_str!("../assets/presets/night-reading.json");
-
-    // Embedded background images (SVG)
-    let fireplace_svg = include_str!("../assets/presets/backgrounds/fireplace.svg");
-    let gradient_svg = include_str!("../assets/presets/backgrounds/gradient.svg");
-    let starry_night_svg = include_str!("../assets/presets/backgrounds/starry-night.svg");
-
-    // Write preset JSONs
-    fs::write(presets_dir.join("cozy-reading.json"), cozy_reading_json)
-        .map_err(|e| format!("Failed to write cozy-reading.json: {}", e))?;
-    fs::write(presets_dir.join("focus-mode.json"), focus_mode_json)
-        .map_err(|e| format!("Failed to write focus-mode.json: {}", e))?;
-    fs::write(presets_dir.join("night-reading.json"), night_reading_json)
-        .map_err(|e| format!("Failed to write night-reading.json: {}", e))?;
-
-    // Write background images
-    fs::write(backgrounds_dir.join("fireplace.svg"), fireplace_svg)
-        .map_err(|e| format!("Failed to write fireplace.svg: {}", e))?;
-    fs::write(backgrounds_dir.join("gradient.svg"), gradient_svg)
-        .map_err(|e| format!("Failed to write gradient.svg: {}", e))?;
-    fs::write(backgrounds_dir.join("starry-night.svg"), starry_night_svg)
-        .map_err(|e| format!("Failed to write starry-night.svg: {}", e))?;
-
-    // Create marker file
-    fs::write(&marker_path, "").map_err(|e| format!("Failed to create marker file: {}", e))?;
+        meta::update_meta(|m| m.builtin_presets_installed = true)?;
+        let _ = fs::remove_file(&marker_path);
+    }
 
-    Ok(())
+    let mut meta = meta::load_meta();
+    let set_hash = builtin_assets_hash();
+    if meta.builtin_assets_hash.as_deref() == Some(set_hash.as_str()) {
+        return Ok(BuiltinAssetChanges::default()); // Up to date
+    }
+
+    let mut changes = BuiltinAssetChanges::default();
+
+    // Built-ins dropped from a release are not listed here, so the user's
+    // copy is left alone
+    for (rel, content) in BUILTIN_ASSETS {
+        let target = app_dir.join(rel);
+        let new_hash = content_hash(content.as_bytes());
+        let installed_hash = meta.builtin_assets.get(*rel).cloned();
+
+        if installed_hash.as_deref() == Some(new_hash.as_str()) {
+            continue; // Unchanged in this release
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        match fs::read(&target) {
+            Err(_) => {
+                fs::write(&target, content)
+                    .map_err(|e| format!("Failed to write {}: {}", rel, e))?;
+                changes.added.push(rel.to_string());
+            }
+            Ok(current) => {
+                let current_hash = content_hash(&current);
+                if current_hash == new_hash {
+                    // Already identical
+                } else if installed_hash.as_deref() == Some(current_hash.as_str()) {
+                    fs::write(&target, content)
+                        .map_err(|e| format!("Failed to write {}: {}", rel, e))?;
+                    changes.updated.push(rel.to_string());
+                } else {
+                    // Modified by the user (or unknown provenance) — keep it
+                    let updated = updated_copy_path(&target);
+                    fs::write(&updated, content)
+                        .map_err(|e| format!("Failed to write {}: {}", updated.display(), e))?;
+                    changes.preserved.push(rel.to_string());
+                }
+            }
+        }
+
+        meta.builtin_assets.insert(rel.to_string(), new_hash);
+    }
+
+    meta.builtin_assets_hash = Some(set_hash);
+    meta.builtin_presets_installed = true;
+    meta::save_meta(&meta)?;
+
+    Ok(changes)
+}
+
+/// "focus-mode.json" -> "focus-mode (updated).json"
+fn updated_copy_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("preset");
+    let name = match path.extension().and_then(|s| s.to_str()) {
+        Some(ext) => format!("{} (updated).{}", stem, ext),
+        None => format!("{} (updated)", stem),
+    };
+    path.with_file_name(name)
 }
 
 /// Helper function to get app directory path
-fn get_app_dir_path() -> Result<std::path::PathBuf, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+pub fn get_app_dir_path() -> Result<PathBuf, AppError> {
+    resolve_app_dir().map(|status| status.path.clone())
+}
+
+/// Directory where extracted book covers are stored
+pub fn get_covers_dir() -> Result<PathBuf, AppError> {
+    Ok(AppPaths::new(get_app_dir_path()?).covers)
+}
 
-    Ok(home_dir.join(".epub-reader"))
+/// Move covers written by older versions into the canonical covers directory
+/// and point every affected book at the new location
+pub fn migrate_legacy_covers(state: &AppState) -> Result<(), String> {
+    let paths = state.paths()?;
+    let legacy_dir = paths.app_dir.join("covers");
+    if !legacy_dir.is_dir() {
+        return Ok(());
+    }
+
+    let covers_dir = &paths.covers;
+    fs::create_dir_all(covers_dir)
+        .map_err(|e| format!("Failed to create covers directory: {}", e))?;
+
+    let entries = fs::read_dir(&legacy_dir)
+        .map_err(|e| format!("Failed to read legacy covers directory: {}", e))?;
+    for entry in entries.flatten() {
+        let target = covers_dir.join(entry.file_name());
+        if let Err(e) = fs::rename(entry.path(), &target) {
+            eprintln!("Failed to move cover {}: {}", entry.path().display(), e);
+        }
+    }
+
+    if paths.library.exists() || paths.library_json.exists() {
+        let moved = |cover: &str| -> Option<String> {
+            let cover = Path::new(cover);
+            if cover.parent() != Some(legacy_dir.as_path()) {
+                return None;
+            }
+            let moved = covers_dir.join(cover.file_name()?);
+            moved.exists().then(|| moved.to_string_lossy().to_string())
+        };
+
+        let affected = state.with_library(|library| {
+            library.books.iter().any(|b| {
+                b.cover_path.as_deref().and_then(moved).is_some()
+                    || b.thumbnail_path.as_deref().and_then(moved).is_some()
+            })
+        })?;
+
+        if affected {
+            state.update_library(|library| {
+                for book in library.books.iter_mut() {
+                    if let Some(new_path) = book.cover_path.as_deref().and_then(moved) {
+                        book.cover_path = Some(new_path);
+                    }
+                    if let Some(new_path) = book.thumbnail_path.as_deref().and_then(moved) {
+                        book.thumbnail_path = Some(new_path);
+                    }
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    // Only removed once everything has moved out
+    let _ = fs::remove_dir(&legacy_dir);
+
+    Ok(())
 }
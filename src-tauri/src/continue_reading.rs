@@ -0,0 +1,181 @@
+/**
+ * The book to pick up again on the home screen, with where it was left and
+ * how long is left, in one call
+ */
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::Path;
+use tauri::State;
+
+use crate::annotations;
+use crate::covers;
+use crate::crash;
+use crate::library::{self, Book, MediaType, ReadingState};
+use crate::logging;
+use crate::reading_speed;
+use crate::sessions::{self, SessionManager};
+use crate::state::AppState;
+
+/// The cover is inlined as a thumbnail at most this size
+const THUMB_WIDTH: usize = 240;
+const THUMB_HEIGHT: usize = 360;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ContinueCard {
+    pub book: Book,
+    /// data: URL of the cover thumbnail
+    #[serde(rename = "coverData")]
+    pub cover_data: Option<String>,
+    #[serde(rename = "spineIndex")]
+    pub spine_index: Option<usize>,
+    #[serde(rename = "chapterTitle")]
+    pub chapter_title: Option<String>,
+    /// 0 to 100
+    pub percent: f32,
+    #[serde(rename = "chapterMinutesLeft")]
+    pub chapter_minutes_left: Option<f64>,
+    #[serde(rename = "bookMinutesLeft")]
+    pub book_minutes_left: Option<f64>,
+    #[serde(rename = "lastRead")]
+    pub last_read: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ContinueReading {
+    /// None when nothing is being read
+    pub card: Option<ContinueCard>,
+    /// Books passed over because their file is gone
+    pub missing: Vec<Book>,
+}
+
+/// Books to offer, best first: the one read in the open or latest session
+/// unless it is finished, abandoned or archived, then the shelf's Reading
+/// books by when they were last opened
+fn candidates(state: &AppState, open: Option<&str>) -> Result<Vec<(Book, DateTime<Utc>)>, String> {
+    let latest = sessions::load_sessions(&state.paths()?.sessions)
+        .into_iter()
+        .max_by_key(|s| s.last_activity);
+    state.with_library(|library| {
+        let session_book = open
+            .map(|id| (id.to_string(), Utc::now()))
+            .or_else(|| latest.map(|s| (s.book_id, s.last_activity)))
+            .and_then(|(id, at)| {
+                let book = library.books.iter().find(|b| b.id == id)?;
+                let done = book.archived
+                    || matches!(
+                        book.reading_state,
+                        ReadingState::Finished | ReadingState::Abandoned
+                    );
+                (!done).then(|| (book.clone(), at))
+            });
+
+        let mut reading: Vec<&Book> = library
+            .books
+            .iter()
+            .filter(|b| b.reading_state == ReadingState::Reading && !b.archived)
+            .collect();
+        reading.sort_by(|a, b| b.last_opened.cmp(&a.last_opened));
+
+        let mut candidates: Vec<(Book, DateTime<Utc>)> = session_book.into_iter().collect();
+        for book in reading {
+            if candidates.iter().all(|(b, _)| b.id != book.id) {
+                candidates.push((book.clone(), book.last_opened));
+            }
+        }
+        candidates
+    })
+}
+
+/// Minutes left in the chapter at the book's progress, from the words per
+/// chapter counted on import; the whole chapter when they weren't
+fn chapter_minutes_left(book: &Book, index: usize, wpm: f64) -> Option<f64> {
+    let cached = book.chapters.as_ref().map(|c| c.words.as_slice());
+    let words_left = match cached.filter(|words| index < words.len()) {
+        Some(words) => {
+            let total: u64 = words.iter().sum();
+            let start: u64 = words[..index].iter().sum();
+            let end = start + words[index];
+            let position = (book.progress.clamp(0.0, 1.0) as f64 * total as f64) as u64;
+            end - position.clamp(start, end)
+        }
+        None => crash::catch_panic("counting chapter words", || {
+            reading_speed::chapter_words(&book.file_path, index)
+        })
+        .unwrap_or_else(|e| {
+            logging::warn(&e);
+            None
+        })?,
+    };
+    Some(words_left as f64 / wpm)
+}
+
+fn card(state: &AppState, book: Book, last_read: DateTime<Utc>) -> Result<ContinueCard, String> {
+    let speed = reading_speed::speed_for(state, Some(&book.id))?;
+    let is_text = book.media_type == MediaType::Text;
+    let spine_index = book
+        .cfi
+        .as_deref()
+        .and_then(annotations::spine_index)
+        .filter(|_| is_text);
+    let chapter_title = spine_index.and_then(|index| {
+        crash::catch_panic("reading the table of contents", || {
+            library::spine_titles(&book.file_path)
+        })
+        .ok()
+        .flatten()?
+        .into_iter()
+        .nth(index)?
+        .1
+    });
+    let cover_data = book
+        .cover_path
+        .as_deref()
+        .and_then(|path| covers::thumbnail_png(Path::new(path), THUMB_WIDTH, THUMB_HEIGHT))
+        .map(|png| {
+            format!(
+                "data:image/png;base64,{}",
+                base64::engine::general_purpose::STANDARD.encode(png)
+            )
+        });
+
+    Ok(ContinueCard {
+        cover_data,
+        chapter_title,
+        percent: (book.progress.clamp(0.0, 1.0) * 100.0).round(),
+        chapter_minutes_left: spine_index
+            .and_then(|index| chapter_minutes_left(&book, index, speed.wpm)),
+        book_minutes_left: book
+            .word_count
+            .filter(|_| is_text)
+            .map(|words| words as f64 * (1.0 - book.progress.clamp(0.0, 1.0) as f64) / speed.wpm),
+        last_read: last_read.max(book.last_opened),
+        spine_index,
+        book,
+    })
+}
+
+/// The book most likely to be continued, with its chapter, progress and
+/// time left. Books whose file is gone are skipped and listed.
+#[tauri::command]
+pub fn get_continue_reading(
+    state: State<'_, AppState>,
+    sessions: State<'_, SessionManager>,
+) -> Result<ContinueReading, String> {
+    let open = sessions::open_session(&sessions).map(|s| s.book_id);
+    let mut missing = Vec::new();
+    for (book, last_read) in candidates(&state, open.as_deref())? {
+        if !Path::new(&book.file_path).is_file() {
+            missing.push(book);
+            continue;
+        }
+        return Ok(ContinueReading {
+            card: Some(card(&state, book, last_read)?),
+            missing,
+        });
+    }
+    Ok(ContinueReading {
+        card: None,
+        missing,
+    })
+}
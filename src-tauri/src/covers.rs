@@ -0,0 +1,497 @@
+/**
+ * Cover downloads from Open Library, refreshing covers from wherever
+ * they came from, and the small thumbnails the library grid shows
+ */
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat, RgbImage};
+use serde::Serialize;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audiobook;
+use crate::crash;
+use crate::isbn;
+use crate::library::{self, Book, CoverSource, MediaType};
+use crate::logging;
+use crate::open_library;
+use crate::state::AppState;
+
+/// Largest cover accepted from the covers API
+const MAX_COVER_BYTES: usize = 10 * 1024 * 1024;
+/// Covers smaller than this on either side are placeholders, not covers
+const MIN_COVER_SIDE: u32 = 16;
+/// Covers are scaled down to fit this for the library grid
+const THUMBNAIL_WIDTH: usize = 200;
+const THUMBNAIL_HEIGHT: usize = 300;
+const THUMBNAIL_QUALITY: u8 = 85;
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct CoverSummary {
+    pub extracted: usize,
+    pub downloaded: usize,
+    /// Books whose file has no cover, the current one kept
+    #[serde(rename = "withoutCover")]
+    pub without_cover: usize,
+    pub failed: usize,
+}
+
+/// Payload of "cover-progress", sent before each book's cover is made again
+#[derive(Debug, Serialize, Clone)]
+struct CoverProgress {
+    current: usize,
+    total: usize,
+    #[serde(rename = "bookId")]
+    book_id: String,
+    title: String,
+}
+
+/// Mime type and size of a JPEG, PNG or GIF, read from its header
+pub fn image_info(data: &[u8]) -> Option<(&'static str, u32, u32)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+        return Some(("image/png", width, height));
+    }
+    if data.starts_with(b"GIF8") {
+        let width = u16::from_le_bytes(data.get(6..8)?.try_into().ok()?);
+        let height = u16::from_le_bytes(data.get(8..10)?.try_into().ok()?);
+        return Some(("image/gif", width as u32, height as u32));
+    }
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    // Walk the JPEG segments up to the frame header that holds the size
+    let mut pos = 2;
+    loop {
+        while *data.get(pos)? != 0xFF {
+            pos += 1;
+        }
+        while *data.get(pos)? == 0xFF {
+            pos += 1;
+        }
+        let marker = *data.get(pos)?;
+        pos += 1;
+        if marker == 0xD8 || (0xD0..=0xD7).contains(&marker) {
+            continue;
+        }
+        let length = u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?) as usize;
+        // SOF0 to SOF15, except the DHT, JPG and DAC markers among them
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            let height = u16::from_be_bytes(data.get(pos + 3..pos + 5)?.try_into().ok()?);
+            let width = u16::from_be_bytes(data.get(pos + 5..pos + 7)?.try_into().ok()?);
+            return Some(("image/jpeg", width as u32, height as u32));
+        }
+        pos += length;
+    }
+}
+
+/// A cover decoded to RGB, with its width and height
+pub fn decode_rgb(path: &Path) -> Option<(Vec<u8>, usize, usize)> {
+    decode_rgb_data(&fs::read(path).ok()?)
+}
+
+/// A PNG, JPEG or WebP image decoded to RGB, with its width and height
+pub fn decode_rgb_data(data: &[u8]) -> Option<(Vec<u8>, usize, usize)> {
+    let rgb = decode(data)?.into_rgb8();
+    let (width, height) = rgb.dimensions();
+    Some((rgb.into_raw(), width as usize, height as usize))
+}
+
+fn decode(data: &[u8]) -> Option<DynamicImage> {
+    image::load_from_memory(data)
+        .ok()
+        .filter(|image| image.width() > 0 && image.height() > 0)
+}
+
+/// An image scaled down to fit `max_width`×`max_height`, never up
+fn scaled(data: &[u8], max_width: usize, max_height: usize) -> Option<RgbImage> {
+    let image = decode(data)?;
+    let (max_width, max_height) = (max_width as u32, max_height as u32);
+    if image.width() <= max_width && image.height() <= max_height {
+        return Some(image.into_rgb8());
+    }
+    Some(image.thumbnail(max_width, max_height).into_rgb8())
+}
+
+/// A cover scaled down to fit `max_width`×`max_height`, as a PNG
+pub fn thumbnail_png(path: &Path, max_width: usize, max_height: usize) -> Option<Vec<u8>> {
+    thumbnail_png_data(&fs::read(path).ok()?, max_width, max_height).map(|(png, _, _)| png)
+}
+
+/// An image scaled down to fit `max_width`×`max_height`, as a PNG, with
+/// its new width and height
+pub fn thumbnail_png_data(
+    data: &[u8],
+    max_width: usize,
+    max_height: usize,
+) -> Option<(Vec<u8>, usize, usize)> {
+    let thumbnail = scaled(data, max_width, max_height)?;
+    let mut png = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .ok()?;
+    let (width, height) = thumbnail.dimensions();
+    Some((png, width as usize, height as usize))
+}
+
+/// An image scaled down to fit `max_width`×`max_height`, as a JPEG
+fn thumbnail_jpeg_data(data: &[u8], max_width: usize, max_height: usize) -> Option<Vec<u8>> {
+    let thumbnail = scaled(data, max_width, max_height)?;
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, THUMBNAIL_QUALITY)
+        .encode_image(&thumbnail)
+        .ok()?;
+    Some(jpeg)
+}
+
+/// The grid thumbnail of the cover saved as `{id}.{ext}`, next to it
+fn thumbnail_file(covers_dir: &Path, id: &str) -> PathBuf {
+    covers_dir.join(format!("{}.thumb.jpg", id))
+}
+
+/// Write the grid thumbnail of a cover image into the covers directory.
+/// An image that can't be decoded gets none, and the thumbnail of an
+/// earlier cover is removed.
+pub fn write_thumbnail(covers_dir: &Path, id: &str, data: &[u8]) -> Option<String> {
+    let file = thumbnail_file(covers_dir, id);
+    let written = thumbnail_jpeg_data(data, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)
+        .and_then(|jpeg| fs::write(&file, jpeg).ok());
+    if written.is_none() {
+        let _ = fs::remove_file(&file);
+        return None;
+    }
+    Some(file.to_string_lossy().to_string())
+}
+
+/// The thumbnail written alongside a saved cover, if there is one
+pub fn thumbnail_of(cover: &str) -> Option<String> {
+    let cover = Path::new(cover);
+    let file = thumbnail_file(cover.parent()?, &cover.file_stem()?.to_string_lossy());
+    file.is_file().then(|| file.to_string_lossy().to_string())
+}
+
+/// Download a cover and check it is a real image, not the 1×1 pixel the
+/// covers API answers with when it has nothing
+async fn fetch_cover(url: &str) -> Result<(Vec<u8>, &'static str), String> {
+    let url = reqwest::Url::parse(url).map_err(|e| format!("Invalid cover URL: {}", e))?;
+    let response = open_library::get(url).await?;
+    if response
+        .content_length()
+        .is_some_and(|length| length as usize > MAX_COVER_BYTES)
+    {
+        return Err("Cover is too large".to_string());
+    }
+    let data = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to download cover: {}", e))?;
+    if data.len() > MAX_COVER_BYTES {
+        return Err("Cover is too large".to_string());
+    }
+
+    let (mime, width, height) = image_info(&data)
+        .ok_or_else(|| "Open Library sent something that isn't an image".to_string())?;
+    if width < MIN_COVER_SIDE || height < MIN_COVER_SIDE {
+        return Err(format!(
+            "Open Library has no cover ({}×{} placeholder)",
+            width, height
+        ));
+    }
+    Ok((data.to_vec(), mime))
+}
+
+/// Cover URLs to try for a book: its confirmed Open Library work first,
+/// then its ISBN
+async fn cover_urls(book: &Book) -> Vec<String> {
+    let mut urls = Vec::new();
+    if let Some(key) = &book.open_library_key {
+        match open_library::work_covers(key).await {
+            Ok(covers) => urls.extend(covers.first().copied().map(open_library::cover_url_for_id)),
+            Err(e) => logging::debug(&format!("No covers listed for {}: {}", key, e)),
+        }
+    }
+    if let Some(isbn) = book
+        .isbn
+        .as_deref()
+        .and_then(|i| isbn::normalize_isbn(i).ok())
+    {
+        urls.push(open_library::cover_url_for_isbn(&isbn));
+    }
+    urls
+}
+
+fn find_book(state: &AppState, book_id: &str) -> Result<Book, String> {
+    let book_id = state.resolve_book_id(book_id);
+    state
+        .with_library(|library| library.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book with id '{}' not found", book_id))
+}
+
+/// Point a book at a newly saved cover. A cover saved under another
+/// extension before is removed.
+fn set_cover(
+    app: &AppHandle,
+    state: &AppState,
+    book_id: &str,
+    cover: String,
+    source: CoverSource,
+) -> Result<Book, String> {
+    let covers_dir = state.paths()?.covers.clone();
+    let book = library::update_book(app, state, book_id, |book| {
+        if let Some(old) = book.cover_path.take().filter(|old| *old != cover) {
+            if Path::new(&old).starts_with(&covers_dir) {
+                let _ = fs::remove_file(&old);
+            }
+        }
+        book.thumbnail_path = thumbnail_of(&cover);
+        book.cover_path = Some(cover.clone());
+        book.cover_source = source;
+    })?;
+    if let Ok(mut covers) = state.covers.lock() {
+        // The cache only remembers covers read from the book file
+        match source {
+            CoverSource::Extracted => covers.insert(book.id.clone(), cover),
+            CoverSource::Remote => covers.remove(&book.id),
+        }
+    }
+    Ok(book)
+}
+
+/// Fetch the large cover of a book from Open Library, by the work the user
+/// confirmed for it or by its ISBN, and use it as the book's cover
+#[tauri::command]
+pub async fn download_cover(app: AppHandle, book_id: String) -> Result<Book, String> {
+    let state = app.state::<AppState>();
+    let book = find_book(&state, &book_id)?;
+    if book.open_library_key.is_none() && book.isbn.is_none() {
+        return Err(format!(
+            "'{}' has no ISBN or Open Library match to look a cover up by",
+            book.title
+        ));
+    }
+    let urls = cover_urls(&book).await;
+
+    let mut last_error = "Open Library has no cover for this book".to_string();
+    for url in urls {
+        match fetch_cover(&url).await {
+            Ok((data, mime)) => {
+                let covers_dir = state.paths()?.covers.clone();
+                let cover = library::save_cover(&covers_dir, &book.id, &data, mime)
+                    .ok_or_else(|| "Failed to save the downloaded cover".to_string())?;
+                return set_cover(&app, &state, &book.id, cover, CoverSource::Remote);
+            }
+            Err(e) => {
+                logging::debug(&format!("No cover at {}: {}", url, e));
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Read the cover from the book file again
+async fn extract(book: &Book, covers_dir: &Path) -> Result<Option<String>, String> {
+    let (path, id, covers_dir) = (
+        book.file_path.clone(),
+        book.id.clone(),
+        covers_dir.to_path_buf(),
+    );
+    let media_type = book.media_type;
+    tauri::async_runtime::spawn_blocking(move || {
+        // A malformed file must not take the app down with it
+        crash::catch_panic("extracting the cover", || match media_type {
+            MediaType::Text => library::extract_cover(&path, &id, &covers_dir),
+            MediaType::Audio => audiobook::extract_cover(&path, &id, &covers_dir),
+        })
+    })
+    .await
+    .map_err(|e| format!("Cover task failed: {}", e))?
+}
+
+/// Make a book's cover again; None when its file has no cover and none
+/// was downloaded
+async fn refresh(app: &AppHandle, book: &Book, download: bool) -> Result<Option<Book>, String> {
+    if book.cover_source == CoverSource::Remote {
+        return download_cover(app.clone(), book.id.clone()).await.map(Some);
+    }
+    if !Path::new(&book.file_path).is_file() {
+        return Err(format!("The file of '{}' is missing", book.title));
+    }
+
+    let state = app.state::<AppState>();
+    let covers_dir = state.paths()?.covers.clone();
+    if let Some(cover) = extract(book, &covers_dir).await? {
+        return set_cover(app, &state, &book.id, cover, CoverSource::Extracted).map(Some);
+    }
+    if download {
+        return download_cover(app.clone(), book.id.clone()).await.map(Some);
+    }
+    Ok(None)
+}
+
+/// Make a book's cover again from where it came from: the book file, with
+/// every extraction strategy of an import, or Open Library. With
+/// `download`, a book file without a cover falls back to Open Library. A
+/// failed download keeps the current cover.
+#[tauri::command]
+pub async fn refresh_cover(
+    app: AppHandle,
+    book_id: String,
+    download: Option<bool>,
+) -> Result<Book, String> {
+    let book = find_book(&app.state::<AppState>(), &book_id)?;
+    refresh(&app, &book, download.unwrap_or(false))
+        .await?
+        .ok_or_else(|| format!("No cover found in '{}'", book.title))
+}
+
+/// Refresh every cover in the library. With `download_missing`, books
+/// whose file has no cover get one from Open Library. "cover-progress" is
+/// emitted before each book.
+#[tauri::command]
+pub async fn refresh_all_covers(
+    app: AppHandle,
+    download_missing: Option<bool>,
+) -> Result<CoverSummary, String> {
+    let books: Vec<Book> = app
+        .state::<AppState>()
+        .with_library(|library| library.books.clone())?;
+
+    let mut summary = CoverSummary::default();
+    let total = books.len();
+    for (index, book) in books.iter().enumerate() {
+        let _ = app.emit(
+            "cover-progress",
+            CoverProgress {
+                current: index + 1,
+                total,
+                book_id: book.id.clone(),
+                title: book.title.clone(),
+            },
+        );
+        match refresh(&app, book, download_missing.unwrap_or(false)).await {
+            Ok(Some(book)) if book.cover_source == CoverSource::Remote => summary.downloaded += 1,
+            Ok(Some(_)) => summary.extracted += 1,
+            Ok(None) => summary.without_cover += 1,
+            Err(e) => {
+                logging::warn(&format!("Cover of {} not refreshed: {}", book.id, e));
+                summary.failed += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Make the grid thumbnail of a cover saved before thumbnails were; the
+/// original when it can't be scaled
+fn lazy_thumbnail(state: &AppState, book: &Book, cover: String) -> Result<String, String> {
+    let covers_dir = state.paths()?.covers.clone();
+    let thumbnail = fs::read(&cover).ok().and_then(|data| {
+        crash::catch_panic("making the cover thumbnail", || {
+            write_thumbnail(&covers_dir, &book.id, &data)
+        })
+        .unwrap_or_else(|e| {
+            logging::warn(&e);
+            None
+        })
+    });
+    let Some(thumbnail) = thumbnail else {
+        logging::debug(&format!("No thumbnail for {}, using the cover", cover));
+        return Ok(cover);
+    };
+    state.update_book_in_memory(&book.id, |book| {
+        book.thumbnail_path = Some(thumbnail.clone());
+        Ok(())
+    })?;
+    Ok(thumbnail)
+}
+
+/// The path of a book's cover at `size`: "thumbnail" for the library grid,
+/// or "original", the default. None when the book has no cover.
+#[tauri::command]
+pub async fn get_cover(
+    app: AppHandle,
+    book_id: String,
+    size: Option<String>,
+) -> Result<Option<String>, String> {
+    let book = find_book(&app.state::<AppState>(), &book_id)?;
+    let Some(cover) = book.cover_path.clone() else {
+        return Ok(None);
+    };
+    match size.as_deref().unwrap_or("original") {
+        "original" => return Ok(Some(cover)),
+        "thumbnail" => {}
+        other => return Err(format!("Unknown cover size: {}", other)),
+    }
+    if let Some(thumbnail) = book
+        .thumbnail_path
+        .clone()
+        .filter(|thumbnail| Path::new(thumbnail).is_file())
+    {
+        return Ok(Some(thumbnail));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        lazy_thumbnail(&app.state::<AppState>(), &book, cover).map(Some)
+    })
+    .await
+    .map_err(|e| format!("Thumbnail task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::{book, temp_dir};
+    use image::Rgb;
+
+    fn png_of(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        RgbImage::from_pixel(width, height, Rgb([200, 40, 40]))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn thumbnails_fit_the_box_and_keep_the_shape() {
+        let (png, width, height) = thumbnail_png_data(&png_of(400, 600), 100, 100).unwrap();
+        assert_eq!((width, height), (67, 100));
+        let decoded = image::load_from_memory(&png).unwrap().into_rgb8();
+        assert_eq!(decoded.dimensions(), (67, 100));
+        assert_eq!(decoded.get_pixel(30, 50), &Rgb([200, 40, 40]));
+    }
+
+    #[test]
+    fn small_images_are_not_scaled_up() {
+        let (_, width, height) = thumbnail_png_data(&png_of(40, 60), 100, 100).unwrap();
+        assert_eq!((width, height), (40, 60));
+        assert!(thumbnail_png_data(b"not an image", 100, 100).is_none());
+    }
+
+    #[test]
+    fn grid_thumbnails_are_jpegs() {
+        let dir = temp_dir("covers_thumbnail");
+        let thumbnail = write_thumbnail(&dir, "a", &png_of(400, 600)).unwrap();
+        assert!(thumbnail.ends_with("a.thumb.jpg"));
+        let data = fs::read(&thumbnail).unwrap();
+        assert_eq!(image_info(&data), Some(("image/jpeg", 200, 300)));
+    }
+
+    #[test]
+    fn a_corrupt_cover_is_used_as_its_own_thumbnail() {
+        let dir = temp_dir("covers_corrupt");
+        let state = AppState::in_dir(&dir);
+        let covers_dir = state.paths().unwrap().covers.clone();
+        fs::create_dir_all(&covers_dir).unwrap();
+        let cover = covers_dir.join("a.jpg");
+        fs::write(&cover, b"\xFF\xD8 truncated").unwrap();
+        // A thumbnail of an earlier cover doesn't outlive it
+        fs::write(thumbnail_file(&covers_dir, "a"), png_of(2, 3)).unwrap();
+
+        let cover = cover.to_string_lossy().to_string();
+        assert_eq!(lazy_thumbnail(&state, &book("a"), cover.clone()), Ok(cover));
+        assert!(!thumbnail_file(&covers_dir, "a").exists());
+    }
+}
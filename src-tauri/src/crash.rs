@@ -0,0 +1,146 @@
+/**
+ * Crash reports: panics are written to crashes/crash-<timestamp>.txt
+ */
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::PathBuf;
+
+use crate::config;
+use crate::logging;
+use crate::meta;
+
+/// Log lines included at the end of a report
+const LOG_TAIL_LINES: usize = 30;
+
+thread_local! {
+    /// Set while running inside `catch_panic`, where a panic is recovered
+    /// and is not a crash
+    static GUARDED: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CrashReport {
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    pub path: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    pub contents: String,
+}
+
+fn crashes_dir() -> Result<PathBuf, String> {
+    Ok(config::get_app_dir_path()?.join("crashes"))
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    match info.location() {
+        Some(location) => format!("{} at {}:{}", payload, location.file(), location.line()),
+        None => payload,
+    }
+}
+
+fn write_crash_report(info: &PanicHookInfo) -> Result<PathBuf, String> {
+    let dir = crashes_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create crashes directory: {}", e))?;
+
+    let now = Local::now();
+    let path = dir.join(format!("crash-{}.txt", now.format("%Y%m%d-%H%M%S")));
+    let thread = std::thread::current();
+
+    let report = format!(
+        "Epilogue {} crashed\nTime: {}\nOS: {} {}\nThread: {}\n\nPanic: {}\n\nBacktrace:\n{}\n\nRecent log:\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        now.to_rfc3339(),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        thread.name().unwrap_or("unnamed"),
+        panic_message(info),
+        Backtrace::force_capture(),
+        logging::tail(LOG_TAIL_LINES).join("\n"),
+    );
+
+    fs::write(&path, report).map_err(|e| format!("Failed to write crash report: {}", e))?;
+    Ok(path)
+}
+
+/// Write a crash report for every unexpected panic, then run the default hook
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if GUARDED.with(|g| g.get()) {
+            logging::error(&format!("Recovered from panic: {}", panic_message(info)));
+            return;
+        }
+
+        match write_crash_report(info) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("{}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+/// Run risky parsing code, turning a panic into an error
+pub fn catch_panic<T>(what: &str, f: impl FnOnce() -> T) -> Result<T, String> {
+    let was_guarded = GUARDED.with(|g| g.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARDED.with(|g| g.set(was_guarded));
+
+    result.map_err(|_| format!("Unexpected error while {}", what))
+}
+
+/// The newest crash report, if the user hasn't been shown it yet
+#[tauri::command]
+pub fn get_last_crash_report() -> Result<Option<CrashReport>, String> {
+    let Ok(entries) = fs::read_dir(crashes_dir()?) else {
+        return Ok(None);
+    };
+
+    // Timestamped names sort chronologically
+    let Some(path) = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("crash-") && n.ends_with(".txt"))
+        })
+        .max()
+    else {
+        return Ok(None);
+    };
+
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if meta::load_meta().last_seen_crash_report.as_deref() == Some(file_name.as_str()) {
+        return Ok(None);
+    }
+
+    let contents =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read crash report: {}", e))?;
+    let created_at = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now());
+
+    meta::update_meta(|m| m.last_seen_crash_report = Some(file_name.clone()))?;
+
+    Ok(Some(CrashReport {
+        file_name,
+        path: path.to_string_lossy().to_string(),
+        created_at,
+        contents,
+    }))
+}
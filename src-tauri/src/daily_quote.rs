@@ -0,0 +1,203 @@
+/**
+ * Quote of the day: one highlight a day from across the library
+ */
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tauri::State;
+
+use crate::annotations::{self, Highlight};
+use crate::config;
+use crate::logging;
+use crate::meta;
+use crate::state::AppState;
+
+/// Text beyond this length makes a quote no more likely
+const MAX_COUNTED_CHARS: usize = 600;
+/// Highlights older than this many months are no more likely
+const MAX_AGE_MONTHS: f64 = 12.0;
+/// A shown quote is fully back in the running after this many days
+const REPEAT_AFTER_DAYS: f64 = 90.0;
+
+/// Where a quote lives, to open the reader at it
+#[derive(Debug, Serialize, Clone)]
+pub struct QuoteLink {
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    pub cfi: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DailyQuote {
+    #[serde(rename = "highlightId")]
+    pub highlight_id: String,
+    pub text: String,
+    pub title: String,
+    pub author: String,
+    pub chapter: Option<String>,
+    pub link: QuoteLink,
+}
+
+struct Candidate {
+    highlight: Highlight,
+    book_id: String,
+    title: String,
+    author: String,
+}
+
+impl Candidate {
+    /// Longer, older and less recently shown highlights weigh more
+    fn weight(&self, last_shown: Option<&DateTime<Utc>>, now: DateTime<Utc>) -> f64 {
+        let length = (self.highlight.text.chars().count().min(MAX_COUNTED_CHARS) as f64).sqrt();
+        let months = (now - self.highlight.created_at).num_days().max(0) as f64 / 30.0;
+        let age = 1.0 + months.min(MAX_AGE_MONTHS);
+        let freshness = last_shown.map_or(1.0, |shown| {
+            ((now - *shown).num_days().max(0) as f64 / REPEAT_AFTER_DAYS).min(1.0)
+        });
+        length * age * freshness
+    }
+
+    fn into_quote(self) -> DailyQuote {
+        DailyQuote {
+            highlight_id: self.highlight.id,
+            text: self.highlight.text,
+            title: self.title,
+            author: self.author,
+            chapter: self.highlight.chapter,
+            link: QuoteLink {
+                book_id: self.book_id,
+                cfi: self.highlight.cfi,
+            },
+        }
+    }
+}
+
+/// Every highlight with text in a book on the shelf, ordered by id so the
+/// daily pick doesn't depend on the order files are read in
+fn candidates(state: &AppState) -> Result<Vec<Candidate>, String> {
+    let books: Vec<(String, String, String)> = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .map(|b| (b.id.clone(), b.title.clone(), b.author.clone()))
+            .collect()
+    })?;
+
+    let mut candidates = Vec::new();
+    for (book_id, title, author) in books {
+        let annotations = match annotations::load_annotations(state, &book_id) {
+            Ok(annotations) => annotations,
+            Err(e) => {
+                logging::warn(&format!("Skipping highlights of {}: {}", book_id, e));
+                continue;
+            }
+        };
+        candidates.extend(
+            annotations
+                .highlights
+                .into_iter()
+                .filter(|h| !h.text.trim().is_empty())
+                .map(|highlight| Candidate {
+                    highlight,
+                    book_id: book_id.clone(),
+                    title: title.clone(),
+                    author: author.clone(),
+                }),
+        );
+    }
+    candidates.sort_by(|a, b| a.highlight.id.cmp(&b.highlight.id));
+    Ok(candidates)
+}
+
+/// Index of a candidate picked by `roll` (0 to 1) in proportion to its
+/// weight. If every quote was shown too recently to count, all count alike.
+fn pick(weights: &[f64], roll: f64) -> Option<usize> {
+    if weights.is_empty() {
+        return None;
+    }
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return Some(((roll * weights.len() as f64) as usize).min(weights.len() - 1));
+    }
+
+    let mut target = roll * total;
+    for (index, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return Some(index);
+        }
+        target -= weight;
+    }
+    weights.iter().rposition(|w| *w > 0.0)
+}
+
+/// A number from 0 to 1 that is the same all day
+fn day_roll(date: NaiveDate) -> f64 {
+    let digest = md5::compute(format!("epilogue-quote-{}", date).as_bytes());
+    let seed = u64::from_be_bytes(digest.0[..8].try_into().unwrap_or_default());
+    (seed >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Today's quote, the same one all day. Returns nothing when the library
+/// has no highlights.
+#[tauri::command]
+pub fn get_quote_of_the_day(state: State<'_, AppState>) -> Result<Option<DailyQuote>, String> {
+    let today = Local::now().date_naive();
+    let mut candidates = candidates(&state)?;
+    let meta = meta::load_meta();
+
+    if let Some((date, id)) = &meta.daily_quote {
+        if *date == today {
+            if let Some(index) = candidates.iter().position(|c| &c.highlight.id == id) {
+                return Ok(Some(candidates.swap_remove(index).into_quote()));
+            }
+        }
+    }
+
+    let now = Utc::now();
+    let weights: Vec<f64> = candidates
+        .iter()
+        .map(|c| c.weight(meta.quote_last_shown.get(&c.highlight.id), now))
+        .collect();
+    let Some(index) = pick(&weights, day_roll(today)) else {
+        return Ok(None);
+    };
+    let quote = candidates.swap_remove(index).into_quote();
+
+    // Safe mode writes nothing to the data directory
+    if !config::is_safe_mode() {
+        let mut last_shown: BTreeMap<String, DateTime<Utc>> = meta
+            .quote_last_shown
+            .into_iter()
+            .filter(|(id, _)| candidates.iter().any(|c| &c.highlight.id == id))
+            .collect();
+        last_shown.insert(quote.highlight_id.clone(), now);
+        meta::update_meta(|m| {
+            m.daily_quote = Some((today, quote.highlight_id.clone()));
+            m.quote_last_shown = last_shown;
+        })?;
+    }
+    Ok(Some(quote))
+}
+
+/// Another quote, picked the same way. Doesn't count as shown, so
+/// tomorrow's quote is unaffected. `current` is skipped when there is
+/// anything else to pick.
+#[tauri::command]
+pub fn shuffle_quote(
+    state: State<'_, AppState>,
+    current: Option<String>,
+) -> Result<Option<DailyQuote>, String> {
+    let mut candidates = candidates(&state)?;
+    if candidates.len() > 1 {
+        candidates.retain(|c| Some(&c.highlight.id) != current.as_ref());
+    }
+
+    let meta = meta::load_meta();
+    let now = Utc::now();
+    let weights: Vec<f64> = candidates
+        .iter()
+        .map(|c| c.weight(meta.quote_last_shown.get(&c.highlight.id), now))
+        .collect();
+    Ok(pick(&weights, rand::random::<f64>())
+        .map(|index| candidates.swap_remove(index).into_quote()))
+}
@@ -0,0 +1,153 @@
+/**
+ * epilogue:// deep links for books and presets
+ */
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Url};
+
+use crate::launch;
+use crate::logging;
+use crate::state::AppState;
+
+pub const SCHEME: &str = "epilogue";
+
+/// Longest CFI or preset code accepted from a link
+const MAX_CFI_LEN: usize = 1024;
+const MAX_PRESET_CODE_LEN: usize = 16 * 1024;
+
+/// A validated deep link, emitted to the frontend as "deep-link"
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "kind")]
+pub enum DeepLink {
+    /// epilogue://book/<id>?cfi=...
+    #[serde(rename = "openBook")]
+    OpenBook {
+        #[serde(rename = "bookId")]
+        book_id: String,
+        cfi: Option<String>,
+    },
+    /// epilogue://preset/import?code=...
+    #[serde(rename = "importPreset")]
+    ImportPreset { code: String },
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DeepLinkError {
+    pub url: String,
+    pub reason: String,
+}
+
+/// Parse and validate an incoming link. Anything not recognised is rejected.
+pub fn parse_deep_link(url: &Url) -> Result<DeepLink, String> {
+    if url.scheme() != SCHEME {
+        return Err(format!("Unsupported scheme: {}", url.scheme()));
+    }
+
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let query = |key: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    };
+
+    match (url.host_str(), segments.as_slice()) {
+        (Some("book"), [id]) => {
+            if !is_book_id(id) {
+                return Err(format!("Invalid book id: {}", id));
+            }
+            let cfi = query("cfi");
+            if let Some(cfi) = &cfi {
+                if !is_cfi(cfi) {
+                    return Err("Invalid reading position".to_string());
+                }
+            }
+            Ok(DeepLink::OpenBook {
+                book_id: id.to_string(),
+                cfi,
+            })
+        }
+        (Some("preset"), ["import"]) => {
+            let code = query("code").ok_or("Missing preset code")?;
+            if !is_preset_code(&code) {
+                return Err("Invalid preset code".to_string());
+            }
+            Ok(DeepLink::ImportPreset { code })
+        }
+        _ => Err("Unknown link".to_string()),
+    }
+}
+
+/// Book ids are the md5 hex digest of the book file, or of its path for
+/// books added before
+fn is_book_id(id: &str) -> bool {
+    id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_cfi(cfi: &str) -> bool {
+    cfi.len() <= MAX_CFI_LEN
+        && cfi.starts_with("epubcfi(")
+        && cfi.ends_with(')')
+        && !cfi.chars().any(|c| c.is_control())
+}
+
+/// Preset codes are URL-safe base64
+fn is_preset_code(code: &str) -> bool {
+    !code.is_empty()
+        && code.len() <= MAX_PRESET_CODE_LEN
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '='))
+}
+
+fn book_exists(state: &AppState, book_id: &str) -> bool {
+    state
+        .with_library(|lib| lib.books.iter().any(|b| b.id == book_id))
+        .unwrap_or(false)
+}
+
+/// Handle links from the OS: bring up the main window, then route each link
+pub fn handle_urls(app: &AppHandle, urls: Vec<Url>) {
+    let urls: Vec<Url> = urls.into_iter().filter(|u| u.scheme() == SCHEME).collect();
+    if urls.is_empty() {
+        return;
+    }
+
+    launch::focus_main_window(app);
+
+    for url in urls {
+        // Links to a book from before it had a content id find it by alias
+        let result = parse_deep_link(&url)
+            .map(|link| match link {
+                DeepLink::OpenBook { book_id, cfi } => DeepLink::OpenBook {
+                    book_id: app.state::<AppState>().resolve_book_id(&book_id),
+                    cfi,
+                },
+                other => other,
+            })
+            .and_then(|link| match &link {
+                DeepLink::OpenBook { book_id, .. }
+                    if !book_exists(&app.state::<AppState>(), book_id) =>
+                {
+                    Err("This book is not in your library".to_string())
+                }
+                _ => Ok(link),
+            });
+
+        match result {
+            Ok(link) => launch::emit_when_ready(app, "deep-link", link),
+            Err(reason) => {
+                logging::warn(&format!("Rejected deep link: {}", reason));
+                launch::emit_when_ready(
+                    app,
+                    "deep-link-error",
+                    DeepLinkError {
+                        url: url.to_string(),
+                        reason,
+                    },
+                );
+            }
+        }
+    }
+}
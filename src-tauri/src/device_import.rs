@@ -0,0 +1,514 @@
+/**
+ * Import highlights from e-readers: Kobo's KoboReader.sqlite and Kindle's
+ * "My Clippings.txt"
+ */
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, State};
+
+use crate::annotations::{self, Highlight};
+use crate::crash;
+use crate::file_access;
+use crate::library::{self, Book, ImportedPosition, MediaType};
+use crate::logging;
+use crate::quote;
+use crate::state::AppState;
+
+/// Kindle clippings are separated by a line of these
+const CLIPPING_SEPARATOR: &str = "==========";
+/// Rough size of a Kindle location in bytes of book content
+const BYTES_PER_KINDLE_LOCATION: usize = 128;
+/// An imported position stops short of finishing the book; the furthest
+/// location seen is only a guess at where the book ends
+const MAX_IMPORTED_PROGRESS: f32 = 0.98;
+
+/// A highlight read from a device, before it is matched to a book
+struct DeviceHighlight {
+    title: String,
+    author: String,
+    text: String,
+    note: Option<String>,
+    position: Position,
+    created_at: Option<DateTime<Utc>>,
+    source_id: String,
+}
+
+/// The latest Kindle bookmark in a book
+struct DeviceBookmark {
+    title: String,
+    author: String,
+    location: usize,
+    /// Bookmark location over the furthest location seen for the title
+    progress: f32,
+    created_at: Option<DateTime<Utc>>,
+}
+
+enum Position {
+    /// Chapter file inside the EPUB, e.g. OEBPS/Text/ch03.xhtml
+    Chapter(String),
+    /// Kindle location number
+    Location(usize),
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UnmatchedBook {
+    pub title: String,
+    pub author: String,
+    pub highlights: usize,
+}
+
+/// A bookmark further along than the library's progress, to offer taking over
+#[derive(Debug, Serialize, Clone)]
+pub struct PositionOffer {
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    pub title: String,
+    /// Approximate, worked out from Kindle locations
+    pub progress: f32,
+    #[serde(rename = "currentProgress")]
+    pub current_progress: f32,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DeviceImportSummary {
+    pub imported: usize,
+    /// Already imported before
+    pub skipped: usize,
+    #[serde(rename = "matchedBooks")]
+    pub matched_books: usize,
+    /// Books with highlights that aren't in the library, to map by hand
+    pub unmatched: Vec<UnmatchedBook>,
+    /// Imported positions ahead of the library's progress
+    pub positions: Vec<PositionOffer>,
+}
+
+/// Lowercase letters and digits only, subtitle dropped
+fn normalize_title(title: &str) -> String {
+    let main = title.split([':', '(']).next().unwrap_or(title);
+    main.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Name parts, so "Tolkien, J.R.R." and "J. R. R. Tolkien" compare equal
+fn author_words(author: &str) -> Vec<String> {
+    let mut words: Vec<String> = author
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1)
+        .map(|w| w.to_lowercase())
+        .collect();
+    words.sort();
+    words
+}
+
+fn matches_book(book: &Book, title: &str, author: &str) -> bool {
+    if normalize_title(&book.title) != normalize_title(title) {
+        return false;
+    }
+    // One side without an author can't contradict the title match
+    let ours = author_words(&book.author);
+    let theirs = author_words(author);
+    ours.is_empty() || theirs.is_empty() || ours.iter().any(|w| theirs.contains(w))
+}
+
+fn read_kobo(path: &Path) -> Result<Vec<DeviceHighlight>, String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open Kobo database: {}", e))?;
+    let mut statement = conn
+        .prepare(
+            "SELECT b.BookmarkID, b.Text, b.Annotation, b.ContentID, b.DateCreated,
+                    c.Title, c.Attribution
+             FROM Bookmark b LEFT JOIN content c ON c.ContentID = b.VolumeID
+             WHERE b.Text IS NOT NULL AND TRIM(b.Text) != ''",
+        )
+        .map_err(|e| format!("Not a Kobo database: {}", e))?;
+
+    let rows = statement
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, Option<String>>(6)?,
+            ))
+        })
+        .map_err(|e| format!("Failed to read Kobo highlights: {}", e))?;
+
+    let mut highlights = Vec::new();
+    for row in rows {
+        let (id, text, annotation, content_id, created, title, author) =
+            row.map_err(|e| format!("Failed to read Kobo highlight: {}", e))?;
+        highlights.push(DeviceHighlight {
+            title: title.unwrap_or_default(),
+            author: author.unwrap_or_default(),
+            text: text.trim().to_string(),
+            note: annotation.filter(|a| !a.trim().is_empty()),
+            position: content_id
+                .as_deref()
+                .and_then(kobo_chapter)
+                .map_or(Position::Unknown, Position::Chapter),
+            created_at: created.as_deref().and_then(|d| {
+                DateTime::parse_from_rfc3339(d)
+                    .map(|d| d.with_timezone(&Utc))
+                    .ok()
+                    .or_else(|| {
+                        NaiveDateTime::parse_from_str(d, "%Y-%m-%dT%H:%M:%S%.f")
+                            .ok()
+                            .map(|d| Utc.from_utc_datetime(&d))
+                    })
+            }),
+            source_id: id,
+        });
+    }
+    Ok(highlights)
+}
+
+/// The chapter file in a Kobo content id: "book.epub#(3)OEBPS/ch03.xhtml"
+/// for EPUBs, "book.kepub.epub!OEBPS!ch03.xhtml" for kepubs
+fn kobo_chapter(content_id: &str) -> Option<String> {
+    if let Some((_, rest)) = content_id.split_once("#(") {
+        let (_, chapter) = rest.split_once(')')?;
+        return Some(chapter.to_string());
+    }
+    let (_, rest) = content_id.split_once('!')?;
+    Some(rest.replace('!', "/"))
+}
+
+fn read_kindle(path: &Path) -> Result<(Vec<DeviceHighlight>, Vec<DeviceBookmark>), String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read Kindle clippings: {}", e))?;
+    let content = content.trim_start_matches('\u{feff}');
+
+    let mut highlights: Vec<DeviceHighlight> = Vec::new();
+    let mut bookmarks: Vec<DeviceBookmark> = Vec::new();
+    // Furthest location seen in any clipping, per title
+    let mut furthest: HashMap<String, usize> = HashMap::new();
+    for block in content.split(CLIPPING_SEPARATOR) {
+        let mut lines = block
+            .lines()
+            .map(|l| l.trim_start_matches('\u{feff}').trim());
+        let Some(heading) = lines.by_ref().find(|l| !l.is_empty()) else {
+            continue;
+        };
+        let Some(details) = lines.next() else {
+            continue;
+        };
+        let text = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+
+        // "Title (Author)"; titles can contain parentheses themselves
+        let (title, author) = match heading.rfind(" (") {
+            Some(i) if heading.ends_with(')') => {
+                (&heading[..i], &heading[i + 2..heading.len() - 1])
+            }
+            _ => (heading, ""),
+        };
+        let lower = details.to_lowercase();
+        let location = kindle_location(&lower);
+        if let Some((_, end)) = kindle_locations(&lower) {
+            let max = furthest.entry(title.trim().to_string()).or_default();
+            *max = (*max).max(end);
+        }
+
+        if lower.contains("your bookmark") {
+            let Some(location) = location else {
+                continue;
+            };
+            let bookmark = DeviceBookmark {
+                title: title.trim().to_string(),
+                author: author.trim().to_string(),
+                location,
+                progress: 0.0,
+                created_at: kindle_date(details),
+            };
+            // Clippings are in the order they were made; a dated one wins
+            // over an older date
+            match bookmarks.iter_mut().find(|b| b.title == bookmark.title) {
+                Some(latest) if latest.created_at <= bookmark.created_at => *latest = bookmark,
+                Some(_) => {}
+                None => bookmarks.push(bookmark),
+            }
+            continue;
+        }
+
+        if lower.contains("your note") {
+            // Notes are separate clippings at the end of their highlight
+            if let Some(highlight) = highlights.iter_mut().rev().find(|h| {
+                h.title == title
+                    && matches!((&h.position, location), (Position::Location(l), Some(n)) if n >= *l)
+            }) {
+                highlight.note = Some(text);
+            }
+            continue;
+        }
+        if !lower.contains("your highlight") || text.is_empty() {
+            continue; // Clipped articles
+        }
+
+        highlights.push(DeviceHighlight {
+            title: title.trim().to_string(),
+            author: author.trim().to_string(),
+            source_id: format!(
+                "{:x}",
+                md5::compute(format!("{}|{}|{}", title, details, text).as_bytes())
+            ),
+            text,
+            note: None,
+            position: location.map_or(Position::Unknown, Position::Location),
+            created_at: kindle_date(details),
+        });
+    }
+
+    for bookmark in &mut bookmarks {
+        let max = furthest.get(&bookmark.title).copied().unwrap_or_default();
+        bookmark.progress = if max > 0 {
+            (bookmark.location as f32 / max as f32).min(MAX_IMPORTED_PROGRESS)
+        } else {
+            0.0
+        };
+    }
+    Ok((highlights, bookmarks))
+}
+
+/// First and last number of the location range after "location" (or
+/// "loc."), e.g. 123 and 125 in "Location 123-125"
+fn kindle_locations(details: &str) -> Option<(usize, usize)> {
+    let start = details
+        .find("location ")
+        .map(|i| i + 9)
+        .or_else(|| details.find("loc. ").map(|i| i + 5))?;
+    let number = |s: &str| -> Option<usize> {
+        s.chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse()
+            .ok()
+    };
+    let rest = &details[start..];
+    let first = number(rest)?;
+    let last = rest
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .strip_prefix('-')
+        .and_then(number)
+        .unwrap_or(first);
+    Some((first, last.max(first)))
+}
+
+fn kindle_location(details: &str) -> Option<usize> {
+    kindle_locations(details).map(|(first, _)| first)
+}
+
+/// "Added on Monday, 1 January 2024 10:00:00" or the US form
+fn kindle_date(details: &str) -> Option<DateTime<Utc>> {
+    let (_, date) = details.split_once("Added on ")?;
+    ["%A, %d %B %Y %H:%M:%S", "%A, %B %d, %Y %I:%M:%S %p"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date.trim(), format).ok())
+        .map(|d| Utc.from_utc_datetime(&d))
+}
+
+/// A chapter-level CFI for a spine item
+fn spine_cfi(index: usize) -> String {
+    format!("epubcfi(/6/{}!/4)", (index + 1) * 2)
+}
+
+/// Where each device position falls in a book, as a spine index
+struct SpineMap {
+    paths: Vec<String>,
+    sizes: Vec<usize>,
+}
+
+impl SpineMap {
+    fn load(path: &str) -> Option<Self> {
+        let mut doc = epub::doc::EpubDoc::new(path).ok()?;
+        let ids: Vec<String> = doc.spine.iter().map(|item| item.idref.clone()).collect();
+        let mut paths = Vec::new();
+        let mut sizes = Vec::new();
+        for id in ids {
+            let path = doc
+                .resources
+                .get(&id)
+                .map(|(path, _)| path.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_default();
+            sizes.push(doc.get_resource(&id).map_or(0, |(data, _)| data.len()));
+            paths.push(path);
+        }
+        Some(Self { paths, sizes })
+    }
+
+    fn index_of(&self, position: &Position) -> Option<usize> {
+        match position {
+            Position::Chapter(chapter) => {
+                let chapter = chapter.trim_start_matches('/');
+                self.paths.iter().position(|p| p.ends_with(chapter))
+            }
+            Position::Location(location) => {
+                let target = location * BYTES_PER_KINDLE_LOCATION;
+                let mut total = 0;
+                for (i, size) in self.sizes.iter().enumerate() {
+                    total += size;
+                    if target < total {
+                        return Some(i);
+                    }
+                }
+                self.sizes.len().checked_sub(1)
+            }
+            Position::Unknown => None,
+        }
+    }
+}
+
+fn load_spine(book: &Book) -> Option<SpineMap> {
+    crash::catch_panic("reading the book's chapters", || {
+        SpineMap::load(&book.file_path)
+    })
+    .unwrap_or_else(|e| {
+        logging::warn(&e);
+        None
+    })
+}
+
+/// Import highlights from an e-reader. `source` is "kobo" (KoboReader.sqlite)
+/// or "kindle" (My Clippings.txt). Highlights already imported are skipped.
+/// The latest Kindle bookmark of each book is kept as its imported
+/// position; the summary lists those ahead of the library's progress.
+#[tauri::command]
+pub fn import_device_annotations(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    source: String,
+    path: String,
+) -> Result<DeviceImportSummary, String> {
+    let path = file_access::check_read_access(&state, &path)?;
+    let (highlights, bookmarks) = match source.as_str() {
+        "kobo" => (read_kobo(&path)?, Vec::new()),
+        "kindle" => read_kindle(&path)?,
+        _ => return Err(format!("Unknown annotation source: {}", source)),
+    };
+    // Highlights only make sense for books with text
+    let books: Vec<Book> = state
+        .with_library(|library| library.books.clone())?
+        .into_iter()
+        .filter(|b| b.media_type == MediaType::Text)
+        .collect();
+
+    let mut by_book: HashMap<String, Vec<DeviceHighlight>> = HashMap::new();
+    let mut unmatched: Vec<UnmatchedBook> = Vec::new();
+    for highlight in highlights {
+        match books
+            .iter()
+            .find(|b| matches_book(b, &highlight.title, &highlight.author))
+        {
+            Some(book) => by_book.entry(book.id.clone()).or_default().push(highlight),
+            None => match unmatched.iter_mut().find(|u| u.title == highlight.title) {
+                Some(entry) => entry.highlights += 1,
+                None => unmatched.push(UnmatchedBook {
+                    title: highlight.title,
+                    author: highlight.author,
+                    highlights: 1,
+                }),
+            },
+        }
+    }
+
+    let mut summary = DeviceImportSummary {
+        matched_books: by_book.len(),
+        unmatched,
+        ..Default::default()
+    };
+    for (book_id, highlights) in by_book {
+        let Some(book) = books.iter().find(|b| b.id == book_id) else {
+            continue;
+        };
+        let spine = load_spine(book);
+
+        let mut chapters: HashMap<String, Option<String>> = HashMap::new();
+        let mut stored = annotations::load_annotations(&state, &book_id)?;
+        for device in highlights {
+            let id = format!(
+                "{:x}",
+                md5::compute(format!("{}|{}", source, device.source_id).as_bytes())
+            );
+            if stored.highlights.iter().any(|h| h.id == id) {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let cfi = spine
+                .as_ref()
+                .and_then(|s| s.index_of(&device.position))
+                .map(spine_cfi)
+                .unwrap_or_default();
+            let chapter = if cfi.is_empty() {
+                None
+            } else {
+                chapters
+                    .entry(cfi.clone())
+                    .or_insert_with(|| {
+                        crash::catch_panic("reading the table of contents", || {
+                            quote::chapter_title(&book.file_path, &cfi)
+                        })
+                        .unwrap_or_default()
+                    })
+                    .clone()
+            };
+
+            let mut highlight = Highlight::new(cfi, device.text, chapter);
+            highlight.id = id;
+            highlight.note = device.note;
+            highlight.origin = Some(source.clone());
+            highlight.source_id = Some(device.source_id);
+            if let Some(created_at) = device.created_at {
+                highlight.created_at = created_at;
+            }
+            stored.highlights.push(highlight);
+            summary.imported += 1;
+        }
+
+        stored
+            .highlights
+            .sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        annotations::save_annotations(&state, &stored)?;
+    }
+
+    for bookmark in bookmarks {
+        let Some(book) = books
+            .iter()
+            .find(|b| matches_book(b, &bookmark.title, &bookmark.author))
+        else {
+            continue;
+        };
+        let cfi = load_spine(book)
+            .and_then(|s| s.index_of(&Position::Location(bookmark.location)))
+            .map(spine_cfi);
+        let position = ImportedPosition {
+            source: source.clone(),
+            progress: bookmark.progress,
+            approximate: true,
+            cfi,
+            location: Some(bookmark.location),
+            imported_at: bookmark.created_at.unwrap_or_else(Utc::now),
+        };
+        let book = library::update_book(&app, &state, &book.id, |book| {
+            book.imported_position = Some(position)
+        })?;
+        if bookmark.progress > book.progress {
+            summary.positions.push(PositionOffer {
+                book_id: book.id,
+                title: book.title,
+                progress: bookmark.progress,
+                current_progress: book.progress,
+            });
+        }
+    }
+
+    Ok(summary)
+}
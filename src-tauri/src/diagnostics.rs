@@ -0,0 +1,328 @@
+/**
+ * Diagnostics bundle for bug reports
+ */
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use tauri::State;
+use zip::write::SimpleFileOptions;
+
+use crate::config;
+use crate::library;
+use crate::logging;
+use crate::preset;
+use crate::state::AppState;
+
+/// How much of the log goes into the bundle
+const LOG_LINES: usize = 2000;
+
+#[derive(Debug, Serialize, Clone)]
+struct PresetCheck {
+    name: String,
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Export a zip with logs, app state summaries and system info.
+/// Personal data is redacted; book titles are only included on request.
+#[tauri::command]
+pub fn export_diagnostics(
+    state: State<'_, AppState>,
+    dest: String,
+    include_titles: Option<bool>,
+) -> Result<String, String> {
+    let include_titles = include_titles.unwrap_or(false);
+    let dest = Path::new(&dest);
+
+    let archive_path = if dest.extension().and_then(|s| s.to_str()) == Some("zip") {
+        dest.to_path_buf()
+    } else {
+        fs::create_dir_all(dest)
+            .map_err(|e| format!("Failed to create diagnostics directory: {}", e))?;
+        dest.join(format!(
+            "epilogue-diagnostics-{}.zip",
+            Utc::now().format("%Y%m%d-%H%M%S")
+        ))
+    };
+
+    let home = dirs::home_dir().map(|h| h.to_string_lossy().to_string());
+    let app_dir = config::get_app_dir_path().ok();
+
+    // Each section is collected independently; a missing source is noted, not fatal
+    let mut sections: Vec<(&str, String)> = Vec::new();
+    let mut problems: Vec<String> = Vec::new();
+
+    let log = logging::tail(LOG_LINES).join("\n");
+    sections.push(("logs/epilogue.log", redact_text(&log, home.as_deref())));
+
+    match app_dir
+        .as_ref()
+        .map(|dir| fs::read_to_string(dir.join("meta.json")))
+    {
+        // Watched folders and vault paths are keys as well as values here
+        Some(Ok(content)) => match serde_json::from_str::<Value>(&content) {
+            Ok(meta) => sections.push(("meta.json", pretty(&redact_value(meta, home.as_deref())))),
+            Err(e) => problems.push(format!("meta.json unreadable: {}", e)),
+        },
+        _ => problems.push("meta.json not available".to_string()),
+    }
+
+    match app_dir
+        .as_ref()
+        .map(|dir| fs::read_to_string(dir.join("preferences.json")))
+    {
+        Some(Ok(content)) => match serde_json::from_str::<Value>(&content) {
+            Ok(prefs) => sections.push((
+                "preferences.json",
+                pretty(&redact_value(prefs, home.as_deref())),
+            )),
+            Err(e) => problems.push(format!("preferences.json unreadable: {}", e)),
+        },
+        _ => problems.push("preferences.json not available".to_string()),
+    }
+
+    match state.with_library(|lib| library_summary(lib, include_titles)) {
+        Ok(summary) => sections.push(("library-summary.json", pretty(&summary))),
+        Err(e) => problems.push(e),
+    }
+
+    sections.push(("presets.json", pretty(&json!(check_presets(&state)))));
+    sections.push(("system.json", pretty(&system_info(home.as_deref()))));
+
+    if !problems.is_empty() {
+        sections.push(("problems.txt", problems.join("\n")));
+    }
+
+    let file = File::create(&archive_path)
+        .map_err(|e| format!("Failed to create diagnostics archive: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+
+    for (name, content) in sections {
+        zip.start_file(name, options)
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        zip.write_all(content.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finalize diagnostics archive: {}", e))?;
+
+    Ok(archive_path.to_string_lossy().to_string())
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+/// Does `text` start with the home directory? Case-insensitive on Windows.
+fn is_under_home(text: &str, home: &str) -> bool {
+    if cfg!(windows) {
+        text.to_lowercase().starts_with(&home.to_lowercase())
+    } else {
+        text.starts_with(home)
+    }
+}
+
+/// Replace a path under the home directory with a placeholder that keeps
+/// only the file extension, e.g. "/home/ann/Music/rain.mp3" -> "<home>/<redacted>.mp3"
+pub fn redact_path(text: &str, home: Option<&str>) -> String {
+    let Some(home) = home.filter(|h| !h.is_empty()) else {
+        return text.to_string();
+    };
+
+    let stripped = text.strip_prefix("file://").unwrap_or(text);
+    if !is_under_home(stripped, home) {
+        return text.to_string();
+    }
+
+    match Path::new(stripped).extension().and_then(|s| s.to_str()) {
+        Some(ext) => format!("<home>/<redacted>.{}", ext),
+        None => "<home>/<redacted>".to_string(),
+    }
+}
+
+/// Redact every string in a JSON document that is a path under home, object
+/// keys included, keeping the document structure intact
+pub fn redact_value(value: Value, home: Option<&str>) -> Value {
+    match value {
+        Value::String(s) => Value::String(redact_path(&s, home)),
+        Value::Array(items) => {
+            Value::Array(items.into_iter().map(|v| redact_value(v, home)).collect())
+        }
+        Value::Object(map) => {
+            let mut redacted = serde_json::Map::new();
+            for (key, value) in map {
+                let mut key = redact_path(&key, home);
+                // Keys that redact alike are numbered rather than merged
+                if redacted.contains_key(&key) {
+                    let base = key.clone();
+                    let mut n = 2;
+                    while redacted.contains_key(&key) {
+                        key = format!("{} ({})", base, n);
+                        n += 1;
+                    }
+                }
+                redacted.insert(key, redact_value(value, home));
+            }
+            Value::Object(redacted)
+        }
+        other => other,
+    }
+}
+
+/// Replace the home directory inside free text (log lines) with "<home>"
+pub fn redact_text(text: &str, home: Option<&str>) -> String {
+    match home.filter(|h| !h.is_empty()) {
+        Some(home) => text.replace(home, "<home>"),
+        None => text.to_string(),
+    }
+}
+
+/// Counts only; titles/authors are included solely when asked for
+fn library_summary(lib: &library::Library, include_titles: bool) -> Value {
+    let with_cover = lib.books.iter().filter(|b| b.cover_path.is_some()).count();
+    let cover_missing_on_disk = lib
+        .books
+        .iter()
+        .filter(|b| {
+            b.cover_path
+                .as_ref()
+                .is_some_and(|c| !Path::new(c).exists())
+        })
+        .count();
+    let file_missing = lib
+        .books
+        .iter()
+        .filter(|b| !Path::new(&b.file_path).exists())
+        .count();
+
+    let mut summary = json!({
+        "bookCount": lib.books.len(),
+        "withCover": with_cover,
+        "coverMissingOnDisk": cover_missing_on_disk,
+        "fileMissing": file_missing,
+    });
+
+    if include_titles {
+        summary["books"] = json!(lib
+            .books
+            .iter()
+            .map(|b| json!({
+                "id": b.id,
+                "title": b.title,
+                "author": b.author,
+                "hasCover": b.cover_path.is_some(),
+            }))
+            .collect::<Vec<_>>());
+    }
+
+    summary
+}
+
+fn check_presets(state: &State<'_, AppState>) -> Vec<PresetCheck> {
+    preset::list_presets(state.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(
+            |name| match preset::load_preset(state.clone(), name.clone()) {
+                Ok(_) => PresetCheck {
+                    name,
+                    valid: true,
+                    error: None,
+                },
+                Err(e) => PresetCheck {
+                    name,
+                    valid: false,
+                    error: Some(e),
+                },
+            },
+        )
+        .collect()
+}
+
+fn system_info(home: Option<&str>) -> Value {
+    let status = config::resolve_app_dir().ok();
+
+    json!({
+        "appVersion": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "family": std::env::consts::FAMILY,
+        "arch": std::env::consts::ARCH,
+        "appDir": status.map(|s| redact_text(&s.path.to_string_lossy(), home)),
+        "safeMode": status.map(|s| s.safe_mode),
+        "generatedAt": Utc::now().to_rfc3339(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOME: Option<&str> = Some("/home/ann");
+
+    #[test]
+    fn redacts_paths_under_home_only() {
+        assert_eq!(
+            redact_path("/home/ann/Books/dune.epub", HOME),
+            "<home>/<redacted>.epub"
+        );
+        assert_eq!(
+            redact_path("file:///home/ann/Music/rain", HOME),
+            "<home>/<redacted>"
+        );
+        assert_eq!(
+            redact_path("/usr/share/fonts/a.ttf", HOME),
+            "/usr/share/fonts/a.ttf"
+        );
+        assert_eq!(redact_path("/home/ann/x.epub", None), "/home/ann/x.epub");
+    }
+
+    #[test]
+    fn redacts_keys_and_values() {
+        let meta = json!({
+            "watched_files": ["/home/ann/Books/a.epub", "/mnt/shared/b.epub"],
+            "obsidian_exports": {
+                "/home/ann/Vault": { "book1": "/home/ann/Vault/Dune.md" },
+            },
+            "readwise_cursors": { "default": "2026-01-01T00:00:00Z" },
+            "cloud_sync_warned": true,
+        });
+        let redacted = redact_value(meta, HOME);
+        assert_eq!(
+            redacted,
+            json!({
+                "watched_files": ["<home>/<redacted>.epub", "/mnt/shared/b.epub"],
+                "obsidian_exports": {
+                    "<home>/<redacted>": { "book1": "<home>/<redacted>.md" },
+                },
+                "readwise_cursors": { "default": "2026-01-01T00:00:00Z" },
+                "cloud_sync_warned": true,
+            })
+        );
+        assert!(!pretty(&redacted).contains("/home/ann"));
+    }
+
+    #[test]
+    fn keys_redacted_alike_are_kept_apart() {
+        let value = json!({ "/home/ann/One": 1, "/home/ann/Two": 2 });
+        let Value::Object(map) = redact_value(value, HOME) else {
+            panic!("expected an object");
+        };
+        assert_eq!(map.len(), 2);
+        assert!(map.contains_key("<home>/<redacted>"));
+        assert!(map.contains_key("<home>/<redacted> (2)"));
+    }
+
+    #[test]
+    fn redacts_home_in_log_text() {
+        assert_eq!(
+            redact_text("Failed to open /home/ann/a.epub", HOME),
+            "Failed to open <home>/a.epub"
+        );
+    }
+}
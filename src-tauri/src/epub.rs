@@ -2,34 +2,47 @@
  * EPUB file operations
  */
 use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+use crate::advanced::AdvancedConfig;
+use crate::audiobook;
+use crate::crash;
+use crate::error::AppError;
+use crate::file_access;
+use crate::state::AppState;
+
+/// Let the app read what the user picked in a dialog, and return its path
+fn pick_and_grant(state: &AppState, picked: Option<PathBuf>, none: &str) -> Result<String, String> {
+    let path = picked.ok_or_else(|| none.to_string())?;
+    state.grant_path(&path);
+    Ok(path.to_string_lossy().to_string())
+}
 
 /// Open native file picker dialog for EPUB files
 #[tauri::command]
-pub fn open_epub_dialog() -> Result<String, String> {
+pub fn open_epub_dialog(state: State<'_, AppState>) -> Result<String, String> {
     use rfd::FileDialog;
 
     let file = FileDialog::new()
         .add_filter("EPUB Files", &["epub"])
         .pick_file();
 
-    match file {
-        Some(path) => {
-            let p: std::path::PathBuf = path;
-            Ok(p.to_string_lossy().to_string())
-        }
-        None => Err("No file selected".to_string()),
-    }
+    pick_and_grant(&state, file, "No file selected")
 }
 
-/// Read EPUB file as byte array
+/// Read EPUB file as byte array. Only library books and files picked in
+/// our dialogs can be read.
 #[tauri::command]
-pub fn read_epub_file(path: String) -> Result<Vec<u8>, String> {
-    fs::read(&path).map_err(|e| format!("Failed to read EPUB file: {}", e))
+pub fn read_epub_file(state: State<'_, AppState>, path: String) -> Result<Vec<u8>, AppError> {
+    let path = file_access::check_read_access(&state, &path)?;
+    fs::read(&path).map_err(|e| format!("Failed to read EPUB file: {}", e).into())
 }
 
 /// Open native file picker dialog for background media (images & videos)
 #[tauri::command]
-pub fn open_media_dialog() -> Result<String, String> {
+pub fn open_media_dialog(state: State<'_, AppState>) -> Result<String, String> {
     use rfd::FileDialog;
 
     let file = FileDialog::new()
@@ -38,29 +51,129 @@ pub fn open_media_dialog() -> Result<String, String> {
         .add_filter("All Media", &["jpg", "jpeg", "png", "gif", "webp", "bmp", "mp4", "webm", "mov", "avi", "mkv"])
         .pick_file();
 
-    match file {
-        Some(path) => {
-            let p: std::path::PathBuf = path;
-            Ok(p.to_string_lossy().to_string())
-        }
-        None => Err("No file selected".to_string()),
-    }
+    pick_and_grant(&state, file, "No file selected")
+}
+
+/// Open native file picker dialog for audiobooks
+#[tauri::command]
+pub fn open_audiobook_dialog(state: State<'_, AppState>) -> Result<String, String> {
+    use rfd::FileDialog;
+
+    let file = FileDialog::new()
+        .add_filter("Audiobooks", audiobook::AUDIOBOOK_EXTENSIONS)
+        .pick_file();
+
+    pick_and_grant(&state, file, "No file selected")
 }
 
 /// Open native file picker dialog for audio files
 #[tauri::command]
-pub fn open_audio_dialog() -> Result<String, String> {
+pub fn open_audio_dialog(state: State<'_, AppState>) -> Result<String, String> {
     use rfd::FileDialog;
 
     let file = FileDialog::new()
         .add_filter("Audio Files", &["mp3", "wav", "ogg", "flac", "aac", "m4a", "wma"])
         .pick_file();
 
-    match file {
-        Some(path) => {
-            let p: std::path::PathBuf = path;
-            Ok(p.to_string_lossy().to_string())
+    pick_and_grant(&state, file, "No file selected")
+}
+
+/// Open native file picker dialog for e-reader annotation exports
+#[tauri::command]
+pub fn open_device_annotations_dialog(state: State<'_, AppState>) -> Result<String, String> {
+    use rfd::FileDialog;
+
+    let file = FileDialog::new()
+        .add_filter("Kobo Database", &["sqlite"])
+        .add_filter("Kindle Clippings", &["txt"])
+        .pick_file();
+
+    pick_and_grant(&state, file, "No file selected")
+}
+
+/// Open native folder picker dialog for importing a folder of books
+#[tauri::command]
+pub fn open_folder_dialog(state: State<'_, AppState>) -> Result<String, String> {
+    use rfd::FileDialog;
+
+    let folder = FileDialog::new().pick_folder();
+
+    pick_and_grant(&state, folder, "No folder selected")
+}
+
+/// Open native file picker dialog for a backup to restore
+#[tauri::command]
+pub fn open_backup_dialog(state: State<'_, AppState>) -> Result<String, String> {
+    use rfd::FileDialog;
+
+    let file = FileDialog::new()
+        .add_filter("Epilogue Backups", &["zip"])
+        .pick_file();
+
+    pick_and_grant(&state, file, "No file selected")
+}
+
+/// Open native file picker dialog for an annotations archive to import
+#[tauri::command]
+pub fn open_annotations_archive_dialog(state: State<'_, AppState>) -> Result<String, String> {
+    use rfd::FileDialog;
+
+    let file = FileDialog::new()
+        .add_filter("Annotation Archives", &["zip"])
+        .pick_file();
+
+    pick_and_grant(&state, file, "No file selected")
+}
+
+/// Refuse an EPUB that would unpack past the zip limits, before the EPUB
+/// parser expands any of it
+pub fn check_zip_limits(path: &Path, limits: &AdvancedConfig) -> Result<(), String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open EPUB: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Not a valid EPUB archive: {}", e))?;
+    limits.check_zip(&mut archive, "EPUB")
+}
+
+/// Check that a file is an EPUB we can open: within the zip limits, the
+/// OCF container layout, then every spine item readable through the EPUB
+/// parser
+pub fn validate_epub(path: &Path, limits: &AdvancedConfig) -> Result<(), String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open EPUB: {}", e))?;
+    let mut archive =
+        zip::ZipArchive::new(file).map_err(|e| format!("Not a valid EPUB archive: {}", e))?;
+    limits.check_zip(&mut archive, "EPUB")?;
+
+    {
+        let mut mimetype = archive
+            .by_index(0)
+            .map_err(|e| format!("Not a valid EPUB archive: {}", e))?;
+        let mut content = String::new();
+        mimetype
+            .read_to_string(&mut content)
+            .map_err(|e| format!("Failed to read EPUB mimetype: {}", e))?;
+        if mimetype.name() != "mimetype"
+            || mimetype.compression() != zip::CompressionMethod::Stored
+            || content != "application/epub+zip"
+        {
+            return Err("EPUB must start with an uncompressed mimetype file".to_string());
         }
-        None => Err("No file selected".to_string()),
     }
+    if archive.by_name("META-INF/container.xml").is_err() {
+        return Err("EPUB is missing META-INF/container.xml".to_string());
+    }
+
+    crash::catch_panic("validating the EPUB", || {
+        let mut doc = epub::doc::EpubDoc::new(path)
+            .map_err(|e| format!("Failed to parse EPUB: {:?}", e))?;
+        if doc.spine.is_empty() {
+            return Err("EPUB has no chapters".to_string());
+        }
+        let idrefs: Vec<String> = doc.spine.iter().map(|item| item.idref.clone()).collect();
+        for idref in idrefs {
+            if doc.get_resource(&idref).is_none() {
+                return Err(format!("EPUB chapter '{}' is missing", idref));
+            }
+        }
+        Ok(())
+    })?
 }
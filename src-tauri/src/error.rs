@@ -0,0 +1,133 @@
+/**
+ * Typed errors shared across commands
+ */
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind")]
+pub enum AppError {
+    /// The data directory can't be created or written to
+    AppDirUnavailable { path: String, reason: String },
+    /// Importing a media file would exceed the configured media quota
+    QuotaExceeded {
+        #[serde(rename = "usedBytes")]
+        used_bytes: u64,
+        #[serde(rename = "fileBytes")]
+        file_bytes: u64,
+        #[serde(rename = "quotaBytes")]
+        quota_bytes: u64,
+    },
+    /// A save location failed its writability preflight
+    NotWritable {
+        path: String,
+        /// "readOnly", "diskFull", "blocked" or "other"
+        reason: String,
+        remedy: String,
+        detail: String,
+    },
+    /// The webview asked for a file it has no business reading
+    PermissionDenied { path: String, reason: String },
+    /// No installed dictionary translates into the language asked for
+    NoDictionary {
+        /// None when the source language wasn't given
+        source: Option<String>,
+        target: String,
+        /// Where dictionaries are installed
+        folder: String,
+    },
+    /// Not an ISBN: the wrong length or characters, or a bad check digit
+    InvalidIsbn {
+        input: String,
+        /// "format" or "checkDigit"
+        reason: String,
+    },
+    /// A valid ISBN that Open Library has no record of
+    IsbnNotFound { isbn: String },
+    /// A link to a note that leads nowhere in the book
+    NoteNotFound {
+        href: String,
+        /// "file" when the linked file is missing, "anchor" when the note is
+        reason: String,
+    },
+    /// Any other failure, carried as a plain message
+    Other { message: String },
+}
+
+const MB: f64 = 1024.0 * 1024.0;
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::AppDirUnavailable { path, reason } => write!(
+                f,
+                "AppDirUnavailable: The Epilogue data folder ({}) is not usable: {}",
+                path, reason
+            ),
+            AppError::QuotaExceeded {
+                used_bytes,
+                file_bytes,
+                quota_bytes,
+            } => write!(
+                f,
+                "QuotaExceeded: Adding this file ({:.1} MB) would exceed the media quota ({:.1} of {:.1} MB used)",
+                *file_bytes as f64 / MB,
+                *used_bytes as f64 / MB,
+                *quota_bytes as f64 / MB
+            ),
+            AppError::NotWritable { remedy, detail, .. } => {
+                write!(f, "NotWritable: {} ({})", remedy, detail)
+            }
+            AppError::PermissionDenied { path, reason } => {
+                write!(f, "PermissionDenied: Access to {} is not allowed: {}", path, reason)
+            }
+            AppError::NoDictionary {
+                source,
+                target,
+                folder,
+            } => write!(
+                f,
+                "NoDictionary: No dictionary installed for {} to {} (add one to {})",
+                source.as_deref().unwrap_or("any language"),
+                target,
+                folder
+            ),
+            AppError::InvalidIsbn { input, reason } if reason == "checkDigit" => write!(
+                f,
+                "InvalidIsbn: {} is not a valid ISBN: the check digit is wrong",
+                input
+            ),
+            AppError::InvalidIsbn { input, .. } => write!(
+                f,
+                "InvalidIsbn: {} is not an ISBN: expected 10 or 13 digits",
+                input
+            ),
+            AppError::IsbnNotFound { isbn } => {
+                write!(f, "IsbnNotFound: Open Library has no book with ISBN {}", isbn)
+            }
+            AppError::NoteNotFound { href, reason } if reason == "file" => write!(
+                f,
+                "NoteNotFound: The file {} links to is not in this book",
+                href
+            ),
+            AppError::NoteNotFound { href, .. } => {
+                write!(f, "NoteNotFound: There is no note at {} in this book", href)
+            }
+            AppError::Other { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<AppError> for String {
+    fn from(error: AppError) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Other { message }
+    }
+}
@@ -0,0 +1,142 @@
+/**
+ * Which files the webview may read through our commands
+ */
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::logging;
+use crate::state::AppState;
+
+/// Debug builds only: set to 1 to let file commands read any path
+const ALLOW_ANY_PATH_VAR: &str = "EPILOGUE_ALLOW_ANY_PATH";
+
+fn denied(path: &str, reason: &str) -> AppError {
+    AppError::PermissionDenied {
+        path: path.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Resolve `path` and check it is a library book, inside the data
+/// directory, or was picked in one of our dialogs this session.
+///
+/// Paths are canonicalized first, so `..` segments and symlinks are judged
+/// by where they actually lead.
+pub fn check_read_access(state: &AppState, path: &str) -> Result<PathBuf, AppError> {
+    let canonical = Path::new(path)
+        .canonicalize()
+        .map_err(|e| AppError::from(format!("Failed to resolve {}: {}", path, e)))?;
+
+    if cfg!(debug_assertions) && std::env::var(ALLOW_ANY_PATH_VAR).is_ok_and(|v| v == "1") {
+        logging::warn(&format!("File access check bypassed for {}", path));
+        return Ok(canonical);
+    }
+
+    let in_app_dir = state
+        .paths()
+        .ok()
+        .and_then(|paths| paths.app_dir.canonicalize().ok())
+        .is_some_and(|app_dir| canonical.starts_with(app_dir));
+    if in_app_dir || state.is_granted(&canonical) || is_library_book(state, &canonical) {
+        return Ok(canonical);
+    }
+
+    logging::warn(&format!("Denied read access to {}", path));
+    Err(denied(path, "not a library book or a file you opened"))
+}
+
+fn is_library_book(state: &AppState, canonical: &Path) -> bool {
+    state
+        .with_library(|lib| {
+            lib.books.iter().any(|book| {
+                Path::new(&book.file_path)
+                    .canonicalize()
+                    .is_ok_and(|p| p == canonical)
+            })
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::temp_dir;
+    use std::fs;
+
+    /// A state over its own data directory, and a file outside it
+    fn setup(name: &str) -> (AppState, PathBuf, PathBuf) {
+        let root = temp_dir(name);
+        let app_dir = root.join("data");
+        let outside = root.join("outside");
+        fs::create_dir_all(&app_dir).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        let secret = outside.join("secret.txt");
+        fs::write(&secret, "secret").unwrap();
+        (AppState::in_dir(&app_dir), app_dir, secret)
+    }
+
+    fn allowed(state: &AppState, path: &Path) -> bool {
+        check_read_access(state, &path.to_string_lossy()).is_ok()
+    }
+
+    #[test]
+    fn ungranted_paths_are_denied() {
+        let (state, app_dir, secret) = setup("file_access_ungranted");
+        assert!(!allowed(&state, &secret));
+
+        let own = app_dir.join("notes.json");
+        fs::write(&own, "{}").unwrap();
+        assert!(allowed(&state, &own));
+    }
+
+    #[test]
+    fn granted_files_are_allowed() {
+        let (state, _, secret) = setup("file_access_granted");
+        state.grant_path(&secret);
+        assert!(allowed(&state, &secret));
+        // Only the file itself, not what sits beside it
+        let sibling = secret.with_file_name("other.txt");
+        fs::write(&sibling, "other").unwrap();
+        assert!(!allowed(&state, &sibling));
+    }
+
+    #[test]
+    fn dot_dot_paths_are_judged_by_where_they_lead() {
+        let (state, app_dir, secret) = setup("file_access_dot_dot");
+        let escaping = app_dir.join("..").join("outside").join("secret.txt");
+        assert!(!allowed(&state, &escaping));
+
+        fs::create_dir_all(app_dir.join("presets")).unwrap();
+        fs::write(app_dir.join("cozy.json"), "{}").unwrap();
+        let staying = app_dir.join("presets").join("..").join("cozy.json");
+        assert!(allowed(&state, &staying));
+
+        state.grant_path(&secret);
+        assert!(allowed(&state, &escaping));
+    }
+
+    #[test]
+    fn missing_files_are_denied() {
+        let (state, app_dir, _) = setup("file_access_missing");
+        assert!(!allowed(&state, &app_dir.join("nothing.json")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_judged_by_their_target() {
+        let (state, app_dir, secret) = setup("file_access_symlinks");
+        // A link inside the data directory to a file outside it
+        let link = app_dir.join("link.txt");
+        std::os::unix::fs::symlink(&secret, &link).unwrap();
+        assert!(!allowed(&state, &link));
+
+        // A linked directory leading out of the data directory
+        let linked_dir = app_dir.join("elsewhere");
+        std::os::unix::fs::symlink(secret.parent().unwrap(), &linked_dir).unwrap();
+        assert!(!allowed(&state, &linked_dir.join("secret.txt")));
+
+        // A link to a granted file reads the granted file
+        state.grant_path(&secret);
+        assert!(allowed(&state, &link));
+    }
+}
@@ -0,0 +1,337 @@
+/**
+ * Book filters: the conditions books are narrowed down by, combined with
+ * AND and OR into rules for smart shelves
+ */
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+use crate::annotations;
+use crate::authors;
+use crate::library::{Book, ReadingState};
+use crate::state::AppState;
+
+/// Deepest nesting of "all" and "any" accepted in a rule
+const MAX_RULE_DEPTH: usize = 8;
+/// Keys a rule object may have
+const RULE_KEYS: &[&str] = &[
+    "all",
+    "any",
+    "tags",
+    "subjects",
+    "status",
+    "language",
+    "rating",
+    "words",
+    "hasAnnotations",
+    "author",
+    "progress",
+    "favorite",
+    "archived",
+];
+const STATUS_NAMES: &[&str] = &["toRead", "reading", "finished", "abandoned"];
+
+/// One condition on a book
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Has any of these tags, ignoring case
+    Tags(Vec<String>),
+    /// Has any of these subjects, ignoring case
+    Subjects(Vec<String>),
+    /// Is on any of these shelves
+    Status(Vec<ReadingState>),
+    /// Language code from the book's metadata; "en" matches "en-GB" too
+    Language(String),
+    /// Rated within the bounds; unrated books never match
+    Rating {
+        min: Option<u8>,
+        max: Option<u8>,
+    },
+    /// Word count within the bounds; books not counted never match
+    Words {
+        min: Option<u64>,
+        max: Option<u64>,
+    },
+    HasAnnotations(bool),
+    /// Author field containing this, ignoring case and accents; kept folded
+    Author(String),
+    /// Progress within the bounds, as a fraction of the book
+    Progress {
+        min: Option<f32>,
+        max: Option<f32>,
+    },
+    Favorite(bool),
+    Archived(bool),
+}
+
+/// Filters combined: every one ("all") or at least one ("any")
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    All(Vec<Rule>),
+    Any(Vec<Rule>),
+    Filter(Filter),
+}
+
+/// What filters need beyond the book itself, loaded once per evaluation
+#[derive(Debug, Default)]
+pub struct FilterContext {
+    /// Books with at least one highlight or note
+    annotated: HashSet<String>,
+}
+
+impl FilterContext {
+    /// The context `rule` needs; annotation files are only read when the
+    /// rule asks about annotations
+    pub fn for_rule(state: &AppState, rule: &Rule, books: &[Book]) -> Self {
+        let mut context = Self::default();
+        if rule.uses_annotations() {
+            context.annotated = books
+                .iter()
+                .filter(|book| {
+                    annotations::load_annotations(state, &book.id)
+                        .is_ok_and(|a| !a.highlights.is_empty())
+                })
+                .map(|book| book.id.clone())
+                .collect();
+        }
+        context
+    }
+}
+
+fn contains_ignoring_case(values: &[String], wanted: &[String]) -> bool {
+    values
+        .iter()
+        .any(|value| wanted.iter().any(|w| value.trim().eq_ignore_ascii_case(w)))
+}
+
+fn within<T: PartialOrd>(value: Option<T>, min: Option<T>, max: Option<T>) -> bool {
+    let Some(value) = value else {
+        return false;
+    };
+    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+}
+
+impl Filter {
+    pub fn matches(&self, book: &Book, context: &FilterContext) -> bool {
+        match self {
+            Filter::Tags(tags) => contains_ignoring_case(&book.tags, tags),
+            Filter::Subjects(subjects) => contains_ignoring_case(&book.subjects, subjects),
+            Filter::Status(states) => states.contains(&book.reading_state),
+            Filter::Language(language) => book.language.as_deref().is_some_and(|l| {
+                let l = l.trim().to_lowercase();
+                l == *language || l.split(['-', '_']).next() == Some(language.as_str())
+            }),
+            Filter::Rating { min, max } => within(book.rating, *min, *max),
+            Filter::Words { min, max } => within(book.word_count, *min, *max),
+            Filter::HasAnnotations(wanted) => context.annotated.contains(&book.id) == *wanted,
+            Filter::Author(author) => authors::fold(&book.author).contains(author.as_str()),
+            Filter::Progress { min, max } => within(Some(book.progress), *min, *max),
+            Filter::Favorite(wanted) => book.favorite == *wanted,
+            Filter::Archived(wanted) => book.archived == *wanted,
+        }
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, book: &Book, context: &FilterContext) -> bool {
+        match self {
+            Rule::All(rules) => rules.iter().all(|r| r.matches(book, context)),
+            Rule::Any(rules) => rules.iter().any(|r| r.matches(book, context)),
+            Rule::Filter(filter) => filter.matches(book, context),
+        }
+    }
+
+    fn uses_annotations(&self) -> bool {
+        match self {
+            Rule::All(rules) | Rule::Any(rules) => rules.iter().any(Rule::uses_annotations),
+            Rule::Filter(filter) => matches!(filter, Filter::HasAnnotations(_)),
+        }
+    }
+
+    /// Parse and check a rule, with errors naming where in the JSON the
+    /// problem is
+    pub fn parse(json: &str) -> Result<Rule, String> {
+        let value: Value =
+            serde_json::from_str(json).map_err(|e| format!("Rules are not valid JSON: {}", e))?;
+        Self::from_value(&value)
+    }
+
+    pub fn from_value(value: &Value) -> Result<Rule, String> {
+        parse_rule(value, "rules", 0)
+    }
+}
+
+/// A string or a list of strings, as a non-empty list
+fn strings(value: &Value, at: &str, what: &str) -> Result<Vec<String>, String> {
+    let items = match value {
+        Value::String(_) => std::slice::from_ref(value),
+        Value::Array(items) if !items.is_empty() => items.as_slice(),
+        _ => return Err(format!("{}: expected a {} or a list of them", at, what)),
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| match item.as_str().map(str::trim) {
+            Some(s) if !s.is_empty() => Ok(s.to_string()),
+            _ => Err(format!("{}[{}]: expected a non-empty {}", at, i, what)),
+        })
+        .collect()
+}
+
+/// `{"min": .., "max": ..}` with at least one of them, each in `range`
+fn bounds(
+    value: &Value,
+    at: &str,
+    range: std::ops::RangeInclusive<u64>,
+) -> Result<(Option<u64>, Option<u64>), String> {
+    let Some(object) = value.as_object() else {
+        return Err(format!("{}: expected {{\"min\": .., \"max\": ..}}", at));
+    };
+    if let Some(key) = object.keys().find(|k| *k != "min" && *k != "max") {
+        return Err(format!(
+            "{}: unknown key '{}', expected min or max",
+            at, key
+        ));
+    }
+    let bound = |key: &str| -> Result<Option<u64>, String> {
+        match object.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => v
+                .as_u64()
+                .filter(|n| range.contains(n))
+                .map(Some)
+                .ok_or_else(|| {
+                    format!(
+                        "{}.{}: expected a whole number from {} to {}",
+                        at,
+                        key,
+                        range.start(),
+                        range.end()
+                    )
+                }),
+        }
+    };
+    let (min, max) = (bound("min")?, bound("max")?);
+    match (min, max) {
+        (None, None) => Err(format!("{}: needs a min, a max or both", at)),
+        (Some(min), Some(max)) if min > max => {
+            Err(format!("{}: min {} is greater than max {}", at, min, max))
+        }
+        _ => Ok((min, max)),
+    }
+}
+
+fn parse_rules(value: &Value, at: &str, depth: usize) -> Result<Vec<Rule>, String> {
+    match value {
+        Value::Array(items) if !items.is_empty() => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| parse_rule(item, &format!("{}[{}]", at, i), depth + 1))
+            .collect(),
+        _ => Err(format!("{}: expected a non-empty list of rules", at)),
+    }
+}
+
+fn parse_rule(value: &Value, at: &str, depth: usize) -> Result<Rule, String> {
+    if depth > MAX_RULE_DEPTH {
+        return Err(format!(
+            "{}: rules are nested deeper than {} levels",
+            at, MAX_RULE_DEPTH
+        ));
+    }
+    let object: &Map<String, Value> = value
+        .as_object()
+        .ok_or_else(|| format!("{}: expected an object like {{\"tags\": [\"sci-fi\"]}}", at))?;
+    let mut entries = object.iter();
+    let (key, value) = match (entries.next(), entries.next()) {
+        (Some(entry), None) => entry,
+        (None, _) => {
+            return Err(format!(
+                "{}: empty rule, expected one of {}",
+                at,
+                RULE_KEYS.join(", ")
+            ))
+        }
+        (Some(_), Some(_)) => {
+            return Err(format!(
+                "{}: a rule has a single key; combine several with \"all\" or \"any\"",
+                at
+            ))
+        }
+    };
+    let at = format!("{}.{}", at, key);
+
+    let filter = match key.as_str() {
+        "all" => return Ok(Rule::All(parse_rules(value, &at, depth)?)),
+        "any" => return Ok(Rule::Any(parse_rules(value, &at, depth)?)),
+        "tags" => Filter::Tags(strings(value, &at, "tag")?),
+        "subjects" => Filter::Subjects(strings(value, &at, "subject")?),
+        "status" => {
+            let names = strings(value, &at, "status")?;
+            let states = names
+                .iter()
+                .map(|name| {
+                    serde_json::from_value::<ReadingState>(Value::String(name.clone())).map_err(
+                        |_| {
+                            format!(
+                                "{}: unknown status '{}', expected one of {}",
+                                at,
+                                name,
+                                STATUS_NAMES.join(", ")
+                            )
+                        },
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Filter::Status(states)
+        }
+        "language" => match value.as_str().map(str::trim) {
+            Some(language) if !language.is_empty() => Filter::Language(language.to_lowercase()),
+            _ => return Err(format!("{}: expected a language code like \"en\"", at)),
+        },
+        "rating" => {
+            let (min, max) = bounds(value, &at, 1..=5)?;
+            Filter::Rating {
+                min: min.map(|n| n as u8),
+                max: max.map(|n| n as u8),
+            }
+        }
+        "words" => {
+            let (min, max) = bounds(value, &at, 0..=u64::MAX)?;
+            Filter::Words { min, max }
+        }
+        "author" => match value.as_str().map(str::trim) {
+            Some(author) if !author.is_empty() => Filter::Author(authors::fold(author)),
+            _ => return Err(format!("{}: expected part of an author's name", at)),
+        },
+        "progress" => {
+            let (min, max) = bounds(value, &at, 0..=100)?;
+            Filter::Progress {
+                min: min.map(|n| n as f32 / 100.0),
+                max: max.map(|n| n as f32 / 100.0),
+            }
+        }
+        "hasAnnotations" => Filter::HasAnnotations(
+            value
+                .as_bool()
+                .ok_or_else(|| format!("{}: expected true or false", at))?,
+        ),
+        "favorite" => Filter::Favorite(
+            value
+                .as_bool()
+                .ok_or_else(|| format!("{}: expected true or false", at))?,
+        ),
+        "archived" => Filter::Archived(
+            value
+                .as_bool()
+                .ok_or_else(|| format!("{}: expected true or false", at))?,
+        ),
+        _ => {
+            return Err(format!(
+                "{}: unknown filter, expected one of {}",
+                at,
+                RULE_KEYS.join(", ")
+            ))
+        }
+    };
+    Ok(Rule::Filter(filter))
+}
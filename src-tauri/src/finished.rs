@@ -0,0 +1,168 @@
+/**
+ * The finished shelf: books by when they were completed, re-reads included
+ */
+use chrono::{DateTime, Datelike, Local, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::State;
+
+use crate::config;
+use crate::library::{Book, Completion, ReadingState};
+use crate::logging;
+use crate::sessions;
+use crate::state::AppState;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FinishedEntry {
+    pub book: Book,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: DateTime<Utc>,
+    /// Days from starting the read (or first opening the book) to finishing
+    #[serde(rename = "daysToFinish")]
+    pub days_to_finish: Option<i64>,
+    /// "2024-03", in local time
+    pub month: String,
+    /// The date was worked out afterwards, not recorded
+    pub inferred: bool,
+    /// Not the first time the book was finished
+    pub reread: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FinishedMonth {
+    /// "2024-03"
+    pub month: String,
+    /// "March 2024"
+    pub label: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FinishedShelf {
+    /// Latest first
+    pub entries: Vec<FinishedEntry>,
+    /// Months with books finished, latest first
+    pub months: Vec<FinishedMonth>,
+}
+
+/// First session start and last session end of each book
+fn session_bounds(state: &AppState) -> HashMap<String, (DateTime<Utc>, DateTime<Utc>)> {
+    let mut bounds: HashMap<String, (DateTime<Utc>, DateTime<Utc>)> = HashMap::new();
+    let Ok(paths) = state.paths() else {
+        return bounds;
+    };
+    for session in sessions::load_sessions(&paths.sessions) {
+        let end = session.end.unwrap_or(session.last_activity);
+        bounds
+            .entry(session.book_id)
+            .and_modify(|(first, last)| {
+                *first = (*first).min(session.start);
+                *last = (*last).max(end);
+            })
+            .or_insert((session.start, end));
+    }
+    bounds
+}
+
+/// Give books finished before completions were recorded a completion, and
+/// a first-opened date where sessions tell it. Books finished without a
+/// date get their last session, else when they were last opened.
+pub fn infer_finish_dates(state: &AppState) -> Result<usize, String> {
+    if config::is_safe_mode() {
+        return Ok(0);
+    }
+    let bounds = session_bounds(state);
+    // Only books something can be inferred for, so the library isn't
+    // rewritten on every launch
+    let needs_dates = state.with_library(|library| {
+        library.books.iter().any(|b| {
+            (b.first_opened.is_none() && bounds.contains_key(&b.id))
+                || (b.reading_state == ReadingState::Finished && b.completions.is_empty())
+        })
+    })?;
+    if !needs_dates {
+        return Ok(0);
+    }
+
+    let updated = state.update_library(|library| {
+        let mut updated = 0;
+        for book in library.books.iter_mut() {
+            let sessions = bounds.get(&book.id);
+            let mut changed = false;
+            if book.first_opened.is_none() {
+                book.first_opened = sessions.map(|(first, _)| *first);
+                changed = book.first_opened.is_some();
+            }
+            if book.reading_state == ReadingState::Finished && book.completions.is_empty() {
+                let (finished_at, inferred) = match book.finished_at {
+                    Some(at) => (at, false),
+                    None => (sessions.map_or(book.last_opened, |(_, last)| *last), true),
+                };
+                book.finished_at = Some(finished_at);
+                book.completions.push(Completion {
+                    finished_at,
+                    started_at: book.first_opened,
+                    inferred,
+                });
+                changed = true;
+            }
+            if changed {
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    })?;
+    if updated > 0 {
+        logging::info(&format!("Inferred reading dates of {} books", updated));
+    }
+    Ok(updated)
+}
+
+/// Books finished in `year` (every year by default), a row per completion
+/// so re-reads show each time, latest first and grouped by month
+#[tauri::command]
+pub fn get_finished_books(
+    state: State<'_, AppState>,
+    year: Option<i32>,
+) -> Result<FinishedShelf, String> {
+    let books = state.with_library(|library| library.books.clone())?;
+
+    let mut entries: Vec<FinishedEntry> = Vec::new();
+    for book in books {
+        for (index, completion) in book.completions.iter().enumerate() {
+            let local = completion.finished_at.with_timezone(&Local);
+            if year.is_some_and(|year| local.year() != year) {
+                continue;
+            }
+            let started = completion.started_at.or(book.first_opened);
+            entries.push(FinishedEntry {
+                book: book.clone(),
+                finished_at: completion.finished_at,
+                days_to_finish: started
+                    .filter(|started| *started <= completion.finished_at)
+                    .map(|started| (completion.finished_at - started).num_days()),
+                month: local.format("%Y-%m").to_string(),
+                inferred: completion.inferred,
+                reread: index > 0,
+            });
+        }
+    }
+    entries.sort_by(|a, b| b.finished_at.cmp(&a.finished_at));
+
+    let mut months: Vec<FinishedMonth> = Vec::new();
+    for entry in &entries {
+        match months.last_mut() {
+            Some(month) if month.month == entry.month => month.count += 1,
+            _ => months.push(FinishedMonth {
+                month: entry.month.clone(),
+                label: entry
+                    .finished_at
+                    .with_timezone(&Local)
+                    .format("%B %Y")
+                    .to_string(),
+                count: 1,
+            }),
+        }
+    }
+    Ok(FinishedShelf { entries, months })
+}
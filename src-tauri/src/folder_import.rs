@@ -0,0 +1,163 @@
+/**
+ * Importing every EPUB in a folder, such as a Calibre library, in one go
+ */
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::advanced::AdvancedConfig;
+use crate::crash;
+use crate::epub;
+use crate::file_access;
+use crate::library::{self, BookNames, LibraryEvent, LibraryEventBatch};
+use crate::logging;
+use crate::state::AppState;
+
+/// Payload of "import-progress", sent before each file is imported
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportProgress {
+    pub current: usize,
+    pub total: usize,
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FailedImport {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct FolderImportSummary {
+    pub imported: usize,
+    /// Files already in the library, under their path or their contents
+    pub skipped: usize,
+    pub failed: Vec<FailedImport>,
+}
+
+fn is_epub(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"))
+}
+
+/// EPUBs in `dir`, in name order, and in its subfolders with `recursive`.
+/// Symlinked folders are not followed, so a link loop can't trap the scan.
+pub fn find_epubs(dir: &Path, recursive: bool, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        logging::warn(&format!("Failed to read folder {}", dir.display()));
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() {
+            if recursive {
+                find_epubs(&path, recursive, found);
+            }
+        } else if is_epub(&path) {
+            found.push(path);
+        }
+    }
+}
+
+/// Import one EPUB the way add_book does, once it is known to open
+pub fn import_file(
+    state: &AppState,
+    limits: &AdvancedConfig,
+    path: &Path,
+) -> Result<LibraryEvent, String> {
+    epub::validate_epub(path, limits)?;
+    let path = path.to_string_lossy().to_string();
+    let names = BookNames::Epub {
+        title: String::new(),
+        author: String::new(),
+    };
+    crash::catch_panic("importing the book", || {
+        library::import_book(state, names, path)
+    })?
+}
+
+fn import_all(app: &AppHandle, folder: &Path, recursive: bool) -> FolderImportSummary {
+    let state = app.state::<AppState>();
+    let limits = app.state::<AdvancedConfig>();
+    let mut files = Vec::new();
+    find_epubs(folder, recursive, &mut files);
+    let known: HashSet<String> = state
+        .with_library(|library| library.books.iter().map(|b| b.file_path.clone()).collect())
+        .unwrap_or_default();
+
+    let mut summary = FolderImportSummary::default();
+    let mut events = LibraryEventBatch::new(app);
+    let total = files.len();
+    for (index, path) in files.iter().enumerate() {
+        let _ = app.emit(
+            "import-progress",
+            ImportProgress {
+                current: index + 1,
+                total,
+                file_name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            },
+        );
+        if known.contains(path.to_string_lossy().as_ref()) {
+            summary.skipped += 1;
+            continue;
+        }
+
+        match import_file(&state, &limits, path) {
+            Ok(event @ LibraryEvent::Added(_)) => {
+                summary.imported += 1;
+                events.push(event);
+            }
+            // The same file was already on the shelf from another folder
+            Ok(event) => {
+                summary.skipped += 1;
+                events.push(event);
+            }
+            Err(reason) => {
+                logging::warn(&format!("Failed to import {}: {}", path.display(), reason));
+                summary.failed.push(FailedImport {
+                    path: path.to_string_lossy().to_string(),
+                    reason,
+                });
+            }
+        }
+    }
+
+    logging::info(&format!(
+        "Imported {} books from {}, skipped {}, {} failed",
+        summary.imported,
+        folder.display(),
+        summary.skipped,
+        summary.failed.len()
+    ));
+    summary
+}
+
+/// Import every EPUB in a folder picked with open_folder_dialog, and its
+/// subfolders with `recursive`. "import-progress" is emitted before each
+/// file; files that fail are reported in the summary rather than stopping
+/// the import.
+#[tauri::command]
+pub async fn import_folder(
+    app: AppHandle,
+    path: String,
+    recursive: bool,
+) -> Result<FolderImportSummary, String> {
+    let folder = file_access::check_read_access(&app.state::<AppState>(), &path)?;
+    if !folder.is_dir() {
+        return Err(format!("Not a folder: {}", path));
+    }
+    tauri::async_runtime::spawn_blocking(move || import_all(&app, &folder, recursive))
+        .await
+        .map_err(|e| format!("Folder import task failed: {}", e))
+}
@@ -0,0 +1,339 @@
+/**
+ * Footnotes and endnotes, looked up by their link so they can be shown in
+ * a popover instead of jumping to the back of the book
+ */
+use ego_tree::NodeRef;
+use scraper::{ElementRef, Html, Node};
+use serde::Serialize;
+use tauri::State;
+
+use crate::chapter;
+use crate::crash;
+use crate::error::AppError;
+use crate::file_access;
+use crate::state::AppState;
+
+/// Most paragraphs a note without a container of its own may span
+const MAX_NOTE_PARAGRAPHS: usize = 8;
+/// epub:type and role values of a single note
+const NOTE_TYPES: &[&str] = &[
+    "footnote",
+    "endnote",
+    "rearnote",
+    "note",
+    "doc-footnote",
+    "doc-endnote",
+];
+/// Elements a note ends before, when it runs over sibling paragraphs
+const NOTE_BREAKS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6", "hr", "section", "aside"];
+/// Blocks a note is cut from when it isn't marked up as one
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "dt",
+    "blockquote",
+    "td",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+/// Elements dropped with everything inside them
+const DROPPED_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "iframe", "object", "embed", "form", "button",
+    "input", "select", "textarea", "svg", "math", "img", "audio", "video",
+];
+/// Elements kept in the snippet; anything else is replaced by its content
+const KEPT_TAGS: &[&str] = &[
+    "p",
+    "br",
+    "em",
+    "strong",
+    "i",
+    "b",
+    "u",
+    "s",
+    "sub",
+    "sup",
+    "small",
+    "q",
+    "cite",
+    "abbr",
+    "code",
+    "blockquote",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "a",
+];
+/// Elements the plain text breaks lines after
+const LINE_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "li",
+    "dt",
+    "dd",
+    "blockquote",
+    "br",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+#[derive(Debug, Serialize, Clone)]
+pub struct NoteContent {
+    /// The note's location, resolved: file inside the EPUB and fragment
+    pub href: String,
+    /// Sanitized HTML of the note, without its link back to the text
+    pub html: String,
+    /// The note as plain text, a line per paragraph
+    pub text: String,
+}
+
+fn not_found(href: &str, reason: &str) -> AppError {
+    AppError::NoteNotFound {
+        href: href.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn has_token(element: ElementRef, attr: &str, tokens: &[&str]) -> bool {
+    element
+        .value()
+        .attr(attr)
+        .is_some_and(|value| value.split_whitespace().any(|t| tokens.contains(&t)))
+}
+
+/// An element marked up as a single note
+fn is_note(element: ElementRef) -> bool {
+    has_token(element, "epub:type", NOTE_TYPES) || has_token(element, "role", NOTE_TYPES)
+}
+
+/// A link from a note back to where it is referenced: marked as such, or a
+/// link whose text is only a number, a mark or an arrow
+fn is_backlink(element: ElementRef) -> bool {
+    let value = element.value();
+    if value.name() != "a" {
+        return false;
+    }
+    if has_token(element, "epub:type", &["backlink"])
+        || has_token(element, "role", &["doc-backlink"])
+    {
+        return true;
+    }
+    if !value.attr("href").is_some_and(|h| h.contains('#')) {
+        return false;
+    }
+    let text: String = element.text().collect();
+    let text = text.trim().to_lowercase();
+    text.is_empty()
+        || matches!(text.as_str(), "back" | "return" | "return to text")
+        || !text.chars().any(char::is_alphabetic)
+}
+
+/// The element a fragment names, by id or by an old-style `<a name>`
+fn find_target<'a>(document: &'a Html, fragment: &str) -> Option<ElementRef<'a>> {
+    let mut elements = document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap);
+    elements.find(|el| {
+        el.value().id() == Some(fragment)
+            || (el.value().name() == "a" && el.value().attr("name") == Some(fragment))
+    })
+}
+
+/// Whether `element` holds an anchor of its own, so starts another note
+fn has_anchor(element: ElementRef) -> bool {
+    element
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .any(|el| {
+            el.value().id().is_some()
+                || (el.value().name() == "a" && el.value().attr("name").is_some())
+        })
+}
+
+/// The elements making up the note `target` points at: its note container
+/// or list item, else the paragraph it is in and the paragraphs following
+/// that belong to the same note
+fn note_nodes(target: ElementRef) -> Vec<ElementRef> {
+    let mut block = None;
+    for element in std::iter::once(target).chain(target.ancestors().filter_map(ElementRef::wrap)) {
+        let name = element.value().name();
+        if name == "body" || name == "html" {
+            break;
+        }
+        if is_note(element) || name == "li" || name == "dd" || name == "aside" {
+            return vec![element];
+        }
+        if block.is_none() && BLOCK_TAGS.contains(&name) {
+            block = Some(element);
+        }
+    }
+
+    // An anchor right before its text, not inside any block
+    let start = block.unwrap_or(target);
+    let mut nodes = vec![start];
+    let name = start.value().name();
+    for sibling in start.next_siblings().filter_map(ElementRef::wrap) {
+        if nodes.len() >= MAX_NOTE_PARAGRAPHS {
+            break;
+        }
+        let sibling_name = sibling.value().name();
+        let ends = if name == "dt" {
+            sibling_name != "dd"
+        } else {
+            NOTE_BREAKS.contains(&sibling_name)
+                || sibling_name == "dt"
+                || is_note(sibling)
+                || has_anchor(sibling)
+        };
+        if ends {
+            break;
+        }
+        nodes.push(sibling);
+    }
+    nodes
+}
+
+/// Write a node as sanitized HTML and as text, leaving out backlinks
+fn write_node(node: NodeRef<Node>, html: &mut String, text: &mut String) {
+    let element = match node.value() {
+        Node::Text(t) => {
+            html.push_str(&escape_html(t));
+            text.push_str(t);
+            return;
+        }
+        Node::Element(element) => element,
+        _ => return,
+    };
+    let name = element.name();
+    if DROPPED_TAGS.contains(&name) || ElementRef::wrap(node).is_some_and(is_backlink) {
+        return;
+    }
+
+    let mut inner = String::new();
+    for child in node.children() {
+        write_node(child, &mut inner, text);
+    }
+    if LINE_TAGS.contains(&name) {
+        text.push('\n');
+    }
+    if !KEPT_TAGS.contains(&name) {
+        html.push_str(&inner);
+        return;
+    }
+    if name == "br" {
+        html.push_str("<br/>");
+        return;
+    }
+    // Wrappers left empty by a removed backlink, like <sup></sup>
+    if inner.trim().is_empty() {
+        return;
+    }
+    html.push('<');
+    html.push_str(name);
+    if name == "a" {
+        let href = element
+            .attr("href")
+            .filter(|h| !h.trim_start().to_lowercase().starts_with("javascript:"));
+        if let Some(href) = href {
+            html.push_str(&format!(" href=\"{}\"", escape_html(href)));
+        }
+    }
+    html.push('>');
+    html.push_str(&inner);
+    html.push_str(&format!("</{}>", name));
+}
+
+/// Sanitized HTML and text of the note in `content` at `fragment`
+fn extract_note(content: &str, fragment: &str) -> Option<(String, String)> {
+    let document = Html::parse_document(content);
+    let target = find_target(&document, fragment)?;
+
+    let mut html = String::new();
+    let mut text = String::new();
+    for (i, element) in note_nodes(target).into_iter().enumerate() {
+        let name = element.value().name();
+        // A container's own tag is left out; a lone <li> means nothing
+        let container = matches!(name, "li" | "dd" | "aside" | "div" | "section");
+        if i == 0 && (container || is_note(element)) {
+            let mut inner = String::new();
+            for child in element.children() {
+                write_node(child, &mut inner, &mut text);
+            }
+            html.push_str(&inner);
+        } else {
+            write_node(*element, &mut html, &mut text);
+        }
+        text.push('\n');
+    }
+
+    let text = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.is_empty() {
+        return None;
+    }
+    Some((html.trim().to_string(), text))
+}
+
+/// The note an internal link points at, for showing in a popover. `from` is
+/// the chapter the link is in, which relative links are resolved against.
+#[tauri::command]
+pub fn get_note_content(
+    state: State<'_, AppState>,
+    path: String,
+    href: String,
+    from: Option<String>,
+) -> Result<NoteContent, AppError> {
+    let path = file_access::check_read_access(&state, &path)?;
+    let resolved = chapter::resolve_href(&href, from.as_deref());
+    let Some((_, fragment)) = resolved.split_once('#').filter(|(_, f)| !f.is_empty()) else {
+        return Err(not_found(&href, "anchor"));
+    };
+    let fragment = fragment.to_string();
+
+    // A malformed EPUB must not take the app down with it
+    let note = crash::catch_panic("reading the note", || -> Result<_, AppError> {
+        let mut doc =
+            epub::doc::EpubDoc::new(&path).map_err(|e| format!("Failed to open EPUB: {:?}", e))?;
+        let (resource, _) =
+            chapter::resource_path(&doc, &resolved).ok_or_else(|| not_found(&href, "file"))?;
+        let content = doc
+            .get_resource_str_by_path(&resource)
+            .ok_or_else(|| format!("Failed to read '{}'", resource.display()))?;
+        let (html, text) =
+            extract_note(&content, &fragment).ok_or_else(|| not_found(&href, "anchor"))?;
+        Ok(NoteContent {
+            href: format!(
+                "{}#{}",
+                resource.to_string_lossy().replace('\\', "/"),
+                fragment
+            ),
+            html,
+            text,
+        })
+    })??;
+    Ok(note)
+}
@@ -0,0 +1,136 @@
+/**
+ * Distraction-free reading: fullscreen and immersive (no decorations) modes
+ */
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, WebviewWindow, Window, WindowEvent};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+
+use crate::logging;
+use crate::window_state;
+
+/// Always leaves fullscreen, even when the webview swallows the key
+const EXIT_SHORTCUT: &str = "Escape";
+
+/// Emitted to the window as "fullscreen-changed" so it can hide its chrome
+#[derive(Debug, Serialize, Clone)]
+pub struct FullscreenState {
+    pub label: String,
+    pub fullscreen: bool,
+    pub immersive: bool,
+}
+
+/// Put a window into (or out of) fullscreen and immersive mode.
+/// macOS gets native fullscreen in its own Space; elsewhere fullscreen is
+/// borderless, which avoids the flicker of exclusive mode switches.
+pub fn apply(window: &WebviewWindow, fullscreen: bool, immersive: bool) -> tauri::Result<()> {
+    #[cfg(target_os = "macos")]
+    {
+        window.set_decorations(!immersive)?;
+        window.set_fullscreen(fullscreen)?;
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        window.set_decorations(!(fullscreen || immersive))?;
+        window.set_fullscreen(fullscreen)?;
+    }
+
+    Ok(())
+}
+
+fn current_state(window: &WebviewWindow) -> FullscreenState {
+    FullscreenState {
+        label: window.label().to_string(),
+        fullscreen: window.is_fullscreen().unwrap_or(false),
+        immersive: window_state::is_immersive(window.label()),
+    }
+}
+
+fn set_mode(
+    app: &AppHandle,
+    window_label: &str,
+    fullscreen: bool,
+    immersive: bool,
+) -> Result<FullscreenState, String> {
+    let window = app
+        .get_webview_window(window_label)
+        .ok_or_else(|| format!("Window not found: {}", window_label))?;
+
+    apply(&window, fullscreen, immersive)
+        .map_err(|e| format!("Failed to change fullscreen mode: {}", e))?;
+    window_state::save_fullscreen(&window, fullscreen, immersive);
+
+    let state = FullscreenState {
+        label: window_label.to_string(),
+        fullscreen,
+        immersive,
+    };
+    let _ = app.emit_to(window_label, "fullscreen-changed", state.clone());
+    update_exit_shortcut(app);
+
+    Ok(state)
+}
+
+/// Toggle fullscreen for a window, keeping its immersive setting
+#[tauri::command]
+pub fn toggle_fullscreen(app: AppHandle, window_label: String) -> Result<FullscreenState, String> {
+    let window = app
+        .get_webview_window(&window_label)
+        .ok_or_else(|| format!("Window not found: {}", window_label))?;
+    let current = current_state(&window);
+
+    set_mode(&app, &window_label, !current.fullscreen, current.immersive)
+}
+
+/// Hide or show a window's decorations without changing fullscreen
+#[tauri::command]
+pub fn set_immersive(
+    app: AppHandle,
+    window_label: String,
+    immersive: bool,
+) -> Result<FullscreenState, String> {
+    let window = app
+        .get_webview_window(&window_label)
+        .ok_or_else(|| format!("Window not found: {}", window_label))?;
+    let current = current_state(&window);
+
+    set_mode(&app, &window_label, current.fullscreen, immersive)
+}
+
+fn focused_distraction_free(app: &AppHandle) -> Option<WebviewWindow> {
+    app.webview_windows().into_values().find(|window| {
+        let state = current_state(window);
+        window.is_focused().unwrap_or(false) && (state.fullscreen || state.immersive)
+    })
+}
+
+/// Escape is only claimed while a fullscreen/immersive window has focus,
+/// so it keeps working normally in every other app
+fn update_exit_shortcut(app: &AppHandle) {
+    let shortcuts = app.global_shortcut();
+    let wanted = focused_distraction_free(app).is_some();
+
+    let result = match (wanted, shortcuts.is_registered(EXIT_SHORTCUT)) {
+        (true, false) => shortcuts.on_shortcut(EXIT_SHORTCUT, |app, _shortcut, event| {
+            if event.state() != ShortcutState::Pressed {
+                return;
+            }
+            if let Some(window) = focused_distraction_free(app) {
+                let _ = set_mode(app, window.label(), false, false);
+            }
+        }),
+        (false, true) => shortcuts.unregister(EXIT_SHORTCUT),
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        logging::warn(&format!("Failed to update Escape shortcut: {}", e));
+    }
+}
+
+/// Window event hook: follow focus so Escape is claimed only when needed
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::Focused(_) = event {
+        update_exit_shortcut(window.app_handle());
+    }
+}
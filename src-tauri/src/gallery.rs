@@ -0,0 +1,259 @@
+/**
+ * A book's illustrations, maps and plates, gathered for a gallery
+ */
+use base64::Engine;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+use crate::chapter;
+use crate::covers;
+use crate::crash;
+use crate::error::AppError;
+use crate::file_access;
+use crate::state::AppState;
+
+/// Images smaller than this on either side are ornaments and icons
+const MIN_IMAGE_SIDE: u32 = 150;
+/// Longest caption kept, in characters
+const MAX_CAPTION_CHARS: usize = 300;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BookImage {
+    /// Path of the image inside the EPUB
+    pub href: String,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// The first chapter the image appears in
+    #[serde(rename = "spineIndex")]
+    pub spine_index: usize,
+    #[serde(rename = "chapterHref")]
+    pub chapter_href: String,
+    /// id of the image or its figure, to jump to it within the chapter
+    pub anchor: Option<String>,
+    pub caption: Option<String>,
+    pub alt: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct GalleryImage {
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// Base64 of the image
+    pub data: String,
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("invalid built-in selector")
+}
+
+fn element_text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A library book's file when `path_or_id` is a book id, else the path
+fn book_path(state: &AppState, path_or_id: &str) -> Result<PathBuf, AppError> {
+    let book_file = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .find(|b| b.id == path_or_id)
+            .map(|b| b.file_path.clone())
+    })?;
+    file_access::check_read_access(state, book_file.as_deref().unwrap_or(path_or_id))
+}
+
+/// Caption of an image: the <figcaption> of its figure, or one right next
+/// to the image
+fn caption(image: ElementRef) -> Option<String> {
+    let figcaption = selector("figcaption");
+    let figure = image
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .take(3)
+        .find(|el| el.value().name() == "figure");
+    let adjacent = || {
+        let element = ElementRef::wrap(image.parent()?).unwrap_or(image);
+        let mut siblings = [image, element]
+            .into_iter()
+            .flat_map(|el| el.prev_siblings().take(2).chain(el.next_siblings().take(2)))
+            .filter_map(ElementRef::wrap);
+        siblings.find(|el| el.value().name() == "figcaption")
+    };
+    let caption = match figure {
+        Some(figure) => figure.select(&figcaption).next(),
+        None => adjacent(),
+    }?;
+    let text: String = element_text(caption)
+        .chars()
+        .take(MAX_CAPTION_CHARS)
+        .collect();
+    (!text.is_empty()).then_some(text)
+}
+
+/// id of the image or of the nearest element around it that has one
+fn anchor(image: ElementRef) -> Option<String> {
+    std::iter::once(image)
+        .chain(image.ancestors().filter_map(ElementRef::wrap).take(3))
+        .take_while(|el| el.value().name() != "body")
+        .find_map(|el| el.value().id())
+        .map(str::to_string)
+}
+
+fn list_images(path: &Path, min_side: u32) -> Result<Vec<BookImage>, String> {
+    let mut doc =
+        epub::doc::EpubDoc::new(path).map_err(|e| format!("Failed to open EPUB: {:?}", e))?;
+    // <img src>, and SVG <image href> or <image xlink:href>
+    let images = selector("img[src], image");
+    let chapters: Vec<(PathBuf, String)> = doc
+        .spine
+        .iter()
+        .filter_map(|item| doc.resources.get(&item.idref).cloned())
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    for (spine_index, (chapter_path, mime)) in chapters.into_iter().enumerate() {
+        if !mime.contains("html") {
+            continue;
+        }
+        let Some(content) = doc.get_resource_str_by_path(&chapter_path) else {
+            continue;
+        };
+        let chapter_href = chapter_path.to_string_lossy().replace('\\', "/");
+        let document = Html::parse_document(&content);
+
+        for image in document.select(&images) {
+            let value = image.value();
+            let Some(src) = value
+                .attr("src")
+                .or_else(|| {
+                    value
+                        .attrs()
+                        .find(|(name, _)| *name == "href")
+                        .map(|(_, v)| v)
+                })
+                .filter(|src| !src.starts_with("data:"))
+            else {
+                continue;
+            };
+            let resolved = chapter::resolve_href(src, Some(&chapter_href));
+            let Some((resource, media_type)) = chapter::resource_path(&doc, &resolved) else {
+                continue;
+            };
+            if !seen.insert(resource.clone()) {
+                continue;
+            }
+            // SVG and WebP have no size we can read, and are left out
+            let Some((_, width, height)) = doc
+                .get_resource_by_path(&resource)
+                .and_then(|data| covers::image_info(&data))
+            else {
+                continue;
+            };
+            if width.min(height) < min_side {
+                continue;
+            }
+            found.push(BookImage {
+                href: resource.to_string_lossy().replace('\\', "/"),
+                media_type,
+                width,
+                height,
+                spine_index,
+                chapter_href: chapter_href.clone(),
+                anchor: anchor(image),
+                caption: caption(image),
+                alt: value
+                    .attr("alt")
+                    .map(str::trim)
+                    .filter(|alt| !alt.is_empty())
+                    .map(str::to_string),
+            });
+        }
+    }
+    Ok(found)
+}
+
+fn serve_image(
+    path: &Path,
+    href: &str,
+    max_dimension: Option<u32>,
+) -> Result<GalleryImage, AppError> {
+    let mut doc =
+        epub::doc::EpubDoc::new(path).map_err(|e| format!("Failed to open EPUB: {:?}", e))?;
+    let (resource, media_type) = chapter::resource_path(&doc, href)
+        .ok_or_else(|| format!("No image '{}' in this book", href))?;
+    let data = doc
+        .get_resource_by_path(&resource)
+        .ok_or_else(|| format!("Failed to read '{}'", resource.display()))?;
+    let (_, width, height) = covers::image_info(&data).unwrap_or(("", 0, 0));
+
+    let downscaled = max_dimension
+        .filter(|max| width.max(height) > *max)
+        .and_then(|max| covers::thumbnail_png_data(&data, max as usize, max as usize));
+    // GIFs aren't decoded, and are served as they are
+    Ok(match downscaled {
+        Some((png, width, height)) => GalleryImage {
+            media_type: "image/png".to_string(),
+            width: width as u32,
+            height: height as u32,
+            data: base64::engine::general_purpose::STANDARD.encode(png),
+        },
+        None => GalleryImage {
+            media_type,
+            width,
+            height,
+            data: base64::engine::general_purpose::STANDARD.encode(data),
+        },
+    })
+}
+
+/// Every image in a book at least `min_side` pixels on each side (150 by
+/// default), in reading order, each with the chapter it first appears in
+#[tauri::command]
+pub async fn get_book_images(
+    app: AppHandle,
+    path_or_id: String,
+    min_side: Option<u32>,
+) -> Result<Vec<BookImage>, AppError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = book_path(&app.state::<AppState>(), &path_or_id)?;
+        // A malformed EPUB must not take the app down with it
+        let images = crash::catch_panic("listing the book's images", || {
+            list_images(&path, min_side.unwrap_or(MIN_IMAGE_SIDE))
+        })??;
+        Ok(images)
+    })
+    .await
+    .map_err(|e| format!("Image listing task failed: {}", e))?
+}
+
+/// An image of a book, scaled down to fit `max_dimension` for a gallery
+/// grid. Without `max_dimension` it is the image as it is in the book.
+#[tauri::command]
+pub async fn get_image(
+    app: AppHandle,
+    path: String,
+    href: String,
+    max_dimension: Option<u32>,
+) -> Result<GalleryImage, AppError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = book_path(&app.state::<AppState>(), &path)?;
+        crash::catch_panic("reading an image", || {
+            serve_image(&path, &href, max_dimension)
+        })?
+    })
+    .await
+    .map_err(|e| format!("Image task failed: {}", e))?
+}
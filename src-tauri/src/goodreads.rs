@@ -0,0 +1,248 @@
+/**
+ * Goodreads-compatible CSV export, also accepted by StoryGraph
+ */
+use serde::Serialize;
+use std::fs;
+use tauri::State;
+
+use crate::isbn;
+use crate::library::{Book, ReadingState};
+use crate::state::AppState;
+
+/// The column layout of a Goodreads library export
+const COLUMNS: &[&str] = &[
+    "Book Id",
+    "Title",
+    "Author",
+    "Author l-f",
+    "Additional Authors",
+    "ISBN",
+    "ISBN13",
+    "My Rating",
+    "Average Rating",
+    "Publisher",
+    "Binding",
+    "Number of Pages",
+    "Year Published",
+    "Original Publication Year",
+    "Date Read",
+    "Date Added",
+    "Bookshelves",
+    "Bookshelves with positions",
+    "Exclusive Shelf",
+    "My Review",
+    "Spoiler",
+    "Private Notes",
+    "Read Count",
+    "Owned Copies",
+];
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct GoodreadsExportSummary {
+    pub path: String,
+    pub rows: usize,
+    pub read: usize,
+    #[serde(rename = "currentlyReading")]
+    pub currently_reading: usize,
+    #[serde(rename = "toRead")]
+    pub to_read: usize,
+    pub abandoned: usize,
+}
+
+fn shelf(state: ReadingState) -> &'static str {
+    match state {
+        ReadingState::Finished => "read",
+        ReadingState::Reading => "currently-reading",
+        ReadingState::ToRead => "to-read",
+        ReadingState::Abandoned => "did-not-finish",
+    }
+}
+
+/// Quote a field when it contains a delimiter, quote or line break
+pub fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Goodreads wraps ISBNs as ="..." so spreadsheets keep leading zeros;
+/// an empty ISBN is still written as =""
+fn isbn_field(isbn: Option<&str>) -> String {
+    format!("=\"{}\"", isbn.unwrap_or_default())
+}
+
+/// "Last, First" for a single "First Last" author
+fn author_last_first(author: &str) -> String {
+    match author.trim().rsplit_once(' ') {
+        Some((first, last)) => format!("{}, {}", last, first),
+        None => author.trim().to_string(),
+    }
+}
+
+fn book_row(book: &Book) -> Vec<String> {
+    // Books added before ISBNs were normalized may still have an ISBN-10
+    let isbn13 = book
+        .isbn
+        .as_deref()
+        .and_then(|i| isbn::normalize_isbn(i).ok());
+    let isbn10 = isbn13.as_deref().and_then(isbn::to_isbn10);
+    let shelf = shelf(book.reading_state);
+    let finished = book.reading_state == ReadingState::Finished;
+
+    vec![
+        String::new(),
+        book.title.clone(),
+        book.author.clone(),
+        author_last_first(&book.author),
+        String::new(),
+        isbn_field(isbn10.as_deref()),
+        isbn_field(isbn13.as_deref()),
+        book.rating.unwrap_or(0).to_string(),
+        String::new(),
+        String::new(),
+        "ebook".to_string(),
+        String::new(),
+        String::new(),
+        String::new(),
+        book.finished_at
+            .map(|d| d.format("%Y/%m/%d").to_string())
+            .unwrap_or_default(),
+        // The date a book was added isn't kept; last opened stands in
+        book.last_opened.format("%Y/%m/%d").to_string(),
+        shelf.to_string(),
+        format!("{} (#1)", shelf),
+        shelf.to_string(),
+        String::new(),
+        String::new(),
+        String::new(),
+        (if finished { "1" } else { "0" }).to_string(),
+        "1".to_string(),
+    ]
+}
+
+fn to_csv(books: &[Book]) -> String {
+    let mut csv = COLUMNS.join(",");
+    csv.push('\n');
+    for book in books {
+        let row: Vec<String> = book_row(book).iter().map(|f| escape_csv(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Write the library as a Goodreads library export. Books without an ISBN
+/// are included too; importers fall back to matching title and author.
+#[tauri::command]
+pub fn export_goodreads_csv(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<GoodreadsExportSummary, String> {
+    let books = state.with_library(|library| library.books.clone())?;
+
+    fs::write(&path, to_csv(&books))
+        .map_err(|e| format!("Failed to write Goodreads export: {}", e))?;
+
+    let mut summary = GoodreadsExportSummary {
+        path,
+        rows: books.len(),
+        ..Default::default()
+    };
+    for book in &books {
+        match book.reading_state {
+            ReadingState::Finished => summary.read += 1,
+            ReadingState::Reading => summary.currently_reading += 1,
+            ReadingState::ToRead => summary.to_read += 1,
+            ReadingState::Abandoned => summary.abandoned += 1,
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+pub mod testing {
+    /// Split CSV text into rows of fields the way spreadsheets do: quoted
+    /// fields may hold commas, line breaks and doubled quotes
+    pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                (true, '"') => quoted = false,
+                (true, c) => field.push(c),
+                (false, '"') => quoted = true,
+                (false, ',') => row.push(std::mem::take(&mut field)),
+                (false, '\r') if chars.peek() == Some(&'\n') => {}
+                (false, '\n') => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (false, c) => field.push(c),
+            }
+        }
+        if !field.is_empty() || !row.is_empty() {
+            row.push(field);
+            rows.push(row);
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::parse_csv;
+    use super::*;
+    use crate::state::testing::book;
+
+    #[test]
+    fn plain_fields_are_not_quoted() {
+        assert_eq!(escape_csv("Dune"), "Dune");
+        assert_eq!(escape_csv(""), "");
+        assert_eq!(escape_csv("He said \"no\""), "\"He said \"\"no\"\"\"");
+    }
+
+    #[test]
+    fn escaped_fields_round_trip() {
+        let fields = [
+            "Dune",
+            "Herbert, Frank",
+            "The \"spice\" must flow",
+            "first line\nsecond line",
+            "windows\r\nline end",
+            "lone\rreturn",
+            "\"\"",
+            ",\n\"",
+            "",
+        ];
+        let line: Vec<String> = fields.iter().map(|f| escape_csv(f)).collect();
+        let csv = format!("{}\n", line.join(","));
+
+        assert_eq!(parse_csv(&csv), vec![fields.to_vec()]);
+    }
+
+    #[test]
+    fn library_export_keeps_one_row_per_book() {
+        let mut first = book("a");
+        first.title = "War, and \"Peace\"".to_string();
+        first.author = "Leo Tolstoy".to_string();
+        let mut second = book("b");
+        second.title = "Two\nLines".to_string();
+
+        let rows = parse_csv(&to_csv(&[first, second]));
+
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| row.len() == COLUMNS.len()));
+        assert_eq!(rows[1][1], "War, and \"Peace\"");
+        assert_eq!(rows[1][2], "Leo Tolstoy");
+        assert_eq!(rows[2][1], "Two\nLines");
+    }
+}
@@ -0,0 +1,262 @@
+/**
+ * Project Gutenberg search and download, through the Gutendex API
+ */
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+use crate::advanced::AdvancedConfig;
+use crate::epub;
+use crate::library::{self, Book};
+use crate::logging;
+use crate::state::AppState;
+
+const GUTENDEX_URL: &str = "https://gutendex.com/books";
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(15);
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+/// Search results are reused for this long
+const SEARCH_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Tag added to every book downloaded from Gutenberg
+const GUTENBERG_TAG: &str = "gutenberg";
+
+type SearchCache = HashMap<(String, String), (Instant, Vec<GutenbergBook>)>;
+
+static SEARCH_CACHE: Mutex<Option<SearchCache>> = Mutex::new(None);
+
+#[derive(Debug, Deserialize)]
+struct GutendexPage {
+    results: Vec<GutendexBook>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GutendexPerson {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GutendexBook {
+    id: u64,
+    title: String,
+    #[serde(default)]
+    authors: Vec<GutendexPerson>,
+    #[serde(default)]
+    subjects: Vec<String>,
+    #[serde(default)]
+    languages: Vec<String>,
+    #[serde(default)]
+    download_count: u64,
+    #[serde(default)]
+    formats: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct GutenbergBook {
+    pub id: u64,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub subjects: Vec<String>,
+    pub languages: Vec<String>,
+    #[serde(rename = "downloadCount")]
+    pub download_count: u64,
+    #[serde(rename = "coverUrl")]
+    pub cover_url: Option<String>,
+    #[serde(rename = "epubUrl")]
+    pub epub_url: Option<String>,
+}
+
+/// Gutenberg lists authors as "Austen, Jane"
+fn display_name(name: &str) -> String {
+    match name.split_once(", ") {
+        Some((last, first)) => format!("{} {}", first, last),
+        None => name.to_string(),
+    }
+}
+
+impl From<GutendexBook> for GutenbergBook {
+    fn from(book: GutendexBook) -> Self {
+        let cover_url = book
+            .formats
+            .iter()
+            .find(|(mime, _)| mime.starts_with("image/"))
+            .map(|(_, url)| url.clone());
+        let epub_url = book
+            .formats
+            .iter()
+            .find(|(mime, _)| mime.starts_with("application/epub+zip"))
+            .map(|(_, url)| url.clone());
+        Self {
+            id: book.id,
+            title: book.title,
+            authors: book.authors.iter().map(|a| display_name(&a.name)).collect(),
+            subjects: book.subjects,
+            languages: book.languages,
+            download_count: book.download_count,
+            cover_url,
+            epub_url,
+        }
+    }
+}
+
+fn client(timeout: Duration) -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .user_agent(concat!("Epilogue/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn cached_search(key: &(String, String)) -> Option<Vec<GutenbergBook>> {
+    let cache = SEARCH_CACHE.lock().ok()?;
+    let (at, results) = cache.as_ref()?.get(key)?;
+    (at.elapsed() < SEARCH_CACHE_TTL).then(|| results.clone())
+}
+
+fn cache_search(key: (String, String), results: &[GutenbergBook]) {
+    if let Ok(mut cache) = SEARCH_CACHE.lock() {
+        let cache = cache.get_or_insert_with(HashMap::new);
+        cache.retain(|_, (at, _)| at.elapsed() < SEARCH_CACHE_TTL);
+        cache.insert(key, (Instant::now(), results.to_vec()));
+    }
+}
+
+async fn fetch_books(query: &[(&str, String)]) -> Result<Vec<GutenbergBook>, String> {
+    let url = reqwest::Url::parse_with_params(GUTENDEX_URL, query)
+        .map_err(|e| format!("Invalid Gutenberg search: {}", e))?;
+    let response = client(SEARCH_TIMEOUT)?
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach Project Gutenberg: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Gutenberg search failed: HTTP {}",
+            response.status()
+        ));
+    }
+
+    let page: GutendexPage = response
+        .json()
+        .await
+        .map_err(|e| format!("Unexpected reply from Gutenberg search: {}", e))?;
+    Ok(page.results.into_iter().map(GutenbergBook::from).collect())
+}
+
+/// Search the Gutenberg catalog, most downloaded first. `language` is a
+/// two-letter code such as "en".
+#[tauri::command]
+pub async fn search_gutenberg(
+    query: String,
+    language: Option<String>,
+) -> Result<Vec<GutenbergBook>, String> {
+    let key = (
+        query.trim().to_lowercase(),
+        language.clone().unwrap_or_default(),
+    );
+    if let Some(results) = cached_search(&key) {
+        return Ok(results);
+    }
+
+    let mut params = vec![("search", query.trim().to_string())];
+    if let Some(language) = language.filter(|l| !l.is_empty()) {
+        params.push(("languages", language));
+    }
+    let results = fetch_books(&params).await?;
+    cache_search(key, &results);
+    Ok(results)
+}
+
+/// EPUB URLs to try, the variant with images first
+fn epub_candidates(book: &GutenbergBook) -> Vec<String> {
+    let mut urls = vec![format!(
+        "https://www.gutenberg.org/ebooks/{}.epub3.images",
+        book.id
+    )];
+    if let Some(url) = &book.epub_url {
+        if url.contains("images") && !url.contains("noimages") {
+            urls.insert(0, url.clone());
+        } else {
+            urls.push(url.clone());
+        }
+    }
+    urls.dedup();
+    urls
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = client(DOWNLOAD_TIMEOUT)?
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Download failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Download failed: HTTP {}", response.status()));
+    }
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Download failed: {}", e))?;
+    // EPUBs are zip files
+    if !bytes.starts_with(b"PK") {
+        return Err("Download wasn't an EPUB".to_string());
+    }
+    Ok(bytes.to_vec())
+}
+
+/// Try each variant, retrying each once
+async fn download_epub(book: &GutenbergBook) -> Result<Vec<u8>, String> {
+    let mut last_error = "No EPUB available for this book".to_string();
+    for url in epub_candidates(book) {
+        for attempt in 0..2 {
+            match download(&url).await {
+                Ok(data) => return Ok(data),
+                Err(e) => {
+                    logging::debug(&format!("{} (attempt {}): {}", url, attempt + 1, e));
+                    last_error = e;
+                }
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Download a Gutenberg book into the managed books folder and add it to
+/// the library, tagged "gutenberg" and with its catalog subjects
+#[tauri::command]
+pub async fn download_gutenberg_book(app: AppHandle, id: u64) -> Result<Book, String> {
+    let book = fetch_books(&[("ids", id.to_string())])
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Gutenberg book {} not found", id))?;
+    let data = download_epub(&book).await?;
+
+    let state = app.state::<AppState>();
+    let books_dir = state.paths()?.books.clone();
+    fs::create_dir_all(&books_dir)
+        .map_err(|e| format!("Failed to create books directory: {}", e))?;
+    let path = books_dir.join(format!("gutenberg-{}.epub", id));
+    fs::write(&path, data).map_err(|e| format!("Failed to save book: {}", e))?;
+    if let Err(e) = epub::check_zip_limits(&path, &app.state::<AdvancedConfig>()) {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+
+    let author = if book.authors.is_empty() {
+        "Unknown".to_string()
+    } else {
+        book.authors.join(", ")
+    };
+    library::import_tagged_book(
+        &app,
+        &state,
+        book.title,
+        author,
+        path.to_string_lossy().to_string(),
+        std::iter::once(GUTENBERG_TAG.to_string())
+            .chain(book.subjects)
+            .collect(),
+    )
+}
@@ -0,0 +1,204 @@
+/**
+ * ISBN normalization and validation, and looking ISBNs up on Open Library
+ */
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+use crate::error::AppError;
+use crate::library::{self, Book};
+use crate::open_library;
+use crate::state::AppState;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct IsbnRecord {
+    pub isbn: String,
+    pub title: String,
+    pub author: Option<String>,
+    #[serde(rename = "publishYear")]
+    pub publish_year: Option<i32>,
+    /// Open Library work key, "/works/OL…W"
+    pub key: String,
+}
+
+fn invalid(input: &str, reason: &str) -> AppError {
+    AppError::InvalidIsbn {
+        input: input.trim().to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Check digit of the first nine digits of an ISBN-10, 10 written as X
+fn isbn10_check(digits: &[u32]) -> char {
+    let sum: u32 = digits.iter().zip((2..=10).rev()).map(|(d, w)| d * w).sum();
+    match (11 - sum % 11) % 11 {
+        10 => 'X',
+        check => char::from_digit(check, 10).unwrap_or('0'),
+    }
+}
+
+/// Check digit of the first twelve digits of an ISBN-13
+fn isbn13_check(digits: &[u32]) -> char {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 })
+        .sum();
+    char::from_digit((10 - sum % 10) % 10, 10).unwrap_or('0')
+}
+
+/// Bring an ISBN to a bare ISBN-13: "urn:isbn:" prefixes, hyphens and
+/// spaces are dropped and ISBN-10s converted. Bad check digits are told
+/// apart from input that isn't an ISBN at all.
+pub fn normalize_isbn(input: &str) -> Result<String, AppError> {
+    let lower = input.trim().to_lowercase();
+    let value = lower
+        .strip_prefix("urn:isbn:")
+        .or_else(|| lower.strip_prefix("isbn:"))
+        .unwrap_or(&lower);
+    let isbn: Vec<char> = value
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let digits: Vec<u32> = isbn.iter().filter_map(|c| c.to_digit(10)).collect();
+
+    match isbn.len() {
+        10 => {
+            // X is only a check digit, so it can only come last
+            if !isbn[..9].iter().all(char::is_ascii_digit)
+                || !(isbn[9].is_ascii_digit() || isbn[9] == 'X')
+            {
+                return Err(invalid(input, "format"));
+            }
+            if isbn10_check(&digits[..9]) != isbn[9] {
+                return Err(invalid(input, "checkDigit"));
+            }
+            let mut isbn13 = vec![9, 7, 8];
+            isbn13.extend(&digits[..9]);
+            let mut out: String = isbn13
+                .iter()
+                .filter_map(|d| char::from_digit(*d, 10))
+                .collect();
+            out.push(isbn13_check(&isbn13));
+            Ok(out)
+        }
+        13 => {
+            if digits.len() != 13 {
+                return Err(invalid(input, "format"));
+            }
+            if isbn13_check(&digits[..12]) != isbn[12] {
+                return Err(invalid(input, "checkDigit"));
+            }
+            Ok(isbn.into_iter().collect())
+        }
+        _ => Err(invalid(input, "format")),
+    }
+}
+
+/// The ISBN-10 of a normalized ISBN-13, which only 978 ISBNs have
+pub fn to_isbn10(isbn13: &str) -> Option<String> {
+    let rest = isbn13.strip_prefix("978")?;
+    let digits: Vec<u32> = rest.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() != 10 {
+        return None;
+    }
+    let mut out = rest[..9].to_string();
+    out.push(isbn10_check(&digits[..9]));
+    Some(out)
+}
+
+/// Title, author and year of the book with an ISBN, from Open Library
+#[tauri::command]
+pub async fn lookup_isbn(isbn: String) -> Result<IsbnRecord, AppError> {
+    let isbn = normalize_isbn(&isbn)?;
+    let found = open_library::search(&[("isbn", isbn.as_str())], Some(&isbn))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::IsbnNotFound { isbn: isbn.clone() })?;
+    Ok(IsbnRecord {
+        isbn,
+        title: found.title,
+        author: (!found.authors.is_empty()).then(|| found.authors.join(", ")),
+        publish_year: found.publish_year,
+        key: found.key,
+    })
+}
+
+/// Set or clear a book's ISBN by hand. It is stored normalized, and
+/// rejected if it doesn't validate.
+#[tauri::command]
+pub fn set_book_isbn(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    isbn: Option<String>,
+) -> Result<Book, AppError> {
+    let isbn = match isbn.filter(|i| !i.trim().is_empty()) {
+        Some(isbn) => Some(normalize_isbn(&isbn)?),
+        None => None,
+    };
+    Ok(library::update_book(&app, &state, &book_id, |book| {
+        book.isbn = isbn
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(input: &str) -> String {
+        match normalize_isbn(input) {
+            Err(AppError::InvalidIsbn { reason, .. }) => reason,
+            other => panic!("expected {} to be invalid, got {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn isbn10_is_converted_to_isbn13() {
+        assert_eq!(normalize_isbn("0306406152").unwrap(), "9780306406157");
+        assert_eq!(normalize_isbn("080442957X").unwrap(), "9780804429573");
+        assert_eq!(normalize_isbn("080442957x").unwrap(), "9780804429573");
+    }
+
+    #[test]
+    fn separators_and_prefixes_are_dropped() {
+        for input in [
+            "978-0-306-40615-7",
+            "978 0 306 40615 7",
+            " 9780306406157 ",
+            "urn:isbn:9780306406157",
+            "URN:ISBN:978-0-306-40615-7",
+            "isbn:0-306-40615-2",
+        ] {
+            assert_eq!(normalize_isbn(input).unwrap(), "9780306406157", "{}", input);
+        }
+    }
+
+    #[test]
+    fn x_only_counts_as_the_last_digit() {
+        assert_eq!(reason("03064X6152"), "format");
+        assert_eq!(reason("X306406152"), "format");
+        assert_eq!(reason("978030640615X"), "format");
+    }
+
+    #[test]
+    fn bad_check_digits_are_told_apart_from_bad_format() {
+        assert_eq!(reason("0306406153"), "checkDigit");
+        assert_eq!(reason("0804429570"), "checkDigit");
+        assert_eq!(reason("9780306406158"), "checkDigit");
+
+        assert_eq!(reason("030640615"), "format");
+        assert_eq!(reason("97803064061577"), "format");
+        assert_eq!(reason("978-0-306-4O615-7"), "format");
+        assert_eq!(reason(""), "format");
+    }
+
+    #[test]
+    fn isbn10_only_exists_for_978() {
+        assert_eq!(to_isbn10("9780306406157").as_deref(), Some("0306406152"));
+        assert_eq!(to_isbn10("9780804429573").as_deref(), Some("080442957X"));
+        assert_eq!(to_isbn10("9791090636071"), None);
+        assert_eq!(to_isbn10("978030640615"), None);
+    }
+}
@@ -0,0 +1,1290 @@
+/**
+ * Direct library sync between Epilogue instances on the same network.
+ *
+ * Instances advertise themselves over mDNS. Pairing shows a 6-digit code on
+ * the instance being paired with, which is typed into the other one. The two
+ * sides then run SPAKE2 with the code as the password: the code never crosses
+ * the network, a listener learns nothing to test guesses against, and a
+ * device in the middle gets one guess per attempt. A code is only made once
+ * the user accepts the request, one pairing runs at a time, and requests
+ * are refused for a while after one is declined or its code missed.
+ *
+ * Every sync request and reply after that is signed with the key the
+ * exchange agrees on. Signing only proves who sent them: sync payloads
+ * (the library, annotations and book files) are not encrypted, so anyone
+ * on the network can read them.
+ */
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use hmac::{Hmac, Mac};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::annotations::{self, BookAnnotations};
+use crate::config;
+use crate::library::{self, Book, Library, LibraryEvent};
+use crate::logging;
+use crate::meta;
+use crate::preferences::UserPreferences;
+use crate::state::AppState;
+
+const SERVICE_TYPE: &str = "_epilogue._tcp.local.";
+/// Pairing keys live in the OS keychain under this service
+const KEYRING_SERVICE: &str = "Epilogue LAN sync";
+/// How long discover_peers listens for announcements
+const DISCOVERY_TIME: Duration = Duration::from_secs(3);
+/// A pairing code is valid this long, for this many attempts
+const PAIRING_TTL: Duration = Duration::from_secs(120);
+const PAIRING_ATTEMPTS: u32 = 3;
+/// How long the user has to accept a pairing request; the device asking
+/// waits for the answer, so this is within IO_TIMEOUT
+const PAIRING_ANSWER_TIME: Duration = Duration::from_secs(45);
+/// Pairing requests are refused this long after one was declined, left
+/// unanswered or had its code missed too often
+const PAIRING_LOCKOUT: Duration = Duration::from_secs(300);
+/// Signed requests older than this are rejected as replays
+const MAX_CLOCK_SKEW_SECS: i64 = 300;
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest message accepted before the sender has shown it holds a pairing
+/// key: pairing messages and sync headers
+const MAX_UNAUTHENTICATED_BYTES: u64 = 16 * 1024;
+/// Largest message accepted after that, book files included
+const MAX_MESSAGE_BYTES: u64 = 512 * 1024 * 1024;
+/// Connections served at once; more are closed right away
+const MAX_CONNECTIONS: usize = 8;
+/// What a sync header is signed for, so it can't pass as anything else
+const SYNC_HEADER: &str = "sync-header";
+/// Managed books larger than this are not copied
+const MAX_SHARED_BOOK_BYTES: u64 = 100 * 1024 * 1024;
+const ACCEPT_POLL: Duration = Duration::from_millis(200);
+/// Labels hashed to the SPAKE2 points M (the initiator's) and N (the
+/// responder's), whose discrete logs nobody knows
+const SPAKE_M: &str = "epilogue-lan-sync SPAKE2 M";
+const SPAKE_N: &str = "epilogue-lan-sync SPAKE2 N";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Another instance seen on the network
+#[derive(Debug, Serialize, Clone)]
+pub struct Peer {
+    pub id: String,
+    pub name: String,
+    pub address: String,
+    pub port: u16,
+    pub paired: bool,
+}
+
+/// Peers we paired with, without their keys (those are in the keychain)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PairedPeer {
+    name: String,
+    /// When we last exchanged changes, by our clock
+    #[serde(rename = "lastSync", default)]
+    last_sync: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct PeersFile {
+    #[serde(default)]
+    peers: HashMap<String, PairedPeer>,
+}
+
+/// A pairing request from another device: waiting for the user to accept
+/// it, then for the other side to enter the code we show
+struct IncomingPairing {
+    device_id: String,
+    name: String,
+    /// The user's answer to the request
+    accepted: Option<bool>,
+    /// Set once accepted
+    code: Option<ShownCode>,
+    attempts: u32,
+    started: Instant,
+}
+
+/// A pairing code we are showing
+struct ShownCode {
+    code: String,
+    /// Our SPAKE2 secret and the message we sent with it
+    secret: Scalar,
+    message: String,
+}
+
+struct Service {
+    daemon: ServiceDaemon,
+    fullname: String,
+    stop: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+pub struct LanSync {
+    service: Mutex<Option<Service>>,
+    /// Peers found by the last discovery, by id
+    discovered: Mutex<HashMap<String, Peer>>,
+    incoming: Mutex<Option<IncomingPairing>>,
+    /// Until when pairing requests are refused
+    pairing_locked_until: Mutex<Option<Instant>>,
+    /// The SPAKE2 message of each peer we asked to pair, by peer id
+    outgoing: Mutex<HashMap<String, String>>,
+}
+
+impl LanSync {
+    fn lock_out_pairing(&self) {
+        if let Ok(mut until) = self.pairing_locked_until.lock() {
+            *until = Some(Instant::now() + PAIRING_LOCKOUT);
+        }
+    }
+
+    fn pairing_locked_out(&self) -> bool {
+        self.pairing_locked_until
+            .lock()
+            .is_ok_and(|until| until.is_some_and(|until| Instant::now() < until))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct SyncDelta {
+    books: Vec<Book>,
+    annotations: Vec<BookAnnotations>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BookFile {
+    #[serde(rename = "bookId")]
+    book_id: String,
+    #[serde(rename = "fileName")]
+    file_name: String,
+    data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SyncRequest {
+    delta: SyncDelta,
+    /// Set to receive managed book files we don't have
+    #[serde(rename = "includeFiles")]
+    include_files: bool,
+    /// Books we already have, so their files aren't sent
+    #[serde(rename = "knownBooks")]
+    known_books: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SyncReply {
+    delta: SyncDelta,
+    files: Vec<BookFile>,
+}
+
+/// A payload signed with the pairing key
+#[derive(Debug, Serialize, Deserialize)]
+struct Signed {
+    #[serde(rename = "deviceId")]
+    device_id: String,
+    timestamp: i64,
+    payload: String,
+    mac: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Request {
+    PairStart {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        name: String,
+    },
+    PairFinish {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        message: String,
+        proof: String,
+    },
+    /// A header signed for SYNC_HEADER, with no payload. The signed sync
+    /// request follows on the next line, signed with the header's MAC as
+    /// context.
+    Sync(Signed),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Response {
+    PairStarted { message: String },
+    Paired { name: String, proof: String },
+    Synced(Signed),
+    Error { message: String },
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct LanSyncSummary {
+    #[serde(rename = "booksSent")]
+    pub books_sent: usize,
+    #[serde(rename = "booksReceived")]
+    pub books_received: usize,
+    #[serde(rename = "annotationsReceived")]
+    pub annotations_received: usize,
+    #[serde(rename = "filesReceived")]
+    pub files_received: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct PairingRequest {
+    #[serde(rename = "peerName")]
+    peer_name: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct PairingCode {
+    #[serde(rename = "peerName")]
+    peer_name: String,
+    code: String,
+}
+
+fn device_id() -> Result<String, String> {
+    if let Some(id) = meta::load_meta().device_id {
+        return Ok(id);
+    }
+    let id = random_hex(8);
+    meta::update_meta(|m| m.device_id = Some(id.clone()))?;
+    Ok(id)
+}
+
+fn device_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .or_else(|_| fs::read_to_string("/etc/hostname").map(|s| s.trim().to_string()))
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "Epilogue".to_string())
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect()
+}
+
+fn hmac_hex(key: &str, message: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(message.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn verify_hmac(key: &str, message: &str, expected: &str) -> bool {
+    let expected: Option<Vec<u8>> = (0..expected.len())
+        .step_by(2)
+        .map(|i| {
+            expected
+                .get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect();
+    let Some(expected) = expected else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(message.as_bytes());
+    // Constant-time comparison
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// The code as a SPAKE2 password
+fn code_scalar(code: &str) -> Scalar {
+    Scalar::hash_from_bytes::<Sha512>(format!("epilogue-pairing-code|{}", code).as_bytes())
+}
+
+/// The point one side blinds its message with
+fn blinding(initiator: bool) -> RistrettoPoint {
+    let label = if initiator { SPAKE_M } else { SPAKE_N };
+    RistrettoPoint::hash_from_bytes::<Sha512>(label.as_bytes())
+}
+
+/// Start our side of the exchange: a fresh secret, and the message to send
+/// (secret * G, blinded with the code)
+fn pake_start(code: &str, initiator: bool) -> (Scalar, String) {
+    let mut wide = [0u8; 64];
+    rand::thread_rng().fill(&mut wide[..]);
+    let secret = Scalar::from_bytes_mod_order_wide(&wide);
+    let point = RistrettoPoint::mul_base(&secret) + blinding(initiator) * code_scalar(code);
+    let message = base64::engine::general_purpose::STANDARD.encode(point.compress().as_bytes());
+    (secret, message)
+}
+
+/// Finish the exchange with the other side's message. Both sides get the
+/// same session secret only if they used the same code; it covers the
+/// initiator's device id and both messages.
+fn pake_finish(
+    code: &str,
+    secret: &Scalar,
+    initiator: bool,
+    initiator_id: &str,
+    ours: &str,
+    theirs: &str,
+) -> Result<String, String> {
+    let invalid = || "Invalid pairing message".to_string();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(theirs)
+        .map_err(|_| invalid())?;
+    let their_point = CompressedRistretto::from_slice(&bytes)
+        .map_err(|_| invalid())?
+        .decompress()
+        .ok_or_else(invalid)?;
+    let w = code_scalar(code);
+    let shared = (their_point - blinding(!initiator) * w) * secret;
+
+    let (a, b) = if initiator {
+        (ours, theirs)
+    } else {
+        (theirs, ours)
+    };
+    let mut hasher = Sha256::new();
+    for part in [
+        b"epilogue-pairing-v2".as_slice(),
+        initiator_id.as_bytes(),
+        a.as_bytes(),
+        b.as_bytes(),
+        shared.compress().as_bytes(),
+        w.as_bytes(),
+    ] {
+        // Length-prefixed, so parts can't run into each other
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn keyring_entry(peer_id: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, peer_id)
+        .map_err(|e| format!("Failed to open the system keychain: {}", e))
+}
+
+fn peer_key(peer_id: &str) -> Option<String> {
+    keyring_entry(peer_id).ok()?.get_password().ok()
+}
+
+fn peers_path(state: &AppState) -> Result<std::path::PathBuf, String> {
+    Ok(state.paths()?.app_dir.join("lan_peers.json"))
+}
+
+fn load_peers(state: &AppState) -> PeersFile {
+    peers_path(state)
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_peers(state: &AppState, peers: &PeersFile) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(peers)
+        .map_err(|e| format!("Failed to serialize LAN peers: {}", e))?;
+    fs::write(peers_path(state)?, json).map_err(|e| format!("Failed to write LAN peers: {}", e))
+}
+
+fn remember_peer(state: &AppState, peer_id: &str, name: &str, key: &str) -> Result<(), String> {
+    keyring_entry(peer_id)?
+        .set_password(key)
+        .map_err(|e| format!("Failed to store pairing key: {}", e))?;
+    let mut peers = load_peers(state);
+    peers.peers.insert(
+        peer_id.to_string(),
+        PairedPeer {
+            name: name.to_string(),
+            last_sync: None,
+        },
+    );
+    save_peers(state, &peers)
+}
+
+/// Sign a payload. Replies pass the request's MAC as `context`, so a reply
+/// can't be replayed for another request.
+fn sign(device_id: &str, key: &str, payload: String, context: &str) -> Signed {
+    let timestamp = Utc::now().timestamp();
+    let mac = hmac_hex(
+        key,
+        &format!("{}|{}|{}|{}", context, device_id, timestamp, payload),
+    );
+    Signed {
+        device_id: device_id.to_string(),
+        timestamp,
+        payload,
+        mac,
+    }
+}
+
+fn verify(signed: &Signed, key: &str, context: &str) -> Result<(), String> {
+    if (Utc::now().timestamp() - signed.timestamp).abs() > MAX_CLOCK_SKEW_SECS {
+        return Err("Request expired; check both clocks".to_string());
+    }
+    let message = format!(
+        "{}|{}|{}|{}",
+        context, signed.device_id, signed.timestamp, signed.payload
+    );
+    if !verify_hmac(key, &message, &signed.mac) {
+        return Err("Signature doesn't match".to_string());
+    }
+    Ok(())
+}
+
+/// Books and annotations changed since `since`
+fn local_delta(state: &AppState, since: Option<DateTime<Utc>>) -> Result<SyncDelta, String> {
+    let books: Vec<Book> = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .filter(|b| since.is_none_or(|since| b.last_opened > since))
+            .cloned()
+            .collect()
+    })?;
+
+    let mut annotations = Vec::new();
+    if let Ok(entries) = fs::read_dir(&state.paths()?.annotations) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let modified = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .map(DateTime::<Utc>::from);
+            if since.is_some_and(|since| modified.is_ok_and(|m| m <= since)) {
+                continue;
+            }
+            let path = entry.path();
+            // Highlights waiting in pending/ belong to no book yet
+            if !path.is_file() {
+                continue;
+            }
+            let Some(book_id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if let Ok(book_annotations) = annotations::load_annotations(state, book_id) {
+                annotations.push(book_annotations);
+            }
+        }
+    }
+
+    Ok(SyncDelta { books, annotations })
+}
+
+/// Merge a peer's changes. Returns (books, annotation sets) that changed.
+fn apply_delta(app: &AppHandle, delta: SyncDelta) -> Result<(usize, usize), String> {
+    let state = app.state::<AppState>();
+
+    let books = delta.books.into_iter().map(without_paths).collect();
+    let merged = state.update_library(|library| {
+        Ok(library::merge_libraries(
+            library,
+            Library {
+                books,
+                ..Library::default()
+            },
+        ))
+    })?;
+    let books = merged.added + merged.updated;
+    if books > 0 {
+        library::emit_library_event(app, LibraryEvent::Reloaded);
+    }
+
+    let mut changed = 0;
+    for incoming in delta.annotations {
+        let mut local = annotations::load_annotations(&state, &incoming.book_id)?;
+        if annotations::merge_annotations(&mut local, incoming) > 0 {
+            annotations::save_annotations(&state, &local)?;
+            changed += 1;
+        }
+    }
+
+    Ok((books, changed))
+}
+
+/// A peer's book without its paths, which mean nothing here and would let
+/// the peer name any file as a library book. A book new to us has no file
+/// until store_files copies one into books/.
+fn without_paths(mut book: Book) -> Book {
+    book.file_path = String::new();
+    book.cover_path = None;
+    book.thumbnail_path = None;
+    book.missing = true;
+    book
+}
+
+/// Managed book files (under books/) the peer doesn't have
+fn shareable_files(state: &AppState, known: &[String]) -> Result<Vec<BookFile>, String> {
+    let Ok(books_dir) = state.paths()?.books.canonicalize() else {
+        return Ok(Vec::new());
+    };
+    let books = state.with_library(|library| library.books.clone())?;
+
+    let mut files = Vec::new();
+    for book in books.into_iter().filter(|b| !known.contains(&b.id)) {
+        let Ok(path) = Path::new(&book.file_path).canonicalize() else {
+            continue;
+        };
+        let too_big = fs::metadata(&path).map_or(true, |m| m.len() > MAX_SHARED_BOOK_BYTES);
+        if !path.starts_with(&books_dir) || too_big {
+            continue;
+        }
+        let (Some(file_name), Ok(data)) = (
+            path.file_name().map(|n| n.to_string_lossy().to_string()),
+            fs::read(&path),
+        ) else {
+            continue;
+        };
+        files.push(BookFile {
+            book_id: book.id,
+            file_name,
+            data: base64::engine::general_purpose::STANDARD.encode(data),
+        });
+    }
+    Ok(files)
+}
+
+/// Save received book files into books/ and point their entries at them,
+/// for books with no file of their own
+fn store_files(app: &AppHandle, files: Vec<BookFile>) -> Result<usize, String> {
+    let state = app.state::<AppState>();
+    let dir = state.paths()?.books.clone();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create books directory: {}", e))?;
+
+    let mut stored = Vec::new();
+    for file in files {
+        // Never trust a peer's file name with a path in it
+        let Some(file_name) = Path::new(&file.file_name).file_name() else {
+            continue;
+        };
+        let path = dir.join(file_name);
+        if path.exists() {
+            continue;
+        }
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(&file.data)
+            .map_err(|e| format!("Received a damaged book file: {}", e))?;
+        fs::write(&path, data).map_err(|e| format!("Failed to save book file: {}", e))?;
+        stored.push((file.book_id, path.to_string_lossy().to_string()));
+    }
+
+    if !stored.is_empty() {
+        state.update_library(|library| {
+            for (book_id, path) in &stored {
+                let book = library
+                    .books
+                    .iter_mut()
+                    .find(|b| &b.id == book_id && !Path::new(&b.file_path).exists());
+                if let Some(book) = book {
+                    book.file_path = path.clone();
+                    book.missing = false;
+                }
+            }
+            Ok(())
+        })?;
+        library::emit_library_event(app, LibraryEvent::Reloaded);
+    }
+    Ok(stored.len())
+}
+
+/// Read one message of at most `limit` bytes
+fn read_message<T: for<'de> Deserialize<'de>>(
+    reader: &mut impl BufRead,
+    limit: u64,
+) -> Result<T, String> {
+    let mut line = String::new();
+    reader
+        .take(limit)
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read from peer: {}", e))?;
+    if !line.ends_with('\n') {
+        return Err("Peer sent a message that is too large or cut short".to_string());
+    }
+    serde_json::from_str(&line).map_err(|e| format!("Peer sent an invalid message: {}", e))
+}
+
+fn write_message<T: Serialize>(mut stream: &TcpStream, message: &T) -> Result<(), String> {
+    let mut line = serde_json::to_string(message)
+        .map_err(|e| format!("Failed to serialize message: {}", e))?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .map_err(|e| format!("Failed to write to peer: {}", e))
+}
+
+/// Wait for the user to accept the pairing request from `device_id`. A
+/// request declined or left unanswered locks pairing out for a while.
+fn wait_for_answer(lan: &LanSync, device_id: &str) -> Result<(), String> {
+    let deadline = Instant::now() + PAIRING_ANSWER_TIME;
+    loop {
+        {
+            let mut incoming = lan.incoming.lock().map_err(|e| e.to_string())?;
+            let Some(pairing) = incoming.as_ref().filter(|p| p.device_id == device_id) else {
+                return Err("Pairing was cancelled".to_string());
+            };
+            match pairing.accepted {
+                Some(true) => return Ok(()),
+                Some(false) => {
+                    *incoming = None;
+                    lan.lock_out_pairing();
+                    return Err("Pairing was declined".to_string());
+                }
+                None if Instant::now() >= deadline => {
+                    *incoming = None;
+                    lan.lock_out_pairing();
+                    return Err("Nobody answered the pairing request".to_string());
+                }
+                None => {}
+            }
+        }
+        std::thread::sleep(ACCEPT_POLL);
+    }
+}
+
+fn handle_request(
+    app: &AppHandle,
+    request: Request,
+    reader: &mut impl BufRead,
+) -> Result<Response, String> {
+    let lan = app.state::<LanSync>();
+    let state = app.state::<AppState>();
+
+    match request {
+        Request::PairStart { device_id, name } => {
+            {
+                let mut incoming = lan.incoming.lock().map_err(|e| e.to_string())?;
+                if lan.pairing_locked_out() {
+                    return Err("Too many pairing requests; try again in a few minutes".to_string());
+                }
+                // A pairing under way is never replaced
+                if incoming
+                    .as_ref()
+                    .is_some_and(|p| p.started.elapsed() < PAIRING_TTL)
+                {
+                    return Err("Another pairing is in progress".to_string());
+                }
+                *incoming = Some(IncomingPairing {
+                    device_id: device_id.clone(),
+                    name: name.clone(),
+                    accepted: None,
+                    code: None,
+                    attempts: 0,
+                    started: Instant::now(),
+                });
+            }
+            let _ = app.emit(
+                "lan-pairing-request",
+                PairingRequest {
+                    peer_name: name.clone(),
+                },
+            );
+            wait_for_answer(&lan, &device_id)?;
+
+            let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+            let (secret, message) = pake_start(&code, false);
+            {
+                let mut incoming = lan.incoming.lock().map_err(|e| e.to_string())?;
+                let Some(pairing) = incoming.as_mut().filter(|p| p.device_id == device_id) else {
+                    return Err("Pairing was cancelled".to_string());
+                };
+                pairing.code = Some(ShownCode {
+                    code: code.clone(),
+                    secret,
+                    message: message.clone(),
+                });
+                pairing.started = Instant::now();
+            }
+            let _ = app.emit(
+                "lan-pairing-code",
+                PairingCode {
+                    peer_name: name,
+                    code,
+                },
+            );
+            Ok(Response::PairStarted { message })
+        }
+        Request::PairFinish {
+            device_id,
+            message,
+            proof,
+        } => {
+            let mut incoming = lan.incoming.lock().map_err(|e| e.to_string())?;
+            let Some(pairing) = incoming
+                .as_mut()
+                .filter(|p| p.device_id == device_id && p.started.elapsed() < PAIRING_TTL)
+            else {
+                return Err("No pairing in progress; start again".to_string());
+            };
+            let Some(shown) = &pairing.code else {
+                return Err("The pairing request hasn't been accepted".to_string());
+            };
+
+            let session = pake_finish(
+                &shown.code,
+                &shown.secret,
+                false,
+                &pairing.device_id,
+                &shown.message,
+                &message,
+            )?;
+            if !verify_hmac(&session, "confirm|a", &proof) {
+                pairing.attempts += 1;
+                if pairing.attempts >= PAIRING_ATTEMPTS {
+                    *incoming = None;
+                    lan.lock_out_pairing();
+                    let _ = app.emit("lan-pairing-failed", ());
+                }
+                return Err("Wrong pairing code".to_string());
+            }
+
+            let key = hmac_hex(&session, "key");
+            let reply = hmac_hex(&session, "confirm|b");
+            remember_peer(&state, &pairing.device_id, &pairing.name, &key)?;
+            let _ = app.emit("lan-paired", pairing.name.clone());
+            *incoming = None;
+            Ok(Response::Paired {
+                name: device_name(),
+                proof: reply,
+            })
+        }
+        Request::Sync(header) => {
+            let key = peer_key(&header.device_id)
+                .ok_or_else(|| "Not paired with this device".to_string())?;
+            verify(&header, &key, SYNC_HEADER)?;
+            // Only now is a large message worth reading
+            let signed: Signed = read_message(reader, MAX_MESSAGE_BYTES)?;
+            if signed.device_id != header.device_id {
+                return Err("Sync request from another device".to_string());
+            }
+            verify(&signed, &key, &header.mac)?;
+            let request: SyncRequest = serde_json::from_str(&signed.payload)
+                .map_err(|e| format!("Invalid sync request: {}", e))?;
+
+            let mut peers = load_peers(&state);
+            let since = peers.peers.get(&signed.device_id).and_then(|p| p.last_sync);
+            let started = Utc::now();
+            let reply = SyncReply {
+                delta: local_delta(&state, since)?,
+                files: if request.include_files {
+                    shareable_files(&state, &request.known_books)?
+                } else {
+                    Vec::new()
+                },
+            };
+            apply_delta(app, request.delta)?;
+
+            if let Some(peer) = peers.peers.get_mut(&signed.device_id) {
+                peer.last_sync = Some(started);
+            }
+            save_peers(&state, &peers)?;
+
+            let payload = serde_json::to_string(&reply)
+                .map_err(|e| format!("Failed to serialize sync reply: {}", e))?;
+            Ok(Response::Synced(sign(
+                &device_id()?,
+                &key,
+                payload,
+                &signed.mac,
+            )))
+        }
+    }
+}
+
+fn serve(app: AppHandle, listener: TcpListener, stop: Arc<AtomicBool>) {
+    let active = Arc::new(AtomicUsize::new(0));
+    while !stop.load(Ordering::SeqCst) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_POLL);
+                continue;
+            }
+            Err(e) => {
+                logging::warn(&format!("LAN sync accept failed: {}", e));
+                continue;
+            }
+        };
+
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            continue;
+        }
+        let app = app.clone();
+        let active = active.clone();
+        std::thread::spawn(move || {
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+            let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+            let mut reader = BufReader::new(&stream);
+            let response = read_message(&mut reader, MAX_UNAUTHENTICATED_BYTES)
+                .and_then(|request| handle_request(&app, request, &mut reader))
+                .unwrap_or_else(|message| Response::Error { message });
+            if let Err(e) = write_message(&stream, &response) {
+                logging::warn(&e);
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+fn start(app: &AppHandle) -> Result<Service, String> {
+    let listener =
+        TcpListener::bind("0.0.0.0:0").map_err(|e| format!("Failed to start LAN sync: {}", e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to start LAN sync: {}", e))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to start LAN sync: {}", e))?
+        .port();
+
+    let id = device_id()?;
+    let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+    let properties = [("id", id.as_str()), ("name", &device_name())];
+    let info = ServiceInfo::new(
+        SERVICE_TYPE,
+        &id,
+        &format!("{}.local.", id),
+        "",
+        port,
+        &properties[..],
+    )
+    .map_err(|e| format!("Failed to advertise LAN sync: {}", e))?
+    .enable_addr_auto();
+    let fullname = info.get_fullname().to_string();
+    daemon
+        .register(info)
+        .map_err(|e| format!("Failed to advertise LAN sync: {}", e))?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+    let handle = app.clone();
+    std::thread::spawn(move || serve(handle, listener, flag));
+
+    logging::info(&format!("LAN sync listening on port {}", port));
+    Ok(Service {
+        daemon,
+        fullname,
+        stop,
+    })
+}
+
+/// Stop advertising and listening
+pub fn shutdown(app: &AppHandle) {
+    let service = app
+        .state::<LanSync>()
+        .service
+        .lock()
+        .ok()
+        .and_then(|mut service| service.take());
+    if let Some(service) = service {
+        service.stop.store(true, Ordering::SeqCst);
+        let _ = service.daemon.unregister(&service.fullname);
+        let _ = service.daemon.shutdown();
+        logging::info("LAN sync stopped");
+    }
+}
+
+/// Start or stop the service to match the preferences
+pub fn apply(app: &AppHandle, prefs: &UserPreferences) {
+    let wanted = prefs.lan_sync_enabled && !config::is_safe_mode();
+    let running = app
+        .state::<LanSync>()
+        .service
+        .lock()
+        .is_ok_and(|service| service.is_some());
+
+    if wanted && !running {
+        match start(app) {
+            Ok(service) => {
+                if let Ok(mut slot) = app.state::<LanSync>().service.lock() {
+                    *slot = Some(service);
+                }
+            }
+            Err(e) => logging::error(&e),
+        }
+    } else if !wanted && running {
+        shutdown(app);
+    }
+}
+
+fn ensure_running(lan: &LanSync) -> Result<(), String> {
+    if lan.service.lock().map_err(|e| e.to_string())?.is_none() {
+        return Err("LAN sync is turned off".to_string());
+    }
+    Ok(())
+}
+
+/// Send a request, and the signed message following it if any, and read
+/// a reply of at most `limit` bytes
+fn send(
+    peer: &Peer,
+    request: &Request,
+    body: Option<&Signed>,
+    limit: u64,
+) -> Result<Response, String> {
+    let ip: IpAddr = peer
+        .address
+        .parse()
+        .map_err(|e| format!("Invalid peer address: {}", e))?;
+    let stream = TcpStream::connect_timeout(&SocketAddr::new(ip, peer.port), IO_TIMEOUT)
+        .map_err(|e| format!("Failed to reach {}: {}", peer.name, e))?;
+    stream
+        .set_read_timeout(Some(IO_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+
+    write_message(&stream, request)?;
+    if let Some(body) = body {
+        write_message(&stream, body)?;
+    }
+    match read_message(&mut BufReader::new(&stream), limit)? {
+        Response::Error { message } => Err(format!("{}: {}", peer.name, message)),
+        response => Ok(response),
+    }
+}
+
+fn discovered_peer(lan: &LanSync, peer_id: &str) -> Result<Peer, String> {
+    lan.discovered
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(peer_id)
+        .cloned()
+        .ok_or_else(|| "Device not found; search for devices again".to_string())
+}
+
+/// Look for other instances with LAN sync turned on
+#[tauri::command]
+pub async fn discover_peers(app: AppHandle) -> Result<Vec<Peer>, String> {
+    ensure_running(&app.state::<LanSync>())?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let lan = app.state::<LanSync>();
+        let own_id = device_id()?;
+        let paired = load_peers(&app.state::<AppState>()).peers;
+
+        let daemon = lan
+            .service
+            .lock()
+            .map_err(|e| e.to_string())?
+            .as_ref()
+            .map(|service| service.daemon.clone())
+            .ok_or_else(|| "LAN sync is turned off".to_string())?;
+        let receiver = daemon
+            .browse(SERVICE_TYPE)
+            .map_err(|e| format!("Failed to search the network: {}", e))?;
+
+        let mut found = HashMap::new();
+        let deadline = Instant::now() + DISCOVERY_TIME;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            let Ok(event) = receiver.recv_timeout(left) else {
+                break;
+            };
+            let ServiceEvent::ServiceResolved(info) = event else {
+                continue;
+            };
+            let Some(id) = info.get_property_val_str("id").map(|s| s.to_string()) else {
+                continue;
+            };
+            // Prefer IPv4, link-local IPv6 needs a scope we don't track
+            let Some(address) = info
+                .get_addresses()
+                .iter()
+                .min_by_key(|ip| !ip.is_ipv4())
+                .map(|ip| ip.to_string())
+            else {
+                continue;
+            };
+            if id == own_id {
+                continue;
+            }
+            let peer = Peer {
+                name: info.get_property_val_str("name").unwrap_or(&id).to_string(),
+                paired: paired.contains_key(&id),
+                address,
+                port: info.get_port(),
+                id: id.clone(),
+            };
+            found.insert(id, peer);
+        }
+        let _ = daemon.stop_browse(SERVICE_TYPE);
+
+        let peers: Vec<Peer> = found.values().cloned().collect();
+        *lan.discovered.lock().map_err(|e| e.to_string())? = found;
+        Ok(peers)
+    })
+    .await
+    .map_err(|e| format!("Discovery failed: {}", e))?
+}
+
+/// Ask a peer to show a pairing code
+#[tauri::command]
+pub async fn request_pairing(app: AppHandle, peer_id: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let lan = app.state::<LanSync>();
+        ensure_running(&lan)?;
+        let peer = discovered_peer(&lan, &peer_id)?;
+
+        let request = Request::PairStart {
+            device_id: device_id()?,
+            name: device_name(),
+        };
+        match send(&peer, &request, None, MAX_UNAUTHENTICATED_BYTES)? {
+            Response::PairStarted { message } => {
+                lan.outgoing
+                    .lock()
+                    .map_err(|e| e.to_string())?
+                    .insert(peer_id, message);
+                Ok(())
+            }
+            _ => Err("Unexpected reply from peer".to_string()),
+        }
+    })
+    .await
+    .map_err(|e| format!("Pairing failed: {}", e))?
+}
+
+/// Finish pairing with the code shown on the peer's screen
+#[tauri::command]
+pub async fn pair_with_peer(app: AppHandle, peer_id: String, code: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let lan = app.state::<LanSync>();
+        ensure_running(&lan)?;
+        let peer = discovered_peer(&lan, &peer_id)?;
+        let code = code.trim().to_string();
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+            return Err("The pairing code has 6 digits".to_string());
+        }
+
+        let their_message = lan
+            .outgoing
+            .lock()
+            .map_err(|e| e.to_string())?
+            .get(&peer_id)
+            .cloned()
+            .ok_or_else(|| "Request a pairing code first".to_string())?;
+
+        let our_id = device_id()?;
+        let (secret, message) = pake_start(&code, true);
+        let session = pake_finish(&code, &secret, true, &our_id, &message, &their_message)?;
+        let request = Request::PairFinish {
+            device_id: our_id,
+            message,
+            proof: hmac_hex(&session, "confirm|a"),
+        };
+        let Response::Paired { name, proof } =
+            send(&peer, &request, None, MAX_UNAUTHENTICATED_BYTES)?
+        else {
+            return Err("Unexpected reply from peer".to_string());
+        };
+        if !verify_hmac(&session, "confirm|b", &proof) {
+            return Err("The peer couldn't prove it knows the code".to_string());
+        }
+
+        let key = hmac_hex(&session, "key");
+        remember_peer(&app.state::<AppState>(), &peer_id, &name, &key)?;
+        lan.outgoing
+            .lock()
+            .map_err(|e| e.to_string())?
+            .remove(&peer_id);
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Pairing failed: {}", e))?
+}
+
+/// Exchange library and annotation changes with a paired peer. With
+/// `include_files`, managed book files we don't have are copied too.
+#[tauri::command]
+pub async fn sync_with_peer(
+    app: AppHandle,
+    peer_id: String,
+    include_files: Option<bool>,
+) -> Result<LanSyncSummary, String> {
+    if config::is_safe_mode() {
+        return Err(config::safe_mode_error().into());
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let lan = app.state::<LanSync>();
+        let state = app.state::<AppState>();
+        ensure_running(&lan)?;
+        let peer = discovered_peer(&lan, &peer_id)?;
+        let key = peer_key(&peer_id).ok_or_else(|| "Pair with this device first".to_string())?;
+
+        state.flush_library()?;
+        let mut peers = load_peers(&state);
+        let since = peers.peers.get(&peer_id).and_then(|p| p.last_sync);
+        let started = Utc::now();
+        let delta = local_delta(&state, since)?;
+        let books_sent = delta.books.len();
+        let request = SyncRequest {
+            delta,
+            include_files: include_files.unwrap_or(false),
+            known_books: state.with_library(|library| {
+                library
+                    .books
+                    .iter()
+                    .filter(|b| Path::new(&b.file_path).exists())
+                    .map(|b| b.id.clone())
+                    .collect()
+            })?,
+        };
+        let payload = serde_json::to_string(&request)
+            .map_err(|e| format!("Failed to serialize sync request: {}", e))?;
+        let our_id = device_id()?;
+        let header = sign(&our_id, &key, String::new(), SYNC_HEADER);
+        let signed = sign(&our_id, &key, payload, &header.mac);
+        let request_mac = signed.mac.clone();
+
+        let Response::Synced(reply) = send(
+            &peer,
+            &Request::Sync(header),
+            Some(&signed),
+            MAX_MESSAGE_BYTES,
+        )?
+        else {
+            return Err("Unexpected reply from peer".to_string());
+        };
+        verify(&reply, &key, &request_mac)?;
+        let reply: SyncReply = serde_json::from_str(&reply.payload)
+            .map_err(|e| format!("Invalid sync reply: {}", e))?;
+
+        let (books_received, annotations_received) = apply_delta(&app, reply.delta)?;
+        let files_received = store_files(&app, reply.files)?;
+
+        if let Some(peer) = peers.peers.get_mut(&peer_id) {
+            peer.last_sync = Some(started);
+        }
+        save_peers(&state, &peers)?;
+
+        Ok(LanSyncSummary {
+            books_sent,
+            books_received,
+            annotations_received,
+            files_received,
+        })
+    })
+    .await
+    .map_err(|e| format!("Sync failed: {}", e))?
+}
+
+/// Accept or decline the pairing request another device sent. Accepting
+/// shows the code to enter on that device.
+#[tauri::command]
+pub fn answer_pairing_request(lan: State<'_, LanSync>, accept: bool) -> Result<(), String> {
+    let mut incoming = lan.incoming.lock().map_err(|e| e.to_string())?;
+    let Some(pairing) = incoming.as_mut().filter(|p| p.accepted.is_none()) else {
+        return Err("No pairing request is waiting".to_string());
+    };
+    pairing.accepted = Some(accept);
+    Ok(())
+}
+
+/// Forget a paired device and its key
+#[tauri::command]
+pub fn forget_peer(state: State<'_, AppState>, peer_id: String) -> Result<(), String> {
+    if let Ok(entry) = keyring_entry(&peer_id) {
+        let _ = entry.delete_credential();
+    }
+    let mut peers = load_peers(&state);
+    peers.peers.remove(&peer_id);
+    save_peers(&state, &peers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(initiator_code: &str, responder_code: &str) -> (String, String) {
+        let (responder_secret, responder_message) = pake_start(responder_code, false);
+        let (initiator_secret, initiator_message) = pake_start(initiator_code, true);
+        let initiator = pake_finish(
+            initiator_code,
+            &initiator_secret,
+            true,
+            "device-a",
+            &initiator_message,
+            &responder_message,
+        )
+        .unwrap();
+        let responder = pake_finish(
+            responder_code,
+            &responder_secret,
+            false,
+            "device-a",
+            &responder_message,
+            &initiator_message,
+        )
+        .unwrap();
+        (initiator, responder)
+    }
+
+    #[test]
+    fn same_code_agrees_on_a_session() {
+        let (initiator, responder) = exchange("123456", "123456");
+        assert_eq!(initiator, responder);
+        assert!(verify_hmac(
+            &responder,
+            "confirm|a",
+            &hmac_hex(&initiator, "confirm|a")
+        ));
+    }
+
+    #[test]
+    fn wrong_code_fails_confirmation() {
+        let (initiator, responder) = exchange("123456", "123457");
+        assert_ne!(initiator, responder);
+        assert!(!verify_hmac(
+            &responder,
+            "confirm|a",
+            &hmac_hex(&initiator, "confirm|a")
+        ));
+    }
+
+    #[test]
+    fn messages_do_not_repeat() {
+        let (_, first) = pake_start("123456", true);
+        let (_, second) = pake_start("123456", true);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn messages_over_the_limit_are_refused() {
+        let line = format!("{}\n", serde_json::json!({ "name": "x".repeat(100) }));
+        let mut small = std::io::Cursor::new(line.clone());
+        assert!(read_message::<serde_json::Value>(&mut small, 64).is_err());
+        let mut large = std::io::Cursor::new(line);
+        assert!(read_message::<serde_json::Value>(&mut large, 1024).is_ok());
+    }
+
+    #[test]
+    fn a_peers_books_come_without_paths() {
+        let mut book = crate::state::testing::book("a");
+        book.file_path = "/home/user/.ssh/id_rsa".to_string();
+        book.cover_path = Some("/etc/passwd".to_string());
+        let book = without_paths(book);
+        assert!(book.file_path.is_empty());
+        assert_eq!(book.cover_path, None);
+        assert!(book.missing);
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let (secret, message) = pake_start("123456", true);
+        assert!(pake_finish("123456", &secret, true, "a", &message, "not base64!").is_err());
+        assert!(pake_finish("123456", &secret, true, "a", &message, "AAAA").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+/**
+ * Opening books handed to Epilogue from outside the app
+ */
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State, WebviewWindow};
+
+use crate::advanced::AdvancedConfig;
+use crate::epub;
+use crate::library;
+use crate::logging;
+use crate::startup;
+use crate::window_state;
+
+/// File extensions Epilogue can open directly
+const SUPPORTED_EXTENSIONS: &[&str] = &["epub"];
+
+/// Events held back until the frontend has registered its listeners
+#[derive(Default)]
+pub struct LaunchState {
+    queue: Mutex<EventQueue>,
+}
+
+/// `ready` and `pending` share a lock, so an event can't be queued just
+/// after the queue was drained
+#[derive(Default)]
+struct EventQueue {
+    ready: bool,
+    pending: Vec<(String, serde_json::Value)>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OpenFileError {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Emit now if the frontend is ready, otherwise queue until it is
+pub fn emit_when_ready<S: Serialize>(app: &AppHandle, event: &str, payload: S) {
+    let state = app.state::<LaunchState>();
+    let payload = serde_json::to_value(payload).unwrap_or(serde_json::Value::Null);
+
+    let Ok(mut queue) = state.queue.lock() else {
+        return;
+    };
+    if queue.ready {
+        let _ = app.emit(event, payload);
+    } else {
+        queue.pending.push((event.to_string(), payload));
+    }
+}
+
+/// Called by the frontend once its event listeners are in place and the
+/// first screen has rendered
+#[tauri::command]
+pub fn frontend_ready(
+    app: AppHandle,
+    window: WebviewWindow,
+    state: State<'_, LaunchState>,
+) -> Result<(), String> {
+    if window.label() == "main" {
+        startup::show_main_window(&app, "frontend ready");
+    }
+    // Emitted under the lock, so they arrive before any event sent after
+    let mut queue = state.queue.lock().map_err(|e| e.to_string())?;
+    queue.ready = true;
+    for (event, payload) in queue.pending.drain(..) {
+        let _ = app.emit(&event, payload);
+    }
+
+    Ok(())
+}
+
+/// Bring the main window to the front, recreating it if it was closed
+pub fn focus_main_window(app: &AppHandle) {
+    let window = match app.get_webview_window("main") {
+        Some(window) => window,
+        None => {
+            // A fresh webview has to register its listeners again
+            if let Ok(mut queue) = app.state::<LaunchState>().queue.lock() {
+                queue.ready = false;
+            }
+
+            let Some(config) = app.config().app.windows.first() else {
+                return;
+            };
+            match tauri::WebviewWindowBuilder::from_config(app, config).and_then(|b| b.build()) {
+                Ok(window) => {
+                    window_state::restore(&window);
+                    window
+                }
+                Err(e) => {
+                    logging::error(&format!("Failed to create main window: {}", e));
+                    return;
+                }
+            }
+        }
+    };
+
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+/// File arguments from a command line: argv[0], flags and URLs (handled as
+/// deep links) are skipped and relative paths are resolved against `cwd`
+pub fn paths_from_args(argv: &[String], cwd: Option<&Path>) -> Vec<PathBuf> {
+    argv.iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-') && !arg.contains("://"))
+        .map(|arg| match cwd {
+            Some(cwd) => cwd.join(arg),
+            None => PathBuf::from(arg),
+        })
+        .collect()
+}
+
+/// Import every valid path and open the first one in the reader.
+/// Invalid paths are reported as "open-file-error" events.
+pub fn open_paths(app: &AppHandle, paths: Vec<PathBuf>) {
+    let mut opened = false;
+    let mut events = library::LibraryEventBatch::new(app);
+
+    for path in paths {
+        let display = path.to_string_lossy().to_string();
+
+        if let Err(reason) = validate_path(&path) {
+            logging::warn(&format!("Cannot open {}: {}", display, reason));
+            emit_when_ready(
+                app,
+                "open-file-error",
+                OpenFileError {
+                    path: display,
+                    reason,
+                },
+            );
+            continue;
+        }
+
+        match import_for_open(app, &display) {
+            Ok(event) => {
+                let book = match &event {
+                    library::LibraryEvent::Added(book) | library::LibraryEvent::Updated(book) => {
+                        book.clone()
+                    }
+                    _ => continue,
+                };
+                events.push(event);
+                if !opened {
+                    emit_when_ready(app, "open-file", book);
+                    opened = true;
+                }
+            }
+            Err(reason) => {
+                logging::error(&format!("Failed to open {}: {}", display, reason));
+                emit_when_ready(
+                    app,
+                    "open-file-error",
+                    OpenFileError {
+                        path: display,
+                        reason,
+                    },
+                );
+            }
+        }
+    }
+}
+
+fn validate_path(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err("File does not exist".to_string());
+    }
+    if !path.is_file() {
+        return Err("Not a file".to_string());
+    }
+
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_lowercase())
+        .unwrap_or_default();
+    if !SUPPORTED_EXTENSIONS.contains(&ext.as_str()) {
+        return Err(format!("Unsupported file type: .{}", ext));
+    }
+
+    Ok(())
+}
+
+/// Called in the running instance when Epilogue is launched a second time
+pub fn handle_second_instance(app: &AppHandle, argv: Vec<String>, cwd: String) {
+    focus_main_window(app);
+    open_paths(app, paths_from_args(&argv, Some(Path::new(&cwd))));
+}
+
+/// Run loop callback. macOS delivers "Open with Epilogue" and double-clicked
+/// files as an open-file apple event rather than as arguments.
+pub fn handle_run_event(app: &AppHandle, event: RunEvent) {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    if let RunEvent::Opened { urls } = event {
+        let paths: Vec<PathBuf> = urls
+            .into_iter()
+            .filter(|url| url.scheme() == "file")
+            .filter_map(|url| url.to_file_path().ok())
+            .collect();
+        if !paths.is_empty() {
+            focus_main_window(app);
+            open_paths(app, paths);
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let _ = (app, event);
+}
+
+/// Add (or re-open) a book from a path we were handed, under the title and
+/// author in the EPUB, or its file name when it has none
+pub fn import_for_open(app: &AppHandle, path: &str) -> Result<library::LibraryEvent, String> {
+    epub::check_zip_limits(Path::new(path), &app.state::<AdvancedConfig>())?;
+    let names = library::BookNames::Epub {
+        title: String::new(),
+        author: String::new(),
+    };
+    library::import_book(&app.state(), names, path.to_string())
+}
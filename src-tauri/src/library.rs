@@ -3,8 +3,46 @@ use chrono::{DateTime, Utc};
  * Library management and persistence
  */
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State, Window};
+
+use crate::advanced::AdvancedConfig;
+use crate::annotations_archive;
+use crate::authors;
+use crate::book_ids;
+use crate::config;
+use crate::covers;
+use crate::crash;
+use crate::file_access;
+use crate::filters::{Filter, FilterContext, Rule};
+use crate::isbn;
+use crate::logging;
+use crate::meta;
+use crate::preflight::{self, PathKind};
+use crate::progress_history;
+use crate::reader_window;
+use crate::series;
+use crate::sessions;
+use crate::smart_shelves::SmartShelf;
+use crate::state::AppState;
+use crate::stats;
+use crate::view_state;
+
+/// Minimum time between two "library-batch" events during bulk operations
+const BATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Longest we wait for pending changes to be written when closing
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The thread that writes scheduled saves, sent when the next one is due.
+/// `None` cancels the save waiting.
+static SAVE_WORKER: OnceLock<mpsc::Sender<Option<Instant>>> = OnceLock::new();
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Book {
@@ -15,289 +53,2732 @@ pub struct Book {
     pub file_path: String,
     #[serde(rename = "coverPath")]
     pub cover_path: Option<String>,
+    /// Where the cover came from, so regenerating it knows where to look
+    #[serde(rename = "coverSource", default)]
+    pub cover_source: CoverSource,
+    /// The cover scaled down for the library grid
+    #[serde(rename = "thumbnailPath", default)]
+    pub thumbnail_path: Option<String>,
     #[serde(rename = "lastOpened")]
     pub last_opened: DateTime<Utc>,
     pub progress: f32,
+    /// The last position read, in whichever reading mode
+    pub cfi: Option<String>,
+    /// Where to resume in each reading mode, since the renderer maps a CFI
+    /// differently when paginated and scrolled
+    #[serde(default)]
+    pub positions: ResumePositions,
+    /// Chapter and page as the reader last showed them, for library cards
+    #[serde(rename = "readingLocation", default)]
+    pub reading_location: Option<ReadingLocation>,
+    /// ISBN from the EPUB metadata, normalized to a bare ISBN-13
+    #[serde(default)]
+    pub isbn: Option<String>,
+    /// Words in the book's text, for reading speed and activity stats
+    #[serde(rename = "wordCount", default)]
+    pub word_count: Option<u64>,
+    /// The user's rating, 1 to 5 stars
+    #[serde(default)]
+    pub rating: Option<u8>,
+    /// The user's review, as long as they like
+    #[serde(default)]
+    pub review: Option<String>,
+    #[serde(rename = "readingState", default)]
+    pub reading_state: ReadingState,
+    /// When the book was last finished; cleared when it leaves the shelf
+    #[serde(rename = "finishedAt", default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Every time the book was finished, first read and re-reads
+    #[serde(default)]
+    pub completions: Vec<Completion>,
+    #[serde(rename = "firstOpened", default)]
+    pub first_opened: Option<DateTime<Utc>>,
+    /// When the current read began
+    #[serde(rename = "startedAt", default)]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(rename = "mediaType", default)]
+    pub media_type: MediaType,
+    /// Duration, chapters and position of an audiobook
+    #[serde(default)]
+    pub audio: Option<AudioInfo>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(rename = "publishYear", default)]
+    pub publish_year: Option<i32>,
+    /// Series from the EPUB metadata (calibre:series or
+    /// belongs-to-collection)
+    #[serde(default)]
+    pub series: Option<String>,
+    #[serde(rename = "seriesIndex", default)]
+    pub series_index: Option<f32>,
+    /// Last volume an omnibus collects ("1-3" is index 1, end 3)
+    #[serde(rename = "seriesEnd", default)]
+    pub series_end: Option<f32>,
+    /// dc:language of the EPUB, e.g. "en" or "pt-BR"
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Subjects from Open Library; the user's own shelves are `tags`
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    /// Open Library work the user confirmed as this book ("/works/OL…W")
+    #[serde(rename = "openLibraryKey", default)]
+    pub open_library_key: Option<String>,
+    /// Reading position read from an e-reader, kept apart from `cfi` and
+    /// `progress` until the user takes it over
+    #[serde(rename = "importedPosition", default)]
+    pub imported_position: Option<ImportedPosition>,
+    /// Reading settings that differ from the preferences for this book
+    #[serde(default)]
+    pub overrides: BookOverrides,
+    /// MD5 of the book file, to recognize the same book under another path
+    #[serde(rename = "contentHash", default)]
+    pub content_hash: Option<String>,
+    /// Chapters read through, for books read out of order
+    #[serde(default)]
+    pub chapters: Option<ChapterProgress>,
+    /// The file wasn't there when the library was last checked
+    #[serde(default)]
+    pub missing: bool,
+    /// Pinned by the user
+    #[serde(default)]
+    pub favorite: bool,
+    /// Hidden from the home screen; progress and annotations are kept
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl Book {
+    /// Reject audiobooks in commands that work on EPUB text (CFIs, TOC)
+    pub fn require_text(&self) -> Result<(), String> {
+        match self.media_type {
+            MediaType::Text => Ok(()),
+            MediaType::Audio => Err(format!("'{}' is an audiobook", self.title)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaType {
+    #[default]
+    Text,
+    Audio,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverSource {
+    /// Read from the book file
+    #[default]
+    Extracted,
+    /// Downloaded from Open Library
+    Remote,
+}
+
+/// Per-book settings; None follows the preference
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BookOverrides {
+    #[serde(rename = "bionicReading", default)]
+    pub bionic_reading: Option<bool>,
+}
+
+/// How the reader lays out a book, as in the readingMode preference
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadingMode {
+    #[default]
+    Paginated,
+    Scrolled,
+}
+
+impl ReadingMode {
+    pub fn from_preference(mode: &str) -> Self {
+        match mode {
+            "scrolled" => ReadingMode::Scrolled,
+            _ => ReadingMode::Paginated,
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            ReadingMode::Paginated => ReadingMode::Scrolled,
+            ReadingMode::Scrolled => ReadingMode::Paginated,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResumePosition {
+    pub cfi: String,
+    /// Scrolled mode only: how far down the chapter, in pixels
+    #[serde(rename = "scrollPixels", default)]
+    pub scroll_pixels: Option<f64>,
+    /// Scrolled mode only: how far down the chapter, 0 to 1
+    #[serde(rename = "scrollPercent", default)]
+    pub scroll_percent: Option<f32>,
+    #[serde(rename = "savedAt")]
+    pub saved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ResumePositions {
+    #[serde(default)]
+    pub paginated: Option<ResumePosition>,
+    #[serde(default)]
+    pub scrolled: Option<ResumePosition>,
+}
+
+impl ResumePositions {
+    pub fn get(&self, mode: ReadingMode) -> Option<&ResumePosition> {
+        match mode {
+            ReadingMode::Paginated => self.paginated.as_ref(),
+            ReadingMode::Scrolled => self.scrolled.as_ref(),
+        }
+    }
+
+    pub fn set(&mut self, mode: ReadingMode, position: ResumePosition) {
+        match mode {
+            ReadingMode::Paginated => self.paginated = Some(position),
+            ReadingMode::Scrolled => self.scrolled = Some(position),
+        }
+    }
+
+    /// Keep the later position of each mode
+    fn merge(&mut self, other: ResumePositions) {
+        for mode in [ReadingMode::Paginated, ReadingMode::Scrolled] {
+            if let Some(theirs) = other.get(mode) {
+                if self.get(mode).is_none_or(|ours| theirs.saved_at > ours.saved_at) {
+                    self.set(mode, theirs.clone());
+                }
+            }
+        }
+    }
+}
+
+/// How the reader showed the book when a position was recorded
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ReaderView {
+    #[serde(default)]
+    pub mode: ReadingMode,
+    #[serde(rename = "scrollPixels", default)]
+    pub scroll_pixels: Option<f64>,
+    #[serde(rename = "scrollPercent", default)]
+    pub scroll_percent: Option<f32>,
+    /// The chapter and page shown
+    #[serde(default)]
+    pub location: Option<ReadingLocation>,
+}
+
+/// Chapter and page as the reader shows them, e.g. "Chapter 12 of 30",
+/// "page 214 of 480". Chapters count from 0.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ReadingLocation {
+    #[serde(rename = "chapterTitle", default)]
+    pub chapter_title: Option<String>,
+    #[serde(rename = "chapterIndex", default)]
+    pub chapter_index: Option<usize>,
+    #[serde(rename = "totalChapters", default)]
+    pub total_chapters: Option<usize>,
+    /// Page, or the renderer's location, from 1
+    #[serde(default)]
+    pub location: Option<u32>,
+    #[serde(rename = "totalLocations", default)]
+    pub total_locations: Option<u32>,
+}
+
+impl ReadingLocation {
+    /// Checked and tidied as sent by the reader
+    fn validated(mut self) -> Result<Self, String> {
+        if let (Some(index), Some(total)) = (self.chapter_index, self.total_chapters) {
+            if index >= total {
+                return Err(format!("Chapter {} is past the {} chapters", index, total));
+            }
+        }
+        if let (Some(location), Some(total)) = (self.location, self.total_locations) {
+            if location > total {
+                return Err(format!(
+                    "Location {} is past the {} locations",
+                    location, total
+                ));
+            }
+        }
+        self.chapter_title = self
+            .chapter_title
+            .map(|title| title.trim().to_string())
+            .filter(|title| !title.is_empty());
+        Ok(self)
+    }
+}
+
+/// Where to resume reading a book in a mode
+#[derive(Debug, Serialize, Clone)]
+pub struct BookPosition {
+    pub cfi: String,
+    /// Mode the position was recorded in
+    pub mode: Option<ReadingMode>,
+    #[serde(rename = "scrollPixels")]
+    pub scroll_pixels: Option<f64>,
+    #[serde(rename = "scrollPercent")]
+    pub scroll_percent: Option<f32>,
+    /// Recorded in the other mode, so only roughly where the user was
+    pub converted: bool,
+}
+
+/// Which spine items have been read through
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ChapterProgress {
+    /// Spine indexes read to the end, sorted
+    pub read: Vec<usize>,
+    /// Words in each spine item, to weigh chapters by their length
+    #[serde(default)]
+    pub words: Vec<u64>,
+}
+
+impl ChapterProgress {
+    /// Share of the book's words in chapters read, or of its chapters when
+    /// the word counts are unknown
+    pub fn progress(&self) -> f32 {
+        let total: u64 = self.words.iter().sum();
+        if total == 0 {
+            let chapters = self.words.len().max(self.read.len());
+            return self.read.len() as f32 / chapters.max(1) as f32;
+        }
+        let read: u64 = self.read.iter().filter_map(|i| self.words.get(*i)).sum();
+        read as f32 / total as f32
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportedPosition {
+    /// "kindle"
+    pub source: String,
+    /// Fraction of the book, 0 to 1
+    pub progress: f32,
+    /// Always true for now: Kindle locations only map roughly onto EPUBs
+    pub approximate: bool,
+    /// Start of the chapter the position falls in, for the reader to jump to
     pub cfi: Option<String>,
+    /// Kindle location the fraction was worked out from
+    pub location: Option<usize>,
+    #[serde(rename = "importedAt")]
+    pub imported_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AudioChapter {
+    pub title: String,
+    #[serde(rename = "startSecs")]
+    pub start_secs: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AudioInfo {
+    #[serde(rename = "durationSecs")]
+    pub duration_secs: f64,
+    /// Seconds listened, where playback resumes
+    #[serde(rename = "positionSecs", default)]
+    pub position_secs: f64,
+    #[serde(default)]
+    pub chapters: Vec<AudioChapter>,
+}
+
+/// One time a book was read to the end
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Completion {
+    #[serde(rename = "finishedAt")]
+    pub finished_at: DateTime<Utc>,
+    #[serde(rename = "startedAt", default)]
+    pub started_at: Option<DateTime<Utc>>,
+    /// Worked out afterwards from sessions or when the book was last opened
+    #[serde(default)]
+    pub inferred: bool,
+}
+
+/// Where a book is on the user's shelves
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadingState {
+    #[default]
+    ToRead,
+    Reading,
+    Finished,
+    /// Put down for good; only the user moves a book off this shelf
+    Abandoned,
+}
+
+impl ReadingState {
+    /// The shelf of a book saved before shelves were kept, from its progress
+    fn from_progress(progress: f32) -> Self {
+        if progress >= FINISHED_PROGRESS {
+            ReadingState::Finished
+        } else if progress > 0.0 {
+            ReadingState::Reading
+        } else {
+            ReadingState::ToRead
+        }
+    }
+}
+
+/// Read a stored book. Books saved before shelves were kept go on the one
+/// their progress puts them on, not all on To Read.
+pub fn parse_book(data: &str) -> serde_json::Result<Book> {
+    let value: serde_json::Value = serde_json::from_str(data)?;
+    let has_state = value.get("readingState").is_some();
+    let mut book: Book = serde_json::from_value(value)?;
+    if !has_state {
+        book.reading_state = ReadingState::from_progress(book.progress);
+    }
+    Ok(book)
+}
+
+/// Read a library.json, with shelves for its books the way parse_book
+/// gives them
+fn parse_library(content: &str) -> serde_json::Result<Library> {
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    let without_state: Vec<bool> = value
+        .get("books")
+        .and_then(serde_json::Value::as_array)
+        .map(|books| {
+            books
+                .iter()
+                .map(|book| book.get("readingState").is_none())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut library: Library = serde_json::from_value(value)?;
+    for (book, without_state) in library.books.iter_mut().zip(without_state) {
+        if without_state {
+            book.reading_state = ReadingState::from_progress(book.progress);
+        }
+    }
+    Ok(library)
+}
+
+/// Progress at which a book counts as finished
+const FINISHED_PROGRESS: f32 = 0.98;
+/// Most books get_books_page returns at once
+const MAX_PAGE_SIZE: usize = 500;
+const MAX_TAG_CHARS: usize = 50;
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Library {
     pub books: Vec<Book>,
+    #[serde(rename = "smartShelves", default)]
+    pub smart_shelves: Vec<SmartShelf>,
+    /// Library-wide lists library.json has always had, kept as they are
+    #[serde(default)]
+    pub tags: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub collections: Vec<serde_json::Value>,
+}
+
+/// A change to the library, announced once it has been saved
+#[derive(Debug, Clone)]
+pub enum LibraryEvent {
+    Added(Book),
+    Updated(Book),
+    Removed(String),
+    Reloaded,
+}
+
+/// Emit a library event to every window. "library-changed" follows each
+/// one for listeners that only care that something changed.
+pub fn emit_library_event(app: &AppHandle, event: LibraryEvent) {
+    let _ = match event {
+        LibraryEvent::Added(book) => app.emit("book-added", book),
+        LibraryEvent::Updated(book) => app.emit("book-updated", book),
+        LibraryEvent::Removed(id) => app.emit("book-removed", id),
+        LibraryEvent::Reloaded => app.emit("library-reloaded", ()),
+    };
+    let _ = app.emit("library-changed", ());
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+struct LibraryBatch {
+    added: Vec<Book>,
+    updated: Vec<Book>,
+    removed: Vec<String>,
+}
+
+/// Collects the events of a bulk operation and emits them together as
+/// "library-batch", at most every BATCH_INTERVAL and once more when dropped
+pub struct LibraryEventBatch {
+    app: AppHandle,
+    pending: LibraryBatch,
+    last_emit: Instant,
 }
 
-/// Add a book to the library
+impl LibraryEventBatch {
+    pub fn new(app: &AppHandle) -> Self {
+        Self {
+            app: app.clone(),
+            pending: LibraryBatch::default(),
+            last_emit: Instant::now(),
+        }
+    }
+
+    pub fn push(&mut self, event: LibraryEvent) {
+        match event {
+            LibraryEvent::Added(book) => self.pending.added.push(book),
+            LibraryEvent::Updated(book) => self.pending.updated.push(book),
+            LibraryEvent::Removed(id) => self.pending.removed.push(id),
+            LibraryEvent::Reloaded => {
+                self.pending = LibraryBatch::default();
+                emit_library_event(&self.app, LibraryEvent::Reloaded);
+                return;
+            }
+        }
+
+        if self.last_emit.elapsed() >= BATCH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        let batch = std::mem::take(&mut self.pending);
+        if batch.added.is_empty() && batch.updated.is_empty() && batch.removed.is_empty() {
+            return;
+        }
+
+        let _ = self.app.emit("library-batch", batch);
+        let _ = self.app.emit("library-changed", ());
+        self.last_emit = Instant::now();
+    }
+}
+
+impl Drop for LibraryEventBatch {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Add a book to the library, under the title and authors the EPUB gives
 #[tauri::command]
 pub fn add_book(
+    app: AppHandle,
+    state: State<'_, AppState>,
     title: String,
     author: String,
     path: String,
     _cover: Option<String>,
 ) -> Result<Book, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+    file_access::check_read_access(&state, &path)?;
+    crate::epub::check_zip_limits(Path::new(&path), &app.state::<AdvancedConfig>())?;
+    let event = import_book(&state, BookNames::Epub { title, author }, path)?;
+    let book = match &event {
+        LibraryEvent::Added(book) | LibraryEvent::Updated(book) => book.clone(),
+        _ => return Err("Unexpected library change".to_string()),
+    };
+    emit_library_event(&app, event);
+
+    Ok(book)
+}
+
+/// The directory covers are extracted into, the one init_library creates
+fn covers_dir(state: &AppState) -> Result<PathBuf, String> {
+    let covers_dir = state.paths()?.covers.clone();
+    fs::create_dir_all(&covers_dir)
+        .map_err(|e| format!("Failed to create covers directory: {}", e))?;
+    Ok(covers_dir)
+}
 
-    let app_dir = home_dir.join(".epub-reader");
-    let library_path = app_dir.join("library.json");
-    let covers_dir = app_dir.join("covers");
+/// Where a new book's title and author come from
+pub enum BookNames {
+    /// The EPUB's own, then these unless they are placeholders
+    Epub { title: String, author: String },
+    /// These, with the EPUB's own for any left out
+    Given {
+        title: Option<String>,
+        author: Option<String>,
+    },
+}
 
-    // Ensure covers directory exists
-    if !covers_dir.exists() {
-        fs::create_dir_all(&covers_dir)
-            .map_err(|e| format!("Failed to create covers directory: {}", e))?;
+impl BookNames {
+    /// The title and author to file the book at `path` under, falling back
+    /// to its file name and "Unknown Author"
+    fn resolve(self, path: &str, epub: (Option<String>, Option<String>)) -> (String, String) {
+        let given = |value: String| {
+            let value = value.trim().to_string();
+            (!value.is_empty() && value != "Unknown" && value != "Unknown Author").then_some(value)
+        };
+        let (epub_title, epub_author) = epub;
+        let (title, author) = match self {
+            BookNames::Epub { title, author } => (
+                epub_title.or_else(|| given(title)),
+                epub_author.or_else(|| given(author)),
+            ),
+            BookNames::Given { title, author } => (title.or(epub_title), author.or(epub_author)),
+        };
+        let title = title.unwrap_or_else(|| {
+            Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "Unknown".to_string())
+        });
+        (
+            title,
+            author.unwrap_or_else(|| "Unknown Author".to_string()),
+        )
     }
+}
 
-    // Create unique ID from path hash
-    let id = format!("{:x}", md5::compute(path.as_bytes()));
+/// Open an EPUB to read its details from, or None if it doesn't open
+fn open_epub(path: &str) -> Option<epub::doc::EpubDoc<BufReader<File>>> {
+    crash::catch_panic("opening the EPUB", || epub::doc::EpubDoc::new(path).ok()).unwrap_or_else(
+        |e| {
+            logging::error(&format!("{}: {}", e, path));
+            None
+        },
+    )
+}
 
-    // Attempt to extract cover image from EPUB
-    let mut cover_path: Option<String> = None;
-    match epub::doc::EpubDoc::new(&path) {
-        Ok(mut doc) => {
-            eprintln!("Opened EPUB for cover extraction: {}", path);
-            
-            let mut cover_data: Option<(Vec<u8>, String)> = None;
-            
-            // Strategy 1: get_cover() (uses <meta name="cover"> tag)
-            if let Some((data, mime)) = doc.get_cover() {
-                eprintln!("Strategy 1 - get_cover() succeeded, mime: {}, size: {} bytes", mime, data.len());
-                cover_data = Some((data, mime));
-            } else {
-                eprintln!("Strategy 1 - get_cover() returned None");
-            }
-            
-            // Strategy 2: get_cover_id() then get_resource()
-            if cover_data.is_none() {
-                if let Some(cover_id) = doc.get_cover_id() {
-                    eprintln!("Strategy 2 - get_cover_id() returned: '{}'", cover_id);
-                    if let Some((data, mime)) = doc.get_resource(&cover_id) {
-                        eprintln!("Strategy 2 - get_resource('{}') succeeded, mime: {}, size: {}", cover_id, mime, data.len());
-                        cover_data = Some((data, mime));
-                    }
-                } else {
-                    eprintln!("Strategy 2 - get_cover_id() returned None");
-                }
-            }
-            
-            // Strategy 3: Try common cover resource IDs
-            if cover_data.is_none() {
-                let common_ids = ["cover-image", "cover", "Cover", "CoverImage", "coverimage"];
-                for cid in &common_ids {
-                    if let Some((data, mime)) = doc.get_resource(cid) {
-                        eprintln!("Strategy 3 - Found cover with id '{}', mime: {}, size: {}", cid, mime, data.len());
-                        cover_data = Some((data, mime));
-                        break;
-                    }
-                }
-            }
-            
-            // Strategy 4: Scan all resources for first image
-            if cover_data.is_none() {
-                eprintln!("Strategy 4 - Scanning all resources for images...");
-                let resource_ids: Vec<String> = doc.resources.keys().cloned().collect();
-                for rid in &resource_ids {
-                    if let Some(mime) = doc.get_resource_mime(rid) {
-                        if mime.starts_with("image/") {
-                            eprintln!("Strategy 4 - Found image resource '{}', mime: {}", rid, mime);
-                            if let Some((data, mime)) = doc.get_resource(rid) {
-                                cover_data = Some((data, mime));
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
-            
-            // Save cover if we found one
-            if let Some((data, mime)) = cover_data {
-                let ext = match mime.as_str() {
-                    "image/jpeg" => "jpg",
-                    "image/png" => "png",
-                    "image/gif" => "gif",
-                    "image/webp" => "webp",
-                    _ => "jpg", // Default fallback
-                };
-                
-                let cover_filename = format!("{}.{}", id, ext);
-                let cover_file_path = covers_dir.join(&cover_filename);
-                
-                match fs::write(&cover_file_path, &data) {
-                    Ok(_) => {
-                        eprintln!("Cover saved: {}", cover_file_path.display());
-                        cover_path = Some(cover_file_path.to_string_lossy().to_string());
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to write cover: {}", e);
-                    }
-                }
+/// Read something from an opened EPUB, logging a malformed one rather than
+/// taking the app down with it
+fn read_epub<R: Read + Seek, T>(
+    doc: &mut Option<epub::doc::EpubDoc<R>>,
+    path: &str,
+    what: &str,
+    read: impl FnOnce(&mut epub::doc::EpubDoc<R>) -> Option<T>,
+) -> Option<T> {
+    let doc = doc.as_mut()?;
+    crash::catch_panic(what, || read(doc)).unwrap_or_else(|e| {
+        logging::error(&format!("{}: {}", e, path));
+        None
+    })
+}
 
-            } else {
-                eprintln!("No cover image found in EPUB: {}", path);
-            }
-        }
-        Err(e) => {
-            eprintln!("Failed to open EPUB for cover extraction: {:?}", e);
-        }
+/// Add a book, or refresh it if it is already in the library. Returns the
+/// change without emitting it, so bulk imports can batch their events.
+pub fn import_book(
+    state: &AppState,
+    names: BookNames,
+    path: String,
+) -> Result<LibraryEvent, String> {
+    let covers_dir = covers_dir(state)?;
+
+    // Books are found by their file. Books added before ISBNs and word
+    // counts were read get them when reopened.
+    let (
+        existing_id,
+        remote_cover,
+        needs_isbn,
+        needs_word_count,
+        needs_hash,
+        needs_series,
+        needs_language,
+    ) = state.with_library(|library| {
+        let existing = library.books.iter().find(|b| b.file_path == path);
+        (
+            existing.map(|b| b.id.clone()),
+            existing.is_some_and(|b| b.cover_source == CoverSource::Remote),
+            existing.is_none_or(|b| b.isbn.is_none()),
+            existing.is_none_or(|b| b.word_count.is_none()),
+            existing.is_none_or(|b| b.content_hash.is_none()),
+            existing.is_none_or(|b| b.series.is_none()),
+            existing.is_none_or(|b| b.language.is_none()),
+        )
+    })?;
+    let content_hash = if needs_hash {
+        file_hash(Path::new(&path))
+    } else {
+        None
+    };
+    // The same file added from another folder is the book already on the
+    // shelf, so nothing is extracted under an id that won't be kept
+    let duplicate = match (&existing_id, &content_hash) {
+        (None, Some(hash)) => state.with_library(|library| {
+            library
+                .books
+                .iter()
+                .find(|b| b.content_hash.as_ref() == Some(hash))
+                .map(|b| (b.id.clone(), b.cover_path.is_some()))
+        })?,
+        _ => None,
+    };
+    let is_new = existing_id.is_none() && duplicate.is_none();
+    // A new book's id comes from its contents, so it is the same book
+    // wherever the file is moved
+    let id = match (existing_id, &duplicate) {
+        (Some(id), _) => id,
+        (None, Some((id, _))) => id.clone(),
+        (None, None) => book_ids::new_book_id(state, &path, content_hash.as_deref())?,
+    };
+    // A downloaded cover is the user's choice, and a duplicate keeps the
+    // cover it has; extracting would also write over its file, named after
+    // the book too
+    let keep_cover = remote_cover || duplicate.is_some_and(|(_, has_cover)| has_cover);
+
+    // Reopening a book whose cover was extracted this session skips the EPUB
+    let cached_cover = state
+        .covers
+        .lock()
+        .ok()
+        .and_then(|mut covers| covers.get(&id))
+        .filter(|cover| Path::new(cover).exists());
+    let needs_cover = !keep_cover && cached_cover.is_none();
+
+    // The EPUB is parsed once, and only when something is read from it
+    let mut doc = if is_new
+        || needs_cover
+        || needs_isbn
+        || needs_word_count
+        || needs_series
+        || needs_language
+    {
+        open_epub(&path)
+    } else {
+        None
+    };
+
+    let cover_path = match cached_cover {
+        _ if keep_cover => None,
+        Some(cover) => Some(cover),
+        None => read_epub(&mut doc, &path, "extracting the cover", |doc| {
+            cover_of(doc, &path, &id, &covers_dir)
+        }),
+    };
+    if let (Some(cover), Ok(mut covers)) = (&cover_path, state.covers.lock()) {
+        covers.insert(id.clone(), cover.clone());
     }
+    let thumbnail_path = cover_path.as_deref().and_then(covers::thumbnail_of);
 
-    // Load existing library
-    let mut library = if library_path.exists() {
-        let content = fs::read_to_string(&library_path)
-            .map_err(|e| format!("Failed to read library: {}", e))?;
-        serde_json::from_str(&content).unwrap_or_default()
+    let isbn = if needs_isbn {
+        read_epub(&mut doc, &path, "reading the ISBN", |doc| extract_isbn(doc))
     } else {
-        Library::default()
+        None
+    };
+    let word_count = if needs_word_count {
+        read_epub(&mut doc, &path, "counting words", count_words)
+    } else {
+        None
     };
 
-    // Check if book already exists
-    if let Some(idx) = library.books.iter().position(|b| b.id == id) {
-        library.books[idx].last_opened = Utc::now();
-        // Update cover if we extracted one
-        if cover_path.is_some() {
-            library.books[idx].cover_path = cover_path;
-        }
-        let book = library.books[idx].clone();
-        save_library(&library, &library_path)?;
-        return Ok(book);
-    }
-
-    // Create new book entry
-    let book = Book {
-        id: id.clone(),
-        title,
-        author,
-        file_path: path,
-        cover_path,
-        last_opened: Utc::now(),
-        progress: 0.0,
-        cfi: None,
+    let (series, series_index, series_end) = if needs_series {
+        read_epub(&mut doc, &path, "reading the series", |doc| {
+            extract_series(doc)
+        })
+        .map_or((None, None, None), |(name, index)| {
+            (
+                Some(name),
+                index.map(|(i, _)| i),
+                index.and_then(|(_, end)| end),
+            )
+        })
+    } else {
+        (None, None, None)
+    };
+    let language = if needs_language {
+        read_epub(&mut doc, &path, "reading the language", |doc| {
+            extract_language(doc)
+        })
+    } else {
+        None
+    };
+    let epub_names = if is_new {
+        read_epub(&mut doc, &path, "reading the title", |doc| {
+            Some(extract_title_author(doc))
+        })
+    } else {
+        None
     };
+    let (title, author) = names.resolve(&path, epub_names.unwrap_or_default());
 
-    library.books.push(book.clone());
-    save_library(&library, &library_path)?;
+    let event = state.update_library(|library| {
+        // Check if book already exists
+        if let Some(existing) = library.books.iter_mut().find(|b| b.file_path == path) {
+            existing.last_opened = Utc::now();
+            existing.missing = false;
+            if content_hash.is_some() {
+                existing.content_hash = content_hash;
+            }
+            if series.is_some() {
+                existing.series = series;
+                existing.series_index = series_index;
+                existing.series_end = series_end;
+            }
+            if language.is_some() {
+                existing.language = language;
+            }
+            if isbn.is_some() {
+                existing.isbn = isbn;
+            }
+            if word_count.is_some() {
+                existing.word_count = word_count;
+            }
+            // Update cover if we extracted one, unless one was downloaded
+            // meanwhile
+            if cover_path.is_some() && existing.cover_source != CoverSource::Remote {
+                existing.thumbnail_path = thumbnail_path;
+                existing.cover_path = cover_path;
+                existing.cover_source = CoverSource::Extracted;
+            }
+            return Ok(LibraryEvent::Updated(existing.clone()));
+        }
 
-    Ok(book)
-}
+        // The same file added from another folder is the book already on
+        // the shelf, moved to the new place if its old file is gone
+        let duplicate = content_hash.as_ref().and_then(|hash| {
+            library
+                .books
+                .iter_mut()
+                .find(|b| b.content_hash.as_ref() == Some(hash))
+        });
+        if let Some(existing) = duplicate {
+            existing.last_opened = Utc::now();
+            if !Path::new(&existing.file_path).exists() {
+                logging::info(&format!(
+                    "Moved '{}' from {} to {}",
+                    existing.title, existing.file_path, path
+                ));
+                existing.file_path = path;
+                existing.missing = false;
+            }
+            if existing.cover_path.is_none() && cover_path.is_some() {
+                existing.thumbnail_path = thumbnail_path;
+                existing.cover_path = cover_path;
+                existing.cover_source = CoverSource::Extracted;
+            }
+            return Ok(LibraryEvent::Updated(existing.clone()));
+        }
 
-/// Get recently opened books
-#[tauri::command]
-pub fn get_recent_books(limit: usize) -> Result<Vec<Book>, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+        // Create new book entry
+        let book = Book {
+            id: id.clone(),
+            title,
+            author,
+            file_path: path,
+            cover_path,
+            cover_source: CoverSource::Extracted,
+            thumbnail_path,
+            last_opened: Utc::now(),
+            progress: 0.0,
+            cfi: None,
+            positions: ResumePositions::default(),
+            reading_location: None,
+            isbn,
+            word_count,
+            rating: None,
+            review: None,
+            reading_state: ReadingState::ToRead,
+            finished_at: None,
+            completions: Vec::new(),
+            first_opened: Some(Utc::now()),
+            started_at: None,
+            tags: Vec::new(),
+            media_type: MediaType::Text,
+            audio: None,
+            description: None,
+            publish_year: None,
+            series,
+            series_index,
+            series_end,
+            language,
+            subjects: Vec::new(),
+            open_library_key: None,
+            imported_position: None,
+            overrides: BookOverrides::default(),
+            content_hash,
+            chapters: None,
+            missing: false,
+            favorite: false,
+            archived: false,
+        };
+        library.books.push(book.clone());
+        Ok(LibraryEvent::Added(book))
+    })?;
 
-    let library_path = home_dir.join(".epub-reader").join("library.json");
+    // Highlights imported before the book was
+    if let LibraryEvent::Added(book) = &event {
+        annotations_archive::attach_pending(state, book);
+    }
 
-    if !library_path.exists() {
-        return Ok(Vec::new());
+    if !meta::load_meta().onboarding.has_imported_first_book {
+        let _ = meta::update_meta(|m| m.onboarding.has_imported_first_book = true);
     }
 
-    let content =
-        fs::read_to_string(&library_path).map_err(|e| format!("Failed to read library: {}", e))?;
+    Ok(event)
+}
 
-    let mut library: Library =
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse library: {}", e))?;
+/// Import a book Epilogue saved into its books folder, adding `tags`, and
+/// emit the change
+pub fn import_tagged_book(
+    app: &AppHandle,
+    state: &AppState,
+    title: String,
+    author: String,
+    path: String,
+    tags: Vec<String>,
+) -> Result<Book, String> {
+    let names = BookNames::Given {
+        title: Some(title),
+        author: Some(author),
+    };
+    let event = import_book(state, names, path)?;
+    let book_id = match &event {
+        LibraryEvent::Added(b) | LibraryEvent::Updated(b) => b.id.clone(),
+        _ => return Err("Unexpected library change".to_string()),
+    };
 
-    // Sort by last_opened descending
-    library
-        .books
-        .sort_by(|a, b| b.last_opened.cmp(&a.last_opened));
+    let imported = state.update_library(|library| {
+        let entry = library
+            .books
+            .iter_mut()
+            .find(|b| b.id == book_id)
+            .ok_or_else(|| format!("Book with id '{}' not found", book_id))?;
+        add_tags(entry, tags);
+        Ok(entry.clone())
+    })?;
+    emit_library_event(
+        app,
+        match event {
+            LibraryEvent::Added(_) => LibraryEvent::Added(imported.clone()),
+            _ => LibraryEvent::Updated(imported.clone()),
+        },
+    );
 
-    Ok(library.books.into_iter().take(limit).collect())
+    Ok(imported)
 }
 
-/// Update reading progress
-#[tauri::command]
-pub fn update_progress(book_id: String, progress: f32, cfi: String) -> Result<(), String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+/// The first dc:title of an EPUB and its dc:creators joined with ", "
+fn extract_title_author<R: Read + Seek>(
+    doc: &epub::doc::EpubDoc<R>,
+) -> (Option<String>, Option<String>) {
+    let values = |key: &str| -> Vec<String> {
+        doc.metadata
+            .get(key)
+            .into_iter()
+            .flatten()
+            .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|value| !value.is_empty())
+            .collect()
+    };
+    let title = values("title").into_iter().next();
+    let creators = values("creator");
+    let author = (!creators.is_empty()).then(|| creators.join(", "));
+    (title, author)
+}
 
-    let library_path = home_dir.join(".epub-reader").join("library.json");
+/// The first dc:identifier of an EPUB that is an ISBN
+fn extract_isbn<R: Read + Seek>(doc: &epub::doc::EpubDoc<R>) -> Option<String> {
+    doc.metadata
+        .get("identifier")?
+        .iter()
+        .find_map(|id| isbn::normalize_isbn(id).ok())
+}
 
-    let content =
-        fs::read_to_string(&library_path).map_err(|e| format!("Failed to read library: {}", e))?;
+/// The first dc:language of an EPUB, lowercased
+fn extract_language<R: Read + Seek>(doc: &epub::doc::EpubDoc<R>) -> Option<String> {
+    doc.metadata
+        .get("language")?
+        .iter()
+        .map(|language| language.trim().to_lowercase())
+        .find(|language| !language.is_empty())
+}
 
-    let mut library: Library =
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse library: {}", e))?;
+/// Series name and position from the EPUB metadata: calibre:series and
+/// calibre:series_index, or EPUB 3's belongs-to-collection and
+/// group-position
+fn extract_series<R: Read + Seek>(
+    doc: &epub::doc::EpubDoc<R>,
+) -> Option<(String, Option<series::SeriesIndex>)> {
+    let first = |key: &str| {
+        doc.metadata
+            .get(key)?
+            .iter()
+            .map(|value| value.trim())
+            .find(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let name = first("calibre:series").or_else(|| first("belongs-to-collection"))?;
+    let index = first("calibre:series_index")
+        .or_else(|| first("group-position"))
+        .and_then(|index| series::parse_index(&index));
+    Some((name, index))
+}
 
-    if let Some(book) = library.books.iter_mut().find(|b| b.id == book_id) {
-        book.progress = progress;
-        book.cfi = Some(cfi);
-        book.last_opened = Utc::now();
-        save_library(&library, &library_path)?;
+/// MD5 of a file, read in chunks
+pub fn file_hash(path: &Path) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut context = md5::Context::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => context.consume(&buffer[..n]),
+            Err(_) => return None,
+        }
     }
+    Some(format!("{:x}", context.compute()))
+}
 
-    Ok(())
+/// Words in an XHTML document, with markup stripped
+pub fn count_text_words(content: &str) -> u64 {
+    let mut in_tag = false;
+    let text: String = content
+        .chars()
+        .map(|c| match c {
+            '<' => {
+                in_tag = true;
+                ' '
+            }
+            '>' => {
+                in_tag = false;
+                ' '
+            }
+            _ if in_tag => ' ',
+            _ => c,
+        })
+        .collect();
+    text.split_whitespace().count() as u64
 }
 
-/// Get last saved progress for a book
-#[tauri::command]
-pub fn get_book_progress(book_id: String) -> Result<Option<String>, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+/// Words across the book's spine
+fn count_words<R: Read + Seek>(doc: &mut epub::doc::EpubDoc<R>) -> Option<u64> {
+    Some(spine_words(doc).iter().sum())
+}
 
-    let library_path = home_dir.join(".epub-reader").join("library.json");
+/// Words in each item of the book's spine
+fn chapter_words(path: &str) -> Option<Vec<u64>> {
+    let mut doc = epub::doc::EpubDoc::new(path).ok()?;
+    Some(spine_words(&mut doc))
+}
 
-    if !library_path.exists() {
-        return Ok(None);
+fn spine_words<R: Read + Seek>(doc: &mut epub::doc::EpubDoc<R>) -> Vec<u64> {
+    let mut words = Vec::new();
+    loop {
+        words.push(
+            doc.get_current_str()
+                .map_or(0, |(content, _)| count_text_words(&content)),
+        );
+        if !doc.go_next() {
+            break;
+        }
     }
+    words
+}
 
-    let content =
-        fs::read_to_string(&library_path).map_err(|e| format!("Failed to read library: {}", e))?;
+/// Write a cover image into the covers directory, named after the book,
+/// with its grid thumbnail. Extracted and downloaded covers both go through
+/// here.
+pub fn save_cover(covers_dir: &Path, id: &str, data: &[u8], mime: &str) -> Option<String> {
+    let ext = match mime {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => "jpg", // Default fallback
+    };
 
-    let library: Library =
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse library: {}", e))?;
+    let cover_filename = format!("{}.{}", id, ext);
+    let cover_file_path = covers_dir.join(&cover_filename);
 
-    if let Some(book) = library.books.iter().find(|b| b.id == book_id) {
-        return Ok(book.cfi.clone());
+    // A cover is optional, so an unwritable covers dir only skips it
+    match preflight::check_writable(PathKind::Covers)
+        .map_err(|e| e.to_string())
+        .and_then(|_| fs::write(&cover_file_path, data).map_err(|e| e.to_string()))
+    {
+        Ok(_) => {
+            logging::debug(&format!("Cover saved: {}", cover_file_path.display()));
+            covers::write_thumbnail(covers_dir, id, data);
+            Some(cover_file_path.to_string_lossy().to_string())
+        }
+        Err(e) => {
+            logging::warn(&format!("Failed to write cover: {}", e));
+            None
+        }
     }
-
-    Ok(None)
 }
 
-/// Remove a book from the library
-#[tauri::command]
-pub fn remove_book(book_id: String) -> Result<(), String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+/// Extract the cover image of an EPUB into the covers directory
+pub fn extract_cover(path: &str, id: &str, covers_dir: &Path) -> Option<String> {
+    match epub::doc::EpubDoc::new(path) {
+        Ok(mut doc) => {
+            logging::debug(&format!("Opened EPUB for cover extraction: {}", path));
+            cover_of(&mut doc, path, id, covers_dir)
+        }
+        Err(e) => {
+            logging::warn(&format!(
+                "Failed to open EPUB for cover extraction: {:?}",
+                e
+            ));
+            None
+        }
+    }
+}
 
-    let app_dir = home_dir.join(".epub-reader");
-    let library_path = app_dir.join("library.json");
+/// Extract the cover image of an opened EPUB into the covers directory
+fn cover_of<R: Read + Seek>(
+    doc: &mut epub::doc::EpubDoc<R>,
+    path: &str,
+    id: &str,
+    covers_dir: &Path,
+) -> Option<String> {
+    let mut cover_data: Option<(Vec<u8>, String)> = None;
 
-    if !library_path.exists() {
-        return Err("Library not found".to_string());
+    // Strategy 1: get_cover() (uses <meta name="cover"> tag)
+    if let Some((data, mime)) = doc.get_cover() {
+        logging::debug(&format!(
+            "Strategy 1 - get_cover() succeeded, mime: {}, size: {} bytes",
+            mime,
+            data.len()
+        ));
+        cover_data = Some((data, mime));
+    } else {
+        logging::debug("Strategy 1 - get_cover() returned None");
     }
 
-    let content =
-        fs::read_to_string(&library_path).map_err(|e| format!("Failed to read library: {}", e))?;
-
-    let mut library: Library =
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse library: {}", e))?;
+    // Strategy 2: get_cover_id() then get_resource()
+    if cover_data.is_none() {
+        if let Some(cover_id) = doc.get_cover_id() {
+            logging::debug(&format!(
+                "Strategy 2 - get_cover_id() returned: '{}'",
+                cover_id
+            ));
+            if let Some((data, mime)) = doc.get_resource(&cover_id) {
+                logging::debug(&format!(
+                    "Strategy 2 - get_resource('{}') succeeded, mime: {}, size: {}",
+                    cover_id,
+                    mime,
+                    data.len()
+                ));
+                cover_data = Some((data, mime));
+            }
+        } else {
+            logging::debug("Strategy 2 - get_cover_id() returned None");
+        }
+    }
 
-    // Find and remove the book
-    let original_len = library.books.len();
-    
-    // Delete cover file if it exists
-    if let Some(book) = library.books.iter().find(|b| b.id == book_id) {
-        if let Some(ref cover) = book.cover_path {
-            let _ = fs::remove_file(cover);
+    // Strategy 3: Try common cover resource IDs
+    if cover_data.is_none() {
+        let common_ids = ["cover-image", "cover", "Cover", "CoverImage", "coverimage"];
+        for cid in &common_ids {
+            if let Some((data, mime)) = doc.get_resource(cid) {
+                logging::debug(&format!(
+                    "Strategy 3 - Found cover with id '{}', mime: {}, size: {}",
+                    cid,
+                    mime,
+                    data.len()
+                ));
+                cover_data = Some((data, mime));
+                break;
+            }
         }
     }
 
-    library.books.retain(|b| b.id != book_id);
+    // Strategy 4: Scan all resources for first image
+    if cover_data.is_none() {
+        logging::debug("Strategy 4 - Scanning all resources for images...");
+        let resource_ids: Vec<String> = doc.resources.keys().cloned().collect();
+        for rid in &resource_ids {
+            if let Some(mime) = doc.get_resource_mime(rid) {
+                if mime.starts_with("image/") {
+                    logging::debug(&format!(
+                        "Strategy 4 - Found image resource '{}', mime: {}",
+                        rid, mime
+                    ));
+                    if let Some((data, mime)) = doc.get_resource(rid) {
+                        cover_data = Some((data, mime));
+                        break;
+                    }
+                }
+            }
+        }
+    }
 
-    if library.books.len() == original_len {
-        return Err(format!("Book with id '{}' not found", book_id));
+    // Save cover if we found one
+    if let Some((data, mime)) = cover_data {
+        save_cover(covers_dir, id, &data, &mime)
+    } else {
+        logging::debug(&format!("No cover image found in EPUB: {}", path));
+        None
     }
+}
 
-    save_library(&library, &library_path)?;
-    Ok(())
+/// The most recently opened books, newest first. Archived books only with
+/// `include_archived`.
+pub fn recent_books(
+    state: &AppState,
+    limit: usize,
+    include_archived: bool,
+) -> Result<Vec<Book>, String> {
+    state.with_library(|library| {
+        let mut books: Vec<Book> = library
+            .books
+            .iter()
+            .filter(|b| include_archived || !b.archived)
+            .cloned()
+            .collect();
+
+        // Sort by last_opened descending
+        books.sort_by(|a, b| b.last_opened.cmp(&a.last_opened));
+
+        books.into_iter().take(limit).collect()
+    })
+}
+
+/// Get recently opened books, leaving out archived ones unless asked for
+#[tauri::command]
+pub fn get_recent_books(
+    state: State<'_, AppState>,
+    limit: usize,
+    include_archived: Option<bool>,
+) -> Result<Vec<Book>, String> {
+    recent_books(&state, limit, include_archived.unwrap_or(false))
 }
 
-fn save_library(library: &Library, path: &Path) -> Result<(), String> {
-    let json = serde_json::to_string_pretty(library)
-        .map_err(|e| format!("Failed to serialize library: {}", e))?;
+#[derive(Debug, Serialize, Clone)]
+pub struct FileCheck {
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    pub missing: bool,
+}
 
-    fs::write(path, json).map_err(|e| format!("Failed to save library: {}", e))?;
+/// Look for every book's file and mark the books whose file is gone, so
+/// they can be relinked rather than removed. Returns every book's result
+/// and the books whose mark changed.
+pub fn check_files(state: &AppState) -> Result<(Vec<FileCheck>, Vec<Book>), String> {
+    let paths: Vec<(String, String, bool)> = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .map(|b| (b.id.clone(), b.file_path.clone(), b.missing))
+            .collect()
+    })?;
+    // Looked for outside the library lock, as a network drive can be slow
+    let checks: Vec<FileCheck> = paths
+        .iter()
+        .map(|(id, path, _)| FileCheck {
+            book_id: id.clone(),
+            missing: !Path::new(path).exists(),
+        })
+        .collect();
+    let changed: HashMap<&str, bool> = paths
+        .iter()
+        .zip(&checks)
+        .filter(|((_, _, was), check)| *was != check.missing)
+        .map(|((id, _, _), check)| (id.as_str(), check.missing))
+        .collect();
+    if changed.is_empty() {
+        return Ok((checks, Vec::new()));
+    }
 
-    Ok(())
+    let updated = state.update_library(|library| {
+        Ok(library
+            .books
+            .iter_mut()
+            .filter_map(|book| {
+                book.missing = *changed.get(book.id.as_str())?;
+                Some(book.clone())
+            })
+            .collect::<Vec<_>>())
+    })?;
+    Ok((checks, updated))
+}
+
+/// Check the books' files in the background once the app is up
+pub fn verify_files_on_startup(app: &AppHandle) {
+    if config::is_safe_mode() {
+        return;
+    }
+    let app = app.clone();
+    std::thread::spawn(move || match check_files(&app.state::<AppState>()) {
+        Ok((checks, updated)) => {
+            let missing = checks.iter().filter(|c| c.missing).count();
+            if missing > 0 {
+                logging::warn(&format!("{} books' files are missing", missing));
+            }
+            let mut events = LibraryEventBatch::new(&app);
+            for book in updated {
+                events.push(LibraryEvent::Updated(book));
+            }
+        }
+        Err(e) => logging::error(&format!("Failed to check library files: {}", e)),
+    });
+}
+
+/// Which books' files are gone, marking them in the library
+#[tauri::command]
+pub fn check_library_files(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<FileCheck>, String> {
+    let (checks, updated) = check_files(&state)?;
+    let mut events = LibraryEventBatch::new(&app);
+    for book in updated {
+        events.push(LibraryEvent::Updated(book));
+    }
+    Ok(checks)
+}
+
+/// Point a book at its file's new place, keeping its id so progress and
+/// annotations stay with it. The file must be an EPUB with the same
+/// contents or title as the book unless `force` is set. A cover whose file
+/// is gone is extracted again.
+#[tauri::command]
+pub fn relink_book(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    new_path: String,
+    force: Option<bool>,
+) -> Result<Book, String> {
+    let path = file_access::check_read_access(&state, &new_path)?;
+    let is_epub = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"));
+    if !is_epub || !path.is_file() {
+        return Err(format!("Not an EPUB file: {}", new_path));
+    }
+    let path_str = path.to_string_lossy().to_string();
+    let book = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .find(|b| b.id == book_id)
+            .cloned()
+            .ok_or_else(|| format!("Book with id '{}' not found", book_id))
+    })??;
+    book.require_text()?;
+
+    let mut doc = open_epub(&path_str);
+    let title = read_epub(&mut doc, &path_str, "reading the title", |doc| {
+        extract_title_author(doc).0
+    });
+    let Some(title) = title else {
+        return Err(format!("Failed to read the EPUB: {}", new_path));
+    };
+    let content_hash = file_hash(&path);
+    let same_contents = book.content_hash.is_some() && book.content_hash == content_hash;
+    let same_title = authors::fold(&title) == authors::fold(&book.title);
+    if !same_contents && !same_title && !force.unwrap_or(false) {
+        return Err(format!(
+            "'{}' doesn't look like '{}'; relink it anyway to use it",
+            title, book.title
+        ));
+    }
+
+    let cover_gone = book
+        .cover_path
+        .as_deref()
+        .is_none_or(|cover| !Path::new(cover).exists());
+    let cover_path = if cover_gone {
+        let covers_dir = covers_dir(&state)?;
+        read_epub(&mut doc, &path_str, "extracting the cover", |doc| {
+            cover_of(doc, &path_str, &book.id, &covers_dir)
+        })
+    } else {
+        None
+    };
+
+    let book = update_book(&app, &state, &book_id, |book| {
+        book.file_path = path_str;
+        book.missing = false;
+        if content_hash.is_some() {
+            book.content_hash = content_hash;
+        }
+        if cover_path.is_some() {
+            book.thumbnail_path = cover_path.as_deref().and_then(covers::thumbnail_of);
+            book.cover_path = cover_path;
+            book.cover_source = CoverSource::Extracted;
+        }
+    })?;
+    logging::info(&format!("Relinked '{}' to {}", book.title, book.file_path));
+    Ok(book)
+}
+
+/// Hash the files of books added before content hashes were kept, then
+/// group the books that share one, most recently opened first
+fn duplicate_groups(state: &AppState) -> Result<Vec<Vec<Book>>, String> {
+    let unhashed: Vec<(String, String)> = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .filter(|b| b.content_hash.is_none() && b.media_type == MediaType::Text)
+            .map(|b| (b.id.clone(), b.file_path.clone()))
+            .collect()
+    })?;
+    let hashes: HashMap<String, String> = unhashed
+        .into_iter()
+        .filter_map(|(id, path)| Some((id, file_hash(Path::new(&path))?)))
+        .collect();
+    if !hashes.is_empty() && !config::is_safe_mode() {
+        state.update_library(|library| {
+            for book in library.books.iter_mut() {
+                if let Some(hash) = hashes.get(&book.id) {
+                    book.content_hash = Some(hash.clone());
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    state.with_library(|library| {
+        let mut groups: HashMap<&str, Vec<Book>> = HashMap::new();
+        for book in &library.books {
+            let hash = book
+                .content_hash
+                .as_deref()
+                .or_else(|| hashes.get(&book.id).map(String::as_str));
+            if let Some(hash) = hash {
+                groups.entry(hash).or_default().push(book.clone());
+            }
+        }
+        let mut groups: Vec<Vec<Book>> = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort_by(|a, b| b.last_opened.cmp(&a.last_opened));
+                group
+            })
+            .collect();
+        groups.sort_by(|a, b| a[0].title.cmp(&b[0].title));
+        groups
+    })
+}
+
+/// Books that are the same file added more than once, in groups
+#[tauri::command]
+pub async fn find_duplicates(app: AppHandle) -> Result<Vec<Vec<Book>>, String> {
+    tauri::async_runtime::spawn_blocking(move || duplicate_groups(&app.state::<AppState>()))
+        .await
+        .map_err(|e| format!("Duplicate search task failed: {}", e))?
+}
+
+/// What get_books orders books by
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BookSort {
+    #[default]
+    LastOpened,
+    /// Ignoring a leading "The", "A" or "An"
+    Title,
+    /// By the first author's last name
+    Author,
+    Progress,
+    /// When the book was first opened, which is when it was added
+    DateAdded,
+    /// Unrated books last, whichever the order
+    Rating,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct BookQuery {
+    #[serde(rename = "sortBy", default)]
+    pub sort_by: BookSort,
+    /// Newest, furthest and best rated first for dates, progress and
+    /// ratings, A to Z otherwise
+    #[serde(default)]
+    pub order: Option<SortOrder>,
+    /// On any of these shelves; every shelf when empty
+    #[serde(default)]
+    pub status: Vec<ReadingState>,
+    /// Part of the author's name, ignoring case and accents
+    #[serde(default)]
+    pub author: Option<String>,
+    /// From 0 to 1
+    #[serde(rename = "minProgress", default)]
+    pub min_progress: Option<f32>,
+    #[serde(rename = "favoritesOnly", default)]
+    pub favorites_only: bool,
+    /// Only archived books with true, none with false; both when unset
+    #[serde(default)]
+    pub archived: Option<bool>,
+    /// List each series once, as the first of its books in the sort order
+    #[serde(rename = "groupBySeries", default)]
+    pub group_by_series: bool,
+    /// A smart shelf rule the books must match too
+    #[serde(default)]
+    pub rules: Option<serde_json::Value>,
+}
+
+impl BookQuery {
+    fn rule(&self) -> Result<Rule, String> {
+        let mut rules = Vec::new();
+        if !self.status.is_empty() {
+            rules.push(Rule::Filter(Filter::Status(self.status.clone())));
+        }
+        if let Some(author) = self.author.as_deref().map(str::trim) {
+            if !author.is_empty() {
+                rules.push(Rule::Filter(Filter::Author(authors::fold(author))));
+            }
+        }
+        if let Some(min) = self.min_progress {
+            rules.push(Rule::Filter(Filter::Progress {
+                min: Some(min.clamp(0.0, 1.0)),
+                max: None,
+            }));
+        }
+        if self.favorites_only {
+            rules.push(Rule::Filter(Filter::Favorite(true)));
+        }
+        if let Some(archived) = self.archived {
+            rules.push(Rule::Filter(Filter::Archived(archived)));
+        }
+        if let Some(value) = &self.rules {
+            rules.push(Rule::from_value(value)?);
+        }
+        Ok(Rule::All(rules))
+    }
+}
+
+/// A title as it is shelved: without case, accents or a leading article
+pub fn title_sort_key(title: &str) -> String {
+    let folded = authors::fold(title.trim());
+    ["the ", "a ", "an "]
+        .iter()
+        .find_map(|article| folded.strip_prefix(article))
+        .map(|rest| rest.trim_start().to_string())
+        .unwrap_or(folded)
+}
+
+fn author_sort_key(author: &str) -> String {
+    let first = authors::split_authors(author)
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    authors::fold(&authors::sort_name(&first))
+}
+
+/// Books matching `query`, sorted as it asks, from `offset` on and at most
+/// `limit` of them, with how many match in all. Only the books returned
+/// are copied out of the library.
+pub fn query_books(
+    state: &AppState,
+    query: &BookQuery,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<(Vec<Book>, usize), String> {
+    let rule = query.rule()?;
+    state.with_library(|library| {
+        let context = FilterContext::for_rule(state, &rule, &library.books);
+        let mut books: Vec<&Book> = library
+            .books
+            .iter()
+            .filter(|book| rule.matches(book, &context))
+            .collect();
+
+        let descending = match query.order {
+            Some(order) => order == SortOrder::Desc,
+            None => matches!(
+                query.sort_by,
+                BookSort::LastOpened | BookSort::Progress | BookSort::DateAdded | BookSort::Rating
+            ),
+        };
+        match query.sort_by {
+            BookSort::LastOpened => books.sort_by_key(|b| b.last_opened),
+            BookSort::Title => books.sort_by_cached_key(|b| title_sort_key(&b.title)),
+            BookSort::Author => {
+                books.sort_by_cached_key(|b| (author_sort_key(&b.author), title_sort_key(&b.title)))
+            }
+            BookSort::Progress => books.sort_by(|a, b| a.progress.total_cmp(&b.progress)),
+            BookSort::DateAdded => books.sort_by_key(|b| b.first_opened),
+            BookSort::Rating => books.sort_by_key(|b| b.rating),
+        }
+        if descending {
+            books.reverse();
+        }
+        if query.sort_by == BookSort::Rating {
+            // A stable sort, so the rated books keep their order
+            books.sort_by_key(|b| b.rating.is_none());
+        }
+        if query.group_by_series {
+            let mut seen = HashSet::new();
+            books.retain(|b| match b.series.as_deref() {
+                Some(name) => seen.insert(series::series_key(name)),
+                None => true,
+            });
+        }
+
+        let total = books.len();
+        let page = books
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        (page, total)
+    })
+}
+
+/// Books filtered and sorted here rather than in the webview, e.g.
+/// `{"sortBy": "title", "status": ["reading"], "minProgress": 0.5}`
+#[tauri::command]
+pub fn get_books(
+    state: State<'_, AppState>,
+    options: Option<BookQuery>,
+) -> Result<Vec<Book>, String> {
+    Ok(query_books(&state, &options.unwrap_or_default(), 0, None)?.0)
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BookPage {
+    pub books: Vec<Book>,
+    pub offset: usize,
+    /// Books matching the filters, on every page
+    #[serde(rename = "totalCount")]
+    pub total_count: usize,
+}
+
+/// One page of get_books, for infinite scrolling
+#[tauri::command]
+pub fn get_books_page(
+    state: State<'_, AppState>,
+    offset: usize,
+    limit: usize,
+    options: Option<BookQuery>,
+) -> Result<BookPage, String> {
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(format!(
+            "Page size must be between 1 and {}, got {}",
+            MAX_PAGE_SIZE, limit
+        ));
+    }
+    let (books, total_count) =
+        query_books(&state, &options.unwrap_or_default(), offset, Some(limit))?;
+    Ok(BookPage {
+        books,
+        offset,
+        total_count,
+    })
+}
+
+/// Start the save thread on first use. It waits for the latest deadline
+/// it was sent, then flushes the library.
+fn save_worker(app: &AppHandle) -> &'static mpsc::Sender<Option<Instant>> {
+    SAVE_WORKER.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<Option<Instant>>();
+        let app = app.clone();
+        std::thread::spawn(move || {
+            let mut due: Option<Instant> = None;
+            loop {
+                let next = match due {
+                    Some(at) => rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+                    None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match next {
+                    Ok(at) => due = at,
+                    Err(RecvTimeoutError::Timeout) => {
+                        due = None;
+                        if let Err(e) = app.state::<AppState>().flush_library() {
+                            logging::error(&format!("Failed to save reading progress: {}", e));
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+        tx
+    })
+}
+
+/// Write unsaved library changes after `delay`. Each call restarts the
+/// wait, so a burst of page turns ends in a single write.
+pub fn schedule_library_save(app: &AppHandle, delay: Duration) -> Result<(), String> {
+    if delay.is_zero() {
+        return app.state::<AppState>().flush_library();
+    }
+    save_worker(app)
+        .send(Some(Instant::now() + delay))
+        .map_err(|e| format!("Failed to schedule a library save: {}", e))
+}
+
+/// Write pending progress before the app or a window goes away. Bounded so
+/// a hung disk can't keep the app from closing.
+pub fn flush_pending(app: &AppHandle) {
+    // Any scheduled save is superseded by this one
+    if let Some(worker) = SAVE_WORKER.get() {
+        let _ = worker.send(None);
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let handle = app.clone();
+    std::thread::spawn(move || {
+        let _ = tx.send(handle.state::<AppState>().flush_library());
+    });
+
+    match rx.recv_timeout(FLUSH_TIMEOUT) {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => logging::error(&format!("Failed to save library on exit: {}", e)),
+        Err(_) => logging::error("Timed out saving library on exit"),
+    }
+}
+
+/// Move a book to Finished, recording the completion. A re-read adds to
+/// the earlier completions.
+fn mark_finished(book: &mut Book) {
+    let now = Utc::now();
+    book.reading_state = ReadingState::Finished;
+    book.finished_at = Some(now);
+    book.completions.push(Completion {
+        finished_at: now,
+        started_at: book.started_at.or(book.first_opened),
+        inferred: false,
+    });
+}
+
+/// Move a book to Reading, starting a new read
+fn mark_started(book: &mut Book) {
+    book.reading_state = ReadingState::Reading;
+    book.started_at = Some(Utc::now());
+}
+
+/// Set a book's progress, moving it to Reading or Finished as it advances.
+/// Abandoned books stay abandoned, and a finished book read again keeps its
+/// finished date. True when this progress finished the book.
+fn record_progress(book: &mut Book, progress: f32) -> bool {
+    book.progress = progress;
+    book.last_opened = Utc::now();
+    if book.reading_state == ReadingState::Abandoned {
+        return false;
+    }
+    if progress >= FINISHED_PROGRESS && book.reading_state != ReadingState::Finished {
+        mark_finished(book);
+        return true;
+    } else if progress > 0.0 && book.reading_state == ReadingState::ToRead {
+        mark_started(book);
+    }
+    false
+}
+
+/// Update reading progress. The chapter and page in `view` are saved for
+/// library cards; the ones saved before are kept when they aren't sent.
+#[tauri::command]
+pub fn update_progress(
+    app: AppHandle,
+    window: Window,
+    state: State<'_, AppState>,
+    book_id: String,
+    progress: f32,
+    cfi: String,
+    view: Option<ReaderView>,
+) -> Result<(), String> {
+    let book_id = state.resolve_book_id(&book_id);
+    let Some(existing) = state.with_library(|library| {
+        library.books.iter().find(|b| b.id == book_id).cloned()
+    })?
+    else {
+        return Ok(());
+    };
+    existing.require_text()?;
+    let preferences = state.preferences()?;
+    let by_chapters = preferences.chapter_weighted_progress;
+    // Callers that don't say are reading in the preferred mode
+    let view = view.unwrap_or_else(|| ReaderView {
+        mode: ReadingMode::from_preference(&preferences.reading_mode),
+        ..Default::default()
+    });
+    let scrolled = view.mode == ReadingMode::Scrolled;
+    let location = view.location.map(ReadingLocation::validated).transpose()?;
+    let position = ResumePosition {
+        cfi: cfi.clone(),
+        scroll_pixels: view.scroll_pixels.filter(|_| scrolled),
+        scroll_percent: view
+            .scroll_percent
+            .filter(|_| scrolled)
+            .map(|p| p.clamp(0.0, 1.0)),
+        saved_at: Utc::now(),
+    };
+
+    // Saved after the autosave debounce, or on close/exit at the latest
+    let (book, finished) = state.update_book_in_memory(&book_id, |book| {
+        book.cfi = Some(cfi);
+        book.positions.set(view.mode, position);
+        if location.is_some() {
+            book.reading_location = location;
+        }
+        // Books without a chapter map keep the renderer's percentage
+        let progress = match &book.chapters {
+            Some(chapters) if by_chapters => chapters.progress(),
+            _ => progress,
+        };
+        let finished = record_progress(book, progress);
+        Ok((book.clone(), finished))
+    })?;
+    progress_history::record(&state, &book.id, book.progress);
+    stats::record_reading_day(&state);
+    sessions::note_progress(&app, &book.id);
+    let debounce_ms = app.state::<AdvancedConfig>().autosave_debounce_ms;
+    schedule_library_save(&app, Duration::from_millis(debounce_ms))?;
+    reader_window::notify_progress(&app, window.label(), &book);
+    if finished {
+        let _ = app.emit("book-finished", &book);
+    }
+    emit_library_event(&app, LibraryEvent::Updated(book));
+
+    Ok(())
+}
+
+/// Update listening progress of an audiobook, in seconds from the start
+#[tauri::command]
+pub fn update_audio_progress(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    advanced: State<'_, AdvancedConfig>,
+    book_id: String,
+    position_secs: f64,
+) -> Result<(), String> {
+    if !position_secs.is_finite() || position_secs < 0.0 {
+        return Err(format!("Invalid position: {}", position_secs));
+    }
+
+    let (book, finished) = state.update_book_in_memory(&book_id, |book| {
+        let Some(audio) = book.audio.as_mut() else {
+            return Err(format!("'{}' is not an audiobook", book.title));
+        };
+        audio.position_secs = position_secs.min(audio.duration_secs);
+        let progress = if audio.duration_secs > 0.0 {
+            (audio.position_secs / audio.duration_secs) as f32
+        } else {
+            0.0
+        };
+        let finished = record_progress(book, progress);
+        Ok((book.clone(), finished))
+    })?;
+    progress_history::record(&state, &book.id, book.progress);
+    stats::record_reading_day(&state);
+    sessions::note_progress(&app, &book.id);
+    schedule_library_save(
+        &app,
+        Duration::from_millis(advanced.autosave_debounce_ms),
+    )?;
+    if finished {
+        let _ = app.emit("book-finished", &book);
+    }
+    emit_library_event(&app, LibraryEvent::Updated(book));
+
+    Ok(())
+}
+
+/// Apply a change to one book's shelf details, save and announce it
+pub fn update_book(
+    app: &AppHandle,
+    state: &AppState,
+    book_id: &str,
+    f: impl FnOnce(&mut Book),
+) -> Result<Book, String> {
+    let book = change_book(state, book_id, f)?;
+    emit_library_event(app, LibraryEvent::Updated(book.clone()));
+    Ok(book)
+}
+
+/// Apply a change to one book and save it. The book is found by its id,
+/// or by an id it had before.
+fn change_book(state: &AppState, book_id: &str, f: impl FnOnce(&mut Book)) -> Result<Book, String> {
+    let book_id = state.resolve_book_id(book_id);
+    state.update_library(|library| {
+        let book = library
+            .books
+            .iter_mut()
+            .find(|b| b.id == book_id)
+            .ok_or_else(|| format!("Book with id '{}' not found", book_id))?;
+        f(book);
+        Ok(book.clone())
+    })
+}
+
+/// Pin a book, or unpin it
+#[tauri::command]
+pub fn toggle_favorite(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<Book, String> {
+    update_book(&app, &state, &book_id, |book| {
+        book.favorite = !book.favorite
+    })
+}
+
+/// Pinned books, last opened first
+#[tauri::command]
+pub fn get_favorites(state: State<'_, AppState>) -> Result<Vec<Book>, String> {
+    let query = BookQuery {
+        favorites_only: true,
+        ..Default::default()
+    };
+    Ok(query_books(&state, &query, 0, None)?.0)
+}
+
+/// Hide a book from the home screen. Its file, cover, progress and
+/// annotations stay as they are.
+#[tauri::command]
+pub fn archive_book(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<Book, String> {
+    update_book(&app, &state, &book_id, |book| book.archived = true)
+}
+
+/// Bring an archived book back to the home screen
+#[tauri::command]
+pub fn unarchive_book(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<Book, String> {
+    update_book(&app, &state, &book_id, |book| book.archived = false)
+}
+
+/// Rate a book from 1 to 5 stars, or clear its rating with none or 0
+#[tauri::command]
+pub fn set_rating(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    rating: Option<u8>,
+) -> Result<Book, String> {
+    let rating = rating.filter(|&r| r != 0);
+    if rating.is_some_and(|r| !(1..=5).contains(&r)) {
+        return Err(format!("Rating must be between 1 and 5, got {:?}", rating));
+    }
+    update_book(&app, &state, &book_id, |book| book.rating = rating)
+}
+
+/// Write a review of a book, or clear it with an empty one. Line breaks
+/// are kept; only the ends are trimmed.
+#[tauri::command]
+pub fn set_review(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    review: Option<String>,
+) -> Result<Book, String> {
+    let review = review
+        .map(|r| r.replace("\r\n", "\n").trim().to_string())
+        .filter(|r| !r.is_empty());
+    update_book(&app, &state, &book_id, |book| book.review = review)
+}
+
+/// Move a book to another shelf. Finishing a book records when; moving a
+/// finished book back to Reading starts a re-read, and earlier completions
+/// are kept.
+#[tauri::command]
+pub fn set_reading_state(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    reading_state: ReadingState,
+) -> Result<Book, String> {
+    update_book(&app, &state, &book_id, |book| {
+        if reading_state == book.reading_state {
+            return;
+        }
+        match reading_state {
+            ReadingState::Finished => mark_finished(book),
+            ReadingState::Reading => {
+                book.finished_at = None;
+                mark_started(book);
+            }
+            ReadingState::ToRead => {
+                book.finished_at = None;
+                book.started_at = None;
+                book.reading_state = ReadingState::ToRead;
+            }
+            ReadingState::Abandoned => {
+                book.finished_at = None;
+                book.reading_state = ReadingState::Abandoned;
+            }
+        }
+    })
+}
+
+/// set_reading_state, under the name the status API was asked for
+#[tauri::command]
+pub fn set_book_status(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    status: ReadingState,
+) -> Result<Book, String> {
+    set_reading_state(app, state, book_id, status)
+}
+
+/// Undo a book being finished, whether by hand or by reaching the end: the
+/// finished date and the completion it recorded are dropped, and the book
+/// goes back to Reading, or To Read if it was never started
+#[tauri::command]
+pub fn mark_unfinished(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<Book, String> {
+    update_book(&app, &state, &book_id, |book| {
+        if book.reading_state != ReadingState::Finished {
+            return;
+        }
+        if let Some(finished_at) = book.finished_at.take() {
+            book.completions.retain(|c| c.finished_at != finished_at);
+        }
+        book.reading_state = if book.progress > 0.0 {
+            ReadingState::Reading
+        } else {
+            ReadingState::ToRead
+        };
+    })
+}
+
+/// Take over the progress of a book's imported position, if it is further
+/// along. The reading position (CFI) is left alone.
+#[tauri::command]
+pub fn accept_imported_progress(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<Book, String> {
+    update_book(&app, &state, &book_id, |book| {
+        if let Some(imported) = &book.imported_position {
+            if imported.progress > book.progress {
+                let progress = imported.progress;
+                record_progress(book, progress);
+            }
+        }
+    })
+}
+
+/// Replace a book's reading overrides
+#[tauri::command]
+pub fn set_book_overrides(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    overrides: BookOverrides,
+) -> Result<Book, String> {
+    update_book(&app, &state, &book_id, |book| book.overrides = overrides)
+}
+
+/// Forget a book's imported position
+#[tauri::command]
+pub fn clear_imported_position(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<Book, String> {
+    update_book(&app, &state, &book_id, |book| book.imported_position = None)
+}
+
+/// Books whose title or author contains `query`, ignoring case and
+/// accents: titles starting with it first, then titles containing it, then
+/// authors. An empty query finds nothing.
+#[tauri::command]
+pub fn search_library(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<Book>, String> {
+    let query = authors::fold(query.trim());
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    state.with_library(|library| {
+        let mut matches: Vec<(u8, &Book)> = library
+            .books
+            .iter()
+            .filter_map(|book| {
+                let title = authors::fold(&book.title);
+                let rank = if title.starts_with(&query) {
+                    0
+                } else if title.contains(&query) {
+                    1
+                } else if authors::fold(&book.author).contains(&query) {
+                    2
+                } else {
+                    return None;
+                };
+                Some((rank, book))
+            })
+            .collect();
+        matches.sort_by(|(a_rank, a), (b_rank, b)| {
+            a_rank
+                .cmp(b_rank)
+                .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+        });
+        matches
+            .into_iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(_, book)| book.clone())
+            .collect()
+    })
+}
+
+/// Tags are the same whatever their case; the first spelling is kept
+fn tag_key(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+pub fn has_tag(tags: &[String], tag: &str) -> bool {
+    let key = tag_key(tag);
+    tags.iter().any(|t| tag_key(t) == key)
+}
+
+/// Add tags a book doesn't have yet, in any case
+pub fn add_tags(book: &mut Book, tags: impl IntoIterator<Item = String>) {
+    for tag in tags {
+        if !has_tag(&book.tags, &tag) {
+            book.tags.push(tag);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// Tag a book; a tag it already has in another case is left as it is
+#[tauri::command]
+pub fn add_tag(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    tag: String,
+) -> Result<Book, String> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ");
+    if tag.is_empty() {
+        return Err("Tag cannot be empty".to_string());
+    }
+    if tag.chars().count() > MAX_TAG_CHARS {
+        return Err(format!("A tag can be at most {} characters", MAX_TAG_CHARS));
+    }
+    update_book(&app, &state, &book_id, |book| add_tags(book, [tag]))
+}
+
+#[tauri::command]
+pub fn remove_tag(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    tag: String,
+) -> Result<Book, String> {
+    let key = tag_key(&tag);
+    update_book(&app, &state, &book_id, |book| {
+        book.tags.retain(|t| tag_key(t) != key)
+    })
+}
+
+/// Every tag in the library with how many books have it, most used first.
+/// Each tag is spelled as on the first book that has it.
+#[tauri::command]
+pub fn list_tags(state: State<'_, AppState>) -> Result<Vec<TagCount>, String> {
+    state.with_library(|library| {
+        let mut counts: Vec<TagCount> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for book in &library.books {
+            // A book tagged twice in different cases counts once
+            let mut seen = HashSet::new();
+            for tag in &book.tags {
+                let key = tag_key(tag);
+                if key.is_empty() || !seen.insert(key.clone()) {
+                    continue;
+                }
+                match index.get(&key) {
+                    Some(&at) => counts[at].count += 1,
+                    None => {
+                        index.insert(key, counts.len());
+                        counts.push(TagCount {
+                            tag: tag.trim().to_string(),
+                            count: 1,
+                        });
+                    }
+                }
+            }
+        }
+        counts.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.tag.to_lowercase().cmp(&b.tag.to_lowercase()))
+        });
+        counts
+    })
+}
+
+/// Books with a tag, in any case
+#[tauri::command]
+pub fn get_books_by_tag(state: State<'_, AppState>, tag: String) -> Result<Vec<Book>, String> {
+    state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .filter(|b| has_tag(&b.tags, &tag))
+            .cloned()
+            .collect()
+    })
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ChapterEntry {
+    #[serde(rename = "spineIndex")]
+    pub spine_index: usize,
+    pub href: String,
+    /// Label of the table of contents entry for this spine item, if any
+    pub title: Option<String>,
+    pub words: u64,
+    pub read: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ChapterReport {
+    pub chapters: Vec<ChapterEntry>,
+    /// Share of the book's words in chapters read
+    pub progress: f32,
+}
+
+/// Table of contents labels by the file they point to, first entry first
+fn toc_labels(points: &[epub::doc::NavPoint], labels: &mut Vec<(PathBuf, String)>) {
+    for point in points {
+        let file = point.content.to_string_lossy();
+        let file = file.split('#').next().unwrap_or_default();
+        labels.push((PathBuf::from(file), point.label.trim().to_string()));
+        toc_labels(&point.children, labels);
+    }
+}
+
+/// The spine with a label from the table of contents for each item
+pub fn spine_titles(path: &str) -> Option<Vec<(String, Option<String>)>> {
+    let doc = epub::doc::EpubDoc::new(path).ok()?;
+    let mut labels = Vec::new();
+    toc_labels(&doc.toc, &mut labels);
+    let spine = doc
+        .spine
+        .iter()
+        .map(|item| {
+            let file = doc
+                .resources
+                .get(&item.idref)
+                .map(|(file, _)| file.clone())
+                .unwrap_or_default();
+            let title = labels
+                .iter()
+                .find(|(target, _)| *target == file || doc.root_base.join(target) == file)
+                .map(|(_, label)| label.clone());
+            (file.to_string_lossy().replace('\\', "/"), title)
+        })
+        .collect();
+    Some(spine)
+}
+
+/// Mark a spine item as read through, or not. With chapter-weighted
+/// progress on, the book's progress follows.
+#[tauri::command]
+pub fn mark_chapter_read(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    spine_index: usize,
+    read: Option<bool>,
+) -> Result<Book, String> {
+    let existing = state
+        .with_library(|library| library.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book with id '{}' not found", book_id))?;
+    existing.require_text()?;
+
+    // Word counts are taken on the first chapter marked
+    let words = match &existing.chapters {
+        Some(chapters) if !chapters.words.is_empty() => chapters.words.clone(),
+        _ => crash::catch_panic("counting chapter words", || {
+            chapter_words(&existing.file_path)
+        })?
+        .ok_or_else(|| format!("Failed to read the chapters of '{}'", existing.title))?,
+    };
+    if spine_index >= words.len() {
+        return Err(format!(
+            "'{}' has {} chapters, not {}",
+            existing.title,
+            words.len(),
+            spine_index + 1
+        ));
+    }
+    let by_chapters = state.preferences()?.chapter_weighted_progress;
+
+    update_book(&app, &state, &book_id, |book| {
+        let chapters = book.chapters.get_or_insert_with(ChapterProgress::default);
+        chapters.words = words;
+        let mark = read.unwrap_or(true);
+        match (chapters.read.binary_search(&spine_index), mark) {
+            (Err(at), true) => chapters.read.insert(at, spine_index),
+            (Ok(at), false) => {
+                chapters.read.remove(at);
+            }
+            _ => {}
+        }
+        if by_chapters {
+            let progress = chapters.progress();
+            record_progress(book, progress);
+        }
+    })
+}
+
+/// Each spine item of a book with its table of contents label and whether
+/// it has been read through
+#[tauri::command]
+pub fn get_chapter_progress(
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<ChapterReport, String> {
+    let book = state
+        .with_library(|library| library.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book with id '{}' not found", book_id))?;
+    book.require_text()?;
+    let spine = crash::catch_panic("reading the table of contents", || {
+        spine_titles(&book.file_path)
+    })?
+    .ok_or_else(|| format!("Failed to read the chapters of '{}'", book.title))?;
+
+    let chapters = book.chapters.unwrap_or_default();
+    let entries = spine
+        .into_iter()
+        .enumerate()
+        .map(|(index, (href, title))| ChapterEntry {
+            spine_index: index,
+            href,
+            title,
+            words: chapters.words.get(index).copied().unwrap_or(0),
+            read: chapters.read.binary_search(&index).is_ok(),
+        })
+        .collect();
+    Ok(ChapterReport {
+        chapters: entries,
+        progress: chapters.progress(),
+    })
+}
+
+/// Where to resume a book in `mode` (the preferred mode by default). The
+/// other mode's position stands in, marked converted, when there is none
+/// for this mode; books saved before positions were kept per mode resume
+/// at their last CFI.
+#[tauri::command]
+pub fn get_book_progress(
+    state: State<'_, AppState>,
+    book_id: String,
+    mode: Option<ReadingMode>,
+) -> Result<Option<BookPosition>, String> {
+    let book_id = state.resolve_book_id(&book_id);
+    let book = state.with_library(|library| {
+        library.books.iter().find(|b| b.id == book_id).cloned()
+    })?;
+    let Some(book) = book else {
+        return Ok(None);
+    };
+    book.require_text()?;
+    let mode = match mode {
+        Some(mode) => mode,
+        None => ReadingMode::from_preference(&state.preferences()?.reading_mode),
+    };
+
+    let saved = [(mode, false), (mode.other(), true)]
+        .into_iter()
+        .find_map(|(m, converted)| Some((m, book.positions.get(m)?, converted)));
+    Ok(match saved {
+        Some((saved_mode, position, converted)) => Some(BookPosition {
+            cfi: position.cfi.clone(),
+            mode: Some(saved_mode),
+            // A scroll offset means nothing to a paginated renderer
+            scroll_pixels: position.scroll_pixels.filter(|_| !converted),
+            scroll_percent: position.scroll_percent.filter(|_| !converted),
+            converted,
+        }),
+        None => book.cfi.map(|cfi| BookPosition {
+            cfi,
+            mode: None,
+            scroll_pixels: None,
+            scroll_percent: None,
+            converted: false,
+        }),
+    })
+}
+
+/// Remove a book from the library
+#[tauri::command]
+pub fn remove_book(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<(), String> {
+    let book_id = state.resolve_book_id(&book_id);
+    state.update_library(|library| {
+        // Find and remove the book
+        let original_len = library.books.len();
+
+        // Delete cover file if it exists
+        if let Some(book) = library.books.iter().find(|b| b.id == book_id) {
+            if let Some(ref cover) = book.cover_path {
+                let _ = fs::remove_file(cover);
+            }
+            if let Some(ref thumbnail) = book.thumbnail_path {
+                let _ = fs::remove_file(thumbnail);
+            }
+        }
+
+        library.books.retain(|b| b.id != book_id);
+
+        if library.books.len() == original_len {
+            return Err(format!("Book with id '{}' not found", book_id));
+        }
+        Ok(())
+    })?;
+
+    if let Ok(mut covers) = state.covers.lock() {
+        covers.remove(&book_id);
+    }
+    view_state::remove(&state, &book_id);
+    emit_library_event(&app, LibraryEvent::Removed(book_id));
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MergeSummary {
+    pub added: usize,
+    pub updated: usize,
+}
+
+/// Merge `incoming` into `local` by book id.
+/// New books are added; for books on both sides the reading state of the
+/// more recently opened entry wins, while local paths and ratings are kept.
+pub fn merge_libraries(local: &mut Library, incoming: Library) -> MergeSummary {
+    let mut summary = MergeSummary::default();
+
+    for book in incoming.books {
+        // Books from before ids came from contents are found by them
+        let same = |b: &Book| {
+            b.id == book.id || (b.content_hash.is_some() && b.content_hash == book.content_hash)
+        };
+        match local.books.iter_mut().find(|b| same(b)) {
+            Some(existing) => {
+                if book.last_opened > existing.last_opened {
+                    existing.progress = book.progress;
+                    existing.cfi = book.cfi;
+                    existing.reading_location = book.reading_location;
+                    existing.last_opened = book.last_opened;
+                    existing.reading_state = book.reading_state;
+                    existing.finished_at = book.finished_at;
+                    existing.started_at = book.started_at;
+                    if let (Some(audio), Some(theirs)) = (existing.audio.as_mut(), &book.audio) {
+                        audio.position_secs = theirs.position_secs;
+                    }
+                    summary.updated += 1;
+                }
+                existing.positions.merge(book.positions);
+                // Completions from both sides, once each
+                for completion in book.completions {
+                    let known = existing
+                        .completions
+                        .iter()
+                        .any(|c| c.finished_at == completion.finished_at);
+                    if !known {
+                        existing.completions.push(completion);
+                    }
+                }
+                existing.completions.sort_by_key(|c| c.finished_at);
+                existing.first_opened = match (existing.first_opened, book.first_opened) {
+                    (Some(ours), Some(theirs)) => Some(ours.min(theirs)),
+                    (ours, theirs) => ours.or(theirs),
+                };
+                // Details only one side knows are kept
+                if existing.isbn.is_none() {
+                    existing.isbn = book.isbn;
+                }
+                if existing.word_count.is_none() {
+                    existing.word_count = book.word_count;
+                }
+                if existing.rating.is_none() {
+                    existing.rating = book.rating;
+                }
+                if existing.review.is_none() {
+                    existing.review = book.review;
+                }
+                if existing.description.is_none() {
+                    existing.description = book.description;
+                }
+                if existing.publish_year.is_none() {
+                    existing.publish_year = book.publish_year;
+                }
+                if existing.series.is_none() {
+                    existing.series = book.series;
+                    existing.series_index = book.series_index;
+                    existing.series_end = book.series_end;
+                }
+                if existing.language.is_none() {
+                    existing.language = book.language;
+                }
+                existing.favorite |= book.favorite;
+                existing.archived |= book.archived;
+                if existing.subjects.is_empty() {
+                    existing.subjects = book.subjects;
+                }
+                if existing.open_library_key.is_none() {
+                    existing.open_library_key = book.open_library_key;
+                }
+                if existing.imported_position.is_none() {
+                    existing.imported_position = book.imported_position;
+                }
+                if existing.content_hash.is_none() {
+                    existing.content_hash = book.content_hash;
+                }
+                match (existing.chapters.as_mut(), book.chapters) {
+                    (Some(ours), Some(theirs)) => {
+                        for index in theirs.read {
+                            if let Err(at) = ours.read.binary_search(&index) {
+                                ours.read.insert(at, index);
+                            }
+                        }
+                    }
+                    (None, theirs) => existing.chapters = theirs,
+                    _ => {}
+                }
+                add_tags(existing, book.tags);
+            }
+            None => {
+                local.books.push(book);
+                summary.added += 1;
+            }
+        }
+    }
+
+    // Smart shelves only one side has
+    for shelf in incoming.smart_shelves {
+        let known = local
+            .smart_shelves
+            .iter()
+            .any(|s| s.name.eq_ignore_ascii_case(&shelf.name));
+        if !known {
+            local.smart_shelves.push(shelf);
+        }
+    }
+    for (local, incoming) in [
+        (&mut local.tags, incoming.tags),
+        (&mut local.collections, incoming.collections),
+    ] {
+        for entry in incoming {
+            if !local.contains(&entry) {
+                local.push(entry);
+            }
+        }
+    }
+
+    summary
+}
+
+/// Read and parse a library file
+pub fn load_library(path: &Path) -> Result<Library, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read library: {}", e))?;
+
+    parse_library(&content).map_err(|e| format!("Failed to parse library: {}", e))
+}
+
+/// The copy of library.json taken before each save, when the library was
+/// kept there
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension("json.bak")
+}
+
+/// Load a library.json being moved into the database, recovering from a
+/// damaged file. When the backup of its last save reads, the damaged file is moved aside as
+/// library.json.corrupt-<timestamp> and the backup takes its place.
+/// Without one the file is left as it is and the error returned, so it is
+/// never replaced by an empty library.
+pub fn load_or_recover_library(path: &Path) -> Result<Library, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read library: {}", e))?;
+    let parse_error = match parse_library(&content) {
+        Ok(library) => return Ok(library),
+        Err(e) => e,
+    };
+    logging::error(&format!("Failed to parse library: {}", parse_error));
+
+    let backup = backup_path(path);
+    let library = load_library(&backup).map_err(|e| {
+        format!(
+            "The library file {} is damaged ({}) and its backup can't be used ({}). \
+             It was left untouched.",
+            path.display(),
+            parse_error,
+            e
+        )
+    })?;
+    let corrupt = path.with_extension(format!(
+        "json.corrupt-{}",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::rename(path, &corrupt)
+        .and_then(|_| fs::copy(&backup, path))
+        .map_err(|e| format!("Failed to restore the library from its backup: {}", e))?;
+    logging::warn(&format!(
+        "Restored the library from its backup; the damaged file was moved to {}",
+        corrupt.display()
+    ));
+    Ok(library)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::preflight::{self, PathKind};
+    use crate::state::testing::{book, epub, temp_dir};
+
+    #[test]
+    fn books_are_found_by_an_id_they_had_before() {
+        let dir = temp_dir("library-alias");
+        let state = AppState::in_dir(&dir);
+        state
+            .update_library(|library| {
+                library.books.push(book("old"));
+                Ok(())
+            })
+            .unwrap();
+        let renames = HashMap::from([("old".to_string(), "new".to_string())]);
+        state
+            .rename_books(&renames, |library| {
+                library.books[0].id = "new".to_string();
+                Ok(())
+            })
+            .unwrap();
+
+        let updated = change_book(&state, "old", |book| book.favorite = true).unwrap();
+        assert_eq!(updated.id, "new");
+        assert!(updated.favorite);
+        state
+            .update_book_in_memory("old", |book| {
+                book.rating = Some(4);
+                Ok(())
+            })
+            .unwrap();
+        let rating = state
+            .with_library(|library| library.books[0].rating)
+            .unwrap();
+        assert_eq!(rating, Some(4));
+    }
+
+    #[test]
+    fn reopening_keeps_a_downloaded_cover() {
+        let dir = temp_dir("library-remote-cover");
+        let state = AppState::in_dir(&dir);
+        let path = dir.join("dune.epub").to_string_lossy().to_string();
+        fs::write(&path, b"epub").unwrap();
+        let remote = dir.join("remote.jpg").to_string_lossy().to_string();
+        let extracted = dir.join("extracted.jpg").to_string_lossy().to_string();
+        fs::write(&extracted, b"cover").unwrap();
+
+        let mut dune = book("a");
+        dune.file_path = path.clone();
+        dune.cover_path = Some(remote.clone());
+        dune.cover_source = CoverSource::Remote;
+        state
+            .update_library(|library| {
+                library.books.push(dune);
+                Ok(())
+            })
+            .unwrap();
+        // A cover extracted earlier this session
+        state
+            .covers
+            .lock()
+            .unwrap()
+            .insert("a".to_string(), extracted);
+
+        let names = BookNames::Epub {
+            title: "Dune".to_string(),
+            author: String::new(),
+        };
+        let event = import_book(&state, names, path).unwrap();
+        let LibraryEvent::Updated(reopened) = event else {
+            panic!("expected the book to be updated");
+        };
+        assert_eq!(reopened.cover_path, Some(remote));
+        assert_eq!(reopened.cover_source, CoverSource::Remote);
+    }
+
+    #[test]
+    fn given_names_come_before_or_after_the_epub_ones() {
+        let epub = || (Some("Dune".to_string()), None);
+        let names = BookNames::Epub {
+            title: "dune_final".to_string(),
+            author: "Unknown Author".to_string(),
+        };
+        assert_eq!(
+            names.resolve("/books/dune.epub", epub()),
+            ("Dune".to_string(), "Unknown Author".to_string())
+        );
+        let names = BookNames::Given {
+            title: Some("Dune (Deluxe)".to_string()),
+            author: None,
+        };
+        assert_eq!(
+            names.resolve("/books/dune.epub", epub()),
+            ("Dune (Deluxe)".to_string(), "Unknown Author".to_string())
+        );
+        let names = BookNames::Given {
+            title: None,
+            author: Some("Frank Herbert".to_string()),
+        };
+        assert_eq!(
+            names.resolve("/books/dune.epub", (None, None)),
+            ("dune".to_string(), "Frank Herbert".to_string())
+        );
+    }
+
+    #[test]
+    fn a_copy_from_another_folder_extracts_no_cover() {
+        let dir = temp_dir("library-duplicate");
+        let state = AppState::in_dir(&dir);
+        let first = epub(&dir.join("dune.epub"), "Dune", "");
+        let moved = dir.join("moved");
+        fs::create_dir_all(&moved).unwrap();
+        let copy = moved.join("dune.epub").to_string_lossy().to_string();
+        fs::copy(&first, &copy).unwrap();
+
+        let cover = dir.join("cover.jpg").to_string_lossy().to_string();
+        let mut dune = book("a");
+        dune.file_path = first;
+        dune.content_hash = file_hash(Path::new(&copy));
+        dune.cover_path = Some(cover.clone());
+        state
+            .update_library(|library| {
+                library.books.push(dune);
+                Ok(())
+            })
+            .unwrap();
+
+        let names = BookNames::Epub {
+            title: String::new(),
+            author: String::new(),
+        };
+        let event = import_book(&state, names, copy).unwrap();
+        let LibraryEvent::Updated(same) = event else {
+            panic!("expected the book on the shelf to be updated");
+        };
+        assert_eq!(same.id, "a");
+        assert_eq!(same.cover_path, Some(cover));
+        // Nothing left behind under the copy's path id
+        let covers = state.paths().unwrap().covers.clone();
+        assert_eq!(fs::read_dir(covers).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn init_and_import_use_the_same_covers_directory() {
+        temp_dir("library-covers");
+        let state = AppState::new();
+        let paths = state.paths().unwrap();
+        config::create_app_dirs(paths).unwrap();
+        assert!(paths.covers.is_dir());
+
+        let import_dir = covers_dir(&state).unwrap();
+        assert_eq!(import_dir, paths.covers);
+        assert_eq!(config::get_covers_dir().unwrap(), import_dir);
+        // The directory probed before a cover is written is the one it goes to
+        assert_eq!(preflight::dir_for(PathKind::Covers).unwrap(), import_dir);
+
+        let cover = save_cover(&import_dir, "a", b"cover", "image/png").expect("cover saved");
+        assert_eq!(Path::new(&cover).parent(), Some(paths.covers.as_path()));
+        assert!(!paths.app_dir.join("covers").exists());
+    }
 }
@@ -0,0 +1,763 @@
+/**
+ * The library in SQLite: a row per book, so a change to one book writes
+ * that row instead of the whole library. library.json, where the library
+ * was kept before, is imported on first use and kept as library.json.bak.
+ * The ids books had before they were renamed are kept as aliases, and
+ * each book's progress over time and the days read beside it.
+ */
+use chrono::{NaiveDate, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::config;
+use crate::library::{self, Book, Library};
+use crate::logging;
+use crate::preflight::{self, PathKind};
+
+/// Stored in the database's user_version; 0 is a database not set up yet.
+/// Version 2 added book_aliases and migrations, version 3
+/// progress_history, version 4 reading_days, version 5 library_lists.
+const SCHEMA_VERSION: i64 = 5;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS books (
+    id TEXT PRIMARY KEY,
+    -- Place on the shelf, the order books were added in
+    position INTEGER NOT NULL,
+    -- The book as JSON, as it was kept in library.json
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS smart_shelves (
+    position INTEGER PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS book_aliases (
+    -- An id a book had before, still accepted for it
+    old_id TEXT PRIMARY KEY,
+    book_id TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS migrations (
+    name TEXT PRIMARY KEY,
+    done_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS progress_history (
+    book_id TEXT NOT NULL,
+    -- Unix seconds
+    at INTEGER NOT NULL,
+    -- From 0 to 1
+    progress REAL NOT NULL,
+    PRIMARY KEY (book_id, at)
+);
+CREATE TABLE IF NOT EXISTS reading_days (
+    -- Local date, YYYY-MM-DD
+    day TEXT PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS library_lists (
+    -- tags or collections, the lists library.json always had
+    name TEXT PRIMARY KEY,
+    -- The list as JSON
+    data TEXT NOT NULL
+);
+";
+
+fn db_error(e: rusqlite::Error) -> String {
+    format!("Library database error: {}", e)
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| format!("Failed to serialize library: {}", e))
+}
+
+/// Open the library database, creating it and importing `legacy_json` the
+/// first time. In safe mode nothing is written: an existing database is
+/// opened read-only, and otherwise the library is read into memory.
+pub fn open(db_path: &Path, legacy_json: &Path) -> Result<Connection, String> {
+    let safe_mode = config::is_safe_mode();
+    let mut conn = if !safe_mode {
+        Connection::open(db_path).map_err(db_error)?
+    } else if db_path.exists() {
+        return Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(db_error);
+    } else {
+        Connection::open_in_memory().map_err(db_error)?
+    };
+
+    let version: i64 = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(db_error)?;
+    if version >= SCHEMA_VERSION {
+        return Ok(conn);
+    }
+
+    // A damaged library.json that can't be recovered stops the migration,
+    // so it is tried again rather than starting from an empty library.
+    // Databases set up before only need the new tables.
+    let library = match version {
+        0 if legacy_json.exists() => Some(library::load_or_recover_library(legacy_json)?),
+        0 => Some(Library::default()),
+        _ => None,
+    };
+    let tx = conn.transaction().map_err(db_error)?;
+    tx.execute_batch(SCHEMA).map_err(db_error)?;
+    if let Some(library) = &library {
+        write_all(&tx, library)?;
+    } else {
+        // Databases made from library.json before the lists were kept get
+        // them from the copy of it left behind
+        let kept = legacy_json.with_extension("json.bak");
+        if let Ok(old) = library::load_library(&kept) {
+            write_lists(&tx, &old)?;
+        }
+    }
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)
+        .map_err(db_error)?;
+    tx.commit().map_err(db_error)?;
+
+    let Some(library) = library else {
+        return Ok(conn);
+    };
+    if legacy_json.exists() && !safe_mode {
+        logging::info(&format!(
+            "Moved {} books from library.json into the library database",
+            library.books.len()
+        ));
+        if let Err(e) = fs::rename(legacy_json, legacy_json.with_extension("json.bak")) {
+            logging::warn(&format!("Failed to keep library.json as a backup: {}", e));
+        }
+    }
+    Ok(conn)
+}
+
+/// Read the whole library. A book row that no longer parses is left in the
+/// database and logged rather than failing the library.
+pub fn load(conn: &Connection) -> Result<Library, String> {
+    let mut library = Library::default();
+
+    let mut books = conn
+        .prepare("SELECT id, data FROM books ORDER BY position")
+        .map_err(db_error)?;
+    let rows = books
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(db_error)?;
+    for row in rows {
+        let (id, data) = row.map_err(db_error)?;
+        match library::parse_book(&data) {
+            Ok(book) => library.books.push(book),
+            Err(e) => logging::error(&format!("Skipped unreadable book '{}': {}", id, e)),
+        }
+    }
+
+    let mut shelves = conn
+        .prepare("SELECT data FROM smart_shelves ORDER BY position")
+        .map_err(db_error)?;
+    let rows = shelves
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(db_error)?;
+    for data in rows {
+        let data = data.map_err(db_error)?;
+        match serde_json::from_str(&data) {
+            Ok(shelf) => library.smart_shelves.push(shelf),
+            Err(e) => logging::error(&format!("Skipped unreadable smart shelf: {}", e)),
+        }
+    }
+    drop(shelves);
+
+    let mut lists = conn
+        .prepare("SELECT name, data FROM library_lists")
+        .map_err(db_error)?;
+    let rows = lists
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(db_error)?;
+    for row in rows {
+        let (name, data) = row.map_err(db_error)?;
+        let list = match name.as_str() {
+            "tags" => &mut library.tags,
+            "collections" => &mut library.collections,
+            _ => continue,
+        };
+        match serde_json::from_str(&data) {
+            Ok(entries) => *list = entries,
+            Err(e) => logging::error(&format!("Skipped unreadable library {}: {}", name, e)),
+        }
+    }
+
+    Ok(library)
+}
+
+/// The library is written the way library.json was: not in safe mode, and
+/// only once the data directory is known to take it
+fn check_writable() -> Result<(), String> {
+    if config::is_safe_mode() {
+        return Err(config::safe_mode_error().into());
+    }
+    preflight::check_writable(PathKind::Library)?;
+    Ok(())
+}
+
+fn write_shelves(conn: &Connection, library: &Library) -> Result<(), String> {
+    conn.execute("DELETE FROM smart_shelves", [])
+        .map_err(db_error)?;
+    let mut insert = conn
+        .prepare_cached("INSERT INTO smart_shelves (position, data) VALUES (?1, ?2)")
+        .map_err(db_error)?;
+    for (position, shelf) in library.smart_shelves.iter().enumerate() {
+        insert
+            .execute(params![position as i64, to_json(shelf)?])
+            .map_err(db_error)?;
+    }
+    Ok(())
+}
+
+fn write_lists(conn: &Connection, library: &Library) -> Result<(), String> {
+    let mut upsert = conn
+        .prepare_cached("INSERT OR REPLACE INTO library_lists (name, data) VALUES (?1, ?2)")
+        .map_err(db_error)?;
+    for (name, list) in [
+        ("tags", &library.tags),
+        ("collections", &library.collections),
+    ] {
+        upsert
+            .execute(params![name, to_json(list)?])
+            .map_err(db_error)?;
+    }
+    Ok(())
+}
+
+/// Replace everything stored with `library`
+fn write_all(conn: &Connection, library: &Library) -> Result<(), String> {
+    conn.execute("DELETE FROM books", []).map_err(db_error)?;
+    let mut insert = conn
+        // A book listed twice in an old library.json is kept once
+        .prepare_cached("INSERT OR REPLACE INTO books (id, position, data) VALUES (?1, ?2, ?3)")
+        .map_err(db_error)?;
+    for (position, book) in library.books.iter().enumerate() {
+        insert
+            .execute(params![book.id, position as i64, to_json(book)?])
+            .map_err(db_error)?;
+    }
+    conn.execute(
+        "DELETE FROM progress_history WHERE book_id NOT IN (SELECT id FROM books)",
+        [],
+    )
+    .map_err(db_error)?;
+    write_shelves(conn, library)?;
+    write_lists(conn, library)
+}
+
+/// Replace the stored library, e.g. with one restored from a backup
+pub fn replace(conn: &mut Connection, library: &Library) -> Result<(), String> {
+    check_writable()?;
+    let tx = conn.transaction().map_err(db_error)?;
+    write_all(&tx, library)?;
+    tx.commit().map_err(db_error)
+}
+
+/// Write the rows of books added, changed or moved between `before` and
+/// `after`, of books removed, and of the `dirty` books changed in memory
+/// before
+fn write_changes(
+    conn: &Connection,
+    before: &Library,
+    after: &Library,
+    dirty: &HashSet<String>,
+) -> Result<(), String> {
+    let old: HashMap<&str, (usize, &Book)> = before
+        .books
+        .iter()
+        .enumerate()
+        .map(|(position, book)| (book.id.as_str(), (position, book)))
+        .collect();
+
+    {
+        let mut upsert = conn
+            .prepare_cached(
+                "INSERT INTO books (id, position, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(id) DO UPDATE SET position = excluded.position, data = excluded.data",
+            )
+            .map_err(db_error)?;
+        for (position, book) in after.books.iter().enumerate() {
+            let data = to_json(book)?;
+            let unchanged = !dirty.contains(&book.id)
+                && old.get(book.id.as_str()).is_some_and(|(was_at, was)| {
+                    *was_at == position && to_json(*was).is_ok_and(|old_data| old_data == data)
+                });
+            if !unchanged {
+                upsert
+                    .execute(params![book.id, position as i64, data])
+                    .map_err(db_error)?;
+            }
+        }
+
+        let kept: HashSet<&str> = after.books.iter().map(|b| b.id.as_str()).collect();
+        let mut delete = conn
+            .prepare_cached("DELETE FROM books WHERE id = ?1")
+            .map_err(db_error)?;
+        let mut delete_history = conn
+            .prepare_cached("DELETE FROM progress_history WHERE book_id = ?1")
+            .map_err(db_error)?;
+        for id in old.keys().filter(|id| !kept.contains(*id)) {
+            delete.execute([id]).map_err(db_error)?;
+            delete_history.execute([id]).map_err(db_error)?;
+        }
+    }
+    if to_json(&before.smart_shelves)? != to_json(&after.smart_shelves)? {
+        write_shelves(conn, after)?;
+    }
+    if before.tags != after.tags || before.collections != after.collections {
+        write_lists(conn, after)?;
+    }
+    Ok(())
+}
+
+/// Write what changed between `before` and `after` in one transaction
+pub fn save_changes(
+    conn: &mut Connection,
+    before: &Library,
+    after: &Library,
+    dirty: &HashSet<String>,
+) -> Result<(), String> {
+    check_writable()?;
+    let tx = conn.transaction().map_err(db_error)?;
+    write_changes(&tx, before, after, dirty)?;
+    tx.commit().map_err(db_error)
+}
+
+/// Like save_changes, for books given new ids: `renames` maps each old id
+/// to the new one, and is kept as aliases in the same transaction. The
+/// books' progress history moves along.
+pub fn save_renamed(
+    conn: &mut Connection,
+    before: &Library,
+    after: &Library,
+    dirty: &HashSet<String>,
+    renames: &HashMap<String, String>,
+) -> Result<(), String> {
+    check_writable()?;
+    let tx = conn.transaction().map_err(db_error)?;
+    for (old_id, new_id) in renames {
+        tx.execute(
+            "UPDATE progress_history SET book_id = ?2 WHERE book_id = ?1",
+            params![old_id, new_id],
+        )
+        .map_err(db_error)?;
+    }
+    write_changes(&tx, before, after, dirty)?;
+    for (old_id, new_id) in renames {
+        // Aliases of the old id move along, so every id resolves in one step
+        tx.execute(
+            "UPDATE book_aliases SET book_id = ?2 WHERE book_id = ?1",
+            params![old_id, new_id],
+        )
+        .map_err(db_error)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO book_aliases (old_id, book_id) VALUES (?1, ?2)",
+            params![old_id, new_id],
+        )
+        .map_err(db_error)?;
+    }
+    tx.commit().map_err(db_error)
+}
+
+/// The id of the book `old_id` was an id of, if it was renamed
+pub fn resolve_alias(conn: &mut Connection, old_id: &str) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT book_id FROM book_aliases WHERE old_id = ?1",
+        [old_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(db_error)
+}
+
+/// Whether the one-off migration `name` has run on this library
+pub fn has_migrated(conn: &mut Connection, name: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM migrations WHERE name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+    .map_err(db_error)
+}
+
+/// Record that the one-off migration `name` has run
+pub fn record_migration(conn: &mut Connection, name: &str) -> Result<(), String> {
+    check_writable()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO migrations (name, done_at) VALUES (?1, ?2)",
+        params![name, Utc::now().to_rfc3339()],
+    )
+    .map_err(db_error)?;
+    Ok(())
+}
+
+/// Write the rows of books changed in place, such as by reading progress:
+/// an UPDATE per book
+pub fn save_books(conn: &mut Connection, books: &[&Book]) -> Result<(), String> {
+    check_writable()?;
+    let tx = conn.transaction().map_err(db_error)?;
+    {
+        let mut update = tx
+            .prepare_cached("UPDATE books SET data = ?2 WHERE id = ?1")
+            .map_err(db_error)?;
+        for book in books {
+            update
+                .execute(params![book.id, to_json(*book)?])
+                .map_err(db_error)?;
+        }
+    }
+    tx.commit().map_err(db_error)
+}
+
+/// Add a point to a book's progress history. A point less than
+/// `interval_secs` after the latest one replaces its progress instead, so
+/// a book gets at most one point per interval however long it is read.
+pub fn add_progress_point(
+    conn: &mut Connection,
+    book_id: &str,
+    at: i64,
+    progress: f32,
+    interval_secs: i64,
+) -> Result<(), String> {
+    check_writable()?;
+    let latest: Option<(i64, f32)> = conn
+        .query_row(
+            "SELECT at, progress FROM progress_history WHERE book_id = ?1
+             ORDER BY at DESC LIMIT 1",
+            [book_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(db_error)?;
+    match latest {
+        Some((_, latest_progress)) if latest_progress == progress => {}
+        Some((latest_at, _)) if at - latest_at < interval_secs => {
+            conn.execute(
+                "UPDATE progress_history SET progress = ?3 WHERE book_id = ?1 AND at = ?2",
+                params![book_id, latest_at, progress],
+            )
+            .map_err(db_error)?;
+        }
+        _ => {
+            conn.execute(
+                "INSERT OR REPLACE INTO progress_history (book_id, at, progress) VALUES (?1, ?2, ?3)",
+                params![book_id, at, progress],
+            )
+            .map_err(db_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// A book's progress history, oldest first, as (unix seconds, progress)
+pub fn progress_history(conn: &mut Connection, book_id: &str) -> Result<Vec<(i64, f32)>, String> {
+    let mut points = conn
+        .prepare("SELECT at, progress FROM progress_history WHERE book_id = ?1 ORDER BY at")
+        .map_err(db_error)?;
+    let rows = points
+        .query_map([book_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(db_error)?;
+    rows.collect::<Result<_, _>>().map_err(db_error)
+}
+
+/// What a backup carries of the database besides the books and shelves
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct LibraryExtras {
+    /// By book id, as (unix seconds, progress)
+    #[serde(rename = "progressHistory", default)]
+    pub progress_history: HashMap<String, Vec<(i64, f32)>>,
+    #[serde(rename = "readingDays", default)]
+    pub reading_days: Vec<NaiveDate>,
+    /// Old id -> id of the book it now names
+    #[serde(rename = "bookAliases", default)]
+    pub book_aliases: HashMap<String, String>,
+}
+
+/// Everything that goes into a backup's library_extras.json
+pub fn export_extras(conn: &mut Connection) -> Result<LibraryExtras, String> {
+    let mut extras = LibraryExtras::default();
+    let mut points = conn
+        .prepare("SELECT book_id, at, progress FROM progress_history ORDER BY book_id, at")
+        .map_err(db_error)?;
+    let rows = points
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?))
+        })
+        .map_err(db_error)?;
+    for row in rows {
+        let (book_id, at, progress) = row.map_err(db_error)?;
+        extras
+            .progress_history
+            .entry(book_id)
+            .or_default()
+            .push((at, progress));
+    }
+    drop(points);
+    extras.reading_days = reading_days(conn)?;
+    extras.reading_days.sort();
+    let mut aliases = conn
+        .prepare("SELECT old_id, book_id FROM book_aliases")
+        .map_err(db_error)?;
+    extras.book_aliases = aliases
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(db_error)?
+        .collect::<Result<_, _>>()
+        .map_err(db_error)?;
+    drop(aliases);
+    Ok(extras)
+}
+
+/// Restore a backup's extras over what is here (`replace`) or beside it.
+/// Only the history and aliases of books in the library are kept.
+pub fn import_extras(
+    conn: &mut Connection,
+    extras: &LibraryExtras,
+    replace: bool,
+) -> Result<(), String> {
+    check_writable()?;
+    let tx = conn.transaction().map_err(db_error)?;
+    if replace {
+        tx.execute("DELETE FROM progress_history", [])
+            .map_err(db_error)?;
+        tx.execute("DELETE FROM reading_days", [])
+            .map_err(db_error)?;
+        tx.execute("DELETE FROM book_aliases", [])
+            .map_err(db_error)?;
+    }
+    {
+        let mut insert = tx
+            .prepare_cached(
+                "INSERT OR IGNORE INTO progress_history (book_id, at, progress)
+                 SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM books WHERE id = ?1)",
+            )
+            .map_err(db_error)?;
+        for (book_id, points) in &extras.progress_history {
+            for (at, progress) in points {
+                insert
+                    .execute(params![book_id, at, progress])
+                    .map_err(db_error)?;
+            }
+        }
+        let mut insert = tx
+            .prepare_cached("INSERT OR IGNORE INTO reading_days (day) VALUES (?1)")
+            .map_err(db_error)?;
+        for day in &extras.reading_days {
+            insert
+                .execute([day.format("%Y-%m-%d").to_string()])
+                .map_err(db_error)?;
+        }
+        // A book's id here wins over an alias of it from the backup
+        let mut insert = tx
+            .prepare_cached(
+                "INSERT OR REPLACE INTO book_aliases (old_id, book_id)
+                 SELECT ?1, ?2 WHERE EXISTS (SELECT 1 FROM books WHERE id = ?2)
+                   AND NOT EXISTS (SELECT 1 FROM books WHERE id = ?1)",
+            )
+            .map_err(db_error)?;
+        for (old_id, book_id) in &extras.book_aliases {
+            insert.execute([old_id, book_id]).map_err(db_error)?;
+        }
+    }
+    tx.commit().map_err(db_error)
+}
+
+/// Remember `day` as a day with some reading
+pub fn add_reading_day(conn: &mut Connection, day: NaiveDate) -> Result<(), String> {
+    check_writable()?;
+    conn.execute(
+        "INSERT OR IGNORE INTO reading_days (day) VALUES (?1)",
+        [day.format("%Y-%m-%d").to_string()],
+    )
+    .map_err(db_error)?;
+    Ok(())
+}
+
+/// Every day remembered with some reading. Unparsable rows are skipped.
+pub fn reading_days(conn: &mut Connection) -> Result<Vec<NaiveDate>, String> {
+    let mut days = conn
+        .prepare("SELECT day FROM reading_days")
+        .map_err(db_error)?;
+    let rows = days
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(db_error)?;
+    let mut parsed = Vec::new();
+    for day in rows {
+        if let Ok(day) = NaiveDate::parse_from_str(&day.map_err(db_error)?, "%Y-%m-%d") {
+            parsed.push(day);
+        }
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::ReadingState;
+    use crate::state::testing::{book, temp_dir};
+
+    fn open_in(dir: &Path) -> Connection {
+        open(&dir.join("library.db"), &dir.join("library.json")).unwrap()
+    }
+
+    fn library_of(ids: &[&str]) -> Library {
+        Library {
+            books: ids.iter().map(|id| book(id)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn progress_points_within_the_interval_update_the_latest() {
+        let dir = temp_dir("library_db_points");
+        let mut conn = open_in(&dir);
+        replace(&mut conn, &library_of(&["a"])).unwrap();
+        add_progress_point(&mut conn, "a", 1_000, 0.1, 600).unwrap();
+        add_progress_point(&mut conn, "a", 1_300, 0.2, 600).unwrap();
+        add_progress_point(&mut conn, "a", 2_000, 0.3, 600).unwrap();
+        assert_eq!(
+            progress_history(&mut conn, "a").unwrap(),
+            vec![(1_000, 0.2), (2_000, 0.3)]
+        );
+    }
+
+    #[test]
+    fn extras_round_trip_through_a_backup() {
+        let dir = temp_dir("library_db_extras");
+        let mut conn = open_in(&dir);
+        replace(&mut conn, &library_of(&["a", "b"])).unwrap();
+        add_progress_point(&mut conn, "a", 1_000, 0.1, 600).unwrap();
+        add_progress_point(&mut conn, "a", 2_000, 0.2, 600).unwrap();
+        add_progress_point(&mut conn, "b", 1_000, 0.5, 600).unwrap();
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 3, d).unwrap();
+        add_reading_day(&mut conn, day(2)).unwrap();
+        add_reading_day(&mut conn, day(1)).unwrap();
+        let renames = HashMap::from([
+            ("old-a".to_string(), "a".to_string()),
+            ("old-b".to_string(), "b".to_string()),
+        ]);
+        let library = library_of(&["a", "b"]);
+        save_renamed(&mut conn, &library, &library, &HashSet::new(), &renames).unwrap();
+        let json = serde_json::to_vec(&export_extras(&mut conn).unwrap()).unwrap();
+
+        // Restored into a library without book b
+        let restored_dir = temp_dir("library_db_extras_restored");
+        let mut restored = open_in(&restored_dir);
+        replace(&mut restored, &library_of(&["a"])).unwrap();
+        add_progress_point(&mut restored, "a", 500, 0.05, 600).unwrap();
+        add_reading_day(&mut restored, day(20)).unwrap();
+        let extras: LibraryExtras = serde_json::from_slice(&json).unwrap();
+        import_extras(&mut restored, &extras, true).unwrap();
+        assert_eq!(
+            progress_history(&mut restored, "a").unwrap(),
+            vec![(1_000, 0.1), (2_000, 0.2)]
+        );
+        assert!(progress_history(&mut restored, "b").unwrap().is_empty());
+        let mut days = reading_days(&mut restored).unwrap();
+        days.sort();
+        assert_eq!(days, vec![day(1), day(2)]);
+        assert_eq!(
+            resolve_alias(&mut restored, "old-a").unwrap().as_deref(),
+            Some("a")
+        );
+        assert_eq!(resolve_alias(&mut restored, "old-b").unwrap(), None);
+    }
+
+    #[test]
+    fn merged_extras_keep_local_points() {
+        let dir = temp_dir("library_db_extras_merge");
+        let mut conn = open_in(&dir);
+        replace(&mut conn, &library_of(&["a"])).unwrap();
+        add_progress_point(&mut conn, "a", 500, 0.05, 600).unwrap();
+        let extras = LibraryExtras {
+            progress_history: HashMap::from([("a".to_string(), vec![(1_000, 0.1)])]),
+            ..Default::default()
+        };
+        import_extras(&mut conn, &extras, false).unwrap();
+        assert_eq!(
+            progress_history(&mut conn, "a").unwrap(),
+            vec![(500, 0.05), (1_000, 0.1)]
+        );
+    }
+
+    #[test]
+    fn tags_and_collections_are_kept() {
+        let dir = temp_dir("library_db_lists");
+        let mut conn = open_in(&dir);
+        let mut library = library_of(&["a"]);
+        library.tags = vec![serde_json::json!("fiction")];
+        library.collections = vec![serde_json::json!({"name": "Summer", "books": ["a"]})];
+        replace(&mut conn, &library).unwrap();
+        let loaded = load(&conn).unwrap();
+        assert_eq!(loaded.tags, library.tags);
+        assert_eq!(loaded.collections, library.collections);
+
+        let mut after = loaded.clone();
+        after.tags.push(serde_json::json!("poetry"));
+        save_changes(&mut conn, &loaded, &after, &HashSet::new()).unwrap();
+        assert_eq!(load(&conn).unwrap().tags, after.tags);
+    }
+
+    #[test]
+    fn upgraded_databases_get_the_lists_from_the_old_library_json() {
+        let dir = temp_dir("library_db_lists_upgrade");
+        let conn = open_in(&dir);
+        conn.pragma_update(None, "user_version", 4).unwrap();
+        drop(conn);
+        fs::write(
+            dir.join("library.json.bak"),
+            r#"{"books": [], "tags": ["fiction"], "collections": []}"#,
+        )
+        .unwrap();
+        let conn = open_in(&dir);
+        assert_eq!(
+            load(&conn).unwrap().tags,
+            vec![serde_json::json!("fiction")]
+        );
+    }
+
+    #[test]
+    fn books_saved_before_shelves_go_on_the_one_their_progress_gives() {
+        let dir = temp_dir("library_db_legacy_states");
+        let conn = open_in(&dir);
+        for (position, (id, progress)) in [("new", 0.0), ("started", 0.4), ("done", 1.0)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut legacy = serde_json::to_value(book(id)).unwrap();
+            legacy["progress"] = serde_json::json!(progress);
+            legacy.as_object_mut().unwrap().remove("readingState");
+            conn.execute(
+                "INSERT INTO books (id, position, data) VALUES (?1, ?2, ?3)",
+                params![id, position as i64, legacy.to_string()],
+            )
+            .unwrap();
+        }
+        let states: Vec<_> = load(&conn)
+            .unwrap()
+            .books
+            .into_iter()
+            .map(|b| b.reading_state)
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ReadingState::ToRead,
+                ReadingState::Reading,
+                ReadingState::Finished
+            ]
+        );
+    }
+
+    #[test]
+    fn old_backups_without_extras_still_parse() {
+        let extras: LibraryExtras = serde_json::from_str("{}").unwrap();
+        assert!(extras.progress_history.is_empty());
+        assert!(extras.reading_days.is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+/**
+ * The library as a CSV or JSON file, for spreadsheets and scripts
+ */
+use serde_json::{Map, Value};
+use std::fs;
+use tauri::State;
+
+use crate::goodreads::escape_csv;
+use crate::library::Book;
+use crate::state::AppState;
+
+/// Every column, in the order they are written when no fields are picked
+const FIELDS: &[&str] = &[
+    "title",
+    "author",
+    "progress",
+    "status",
+    "rating",
+    "tags",
+    "lastOpened",
+    "filePath",
+];
+
+fn field_value(book: &Book, field: &str) -> Value {
+    match field {
+        "title" => Value::from(book.title.clone()),
+        "author" => Value::from(book.author.clone()),
+        // From 0 to 1, without the float noise of an f32
+        "progress" => Value::from((book.progress as f64 * 10_000.0).round() / 10_000.0),
+        "status" => serde_json::to_value(book.reading_state).unwrap_or(Value::Null),
+        "rating" => book.rating.map(Value::from).unwrap_or(Value::Null),
+        "tags" => Value::from(book.tags.clone()),
+        "lastOpened" => Value::from(book.last_opened.to_rfc3339()),
+        "filePath" => Value::from(book.file_path.clone()),
+        _ => Value::Null,
+    }
+}
+
+/// Tags go into one cell, separated by semicolons
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => escape_csv(s),
+        Value::Array(items) => {
+            let items: Vec<&str> = items.iter().filter_map(Value::as_str).collect();
+            escape_csv(&items.join("; "))
+        }
+        other => other.to_string(),
+    }
+}
+
+fn to_csv(books: &[Book], fields: &[&str]) -> String {
+    let mut csv = fields.join(",");
+    csv.push('\n');
+    for book in books {
+        let row: Vec<String> = fields
+            .iter()
+            .map(|field| csv_cell(&field_value(book, field)))
+            .collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn to_json(books: &[Book], fields: &[&str]) -> Result<String, String> {
+    let rows: Vec<Map<String, Value>> = books
+        .iter()
+        .map(|book| {
+            fields
+                .iter()
+                .map(|field| (field.to_string(), field_value(book, field)))
+                .collect()
+        })
+        .collect();
+    serde_json::to_string_pretty(&rows).map_err(|e| format!("Failed to serialize library: {}", e))
+}
+
+/// Write the library to `dest_path` as "csv" or "json", one row per book,
+/// and return the rows written. `fields` picks the columns, e.g.
+/// `["title", "rating"]`; every column when none are given. Dates are
+/// ISO 8601.
+#[tauri::command]
+pub fn export_library(
+    state: State<'_, AppState>,
+    format: String,
+    dest_path: String,
+    fields: Option<Vec<String>>,
+) -> Result<usize, String> {
+    let fields: Vec<&str> = match &fields {
+        Some(picked) if !picked.is_empty() => picked
+            .iter()
+            .map(|field| {
+                FIELDS
+                    .iter()
+                    .find(|known| known.eq_ignore_ascii_case(field.trim()))
+                    .copied()
+                    .ok_or_else(|| format!("Unknown export field: {}", field))
+            })
+            .collect::<Result<_, _>>()?,
+        _ => FIELDS.to_vec(),
+    };
+    let books = state.with_library(|library| library.books.clone())?;
+
+    let content = match format.to_lowercase().as_str() {
+        "csv" => to_csv(&books, &fields),
+        "json" => to_json(&books, &fields)?,
+        _ => return Err(format!("Unsupported export format: {}", format)),
+    };
+    fs::write(&dest_path, content).map_err(|e| format!("Failed to write library export: {}", e))?;
+    Ok(books.len())
+}
@@ -0,0 +1,295 @@
+/**
+ * The library as a single self-contained HTML page, to share what was read
+ */
+use base64::Engine;
+use chrono::{Datelike, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+use crate::annotations;
+use crate::covers;
+use crate::library::{Book, ReadingState};
+use crate::logging;
+use crate::state::AppState;
+
+/// Covers are embedded as thumbnails at most this size
+const THUMB_WIDTH: usize = 96;
+const THUMB_HEIGHT: usize = 144;
+
+const STYLE: &str = r#"
+body { font-family: Georgia, "Times New Roman", serif; max-width: 960px; margin: 2rem auto; padding: 0 1rem; color: #222; background: #fbfaf7; }
+h1 { font-weight: normal; margin-bottom: 0.2rem; }
+h2 { font-weight: normal; border-bottom: 1px solid #ddd; padding-bottom: 0.3rem; margin-top: 2.5rem; }
+.subtitle { color: #777; margin-top: 0; }
+.book { display: flex; gap: 1rem; margin: 1rem 0; }
+.cover { width: 64px; height: 96px; object-fit: cover; flex: none; border-radius: 2px; background: #e4e0d8; box-shadow: 0 1px 3px rgba(0,0,0,.2); }
+.book h3 { margin: 0; font-size: 1.05rem; }
+.author, .meta { color: #666; margin: 0.15rem 0; }
+.review { font-style: italic; margin: 0.3rem 0; white-space: pre-line; }
+.stars { color: #c08a00; letter-spacing: 1px; }
+.notes { margin: 0.4rem 0 0; padding-left: 1.2rem; color: #444; font-size: 0.92rem; }
+table { width: 100%; border-collapse: collapse; font-size: 0.92rem; }
+th, td { text-align: left; padding: 0.35rem 0.5rem; border-bottom: 1px solid #eee; }
+th { cursor: pointer; user-select: none; white-space: nowrap; }
+th[data-dir="asc"]::after { content: " \25B2"; }
+th[data-dir="desc"]::after { content: " \25BC"; }
+footer { color: #999; font-size: 0.8rem; margin: 3rem 0 1rem; }
+"#;
+
+/// Click a column heading to sort by it, again to reverse
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach((th, col) => th.addEventListener("click", () => {
+  const body = th.closest("table").tBodies[0];
+  const asc = th.dataset.dir !== "asc";
+  th.parentElement.querySelectorAll("th").forEach(h => delete h.dataset.dir);
+  th.dataset.dir = asc ? "asc" : "desc";
+  const key = row => row.cells[col].dataset.sort ?? row.cells[col].textContent.trim().toLowerCase();
+  [...body.rows].sort((a, b) => {
+    const x = key(a), y = key(b), n = parseFloat(x) - parseFloat(y);
+    return (isNaN(n) ? x.localeCompare(y) : n) * (asc ? 1 : -1);
+  }).forEach(row => body.appendChild(row));
+}));
+"#;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LibraryReportOptions {
+    #[serde(rename = "currentlyReading", default = "default_true")]
+    pub currently_reading: bool,
+    #[serde(rename = "finishedThisYear", default = "default_true")]
+    pub finished_this_year: bool,
+    #[serde(default = "default_true")]
+    pub catalog: bool,
+    /// The notes written on highlights; private, so left out by default
+    #[serde(rename = "includeNotes", default)]
+    pub include_notes: bool,
+    /// Year of the finished section, this year by default
+    #[serde(default)]
+    pub year: Option<i32>,
+}
+
+impl Default for LibraryReportOptions {
+    fn default() -> Self {
+        Self {
+            currently_reading: true,
+            finished_this_year: true,
+            catalog: true,
+            include_notes: false,
+            year: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct LibraryReportSummary {
+    pub path: String,
+    #[serde(rename = "currentlyReading")]
+    pub currently_reading: usize,
+    pub finished: usize,
+    pub catalog: usize,
+    pub bytes: usize,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn stars(rating: Option<u8>) -> String {
+    match rating {
+        Some(rating) => format!(
+            "<span class=\"stars\" title=\"{} of 5\">{}{}</span>",
+            rating,
+            "★".repeat(rating as usize),
+            "☆".repeat(5usize.saturating_sub(rating as usize))
+        ),
+        None => String::new(),
+    }
+}
+
+fn cover_img(book: &Book) -> String {
+    let thumbnail = book
+        .cover_path
+        .as_deref()
+        .and_then(|path| covers::thumbnail_png(Path::new(path), THUMB_WIDTH, THUMB_HEIGHT));
+    match thumbnail {
+        Some(png) => format!(
+            "<img class=\"cover\" alt=\"\" src=\"data:image/png;base64,{}\">",
+            base64::engine::general_purpose::STANDARD.encode(png)
+        ),
+        // GIF and WebP covers aren't decoded; a blank cover stands in
+        None => "<div class=\"cover\"></div>".to_string(),
+    }
+}
+
+/// Notes the user wrote on the book's highlights
+fn notes(state: &AppState, book: &Book) -> String {
+    let annotations = match annotations::load_annotations(state, &book.id) {
+        Ok(annotations) => annotations,
+        Err(e) => {
+            logging::warn(&format!("Skipping notes of {}: {}", book.id, e));
+            return String::new();
+        }
+    };
+    let items: Vec<String> = annotations
+        .highlights
+        .iter()
+        .filter_map(|h| h.note.as_deref())
+        .filter(|note| !note.trim().is_empty())
+        .map(|note| format!("<li>{}</li>", escape_html(note.trim())))
+        .collect();
+    if items.is_empty() {
+        return String::new();
+    }
+    format!("<ul class=\"notes\">{}</ul>", items.join(""))
+}
+
+fn book_entry(state: &AppState, book: &Book, meta: &str, options: &LibraryReportOptions) -> String {
+    let review = book
+        .review
+        .as_deref()
+        .map(|r| format!("<p class=\"review\">“{}”</p>", escape_html(r)))
+        .unwrap_or_default();
+    let notes = if options.include_notes {
+        notes(state, book)
+    } else {
+        String::new()
+    };
+    format!(
+        "<div class=\"book\">{}<div><h3>{}</h3><p class=\"author\">{}</p><p class=\"meta\">{} {}</p>{}{}</div></div>\n",
+        cover_img(book),
+        escape_html(&book.title),
+        escape_html(&book.author),
+        meta,
+        stars(book.rating),
+        review,
+        notes
+    )
+}
+
+fn status(book: &Book) -> &'static str {
+    match book.reading_state {
+        ReadingState::Finished => "Finished",
+        ReadingState::Reading => "Reading",
+        ReadingState::ToRead => "To read",
+        ReadingState::Abandoned => "Abandoned",
+    }
+}
+
+fn catalog_table(books: &[Book]) -> String {
+    let mut rows = String::new();
+    for book in books {
+        let finished = book
+            .finished_at
+            .map(|d| d.with_timezone(&Local).date_naive());
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td data-sort=\"{}\">{}</td><td data-sort=\"{:.3}\">{}%</td><td data-sort=\"{}\">{}</td></tr>\n",
+            escape_html(&book.title),
+            escape_html(&book.author),
+            status(book),
+            book.rating.unwrap_or(0),
+            stars(book.rating),
+            book.progress,
+            (book.progress * 100.0).round(),
+            finished.map(|d| d.format("%Y%m%d").to_string()).unwrap_or_else(|| "0".to_string()),
+            finished.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+        ));
+    }
+    format!(
+        "<table class=\"sortable\"><thead><tr><th>Title</th><th>Author</th><th>Status</th><th>Rating</th><th>Progress</th><th>Finished</th></tr></thead><tbody>\n{}</tbody></table>\n",
+        rows
+    )
+}
+
+fn render(
+    state: &AppState,
+    options: &LibraryReportOptions,
+) -> Result<(String, LibraryReportSummary), String> {
+    let mut books = state.with_library(|library| library.books.clone())?;
+    books.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
+    let today = Local::now();
+    let year = options.year.unwrap_or(today.year());
+    let mut summary = LibraryReportSummary::default();
+
+    let mut body = format!(
+        "<h1>My library</h1>\n<p class=\"subtitle\">{} books · {}</p>\n",
+        books.len(),
+        today.format("%B %-d, %Y")
+    );
+
+    if options.currently_reading {
+        let mut reading: Vec<&Book> = books
+            .iter()
+            .filter(|b| b.reading_state == ReadingState::Reading)
+            .collect();
+        reading.sort_by(|a, b| b.last_opened.cmp(&a.last_opened));
+        summary.currently_reading = reading.len();
+        body.push_str("<h2>Currently reading</h2>\n");
+        if reading.is_empty() {
+            body.push_str("<p class=\"meta\">Nothing at the moment.</p>\n");
+        }
+        for book in reading {
+            let meta = format!("{}% read", (book.progress * 100.0).round());
+            body.push_str(&book_entry(state, book, &meta, options));
+        }
+    }
+
+    if options.finished_this_year {
+        let mut finished: Vec<(&Book, chrono::NaiveDate)> = books
+            .iter()
+            .filter(|b| b.reading_state == ReadingState::Finished)
+            .filter_map(|b| Some((b, b.finished_at?.with_timezone(&Local).date_naive())))
+            .filter(|(_, date)| date.year() == year)
+            .collect();
+        finished.sort_by_key(|(_, date)| *date);
+        summary.finished = finished.len();
+        body.push_str(&format!("<h2>Finished in {}</h2>\n", year));
+        if finished.is_empty() {
+            body.push_str("<p class=\"meta\">No books finished yet.</p>\n");
+        }
+        for (book, date) in finished {
+            let meta = format!("Finished {}", date.format("%B %-d"));
+            body.push_str(&book_entry(state, book, &meta, options));
+        }
+    }
+
+    if options.catalog {
+        summary.catalog = books.len();
+        body.push_str("<h2>Catalog</h2>\n");
+        body.push_str(&catalog_table(&books));
+    }
+
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>My library</title>\n<style>{}</style>\n</head>\n<body>\n{}<footer>Made with Epilogue</footer>\n<script>{}</script>\n</body>\n</html>\n",
+        STYLE, body, SCRIPT
+    );
+    summary.bytes = html.len();
+    Ok((html, summary))
+}
+
+/// Write the library as one HTML file that works offline: currently
+/// reading, finished this year with ratings and reviews, and a sortable
+/// catalog. Only the first two sections carry cover thumbnails.
+#[tauri::command]
+pub async fn export_library_html(
+    app: AppHandle,
+    path: String,
+    options: Option<LibraryReportOptions>,
+) -> Result<LibraryReportSummary, String> {
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let (html, mut summary) = render(&app.state::<AppState>(), &options)?;
+        fs::write(&path, html).map_err(|e| format!("Failed to write library report: {}", e))?;
+        summary.path = path;
+        Ok(summary)
+    })
+    .await
+    .map_err(|e| format!("Report task failed: {}", e))?
+}
@@ -0,0 +1,110 @@
+/**
+ * Plain-text application log in logs/epilogue.log
+ */
+use chrono::Local;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use crate::config;
+
+/// Rotate to epilogue.log.1 once the log grows past this size
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Serializes writers so lines from different threads never interleave
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+/// Most verbose level written: 0 = error, 1 = warn, 2 = info, 3 = debug
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(2);
+
+fn level_rank(level: &str) -> u8 {
+    match level.to_ascii_lowercase().as_str() {
+        "error" => 0,
+        "warn" => 1,
+        "info" => 2,
+        _ => 3,
+    }
+}
+
+/// Set the most verbose level that gets logged
+pub fn set_level(level: &str) {
+    MAX_LEVEL.store(level_rank(level), Ordering::Relaxed);
+}
+
+pub fn logs_dir() -> Result<PathBuf, String> {
+    Ok(config::get_app_dir_path()?.join("logs"))
+}
+
+pub fn log_path() -> Result<PathBuf, String> {
+    Ok(logs_dir()?.join("epilogue.log"))
+}
+
+/// Write a line to stderr and the log file; logging never fails the caller
+pub fn write(level: &str, message: &str) {
+    if level_rank(level) > MAX_LEVEL.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let line = format!(
+        "{} [{}] {}",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        level,
+        message
+    );
+    eprintln!("{}", line);
+
+    let Ok(path) = log_path() else {
+        return;
+    };
+    let _guard = LOG_LOCK.lock();
+
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+
+    if fs::metadata(&path)
+        .map(|m| m.len() > MAX_LOG_BYTES)
+        .unwrap_or(false)
+    {
+        let _ = fs::rename(&path, path.with_extension("log.1"));
+    }
+
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+pub fn info(message: &str) {
+    write("INFO", message);
+}
+
+pub fn warn(message: &str) {
+    write("WARN", message);
+}
+
+pub fn error(message: &str) {
+    write("ERROR", message);
+}
+
+pub fn debug(message: &str) {
+    write("DEBUG", message);
+}
+
+/// Last `count` lines of the log, oldest first
+pub fn tail(count: usize) -> Vec<String> {
+    let Ok(path) = log_path() else {
+        return Vec::new();
+    };
+
+    let mut lines: Vec<String> = Vec::new();
+    for file in [path.with_extension("log.1"), path] {
+        if let Ok(content) = fs::read_to_string(&file) {
+            lines.extend(content.lines().map(|l| l.to_string()));
+        }
+    }
+
+    let start = lines.len().saturating_sub(count);
+    lines.split_off(start)
+}
@@ -1,50 +1,400 @@
 // Prevents additional console window on Windows in release builds
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod accessibility;
+mod activity;
+mod advanced;
+mod annotations;
+mod annotations_archive;
+mod app_menu;
+mod article;
+mod audiobook;
+mod authors;
+mod backup;
+mod bionic;
+mod book_ids;
+mod calibre;
+mod chapter;
+mod cloud_sync;
 mod config;
+mod continue_reading;
+mod covers;
+mod crash;
+mod daily_quote;
+mod deeplink;
+mod device_import;
+mod diagnostics;
 mod epub;
+mod error;
+mod file_access;
+mod finished;
+mod folder_import;
+mod footnote;
+mod filters;
+mod fullscreen;
+mod gallery;
+mod goodreads;
+mod gutenberg;
+mod isbn;
+mod lan_sync;
+mod launch;
 mod library;
+mod library_db;
+mod library_export;
+mod library_report;
+mod logging;
+mod maintenance;
+mod markdown_export;
+mod media;
+mod media_keys;
+mod meta;
+mod obsidian;
+mod open_library;
+mod pdf_export;
+mod preflight;
 mod preset;
 mod preferences;
+mod progress_history;
+mod quote;
+mod quote_image;
+mod reading_speed;
+mod readwise;
+mod reader_window;
+mod series;
+mod sessions;
+mod sleep_inhibit;
+mod smart_shelves;
+mod stardict;
+mod startup;
+mod state;
+mod stats;
+mod translate;
+mod tray;
+mod tts;
+mod update;
+mod view_state;
+mod vocabulary;
+mod watched_folders;
+mod webdav;
+mod window_state;
+
+use tauri::Manager;
+use tauri_plugin_deep_link::DeepLinkExt;
 
 fn main() {
+    startup::mark_launch();
+    crash::install_panic_hook();
+
     tauri::Builder::default()
+        // Must be registered first so a second launch exits before doing any work
+        .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+            launch::handle_second_instance(app, argv, cwd);
+        }))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_fs::init())
-        .setup(|_app| {
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .plugin(tauri_plugin_opener::init())
+        .manage(launch::LaunchState::default())
+        .manage(reader_window::ReaderWindows::default())
+        .manage(sleep_inhibit::SleepInhibit::default())
+        .manage(sessions::SessionManager::default())
+        .manage(startup::StartupCache::default())
+        .manage(lan_sync::LanSync::default())
+        .manage(tts::TtsPlayer::default())
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::CloseRequested { .. } = event {
+                library::flush_pending(window.app_handle());
+                view_state::flush_pending(window.app_handle());
+            }
+            window_state::on_window_event(window, event);
+            tray::on_window_event(window, event);
+            reader_window::on_window_event(window, event);
+            fullscreen::on_window_event(window, event);
+            sleep_inhibit::on_window_event(window, event);
+            sessions::on_window_event(window, event);
+        })
+        .setup(|app| {
+            // Verify the data directory before anything touches it. The
+            // frontend isn't listening yet, so the warning waits for it.
+            match config::resolve_app_dir() {
+                Ok(status) if status.safe_mode => {
+                    launch::emit_when_ready(app.handle(), "app-dir-unavailable", status.clone());
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    logging::error(&e.to_string());
+                    launch::emit_when_ready(app.handle(), "app-dir-unavailable", e);
+                }
+            }
+            app.manage(state::AppState::new());
+
+            // The window starts hidden so it can be placed before it appears;
+            // it is shown once the frontend has painted (see startup.rs)
+            if let Some(window) = app.get_webview_window("main") {
+                window_state::restore(&window);
+            }
+
+            // Power-user settings; a bad file falls back to defaults
+            let (advanced, warnings) = advanced::load_advanced_config();
+            logging::set_level(&advanced.log_level);
+            for warning in &warnings {
+                logging::warn(warning);
+            }
+            if !warnings.is_empty() {
+                launch::emit_when_ready(app.handle(), "advanced-config-warnings", warnings);
+            }
+            app.manage(advanced);
+
             // Initialize library on first launch
-            if let Err(e) = config::init_library() {
-                eprintln!("Failed to initialize library: {}", e);
+            if let Err(e) = config::init_library(app.state()) {
+                logging::error(&format!("Failed to initialize library: {}", e));
             }
 
-            // Copy built-in presets on first run
-            if let Err(e) = config::copy_builtin_presets() {
-                eprintln!("Failed to copy built-in presets: {}", e);
+            // A synced data folder and its conflicted copies put the
+            // library at risk
+            cloud_sync::check(app.handle());
+
+            // Books finished before completion dates were kept get one
+            if let Err(e) = finished::infer_finish_dates(&app.state()) {
+                logging::error(&format!("Failed to infer finish dates: {}", e));
             }
 
+            // Move covers from the pre-cache location
+            if let Err(e) = config::migrate_legacy_covers(&app.state()) {
+                logging::error(&format!("Failed to migrate covers: {}", e));
+            }
+
+            // Books added when ids came from file paths get content ids
+            if let Err(e) = book_ids::migrate_to_content_ids(app.handle()) {
+                logging::error(&format!("Failed to give books content ids: {}", e));
+            }
+
+            // Copy built-in presets on first run, refresh them after updates
+            if let Err(e) = config::copy_builtin_presets(app.handle().clone()) {
+                logging::error(&format!("Failed to copy built-in presets: {}", e));
+            }
+            startup::preload(app.handle());
+
+            tray::init(app.handle());
+            app_menu::init(app.handle());
+            if let Ok(prefs) = app.state::<state::AppState>().preferences() {
+                media_keys::sync(app.handle(), &prefs);
+                lan_sync::apply(app.handle(), &prefs);
+            }
+            maintenance::start(app.handle());
+            accessibility::watch(app.handle());
+            library::verify_files_on_startup(app.handle());
+            watched_folders::watch(app.handle());
+            sessions::recover_open_session(app.handle());
+            sessions::start_idle_watch(app.handle());
+            webdav::sync_on_startup(app.handle());
+
+            // epilogue:// links, both the one we were launched with and later ones
+            #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+            if let Err(e) = app.deep_link().register_all() {
+                logging::warn(&format!("Failed to register epilogue:// links: {}", e));
+            }
+            let handle = app.handle().clone();
+            app.deep_link()
+                .on_open_url(move |event| deeplink::handle_urls(&handle, event.urls()));
+            if let Ok(Some(urls)) = app.deep_link().get_current() {
+                deeplink::handle_urls(app.handle(), urls);
+            }
+
+            // Open any books passed on the command line
+            let argv: Vec<String> = std::env::args().collect();
+            let cwd = std::env::current_dir().ok();
+            launch::open_paths(app.handle(), launch::paths_from_args(&argv, cwd.as_deref()));
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
+            advanced::get_advanced_config,
+            backup::backup_app_data,
+            backup::restore_app_data,
+            epub::open_backup_dialog,
             config::get_app_dir,
+            config::get_app_dir_status,
+            config::get_app_info,
             config::init_library,
             config::copy_builtin_presets,
+            diagnostics::export_diagnostics,
             epub::open_epub_dialog,
             epub::read_epub_file,
             epub::open_media_dialog,
             epub::open_audio_dialog,
+            preflight::preflight_check,
             preset::list_presets,
             preset::load_preset,
             preset::list_backgrounds,
             preset::save_custom_preset,
             preset::delete_preset,
+            launch::frontend_ready,
             library::add_book,
             library::get_recent_books,
             library::update_progress,
             library::get_book_progress,
             library::remove_book,
+            media::get_media_usage,
+            media::import_background,
+            media::import_music,
+            media::delete_background,
+            media::remove_music,
+            meta::get_onboarding_state,
+            meta::set_onboarding_step,
             preferences::get_preferences,
             preferences::set_preferences,
+            update::check_for_updates,
+            reader_window::open_book_window,
+            fullscreen::toggle_fullscreen,
+            fullscreen::set_immersive,
+            sleep_inhibit::inhibit_sleep,
+            sleep_inhibit::release_sleep_inhibit,
+            startup::get_startup_state,
+            crash::get_last_crash_report,
+            sessions::start_reading_session,
+            sessions::end_reading_session,
+            sessions::record_activity,
+            sessions::get_reading_sessions,
+            quote::copy_quote,
+            annotations::get_highlights,
+            library::set_rating,
+            library::set_reading_state,
+            library::set_book_status,
+            goodreads::export_goodreads_csv,
+            webdav::get_sync_settings,
+            webdav::set_sync_settings,
+            webdav::validate_sync_settings,
+            webdav::sync_now,
+            lan_sync::discover_peers,
+            lan_sync::request_pairing,
+            lan_sync::pair_with_peer,
+            lan_sync::answer_pairing_request,
+            lan_sync::sync_with_peer,
+            lan_sync::forget_peer,
+            epub::open_device_annotations_dialog,
+            device_import::import_device_annotations,
+            gutenberg::search_gutenberg,
+            gutenberg::download_gutenberg_book,
+            readwise::export_highlights_readwise,
+            obsidian::export_to_obsidian,
+            obsidian::export_all_to_obsidian,
+            article::save_article_as_epub,
+            epub::open_audiobook_dialog,
+            audiobook::add_audiobook,
+            library::update_audio_progress,
+            tts::speak_text,
+            tts::pause_tts,
+            tts::resume_tts,
+            tts::stop_tts,
+            tts::get_tts_voices,
+            activity::get_activity_heatmap,
+            reading_speed::get_reading_speed,
+            reading_speed::set_reading_speed_override,
+            reading_speed::estimate_time_remaining,
+            sessions::start_timed_session,
+            sessions::pause_session,
+            sessions::resume_session,
+            sessions::get_timed_session,
+            daily_quote::get_quote_of_the_day,
+            daily_quote::shuffle_quote,
+            translate::translate_text,
+            quote_image::render_quote_image,
+            open_library::fetch_book_metadata,
+            open_library::apply_fetched_metadata,
+            covers::download_cover,
+            covers::refresh_cover,
+            covers::refresh_all_covers,
+            isbn::lookup_isbn,
+            isbn::set_book_isbn,
+            config::set_data_directory,
+            cloud_sync::get_cloud_sync_status,
+            cloud_sync::merge_external_library,
+            library::accept_imported_progress,
+            library::clear_imported_position,
+            library::set_review,
+            library_report::export_library_html,
+            library::set_book_overrides,
+            chapter::get_chapter,
+            footnote::get_note_content,
+            gallery::get_book_images,
+            gallery::get_image,
+            library::mark_chapter_read,
+            library::get_chapter_progress,
+            view_state::save_view_state,
+            view_state::get_view_state,
+            finished::get_finished_books,
+            authors::list_authors,
+            authors::get_author,
+            smart_shelves::list_smart_shelves,
+            smart_shelves::create_smart_shelf,
+            smart_shelves::update_smart_shelf,
+            smart_shelves::delete_smart_shelf,
+            smart_shelves::evaluate_smart_shelf,
+            series::get_series_status,
+            series::get_up_next_suggestions,
+            maintenance::run_maintenance,
+            maintenance::get_maintenance_status,
+            annotations_archive::export_annotations_archive,
+            annotations_archive::import_annotations_archive,
+            epub::open_annotations_archive_dialog,
+            annotations::set_highlight_category,
+            vocabulary::add_vocabulary_entry,
+            vocabulary::list_vocabulary,
+            vocabulary::remove_vocabulary_entry,
+            vocabulary::export_vocabulary_csv,
+            accessibility::detect_accessibility_preferences,
+            preferences::get_effective_preferences,
+            library::add_tag,
+            library::remove_tag,
+            library::list_tags,
+            library::get_books_by_tag,
+            pdf_export::export_chapter_pdf,
+            library::search_library,
+            continue_reading::get_continue_reading,
+            markdown_export::export_annotations,
+            sessions::get_reading_time,
+            sessions::get_total_reading_time,
+            stats::get_reading_stats,
+            library::check_library_files,
+            library::relink_book,
+            library::find_duplicates,
+            epub::open_folder_dialog,
+            folder_import::import_folder,
+            watched_folders::add_watched_folder,
+            watched_folders::remove_watched_folder,
+            watched_folders::list_watched_folders,
+            library::get_books,
+            library::get_books_page,
+            library::toggle_favorite,
+            library::get_favorites,
+            library::mark_unfinished,
+            series::get_series,
+            series::set_series,
+            library_export::export_library,
+            calibre::import_calibre,
+            library::archive_book,
+            library::unarchive_book,
+            covers::get_cover,
+            progress_history::get_progress_history,
+            stats::get_streak,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::ExitRequested { .. } = event {
+                sessions::end_open_session(app);
+                library::flush_pending(app);
+                view_state::flush_pending(app);
+                maintenance::shutdown();
+                app.state::<sleep_inhibit::SleepInhibit>().release(None);
+                media_keys::release(app);
+                lan_sync::shutdown(app);
+                tts::shutdown(app);
+            }
+            launch::handle_run_event(app, event);
+        });
 }
 
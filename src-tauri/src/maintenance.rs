@@ -0,0 +1,372 @@
+/**
+ * Occasional housekeeping run on a background thread, one task at a time,
+ * so it needn't happen at startup. When each task last ran and is next due
+ * is kept in meta.json.
+ */
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+
+use crate::advanced::AdvancedConfig;
+use crate::config;
+use crate::crash;
+use crate::logging;
+use crate::meta;
+use crate::state::AppState;
+use crate::update;
+
+/// Wait after launch before the first round, so startup isn't slowed
+const STARTUP_DELAY: std::time::Duration = std::time::Duration::from_secs(120);
+/// How often due tasks are looked for
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+/// Steps the waits are slept in, to notice the app exiting
+const SLEEP_STEP: std::time::Duration = std::time::Duration::from_secs(1);
+/// Covers younger than this are left alone, as an import may be writing
+/// them
+const ORPHAN_COVER_MIN_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Held while a task runs, so tasks never overlap
+static RUNNING: Mutex<()> = Mutex::new(());
+static EXITING: AtomicBool = AtomicBool::new(false);
+
+struct Task {
+    name: &'static str,
+    interval_hours: i64,
+    /// A short summary of what was done, for the status
+    run: fn(&AppHandle) -> Result<String, String>,
+}
+
+const TASKS: &[Task] = &[
+    Task {
+        name: "update-check",
+        interval_hours: 24,
+        run: update::maintenance_check,
+    },
+    Task {
+        name: "orphan-covers",
+        interval_hours: 7 * 24,
+        run: remove_orphan_covers,
+    },
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// A task's last run and next one, as kept in meta.json
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MaintenanceRecord {
+    #[serde(rename = "lastRun", default)]
+    pub last_run: Option<DateTime<Utc>>,
+    #[serde(rename = "nextRun", default)]
+    pub next_run: Option<DateTime<Utc>>,
+    #[serde(rename = "durationMs", default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub outcome: Option<TaskOutcome>,
+    /// What the task reported, or why it failed
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MaintenanceStatus {
+    pub name: String,
+    #[serde(rename = "intervalHours")]
+    pub interval_hours: i64,
+    #[serde(flatten)]
+    pub record: MaintenanceRecord,
+}
+
+/// When a task is next due: an interval from now, give or take a tenth,
+/// so tasks with the same interval drift apart
+fn next_run(task: &Task, now: DateTime<Utc>) -> DateTime<Utc> {
+    let interval = task.interval_hours * 60;
+    let jitter = rand::thread_rng().gen_range(-interval / 10..=interval / 10);
+    now + Duration::minutes(interval + jitter)
+}
+
+/// Stop starting tasks; the one running, if any, finishes
+pub fn shutdown() {
+    EXITING.store(true, Ordering::SeqCst);
+}
+
+pub fn is_exiting() -> bool {
+    EXITING.load(Ordering::SeqCst)
+}
+
+/// Run a task, waiting for any other to finish first, and record the run
+fn run_task(app: &AppHandle, task: &Task) -> MaintenanceRecord {
+    let _running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+    let started = Instant::now();
+    let result = crash::catch_panic(task.name, || (task.run)(app)).and_then(|r| r);
+    let now = Utc::now();
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let (outcome, message) = match result {
+        Ok(message) => {
+            logging::info(&format!(
+                "Maintenance '{}' done in {} ms: {}",
+                task.name, duration_ms, message
+            ));
+            (TaskOutcome::Succeeded, message)
+        }
+        Err(e) => {
+            logging::error(&format!(
+                "Maintenance '{}' failed after {} ms: {}",
+                task.name, duration_ms, e
+            ));
+            (TaskOutcome::Failed, e)
+        }
+    };
+    let record = MaintenanceRecord {
+        last_run: Some(now),
+        next_run: Some(next_run(task, now)),
+        duration_ms: Some(duration_ms),
+        outcome: Some(outcome),
+        message: Some(message),
+    };
+    let saved = meta::update_meta(|m| {
+        m.maintenance.insert(task.name.to_string(), record.clone());
+    });
+    if let Err(e) = saved {
+        logging::error(&e);
+    }
+    record
+}
+
+/// Run the tasks that are due, in order, unless the app is exiting
+fn run_due(app: &AppHandle) {
+    if config::is_safe_mode() {
+        return;
+    }
+    let records = meta::load_meta().maintenance;
+    let now = Utc::now();
+    for task in TASKS {
+        if is_exiting() {
+            return;
+        }
+        let due = records
+            .get(task.name)
+            .and_then(|r| r.next_run)
+            .is_none_or(|next| next <= now);
+        if due {
+            run_task(app, task);
+        }
+    }
+}
+
+/// Sleep, waking early when the app exits. Returns whether it is exiting.
+fn sleep(duration: std::time::Duration) -> bool {
+    let until = Instant::now() + duration;
+    while Instant::now() < until {
+        if is_exiting() {
+            return true;
+        }
+        std::thread::sleep(SLEEP_STEP);
+    }
+    is_exiting()
+}
+
+/// Start the scheduler thread
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        if sleep(STARTUP_DELAY) {
+            return;
+        }
+        loop {
+            run_due(&app);
+            if sleep(CHECK_INTERVAL) {
+                return;
+            }
+        }
+    });
+}
+
+/// Delete cached covers no book uses any more, left behind by removed
+/// books and replaced covers, then keep the covers directory under its size
+/// cap
+fn remove_orphan_covers(app: &AppHandle) -> Result<String, String> {
+    let state = app.state::<AppState>();
+    let covers_dir = state.paths()?.covers.clone();
+    if !covers_dir.is_dir() {
+        return Ok("No covers".to_string());
+    }
+    let used: HashSet<String> = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .flat_map(|b| [b.cover_path.as_deref(), b.thumbnail_path.as_deref()])
+            .flatten()
+            .filter_map(|cover| Path::new(cover).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect()
+    })?;
+
+    let entries =
+        fs::read_dir(&covers_dir).map_err(|e| format!("Failed to read covers directory: {}", e))?;
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        if is_exiting() {
+            break;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let old_enough = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= ORPHAN_COVER_MIN_AGE);
+        if used.contains(&name) || !old_enough || !entry.path().is_file() {
+            continue;
+        }
+        match fs::remove_file(entry.path()) {
+            Ok(_) => removed += 1,
+            Err(e) => logging::warn(&format!("Failed to remove cover {}: {}", name, e)),
+        }
+    }
+
+    let cap = app
+        .state::<AdvancedConfig>()
+        .cover_cache_max_mb
+        .saturating_mul(1024 * 1024);
+    let trimmed = trim_thumbnails(&state, &covers_dir, cap)?;
+    Ok(format!(
+        "Removed {} unused covers and {} thumbnails over the size cap",
+        removed, trimmed
+    ))
+}
+
+/// Thumbnails to delete, from `thumbnails` (path and size, the books
+/// opened longest ago first), to bring `total` bytes down to `cap`
+fn thumbnails_over_cap(thumbnails: Vec<(String, u64)>, total: u64, cap: u64) -> Vec<String> {
+    let mut total = total;
+    thumbnails
+        .into_iter()
+        .take_while(|(_, size)| {
+            let over = total > cap;
+            total = total.saturating_sub(*size);
+            over
+        })
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Delete grid thumbnails, of the books opened longest ago first, while the
+/// covers directory is over `cap` bytes. Thumbnails are made again when
+/// next shown, unlike covers, which may have been downloaded.
+fn trim_thumbnails(state: &AppState, covers_dir: &Path, cap: u64) -> Result<usize, String> {
+    let total: u64 = fs::read_dir(covers_dir)
+        .map_err(|e| format!("Failed to read covers directory: {}", e))?
+        .flatten()
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum();
+    if total <= cap {
+        return Ok(0);
+    }
+
+    let mut books = state.with_library(|library| library.books.clone())?;
+    books.sort_by_key(|book| book.last_opened);
+    let thumbnails = books
+        .into_iter()
+        .filter_map(|book| book.thumbnail_path)
+        .filter_map(|path| {
+            let size = fs::metadata(&path).ok()?.len();
+            Some((path, size))
+        })
+        .collect();
+
+    let mut trimmed = 0;
+    for path in thumbnails_over_cap(thumbnails, total, cap) {
+        match fs::remove_file(&path) {
+            Ok(_) => trimmed += 1,
+            Err(e) => logging::warn(&format!("Failed to remove thumbnail {}: {}", path, e)),
+        }
+    }
+    Ok(trimmed)
+}
+
+/// Run a maintenance task now, whether it is due or not, for debugging
+#[tauri::command]
+pub async fn run_maintenance(
+    app: AppHandle,
+    task_name: String,
+) -> Result<MaintenanceStatus, String> {
+    let task = TASKS.iter().find(|t| t.name == task_name).ok_or_else(|| {
+        format!(
+            "Unknown maintenance task '{}', expected one of {}",
+            task_name,
+            TASKS.iter().map(|t| t.name).collect::<Vec<_>>().join(", ")
+        )
+    })?;
+    if config::is_safe_mode() {
+        return Err("Maintenance doesn't run in safe mode".to_string());
+    }
+    if is_exiting() {
+        return Err("The app is closing".to_string());
+    }
+    let record = tauri::async_runtime::spawn_blocking(move || run_task(&app, task))
+        .await
+        .map_err(|e| format!("Maintenance task failed: {}", e))?;
+    Ok(MaintenanceStatus {
+        name: task.name.to_string(),
+        interval_hours: task.interval_hours,
+        record,
+    })
+}
+
+/// Every maintenance task with its last run, how long it took and how it
+/// went, and when it is next due
+#[tauri::command]
+pub fn get_maintenance_status() -> Result<Vec<MaintenanceStatus>, String> {
+    let mut records = meta::load_meta().maintenance;
+    Ok(TASKS
+        .iter()
+        .map(|task| MaintenanceStatus {
+            name: task.name.to_string(),
+            interval_hours: task.interval_hours,
+            record: records.remove(task.name).unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thumbnails() -> Vec<(String, u64)> {
+        ["oldest", "older", "newer", "newest"]
+            .iter()
+            .map(|name| (name.to_string(), 10))
+            .collect()
+    }
+
+    #[test]
+    fn nothing_is_trimmed_under_the_cap() {
+        assert!(thumbnails_over_cap(thumbnails(), 100, 100).is_empty());
+    }
+
+    #[test]
+    fn thumbnails_of_books_opened_longest_ago_go_first() {
+        assert_eq!(
+            thumbnails_over_cap(thumbnails(), 115, 100),
+            ["oldest", "older"]
+        );
+        assert_eq!(thumbnails_over_cap(thumbnails(), 110, 100), ["oldest"]);
+        // Covers alone over the cap: every thumbnail goes, nothing more
+        assert_eq!(thumbnails_over_cap(thumbnails(), 500, 100).len(), 4);
+    }
+}
@@ -0,0 +1,154 @@
+/**
+ * Highlights and notes as plain Markdown: one file per book, grouped under
+ * the chapters they were made in, in reading order
+ */
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use tauri::State;
+
+use crate::annotations::{self, Highlight, HighlightCategory};
+use crate::library::Book;
+use crate::obsidian;
+use crate::state::AppState;
+
+/// Book id that exports every book with highlights into a folder
+const ALL_BOOKS: &str = "all";
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct AnnotationsExport {
+    /// Files written; empty when the Markdown was returned instead
+    pub paths: Vec<String>,
+    /// The Markdown of a single book exported without a destination
+    pub markdown: Option<String>,
+    pub highlights: usize,
+}
+
+/// Highlights that go into exports, in the order they appear in the book
+fn exported_highlights(
+    state: &AppState,
+    book: &Book,
+    categories: &[HighlightCategory],
+) -> Result<Vec<Highlight>, String> {
+    let mut highlights: Vec<Highlight> = annotations::load_annotations(state, &book.id)?
+        .highlights
+        .into_iter()
+        .filter(|h| !h.text.trim().is_empty())
+        .filter(|h| annotations::is_exported(h, categories))
+        .collect();
+    // Highlights whose CFI can't be placed go after the rest
+    highlights.sort_by_key(|h| {
+        (
+            annotations::spine_index(&h.cfi).unwrap_or(usize::MAX),
+            annotations::char_offset(&h.cfi),
+        )
+    });
+    Ok(highlights)
+}
+
+/// The book's heading, then a section per chapter with each highlight as a
+/// blockquote followed by its note
+fn book_markdown(book: &Book, highlights: &[Highlight]) -> String {
+    let mut markdown = format!("# {}\n\n*{}*\n", book.title.trim(), book.author.trim());
+    let mut chapter: Option<Option<&str>> = None;
+    for highlight in highlights {
+        let this_chapter = highlight
+            .chapter
+            .as_deref()
+            .map(str::trim)
+            .filter(|c| !c.is_empty());
+        if chapter != Some(this_chapter) {
+            markdown.push_str(&format!(
+                "\n## {}\n",
+                this_chapter.unwrap_or("Untitled chapter")
+            ));
+            chapter = Some(this_chapter);
+        }
+
+        markdown.push('\n');
+        for line in highlight.text.trim().lines().map(str::trim_end) {
+            if line.is_empty() {
+                markdown.push_str(">\n");
+            } else {
+                markdown.push_str(&format!("> {}\n", line));
+            }
+        }
+        if let Some(note) = highlight.note.as_deref().map(str::trim) {
+            if !note.is_empty() {
+                markdown.push('\n');
+                markdown.push_str(note);
+                markdown.push('\n');
+            }
+        }
+    }
+    markdown
+}
+
+fn write_markdown(path: &Path, markdown: &str) -> Result<(), String> {
+    fs::write(path, markdown).map_err(|e| format!("Failed to write Markdown export: {}", e))
+}
+
+/// Export the highlights and notes of a book as Markdown. `format` is
+/// "markdown". With `dest`, the file is written there; without, the
+/// Markdown is returned for the frontend to save. A `book_id` of "all"
+/// writes a file for every book with highlights into the `dest` folder.
+/// Highlights of private categories are left out.
+#[tauri::command]
+pub fn export_annotations(
+    state: State<'_, AppState>,
+    book_id: String,
+    format: String,
+    dest: Option<String>,
+) -> Result<AnnotationsExport, String> {
+    if !format.eq_ignore_ascii_case("markdown") {
+        return Err(format!("Unsupported export format: {}", format));
+    }
+    let categories = state.preferences()?.highlight_categories;
+
+    if book_id == ALL_BOOKS {
+        let dest = dest.ok_or("Choose a folder to export every book into")?;
+        let folder = Path::new(&dest);
+        if !folder.is_dir() {
+            return Err(format!("Export folder not found: {}", dest));
+        }
+        let books = state.with_library(|library| library.books.clone())?;
+        let mut export = AnnotationsExport::default();
+        for book in &books {
+            let highlights = exported_highlights(&state, book, &categories)?;
+            if highlights.is_empty() {
+                continue;
+            }
+            let path = folder.join(obsidian::note_file_name(book));
+            write_markdown(&path, &book_markdown(book, &highlights))?;
+            export.paths.push(path.to_string_lossy().to_string());
+            export.highlights += highlights.len();
+        }
+        return Ok(export);
+    }
+
+    let book = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .find(|b| b.id == book_id)
+            .cloned()
+            .ok_or_else(|| format!("Book with id '{}' not found", book_id))
+    })??;
+    let highlights = exported_highlights(&state, &book, &categories)?;
+    let markdown = book_markdown(&book, &highlights);
+    match dest {
+        Some(dest) => {
+            write_markdown(Path::new(&dest), &markdown)?;
+            Ok(AnnotationsExport {
+                paths: vec![dest],
+                markdown: None,
+                highlights: highlights.len(),
+            })
+        }
+        None => Ok(AnnotationsExport {
+            paths: Vec::new(),
+            markdown: Some(markdown),
+            highlights: highlights.len(),
+        }),
+    }
+}
@@ -0,0 +1,201 @@
+/**
+ * Managed background media and music, with a size quota
+ */
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::file_access;
+use crate::preflight::{self, PathKind};
+use crate::state::AppState;
+
+const BACKGROUND_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "bmp", "svg", "mp4", "webm", "mov", "avi", "mkv",
+];
+const MUSIC_EXTENSIONS: &[&str] = &["mp3", "wav", "ogg", "flac", "aac", "m4a", "wma"];
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MediaFile {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MediaUsage {
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+    /// None when the quota is disabled
+    #[serde(rename = "quotaBytes")]
+    pub quota_bytes: Option<u64>,
+    #[serde(rename = "backgroundsBytes")]
+    pub backgrounds_bytes: u64,
+    #[serde(rename = "musicBytes")]
+    pub music_bytes: u64,
+    pub backgrounds: Vec<MediaFile>,
+    pub music: Vec<MediaFile>,
+}
+
+/// List the files directly inside `dir`, largest first
+fn list_media(dir: &Path) -> Vec<MediaFile> {
+    let mut files: Vec<MediaFile> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| {
+                    let metadata = entry.metadata().ok()?;
+                    if !metadata.is_file() {
+                        return None;
+                    }
+                    // Manifests and other dotfiles aren't user media
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    if name.starts_with('.') || name.ends_with(".json") {
+                        return None;
+                    }
+                    Some(MediaFile {
+                        name,
+                        path: entry.path().to_string_lossy().to_string(),
+                        size: metadata.len(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    files.sort_by(|a, b| b.size.cmp(&a.size));
+    files
+}
+
+/// Quota in bytes from preferences, or None when disabled
+fn quota_bytes(state: &AppState) -> Option<u64> {
+    let prefs = state.preferences().unwrap_or_default();
+    prefs
+        .media_quota_enabled
+        .then_some(prefs.media_quota_mb * 1024 * 1024)
+}
+
+fn media_usage(state: &AppState) -> Result<MediaUsage, AppError> {
+    let paths = state.paths()?;
+    let backgrounds = list_media(&paths.backgrounds);
+    let music = list_media(&paths.music);
+
+    let backgrounds_bytes = backgrounds.iter().map(|f| f.size).sum::<u64>();
+    let music_bytes = music.iter().map(|f| f.size).sum::<u64>();
+
+    Ok(MediaUsage {
+        total_bytes: backgrounds_bytes + music_bytes,
+        quota_bytes: quota_bytes(state),
+        backgrounds_bytes,
+        music_bytes,
+        backgrounds,
+        music,
+    })
+}
+
+/// Get disk usage of managed backgrounds and music
+#[tauri::command]
+pub fn get_media_usage(state: State<'_, AppState>) -> Result<MediaUsage, AppError> {
+    media_usage(&state)
+}
+
+/// Copy a file into a managed media directory after checking type and quota
+fn import_media(
+    state: &AppState,
+    source: &str,
+    dir: &Path,
+    extensions: &[&str],
+) -> Result<String, AppError> {
+    // Only files the user picked, never arbitrary paths from the webview
+    file_access::check_read_access(state, source)?;
+    let source = Path::new(source);
+
+    let ext = source
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_lowercase())
+        .unwrap_or_default();
+    if !extensions.contains(&ext.as_str()) {
+        return Err(format!("Unsupported media type: {}", source.display()).into());
+    }
+
+    let file_bytes = fs::metadata(source)
+        .map_err(|e| format!("Failed to read {}: {}", source.display(), e))?
+        .len();
+
+    if let Some(quota) = quota_bytes(state) {
+        let usage = media_usage(state)?;
+        if usage.total_bytes + file_bytes > quota {
+            return Err(AppError::QuotaExceeded {
+                used_bytes: usage.total_bytes,
+                file_bytes,
+                quota_bytes: quota,
+            });
+        }
+    }
+
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create media directory: {}", e))?;
+    preflight::check_writable(PathKind::Media)?;
+
+    // Never overwrite: "loop.mp4" becomes "loop-1.mp4", "loop-2.mp4", ...
+    let stem = source
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("media")
+        .to_string();
+    let mut target = dir.join(format!("{}.{}", stem, ext));
+    let mut n = 1;
+    while target.exists() {
+        target = dir.join(format!("{}-{}.{}", stem, n, ext));
+        n += 1;
+    }
+
+    fs::copy(source, &target).map_err(|e| format!("Failed to import media: {}", e))?;
+
+    Ok(target.to_string_lossy().to_string())
+}
+
+/// Delete a file by name from a managed media directory
+fn delete_media(dir: &Path, name: &str) -> Result<(), AppError> {
+    // Only bare file names, never paths out of the directory
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(format!("Invalid media file name: {}", name).into());
+    }
+
+    let path = dir.join(name);
+    if !path.is_file() {
+        return Err(format!("Media file '{}' not found", name).into());
+    }
+
+    fs::remove_file(&path).map_err(|e| format!("Failed to delete media file: {}", e).into())
+}
+
+/// Import a background image or video into the managed backgrounds folder
+#[tauri::command]
+pub fn import_background(state: State<'_, AppState>, path: String) -> Result<String, AppError> {
+    import_media(
+        &state,
+        &path,
+        &state.paths()?.backgrounds,
+        BACKGROUND_EXTENSIONS,
+    )
+}
+
+/// Import an audio file into the managed music folder
+#[tauri::command]
+pub fn import_music(state: State<'_, AppState>, path: String) -> Result<String, AppError> {
+    import_media(&state, &path, &state.paths()?.music, MUSIC_EXTENSIONS)
+}
+
+/// Delete a managed background by file name
+#[tauri::command]
+pub fn delete_background(state: State<'_, AppState>, name: String) -> Result<(), AppError> {
+    delete_media(&state.paths()?.backgrounds, &name)
+}
+
+/// Delete a managed music track by file name
+#[tauri::command]
+pub fn remove_music(state: State<'_, AppState>, name: String) -> Result<(), AppError> {
+    delete_media(&state.paths()?.music, &name)
+}
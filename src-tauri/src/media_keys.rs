@@ -0,0 +1,82 @@
+/**
+ * Keyboard media keys control the ambient music, even from other apps
+ */
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+
+use crate::launch;
+use crate::logging;
+use crate::preferences::UserPreferences;
+
+/// Media key -> event emitted to the frontend player
+const MEDIA_KEYS: &[(&str, &str)] = &[
+    ("MediaPlayPause", "media-play-pause"),
+    ("MediaTrackNext", "media-next-track"),
+];
+
+/// Registration failures are reported once per run, not on every retry
+static FAILURE_REPORTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize, Clone)]
+struct MediaKeysUnavailable {
+    key: String,
+    reason: String,
+}
+
+/// Media keys are claimed only while there is ambient music to control
+fn wanted(prefs: &UserPreferences) -> bool {
+    prefs.media_keys_enabled && prefs.bg_music_path.is_some() && !prefs.bg_music_muted
+}
+
+/// Register or release the media keys to match the preferences
+pub fn sync(app: &AppHandle, prefs: &UserPreferences) {
+    if wanted(prefs) {
+        register(app);
+    } else {
+        release(app);
+    }
+}
+
+fn register(app: &AppHandle) {
+    let shortcuts = app.global_shortcut();
+
+    for &(key, event) in MEDIA_KEYS {
+        if shortcuts.is_registered(key) {
+            continue;
+        }
+
+        let result = shortcuts.on_shortcut(key, move |app, _shortcut, e| {
+            if e.state() == ShortcutState::Pressed {
+                let _ = app.emit(event, ());
+            }
+        });
+        if let Err(e) = result {
+            // Usually grabbed by the OS or another player
+            logging::warn(&format!("Failed to register {}: {}", key, e));
+            if !FAILURE_REPORTED.swap(true, Ordering::SeqCst) {
+                launch::emit_when_ready(
+                    app,
+                    "media-keys-unavailable",
+                    MediaKeysUnavailable {
+                        key: key.to_string(),
+                        reason: e.to_string(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Give the media keys back to the system
+pub fn release(app: &AppHandle) {
+    let shortcuts = app.global_shortcut();
+    for &(key, _) in MEDIA_KEYS {
+        if shortcuts.is_registered(key) {
+            if let Err(e) = shortcuts.unregister(key) {
+                logging::warn(&format!("Failed to release {}: {}", key, e));
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+/**
+ * App metadata (meta.json): first-run and onboarding state
+ */
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use crate::config;
+use crate::maintenance::MaintenanceRecord;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct OnboardingState {
+    #[serde(rename = "hasCompletedWelcome", default)]
+    pub has_completed_welcome: bool,
+    #[serde(rename = "hasImportedFirstBook", default)]
+    pub has_imported_first_book: bool,
+    #[serde(rename = "hasSeenPresetTour", default)]
+    pub has_seen_preset_tour: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppMeta {
+    #[serde(rename = "firstLaunchAt", default)]
+    pub first_launch_at: Option<DateTime<Utc>>,
+    #[serde(rename = "builtinPresetsInstalled", default)]
+    pub builtin_presets_installed: bool,
+    /// Hash of the embedded built-in asset set last installed
+    #[serde(rename = "builtinAssetsHash", default)]
+    pub builtin_assets_hash: Option<String>,
+    /// Per-file hash of each built-in as last installed
+    #[serde(rename = "builtinAssets", default)]
+    pub builtin_assets: BTreeMap<String, String>,
+    #[serde(default)]
+    pub onboarding: OnboardingState,
+    #[serde(rename = "lastUpdateCheck", default)]
+    pub last_update_check: Option<DateTime<Utc>>,
+    /// Crash report already offered to the user
+    #[serde(rename = "lastSeenCrashReport", default)]
+    pub last_seen_crash_report: Option<String>,
+    /// Random id this install is known by to LAN sync peers
+    #[serde(rename = "deviceId", default)]
+    pub device_id: Option<String>,
+    /// Newest highlight already exported to Readwise, per destination
+    #[serde(rename = "readwiseCursors", default)]
+    pub readwise_cursors: BTreeMap<String, DateTime<Utc>>,
+    /// Annotations hash of each book last exported, per Obsidian vault
+    #[serde(rename = "obsidianExports", default)]
+    pub obsidian_exports: BTreeMap<String, BTreeMap<String, String>>,
+    /// Highlight picked as quote of the day, and the day it was picked for
+    #[serde(rename = "dailyQuote", default)]
+    pub daily_quote: Option<(NaiveDate, String)>,
+    /// When each highlight was last the quote of the day
+    #[serde(rename = "quoteLastShown", default)]
+    pub quote_last_shown: BTreeMap<String, DateTime<Utc>>,
+    /// Data directory the cloud sync warning was last shown for
+    #[serde(rename = "cloudSyncWarned", default)]
+    pub cloud_sync_warned: Option<String>,
+    /// Last and next run of each maintenance task
+    #[serde(default)]
+    pub maintenance: BTreeMap<String, MaintenanceRecord>,
+    /// Files in watched folders already taken in, so a book removed from
+    /// the library isn't imported again
+    #[serde(rename = "watchedFiles", default)]
+    pub watched_files: BTreeSet<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OnboardingInfo {
+    #[serde(rename = "isFirstRun")]
+    pub is_first_run: bool,
+    #[serde(rename = "firstLaunchAt")]
+    pub first_launch_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub state: OnboardingState,
+}
+
+fn meta_path() -> Result<std::path::PathBuf, String> {
+    Ok(config::get_app_dir_path()?.join("meta.json"))
+}
+
+/// Load meta.json, falling back to defaults when missing or unreadable
+pub fn load_meta() -> AppMeta {
+    let Ok(path) = meta_path() else {
+        return AppMeta::default();
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            eprintln!("Failed to parse meta.json, using defaults: {}", e);
+            AppMeta::default()
+        }),
+        Err(_) => AppMeta::default(),
+    }
+}
+
+pub fn save_meta(meta: &AppMeta) -> Result<(), String> {
+    let path = meta_path()?;
+
+    let json = serde_json::to_string_pretty(meta)
+        .map_err(|e| format!("Failed to serialize meta.json: {}", e))?;
+
+    fs::write(&path, json).map_err(|e| format!("Failed to write meta.json: {}", e))
+}
+
+/// Load, modify and save meta.json in one step
+pub fn update_meta<F: FnOnce(&mut AppMeta)>(f: F) -> Result<AppMeta, String> {
+    let mut meta = load_meta();
+    f(&mut meta);
+    save_meta(&meta)?;
+    Ok(meta)
+}
+
+/// Get the first-run / onboarding state
+#[tauri::command]
+pub fn get_onboarding_state() -> Result<OnboardingInfo, String> {
+    let meta = load_meta();
+
+    Ok(OnboardingInfo {
+        is_first_run: !meta.onboarding.has_completed_welcome,
+        first_launch_at: meta.first_launch_at,
+        state: meta.onboarding,
+    })
+}
+
+/// Mark an onboarding step as done
+#[tauri::command]
+pub fn set_onboarding_step(step: String) -> Result<OnboardingInfo, String> {
+    let valid_steps = [
+        "has_completed_welcome",
+        "has_imported_first_book",
+        "has_seen_preset_tour",
+    ];
+    if !valid_steps.contains(&step.as_str()) {
+        return Err(format!("Unknown onboarding step: {}", step));
+    }
+
+    update_meta(|meta| match step.as_str() {
+        "has_completed_welcome" => meta.onboarding.has_completed_welcome = true,
+        "has_imported_first_book" => meta.onboarding.has_imported_first_book = true,
+        _ => meta.onboarding.has_seen_preset_tour = true,
+    })?;
+
+    get_onboarding_state()
+}
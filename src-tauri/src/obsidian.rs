@@ -0,0 +1,339 @@
+/**
+ * Book notes in an Obsidian vault: one Markdown note per book, with the
+ * highlights kept in a managed section so the user's own writing survives
+ */
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+use crate::annotations::{self, BookAnnotations, Highlight, HighlightCategory};
+use crate::library::Book;
+use crate::meta;
+use crate::state::AppState;
+
+const SECTION_START: &str = "<!-- epilogue:highlights:start -->";
+const SECTION_END: &str = "<!-- epilogue:highlights:end -->";
+/// Frontmatter keys Epilogue writes; any other key is left alone
+const MANAGED_KEYS: &[&str] = &["title", "author", "tags", "progress", "rating", "finished"];
+/// Characters Obsidian or the filesystem won't take in a note name
+const INVALID_NAME_CHARS: &[char] = &[
+    '/', '\\', ':', '*', '?', '"', '<', '>', '|', '#', '^', '[', ']',
+];
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ObsidianNote {
+    pub path: String,
+    pub highlights: usize,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ObsidianExportSummary {
+    pub written: Vec<ObsidianNote>,
+    /// Books whose annotations hadn't changed since the last export
+    pub unchanged: usize,
+}
+
+/// "<Author> - <Title>.md", without characters that can't be in a note name
+pub fn note_file_name(book: &Book) -> String {
+    let name: String = format!("{} - {}", book.author, book.title)
+        .chars()
+        .map(|c| {
+            if INVALID_NAME_CHARS.contains(&c) {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("{}.md", name.trim().trim_end_matches('.'))
+}
+
+fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Obsidian tags can't contain spaces or most punctuation
+fn tag_slug(tag: &str) -> String {
+    tag.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '/' && c != '_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn frontmatter_lines(book: &Book) -> Vec<String> {
+    let mut tags: Vec<String> = std::iter::once("book".to_string())
+        .chain(book.tags.iter().map(|t| tag_slug(t)))
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.dedup();
+
+    vec![
+        format!("title: {}", yaml_string(&book.title)),
+        format!("author: {}", yaml_string(&book.author)),
+        format!("tags: [{}]", tags.join(", ")),
+        format!("progress: {}", (book.progress * 100.0).round() as u32),
+        format!(
+            "rating: {}",
+            book.rating.map(|r| r.to_string()).unwrap_or_default()
+        ),
+        format!(
+            "finished: {}",
+            book.finished_at
+                .map(|d| d.format("%Y-%m-%d").to_string())
+                .unwrap_or_default()
+        ),
+    ]
+}
+
+fn highlight_markdown(highlight: &Highlight) -> String {
+    let mut block: String = highlight
+        .text
+        .trim()
+        .lines()
+        .map(|line| format!("> {}\n", line))
+        .collect();
+    if let Some(note) = highlight.note.as_deref().filter(|n| !n.trim().is_empty()) {
+        block.push('\n');
+        block.push_str(note.trim());
+        block.push('\n');
+    }
+    block.push_str(&format!(
+        "\n— {}{}\n",
+        highlight
+            .chapter
+            .as_deref()
+            .map(|c| format!("{}, ", c))
+            .unwrap_or_default(),
+        highlight.created_at.format("%Y-%m-%d")
+    ));
+    block
+}
+
+/// The highlights section; with highlights in more than one category, a
+/// heading per category in palette order. Private categories are left out.
+fn managed_section(annotations: &BookAnnotations, categories: &[HighlightCategory]) -> String {
+    let mut highlights: Vec<&Highlight> = annotations
+        .highlights
+        .iter()
+        .filter(|h| !h.text.trim().is_empty())
+        .filter(|h| annotations::is_exported(h, categories))
+        .collect();
+    // Reading order rather than the order they were made
+    highlights.sort_by_key(|h| {
+        (
+            annotations::spine_index(&h.cfi),
+            annotations::char_offset(&h.cfi),
+        )
+    });
+
+    let mut section = format!("{}\n## Highlights\n", SECTION_START);
+    let category_id = |h: &Highlight| annotations::category_of(h, categories).map(|c| c.id.clone());
+    let first = highlights.first().map(|h| category_id(h));
+    if highlights.iter().all(|h| Some(category_id(h)) == first) {
+        for highlight in highlights {
+            section.push('\n');
+            section.push_str(&highlight_markdown(highlight));
+        }
+    } else {
+        for category in categories {
+            let mut in_category = highlights
+                .iter()
+                .filter(|h| category_id(h).as_ref() == Some(&category.id))
+                .peekable();
+            if in_category.peek().is_none() {
+                continue;
+            }
+            section.push_str(&format!("\n### {}\n", category.label.trim()));
+            for highlight in in_category {
+                section.push('\n');
+                section.push_str(&highlight_markdown(highlight));
+            }
+        }
+    }
+    section.push_str(SECTION_END);
+    section
+}
+
+/// Split a note into its frontmatter lines and the body after it
+fn split_frontmatter(content: &str) -> (Vec<String>, String) {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return (Vec::new(), content.to_string());
+    };
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line == "---" {
+            return (lines, rest[offset..].to_string());
+        }
+        lines.push(line.to_string());
+    }
+    // No closing delimiter, so it wasn't frontmatter after all
+    (Vec::new(), content.to_string())
+}
+
+fn is_managed_key(line: &str) -> bool {
+    line.split_once(':')
+        .is_some_and(|(key, _)| MANAGED_KEYS.contains(&key.trim()))
+}
+
+/// Replace the managed frontmatter keys and section of `existing`, keeping
+/// everything else. A note without the section markers gets the section
+/// appended.
+fn update_note(existing: &str, book: &Book, section: &str) -> String {
+    let (old_frontmatter, body) = split_frontmatter(existing);
+    let mut frontmatter = frontmatter_lines(book);
+    let mut skipping_list = false;
+    for line in old_frontmatter {
+        // Block-style lists of a managed key ("tags:\n  - a") go with it
+        if skipping_list && (line.starts_with(' ') || line.starts_with('-')) {
+            continue;
+        }
+        skipping_list = is_managed_key(&line);
+        if !skipping_list {
+            frontmatter.push(line);
+        }
+    }
+
+    let body = match (body.find(SECTION_START), body.find(SECTION_END)) {
+        (Some(start), Some(end)) if start < end => format!(
+            "{}{}{}",
+            &body[..start],
+            section,
+            &body[end + SECTION_END.len()..]
+        ),
+        _ if body.trim().is_empty() => format!("\n# {}\n\n{}\n", book.title, section),
+        _ => format!("{}\n\n{}\n", body.trim_end(), section),
+    };
+    format!("---\n{}\n---\n{}", frontmatter.join("\n"), body)
+}
+
+/// Hash of what goes into a note's section, palette included since the
+/// category headings come from it
+fn annotations_hash(
+    annotations: &BookAnnotations,
+    categories: &[HighlightCategory],
+) -> Result<String, String> {
+    let json = serde_json::to_vec(&(&annotations.highlights, categories))
+        .map_err(|e| format!("Failed to serialize annotations: {}", e))?;
+    Ok(format!("{:x}", md5::compute(json)))
+}
+
+fn write_note(
+    vault: &Path,
+    book: &Book,
+    annotations: &BookAnnotations,
+    categories: &[HighlightCategory],
+) -> Result<ObsidianNote, String> {
+    let path = vault.join(note_file_name(book));
+    let existing = if path.exists() {
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read note: {}", e))?
+    } else {
+        String::new()
+    };
+
+    let content = update_note(&existing, book, &managed_section(annotations, categories));
+    if content != existing {
+        fs::write(&path, content).map_err(|e| format!("Failed to write note: {}", e))?;
+    }
+    Ok(ObsidianNote {
+        path: path.to_string_lossy().to_string(),
+        highlights: annotations.highlights.len(),
+    })
+}
+
+fn vault_dir(vault_path: &str) -> Result<PathBuf, String> {
+    let vault = PathBuf::from(vault_path);
+    if !vault.is_dir() {
+        return Err(format!("Vault folder not found: {}", vault_path));
+    }
+    Ok(vault)
+}
+
+fn remember_exports(vault_path: &str, hashes: BTreeMap<String, String>) -> Result<(), String> {
+    meta::update_meta(|m| {
+        m.obsidian_exports
+            .entry(vault_path.to_string())
+            .or_default()
+            .extend(hashes);
+    })?;
+    Ok(())
+}
+
+/// Write or update the note of one book in the vault
+#[tauri::command]
+pub fn export_to_obsidian(
+    state: State<'_, AppState>,
+    vault_path: String,
+    book_id: String,
+) -> Result<ObsidianNote, String> {
+    let vault = vault_dir(&vault_path)?;
+    let book = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .find(|b| b.id == book_id)
+            .cloned()
+            .ok_or_else(|| format!("Book with id '{}' not found", book_id))
+    })??;
+    let annotations = annotations::load_annotations(&state, &book.id)?;
+    let categories = state.preferences()?.highlight_categories;
+
+    let note = write_note(&vault, &book, &annotations, &categories)?;
+    remember_exports(
+        &vault_path,
+        BTreeMap::from([(
+            book.id.clone(),
+            annotations_hash(&annotations, &categories)?,
+        )]),
+    )?;
+    Ok(note)
+}
+
+/// Update the notes of every book with highlights, skipping books whose
+/// annotations haven't changed since they were last exported to this vault
+#[tauri::command]
+pub fn export_all_to_obsidian(
+    state: State<'_, AppState>,
+    vault_path: String,
+) -> Result<ObsidianExportSummary, String> {
+    let vault = vault_dir(&vault_path)?;
+    let books = state.with_library(|library| library.books.clone())?;
+    let exported = meta::load_meta()
+        .obsidian_exports
+        .remove(&vault_path)
+        .unwrap_or_default();
+    let categories = state.preferences()?.highlight_categories;
+
+    let mut summary = ObsidianExportSummary::default();
+    let mut hashes = BTreeMap::new();
+    for book in &books {
+        let annotations = annotations::load_annotations(&state, &book.id)?;
+        if annotations.highlights.is_empty() {
+            continue;
+        }
+        let hash = annotations_hash(&annotations, &categories)?;
+        // A deleted note is written again even if nothing changed
+        if exported.get(&book.id) == Some(&hash) && vault.join(note_file_name(book)).exists() {
+            summary.unchanged += 1;
+            continue;
+        }
+
+        summary
+            .written
+            .push(write_note(&vault, book, &annotations, &categories)?);
+        hashes.insert(book.id.clone(), hash);
+    }
+
+    if !hashes.is_empty() {
+        remember_exports(&vault_path, hashes)?;
+    }
+    Ok(summary)
+}
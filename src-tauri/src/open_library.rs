@@ -0,0 +1,324 @@
+/**
+ * Book metadata from Open Library, fetched only when the user asks
+ */
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+use crate::isbn;
+use crate::library::{self, Book};
+use crate::logging;
+use crate::state::AppState;
+
+const SEARCH_URL: &str = "https://openlibrary.org/search.json";
+const WORKS_URL: &str = "https://openlibrary.org";
+const COVERS_URL: &str = "https://covers.openlibrary.org/b";
+const SEARCH_FIELDS: &str = "key,title,author_name,first_publish_year,subject,cover_i,isbn";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// Open Library asks identified clients to stay under three requests a second
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_millis(350);
+const MAX_CANDIDATES: usize = 5;
+const MAX_SUBJECTS: usize = 12;
+
+/// Fields apply_fetched_metadata can set
+pub const METADATA_FIELDS: &[&str] = &[
+    "title",
+    "author",
+    "description",
+    "publishYear",
+    "subjects",
+    "isbn",
+];
+
+/// When the next request may go out
+static NEXT_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// A possible match for a book, for the user to confirm
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetadataCandidate {
+    /// Open Library work key, "/works/OL…W"
+    pub key: String,
+    pub title: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(rename = "publishYear", default)]
+    pub publish_year: Option<i32>,
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    #[serde(rename = "coverUrl", default)]
+    pub cover_url: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub isbn: Option<String>,
+}
+
+/// Wait until the rate limit allows another request
+async fn wait_turn() {
+    let wait = {
+        let Ok(mut next) = NEXT_REQUEST.lock() else {
+            return;
+        };
+        let now = Instant::now();
+        let at = next.map_or(now, |t| t.max(now));
+        *next = Some(at + MIN_REQUEST_INTERVAL);
+        at - now
+    };
+    if !wait.is_zero() {
+        let _ = tauri::async_runtime::spawn_blocking(move || std::thread::sleep(wait)).await;
+    }
+}
+
+/// GET a URL on Open Library, keeping to the rate limit
+pub async fn get(url: reqwest::Url) -> Result<reqwest::Response, String> {
+    wait_turn().await;
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("Epilogue/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Open Library request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Open Library returned {}", response.status()));
+    }
+    Ok(response)
+}
+
+async fn get_json(url: reqwest::Url) -> Result<Value, String> {
+    get(url)
+        .await?
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse Open Library response: {}", e))
+}
+
+/// A string, or the {"type": "/type/text", "value": …} object some records
+/// use instead
+fn text_value(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) => Some(s.as_str()),
+        Value::Object(map) => map.get("value").and_then(Value::as_str),
+        Value::Array(items) => return items.iter().find_map(text_value),
+        _ => None,
+    }?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// A list of strings, also when a record has a single string instead
+fn text_values(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(items)) => items.iter().filter_map(text_value).collect(),
+        Some(value) => text_value(value).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// A year given as a number, or somewhere in a date string ("May 1999")
+fn year_value(value: Option<&Value>) -> Option<i32> {
+    match value? {
+        Value::Number(n) => n.as_i64().map(|n| n as i32),
+        Value::String(s) => s
+            .split(|c: char| !c.is_ascii_digit())
+            .find(|part| part.len() == 4)
+            .and_then(|part| part.parse().ok()),
+        Value::Array(items) => items.iter().find_map(|item| year_value(Some(item))),
+        _ => None,
+    }
+}
+
+pub fn cover_url_for_id(cover_id: i64) -> String {
+    format!("{}/id/{}-L.jpg", COVERS_URL, cover_id)
+}
+
+/// The large cover of an edition. `default=false` turns a missing cover
+/// into a 404 instead of a blank image.
+pub fn cover_url_for_isbn(isbn: &str) -> String {
+    format!("{}/isbn/{}-L.jpg?default=false", COVERS_URL, isbn)
+}
+
+fn candidate(doc: &Value, isbn: Option<&str>) -> Option<MetadataCandidate> {
+    let key = doc.get("key").and_then(text_value)?;
+    let title = doc.get("title").and_then(text_value)?;
+    let mut subjects = text_values(doc.get("subject"));
+    subjects.truncate(MAX_SUBJECTS);
+    // The ISBN searched for, or the first valid one of the work's editions
+    let isbn = isbn.map(str::to_string).or_else(|| {
+        text_values(doc.get("isbn"))
+            .iter()
+            .find_map(|i| isbn::normalize_isbn(i).ok())
+    });
+    Some(MetadataCandidate {
+        key,
+        title,
+        authors: text_values(doc.get("author_name")),
+        publish_year: year_value(doc.get("first_publish_year")),
+        subjects,
+        cover_url: doc
+            .get("cover_i")
+            .and_then(Value::as_i64)
+            .map(cover_url_for_id),
+        description: None,
+        isbn,
+    })
+}
+
+pub async fn search(
+    params: &[(&str, &str)],
+    isbn: Option<&str>,
+) -> Result<Vec<MetadataCandidate>, String> {
+    let limit = MAX_CANDIDATES.to_string();
+    let mut query = params.to_vec();
+    query.extend([("fields", SEARCH_FIELDS), ("limit", limit.as_str())]);
+    let url = reqwest::Url::parse_with_params(SEARCH_URL, &query)
+        .map_err(|e| format!("Invalid search: {}", e))?;
+    let page = get_json(url).await?;
+    Ok(page
+        .get("docs")
+        .and_then(Value::as_array)
+        .map(|docs| docs.iter().filter_map(|doc| candidate(doc, isbn)).collect())
+        .unwrap_or_default())
+}
+
+/// The description of a work, which search results leave out
+async fn work_description(key: &str) -> Option<String> {
+    if !key.starts_with("/works/") {
+        return None;
+    }
+    let url = reqwest::Url::parse(&format!("{}{}.json", WORKS_URL, key)).ok()?;
+    match get_json(url).await {
+        Ok(work) => work.get("description").and_then(text_value),
+        Err(e) => {
+            logging::debug(&format!("No description for {}: {}", key, e));
+            None
+        }
+    }
+}
+
+/// Cover ids of a work
+pub async fn work_covers(key: &str) -> Result<Vec<i64>, String> {
+    let url = reqwest::Url::parse(&format!("{}{}.json", WORKS_URL, key))
+        .map_err(|e| format!("Invalid Open Library key: {}", e))?;
+    let work = get_json(url).await?;
+    Ok(work
+        .get("covers")
+        .and_then(Value::as_array)
+        .map(|covers| {
+            covers
+                .iter()
+                .filter_map(Value::as_i64)
+                // -1 marks a deleted cover
+                .filter(|id| *id > 0)
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+
+fn find_book(state: &AppState, book_id: &str) -> Result<Book, String> {
+    state
+        .with_library(|library| library.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book with id '{}' not found", book_id))
+}
+
+/// Look a book up on Open Library by its ISBN, or by title and author, and
+/// return the matches for the user to pick from
+#[tauri::command]
+pub async fn fetch_book_metadata(
+    app: AppHandle,
+    book_id: String,
+) -> Result<Vec<MetadataCandidate>, String> {
+    let book = find_book(&app.state::<AppState>(), &book_id)?;
+
+    let mut candidates = match &book.isbn {
+        Some(isbn) => search(&[("isbn", isbn.as_str())], Some(isbn)).await?,
+        None => Vec::new(),
+    };
+    if candidates.is_empty() {
+        let mut params = vec![("title", book.title.as_str())];
+        if !is_placeholder_author(&book.author) {
+            params.push(("author", book.author.as_str()));
+        }
+        candidates = search(&params, None).await?;
+    }
+
+    for candidate in &mut candidates {
+        candidate.description = work_description(&candidate.key).await;
+    }
+    Ok(candidates)
+}
+
+fn is_placeholder_title(title: &str) -> bool {
+    title.trim().is_empty() || title == "Untitled"
+}
+
+fn is_placeholder_author(author: &str) -> bool {
+    author.trim().is_empty() || author == "Unknown"
+}
+
+/// Copy the chosen `fields` (all by default) of a confirmed match to a
+/// book. Fields the book already has a value for are kept unless
+/// `overwrite` is set.
+#[tauri::command]
+pub fn apply_fetched_metadata(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    candidate: MetadataCandidate,
+    fields: Option<Vec<String>>,
+    overwrite: Option<bool>,
+) -> Result<Book, String> {
+    let fields = fields.unwrap_or_else(|| METADATA_FIELDS.iter().map(|f| f.to_string()).collect());
+    if let Some(unknown) = fields
+        .iter()
+        .find(|f| !METADATA_FIELDS.contains(&f.as_str()))
+    {
+        return Err(format!(
+            "Unknown metadata field '{}' (allowed: {})",
+            unknown,
+            METADATA_FIELDS.join(", ")
+        ));
+    }
+    let overwrite = overwrite.unwrap_or(false);
+    let wants = |field: &str| fields.iter().any(|f| f == field);
+
+    library::update_book(&app, &state, &book_id, |book| {
+        if wants("title") && (overwrite || is_placeholder_title(&book.title)) {
+            book.title = candidate.title.clone();
+        }
+        if wants("author")
+            && !candidate.authors.is_empty()
+            && (overwrite || is_placeholder_author(&book.author))
+        {
+            book.author = candidate.authors.join(", ");
+        }
+        if wants("description")
+            && candidate.description.is_some()
+            && (overwrite || book.description.is_none())
+        {
+            book.description = candidate.description.clone();
+        }
+        if wants("publishYear")
+            && candidate.publish_year.is_some()
+            && (overwrite || book.publish_year.is_none())
+        {
+            book.publish_year = candidate.publish_year;
+        }
+        if wants("subjects")
+            && !candidate.subjects.is_empty()
+            && (overwrite || book.subjects.is_empty())
+        {
+            book.subjects = candidate.subjects.clone();
+        }
+        if wants("isbn") && candidate.isbn.is_some() && (overwrite || book.isbn.is_none()) {
+            book.isbn = candidate.isbn.clone();
+        }
+        book.open_library_key = Some(candidate.key.clone());
+    })
+}
@@ -0,0 +1,1095 @@
+/**
+ * Chapters and whole books printed to PDF: the text laid out in pages with
+ * the book's images, a running header and page numbers
+ */
+use ego_tree::NodeRef;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use rusttype::{Font, Scale};
+use scraper::{Html, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+use crate::annotations;
+use crate::chapter;
+use crate::covers;
+use crate::crash;
+use crate::state::AppState;
+
+/// DejaVu Sans, embedded so any script the book uses prints
+const FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+/// Points in a millimetre
+const MM: f32 = 72.0 / 25.4;
+const DEFAULT_MARGIN_MM: f32 = 20.0;
+const MIN_MARGIN_MM: f32 = 5.0;
+const MAX_MARGIN_MM: f32 = 50.0;
+const DEFAULT_LINE_HEIGHT: f32 = 1.5;
+/// CSS pixels are three quarters of a point
+const PX_TO_PT: f32 = 0.75;
+const HEADER_PT: f32 = 8.5;
+/// Bold is drawn by stroking the outline this wide, as a share of the size
+const BOLD_STROKE: f32 = 0.03;
+/// Italic is drawn slanted by this much
+const ITALIC_SKEW: f32 = 0.2;
+/// Blockquotes and list items are indented this many ems
+const INDENT_EMS: f32 = 1.5;
+/// Highlight fragments shorter than this are too likely to match elsewhere
+const MIN_HIGHLIGHT_CHARS: usize = 4;
+/// Elements dropped with everything inside them
+const SKIPPED_TAGS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "math", "audio", "video", "iframe",
+    "object", "embed", "form", "button", "input", "select", "textarea",
+];
+/// Elements that start a new paragraph
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "li",
+    "blockquote",
+    "pre",
+    "dt",
+    "dd",
+    "td",
+    "th",
+    "tr",
+    "section",
+    "article",
+    "aside",
+    "header",
+    "footer",
+    "figure",
+    "figcaption",
+    "ul",
+    "ol",
+    "dl",
+    "table",
+    "hr",
+    "nav",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PaperSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl PaperSize {
+    /// Width and height in points
+    fn dimensions(self) -> (f32, f32) {
+        match self {
+            PaperSize::A4 => (595.28, 841.89),
+            PaperSize::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PdfExportOptions {
+    #[serde(rename = "paperSize", default)]
+    pub paper_size: PaperSize,
+    /// Underline the text of the book's highlights
+    #[serde(rename = "includeHighlights", default)]
+    pub include_highlights: bool,
+    /// Every chapter, each starting on a new page, instead of one
+    #[serde(rename = "wholeBook", default)]
+    pub whole_book: bool,
+    #[serde(rename = "marginMm", default)]
+    pub margin_mm: Option<f32>,
+    /// Line spacing as a multiple of the font size
+    #[serde(rename = "lineHeight", default)]
+    pub line_height: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PdfExport {
+    pub path: String,
+    pub pages: usize,
+    pub chapters: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Run {
+    text: String,
+    style: Style,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockKind {
+    Paragraph,
+    /// Blockquotes and list items
+    Indented,
+    Heading(u8),
+}
+
+struct Image {
+    rgb: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+enum Block {
+    Text(BlockKind, Vec<Run>),
+    /// Index into the document's images
+    Image(usize),
+    PageBreak,
+}
+
+/// Turns chapter markup into blocks of styled text and images
+struct Extractor<'a, R: Read + Seek> {
+    doc: &'a mut epub::doc::EpubDoc<R>,
+    chapter_href: String,
+    blocks: Vec<Block>,
+    runs: Vec<Run>,
+    kind: BlockKind,
+    images: &'a mut Vec<Image>,
+    /// Images by their path in the book, so one used twice is stored once
+    image_ids: &'a mut HashMap<PathBuf, usize>,
+}
+
+impl<R: Read + Seek> Extractor<'_, R> {
+    fn push_text(&mut self, text: &str, style: Style) {
+        let mut collapsed = String::with_capacity(text.len());
+        let mut space = false;
+        for c in text.chars() {
+            if c.is_whitespace() {
+                space = true;
+                continue;
+            }
+            if space {
+                collapsed.push(' ');
+                space = false;
+            }
+            collapsed.push(c);
+        }
+        if space {
+            collapsed.push(' ');
+        }
+        if collapsed.is_empty() {
+            return;
+        }
+        match self.runs.last_mut() {
+            Some(last) if last.style == style && last.text != "\n" => {
+                last.text.push_str(&collapsed)
+            }
+            _ => self.runs.push(Run {
+                text: collapsed,
+                style,
+            }),
+        }
+    }
+
+    /// End the paragraph being collected
+    fn flush(&mut self) {
+        let mut runs = std::mem::take(&mut self.runs);
+        // Spaces at the ends of a paragraph, and doubled between runs
+        let mut after_space = true;
+        for run in &mut runs {
+            if run.text == "\n" {
+                after_space = true;
+                continue;
+            }
+            if after_space {
+                run.text = run.text.trim_start().to_string();
+            }
+            if !run.text.is_empty() {
+                after_space = run.text.ends_with(' ');
+            }
+        }
+        while runs
+            .last()
+            .is_some_and(|r| r.text == "\n" || r.text.trim().is_empty())
+        {
+            runs.pop();
+        }
+        if let Some(last) = runs.last_mut() {
+            last.text = last.text.trim_end().to_string();
+        }
+        runs.retain(|r| !r.text.is_empty());
+        if !runs.is_empty() {
+            self.blocks.push(Block::Text(self.kind, runs));
+        }
+    }
+
+    fn push_image(&mut self, src: &str) {
+        if src.starts_with("data:") {
+            return;
+        }
+        let resolved = chapter::resolve_href(src, Some(&self.chapter_href));
+        let Some((resource, _)) = chapter::resource_path(self.doc, &resolved) else {
+            return;
+        };
+        let id = match self.image_ids.get(&resource) {
+            Some(&id) => Some(id),
+            // SVG, GIF and WebP aren't decoded, and are left out
+            None => self
+                .doc
+                .get_resource_by_path(&resource)
+                .and_then(|data| covers::decode_rgb_data(&data))
+                .filter(|(_, width, height)| *width > 0 && *height > 0)
+                .map(|(rgb, width, height)| {
+                    self.images.push(Image { rgb, width, height });
+                    self.image_ids.insert(resource, self.images.len() - 1);
+                    self.images.len() - 1
+                }),
+        };
+        if let Some(id) = id {
+            self.flush();
+            self.blocks.push(Block::Image(id));
+        }
+    }
+
+    fn walk(&mut self, node: NodeRef<Node>, style: Style) {
+        for child in node.children() {
+            let element = match child.value() {
+                Node::Text(text) => {
+                    self.push_text(text, style);
+                    continue;
+                }
+                Node::Element(element) => element,
+                _ => continue,
+            };
+            let name = element.name().to_lowercase();
+            if SKIPPED_TAGS.contains(&name.as_str()) {
+                continue;
+            }
+            match name.as_str() {
+                "br" => {
+                    self.runs.push(Run {
+                        text: "\n".to_string(),
+                        style,
+                    });
+                    continue;
+                }
+                "img" => {
+                    if let Some(src) = element.attr("src") {
+                        self.push_image(src);
+                    }
+                    continue;
+                }
+                // SVG <image href> or <image xlink:href>
+                "image" => {
+                    let href = element
+                        .attrs()
+                        .find(|(name, _)| *name == "href" || name.ends_with(":href"))
+                        .map(|(_, v)| v.to_string());
+                    if let Some(href) = href {
+                        self.push_image(&href);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let style = Style {
+                bold: style.bold || matches!(name.as_str(), "b" | "strong" | "th"),
+                italic: style.italic || matches!(name.as_str(), "i" | "em" | "cite" | "var"),
+                underline: style.underline || name == "u",
+            };
+            if BLOCK_TAGS.contains(&name.as_str()) {
+                self.flush();
+                let outer = self.kind;
+                self.kind = match name.as_bytes() {
+                    [b'h', level @ b'1'..=b'6'] => BlockKind::Heading(level - b'0'),
+                    _ if matches!(name.as_str(), "blockquote" | "li" | "dd") => BlockKind::Indented,
+                    _ => outer,
+                };
+                self.walk(child, style);
+                self.flush();
+                self.kind = outer;
+            } else {
+                self.walk(child, style);
+            }
+        }
+    }
+}
+
+/// Split runs so the first occurrence of `text` is underlined. Returns
+/// whether it was found.
+fn underline(runs: &mut Vec<Run>, text: &str) -> bool {
+    let plain: String = runs.iter().map(|r| r.text.as_str()).collect();
+    let Some(start) = plain.find(text) else {
+        return false;
+    };
+    let end = start + text.len();
+
+    let mut split = Vec::with_capacity(runs.len() + 2);
+    let mut pos = 0;
+    for run in runs.drain(..) {
+        let (from, to) = (pos, pos + run.text.len());
+        pos = to;
+        let cut_start = start.clamp(from, to) - from;
+        let cut_end = end.clamp(from, to) - from;
+        for (range, underlined) in [
+            (0..cut_start, false),
+            (cut_start..cut_end, true),
+            (cut_end..run.text.len(), false),
+        ] {
+            if range.is_empty() {
+                continue;
+            }
+            split.push(Run {
+                text: run.text[range].to_string(),
+                style: Style {
+                    underline: run.style.underline || underlined,
+                    ..run.style
+                },
+            });
+        }
+    }
+    *runs = split;
+    true
+}
+
+/// Underline each paragraph of each highlight where it first appears
+fn underline_highlights(blocks: &mut [Block], highlights: &[String]) {
+    for highlight in highlights {
+        for part in highlight.split('\n') {
+            let part = part.split_whitespace().collect::<Vec<_>>().join(" ");
+            if part.chars().count() < MIN_HIGHLIGHT_CHARS {
+                continue;
+            }
+            for block in blocks.iter_mut() {
+                if let Block::Text(_, runs) = block {
+                    if underline(runs, &part) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Page {
+    ops: String,
+    images: Vec<usize>,
+    empty: bool,
+}
+
+/// A piece of a line: a word, or a single CJK character
+struct Piece {
+    text: String,
+    style: Style,
+    space_before: bool,
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x11FF | 0x2E80..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF
+            | 0xFF00..=0xFFEF | 0x20000..=0x2FA1F)
+}
+
+/// Pieces a line may break between, with None for a forced line break
+fn pieces(runs: &[Run]) -> Vec<Option<Piece>> {
+    let mut pieces = Vec::new();
+    let mut space = false;
+    for run in runs {
+        if run.text == "\n" {
+            pieces.push(None);
+            space = false;
+            continue;
+        }
+        let mut current = String::new();
+        let mut flush = |current: &mut String, space: &mut bool| {
+            if !current.is_empty() {
+                pieces.push(Some(Piece {
+                    text: std::mem::take(current),
+                    style: run.style,
+                    space_before: *space,
+                }));
+                *space = false;
+            }
+        };
+        for c in run.text.chars() {
+            if c.is_whitespace() {
+                flush(&mut current, &mut space);
+                space = true;
+            } else if is_cjk(c) {
+                flush(&mut current, &mut space);
+                current.push(c);
+                flush(&mut current, &mut space);
+            } else {
+                // A word running over from the run before stays one piece
+                current.push(c);
+            }
+        }
+        flush(&mut current, &mut space);
+    }
+    pieces
+}
+
+/// Text encoded for a PDF content stream: glyph ids as hex
+fn glyph_hex(font: &Font, text: &str, used: &mut BTreeMap<u16, char>) -> String {
+    let mut hex = String::with_capacity(text.len() * 4);
+    for c in text.chars() {
+        let id = font.glyph(c).id().0;
+        used.entry(id).or_insert(c);
+        let _ = write!(hex, "{:04X}", id);
+    }
+    hex
+}
+
+/// A string for the document info, in UTF-16 so any title fits
+fn info_string(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        let _ = write!(hex, "{:04X}", unit);
+    }
+    hex.push('>');
+    hex
+}
+
+struct Layout<'a> {
+    font: &'a Font<'a>,
+    /// Scale at which glyph metrics come out in ems
+    em: Scale,
+    ascent: f32,
+    descent: f32,
+    width: f32,
+    height: f32,
+    margin: f32,
+    body_size: f32,
+    line_height: f32,
+    pages: Vec<Page>,
+    /// Top of the space left on the current page
+    y: f32,
+    used: BTreeMap<u16, char>,
+}
+
+impl Layout<'_> {
+    fn advance(&self, c: char) -> f32 {
+        self.font.glyph(c).scaled(self.em).h_metrics().advance_width
+    }
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.advance(c)).sum::<f32>() * size
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page {
+            empty: true,
+            ..Page::default()
+        });
+        self.y = self.height - self.margin;
+    }
+
+    fn page(&mut self) -> &mut Page {
+        if self.pages.is_empty() {
+            self.new_page();
+        }
+        self.pages.last_mut().expect("a page was just added")
+    }
+
+    /// Start a new page unless `height` still fits on this one
+    fn reserve(&mut self, height: f32) {
+        let empty = self.page().empty;
+        if self.y - height < self.margin && !empty {
+            self.new_page();
+        }
+    }
+
+    fn show(&mut self, x: f32, baseline: f32, text: &str, size: f32, style: Style) {
+        let hex = glyph_hex(self.font, text, &mut self.used);
+        let skew = if style.italic { ITALIC_SKEW } else { 0.0 };
+        let mode = if style.bold {
+            format!("2 Tr {:.2} w", size * BOLD_STROKE)
+        } else {
+            "0 Tr".to_string()
+        };
+        let underline = style.underline.then(|| {
+            format!(
+                "{:.2} {:.2} {:.2} {:.2} re f\n",
+                x,
+                baseline - size * 0.15,
+                self.text_width(text, size),
+                (size * 0.06).max(0.4)
+            )
+        });
+        let page = self.page();
+        page.empty = false;
+        let _ = writeln!(
+            page.ops,
+            "BT /F1 {:.2} Tf {} 1 0 {:.2} 1 {:.2} {:.2} Tm <{}> Tj ET",
+            size, mode, skew, x, baseline, hex
+        );
+        if let Some(underline) = underline {
+            page.ops.push_str(&underline);
+        }
+    }
+
+    /// Lay out a line of pieces at the current position
+    fn line(&mut self, line: &[(f32, &Piece)], size: f32, indent: f32) {
+        let advance = size * self.line_height;
+        self.reserve(advance);
+        let baseline =
+            self.y - (advance - (self.ascent + self.descent) * size) / 2.0 - self.ascent * size;
+
+        // Neighbouring pieces of one style are drawn as one string, with the
+        // spaces between them so the text can be copied
+        let mut start = 0;
+        while start < line.len() {
+            let style = line[start].1.style;
+            let mut end = start + 1;
+            while end < line.len() && line[end].1.style == style {
+                end += 1;
+            }
+            let mut text = String::new();
+            for (i, (_, piece)) in line[start..end].iter().enumerate() {
+                if piece.space_before && i > 0 {
+                    text.push(' ');
+                }
+                text.push_str(&piece.text);
+            }
+            self.show(
+                self.margin + indent + line[start].0,
+                baseline,
+                &text,
+                size,
+                style,
+            );
+            start = end;
+        }
+        self.y -= advance;
+    }
+
+    fn paragraph(&mut self, runs: &[Run], size: f32, indent: f32, bold: bool) {
+        let max_width = self.width - 2.0 * self.margin - indent;
+        let space = self.text_width(" ", size);
+        let mut all = pieces(runs);
+        if bold {
+            for piece in all.iter_mut().flatten() {
+                piece.style.bold = true;
+            }
+        }
+
+        // Words too long for a line are cut where they reach its end
+        let mut split = Vec::with_capacity(all.len());
+        for piece in all {
+            let Some(piece) = piece else {
+                split.push(None);
+                continue;
+            };
+            if self.text_width(&piece.text, size) <= max_width {
+                split.push(Some(piece));
+                continue;
+            }
+            let mut current = String::new();
+            let mut space_before = piece.space_before;
+            for c in piece.text.chars() {
+                current.push(c);
+                if self.text_width(&current, size) > max_width && current.chars().count() > 1 {
+                    current.pop();
+                    split.push(Some(Piece {
+                        text: std::mem::take(&mut current),
+                        style: piece.style,
+                        space_before,
+                    }));
+                    space_before = false;
+                    current.push(c);
+                }
+            }
+            split.push(Some(Piece {
+                text: current,
+                style: piece.style,
+                space_before,
+            }));
+        }
+
+        let mut line: Vec<(f32, &Piece)> = Vec::new();
+        let mut x = 0.0;
+        for piece in &split {
+            let Some(piece) = piece else {
+                self.line(&line, size, indent);
+                line.clear();
+                x = 0.0;
+                continue;
+            };
+            let gap = if piece.space_before && !line.is_empty() {
+                space
+            } else {
+                0.0
+            };
+            let width = self.text_width(&piece.text, size);
+            if !line.is_empty() && x + gap + width > max_width {
+                self.line(&line, size, indent);
+                line.clear();
+                x = 0.0;
+                line.push((0.0, piece));
+                x += width;
+                continue;
+            }
+            line.push((x + gap, piece));
+            x += gap + width;
+        }
+        if !line.is_empty() {
+            self.line(&line, size, indent);
+        }
+    }
+
+    fn image(&mut self, id: usize, image: &Image) {
+        let max_width = self.width - 2.0 * self.margin;
+        let max_height = self.height - 2.0 * self.margin;
+        let scale = PX_TO_PT
+            .min(max_width / image.width as f32)
+            .min(max_height / image.height as f32);
+        let (width, height) = (image.width as f32 * scale, image.height as f32 * scale);
+        self.reserve(height);
+        let x = self.margin + (max_width - width) / 2.0;
+        let y = self.y - height;
+        let page = self.page();
+        page.empty = false;
+        if !page.images.contains(&id) {
+            page.images.push(id);
+        }
+        let _ = writeln!(
+            page.ops,
+            "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q",
+            width, height, x, y, id
+        );
+        self.y = y - self.body_size * 0.5;
+    }
+
+    fn blocks(&mut self, blocks: &[Block], images: &[Image]) {
+        for block in blocks {
+            match block {
+                Block::PageBreak => {
+                    if !self.page().empty {
+                        self.new_page();
+                    }
+                }
+                Block::Image(id) => self.image(*id, &images[*id]),
+                Block::Text(BlockKind::Heading(level), runs) => {
+                    let factor = match level {
+                        1 => 1.6,
+                        2 => 1.4,
+                        3 => 1.2,
+                        _ => 1.1,
+                    };
+                    let size = self.body_size * factor;
+                    // A heading isn't left alone at the bottom of a page
+                    self.reserve(size * self.line_height * 2.0 + self.body_size * 3.0);
+                    if !self.page().empty {
+                        self.y -= size * 0.6;
+                    }
+                    self.paragraph(runs, size, 0.0, true);
+                    self.y -= size * 0.4;
+                }
+                Block::Text(kind, runs) => {
+                    let indent = if *kind == BlockKind::Indented {
+                        self.body_size * INDENT_EMS
+                    } else {
+                        0.0
+                    };
+                    self.paragraph(runs, self.body_size, indent, false);
+                    self.y -= self.body_size * 0.5;
+                }
+            }
+        }
+    }
+
+    /// The title and author at the top of each page, and the page number at
+    /// the bottom
+    fn decorate(&mut self, header: &str) {
+        let count = self.pages.len();
+        let max_width = self.width - 2.0 * self.margin;
+        let mut header = header.to_string();
+        if self.text_width(&header, HEADER_PT) > max_width {
+            while !header.is_empty()
+                && self.text_width(&format!("{}\u{2026}", header), HEADER_PT) > max_width
+            {
+                header.pop();
+            }
+            header.push('\u{2026}');
+        }
+        let header_y = self.height - self.margin / 2.0;
+        for index in 0..count {
+            let number = format!("{} / {}", index + 1, count);
+            let number_x = (self.width - self.text_width(&number, HEADER_PT)) / 2.0;
+            let header_hex = glyph_hex(self.font, &header, &mut self.used);
+            let number_hex = glyph_hex(self.font, &number, &mut self.used);
+            let page = &mut self.pages[index];
+            let _ = writeln!(
+                page.ops,
+                "0.4 g BT /F1 {size} Tf 0 Tr 1 0 0 1 {:.2} {:.2} Tm <{}> Tj ET \
+                 BT /F1 {size} Tf 0 Tr 1 0 0 1 {:.2} {:.2} Tm <{}> Tj ET 0 g",
+                self.margin,
+                header_y,
+                header_hex,
+                number_x,
+                self.margin / 2.0 - HEADER_PT,
+                number_hex,
+                size = HEADER_PT,
+            );
+        }
+    }
+}
+
+/// PDF objects, numbered from 1 in the order they are added
+#[derive(Default)]
+struct PdfWriter {
+    objects: Vec<Vec<u8>>,
+}
+
+impl PdfWriter {
+    /// Number an object that is written later
+    fn reserve(&mut self) -> usize {
+        self.objects.push(Vec::new());
+        self.objects.len()
+    }
+
+    fn set(&mut self, id: usize, body: impl Into<Vec<u8>>) {
+        self.objects[id - 1] = body.into();
+    }
+
+    fn add(&mut self, body: impl Into<Vec<u8>>) -> usize {
+        let id = self.reserve();
+        self.set(id, body);
+        id
+    }
+
+    /// A compressed stream with the given dictionary entries
+    fn add_stream(&mut self, dict: &str, data: &[u8]) -> Result<usize, String> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(data)
+            .map_err(|e| format!("Failed to compress PDF stream: {}", e))?;
+        let compressed = encoder
+            .finish()
+            .map_err(|e| format!("Failed to compress PDF stream: {}", e))?;
+        let mut body = format!(
+            "<< {} /Filter /FlateDecode /Length {} >>\nstream\n",
+            dict,
+            compressed.len()
+        )
+        .into_bytes();
+        body.extend_from_slice(&compressed);
+        body.extend_from_slice(b"\nendstream");
+        Ok(self.add(body))
+    }
+
+    fn finish(self, root: usize, info: usize) -> Vec<u8> {
+        let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(self.objects.len());
+        for (index, body) in self.objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+            out.extend_from_slice(body);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.objects.len() + 1,
+            root,
+            info,
+            xref
+        );
+        out.extend_from_slice(table.as_bytes());
+        out
+    }
+}
+
+/// The embedded font: a CID font over the TrueType file, with the widths
+/// and a ToUnicode map of the glyphs used so the text can be copied
+fn write_font(pdf: &mut PdfWriter, layout: &Layout) -> Result<usize, String> {
+    let font_file = pdf.add_stream(&format!("/Length1 {}", FONT.len()), FONT)?;
+    let units = layout.font.units_per_em() as f32;
+    let metrics = layout.font.v_metrics_unscaled();
+    let descriptor = pdf.add(format!(
+        "<< /Type /FontDescriptor /FontName /DejaVuSans /Flags 32 \
+         /FontBBox [-1021 -463 1793 1232] /ItalicAngle 0 /Ascent {:.0} /Descent {:.0} \
+         /CapHeight {:.0} /StemV 80 /FontFile2 {} 0 R >>",
+        metrics.ascent / units * 1000.0,
+        metrics.descent / units * 1000.0,
+        metrics.ascent / units * 1000.0,
+        font_file
+    ));
+
+    let mut widths = String::new();
+    for (&id, &c) in &layout.used {
+        let _ = write!(widths, "{} [{:.0}] ", id, layout.advance(c) * 1000.0);
+    }
+    let cid_font = pdf.add(format!(
+        "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /DejaVuSans \
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+         /FontDescriptor {} 0 R /CIDToGIDMap /Identity /W [{}] >>",
+        descriptor, widths
+    ));
+
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+         1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    );
+    let used: Vec<(&u16, &char)> = layout.used.iter().collect();
+    // bfchar sections hold at most 100 entries
+    for chunk in used.chunks(100) {
+        let _ = writeln!(cmap, "{} beginbfchar", chunk.len());
+        for (id, c) in chunk {
+            let mut utf16 = String::new();
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                let _ = write!(utf16, "{:04X}", unit);
+            }
+            let _ = writeln!(cmap, "<{:04X}> <{}>", id, utf16);
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    let to_unicode = pdf.add_stream("", cmap.as_bytes())?;
+
+    Ok(pdf.add(format!(
+        "<< /Type /Font /Subtype /Type0 /BaseFont /DejaVuSans /Encoding /Identity-H \
+         /DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>",
+        cid_font, to_unicode
+    )))
+}
+
+fn write_pdf(
+    layout: &Layout,
+    images: &[Image],
+    title: &str,
+    author: &str,
+) -> Result<Vec<u8>, String> {
+    let mut pdf = PdfWriter::default();
+    let catalog = pdf.reserve();
+    let pages = pdf.reserve();
+    let font = write_font(&mut pdf, layout)?;
+
+    let mut image_objects = Vec::with_capacity(images.len());
+    for image in images {
+        image_objects.push(pdf.add_stream(
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} \
+                 /ColorSpace /DeviceRGB /BitsPerComponent 8",
+                image.width, image.height
+            ),
+            &image.rgb,
+        )?);
+    }
+
+    let mut kids = Vec::with_capacity(layout.pages.len());
+    for page in &layout.pages {
+        let content = pdf.add_stream("", page.ops.as_bytes())?;
+        let xobjects: String = page
+            .images
+            .iter()
+            .map(|&id| format!("/Im{} {} 0 R ", id, image_objects[id]))
+            .collect();
+        kids.push(pdf.add(format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] \
+             /Resources << /Font << /F1 {} 0 R >> /XObject << {}>> >> /Contents {} 0 R >>",
+            pages, layout.width, layout.height, font, xobjects, content
+        )));
+    }
+    pdf.set(
+        pages,
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.iter()
+                .map(|id| format!("{} 0 R", id))
+                .collect::<Vec<_>>()
+                .join(" "),
+            kids.len()
+        ),
+    );
+    pdf.set(
+        catalog,
+        format!("<< /Type /Catalog /Pages {} 0 R >>", pages),
+    );
+    let info = pdf.add(format!(
+        "<< /Title {} /Author {} /Producer (Epilogue) >>",
+        info_string(title),
+        info_string(author)
+    ));
+    Ok(pdf.finish(catalog, info))
+}
+
+fn export_pdf(
+    state: &AppState,
+    book_id: &str,
+    spine_index: usize,
+    dest: &str,
+    options: &PdfExportOptions,
+) -> Result<PdfExport, String> {
+    if !dest.to_lowercase().ends_with(".pdf") {
+        return Err(format!("PDFs are saved as .pdf, got {}", dest));
+    }
+    let margin_mm = options.margin_mm.unwrap_or(DEFAULT_MARGIN_MM);
+    if !(MIN_MARGIN_MM..=MAX_MARGIN_MM).contains(&margin_mm) {
+        return Err(format!(
+            "Margins must be between {} and {} mm, got {}",
+            MIN_MARGIN_MM, MAX_MARGIN_MM, margin_mm
+        ));
+    }
+    let line_height = options.line_height.unwrap_or(DEFAULT_LINE_HEIGHT);
+    if !(1.0..=3.0).contains(&line_height) {
+        return Err(format!(
+            "Line height must be between 1 and 3, got {}",
+            line_height
+        ));
+    }
+
+    let book = state
+        .with_library(|library| library.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book with id '{}' not found", book_id))?;
+    book.require_text()?;
+    let preferences = state.preferences()?;
+    let highlights: Vec<(usize, String)> = if options.include_highlights {
+        annotations::load_annotations(state, book_id)?
+            .highlights
+            .into_iter()
+            .filter(|h| annotations::is_exported(h, &preferences.highlight_categories))
+            .filter_map(|h| Some((annotations::spine_index(&h.cfi)?, h.text)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut doc = epub::doc::EpubDoc::new(&book.file_path)
+        .map_err(|e| format!("Failed to open EPUB: {:?}", e))?;
+    let spine: Vec<(PathBuf, String)> = doc
+        .spine
+        .iter()
+        .filter_map(|item| doc.resources.get(&item.idref).cloned())
+        .collect();
+    let chapters: Vec<usize> = if options.whole_book {
+        (0..spine.len()).collect()
+    } else if spine_index < spine.len() {
+        vec![spine_index]
+    } else {
+        return Err(format!(
+            "'{}' has {} chapters, there is no chapter {}",
+            book.title,
+            spine.len(),
+            spine_index
+        ));
+    };
+
+    let mut blocks = Vec::new();
+    let mut images = Vec::new();
+    let mut image_ids = HashMap::new();
+    let mut printed = 0;
+    for index in chapters {
+        let (path, mime) = &spine[index];
+        if !mime.contains("html") {
+            continue;
+        }
+        let Some(content) = doc.get_resource_str_by_path(path) else {
+            continue;
+        };
+        let document = Html::parse_document(&content);
+        let mut extractor = Extractor {
+            doc: &mut doc,
+            chapter_href: path.to_string_lossy().replace('\\', "/"),
+            blocks: Vec::new(),
+            runs: Vec::new(),
+            kind: BlockKind::Paragraph,
+            images: &mut images,
+            image_ids: &mut image_ids,
+        };
+        extractor.walk(document.tree.root(), Style::default());
+        extractor.flush();
+        let mut chapter_blocks = extractor.blocks;
+        if chapter_blocks.is_empty() {
+            continue;
+        }
+        let chapter_highlights: Vec<String> = highlights
+            .iter()
+            .filter(|(at, _)| *at == index)
+            .map(|(_, text)| text.clone())
+            .collect();
+        underline_highlights(&mut chapter_blocks, &chapter_highlights);
+
+        if printed > 0 {
+            blocks.push(Block::PageBreak);
+        }
+        blocks.append(&mut chapter_blocks);
+        printed += 1;
+    }
+    if printed == 0 {
+        return Err("Nothing to print in this chapter".to_string());
+    }
+
+    let font = Font::try_from_bytes(FONT).ok_or("Failed to load the PDF font")?;
+    let metrics = font.v_metrics_unscaled();
+    let units = font.units_per_em() as f32;
+    let (width, height) = options.paper_size.dimensions();
+    let mut layout = Layout {
+        em: Scale::uniform((metrics.ascent - metrics.descent) / units),
+        ascent: metrics.ascent / units,
+        descent: -metrics.descent / units,
+        font: &font,
+        width,
+        height,
+        margin: margin_mm * MM,
+        body_size: preferences.font_size as f32 * PX_TO_PT,
+        line_height,
+        pages: Vec::new(),
+        y: 0.0,
+        used: BTreeMap::new(),
+    };
+    layout.new_page();
+    layout.blocks(&blocks, &images);
+    layout.decorate(&format!("{} \u{2014} {}", book.title, book.author));
+
+    let pdf = write_pdf(&layout, &images, &book.title, &book.author)?;
+    fs::write(dest, pdf).map_err(|e| format!("Failed to save PDF: {}", e))?;
+    Ok(PdfExport {
+        path: dest.to_string(),
+        pages: layout.pages.len(),
+        chapters: printed,
+    })
+}
+
+/// Print a chapter, or with `wholeBook` every chapter, to a PDF at `dest`.
+/// The text is set at the reader's font size in the bundled font, which
+/// covers more scripts than the PDF viewers' built-in ones.
+#[tauri::command]
+pub async fn export_chapter_pdf(
+    app: AppHandle,
+    book_id: String,
+    spine_index: usize,
+    dest: String,
+    options: Option<PdfExportOptions>,
+) -> Result<PdfExport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let options = options.unwrap_or_default();
+        crash::catch_panic("exporting a PDF", || {
+            export_pdf(
+                &app.state::<AppState>(),
+                &book_id,
+                spine_index,
+                &dest,
+                &options,
+            )
+        })?
+    })
+    .await
+    .map_err(|e| format!("PDF export task failed: {}", e))?
+}
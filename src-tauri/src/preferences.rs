@@ -2,7 +2,20 @@
  * User preferences management and persistence
  */
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, State};
+
+use crate::accessibility::{self, AccessibilityPreferences};
+use crate::annotations::{self, HighlightCategory};
+use crate::config;
+use crate::lan_sync;
+use crate::media_keys;
+use crate::preflight::{self, PathKind};
+use crate::quote;
+use crate::state::AppState;
+use crate::tts;
 
 fn default_reading_mode() -> String {
     "paginated".to_string()
@@ -31,6 +44,33 @@ fn default_true() -> bool {
 fn default_music_volume() -> u32 {
     50
 }
+fn default_close_behavior() -> String {
+    "quit".to_string()
+}
+fn default_media_quota_mb() -> u64 {
+    2048
+}
+fn default_window_zoom() -> f64 {
+    1.0
+}
+fn default_quote_template() -> String {
+    quote::DEFAULT_TEMPLATE.to_string()
+}
+fn default_tts_rate() -> f32 {
+    1.0
+}
+fn default_page_transition() -> String {
+    "slide".to_string()
+}
+
+/// Allowed range for the window zoom factor
+pub const MIN_WINDOW_ZOOM: f64 = 0.5;
+pub const MAX_WINDOW_ZOOM: f64 = 3.0;
+
+/// Background files played as video rather than shown as an image
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "mov", "avi", "mkv"];
+/// Why a setting isn't the one saved, shown next to the disabled control
+pub const OVERRIDDEN_BY_ACCESSIBILITY: &str = "overridden by system accessibility";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserPreferences {
@@ -66,6 +106,106 @@ pub struct UserPreferences {
     pub scrollbar_track: String,
     #[serde(rename = "scrollbarThumb", default = "default_scrollbar_thumb")]
     pub scrollbar_thumb: String,
+    #[serde(rename = "mediaQuotaEnabled", default = "default_true")]
+    pub media_quota_enabled: bool,
+    #[serde(rename = "mediaQuotaMb", default = "default_media_quota_mb")]
+    pub media_quota_mb: u64,
+    /// "quit" or "tray" (hide to the system tray)
+    #[serde(rename = "closeBehavior", default = "default_close_behavior")]
+    pub close_behavior: String,
+    #[serde(rename = "updateCheckEnabled", default = "default_true")]
+    pub update_check_enabled: bool,
+    /// Zoom factor of the whole UI (1.0 = 100%)
+    /// Play/pause and next-track keys control the ambient music
+    #[serde(rename = "mediaKeysEnabled", default = "default_true")]
+    pub media_keys_enabled: bool,
+    #[serde(rename = "windowZoom", default = "default_window_zoom")]
+    pub window_zoom: f64,
+    /// User key bindings: action -> accelerator (e.g. "nextPage" -> "Ctrl+Right")
+    #[serde(default)]
+    pub shortcuts: HashMap<String, String>,
+    /// Citation format for copied quotes, see quote::PLACEHOLDERS
+    #[serde(rename = "quoteTemplate", default = "default_quote_template")]
+    pub quote_template: String,
+    /// Offer library sync to other Epilogue instances on the local network
+    #[serde(rename = "lanSyncEnabled", default)]
+    pub lan_sync_enabled: bool,
+    #[serde(default)]
+    pub tts: TtsPreferences,
+    /// Show a system notification when a timed session reaches its goal
+    #[serde(rename = "sessionGoalNotification", default = "default_true")]
+    pub session_goal_notification: bool,
+    /// Language selected text is translated into, e.g. "en"
+    #[serde(rename = "translationTargetLanguage", default)]
+    pub translation_target_language: Option<String>,
+    /// Send sentences to the online translation endpoint
+    #[serde(rename = "translationOnlineEnabled", default)]
+    pub translation_online_enabled: bool,
+    /// LibreTranslate-compatible /translate URL
+    #[serde(rename = "translationEndpoint", default)]
+    pub translation_endpoint: Option<String>,
+    /// Bold the first part of each word; books can opt out in their overrides
+    #[serde(rename = "bionicReading", default)]
+    pub bionic_reading: bool,
+    /// Work out progress from the chapters read, weighted by their words,
+    /// for books that have chapters marked read
+    #[serde(rename = "chapterWeightedProgress", default)]
+    pub chapter_weighted_progress: bool,
+    /// Highlight colors and what they mean; the first is the default
+    #[serde(
+        rename = "highlightCategories",
+        default = "annotations::default_categories"
+    )]
+    pub highlight_categories: Vec<HighlightCategory>,
+    /// "slide", "fade" or "none" between pages
+    #[serde(rename = "pageTransition", default = "default_page_transition")]
+    pub page_transition: String,
+    /// Keep page transitions and video backgrounds when the OS asks for
+    /// reduced motion
+    #[serde(rename = "ignoreSystemReducedMotion", default)]
+    pub ignore_system_reduced_motion: bool,
+    /// Folders whose new EPUBs are imported on their own
+    #[serde(rename = "watchedFolders", default)]
+    pub watched_folders: Vec<String>,
+}
+
+/// A setting that isn't applied as saved, and why
+#[derive(Debug, Serialize, Clone)]
+pub struct OverriddenPreference {
+    pub field: String,
+    pub reason: String,
+}
+
+/// Preferences as the reader should apply them, after the OS's accessibility
+/// settings
+#[derive(Debug, Serialize, Clone)]
+pub struct EffectivePreferences {
+    #[serde(flatten)]
+    pub preferences: UserPreferences,
+    /// Whether video backgrounds play, whether set here or by a preset
+    #[serde(rename = "videoBackgrounds")]
+    pub video_backgrounds: bool,
+    pub accessibility: AccessibilityPreferences,
+    pub overridden: Vec<OverriddenPreference>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TtsPreferences {
+    /// Voice id from get_tts_voices; None for the system default
+    #[serde(default)]
+    pub voice: Option<String>,
+    /// Speaking rate as a multiple of the voice's normal rate
+    #[serde(default = "default_tts_rate")]
+    pub rate: f32,
+}
+
+impl Default for TtsPreferences {
+    fn default() -> Self {
+        Self {
+            voice: None,
+            rate: default_tts_rate(),
+        }
+    }
 }
 
 impl Default for UserPreferences {
@@ -87,43 +227,146 @@ impl Default for UserPreferences {
             bg_music_muted: true,
             scrollbar_track: default_scrollbar_track(),
             scrollbar_thumb: default_scrollbar_thumb(),
+            media_quota_enabled: true,
+            media_quota_mb: default_media_quota_mb(),
+            close_behavior: default_close_behavior(),
+            update_check_enabled: true,
+            media_keys_enabled: true,
+            window_zoom: default_window_zoom(),
+            shortcuts: HashMap::new(),
+            quote_template: default_quote_template(),
+            lan_sync_enabled: false,
+            tts: TtsPreferences::default(),
+            session_goal_notification: true,
+            translation_target_language: None,
+            translation_online_enabled: false,
+            translation_endpoint: None,
+            bionic_reading: false,
+            chapter_weighted_progress: false,
+            highlight_categories: annotations::default_categories(),
+            page_transition: default_page_transition(),
+            ignore_system_reduced_motion: false,
+            watched_folders: Vec::new(),
         }
     }
 }
 
-fn preferences_path() -> Result<std::path::PathBuf, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
-    Ok(home_dir.join(".epub-reader").join("preferences.json"))
+/// Merge two raw preference documents: every key already set in `local`
+/// wins, keys only present in `incoming` are filled in
+pub fn merge_preferences(
+    local: serde_json::Value,
+    incoming: serde_json::Value,
+) -> serde_json::Value {
+    match (local, incoming) {
+        (serde_json::Value::Object(mut local), serde_json::Value::Object(incoming)) => {
+            for (key, value) in incoming {
+                local.entry(key).or_insert(value);
+            }
+            serde_json::Value::Object(local)
+        }
+        (serde_json::Value::Null, incoming) => incoming,
+        (local, _) => local,
+    }
 }
 
-/// Get user preferences
-#[tauri::command]
-pub fn get_preferences() -> Result<UserPreferences, String> {
-    let path = preferences_path()?;
-
+/// Read preferences from disk; a missing or unparsable file gives defaults
+pub fn read_preferences(path: &Path) -> Result<UserPreferences, String> {
     if !path.exists() {
         return Ok(UserPreferences::default());
     }
 
-    let content = fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read preferences: {}", e))?;
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read preferences: {}", e))?;
+
+    serde_json::from_str(&content)
+        .map_err(|e| {
+            eprintln!("Failed to parse preferences, using defaults: {}", e);
+            // Return defaults if parse fails
+            format!("Parse error: {}", e)
+        })
+        .or_else(|_| Ok(UserPreferences::default()))
+}
+
+/// Get user preferences
+#[tauri::command]
+pub fn get_preferences(state: State<'_, AppState>) -> Result<UserPreferences, String> {
+    state.preferences()
+}
+
+/// Preferences with what the OS's reduced-motion setting forces: no page
+/// transitions and no video backgrounds, unless the user opted out
+#[tauri::command]
+pub fn get_effective_preferences(
+    state: State<'_, AppState>,
+) -> Result<EffectivePreferences, String> {
+    let mut preferences = state.preferences()?;
+    let accessibility = accessibility::current();
+    let mut overridden = Vec::new();
+    let mut video_backgrounds = true;
+
+    if accessibility.reduced_motion && !preferences.ignore_system_reduced_motion {
+        for field in ["pageTransition", "videoBackgrounds"] {
+            overridden.push(OverriddenPreference {
+                field: field.to_string(),
+                reason: OVERRIDDEN_BY_ACCESSIBILITY.to_string(),
+            });
+        }
+        preferences.page_transition = "none".to_string();
+        video_backgrounds = false;
+        let is_video = preferences
+            .bg_media_path
+            .as_deref()
+            .and_then(|path| Path::new(path).extension())
+            .is_some_and(|ext| {
+                VIDEO_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+            });
+        if is_video {
+            preferences.bg_media_path = None;
+        }
+    }
 
-    serde_json::from_str(&content).map_err(|e| {
-        eprintln!("Failed to parse preferences, using defaults: {}", e);
-        // Return defaults if parse fails
-        format!("Parse error: {}", e)
-    }).or_else(|_| Ok(UserPreferences::default()))
+    Ok(EffectivePreferences {
+        preferences,
+        video_backgrounds,
+        accessibility,
+        overridden,
+    })
 }
 
 /// Save user preferences
 #[tauri::command]
-pub fn set_preferences(prefs: UserPreferences) -> Result<(), String> {
-    let path = preferences_path()?;
+pub fn set_preferences(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    prefs: UserPreferences,
+) -> Result<(), String> {
+    let previous = state.preferences()?.highlight_categories;
+    save_preferences(&state, prefs.clone())?;
+
+    // Highlights of a deleted category go to the default one
+    let removed = previous
+        .iter()
+        .any(|old| !prefs.highlight_categories.iter().any(|c| c.id == old.id));
+    if removed && !config::is_safe_mode() {
+        annotations::reassign_removed_categories(&state, &prefs.highlight_categories)?;
+    }
+
+    // Settings that live outside the webview
+    media_keys::sync(&app, &prefs);
+    lan_sync::apply(&app, &prefs);
+    Ok(())
+}
+
+/// Validate, write and cache preferences
+pub fn save_preferences(state: &AppState, prefs: UserPreferences) -> Result<(), String> {
+    let path = state.paths()?.preferences.clone();
 
     // Validate font size range
     if prefs.font_size < 12 || prefs.font_size > 32 {
-        return Err(format!("Font size must be between 12 and 32, got {}", prefs.font_size));
+        return Err(format!(
+            "Font size must be between 12 and 32, got {}",
+            prefs.font_size
+        ));
     }
 
     // Validate font family
@@ -138,17 +381,78 @@ pub fn set_preferences(prefs: UserPreferences) -> Result<(), String> {
         return Err(format!("Invalid reading mode: {}", prefs.reading_mode));
     }
 
-    // Ensure directory exists
+    // Validate media quota (100 MB to 100 GB)
+    if prefs.media_quota_mb < 100 || prefs.media_quota_mb > 102_400 {
+        return Err(format!(
+            "Media quota must be between 100 and 102400 MB, got {}",
+            prefs.media_quota_mb
+        ));
+    }
+
+    // Validate window zoom
+    if !(MIN_WINDOW_ZOOM..=MAX_WINDOW_ZOOM).contains(&prefs.window_zoom) {
+        return Err(format!(
+            "Window zoom must be between {} and {}, got {}",
+            MIN_WINDOW_ZOOM, MAX_WINDOW_ZOOM, prefs.window_zoom
+        ));
+    }
+
+    // Validate speaking rate
+    if !(tts::MIN_TTS_RATE..=tts::MAX_TTS_RATE).contains(&prefs.tts.rate) {
+        return Err(format!(
+            "Speaking rate must be between {} and {}, got {}",
+            tts::MIN_TTS_RATE,
+            tts::MAX_TTS_RATE,
+            prefs.tts.rate
+        ));
+    }
+
+    // Validate page transition
+    let valid_transitions = ["slide", "fade", "none"];
+    if !valid_transitions.contains(&prefs.page_transition.as_str()) {
+        return Err(format!(
+            "Invalid page transition: {}",
+            prefs.page_transition
+        ));
+    }
+
+    // Validate quote template
+    quote::validate_template(&prefs.quote_template)?;
+
+    // Validate highlight palette
+    annotations::validate_categories(&prefs.highlight_categories)?;
+
+    // Validate translation endpoint
+    if let Some(endpoint) = &prefs.translation_endpoint {
+        let url = reqwest::Url::parse(endpoint)
+            .map_err(|e| format!("Invalid translation endpoint: {}", e))?;
+        if !["http", "https"].contains(&url.scheme()) {
+            return Err(format!("Invalid translation endpoint: {}", endpoint));
+        }
+    }
+
+    // Validate close behavior
+    let valid_close_behaviors = ["quit", "tray"];
+    if !valid_close_behaviors.contains(&prefs.close_behavior.as_str()) {
+        return Err(format!("Invalid close behavior: {}", prefs.close_behavior));
+    }
+
+    // Safe mode keeps preferences for this session only
+    if config::is_safe_mode() {
+        return state.set_cached_preferences(prefs);
+    }
+
+    // Ensure directory exists and can be written
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .map_err(|e| format!("Failed to create preferences directory: {}", e))?;
     }
+    preflight::check_writable(PathKind::Preferences)?;
 
     let json = serde_json::to_string_pretty(&prefs)
         .map_err(|e| format!("Failed to serialize preferences: {}", e))?;
 
-    fs::write(&path, json)
-        .map_err(|e| format!("Failed to write preferences: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write preferences: {}", e))?;
 
-    Ok(())
+    state.set_cached_preferences(prefs)
 }
@@ -0,0 +1,139 @@
+/**
+ * Writability preflight checks run before saving
+ */
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::error::AppError;
+
+/// A successful check is trusted for this long before probing again
+const CHECK_TTL: Duration = Duration::from_secs(30);
+
+static LAST_OK: Mutex<Option<HashMap<PathKind, Instant>>> = Mutex::new(None);
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum PathKind {
+    Library,
+    Preferences,
+    Presets,
+    Covers,
+    Media,
+    Annotations,
+}
+
+/// Directory that has to be writable for a kind of save
+pub fn dir_for(kind: PathKind) -> Result<PathBuf, AppError> {
+    let app_dir = config::get_app_dir_path()?;
+
+    Ok(match kind {
+        PathKind::Library | PathKind::Preferences => app_dir,
+        PathKind::Presets => app_dir.join("presets"),
+        PathKind::Covers => config::get_covers_dir()?,
+        PathKind::Media => app_dir.join("media"),
+        PathKind::Annotations => app_dir.join("annotations"),
+    })
+}
+
+/// Verify that a save of the given kind can be written, before writing it
+pub fn check_writable(kind: PathKind) -> Result<(), AppError> {
+    if let Ok(guard) = LAST_OK.lock() {
+        if let Some(at) = guard.as_ref().and_then(|m| m.get(&kind)) {
+            if at.elapsed() < CHECK_TTL {
+                return Ok(());
+            }
+        }
+    }
+
+    let dir = dir_for(kind)?;
+    probe(&dir).map_err(|e| classify(&dir, &e))?;
+
+    if let Ok(mut guard) = LAST_OK.lock() {
+        guard.get_or_insert_with(HashMap::new).insert(kind, Instant::now());
+    }
+
+    Ok(())
+}
+
+/// Create the directory if needed and write/remove a probe file in it
+fn probe(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".preflight");
+    fs::write(&probe, b"ok")?;
+    fs::remove_file(&probe)
+}
+
+/// Turn a raw IO error into a typed error with a remedy the user can act on
+fn classify(dir: &Path, error: &io::Error) -> AppError {
+    let path = dir.to_string_lossy().to_string();
+    let detail = error.to_string();
+
+    // ENOSPC on Unix; ERROR_HANDLE_DISK_FULL / ERROR_DISK_FULL on Windows
+    let disk_full = matches!(error.raw_os_error(), Some(28) if cfg!(unix))
+        || matches!(error.raw_os_error(), Some(39) | Some(112) if cfg!(windows));
+    // EROFS
+    let read_only_fs = matches!(error.raw_os_error(), Some(30) if cfg!(unix));
+    // EPERM (rather than EACCES) is what macOS sandboxing/TCC returns
+    let sandboxed = matches!(error.raw_os_error(), Some(1) if cfg!(unix));
+
+    let (reason, remedy) = if disk_full {
+        (
+            "diskFull",
+            "The disk is full. Free up some space and try again.".to_string(),
+        )
+    } else if read_only_fs {
+        (
+            "readOnly",
+            "The drive holding your Epilogue data is read-only. Remount it as writable or move the data folder.".to_string(),
+        )
+    } else if sandboxed {
+        (
+            "blocked",
+            "The operating system blocked access to this folder. Grant Epilogue access in your privacy/security settings.".to_string(),
+        )
+    } else if error.kind() == io::ErrorKind::PermissionDenied {
+        let read_only_dir = fs::metadata(dir)
+            .map(|m| m.permissions().readonly())
+            .unwrap_or(false);
+        if read_only_dir {
+            (
+                "readOnly",
+                format!("The folder {} is read-only. Clear the read-only flag or fix its permissions.", path),
+            )
+        } else if cfg!(windows) {
+            (
+                "blocked",
+                "Access was denied. If Windows Controlled Folder Access or antivirus is enabled, allow Epilogue through it.".to_string(),
+            )
+        } else {
+            (
+                "blocked",
+                format!("You don't have permission to write to {}. Fix the folder's permissions.", path),
+            )
+        }
+    } else {
+        (
+            "other",
+            format!("Epilogue could not write to {}. Check that the folder exists and is writable.", path),
+        )
+    };
+
+    AppError::NotWritable {
+        path,
+        reason: reason.to_string(),
+        remedy,
+        detail,
+    }
+}
+
+/// Run a writability preflight for the given kind of data
+#[tauri::command]
+pub fn preflight_check(path_kind: PathKind) -> Result<(), AppError> {
+    check_writable(path_kind)
+}
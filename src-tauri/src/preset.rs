@@ -3,6 +3,10 @@
  */
 use serde::{Deserialize, Serialize};
 use std::fs;
+use tauri::State;
+
+use crate::preflight::{self, PathKind};
+use crate::state::AppState;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Preset {
@@ -63,11 +67,15 @@ pub fn validate_preset(preset: &Preset) -> Result<(), String> {
 
 /// List all available presets
 #[tauri::command]
-pub fn list_presets() -> Result<Vec<String>, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
-
-    let presets_dir = home_dir.join(".epub-reader").join("presets");
+pub fn list_presets(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let presets_dir = &state.paths()?.presets;
+
+    // Adding or removing a file changes the directory's mtime, so presets
+    // dropped in by hand still show up
+    let modified = fs::metadata(presets_dir).and_then(|m| m.modified()).ok();
+    if let Some(names) = modified.and_then(|m| state.cached_preset_list(m)) {
+        return Ok(names);
+    }
 
     if !presets_dir.exists() {
         return Ok(Vec::new());
@@ -75,7 +83,7 @@ pub fn list_presets() -> Result<Vec<String>, String> {
 
     let mut presets = Vec::new();
 
-    let entries = fs::read_dir(&presets_dir)
+    let entries = fs::read_dir(presets_dir)
         .map_err(|e| format!("Failed to read presets directory: {}", e))?;
 
     for entry in entries {
@@ -89,19 +97,20 @@ pub fn list_presets() -> Result<Vec<String>, String> {
         }
     }
 
+    if let Some(modified) = modified {
+        state.set_preset_list(modified, presets.clone());
+    }
     Ok(presets)
 }
 
 /// Load a preset by name (with relaxed validation)
 #[tauri::command]
-pub fn load_preset(preset_name: String) -> Result<Preset, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+pub fn load_preset(state: State<'_, AppState>, preset_name: String) -> Result<Preset, String> {
+    read_preset(&state, &preset_name)
+}
 
-    let preset_path = home_dir
-        .join(".epub-reader")
-        .join("presets")
-        .join(format!("{}.json", preset_name));
+pub fn read_preset(state: &AppState, preset_name: &str) -> Result<Preset, String> {
+    let preset_path = state.paths()?.presets.join(format!("{}.json", preset_name));
 
     if !preset_path.exists() {
         return Err(format!("Preset '{}' not found", preset_name));
@@ -120,14 +129,8 @@ pub fn load_preset(preset_name: String) -> Result<Preset, String> {
 
 /// List all available background images
 #[tauri::command]
-pub fn list_backgrounds() -> Result<Vec<String>, String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
-
-    let backgrounds_dir = home_dir
-        .join(".epub-reader")
-        .join("media")
-        .join("backgrounds");
+pub fn list_backgrounds(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let backgrounds_dir = &state.paths()?.backgrounds;
 
     if !backgrounds_dir.exists() {
         return Ok(Vec::new());
@@ -136,7 +139,7 @@ pub fn list_backgrounds() -> Result<Vec<String>, String> {
     let mut backgrounds = Vec::new();
     let valid_extensions = ["jpg", "jpeg", "png", "webp", "svg"];
 
-    let entries = fs::read_dir(&backgrounds_dir)
+    let entries = fs::read_dir(backgrounds_dir)
         .map_err(|e| format!("Failed to read backgrounds directory: {}", e))?;
 
     for entry in entries {
@@ -157,7 +160,11 @@ pub fn list_backgrounds() -> Result<Vec<String>, String> {
 
 /// Save a custom user preset
 #[tauri::command]
-pub fn save_custom_preset(name: String, preset_json: String) -> Result<Preset, String> {
+pub fn save_custom_preset(
+    state: State<'_, AppState>,
+    name: String,
+    preset_json: String,
+) -> Result<Preset, String> {
     let mut preset: Preset = serde_json::from_str(&preset_json)
         .map_err(|e| format!("Failed to parse preset JSON: {}", e))?;
 
@@ -165,12 +172,11 @@ pub fn save_custom_preset(name: String, preset_json: String) -> Result<Preset, S
 
     validate_preset(&preset)?;
 
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
-    let presets_dir = home_dir.join(".epub-reader").join("presets");
+    let presets_dir = &state.paths()?.presets;
 
-    fs::create_dir_all(&presets_dir)
+    fs::create_dir_all(presets_dir)
         .map_err(|e| format!("Failed to create presets directory: {}", e))?;
+    preflight::check_writable(PathKind::Presets)?;
 
     let preset_path = presets_dir.join(format!("{}.json", name));
 
@@ -179,20 +185,15 @@ pub fn save_custom_preset(name: String, preset_json: String) -> Result<Preset, S
 
     fs::write(&preset_path, json)
         .map_err(|e| format!("Failed to write preset file: {}", e))?;
+    state.invalidate_preset_list();
 
     Ok(preset)
 }
 
 /// Delete a user-created preset
 #[tauri::command]
-pub fn delete_preset(name: String) -> Result<(), String> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
-
-    let preset_path = home_dir
-        .join(".epub-reader")
-        .join("presets")
-        .join(format!("{}.json", name));
+pub fn delete_preset(state: State<'_, AppState>, name: String) -> Result<(), String> {
+    let preset_path = state.paths()?.presets.join(format!("{}.json", name));
 
     if !preset_path.exists() {
         return Err(format!("Preset '{}' not found", name));
@@ -200,6 +201,7 @@ pub fn delete_preset(name: String) -> Result<(), String> {
 
     fs::remove_file(&preset_path)
         .map_err(|e| format!("Failed to delete preset: {}", e))?;
+    state.invalidate_preset_list();
 
     Ok(())
 }
@@ -0,0 +1,55 @@
+/**
+ * How far into each book the reader was over time, for a sparkline per
+ * book. Kept in the library database, at most a point per book every ten
+ * minutes, and written with the library's scheduled saves rather than on
+ * every page turn.
+ */
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tauri::State;
+
+use crate::config;
+use crate::library_db;
+use crate::logging;
+use crate::state::AppState;
+
+/// Progress made within this long of the latest point updates that point
+pub const POINT_INTERVAL_SECS: i64 = 10 * 60;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ProgressPoint {
+    pub at: DateTime<Utc>,
+    /// From 0 to 1
+    pub progress: f32,
+}
+
+/// Add the book's progress now to its history, with the next save
+pub fn record(state: &AppState, book_id: &str, progress: f32) {
+    if config::is_safe_mode() {
+        return;
+    }
+    state.queue_progress_point(book_id, Utc::now().timestamp(), progress);
+}
+
+/// A book's progress over time, oldest first
+#[tauri::command]
+pub fn get_progress_history(
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<Vec<ProgressPoint>, String> {
+    let book_id = state.resolve_book_id(&book_id);
+    // The point of the page being read may not be saved yet
+    if let Err(e) = state.flush_library() {
+        logging::warn(&format!("Failed to save progress history: {}", e));
+    }
+    let points = state.with_db(|conn| library_db::progress_history(conn, &book_id))?;
+    Ok(points
+        .into_iter()
+        .filter_map(|(at, progress)| {
+            Some(ProgressPoint {
+                at: DateTime::from_timestamp(at, 0)?,
+                progress,
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,148 @@
+/**
+ * Copy a passage to the clipboard with a citation
+ */
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use crate::annotations::{self, Highlight};
+use crate::crash;
+use crate::logging;
+use crate::state::AppState;
+
+/// Placeholders a quote template may use
+pub const PLACEHOLDERS: &[&str] = &["quote", "author", "title", "chapter", "percent"];
+
+pub const DEFAULT_TEMPLATE: &str =
+    "\u{201c}{quote}\u{201d}\n\u{2014} {author}, {title} ({chapter})";
+
+/// Split a template into literal text and placeholder names
+fn parse_template(template: &str) -> Result<Vec<(bool, &str)>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push((false, &rest[..open]));
+        }
+        let after = &rest[open + 1..];
+        let close = after
+            .find('}')
+            .ok_or_else(|| "Quote template has an unclosed '{'".to_string())?;
+        parts.push((true, &after[..close]));
+        rest = &after[close + 1..];
+    }
+    if rest.contains('}') {
+        return Err("Quote template has an unmatched '}'".to_string());
+    }
+    if !rest.is_empty() {
+        parts.push((false, rest));
+    }
+    Ok(parts)
+}
+
+/// Reject templates with placeholders we don't know how to fill
+pub fn validate_template(template: &str) -> Result<(), String> {
+    for (is_placeholder, name) in parse_template(template)? {
+        if is_placeholder && !PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "Unknown placeholder {{{}}} in quote template (allowed: {})",
+                name,
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn render_template(template: &str, value: impl Fn(&str) -> String) -> Result<String, String> {
+    Ok(parse_template(template)?
+        .into_iter()
+        .map(|(is_placeholder, part)| {
+            if is_placeholder {
+                value(part)
+            } else {
+                part.to_string()
+            }
+        })
+        .collect())
+}
+
+/// Title of the TOC entry the CFI falls under: the last entry starting at
+/// or before its spine item
+pub fn chapter_title(path: &str, cfi: &str) -> Option<String> {
+    let target = annotations::spine_index(cfi)?;
+    let doc = epub::doc::EpubDoc::new(path).ok()?;
+
+    let mut entries = Vec::new();
+    let mut stack: Vec<_> = doc.toc.iter().rev().collect();
+    while let Some(point) = stack.pop() {
+        entries.push(point);
+        stack.extend(point.children.iter().rev());
+    }
+
+    entries
+        .into_iter()
+        .filter_map(|point| {
+            // Drop the fragment, chapters are matched by file
+            let content = point.content.to_string_lossy();
+            let file = PathBuf::from(content.split('#').next().unwrap_or_default());
+            let index = doc.resource_uri_to_chapter(&file)?;
+            (index <= target).then_some((index, point.label.trim().to_string()))
+        })
+        .max_by_key(|(index, _)| *index)
+        .map(|(_, label)| label)
+}
+
+/// Copy a quote with its citation and keep it with the book's highlights.
+/// Returns the text that was copied.
+#[tauri::command]
+pub fn copy_quote(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    text: String,
+    cfi: String,
+    category: Option<String>,
+) -> Result<String, String> {
+    let book_id = state.resolve_book_id(&book_id);
+    let book = state
+        .with_library(|lib| lib.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    book.require_text()?;
+    annotations::check_category(&state, category.as_deref())?;
+    let template = state.preferences()?.quote_template;
+
+    // A book we can't parse still gets quoted, just without a chapter
+    let chapter = crash::catch_panic("reading the table of contents", || {
+        chapter_title(&book.file_path, &cfi)
+    })
+    .unwrap_or_else(|e| {
+        logging::warn(&e);
+        None
+    });
+
+    let quote = text.trim();
+    let citation = render_template(&template, |name| match name {
+        "quote" => quote.to_string(),
+        "author" => book.author.clone(),
+        "title" => book.title.clone(),
+        "chapter" => chapter.clone().unwrap_or_default(),
+        "percent" => format!("{}%", (book.progress * 100.0).round()),
+        _ => String::new(),
+    })?;
+
+    app.clipboard()
+        .write_text(citation.clone())
+        .map_err(|e| format!("Failed to copy quote: {}", e))?;
+
+    let mut highlight = Highlight::new(cfi, quote.to_string(), chapter);
+    highlight.quoted = true;
+    highlight.category = category;
+    if let Err(e) = annotations::add_highlight(&state, &book_id, highlight) {
+        // The quote is on the clipboard already; don't fail the copy
+        logging::warn(&format!("Failed to save quoted highlight: {}", e));
+    }
+
+    Ok(citation)
+}
@@ -0,0 +1,546 @@
+/**
+ * Shareable quote images: a highlight with its book and author, as a PNG
+ */
+use base64::Engine;
+use rusttype::{point, Font, Scale};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+use unicode_bidi::BidiInfo;
+
+use crate::annotations;
+use crate::covers;
+use crate::crash;
+use crate::preset;
+use crate::state::AppState;
+
+/// DejaVu Sans (Bitstream Vera license, see assets/fonts), for its script
+/// coverage: Latin, Greek, Cyrillic, Hebrew and Arabic
+const FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+/// Quote text starts at this size and shrinks until it fits
+const MAX_QUOTE_PX: f32 = 64.0;
+const MIN_QUOTE_PX: f32 = 24.0;
+const ATTRIBUTION_PX: f32 = 30.0;
+const MAX_ATTRIBUTION_LINES: usize = 2;
+const LINE_SPACING: f32 = 1.35;
+/// Covers are sampled on a grid this many pixels wide and high
+const COLOR_SAMPLES: usize = 64;
+
+const LIGHT: Rgb = [0xF7, 0xF3, 0xEA];
+const DARK: Rgb = [0x16, 0x16, 0x1D];
+
+type Rgb = [u8; 3];
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteTheme {
+    #[default]
+    Light,
+    Dark,
+    /// Tinted with the dominant color of the book's cover
+    Cover,
+    /// The reader colors of the active preset
+    Preset,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteImageSize {
+    /// 1080×1080
+    #[default]
+    Square,
+    /// 1600×900
+    Wide,
+}
+
+impl QuoteImageSize {
+    fn dimensions(self) -> (u32, u32) {
+        match self {
+            QuoteImageSize::Square => (1080, 1080),
+            QuoteImageSize::Wide => (1600, 900),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct QuoteImageStyle {
+    #[serde(default)]
+    pub theme: QuoteTheme,
+    #[serde(default)]
+    pub size: QuoteImageSize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct QuoteImage {
+    pub width: u32,
+    pub height: u32,
+    /// Where the image was saved, when a destination was given
+    pub path: Option<String>,
+    /// The PNG as base64 otherwise
+    pub data: Option<String>,
+}
+
+struct Palette {
+    background: Rgb,
+    text: Rgb,
+    muted: Rgb,
+}
+
+fn mix(a: Rgb, b: Rgb, t: f32) -> Rgb {
+    [0, 1, 2].map(|i| (a[i] as f32 + (b[i] as f32 - a[i] as f32) * t).round() as u8)
+}
+
+fn luminance(color: Rgb) -> f32 {
+    (0.2126 * color[0] as f32 + 0.7152 * color[1] as f32 + 0.0722 * color[2] as f32) / 255.0
+}
+
+impl Palette {
+    /// Readable text colors for a background
+    fn on(background: Rgb) -> Self {
+        let text = if luminance(background) > 0.55 {
+            [0x22, 0x22, 0x22]
+        } else {
+            [0xF2, 0xF0, 0xEA]
+        };
+        Self::with_text(background, text)
+    }
+
+    fn with_text(background: Rgb, text: Rgb) -> Self {
+        Self {
+            background,
+            text,
+            muted: mix(text, background, 0.35),
+        }
+    }
+}
+
+/// "#rgb", "#rrggbb" or "#rrggbbaa"; the alpha is ignored
+fn parse_hex(color: &str) -> Option<Rgb> {
+    let hex = color.trim().strip_prefix('#')?;
+    if !hex.is_ascii() {
+        return None;
+    }
+    let hex: String = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 | 8 => hex[..6].to_string(),
+        _ => return None,
+    };
+    let value = u32::from_str_radix(&hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+/// The most common color of an image, favoring saturated ones over the
+/// white or black margins many covers have
+fn dominant_color(rgb: &[u8], width: usize, height: usize) -> Option<Rgb> {
+    let mut buckets: HashMap<u16, (u32, [u32; 3])> = HashMap::new();
+    for y in (0..height).step_by((height / COLOR_SAMPLES).max(1)) {
+        for x in (0..width).step_by((width / COLOR_SAMPLES).max(1)) {
+            let i = (y * width + x) * 3;
+            let Some(p) = rgb.get(i..i + 3) else {
+                continue;
+            };
+            let key = ((p[0] >> 4) as u16) << 8 | ((p[1] >> 4) as u16) << 4 | (p[2] >> 4) as u16;
+            let bucket = buckets.entry(key).or_default();
+            bucket.0 += 1;
+            for (sum, value) in bucket.1.iter_mut().zip(p) {
+                *sum += *value as u32;
+            }
+        }
+    }
+
+    let score = |(count, sums): &(u32, [u32; 3])| {
+        let max = sums.iter().max().copied().unwrap_or(0) as f32;
+        let min = sums.iter().min().copied().unwrap_or(0) as f32;
+        let saturation = (max - min) / (*count as f32 * 255.0);
+        *count as f32 * (0.3 + saturation)
+    };
+    let (count, sums) = buckets
+        .values()
+        .max_by(|a, b| score(a).total_cmp(&score(b)))?;
+    Some(sums.map(|sum| (sum / count) as u8))
+}
+
+fn palette(state: &AppState, theme: QuoteTheme, cover_path: Option<&str>) -> Palette {
+    match theme {
+        QuoteTheme::Light => Palette::on(LIGHT),
+        QuoteTheme::Dark => Palette::on(DARK),
+        QuoteTheme::Cover => {
+            let dominant = cover_path
+                .and_then(|path| covers::decode_rgb(Path::new(path)))
+                .and_then(|(rgb, width, height)| dominant_color(&rgb, width, height));
+            match dominant {
+                // Toned down towards light or dark so text stays readable
+                Some(color) => {
+                    let base = if luminance(color) > 0.5 { LIGHT } else { DARK };
+                    Palette::on(mix(color, base, 0.3))
+                }
+                None => Palette::on(LIGHT),
+            }
+        }
+        QuoteTheme::Preset => {
+            let reader = state
+                .preferences()
+                .ok()
+                .and_then(|prefs| prefs.last_preset)
+                .and_then(|name| preset::read_preset(state, &name).ok())
+                .map(|preset| preset.reader);
+            let Some(background) = reader.as_ref().and_then(|r| parse_hex(&r.background_color))
+            else {
+                return Palette::on(LIGHT);
+            };
+            match reader
+                .as_ref()
+                .and_then(|r| r.text_color.as_deref())
+                .and_then(parse_hex)
+            {
+                Some(text) => Palette::with_text(background, text),
+                None => Palette::on(background),
+            }
+        }
+    }
+}
+
+fn text_width(font: &Font, scale: Scale, text: &str) -> f32 {
+    font.layout(text, scale, point(0.0, 0.0))
+        .last()
+        .map_or(0.0, |g| {
+            g.position().x + g.unpositioned().h_metrics().advance_width
+        })
+}
+
+/// Han, kana and hangul, which wrap between any two characters
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x11FF | 0x2E80..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF
+            | 0xFF00..=0xFFEF | 0x20000..=0x2FA1F)
+}
+
+/// Pieces a line may break between: words, and single CJK characters.
+/// Each comes with whether a space precedes it.
+fn break_units(paragraph: &str) -> Vec<(bool, String)> {
+    fn flush(units: &mut Vec<(bool, String)>, current: &mut String, space: &mut bool) {
+        if !current.is_empty() {
+            units.push((*space, std::mem::take(current)));
+            *space = false;
+        }
+    }
+
+    let mut units = Vec::new();
+    let mut current = String::new();
+    let mut space = false;
+    for c in paragraph.chars() {
+        if c.is_whitespace() {
+            flush(&mut units, &mut current, &mut space);
+            space = true;
+        } else if is_cjk(c) {
+            flush(&mut units, &mut current, &mut space);
+            units.push((space, c.to_string()));
+            space = false;
+        } else {
+            current.push(c);
+        }
+    }
+    flush(&mut units, &mut current, &mut space);
+    units
+}
+
+/// Greedy line breaking; words wider than a line are split between
+/// characters
+fn wrap(font: &Font, scale: Scale, text: &str, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines().filter(|p| !p.trim().is_empty()) {
+        let mut line = String::new();
+        for (space, unit) in break_units(paragraph) {
+            let joined = match (line.is_empty(), space) {
+                (true, _) => unit.clone(),
+                (false, true) => format!("{} {}", line, unit),
+                (false, false) => format!("{}{}", line, unit),
+            };
+            if text_width(font, scale, &joined) <= max_width {
+                line = joined;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for c in unit.chars() {
+                let joined = format!("{}{}", line, c);
+                if !line.is_empty() && text_width(font, scale, &joined) > max_width {
+                    lines.push(std::mem::replace(&mut line, c.to_string()));
+                } else {
+                    line = joined;
+                }
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// Keep at most `max_lines`, ending the last kept one with an ellipsis
+fn truncate_lines(
+    font: &Font,
+    scale: Scale,
+    mut lines: Vec<String>,
+    max_lines: usize,
+    max_width: f32,
+) -> Vec<String> {
+    if lines.len() <= max_lines {
+        return lines;
+    }
+    lines.truncate(max_lines.max(1));
+    if let Some(last) = lines.last_mut() {
+        while !last.is_empty() && text_width(font, scale, &format!("{}\u{2026}", last)) > max_width
+        {
+            last.pop();
+        }
+        *last = format!("{}\u{2026}", last.trim_end());
+    }
+    lines
+}
+
+/// Lines in display order. Text is drawn left to right, so right-to-left
+/// runs are reversed here.
+fn visual_order(line: &str) -> String {
+    let bidi = BidiInfo::new(line, None);
+    match bidi.paragraphs.first() {
+        Some(paragraph) if bidi.has_rtl() => bidi
+            .reorder_line(paragraph, paragraph.range.clone())
+            .into_owned(),
+        _ => line.to_string(),
+    }
+}
+
+/// The largest size at which the quote fits the box, cut short if it
+/// doesn't fit even at the smallest
+fn fit_quote(font: &Font, text: &str, max_width: f32, max_height: f32) -> (Scale, Vec<String>) {
+    let mut px = MAX_QUOTE_PX;
+    loop {
+        let scale = Scale::uniform(px);
+        let lines = wrap(font, scale, text, max_width);
+        let line_height = px * LINE_SPACING;
+        if lines.len() as f32 * line_height <= max_height {
+            return (scale, lines);
+        }
+        if px <= MIN_QUOTE_PX {
+            let max_lines = (max_height / line_height).floor() as usize;
+            return (
+                scale,
+                truncate_lines(font, scale, lines, max_lines, max_width),
+            );
+        }
+        px = (px - 2.0).max(MIN_QUOTE_PX);
+    }
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: Rgb) -> Self {
+        Self {
+            width,
+            height,
+            pixels: background.repeat((width * height) as usize),
+        }
+    }
+
+    fn blend(&mut self, x: i32, y: i32, color: Rgb, alpha: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        let under = [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]];
+        self.pixels[i..i + 3].copy_from_slice(&mix(under, color, alpha.clamp(0.0, 1.0)));
+    }
+
+    /// Draw a line of text centered on `center_x`
+    fn draw_line(
+        &mut self,
+        font: &Font,
+        scale: Scale,
+        text: &str,
+        center_x: f32,
+        baseline: f32,
+        color: Rgb,
+    ) {
+        let start = point(center_x - text_width(font, scale, text) / 2.0, baseline);
+        for glyph in font.layout(text, scale, start) {
+            if let Some(bounds) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, coverage| {
+                    self.blend(
+                        bounds.min.x + x as i32,
+                        bounds.min.y + y as i32,
+                        color,
+                        coverage,
+                    )
+                });
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Rgb) {
+        for py in y.round() as i32..(y + height).round() as i32 {
+            for px in x.round() as i32..(x + width).round() as i32 {
+                self.blend(px, py, color, 1.0);
+            }
+        }
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>, String> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| format!("Failed to encode quote image: {}", e))?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(|e| format!("Failed to encode quote image: {}", e))?;
+        writer
+            .finish()
+            .map_err(|e| format!("Failed to encode quote image: {}", e))?;
+        Ok(png)
+    }
+}
+
+fn render(
+    quote: &str,
+    attribution: &str,
+    palette: &Palette,
+    size: QuoteImageSize,
+) -> Result<Vec<u8>, String> {
+    let font = Font::try_from_bytes(FONT).ok_or("Failed to load the quote font")?;
+    let (width, height) = size.dimensions();
+    let margin = width.min(height) as f32 * 0.1;
+    let max_width = width as f32 - 2.0 * margin;
+    let center_x = width as f32 / 2.0;
+    let mut canvas = Canvas::new(width, height, palette.background);
+
+    let attribution_scale = Scale::uniform(ATTRIBUTION_PX);
+    let attribution_line_height = ATTRIBUTION_PX * LINE_SPACING;
+    let attribution_lines = truncate_lines(
+        &font,
+        attribution_scale,
+        wrap(&font, attribution_scale, attribution, max_width),
+        MAX_ATTRIBUTION_LINES,
+        max_width,
+    );
+    let attribution_height = attribution_lines.len() as f32 * attribution_line_height;
+    let gap = margin * 0.6;
+
+    let quote_box_height = height as f32 - 2.0 * margin - attribution_height - gap;
+    let (scale, lines) = fit_quote(&font, quote, max_width, quote_box_height);
+    let line_height = scale.y * LINE_SPACING;
+    let ascent = font.v_metrics(scale).ascent;
+    let top = margin + (quote_box_height - lines.len() as f32 * line_height) / 2.0;
+    for (index, line) in lines.iter().enumerate() {
+        let baseline = top + index as f32 * line_height + ascent;
+        canvas.draw_line(
+            &font,
+            scale,
+            &visual_order(line),
+            center_x,
+            baseline,
+            palette.text,
+        );
+    }
+
+    let attribution_top = height as f32 - margin - attribution_height;
+    canvas.fill_rect(
+        center_x - 30.0,
+        attribution_top - gap / 2.0,
+        60.0,
+        3.0,
+        palette.muted,
+    );
+    let attribution_ascent = font.v_metrics(attribution_scale).ascent;
+    for (index, line) in attribution_lines.iter().enumerate() {
+        let baseline =
+            attribution_top + index as f32 * attribution_line_height + attribution_ascent;
+        canvas.draw_line(
+            &font,
+            attribution_scale,
+            &visual_order(line),
+            center_x,
+            baseline,
+            palette.muted,
+        );
+    }
+
+    canvas.encode_png()
+}
+
+fn render_highlight(
+    state: &AppState,
+    highlight_id: &str,
+    style: &QuoteImageStyle,
+    dest: Option<&str>,
+) -> Result<QuoteImage, String> {
+    if let Some(dest) = dest {
+        if !dest.to_lowercase().ends_with(".png") {
+            return Err(format!("Quote images are saved as .png, got {}", dest));
+        }
+    }
+
+    let (book_id, highlight) = annotations::find_highlight(state, highlight_id)?
+        .ok_or_else(|| format!("Highlight '{}' not found", highlight_id))?;
+    let book = state
+        .with_library(|library| library.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book with id '{}' not found", book_id))?;
+
+    let palette = palette(state, style.theme, book.cover_path.as_deref());
+    let quote = format!("\u{201c}{}\u{201d}", highlight.text.trim());
+    let attribution = format!("\u{2014} {}, {}", book.author, book.title);
+    let png = crash::catch_panic("rendering a quote image", || {
+        render(&quote, &attribution, &palette, style.size)
+    })??;
+
+    let (width, height) = style.size.dimensions();
+    match dest {
+        Some(dest) => {
+            fs::write(dest, &png).map_err(|e| format!("Failed to save quote image: {}", e))?;
+            Ok(QuoteImage {
+                width,
+                height,
+                path: Some(dest.to_string()),
+                data: None,
+            })
+        }
+        None => Ok(QuoteImage {
+            width,
+            height,
+            path: None,
+            data: Some(base64::engine::general_purpose::STANDARD.encode(png)),
+        }),
+    }
+}
+
+/// Render a highlight as a shareable PNG, saved to `dest` or returned as
+/// base64
+#[tauri::command]
+pub async fn render_quote_image(
+    app: AppHandle,
+    highlight_id: String,
+    style: Option<QuoteImageStyle>,
+    dest: Option<String>,
+) -> Result<QuoteImage, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        render_highlight(
+            &app.state::<AppState>(),
+            &highlight_id,
+            &style.unwrap_or_default(),
+            dest.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| format!("Rendering task failed: {}", e))?
+}
@@ -0,0 +1,126 @@
+/**
+ * Extra windows that each show a single book, next to the main window
+ */
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{
+    AppHandle, Emitter, Manager, State, WebviewUrl, WebviewWindowBuilder, Window, WindowEvent,
+};
+
+use crate::app_menu;
+use crate::library::Book;
+use crate::state::AppState;
+use crate::window_state;
+
+const LABEL_PREFIX: &str = "reader-";
+
+/// Book id -> label of the reader window showing it
+#[derive(Default)]
+pub struct ReaderWindows {
+    open: Mutex<HashMap<String, String>>,
+}
+
+impl ReaderWindows {
+    /// Label of the window the book is open in, if any
+    pub fn window_for(&self, book_id: &str) -> Option<String> {
+        self.open.lock().ok()?.get(book_id).cloned()
+    }
+
+    fn insert(&self, book_id: String, label: String) {
+        if let Ok(mut open) = self.open.lock() {
+            open.insert(book_id, label);
+        }
+    }
+
+    fn remove_window(&self, label: &str) {
+        if let Ok(mut open) = self.open.lock() {
+            open.retain(|_, l| l != label);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct BookProgress {
+    #[serde(rename = "bookId")]
+    book_id: String,
+    progress: f32,
+    cfi: Option<String>,
+}
+
+/// Open a book in its own window, or focus the window it is already open in
+#[tauri::command]
+pub fn open_book_window(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    windows: State<'_, ReaderWindows>,
+    book_id: String,
+) -> Result<String, String> {
+    let book_id = state.resolve_book_id(&book_id);
+    let book = state
+        .with_library(|lib| lib.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+
+    if let Some(window) = windows
+        .window_for(&book.id)
+        .and_then(|label| app.get_webview_window(&label))
+    {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+        return Ok(window.label().to_string());
+    }
+
+    let label = format!("{}{}", LABEL_PREFIX, book.id);
+    let url = WebviewUrl::App(format!("index.html?book={}", book.id).into());
+    let window = WebviewWindowBuilder::new(&app, label.as_str(), url)
+        .title(format!("{} - Epilogue", book.title))
+        .inner_size(900.0, 800.0)
+        .min_inner_size(400.0, 300.0)
+        .visible(false)
+        .build()
+        .map_err(|e| format!("Failed to open reader window: {}", e))?;
+
+    // Bring back this book's fullscreen/immersive setup from last time
+    window_state::restore(&window);
+    app_menu::apply_zoom(&app);
+    let _ = window.show();
+
+    windows.insert(book.id, label.clone());
+    Ok(label)
+}
+
+/// Tell the reader window showing this book about progress made elsewhere
+/// (e.g. the same book read in the main window)
+pub fn notify_progress(app: &AppHandle, from_window: &str, book: &Book) {
+    let Some(label) = app.state::<ReaderWindows>().window_for(&book.id) else {
+        return;
+    };
+    if label == from_window {
+        return;
+    }
+
+    let _ = app.emit_to(
+        label.as_str(),
+        "book-progress",
+        BookProgress {
+            book_id: book.id.clone(),
+            progress: book.progress,
+            cfi: book.cfi.clone(),
+        },
+    );
+}
+
+/// Window event hook: forget reader windows once they are gone
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if !window.label().starts_with(LABEL_PREFIX) {
+        return;
+    }
+
+    if let WindowEvent::Destroyed = event {
+        window
+            .app_handle()
+            .state::<ReaderWindows>()
+            .remove_window(window.label());
+    }
+}
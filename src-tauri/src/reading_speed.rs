@@ -0,0 +1,333 @@
+/**
+ * Measured reading speed: words per minute from reading sessions, used for
+ * time estimates instead of a fixed guess
+ */
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::time::UNIX_EPOCH;
+use tauri::State;
+
+use crate::annotations;
+use crate::config;
+use crate::crash;
+use crate::library::{self, Book};
+use crate::logging;
+use crate::sessions::{self, ReadingSession};
+use crate::state::AppState;
+
+/// Used until enough sessions are measured
+const DEFAULT_WPM: f64 = 250.0;
+/// Shorter sessions say little about speed
+const MIN_SESSION_SECONDS: i64 = 120;
+/// Speeds outside this range are a tab left open or skimming, not reading
+const MIN_PLAUSIBLE_WPM: f64 = 50.0;
+const MAX_PLAUSIBLE_WPM: f64 = 1200.0;
+/// The personal speed follows this many most recent sessions
+const ROLLING_SESSIONS: usize = 50;
+/// Sessions further from the median than this many deviations are outliers
+const OUTLIER_DEVIATIONS: f64 = 3.0;
+/// Scales the median absolute deviation to a standard deviation
+const MAD_SCALE: f64 = 1.4826;
+/// Sessions of one book needed before it gets a speed of its own
+const MIN_BOOK_SESSIONS: usize = 5;
+/// Sessions needed for full confidence
+const CONFIDENT_SESSIONS: f64 = 20.0;
+
+/// stats/reading_speed.json
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct ReadingSpeedStats {
+    #[serde(default)]
+    wpm: Option<f64>,
+    #[serde(default)]
+    confidence: f64,
+    #[serde(default)]
+    samples: usize,
+    /// Measured per book, for books with enough sessions
+    #[serde(rename = "bookWpm", default)]
+    book_wpm: BTreeMap<String, f64>,
+    /// Set by the user per book, e.g. for books in another language
+    #[serde(default)]
+    overrides: BTreeMap<String, f64>,
+    /// Size and modification time of the sessions log last measured
+    #[serde(rename = "sessionsVersion", default)]
+    sessions_version: Option<String>,
+    #[serde(rename = "updatedAt", default)]
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ReadingSpeed {
+    pub wpm: f64,
+    /// 0 to 1: how many sessions the value rests on and how consistent they are
+    pub confidence: f64,
+    pub samples: usize,
+    /// "override", "book", "measured" or "default"
+    pub source: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TimeEstimate {
+    /// Minutes to finish the book at the current progress
+    pub minutes: Option<f64>,
+    /// Minutes to read the chapter the CFI points into
+    #[serde(rename = "chapterMinutes")]
+    pub chapter_minutes: Option<f64>,
+    pub speed: ReadingSpeed,
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    words: f64,
+    minutes: f64,
+}
+
+impl Sample {
+    fn wpm(&self) -> f64 {
+        self.words / self.minutes
+    }
+}
+
+fn sample(session: &ReadingSession) -> Option<Sample> {
+    let words = session.words.filter(|w| *w > 0)? as f64;
+    if session.active_seconds < MIN_SESSION_SECONDS {
+        return None;
+    }
+    let sample = Sample {
+        words,
+        minutes: session.active_seconds as f64 / 60.0,
+    };
+    (MIN_PLAUSIBLE_WPM..=MAX_PLAUSIBLE_WPM)
+        .contains(&sample.wpm())
+        .then_some(sample)
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// Words per minute over the samples within a few median absolute
+/// deviations of the median, and a confidence for it
+fn robust_speed(samples: &[Sample]) -> Option<(f64, f64, usize)> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut speeds: Vec<f64> = samples.iter().map(Sample::wpm).collect();
+    let center = median(&mut speeds);
+    let mut deviations: Vec<f64> = speeds.iter().map(|s| (s - center).abs()).collect();
+    let spread = median(&mut deviations) * MAD_SCALE;
+
+    let inliers: Vec<&Sample> = samples
+        .iter()
+        .filter(|s| spread == 0.0 || (s.wpm() - center).abs() <= OUTLIER_DEVIATIONS * spread)
+        .collect();
+    let words: f64 = inliers.iter().map(|s| s.words).sum();
+    let minutes: f64 = inliers.iter().map(|s| s.minutes).sum();
+    if minutes <= 0.0 {
+        return None;
+    }
+
+    let confidence =
+        (inliers.len() as f64 / CONFIDENT_SESSIONS).min(1.0) * (1.0 - (spread / center).min(1.0));
+    Some((words / minutes, confidence, inliers.len()))
+}
+
+fn sessions_version(state: &AppState) -> Result<String, String> {
+    let path = &state.paths()?.sessions;
+    Ok(match fs::metadata(path) {
+        Ok(metadata) => format!(
+            "{}:{}",
+            metadata.len(),
+            metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_millis())
+        ),
+        Err(_) => String::new(),
+    })
+}
+
+fn load_stats(state: &AppState) -> Result<ReadingSpeedStats, String> {
+    let path = &state.paths()?.reading_speed;
+    let Ok(content) = fs::read_to_string(path) else {
+        return Ok(ReadingSpeedStats::default());
+    };
+    Ok(serde_json::from_str(&content).unwrap_or_else(|e| {
+        logging::warn(&format!(
+            "Failed to parse reading speed, measuring again: {}",
+            e
+        ));
+        ReadingSpeedStats::default()
+    }))
+}
+
+fn save_stats(state: &AppState, stats: &ReadingSpeedStats) -> Result<(), String> {
+    // Safe mode writes nothing to the data directory
+    if config::is_safe_mode() {
+        return Ok(());
+    }
+
+    let path = &state.paths()?.reading_speed;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create stats directory: {}", e))?;
+    }
+    let json = serde_json::to_string_pretty(stats)
+        .map_err(|e| format!("Failed to serialize reading speed: {}", e))?;
+    fs::write(path, json).map_err(|e| format!("Failed to save reading speed: {}", e))
+}
+
+/// The saved stats, measured again if sessions were recorded since
+fn current_stats(state: &AppState) -> Result<ReadingSpeedStats, String> {
+    let mut stats = load_stats(state)?;
+    let version = sessions_version(state)?;
+    if stats.sessions_version.as_deref() == Some(version.as_str()) {
+        return Ok(stats);
+    }
+
+    let sessions = sessions::load_sessions(&state.paths()?.sessions);
+    let mut by_book: HashMap<&str, Vec<Sample>> = HashMap::new();
+    let mut recent = Vec::new();
+    for session in sessions.iter().rev() {
+        if let Some(sample) = sample(session) {
+            if recent.len() < ROLLING_SESSIONS {
+                recent.push(sample);
+            }
+            by_book
+                .entry(session.book_id.as_str())
+                .or_default()
+                .push(sample);
+        }
+    }
+
+    let measured = robust_speed(&recent);
+    stats.wpm = measured.map(|(wpm, _, _)| wpm);
+    stats.confidence = measured.map_or(0.0, |(_, confidence, _)| confidence);
+    stats.samples = measured.map_or(0, |(_, _, samples)| samples);
+    stats.book_wpm = by_book
+        .into_iter()
+        .filter_map(|(book_id, samples)| {
+            let (wpm, _, count) = robust_speed(&samples)?;
+            (count >= MIN_BOOK_SESSIONS).then(|| (book_id.to_string(), wpm))
+        })
+        .collect();
+    stats.sessions_version = Some(version);
+    stats.updated_at = Some(Utc::now());
+    save_stats(state, &stats)?;
+    Ok(stats)
+}
+
+/// Speed for a book: the user's override, then the book's own measured
+/// speed, then the personal one, then a default
+pub fn speed_for(state: &AppState, book_id: Option<&str>) -> Result<ReadingSpeed, String> {
+    let stats = current_stats(state)?;
+    let speed = |wpm: f64, confidence: f64, source: &str| ReadingSpeed {
+        wpm: wpm.round(),
+        confidence,
+        samples: stats.samples,
+        source: source.to_string(),
+    };
+
+    if let Some(book_id) = book_id {
+        if let Some(wpm) = stats.overrides.get(book_id) {
+            return Ok(speed(*wpm, 1.0, "override"));
+        }
+        if let Some(wpm) = stats.book_wpm.get(book_id) {
+            return Ok(speed(*wpm, stats.confidence, "book"));
+        }
+    }
+    Ok(match stats.wpm {
+        Some(wpm) => speed(wpm, stats.confidence, "measured"),
+        None => speed(DEFAULT_WPM, 0.0, "default"),
+    })
+}
+
+/// Words in the spine item `index`
+pub fn chapter_words(path: &str, index: usize) -> Option<u64> {
+    let mut doc = epub::doc::EpubDoc::new(path).ok()?;
+    if !doc.set_current_page(index) {
+        return None;
+    }
+    let (content, _) = doc.get_current_str()?;
+    Some(library::count_text_words(&content))
+}
+
+/// The personal reading speed, or the one used for `book_id`
+#[tauri::command]
+pub fn get_reading_speed(
+    state: State<'_, AppState>,
+    book_id: Option<String>,
+) -> Result<ReadingSpeed, String> {
+    speed_for(&state, book_id.as_deref())
+}
+
+/// Set or clear the words per minute used for one book
+#[tauri::command]
+pub fn set_reading_speed_override(
+    state: State<'_, AppState>,
+    book_id: String,
+    wpm: Option<f64>,
+) -> Result<ReadingSpeed, String> {
+    if let Some(wpm) = wpm {
+        if !(MIN_PLAUSIBLE_WPM..=MAX_PLAUSIBLE_WPM).contains(&wpm) {
+            return Err(format!(
+                "Reading speed must be between {} and {} words per minute, got {}",
+                MIN_PLAUSIBLE_WPM, MAX_PLAUSIBLE_WPM, wpm
+            ));
+        }
+    }
+
+    let mut stats = current_stats(&state)?;
+    match wpm {
+        Some(wpm) => stats.overrides.insert(book_id.clone(), wpm),
+        None => stats.overrides.remove(&book_id),
+    };
+    save_stats(&state, &stats)?;
+    speed_for(&state, Some(&book_id))
+}
+
+/// Time left in a book at the reader's speed, and the time for the chapter
+/// `cfi` points into
+#[tauri::command]
+pub fn estimate_time_remaining(
+    state: State<'_, AppState>,
+    book_id: String,
+    cfi: Option<String>,
+) -> Result<TimeEstimate, String> {
+    let book: Book = state
+        .with_library(|library| library.books.iter().find(|b| b.id == book_id).cloned())?
+        .ok_or_else(|| format!("Book with id '{}' not found", book_id))?;
+    book.require_text()?;
+    let speed = speed_for(&state, Some(&book.id))?;
+
+    let minutes = book
+        .word_count
+        .map(|words| words as f64 * (1.0 - book.progress.clamp(0.0, 1.0) as f64) / speed.wpm);
+    let chapter_minutes = cfi
+        .as_deref()
+        .and_then(annotations::spine_index)
+        .and_then(|index| {
+            crash::catch_panic("counting chapter words", || {
+                chapter_words(&book.file_path, index)
+            })
+            .unwrap_or_else(|e| {
+                logging::warn(&e);
+                None
+            })
+        })
+        .map(|words| words as f64 / speed.wpm);
+
+    Ok(TimeEstimate {
+        minutes,
+        chapter_minutes,
+        speed,
+    })
+}
@@ -0,0 +1,163 @@
+/**
+ * Highlights export in Readwise's CSV import format
+ */
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use tauri::{AppHandle, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use crate::annotations::{self, Highlight};
+use crate::goodreads::escape_csv;
+use crate::library::Book;
+use crate::meta;
+use crate::state::AppState;
+
+const COLUMNS: &[&str] = &[
+    "Highlight",
+    "Title",
+    "Author",
+    "URL",
+    "Note",
+    "Location",
+    "Date",
+];
+/// Destination name that puts the CSV on the clipboard instead of a file
+const CLIPBOARD: &str = "clipboard";
+/// Location = spine index * this + character offset, so highlights sort in
+/// reading order
+const LOCATION_SPINE_STRIDE: usize = 100_000;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ReadwiseExportSummary {
+    pub destination: String,
+    pub highlights: usize,
+    pub books: usize,
+}
+
+fn location(highlight: &Highlight) -> String {
+    match annotations::spine_index(&highlight.cfi) {
+        Some(index) => {
+            (index * LOCATION_SPINE_STRIDE + annotations::char_offset(&highlight.cfi)).to_string()
+        }
+        None => String::new(),
+    }
+}
+
+fn highlight_row(book: &Book, highlight: &Highlight) -> String {
+    [
+        highlight.text.clone(),
+        book.title.clone(),
+        book.author.clone(),
+        String::new(),
+        highlight.note.clone().unwrap_or_default(),
+        location(highlight),
+        highlight.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+    ]
+    .iter()
+    .map(|field| escape_csv(field))
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+/// Export every highlight as Readwise CSV to `destination`, a file path or
+/// "clipboard". With `since_last_export`, only highlights made after the
+/// previous export to the same destination are included. Highlights of
+/// private categories are left out.
+#[tauri::command]
+pub fn export_highlights_readwise(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    destination: String,
+    since_last_export: Option<bool>,
+) -> Result<ReadwiseExportSummary, String> {
+    let cursor = if since_last_export.unwrap_or(false) {
+        meta::load_meta()
+            .readwise_cursors
+            .get(&destination)
+            .copied()
+    } else {
+        None
+    };
+    let books = state.with_library(|library| library.books.clone())?;
+    let categories = state.preferences()?.highlight_categories;
+
+    let mut csv = COLUMNS.join(",");
+    csv.push('\n');
+    let mut exported = 0;
+    let mut exported_books = 0;
+    let mut newest: Option<DateTime<Utc>> = cursor;
+    for book in &books {
+        let highlights: Vec<Highlight> = annotations::load_annotations(&state, &book.id)?
+            .highlights
+            .into_iter()
+            .filter(|h| !h.text.trim().is_empty())
+            .filter(|h| annotations::is_exported(h, &categories))
+            .filter(|h| cursor.is_none_or(|c| h.created_at > c))
+            .collect();
+        if highlights.is_empty() {
+            continue;
+        }
+
+        for highlight in &highlights {
+            csv.push_str(&highlight_row(book, highlight));
+            csv.push('\n');
+            newest = newest.max(Some(highlight.created_at));
+        }
+        exported += highlights.len();
+        exported_books += 1;
+    }
+
+    if destination == CLIPBOARD {
+        app.clipboard()
+            .write_text(csv)
+            .map_err(|e| format!("Failed to copy highlights: {}", e))?;
+    } else {
+        fs::write(&destination, csv)
+            .map_err(|e| format!("Failed to write Readwise export: {}", e))?;
+    }
+
+    if let Some(newest) = newest {
+        meta::update_meta(|m| {
+            m.readwise_cursors.insert(destination.clone(), newest);
+        })?;
+    }
+
+    Ok(ReadwiseExportSummary {
+        destination,
+        highlights: exported,
+        books: exported_books,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::goodreads::testing::parse_csv;
+    use crate::state::testing::book;
+
+    #[test]
+    fn highlight_rows_round_trip() {
+        let mut book = book("a");
+        book.title = "Notes, \"Quoted\"".to_string();
+        let mut highlight = Highlight::new(
+            "epubcfi(/6/4!/4/2/1:0)".to_string(),
+            "She said, \"wait\".".to_string(),
+            None,
+        );
+        highlight.note = Some("A note\nover two lines,\r\nand a third".to_string());
+
+        let csv = format!(
+            "{}\n{}\n",
+            COLUMNS.join(","),
+            highlight_row(&book, &highlight)
+        );
+        let rows = parse_csv(&csv);
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].len(), COLUMNS.len());
+        assert_eq!(rows[1][0], "She said, \"wait\".");
+        assert_eq!(rows[1][1], "Notes, \"Quoted\"");
+        assert_eq!(rows[1][4], "A note\nover two lines,\r\nand a third");
+    }
+}
@@ -0,0 +1,306 @@
+/**
+ * Series on the shelf: volumes in order, the gaps between them, and which
+ * volume to read next
+ */
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use tauri::{AppHandle, State};
+
+use crate::library::{self, Book, ReadingState};
+use crate::state::AppState;
+
+/// Gaps are only looked for up to this volume, so a year used as an index
+/// doesn't list two thousand missing books
+const MAX_GAP_INDEX: u32 = 200;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum VolumeStatus {
+    Finished,
+    InProgress,
+    Unread,
+    Abandoned,
+    /// A volume before the last one owned that isn't in the library
+    Missing,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SeriesVolume {
+    /// Position in the series; books without one are listed last
+    pub index: Option<f32>,
+    /// Last volume of an omnibus
+    #[serde(rename = "indexEnd")]
+    pub index_end: Option<f32>,
+    pub status: VolumeStatus,
+    /// None for a missing volume
+    pub book: Option<Book>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SeriesStatus {
+    pub name: String,
+    pub volumes: Vec<SeriesVolume>,
+    pub finished: usize,
+    /// Whole volume numbers not in the library
+    pub missing: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SeriesBooks {
+    pub name: String,
+    /// In series order, books without an index last
+    pub books: Vec<Book>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UpNext {
+    pub series: String,
+    /// The furthest volume finished
+    pub after: Book,
+    /// The volume to read next, when the library has it
+    pub next: Option<Book>,
+    #[serde(rename = "nextIndex")]
+    pub next_index: f32,
+    /// The next volume isn't in the library, though later ones are
+    pub missing: bool,
+}
+
+/// Position in a series, and the end of an omnibus's range
+pub type SeriesIndex = (f32, Option<f32>);
+
+/// A series index from metadata: "2", "2.5", or "1-3" for an omnibus
+pub fn parse_index(value: &str) -> Option<SeriesIndex> {
+    let number = |s: &str| {
+        s.trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|n| n.is_finite() && *n >= 0.0)
+    };
+    match value.trim().split_once(['-', '–', '—']) {
+        Some((start, end)) => {
+            let (start, end) = (number(start)?, number(end)?);
+            Some((start, (end > start).then_some(end)))
+        }
+        None => Some((number(value)?, None)),
+    }
+}
+
+pub fn series_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+fn status_of(book: &Book) -> VolumeStatus {
+    match book.reading_state {
+        ReadingState::Finished => VolumeStatus::Finished,
+        ReadingState::Reading => VolumeStatus::InProgress,
+        ReadingState::ToRead if book.progress > 0.0 => VolumeStatus::InProgress,
+        ReadingState::ToRead => VolumeStatus::Unread,
+        ReadingState::Abandoned => VolumeStatus::Abandoned,
+    }
+}
+
+/// The furthest position a book reaches: its end for an omnibus
+fn last_index(book: &Book) -> Option<f32> {
+    book.series_end.or(book.series_index)
+}
+
+/// Whole volume numbers a book stands for: 1 to 3 for an omnibus "1-3",
+/// none for a novella at 2.5
+fn covered(book: &Book) -> impl Iterator<Item = u32> {
+    let (from, to) = match (book.series_index, last_index(book)) {
+        (Some(start), Some(end)) => (
+            start.ceil() as u32,
+            end.floor().min(MAX_GAP_INDEX as f32) as u32,
+        ),
+        _ => (1, 0),
+    };
+    from..=to
+}
+
+/// By index, books without one last, then by title
+fn by_index(a: &Book, b: &Book) -> Ordering {
+    let index = match (a.series_index, b.series_index) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    index.then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+}
+
+/// Books of each series by series key, in series order
+fn group_series(books: Vec<Book>) -> HashMap<String, Vec<Book>> {
+    let mut groups: HashMap<String, Vec<Book>> = HashMap::new();
+    for book in books {
+        if let Some(key) = book.series.as_deref().map(series_key) {
+            groups.entry(key).or_default().push(book);
+        }
+    }
+    for books in groups.values_mut() {
+        books.sort_by(by_index);
+    }
+    groups
+}
+
+/// Whole volumes between 1 and the last one owned that no book covers
+fn missing_volumes(books: &[Book]) -> Vec<u32> {
+    let owned: BTreeSet<u32> = books.iter().flat_map(covered).collect();
+    let Some(&last) = owned.last() else {
+        return Vec::new();
+    };
+    (1..last).filter(|i| !owned.contains(i)).collect()
+}
+
+/// What to read after the furthest volume finished in a series
+fn up_next(name: &str, books: &[Book]) -> Option<UpNext> {
+    let after = books
+        .iter()
+        .filter(|b| b.reading_state == ReadingState::Finished)
+        .filter(|b| last_index(b).is_some())
+        .max_by(|a, b| {
+            last_index(a)
+                .partial_cmp(&last_index(b))
+                .unwrap_or(Ordering::Equal)
+        })?;
+    let reached = last_index(after)?;
+    let next_whole = reached.floor() + 1.0;
+
+    let next = books
+        .iter()
+        .find(|b| last_index(b).is_some_and(|last| last > reached))?;
+    let start = next.series_index.unwrap_or(reached);
+    if start > next_whole {
+        // Later volumes are on the shelf but the next one isn't
+        return Some(UpNext {
+            series: name.to_string(),
+            after: after.clone(),
+            next: None,
+            next_index: next_whole,
+            missing: true,
+        });
+    }
+    // Already being read or put away, nothing to suggest
+    if status_of(next) != VolumeStatus::Unread || next.archived {
+        return None;
+    }
+    Some(UpNext {
+        series: name.to_string(),
+        after: after.clone(),
+        next: Some(next.clone()),
+        next_index: start,
+        missing: false,
+    })
+}
+
+/// A series' volumes in order with how far each is read, and the volumes
+/// missing from the library in between
+#[tauri::command]
+pub fn get_series_status(
+    state: State<'_, AppState>,
+    series_name: String,
+) -> Result<SeriesStatus, String> {
+    let books = state.with_library(|library| library.books.clone())?;
+    let books = group_series(books)
+        .remove(&series_key(&series_name))
+        .ok_or_else(|| format!("No books of the series '{}'", series_name.trim()))?;
+    let missing = missing_volumes(&books);
+
+    let mut volumes: Vec<SeriesVolume> = books
+        .iter()
+        .map(|book| SeriesVolume {
+            index: book.series_index,
+            index_end: book.series_end,
+            status: status_of(book),
+            book: Some(book.clone()),
+        })
+        .collect();
+    for &index in &missing {
+        // Before the first volume past the gap; volumes without an index
+        // stay last
+        let at = volumes
+            .iter()
+            .position(|v| v.index.is_none_or(|i| i > index as f32))
+            .unwrap_or(volumes.len());
+        volumes.insert(
+            at,
+            SeriesVolume {
+                index: Some(index as f32),
+                index_end: None,
+                status: VolumeStatus::Missing,
+                book: None,
+            },
+        );
+    }
+
+    Ok(SeriesStatus {
+        // The name as the first volume spells it
+        name: books
+            .iter()
+            .find_map(|b| b.series.clone())
+            .unwrap_or(series_name),
+        finished: books
+            .iter()
+            .filter(|b| b.reading_state == ReadingState::Finished)
+            .count(),
+        volumes,
+        missing,
+    })
+}
+
+/// For every series with a volume finished, the next one: on the shelf and
+/// unread, or missing when the library skips it. Most recently finished
+/// series first.
+#[tauri::command]
+pub fn get_up_next_suggestions(state: State<'_, AppState>) -> Result<Vec<UpNext>, String> {
+    let books = state.with_library(|library| library.books.clone())?;
+    let mut suggestions: Vec<UpNext> = group_series(books)
+        .values()
+        .filter_map(|books| {
+            let name = books.iter().find_map(|b| b.series.clone())?;
+            up_next(&name, books)
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.after.finished_at.cmp(&a.after.finished_at));
+    Ok(suggestions)
+}
+
+/// Every series in the library with its books in order, by name
+#[tauri::command]
+pub fn get_series(state: State<'_, AppState>) -> Result<Vec<SeriesBooks>, String> {
+    let books = state.with_library(|library| library.books.clone())?;
+    let mut series: Vec<SeriesBooks> = group_series(books)
+        .into_values()
+        .filter_map(|books| {
+            Some(SeriesBooks {
+                name: books.iter().find_map(|b| b.series.clone())?,
+                books,
+            })
+        })
+        .collect();
+    series.sort_by_cached_key(|s| series_key(&s.name));
+    Ok(series)
+}
+
+/// Correct a book's series by hand. An empty name takes the book out of
+/// its series.
+#[tauri::command]
+pub fn set_series(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    book_id: String,
+    name: Option<String>,
+    index: Option<f32>,
+) -> Result<Book, String> {
+    if let Some(index) = index {
+        if !index.is_finite() || index < 0.0 {
+            return Err(format!("Invalid series index: {}", index));
+        }
+    }
+    let name = name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+    library::update_book(&app, &state, &book_id, |book| {
+        book.series_index = name.as_ref().and(index);
+        book.series_end = None;
+        book.series = name;
+    })
+}
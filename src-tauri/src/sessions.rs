@@ -0,0 +1,930 @@
+/**
+ * Reading sessions: how long a book was actually read, minus idle time
+ */
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::{AppHandle, Emitter, Manager, State, Window, WindowEvent};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::advanced::AdvancedConfig;
+use crate::config;
+use crate::logging;
+use crate::sleep_inhibit::SleepInhibit;
+use crate::state::AppState;
+
+/// Sessions without any activity for this long are closed automatically
+const AUTO_CLOSE_AFTER_MINUTES: i64 = 60;
+/// How often open sessions are checked for auto-closing
+const IDLE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Sleep inhibit reason held while a session is open
+const INHIBIT_REASON: &str = "reading";
+/// How often a timed session reports its clock
+const TICK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+/// How often the open session's last activity is written to disk
+const PERSIST_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Longest goal a timed session accepts
+const MAX_TARGET_MINUTES: u32 = 24 * 60;
+/// Sessions longer than this are the app left open, not reading, and are
+/// not recorded
+const MAX_SESSION_HOURS: i64 = 6;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadingSession {
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    #[serde(rename = "lastActivity")]
+    pub last_activity: DateTime<Utc>,
+    #[serde(rename = "activeSeconds")]
+    pub active_seconds: i64,
+    #[serde(rename = "idleSeconds")]
+    pub idle_seconds: i64,
+    /// Book progress when the session started and ended
+    #[serde(rename = "startProgress", default)]
+    pub start_progress: Option<f32>,
+    #[serde(rename = "endProgress", default)]
+    pub end_progress: Option<f32>,
+    /// Words read: the progress made times the book's word count
+    #[serde(default)]
+    pub words: Option<u64>,
+}
+
+impl ReadingSession {
+    fn new(book_id: String, now: DateTime<Utc>, progress: Option<f32>) -> Self {
+        Self {
+            book_id,
+            start: now,
+            end: None,
+            last_activity: now,
+            active_seconds: 0,
+            idle_seconds: 0,
+            start_progress: progress,
+            end_progress: None,
+            words: None,
+        }
+    }
+
+    /// Account the time since the last activity as active, or as idle when
+    /// the gap is longer than the threshold
+    fn record_activity(&mut self, now: DateTime<Utc>, idle_threshold: Duration) {
+        let gap = now - self.last_activity;
+        if gap <= Duration::zero() {
+            return;
+        }
+        if gap > idle_threshold {
+            self.idle_seconds += gap.num_seconds();
+        } else {
+            self.active_seconds += gap.num_seconds();
+        }
+        self.last_activity = now;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PauseReason {
+    /// The window lost focus; regaining it resumes the clock
+    Blur,
+    /// pause_session(); only resume_session() resumes
+    User,
+}
+
+/// Clock of a session started with a goal. Only time with the clock
+/// running counts towards the goal.
+struct SessionTimer {
+    book_id: String,
+    target: std::time::Duration,
+    /// Time counted up to the last pause
+    counted: std::time::Duration,
+    running_since: Option<Instant>,
+    paused: Option<PauseReason>,
+    goal_reached: bool,
+    /// Changed whenever the clock restarts, so stale tick threads stop
+    generation: u64,
+}
+
+impl SessionTimer {
+    fn elapsed(&self) -> std::time::Duration {
+        self.counted
+            + self
+                .running_since
+                .map_or_else(Default::default, |t| t.elapsed())
+    }
+
+    fn pause(&mut self, reason: PauseReason) -> bool {
+        if self.paused.is_some() {
+            return false;
+        }
+        self.counted = self.elapsed();
+        self.running_since = None;
+        self.paused = Some(reason);
+        true
+    }
+
+    fn resume(&mut self, generation: u64) -> bool {
+        if self.paused.take().is_none() {
+            return false;
+        }
+        self.running_since = Some(Instant::now());
+        self.generation = generation;
+        true
+    }
+
+    fn tick(&self) -> SessionTick {
+        let elapsed = self.elapsed();
+        SessionTick {
+            book_id: self.book_id.clone(),
+            elapsed_seconds: elapsed.as_secs(),
+            target_seconds: self.target.as_secs(),
+            remaining_seconds: self.target.saturating_sub(elapsed).as_secs(),
+            overtime_seconds: elapsed.saturating_sub(self.target).as_secs(),
+            paused: self.paused.is_some(),
+            goal_reached: self.goal_reached,
+        }
+    }
+}
+
+/// Payload of "session-tick" and "session-goal-reached"
+#[derive(Debug, Serialize, Clone)]
+pub struct SessionTick {
+    #[serde(rename = "bookId")]
+    pub book_id: String,
+    #[serde(rename = "elapsedSeconds")]
+    pub elapsed_seconds: u64,
+    #[serde(rename = "targetSeconds")]
+    pub target_seconds: u64,
+    #[serde(rename = "remainingSeconds")]
+    pub remaining_seconds: u64,
+    /// Time read past the goal
+    #[serde(rename = "overtimeSeconds")]
+    pub overtime_seconds: u64,
+    pub paused: bool,
+    #[serde(rename = "goalReached")]
+    pub goal_reached: bool,
+}
+
+/// Time read and the sessions it was read in
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ReadingTime {
+    #[serde(rename = "activeSeconds")]
+    pub active_seconds: i64,
+    pub sessions: usize,
+}
+
+impl ReadingTime {
+    fn add(&mut self, session: &ReadingSession) {
+        self.active_seconds += session.active_seconds;
+        self.sessions += 1;
+    }
+}
+
+/// The session currently open, if any, and its goal clock
+#[derive(Default)]
+pub struct SessionManager {
+    open: Mutex<Option<ReadingSession>>,
+    timer: Mutex<Option<SessionTimer>>,
+    generations: AtomicU64,
+    /// When the open session was last written to disk by activity
+    persisted_at: Mutex<Option<Instant>>,
+}
+
+impl SessionManager {
+    fn next_generation(&self) -> u64 {
+        self.generations.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+fn idle_threshold(app: &AppHandle) -> Duration {
+    Duration::minutes(app.state::<AdvancedConfig>().idle_threshold_minutes as i64)
+}
+
+/// A book's current progress and word count
+fn book_progress(app: &AppHandle, book_id: &str) -> Option<(f32, Option<u64>)> {
+    app.state::<AppState>()
+        .with_library(|library| {
+            library
+                .books
+                .iter()
+                .find(|b| b.id == book_id)
+                .map(|b| (b.progress, b.word_count))
+        })
+        .ok()
+        .flatten()
+}
+
+fn append_session(state: &AppState, session: &ReadingSession) -> Result<(), String> {
+    // Safe mode writes nothing to the data directory
+    if config::is_safe_mode() {
+        return Ok(());
+    }
+
+    let path = &state.paths()?.sessions;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create stats directory: {}", e))?;
+    }
+
+    let line = serde_json::to_string(session)
+        .map_err(|e| format!("Failed to serialize reading session: {}", e))?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open sessions file: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to save reading session: {}", e))
+}
+
+/// Keep the open session on disk, so one left open by a crash can be
+/// recorded on the next launch
+fn persist_open(state: &AppState, session: Option<&ReadingSession>) -> Result<(), String> {
+    if config::is_safe_mode() {
+        return Ok(());
+    }
+    let path = &state.paths()?.open_session;
+    let Some(session) = session else {
+        if path.exists() {
+            fs::remove_file(path).map_err(|e| format!("Failed to remove open session: {}", e))?;
+        }
+        return Ok(());
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create stats directory: {}", e))?;
+    }
+    let json = serde_json::to_string(session)
+        .map_err(|e| format!("Failed to serialize reading session: {}", e))?;
+    fs::write(path, json).map_err(|e| format!("Failed to save open session: {}", e))
+}
+
+/// Every recorded session, oldest first. Unreadable lines are skipped.
+pub fn load_sessions(path: &Path) -> Vec<ReadingSession> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Add sessions recorded on another machine, skipping ones already in the
+/// log. Returns how many were added.
+pub fn merge_sessions(state: &AppState, incoming: Vec<ReadingSession>) -> Result<usize, String> {
+    let path = &state.paths()?.sessions;
+    let mut sessions = load_sessions(path);
+    let before = sessions.len();
+    for session in incoming {
+        if !sessions
+            .iter()
+            .any(|s| s.book_id == session.book_id && s.start == session.start)
+        {
+            sessions.push(session);
+        }
+    }
+    let added = sessions.len() - before;
+    if added == 0 {
+        return Ok(0);
+    }
+
+    sessions.sort_by(|a, b| a.start.cmp(&b.start));
+    let mut content = String::new();
+    for session in &sessions {
+        let line = serde_json::to_string(session)
+            .map_err(|e| format!("Failed to serialize reading session: {}", e))?;
+        content.push_str(&line);
+        content.push('\n');
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create stats directory: {}", e))?;
+    }
+    fs::write(path, content).map_err(|e| format!("Failed to save reading sessions: {}", e))?;
+    Ok(added)
+}
+
+/// End `session` at `end`, or at its last activity when that is later,
+/// with the book's progress and word count at that point. False when it
+/// ran so long the app must have been left open, and isn't recorded.
+fn finish_session(
+    session: &mut ReadingSession,
+    end: DateTime<Utc>,
+    idle_threshold: Duration,
+    progress: Option<(f32, Option<u64>)>,
+) -> bool {
+    session.record_activity(end, idle_threshold);
+    let end = session.last_activity.max(end);
+    session.end = Some(end);
+    if let Some((progress, word_count)) = progress {
+        session.end_progress = Some(progress);
+        // Only forward progress counts; jumping back isn't reading
+        let advanced = (progress - session.start_progress.unwrap_or(progress)).max(0.0);
+        session.words = word_count.map(|count| (count as f64 * advanced as f64).round() as u64);
+    }
+    if end - session.start > Duration::hours(MAX_SESSION_HOURS) {
+        logging::info(&format!(
+            "Discarded a reading session of {} hours for {}, the app was left open",
+            (end - session.start).num_hours(),
+            session.book_id
+        ));
+        return false;
+    }
+    true
+}
+
+/// Close the open session at `end` and record it, unless it ran so long
+/// the app must have been left open
+fn close_session(app: &AppHandle, mut session: ReadingSession, end: DateTime<Utc>) {
+    let progress = book_progress(app, &session.book_id);
+    if finish_session(&mut session, end, idle_threshold(app), progress) {
+        if let Err(e) = append_session(&app.state::<AppState>(), &session) {
+            logging::error(&e);
+        }
+    }
+    app.state::<SleepInhibit>().release(Some(INHIBIT_REASON));
+
+    // The goal clock ends with its session
+    let timer = app
+        .state::<SessionManager>()
+        .timer
+        .lock()
+        .ok()
+        .and_then(|mut timer| match timer.as_ref() {
+            Some(t) if t.book_id == session.book_id => timer.take(),
+            _ => None,
+        });
+    if let Some(timer) = timer {
+        let _ = app.emit("session-tick", timer.tick());
+    }
+}
+
+/// A copy of the session being read right now
+pub fn open_session(sessions: &SessionManager) -> Option<ReadingSession> {
+    sessions.open.lock().ok()?.clone()
+}
+
+/// Close whatever session is open, e.g. on exit
+pub fn end_open_session(app: &AppHandle) {
+    let session = app
+        .state::<SessionManager>()
+        .open
+        .lock()
+        .ok()
+        .and_then(|mut open| open.take());
+    if let Some(session) = session {
+        close_session(app, session, Utc::now());
+        forget_open(app);
+    }
+}
+
+fn forget_open(app: &AppHandle) {
+    if let Err(e) = persist_open(&app.state::<AppState>(), None) {
+        logging::warn(&e);
+    }
+}
+
+/// The open session kept on disk by `persist_open`
+fn load_open_session(path: &Path) -> Option<ReadingSession> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// Record the session a crash left open, ending it at its last activity
+/// written to disk rather than losing it
+pub fn recover_open_session(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let Ok(path) = state.paths().map(|paths| paths.open_session.clone()) else {
+        return;
+    };
+    let Some(session) = load_open_session(&path) else {
+        forget_open(app);
+        return;
+    };
+    let end = session.last_activity;
+    logging::info(&format!(
+        "Recording the reading session left open for {}",
+        session.book_id
+    ));
+    close_session(app, session, end);
+    forget_open(app);
+}
+
+/// Open a session for `book_id`, closing one open for another book
+fn begin_session(
+    app: &AppHandle,
+    sessions: &SessionManager,
+    book_id: String,
+) -> Result<(), String> {
+    let now = Utc::now();
+    let previous = {
+        let mut open = sessions.open.lock().map_err(|e| e.to_string())?;
+        if open.as_ref().is_some_and(|s| s.book_id == book_id) {
+            return Ok(());
+        }
+        let progress = book_progress(app, &book_id).map(|(progress, _)| progress);
+        let session = ReadingSession::new(book_id, now, progress);
+        if let Err(e) = persist_open(&app.state::<AppState>(), Some(&session)) {
+            logging::warn(&e);
+        }
+        open.replace(session)
+    };
+    if let Some(previous) = previous {
+        close_session(app, previous, now);
+    }
+
+    if let Err(e) = app.state::<SleepInhibit>().acquire(INHIBIT_REASON) {
+        logging::debug(&e);
+    }
+    Ok(())
+}
+
+/// Start reading a book. A session still open for another book is closed.
+#[tauri::command]
+pub fn start_reading_session(
+    app: AppHandle,
+    sessions: State<'_, SessionManager>,
+    book_id: String,
+) -> Result<(), String> {
+    begin_session(&app, &sessions, book_id)
+}
+
+fn notify_goal_reached(app: &AppHandle, tick: &SessionTick) {
+    let state = app.state::<AppState>();
+    if !state
+        .preferences()
+        .is_ok_and(|prefs| prefs.session_goal_notification)
+    {
+        return;
+    }
+    let title = state
+        .with_library(|library| {
+            library
+                .books
+                .iter()
+                .find(|b| b.id == tick.book_id)
+                .map(|b| b.title.clone())
+        })
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let minutes = tick.target_seconds / 60;
+    let body = if title.is_empty() {
+        format!("You read for {} minutes", minutes)
+    } else {
+        format!("You read {} for {} minutes", title, minutes)
+    };
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("Reading goal reached")
+        .body(body)
+        .show()
+    {
+        logging::warn(&format!("Failed to show goal notification: {}", e));
+    }
+}
+
+/// Emit "session-tick" while the clock of `generation` runs, and
+/// "session-goal-reached" once when it passes the goal
+fn spawn_ticker(app: &AppHandle, generation: u64) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        let wait = {
+            let sessions = app.state::<SessionManager>();
+            let Ok(timer) = sessions.timer.lock() else {
+                return;
+            };
+            match timer.as_ref() {
+                Some(t) if t.generation == generation && t.paused.is_none() => {
+                    if t.goal_reached {
+                        TICK_INTERVAL
+                    } else {
+                        // Wake up at the goal rather than up to a tick late
+                        TICK_INTERVAL.min(t.target.saturating_sub(t.elapsed()))
+                    }
+                }
+                _ => return,
+            }
+        };
+        std::thread::sleep(wait);
+
+        let (tick, reached) = {
+            let sessions = app.state::<SessionManager>();
+            let Ok(mut timer) = sessions.timer.lock() else {
+                return;
+            };
+            let Some(t) = timer.as_mut() else {
+                return;
+            };
+            if t.generation != generation || t.paused.is_some() {
+                return;
+            }
+            let reached = !t.goal_reached && t.elapsed() >= t.target;
+            t.goal_reached |= reached;
+            (t.tick(), reached)
+        };
+        if reached {
+            let _ = app.emit("session-goal-reached", tick.clone());
+            notify_goal_reached(&app, &tick);
+        }
+        let _ = app.emit("session-tick", tick);
+    });
+}
+
+/// Start reading a book with a goal of `target_minutes`. Progress is
+/// reported through "session-tick" every 30 seconds; reading on past the
+/// goal counts as overtime until the session ends.
+#[tauri::command]
+pub fn start_timed_session(
+    app: AppHandle,
+    sessions: State<'_, SessionManager>,
+    book_id: String,
+    target_minutes: u32,
+) -> Result<SessionTick, String> {
+    if !(1..=MAX_TARGET_MINUTES).contains(&target_minutes) {
+        return Err(format!(
+            "Session goal must be between 1 and {} minutes, got {}",
+            MAX_TARGET_MINUTES, target_minutes
+        ));
+    }
+    if let Some(timer) = sessions.timer.lock().map_err(|e| e.to_string())?.as_ref() {
+        return Err(format!(
+            "A timed session is already running for '{}'",
+            timer.book_id
+        ));
+    }
+
+    begin_session(&app, &sessions, book_id.clone())?;
+
+    let generation = sessions.next_generation();
+    let tick = {
+        let mut timer = sessions.timer.lock().map_err(|e| e.to_string())?;
+        if timer.is_some() {
+            return Err("A timed session is already running".to_string());
+        }
+        let t = timer.insert(SessionTimer {
+            book_id,
+            target: std::time::Duration::from_secs(target_minutes as u64 * 60),
+            counted: std::time::Duration::ZERO,
+            running_since: Some(Instant::now()),
+            paused: None,
+            goal_reached: false,
+            generation,
+        });
+        t.tick()
+    };
+    spawn_ticker(&app, generation);
+    let _ = app.emit("session-tick", tick.clone());
+    Ok(tick)
+}
+
+/// Stop the clock of the timed session
+#[tauri::command]
+pub fn pause_session(
+    app: AppHandle,
+    sessions: State<'_, SessionManager>,
+) -> Result<Option<SessionTick>, String> {
+    let tick = {
+        let mut timer = sessions.timer.lock().map_err(|e| e.to_string())?;
+        let Some(t) = timer.as_mut() else {
+            return Ok(None);
+        };
+        // An explicit pause outlasts a focus change
+        if !t.pause(PauseReason::User) {
+            t.paused = Some(PauseReason::User);
+        }
+        t.tick()
+    };
+    let _ = app.emit("session-tick", tick.clone());
+    Ok(Some(tick))
+}
+
+/// Restart the clock of a paused timed session
+#[tauri::command]
+pub fn resume_session(
+    app: AppHandle,
+    sessions: State<'_, SessionManager>,
+) -> Result<Option<SessionTick>, String> {
+    let (tick, generation) = {
+        let mut timer = sessions.timer.lock().map_err(|e| e.to_string())?;
+        let Some(t) = timer.as_mut() else {
+            return Ok(None);
+        };
+        let generation = sessions.next_generation();
+        let resumed = t.resume(generation);
+        (t.tick(), resumed.then_some(generation))
+    };
+    if let Some(generation) = generation {
+        spawn_ticker(&app, generation);
+    }
+    let _ = app.emit("session-tick", tick.clone());
+    Ok(Some(tick))
+}
+
+/// The clock of the timed session, if one is running
+#[tauri::command]
+pub fn get_timed_session(
+    sessions: State<'_, SessionManager>,
+) -> Result<Option<SessionTick>, String> {
+    Ok(sessions
+        .timer
+        .lock()
+        .map_err(|e| e.to_string())?
+        .as_ref()
+        .map(SessionTimer::tick))
+}
+
+/// Pause the goal clock while no window has focus
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    let WindowEvent::Focused(focused) = event else {
+        return;
+    };
+    let app = window.app_handle();
+    // Focus moving to another Epilogue window isn't a break from reading
+    if !*focused
+        && app
+            .webview_windows()
+            .values()
+            .any(|w| w.label() != window.label() && w.is_focused().unwrap_or(false))
+    {
+        return;
+    }
+    let sessions = app.state::<SessionManager>();
+    let (tick, generation) = {
+        let Ok(mut timer) = sessions.timer.lock() else {
+            return;
+        };
+        let Some(t) = timer.as_mut() else {
+            return;
+        };
+        if *focused {
+            if t.paused != Some(PauseReason::Blur) || !t.resume(sessions.next_generation()) {
+                return;
+            }
+            (t.tick(), Some(t.generation))
+        } else {
+            if !t.pause(PauseReason::Blur) {
+                return;
+            }
+            (t.tick(), None)
+        }
+    };
+    if let Some(generation) = generation {
+        spawn_ticker(app, generation);
+    }
+    let _ = app.emit("session-tick", tick);
+}
+
+/// Stop reading a book
+#[tauri::command]
+pub fn end_reading_session(
+    app: AppHandle,
+    sessions: State<'_, SessionManager>,
+    book_id: String,
+) -> Result<(), String> {
+    let session = {
+        let mut open = sessions.open.lock().map_err(|e| e.to_string())?;
+        match open.as_ref() {
+            Some(session) if session.book_id == book_id => open.take(),
+            _ => None,
+        }
+    };
+    if let Some(session) = session {
+        close_session(&app, session, Utc::now());
+        forget_open(&app);
+    }
+    Ok(())
+}
+
+/// The reader saw the user scroll, turn a page or press a key. Called
+/// throttled by the frontend.
+#[tauri::command]
+pub fn record_activity(app: AppHandle, sessions: State<'_, SessionManager>) -> Result<(), String> {
+    touch_session(&app, &sessions, None)
+}
+
+/// Progress was saved for `book_id`, which counts as activity in its session
+pub fn note_progress(app: &AppHandle, book_id: &str) {
+    if let Err(e) = touch_session(app, &app.state::<SessionManager>(), Some(book_id)) {
+        logging::warn(&e);
+    }
+}
+
+/// Account activity in the open session, when it is for `book_id` if one
+/// is given. The session is written to disk once a minute at most, so a
+/// crash ends it close to when reading stopped.
+fn touch_session(
+    app: &AppHandle,
+    sessions: &SessionManager,
+    book_id: Option<&str>,
+) -> Result<(), String> {
+    let threshold = idle_threshold(app);
+    let mut open = sessions.open.lock().map_err(|e| e.to_string())?;
+    let Some(session) = open
+        .as_mut()
+        .filter(|s| book_id.is_none_or(|id| s.book_id == id))
+    else {
+        return Ok(());
+    };
+    session.record_activity(Utc::now(), threshold);
+
+    let mut persisted_at = sessions.persisted_at.lock().map_err(|e| e.to_string())?;
+    if persisted_at.is_some_and(|at| at.elapsed() < PERSIST_INTERVAL) {
+        return Ok(());
+    }
+    *persisted_at = Some(Instant::now());
+    // Written under the lock, so a session closed meanwhile isn't brought back
+    persist_open(&app.state::<AppState>(), Some(session))
+}
+
+/// Recorded sessions, newest first, optionally for one book. The open
+/// session is included with no end.
+#[tauri::command]
+pub fn get_reading_sessions(
+    state: State<'_, AppState>,
+    sessions: State<'_, SessionManager>,
+    book_id: Option<String>,
+) -> Result<Vec<ReadingSession>, String> {
+    let mut all = all_sessions(&state, &sessions)?;
+    all.retain(|s| book_id.as_ref().is_none_or(|id| &s.book_id == id));
+    all.reverse();
+    Ok(all)
+}
+
+/// Sessions recorded so far plus the open one
+fn all_sessions(
+    state: &AppState,
+    sessions: &SessionManager,
+) -> Result<Vec<ReadingSession>, String> {
+    let mut all = load_sessions(&state.paths()?.sessions);
+    if let Some(open) = sessions.open.lock().map_err(|e| e.to_string())?.clone() {
+        all.push(open);
+    }
+    Ok(all)
+}
+
+/// Time spent reading a book, over all its sessions
+#[tauri::command]
+pub fn get_reading_time(
+    state: State<'_, AppState>,
+    sessions: State<'_, SessionManager>,
+    book_id: String,
+) -> Result<ReadingTime, String> {
+    let book_id = state.resolve_book_id(&book_id);
+    let mut time = ReadingTime::default();
+    for session in all_sessions(&state, &sessions)?
+        .iter()
+        .filter(|s| s.book_id == book_id)
+    {
+        time.add(session);
+    }
+    Ok(time)
+}
+
+/// Time spent reading any book in `period`: "today", "week" (from Monday),
+/// "month", "year" or "all", in local time
+#[tauri::command]
+pub fn get_total_reading_time(
+    state: State<'_, AppState>,
+    sessions: State<'_, SessionManager>,
+    period: String,
+) -> Result<ReadingTime, String> {
+    let today = Local::now().date_naive();
+    let first_day = match period.as_str() {
+        "today" => Some(today),
+        "week" => Some(today - Duration::days(today.weekday().num_days_from_monday() as i64)),
+        "month" => today.with_day(1),
+        "year" => today.with_ordinal(1),
+        "all" => None,
+        _ => return Err(format!("Unknown period: {}", period)),
+    };
+    let since = first_day
+        .map(|day| day.and_time(NaiveTime::MIN))
+        .and_then(|start| Local.from_local_datetime(&start).earliest())
+        .map(|start| start.with_timezone(&Utc));
+
+    let mut time = ReadingTime::default();
+    for session in all_sessions(&state, &sessions)?
+        .iter()
+        .filter(|s| since.is_none_or(|since| s.start >= since))
+    {
+        time.add(session);
+    }
+    Ok(time)
+}
+
+/// Close sessions nobody has touched for an hour, ending them at the last
+/// activity so the walk-away time is not counted
+pub fn start_idle_watch(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(IDLE_CHECK_INTERVAL);
+
+        let cutoff = Utc::now() - Duration::minutes(AUTO_CLOSE_AFTER_MINUTES);
+        let stale = app
+            .state::<SessionManager>()
+            .open
+            .lock()
+            .ok()
+            .and_then(|mut open| match open.as_ref() {
+                Some(session) if session.last_activity < cutoff => open.take(),
+                _ => None,
+            });
+        if let Some(session) = stale {
+            logging::info(&format!(
+                "Closed idle reading session for {}",
+                session.book_id
+            ));
+            // Sleep inhibits must be released on the thread that took them
+            let handle = app.clone();
+            let _ = app.run_on_main_thread(move || {
+                let end = session.last_activity;
+                close_session(&handle, session, end);
+                forget_open(&handle);
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::temp_dir;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 1, 20, 0, 0).unwrap() + Duration::minutes(minutes)
+    }
+
+    fn session(book_id: &str, start: i64, last_activity: i64) -> ReadingSession {
+        let mut session = ReadingSession::new(book_id.to_string(), at(start), Some(0.1));
+        session.record_activity(at(last_activity), Duration::minutes(5));
+        session
+    }
+
+    #[test]
+    fn a_recovered_session_ends_at_its_last_activity() {
+        let dir = temp_dir("sessions_recover");
+        let state = AppState::in_dir(&dir);
+        persist_open(&state, Some(&session("a", 0, 4))).unwrap();
+
+        let mut recovered = load_open_session(&state.paths().unwrap().open_session).unwrap();
+        let end = recovered.last_activity;
+        assert!(finish_session(
+            &mut recovered,
+            end,
+            Duration::minutes(5),
+            Some((0.3, Some(1_000)))
+        ));
+        assert_eq!(recovered.end, Some(at(4)));
+        assert_eq!(recovered.active_seconds, 4 * 60);
+        assert_eq!(recovered.words, Some(200));
+    }
+
+    #[test]
+    fn sessions_longer_than_the_cap_are_not_recorded() {
+        let hours = MAX_SESSION_HOURS * 60;
+        let mut left_open = session("a", 0, hours + 1);
+        let end = left_open.last_activity;
+        assert!(!finish_session(
+            &mut left_open,
+            end,
+            Duration::minutes(5),
+            None
+        ));
+
+        let mut long_read = session("a", 0, hours);
+        let end = long_read.last_activity;
+        assert!(finish_session(
+            &mut long_read,
+            end,
+            Duration::minutes(5),
+            None
+        ));
+    }
+
+    #[test]
+    fn merging_skips_sessions_already_in_the_log() {
+        let dir = temp_dir("sessions_merge");
+        let state = AppState::in_dir(&dir);
+        assert_eq!(
+            merge_sessions(&state, vec![session("a", 30, 40), session("b", 0, 10)]).unwrap(),
+            2
+        );
+        assert_eq!(
+            merge_sessions(&state, vec![session("a", 30, 45), session("a", 60, 70)]).unwrap(),
+            1
+        );
+
+        let sessions = load_sessions(&state.paths().unwrap().sessions);
+        let keys: Vec<(&str, DateTime<Utc>)> = sessions
+            .iter()
+            .map(|s| (s.book_id.as_str(), s.start))
+            .collect();
+        assert_eq!(keys, vec![("b", at(0)), ("a", at(30)), ("a", at(60))]);
+    }
+}
@@ -0,0 +1,221 @@
+/**
+ * Keeping the screen awake while reading or while ambient media plays
+ */
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use tauri::{Manager, State, Window, WindowEvent};
+
+/// Reasons the screen is being kept awake, and the single OS assertion held
+/// for all of them. Repeated requests share that one assertion.
+#[derive(Default)]
+pub struct SleepInhibit {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    reasons: BTreeSet<String>,
+    assertion: Option<platform::Assertion>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SleepInhibitStatus {
+    pub active: bool,
+    pub reasons: Vec<String>,
+}
+
+impl SleepInhibit {
+    pub fn acquire(&self, reason: &str) -> Result<(), String> {
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        if inner.assertion.is_none() {
+            inner.assertion = Some(platform::acquire(reason)?);
+        }
+        inner.reasons.insert(reason.to_string());
+        Ok(())
+    }
+
+    /// Drop one reason, or all of them; the OS assertion goes with the last
+    pub fn release(&self, reason: Option<&str>) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        match reason {
+            Some(reason) => {
+                inner.reasons.remove(reason);
+            }
+            None => inner.reasons.clear(),
+        }
+        if inner.reasons.is_empty() {
+            inner.assertion = None;
+        }
+    }
+
+    pub fn status(&self) -> SleepInhibitStatus {
+        match self.inner.lock() {
+            Ok(inner) => SleepInhibitStatus {
+                active: inner.assertion.is_some(),
+                reasons: inner.reasons.iter().cloned().collect(),
+            },
+            Err(_) => SleepInhibitStatus {
+                active: false,
+                reasons: Vec::new(),
+            },
+        }
+    }
+}
+
+/// Keep the screen awake, e.g. for "reading" or "media"
+#[tauri::command]
+pub fn inhibit_sleep(state: State<'_, SleepInhibit>, reason: String) -> Result<(), String> {
+    state.acquire(&reason)
+}
+
+/// Stop keeping the screen awake for `reason`, or for every reason
+#[tauri::command]
+pub fn release_sleep_inhibit(
+    state: State<'_, SleepInhibit>,
+    reason: Option<String>,
+) -> Result<(), String> {
+    state.release(reason.as_deref());
+    Ok(())
+}
+
+/// Window event hook: nobody is watching an unfocused window
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::Focused(false) = event {
+        window.state::<SleepInhibit>().release(None);
+    }
+}
+
+// Tauri runs synchronous commands and window events on the main thread, so
+// assertions are created and released on the same thread (which Windows'
+// per-thread execution state requires).
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows_sys::Win32::System::Power::{
+        SetThreadExecutionState, ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED,
+    };
+
+    pub struct Assertion;
+
+    pub fn acquire(_reason: &str) -> Result<Assertion, String> {
+        let previous = unsafe {
+            SetThreadExecutionState(ES_CONTINUOUS | ES_DISPLAY_REQUIRED | ES_SYSTEM_REQUIRED)
+        };
+        if previous == 0 {
+            return Err("Failed to keep the display awake".to_string());
+        }
+        Ok(Assertion)
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            unsafe {
+                SetThreadExecutionState(ES_CONTINUOUS);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use core_foundation::base::TCFType;
+    use core_foundation::string::{CFString, CFStringRef};
+
+    const ASSERTION_LEVEL_ON: u32 = 255;
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: CFStringRef,
+            level: u32,
+            name: CFStringRef,
+            id: *mut u32,
+        ) -> i32;
+        fn IOPMAssertionRelease(id: u32) -> i32;
+    }
+
+    pub struct Assertion(u32);
+
+    pub fn acquire(reason: &str) -> Result<Assertion, String> {
+        let kind = CFString::new("PreventUserIdleDisplaySleep");
+        let name = CFString::new(&format!("Epilogue: {}", reason));
+        let mut id = 0;
+        let result = unsafe {
+            IOPMAssertionCreateWithName(
+                kind.as_concrete_TypeRef(),
+                ASSERTION_LEVEL_ON,
+                name.as_concrete_TypeRef(),
+                &mut id,
+            )
+        };
+        if result != 0 {
+            return Err(format!("Failed to create power assertion: {}", result));
+        }
+        Ok(Assertion(id))
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            unsafe {
+                IOPMAssertionRelease(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use zbus::blocking::Connection;
+
+    const SERVICE: &str = "org.freedesktop.ScreenSaver";
+    const PATH: &str = "/org/freedesktop/ScreenSaver";
+
+    /// The inhibit lasts as long as the connection, so it is kept open
+    pub struct Assertion {
+        connection: Connection,
+        cookie: u32,
+    }
+
+    pub fn acquire(reason: &str) -> Result<Assertion, String> {
+        let connection = Connection::session()
+            .map_err(|e| format!("Failed to connect to the session bus: {}", e))?;
+        let cookie: u32 = connection
+            .call_method(
+                Some(SERVICE),
+                PATH,
+                Some(SERVICE),
+                "Inhibit",
+                &("Epilogue", reason),
+            )
+            .and_then(|reply| reply.body().deserialize())
+            .map_err(|e| format!("Failed to inhibit the screensaver: {}", e))?;
+
+        Ok(Assertion { connection, cookie })
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            if let Err(e) = self.connection.call_method(
+                Some(SERVICE),
+                PATH,
+                Some(SERVICE),
+                "UnInhibit",
+                &(self.cookie,),
+            ) {
+                crate::logging::warn(&format!("Failed to release screensaver inhibit: {}", e));
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    pub struct Assertion;
+
+    pub fn acquire(_reason: &str) -> Result<Assertion, String> {
+        Err("Keeping the screen awake is not supported on this platform".to_string())
+    }
+}
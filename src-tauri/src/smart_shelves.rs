@@ -0,0 +1,169 @@
+/**
+ * Smart shelves: saved filter rules whose books are worked out each time
+ * the shelf is opened, so they stay current as the library changes
+ */
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::filters::{FilterContext, Rule};
+use crate::library::Book;
+use crate::state::AppState;
+
+const MAX_SHELF_NAME_CHARS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmartShelf {
+    pub name: String,
+    /// The rule as the user wrote it, checked when it was saved
+    pub rules: Value,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+fn check_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Shelf name cannot be empty".to_string());
+    }
+    if name.chars().count() > MAX_SHELF_NAME_CHARS {
+        return Err(format!(
+            "Shelf name is longer than {} characters",
+            MAX_SHELF_NAME_CHARS
+        ));
+    }
+    Ok(name.to_string())
+}
+
+fn find<'a>(shelves: &'a mut [SmartShelf], name: &str) -> Result<&'a mut SmartShelf, String> {
+    let name = name.trim();
+    shelves
+        .iter_mut()
+        .find(|s| s.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("No smart shelf named '{}'", name))
+}
+
+fn emit_shelves(app: &AppHandle, state: &AppState) {
+    if let Ok(shelves) = state.with_library(|library| library.smart_shelves.clone()) {
+        let _ = app.emit("smart-shelves-changed", shelves);
+    }
+}
+
+#[tauri::command]
+pub fn list_smart_shelves(state: State<'_, AppState>) -> Result<Vec<SmartShelf>, String> {
+    state.with_library(|library| library.smart_shelves.clone())
+}
+
+/// Save a shelf of the books matching `rules_json`, e.g.
+/// `{"all": [{"status": "toRead"}, {"tags": ["sci-fi"]}, {"words": {"max": 90000}}]}`
+#[tauri::command]
+pub fn create_smart_shelf(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    rules_json: String,
+) -> Result<SmartShelf, String> {
+    let name = check_name(&name)?;
+    Rule::parse(&rules_json)?;
+    let rules: Value = serde_json::from_str(&rules_json).map_err(|e| e.to_string())?;
+
+    let shelf = state.update_library(|library| {
+        if find(&mut library.smart_shelves, &name).is_ok() {
+            return Err(format!("A smart shelf named '{}' already exists", name));
+        }
+        let shelf = SmartShelf {
+            name,
+            rules,
+            created_at: Utc::now(),
+        };
+        library.smart_shelves.push(shelf.clone());
+        Ok(shelf)
+    })?;
+    emit_shelves(&app, &state);
+    Ok(shelf)
+}
+
+/// Rename a shelf, change its rules, or both
+#[tauri::command]
+pub fn update_smart_shelf(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    new_name: Option<String>,
+    rules_json: Option<String>,
+) -> Result<SmartShelf, String> {
+    let new_name = new_name.as_deref().map(check_name).transpose()?;
+    let rules = match rules_json {
+        Some(json) => {
+            Rule::parse(&json)?;
+            Some(serde_json::from_str::<Value>(&json).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+
+    let shelf = state.update_library(|library| {
+        if let Some(new_name) = &new_name {
+            let taken = library.smart_shelves.iter().any(|s| {
+                s.name.eq_ignore_ascii_case(new_name) && !s.name.eq_ignore_ascii_case(name.trim())
+            });
+            if taken {
+                return Err(format!("A smart shelf named '{}' already exists", new_name));
+            }
+        }
+        let shelf = find(&mut library.smart_shelves, &name)?;
+        if let Some(new_name) = new_name {
+            shelf.name = new_name;
+        }
+        if let Some(rules) = rules {
+            shelf.rules = rules;
+        }
+        Ok(shelf.clone())
+    })?;
+    emit_shelves(&app, &state);
+    Ok(shelf)
+}
+
+#[tauri::command]
+pub fn delete_smart_shelf(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+) -> Result<(), String> {
+    state.update_library(|library| {
+        let before = library.smart_shelves.len();
+        library
+            .smart_shelves
+            .retain(|s| !s.name.eq_ignore_ascii_case(name.trim()));
+        if library.smart_shelves.len() == before {
+            return Err(format!("No smart shelf named '{}'", name.trim()));
+        }
+        Ok(())
+    })?;
+    emit_shelves(&app, &state);
+    Ok(())
+}
+
+/// The books on a smart shelf right now, in library order. Tags and
+/// subjects a rule names that no book has any more just match nothing.
+#[tauri::command]
+pub fn evaluate_smart_shelf(state: State<'_, AppState>, name: String) -> Result<Vec<Book>, String> {
+    let (rules, books) = state.with_library(|library| {
+        let rules = library
+            .smart_shelves
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name.trim()))
+            .map(|s| s.rules.clone());
+        (rules, library.books.clone())
+    })?;
+    let rules = rules.ok_or_else(|| format!("No smart shelf named '{}'", name.trim()))?;
+    // Rules are checked on save, but the library file may have been edited
+    let rule = Rule::from_value(&rules)
+        .map_err(|e| format!("Shelf '{}' is broken: {}", name.trim(), e))?;
+
+    let context = FilterContext::for_rule(&state, &rule, &books);
+    Ok(books
+        .into_iter()
+        .filter(|book| rule.matches(book, &context))
+        .collect())
+}
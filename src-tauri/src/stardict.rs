@@ -0,0 +1,334 @@
+/**
+ * StarDict dictionaries (.ifo/.idx/.dict) installed under dictionaries/
+ */
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::logging;
+
+/// Loaded dictionaries, by .ifo path
+type DictionaryCache = HashMap<PathBuf, Arc<Dictionary>>;
+
+static LOADED: Mutex<Option<DictionaryCache>> = Mutex::new(None);
+
+/// What the .ifo file says about a dictionary
+#[derive(Debug, Clone)]
+pub struct DictionaryInfo {
+    pub name: String,
+    /// Language pair from the folder name ("de-en") or a `lang=` line
+    pub source_lang: Option<String>,
+    pub target_lang: Option<String>,
+    pub ifo: PathBuf,
+    word_count: usize,
+    offset_bits: u32,
+    same_type_sequence: Option<String>,
+}
+
+/// The article text, uncompressed in memory for .dict.dz, read from disk
+/// for plain .dict
+enum DictData {
+    Memory(Vec<u8>),
+    File(Mutex<File>),
+}
+
+pub struct Dictionary {
+    pub info: DictionaryInfo,
+    /// Lowercased headword -> (offset, size) of its articles
+    index: HashMap<String, Vec<(u64, u32)>>,
+    data: DictData,
+}
+
+/// The pair in a "de-en" or "de_en" name, normalized to primary subtags
+fn language_pair(name: &str) -> Option<(String, String)> {
+    let (source, target) = name.split_once(['-', '_'])?;
+    let valid =
+        |code: &str| (2..=3).contains(&code.len()) && code.chars().all(|c| c.is_ascii_alphabetic());
+    (valid(source) && valid(target)).then(|| (source.to_lowercase(), target.to_lowercase()))
+}
+
+/// Primary subtag of a language code: "pt-BR" -> "pt"
+pub fn primary_language(code: &str) -> String {
+    code.split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+fn read_info(ifo: &Path) -> Result<DictionaryInfo, String> {
+    let content =
+        fs::read_to_string(ifo).map_err(|e| format!("Failed to read {}: {}", ifo.display(), e))?;
+    let mut lines = content.lines();
+    let magic = lines
+        .next()
+        .map(|l| l.trim_start_matches('\u{feff}').trim());
+    if magic != Some("StarDict's dict ifo file") {
+        return Err(format!("Not a StarDict .ifo file: {}", ifo.display()));
+    }
+    let fields: HashMap<&str, &str> = lines
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect();
+
+    let folder_pair = ifo
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .and_then(|name| language_pair(&name.to_string_lossy()));
+    let pair = folder_pair.or_else(|| fields.get("lang").and_then(|lang| language_pair(lang)));
+
+    Ok(DictionaryInfo {
+        name: fields
+            .get("bookname")
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| {
+                ifo.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            }),
+        source_lang: pair.as_ref().map(|(source, _)| source.clone()),
+        target_lang: pair.map(|(_, target)| target),
+        ifo: ifo.to_path_buf(),
+        word_count: fields
+            .get("wordcount")
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0),
+        offset_bits: match fields.get("idxoffsetbits") {
+            Some(&"64") => 64,
+            _ => 32,
+        },
+        same_type_sequence: fields
+            .get("sametypesequence")
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string()),
+    })
+}
+
+/// Every dictionary under `dir`, directly or one folder down
+pub fn installed(dir: &Path) -> Vec<DictionaryInfo> {
+    let mut ifos = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if let Ok(children) = fs::read_dir(&path) {
+                ifos.extend(children.flatten().map(|c| c.path()));
+            }
+        } else {
+            ifos.push(path);
+        }
+    }
+
+    let mut dictionaries: Vec<DictionaryInfo> = ifos
+        .into_iter()
+        .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("ifo")))
+        .filter_map(|ifo| read_info(&ifo).map_err(|e| logging::warn(&e)).ok())
+        .collect();
+    dictionaries.sort_by(|a, b| a.ifo.cmp(&b.ifo));
+    dictionaries
+}
+
+/// The first of `candidates` next to the .ifo that exists
+fn sibling(ifo: &Path, candidates: &[&str]) -> Option<PathBuf> {
+    candidates
+        .iter()
+        .map(|extension| ifo.with_extension(extension))
+        .find(|path| path.exists())
+}
+
+fn read_maybe_gzipped(path: &Path) -> Result<Vec<u8>, String> {
+    let raw = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if path.extension().is_some_and(|e| e == "gz" || e == "dz") {
+        let mut data = Vec::new();
+        GzDecoder::new(raw.as_slice())
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Failed to decompress {}: {}", path.display(), e))?;
+        return Ok(data);
+    }
+    Ok(raw)
+}
+
+fn parse_index(
+    data: &[u8],
+    offset_bits: u32,
+    word_count: usize,
+) -> HashMap<String, Vec<(u64, u32)>> {
+    let offset_len = if offset_bits == 64 { 8 } else { 4 };
+    let mut index: HashMap<String, Vec<(u64, u32)>> = HashMap::with_capacity(word_count);
+    let mut pos = 0;
+    while pos < data.len() {
+        let Some(end) = data[pos..].iter().position(|b| *b == 0) else {
+            break;
+        };
+        let word = String::from_utf8_lossy(&data[pos..pos + end]).to_lowercase();
+        pos += end + 1;
+        let Some(fields) = data.get(pos..pos + offset_len + 4) else {
+            break;
+        };
+        let offset = if offset_len == 8 {
+            u64::from_be_bytes(fields[..8].try_into().unwrap_or_default())
+        } else {
+            u32::from_be_bytes(fields[..4].try_into().unwrap_or_default()) as u64
+        };
+        let size = u32::from_be_bytes(fields[offset_len..].try_into().unwrap_or_default());
+        pos += offset_len + 4;
+        index.entry(word).or_default().push((offset, size));
+    }
+    index
+}
+
+/// Open a dictionary, reusing it if it was loaded before
+pub fn load(info: &DictionaryInfo) -> Result<Arc<Dictionary>, String> {
+    if let Some(dictionary) = LOADED
+        .lock()
+        .ok()
+        .and_then(|cache| cache.as_ref()?.get(&info.ifo).cloned())
+    {
+        return Ok(dictionary);
+    }
+
+    let idx = sibling(&info.ifo, &["idx", "idx.gz"])
+        .ok_or_else(|| format!("{} has no .idx file", info.name))?;
+    let dict = sibling(&info.ifo, &["dict", "dict.dz"])
+        .ok_or_else(|| format!("{} has no .dict file", info.name))?;
+
+    let index = parse_index(
+        &read_maybe_gzipped(&idx)?,
+        info.offset_bits,
+        info.word_count,
+    );
+    let data = if dict.extension().is_some_and(|e| e == "dz") {
+        DictData::Memory(read_maybe_gzipped(&dict)?)
+    } else {
+        DictData::File(Mutex::new(
+            File::open(&dict).map_err(|e| format!("Failed to open {}: {}", dict.display(), e))?,
+        ))
+    };
+
+    let dictionary = Arc::new(Dictionary {
+        info: info.clone(),
+        index,
+        data,
+    });
+    if let Ok(mut cache) = LOADED.lock() {
+        cache
+            .get_or_insert_with(HashMap::new)
+            .insert(info.ifo.clone(), dictionary.clone());
+    }
+    Ok(dictionary)
+}
+
+/// Drop markup from HTML, XDXF and Pango articles
+fn strip_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Text of one article. Fields are typed by a letter: lowercase ones are
+/// text, uppercase ones binary (sounds, pictures) and skipped.
+fn article_text(data: &[u8], same_type_sequence: Option<&str>) -> String {
+    let mut parts = Vec::new();
+    let mut pos = 0;
+    let types: Vec<u8> = same_type_sequence
+        .map(|s| s.bytes().collect())
+        .unwrap_or_default();
+    let mut field = 0;
+
+    while pos < data.len() {
+        let kind = if types.is_empty() {
+            let kind = data[pos];
+            pos += 1;
+            kind
+        } else if let Some(kind) = types.get(field) {
+            *kind
+        } else {
+            break;
+        };
+        // The last field of a type sequence has no terminator or size
+        let last = !types.is_empty() && field + 1 == types.len();
+        field += 1;
+
+        if kind.is_ascii_lowercase() {
+            let end = if last {
+                data.len()
+            } else {
+                data[pos..]
+                    .iter()
+                    .position(|b| *b == 0)
+                    .map_or(data.len(), |end| pos + end)
+            };
+            let text = String::from_utf8_lossy(&data[pos..end]);
+            parts.push(match kind {
+                b'h' | b'g' | b'x' => strip_markup(&text),
+                _ => text.to_string(),
+            });
+            pos = end + 1;
+        } else {
+            let size = if last {
+                data.len() - pos
+            } else {
+                let Some(size) = data.get(pos..pos + 4) else {
+                    break;
+                };
+                pos += 4;
+                u32::from_be_bytes(size.try_into().unwrap_or_default()) as usize
+            };
+            pos += size;
+        }
+    }
+    parts
+        .iter()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Dictionary {
+    fn read_article(&self, offset: u64, size: u32) -> Option<Vec<u8>> {
+        match &self.data {
+            DictData::Memory(data) => data
+                .get(offset as usize..offset as usize + size as usize)
+                .map(|a| a.to_vec()),
+            DictData::File(file) => {
+                let mut file = file.lock().ok()?;
+                file.seek(SeekFrom::Start(offset)).ok()?;
+                let mut article = vec![0u8; size as usize];
+                file.read_exact(&mut article).ok()?;
+                Some(article)
+            }
+        }
+    }
+
+    /// Articles for a headword, case-insensitively
+    pub fn lookup(&self, word: &str) -> Option<String> {
+        let entries = self.index.get(&word.trim().to_lowercase())?;
+        let articles: Vec<String> = entries
+            .iter()
+            .filter_map(|(offset, size)| self.read_article(*offset, *size))
+            .map(|data| article_text(&data, self.info.same_type_sequence.as_deref()))
+            .filter(|text| !text.is_empty())
+            .collect();
+        (!articles.is_empty()).then(|| articles.join("\n\n"))
+    }
+}
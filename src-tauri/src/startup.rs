@@ -0,0 +1,153 @@
+/**
+ * Everything the first screen needs, loaded before the window is shown
+ */
+use base64::Engine;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+use crate::library::{self, Book};
+use crate::logging;
+use crate::preferences::UserPreferences;
+use crate::preset::{self, Preset};
+use crate::state::AppState;
+
+/// Books on the shelf at launch
+const RECENT_LIMIT: usize = 10;
+/// Show the window anyway if the frontend hasn't said it's ready by then
+const SHOW_TIMEOUT: Duration = Duration::from_secs(3);
+/// Covers larger than this are loaded by path instead of inlined
+const MAX_INLINE_COVER_BYTES: u64 = 256 * 1024;
+
+static LAUNCHED_AT: OnceLock<Instant> = OnceLock::new();
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StartupBook {
+    #[serde(flatten)]
+    pub book: Book,
+    /// data: URL of the cover, so the shelf paints without extra requests
+    #[serde(rename = "coverData")]
+    pub cover_data: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StartupState {
+    pub preferences: UserPreferences,
+    #[serde(rename = "recentBooks")]
+    pub recent_books: Vec<StartupBook>,
+    #[serde(rename = "activePreset")]
+    pub active_preset: Option<Preset>,
+}
+
+/// Startup state prepared in setup(), handed out once
+#[derive(Default)]
+pub struct StartupCache(Mutex<Option<StartupState>>);
+
+/// Start the cold-start clock; call first thing in main()
+pub fn mark_launch() {
+    LAUNCHED_AT.get_or_init(Instant::now);
+}
+
+fn cover_data_url(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    if fs::metadata(path).ok()?.len() > MAX_INLINE_COVER_BYTES {
+        return None;
+    }
+
+    let mime = match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        _ => "image/jpeg",
+    };
+    let data = fs::read(path).ok()?;
+    Some(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(data)
+    ))
+}
+
+fn load_startup_state(state: &AppState) -> Result<StartupState, String> {
+    let preferences = state.preferences()?;
+    let recent_books = library::recent_books(state, RECENT_LIMIT, false)?
+        .into_iter()
+        .map(|book| StartupBook {
+            cover_data: book.cover_path.as_deref().and_then(cover_data_url),
+            book,
+        })
+        .collect();
+    // A missing or broken preset falls back to the frontend's defaults
+    let active_preset = preferences
+        .last_preset
+        .as_deref()
+        .and_then(|name| preset::read_preset(state, name).ok());
+
+    Ok(StartupState {
+        preferences,
+        recent_books,
+        active_preset,
+    })
+}
+
+/// Load the startup state into managed state and show the main window once
+/// the frontend is ready, or after a timeout at the latest
+pub fn preload(app: &AppHandle) {
+    let started = Instant::now();
+    match load_startup_state(&app.state::<AppState>()) {
+        Ok(startup) => {
+            if let Ok(mut cache) = app.state::<StartupCache>().0.lock() {
+                *cache = Some(startup);
+            }
+            logging::debug(&format!(
+                "Startup state loaded in {} ms",
+                started.elapsed().as_millis()
+            ));
+        }
+        Err(e) => logging::warn(&format!("Failed to preload startup state: {}", e)),
+    }
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(SHOW_TIMEOUT);
+        show_main_window(&app, "timeout");
+    });
+}
+
+/// Show the main window the first time we are asked to (ready or timeout)
+pub fn show_main_window(app: &AppHandle, trigger: &str) {
+    if SHOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+    }
+
+    if let Some(launched) = LAUNCHED_AT.get() {
+        logging::info(&format!(
+            "Main window shown {} ms after launch ({})",
+            launched.elapsed().as_millis(),
+            trigger
+        ));
+    }
+}
+
+/// Preferences, recent books and the active preset in one call
+#[tauri::command]
+pub fn get_startup_state(
+    state: State<'_, AppState>,
+    cache: State<'_, StartupCache>,
+) -> Result<StartupState, String> {
+    let cached = cache.0.lock().map_err(|e| e.to_string())?.take();
+    match cached {
+        Some(startup) => Ok(startup),
+        // Later calls (e.g. a reloaded webview) get fresh data
+        None => load_startup_state(&state),
+    }
+}
@@ -0,0 +1,650 @@
+/**
+ * Shared application state: resolved paths and in-memory caches
+ */
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+use crate::config;
+use crate::error::AppError;
+use crate::library::{Book, Library};
+use crate::library_db;
+use crate::preferences::{self, UserPreferences};
+use crate::progress_history;
+
+/// Number of book covers remembered by the cover cache
+const COVER_CACHE_CAPACITY: usize = 64;
+
+/// Files and directories under the data directory, resolved once
+#[derive(Debug, Clone)]
+pub struct AppPaths {
+    pub app_dir: PathBuf,
+    pub library: PathBuf,
+    /// Where the library was kept before the database; imported once
+    pub library_json: PathBuf,
+    pub preferences: PathBuf,
+    pub presets: PathBuf,
+    pub covers: PathBuf,
+    pub backgrounds: PathBuf,
+    pub music: PathBuf,
+    pub sessions: PathBuf,
+    pub open_session: PathBuf,
+    pub reading_speed: PathBuf,
+    pub annotations: PathBuf,
+    pub sync: PathBuf,
+    pub books: PathBuf,
+    pub dictionaries: PathBuf,
+    pub view_state: PathBuf,
+    pub vocabulary: PathBuf,
+}
+
+impl AppPaths {
+    pub fn new(app_dir: PathBuf) -> Self {
+        Self {
+            library: app_dir.join("library.db"),
+            library_json: app_dir.join("library.json"),
+            preferences: app_dir.join("preferences.json"),
+            presets: app_dir.join("presets"),
+            covers: app_dir.join("cache").join("covers"),
+            backgrounds: app_dir.join("media").join("backgrounds"),
+            music: app_dir.join("media").join("music"),
+            sessions: app_dir.join("stats").join("sessions.jsonl"),
+            open_session: app_dir.join("stats").join("open_session.json"),
+            reading_speed: app_dir.join("stats").join("reading_speed.json"),
+            annotations: app_dir.join("annotations"),
+            sync: app_dir.join("sync.json"),
+            books: app_dir.join("books"),
+            dictionaries: app_dir.join("dictionaries"),
+            view_state: app_dir.join("view_state"),
+            vocabulary: app_dir.join("vocabulary.json"),
+            app_dir,
+        }
+    }
+}
+
+/// Least-recently-used map of book id -> extracted cover path, so reopening
+/// a book doesn't unpack its EPUB again just to find the cover
+pub struct CoverCache {
+    entries: VecDeque<(String, String)>,
+    capacity: usize,
+}
+
+impl CoverCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn get(&mut self, book_id: &str) -> Option<String> {
+        let index = self.entries.iter().position(|(id, _)| id == book_id)?;
+        let entry = self.entries.remove(index)?;
+        let cover = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(cover)
+    }
+
+    pub fn insert(&mut self, book_id: String, cover: String) {
+        self.remove(&book_id);
+        self.entries.push_front((book_id, cover));
+        self.entries.truncate(self.capacity);
+    }
+
+    pub fn remove(&mut self, book_id: &str) {
+        self.entries.retain(|(id, _)| id != book_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// State shared by all commands, registered with `.manage()`
+pub struct AppState {
+    paths: Result<AppPaths, AppError>,
+    /// Loaded once, by init_library at startup; commands read and change
+    /// this copy under the lock, so concurrent updates can't undo each other
+    library: RwLock<Option<Library>>,
+    /// Opened with the library; always locked after `library`
+    library_db: Mutex<Option<Connection>>,
+    /// Books changed in memory and not yet written to the database
+    dirty_books: Mutex<HashSet<String>>,
+    /// Latest progress of each book read since the last save, written to
+    /// its progress history with the books
+    progress_points: Mutex<HashMap<String, (i64, f32)>>,
+    preferences: RwLock<Option<UserPreferences>>,
+    /// Preset names with the presets directory mtime they were listed at
+    preset_list: Mutex<Option<(SystemTime, Vec<String>)>>,
+    pub covers: Mutex<CoverCache>,
+    /// The local day last saved as a day with some reading
+    pub last_reading_day: Mutex<Option<NaiveDate>>,
+    /// Files the user picked in one of our dialogs this session (canonical)
+    granted_paths: Mutex<HashSet<PathBuf>>,
+}
+
+fn poisoned<T>(e: std::sync::PoisonError<T>) -> String {
+    format!("App state lock poisoned: {}", e)
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AppState {
+    pub fn new() -> Self {
+        Self {
+            paths: config::get_app_dir_path().map(AppPaths::new),
+            library: RwLock::new(None),
+            library_db: Mutex::new(None),
+            dirty_books: Mutex::new(HashSet::new()),
+            progress_points: Mutex::new(HashMap::new()),
+            preferences: RwLock::new(None),
+            preset_list: Mutex::new(None),
+            covers: Mutex::new(CoverCache::new(COVER_CACHE_CAPACITY)),
+            last_reading_day: Mutex::new(None),
+            granted_paths: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn paths(&self) -> Result<&AppPaths, AppError> {
+        self.paths.as_ref().map_err(|e| e.clone())
+    }
+
+    /// Run `f` on the library database, opening it on first use
+    pub fn with_db<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut db = self.library_db.lock().map_err(poisoned)?;
+        let conn = match db.as_mut() {
+            Some(conn) => conn,
+            None => {
+                let paths = self.paths()?;
+                db.insert(library_db::open(&paths.library, &paths.library_json)?)
+            }
+        };
+        f(conn)
+    }
+
+    fn load_into<'a>(&self, slot: &'a mut Option<Library>) -> Result<&'a mut Library, String> {
+        if slot.is_none() {
+            *slot = Some(self.with_db(|conn| library_db::load(conn))?);
+        }
+        slot.as_mut()
+            .ok_or_else(|| "Library not loaded".to_string())
+    }
+
+    /// Read the in-memory library
+    pub fn with_library<T>(&self, f: impl FnOnce(&Library) -> T) -> Result<T, String> {
+        if let Some(library) = self.library.read().map_err(poisoned)?.as_ref() {
+            return Ok(f(library));
+        }
+
+        let mut guard = self.library.write().map_err(poisoned)?;
+        Ok(f(self.load_into(&mut guard)?))
+    }
+
+    /// Change the library and save what changed. The in-memory copy is
+    /// only replaced once the save succeeded, so a failed write leaves both
+    /// untouched.
+    pub fn update_library<T>(
+        &self,
+        f: impl FnOnce(&mut Library) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut guard = self.library.write().map_err(poisoned)?;
+        let before = self.load_into(&mut guard)?;
+        let mut library = before.clone();
+
+        let result = f(&mut library)?;
+        let mut dirty = self.dirty_books.lock().map_err(poisoned)?;
+        self.with_db(|conn| library_db::save_changes(conn, before, &library, &dirty))?;
+        dirty.clear();
+        *guard = Some(library);
+
+        Ok(result)
+    }
+
+    /// Change the library with `f`, which gives books the new ids of
+    /// `renames` (old id to new), and save it with the old ids kept as
+    /// aliases
+    pub fn rename_books<T>(
+        &self,
+        renames: &HashMap<String, String>,
+        f: impl FnOnce(&mut Library) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut guard = self.library.write().map_err(poisoned)?;
+        let before = self.load_into(&mut guard)?;
+        let mut library = before.clone();
+
+        let result = f(&mut library)?;
+        let mut dirty = self.dirty_books.lock().map_err(poisoned)?;
+        let renamed_dirty: HashSet<String> = dirty
+            .iter()
+            .map(|id| renames.get(id).unwrap_or(id).clone())
+            .collect();
+        self.with_db(|conn| {
+            library_db::save_renamed(conn, before, &library, &renamed_dirty, renames)
+        })?;
+        dirty.clear();
+        *guard = Some(library);
+
+        if let Ok(mut points) = self.progress_points.lock() {
+            *points = std::mem::take(&mut *points)
+                .into_iter()
+                .map(|(id, point)| (renames.get(&id).cloned().unwrap_or(id), point))
+                .collect();
+        }
+        Ok(result)
+    }
+
+    /// The id of the book `book_id` names: itself, or the book it was an
+    /// id of before the book was renamed
+    pub fn resolve_book_id(&self, book_id: &str) -> String {
+        let known = self
+            .with_library(|library| library.books.iter().any(|b| b.id == book_id))
+            .unwrap_or(false);
+        if known {
+            return book_id.to_string();
+        }
+        self.with_db(|conn| library_db::resolve_alias(conn, book_id))
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| book_id.to_string())
+    }
+
+    /// Change a book in memory only. Its row is written by the next
+    /// `flush_library` (scheduled saves, window close, exit).
+    pub fn update_book_in_memory<T>(
+        &self,
+        book_id: &str,
+        f: impl FnOnce(&mut Book) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let book_id = self.resolve_book_id(book_id);
+        let mut guard = self.library.write().map_err(poisoned)?;
+        let book = self
+            .load_into(&mut guard)?
+            .books
+            .iter_mut()
+            .find(|b| b.id == book_id)
+            .ok_or_else(|| format!("Book with id '{}' not found", book_id))?;
+        let result = f(book)?;
+        self.dirty_books.lock().map_err(poisoned)?.insert(book_id);
+
+        Ok(result)
+    }
+
+    /// Remember a book's progress now for its history, written by the next
+    /// `flush_library` like the book itself
+    pub fn queue_progress_point(&self, book_id: &str, at: i64, progress: f32) {
+        if let Ok(mut points) = self.progress_points.lock() {
+            points.insert(book_id.to_string(), (at, progress));
+        }
+    }
+
+    /// Write the books changed in memory since the last save, and their
+    /// progress history
+    pub fn flush_library(&self) -> Result<(), String> {
+        let guard = self.library.read().map_err(poisoned)?;
+        let mut dirty = self.dirty_books.lock().map_err(poisoned)?;
+        let mut points = self.progress_points.lock().map_err(poisoned)?;
+        let Some(library) = guard
+            .as_ref()
+            .filter(|_| !dirty.is_empty() || !points.is_empty())
+        else {
+            return Ok(());
+        };
+        let books: Vec<&Book> = library
+            .books
+            .iter()
+            .filter(|b| dirty.contains(&b.id))
+            .collect();
+        self.with_db(|conn| {
+            library_db::save_books(conn, &books)?;
+            // Not for books removed since
+            for (book_id, (at, progress)) in points.iter() {
+                if library.books.iter().any(|b| &b.id == book_id) {
+                    library_db::add_progress_point(
+                        conn,
+                        book_id,
+                        *at,
+                        *progress,
+                        progress_history::POINT_INTERVAL_SECS,
+                    )?;
+                }
+            }
+            Ok(())
+        })?;
+        dirty.clear();
+        points.clear();
+        Ok(())
+    }
+
+    /// Current preferences, read from disk on first use
+    pub fn preferences(&self) -> Result<UserPreferences, String> {
+        if let Some(prefs) = self.preferences.read().map_err(poisoned)?.as_ref() {
+            return Ok(prefs.clone());
+        }
+
+        // Safe mode never reads what a previous safe-mode session left behind
+        let prefs = if config::is_safe_mode() {
+            UserPreferences::default()
+        } else {
+            preferences::read_preferences(&self.paths()?.preferences)?
+        };
+        *self.preferences.write().map_err(poisoned)? = Some(prefs.clone());
+        Ok(prefs)
+    }
+
+    pub fn set_cached_preferences(&self, prefs: UserPreferences) -> Result<(), String> {
+        *self.preferences.write().map_err(poisoned)? = Some(prefs);
+        Ok(())
+    }
+
+    /// Cached preset names if the directory hasn't changed since listing
+    pub fn cached_preset_list(&self, modified: SystemTime) -> Option<Vec<String>> {
+        match self.preset_list.lock().ok()?.as_ref() {
+            Some((listed_at, names)) if *listed_at == modified => Some(names.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn set_preset_list(&self, modified: SystemTime, names: Vec<String>) {
+        if let Ok(mut list) = self.preset_list.lock() {
+            *list = Some((modified, names));
+        }
+    }
+
+    pub fn invalidate_preset_list(&self) {
+        if let Ok(mut list) = self.preset_list.lock() {
+            *list = None;
+        }
+    }
+
+    /// Allow the webview to read a file the user picked in a dialog
+    pub fn grant_path(&self, path: &Path) {
+        if let (Ok(path), Ok(mut granted)) = (path.canonicalize(), self.granted_paths.lock()) {
+            granted.insert(path);
+        }
+    }
+
+    pub fn is_granted(&self, canonical: &Path) -> bool {
+        self.granted_paths
+            .lock()
+            .map(|granted| granted.contains(canonical))
+            .unwrap_or(false)
+    }
+
+    /// Drop every cache after the files were changed behind our back
+    /// (restore from backup, migrations)
+    pub fn invalidate(&self) {
+        if let Ok(mut library) = self.library.write() {
+            *library = None;
+            if let Ok(mut dirty) = self.dirty_books.lock() {
+                dirty.clear();
+            }
+            if let Ok(mut points) = self.progress_points.lock() {
+                points.clear();
+            }
+            // The database file may have been replaced
+            if let Ok(mut db) = self.library_db.lock() {
+                *db = None;
+            }
+        }
+        if let Ok(mut prefs) = self.preferences.write() {
+            *prefs = None;
+        }
+        self.invalidate_preset_list();
+        if let Ok(mut covers) = self.covers.lock() {
+            covers.clear();
+        }
+        if let Ok(mut day) = self.last_reading_day.lock() {
+            *day = None;
+        }
+    }
+}
+
+/// Helpers for tests that need a data directory and a library
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use std::fs;
+    use std::sync::Once;
+
+    static REDIRECT_HOME: Once = Once::new();
+
+    /// An empty directory for one test. The home directory is moved under
+    /// the temp dir too, so the data directory the app resolves (and
+    /// probes before writes) is never the real one.
+    pub fn temp_dir(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("epilogue-tests-{}", std::process::id()));
+        REDIRECT_HOME.call_once(|| {
+            std::env::set_var("HOME", root.join("home"));
+            std::env::set_var("XDG_CONFIG_HOME", root.join("home").join(".config"));
+        });
+        let dir = root.join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create test directory");
+        dir
+    }
+
+    impl AppState {
+        /// A state over `dir` rather than the app's data directory
+        pub fn in_dir(dir: &Path) -> Self {
+            Self {
+                paths: Ok(AppPaths::new(dir.to_path_buf())),
+                ..Self::new()
+            }
+        }
+    }
+
+    /// A book with only the fields every book has
+    pub fn book(id: &str) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": format!("Book {}", id),
+            "author": "Author",
+            "filePath": format!("/books/{}.epub", id),
+            "lastOpened": "2026-01-01T00:00:00Z",
+            "progress": 0.0,
+        }))
+        .expect("valid book")
+    }
+
+    /// Write a one-chapter EPUB with a cover image to `path`. `metadata` is
+    /// extra OPF metadata, e.g. `<dc:language>en</dc:language>`.
+    pub fn epub(path: &Path, title: &str, metadata: &str) -> String {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let opf = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>{title}</dc:title>
+    <dc:creator>Test Author</dc:creator>
+    <dc:identifier id="id">{title}</dc:identifier>
+    <meta name="cover" content="cover-image"/>
+    {metadata}
+  </metadata>
+  <manifest>
+    <item id="cover-image" href="cover.png" media-type="image/png"/>
+    <item id="chapter" href="chapter.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine>
+    <itemref idref="chapter"/>
+  </spine>
+</package>"#
+        );
+        let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+        let files = [
+            ("mimetype", "application/epub+zip"),
+            ("META-INF/container.xml", container),
+            ("content.opf", &opf),
+            (
+                "chapter.xhtml",
+                "<html><body><p>Three short words</p></body></html>",
+            ),
+            ("cover.png", "not really a png"),
+        ];
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).expect("create epub"));
+        for (name, data) in files {
+            // The mimetype comes first and uncompressed, as in any EPUB
+            let options = match name {
+                "mimetype" => {
+                    SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored)
+                }
+                _ => SimpleFileOptions::default(),
+            };
+            zip.start_file(name, options).expect("start epub entry");
+            zip.write_all(data.as_bytes()).expect("write epub entry");
+        }
+        zip.finish().expect("finish epub");
+        path.to_string_lossy().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{book, temp_dir};
+    use super::*;
+
+    /// The row of a book changed in memory is only written by the flush
+    #[test]
+    fn flush_writes_books_changed_in_memory() {
+        let dir = temp_dir("state_flush");
+        let state = AppState::in_dir(&dir);
+        state
+            .update_library(|library| {
+                library.books = vec![book("a"), book("b")];
+                Ok(())
+            })
+            .unwrap();
+        state
+            .update_book_in_memory("a", |book| {
+                book.progress = 0.42;
+                book.cfi = Some("epubcfi(/6/4)".to_string());
+                Ok(())
+            })
+            .unwrap();
+
+        let stored = |id: &str| {
+            let paths = AppPaths::new(dir.clone());
+            let conn = library_db::open(&paths.library, &paths.library_json).unwrap();
+            let library = library_db::load(&conn).unwrap();
+            library.books.into_iter().find(|b| b.id == id).unwrap()
+        };
+        assert_eq!(stored("a").progress, 0.0);
+
+        state.flush_library().unwrap();
+        let a = stored("a");
+        assert_eq!(a.progress, 0.42);
+        assert_eq!(a.cfi.as_deref(), Some("epubcfi(/6/4)"));
+        assert_eq!(stored("b").progress, 0.0);
+    }
+
+    /// Threads updating progress in memory while others save whole-library
+    /// changes and flush; afterwards every update is in the database
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 40;
+        let dir = temp_dir("state_concurrent_updates");
+        let state = AppState::in_dir(&dir);
+        state
+            .update_library(|library| {
+                library.books = (0..THREADS).map(|i| book(&format!("b{}", i))).collect();
+                library.books.push(book("shared"));
+                Ok(())
+            })
+            .unwrap();
+
+        std::thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let state = &state;
+                scope.spawn(move || {
+                    let id = format!("b{}", thread);
+                    for round in 1..=ROUNDS {
+                        state
+                            .update_book_in_memory(&id, |book| {
+                                book.progress = round as f32 / ROUNDS as f32;
+                                book.cfi = Some(format!("epubcfi(/6/{})", round));
+                                Ok(())
+                            })
+                            .unwrap();
+                        if round % 5 == 0 {
+                            state
+                                .update_library(|library| {
+                                    let shared = library
+                                        .books
+                                        .iter_mut()
+                                        .find(|b| b.id == "shared")
+                                        .unwrap();
+                                    shared.tags.push(format!("{}-{}", thread, round));
+                                    Ok(())
+                                })
+                                .unwrap();
+                        }
+                        // Scheduled saves run in between
+                        if round % 7 == 0 {
+                            state.flush_library().unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        state.flush_library().unwrap();
+
+        // A fresh state reads everything back from the database
+        let reopened = AppState::in_dir(&dir);
+        let books = reopened.with_library(|l| l.books.clone()).unwrap();
+        for thread in 0..THREADS {
+            let book = books
+                .iter()
+                .find(|b| b.id == format!("b{}", thread))
+                .unwrap();
+            assert_eq!(book.progress, 1.0);
+            assert_eq!(book.cfi.as_deref(), Some("epubcfi(/6/40)"));
+        }
+        let shared = books.iter().find(|b| b.id == "shared").unwrap();
+        assert_eq!(shared.tags.len(), THREADS * ROUNDS / 5);
+    }
+
+    #[test]
+    fn progress_points_wait_for_the_flush() {
+        let state = AppState::in_dir(&temp_dir("state_progress_points"));
+        state
+            .update_library(|library| {
+                library.books = vec![book("a"), book("b")];
+                Ok(())
+            })
+            .unwrap();
+
+        state.queue_progress_point("a", 1_000, 0.25);
+        state.queue_progress_point("b", 1_000, 0.5);
+        let history = |id: &str| state.with_db(|conn| library_db::progress_history(conn, id));
+        assert!(history("a").unwrap().is_empty());
+
+        // Book b is removed before the save, so its point is dropped
+        state
+            .update_library(|library| {
+                library.books.retain(|b| b.id == "a");
+                Ok(())
+            })
+            .unwrap();
+        state.flush_library().unwrap();
+        assert_eq!(history("a").unwrap(), vec![(1_000, 0.25)]);
+        assert!(history("b").unwrap().is_empty());
+    }
+}
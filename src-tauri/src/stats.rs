@@ -0,0 +1,301 @@
+/**
+ * Reading statistics for the stats page, from the library and the sessions
+ * log in one call, and the daily reading streak
+ */
+use chrono::{Datelike, Duration, Local, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use tauri::State;
+
+use crate::activity::WORDS_PER_PAGE;
+use crate::config;
+use crate::library::{Book, ReadingState};
+use crate::library_db;
+use crate::logging;
+use crate::sessions::{self, ReadingSession, SessionManager};
+use crate::state::AppState;
+
+/// get_streak lists the days read in this many last days, today included
+const ACTIVE_DAYS_WINDOW: i64 = 90;
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ReadingStats {
+    #[serde(rename = "totalBooks")]
+    pub total_books: usize,
+    /// Books finished, by date or by being read to the end
+    #[serde(rename = "finishedBooks")]
+    pub finished_books: usize,
+    /// Completions in the current local year, re-reads included
+    #[serde(rename = "finishedThisYear")]
+    pub finished_this_year: usize,
+    #[serde(rename = "inProgressBooks")]
+    pub in_progress_books: usize,
+    #[serde(rename = "abandonedBooks")]
+    pub abandoned_books: usize,
+    /// Mean stars of the finished books rated, None when none are
+    #[serde(rename = "averageRating")]
+    pub average_rating: Option<f32>,
+    /// None until a session has been recorded
+    #[serde(rename = "totalReadingSeconds")]
+    pub total_reading_seconds: Option<i64>,
+    #[serde(rename = "pagesLast7Days")]
+    pub pages_last_7_days: u32,
+    #[serde(rename = "pagesLast30Days")]
+    pub pages_last_30_days: u32,
+    /// Percent of books advanced, summed over the books read
+    #[serde(rename = "percentLast7Days")]
+    pub percent_last_7_days: f32,
+    #[serde(rename = "percentLast30Days")]
+    pub percent_last_30_days: f32,
+    /// Most consecutive local days with some reading
+    #[serde(rename = "longestStreakDays")]
+    pub longest_streak_days: u32,
+    #[serde(rename = "currentStreakDays")]
+    pub current_streak_days: u32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ReadingStreak {
+    /// Days in a row with some reading, up to today
+    #[serde(rename = "currentDays")]
+    pub current_days: u32,
+    #[serde(rename = "longestDays")]
+    pub longest_days: u32,
+    /// Local days with some reading in the last 90, oldest first
+    #[serde(rename = "activeDays")]
+    pub active_days: Vec<NaiveDate>,
+}
+
+/// Remember today, in local time, as a day with some reading. Written
+/// once a day rather than on every page turn. Streaks are a nicety, so a
+/// failed write is only logged.
+pub fn record_reading_day(state: &AppState) {
+    if config::is_safe_mode() {
+        return;
+    }
+    let today = Local::now().date_naive();
+    let Ok(mut last) = state.last_reading_day.lock() else {
+        return;
+    };
+    if *last == Some(today) {
+        return;
+    }
+    match state.with_db(|conn| library_db::add_reading_day(conn, today)) {
+        Ok(()) => *last = Some(today),
+        Err(e) => logging::warn(&format!("Failed to record the reading day: {}", e)),
+    }
+}
+
+/// Local days with some reading: those progress was saved on, and those
+/// of the sessions with active time
+fn reading_days(state: &AppState, sessions: &[ReadingSession]) -> BTreeSet<NaiveDate> {
+    let mut days: BTreeSet<NaiveDate> = state
+        .with_db(library_db::reading_days)
+        .unwrap_or_else(|e| {
+            logging::warn(&format!("Failed to read the reading days: {}", e));
+            Vec::new()
+        })
+        .into_iter()
+        .collect();
+    days.extend(
+        sessions
+            .iter()
+            .filter(|s| s.active_seconds > 0)
+            .map(|s| s.start.with_timezone(&Local).date_naive()),
+    );
+    days
+}
+
+/// A book finished: dated, on the Finished shelf, or read to the end
+fn is_finished(book: &Book) -> bool {
+    book.finished_at.is_some()
+        || book.reading_state == ReadingState::Finished
+        || book.progress >= 1.0
+}
+
+/// Length of the longest run of consecutive days in `days`
+pub fn longest_streak(days: &BTreeSet<NaiveDate>) -> u32 {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in days {
+        run = match previous {
+            Some(p) if p.succ_opt() == Some(*day) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(*day);
+    }
+    longest
+}
+
+/// Days in a row in `days` up to `today`, or up to the day before while
+/// today has no reading yet
+pub fn current_streak(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> u32 {
+    let mut day = if days.contains(&today) {
+        Some(today)
+    } else {
+        today.pred_opt()
+    };
+    let mut streak = 0;
+    while let Some(d) = day.filter(|d| days.contains(d)) {
+        streak += 1;
+        day = d.pred_opt();
+    }
+    streak
+}
+
+/// Totals of the shelf and of the reading done, with the books finished
+/// this year and their average rating, pages read and progress made over
+/// the last 7 and 30 days and the current and longest daily streaks
+#[tauri::command]
+pub fn get_reading_stats(
+    state: State<'_, AppState>,
+    session_manager: State<'_, SessionManager>,
+) -> Result<ReadingStats, String> {
+    let mut stats = state.with_library(|library| {
+        let mut stats = ReadingStats {
+            total_books: library.books.len(),
+            ..Default::default()
+        };
+        let year = Local::now().year();
+        let mut ratings = Vec::new();
+        for book in &library.books {
+            stats.finished_this_year += book
+                .completions
+                .iter()
+                .filter(|c| c.finished_at.with_timezone(&Local).year() == year)
+                .count();
+            if book.reading_state == ReadingState::Abandoned {
+                stats.abandoned_books += 1;
+            } else if is_finished(book) {
+                stats.finished_books += 1;
+                ratings.extend(book.rating);
+            } else if book.progress > 0.0 {
+                stats.in_progress_books += 1;
+            }
+        }
+        if !ratings.is_empty() {
+            let sum: u32 = ratings.iter().map(|&r| r as u32).sum();
+            stats.average_rating = Some(sum as f32 / ratings.len() as f32);
+        }
+        stats
+    })?;
+
+    let mut all = sessions::load_sessions(&state.paths()?.sessions);
+    all.extend(sessions::open_session(&session_manager));
+
+    let now = Utc::now();
+    let week_ago = now - Duration::days(7);
+    let month_ago = now - Duration::days(30);
+    let mut total_seconds = None;
+    let (mut words_7, mut words_30) = (0u64, 0u64);
+    for session in &all {
+        *total_seconds.get_or_insert(0) += session.active_seconds;
+
+        let end = session.end.unwrap_or(session.last_activity);
+        if end < month_ago {
+            continue;
+        }
+        let words = session.words.unwrap_or(0);
+        let percent = match (session.start_progress, session.end_progress) {
+            (Some(start), Some(end)) => (end - start).max(0.0) * 100.0,
+            _ => 0.0,
+        };
+        words_30 += words;
+        stats.percent_last_30_days += percent;
+        if end >= week_ago {
+            words_7 += words;
+            stats.percent_last_7_days += percent;
+        }
+    }
+
+    stats.total_reading_seconds = total_seconds;
+    stats.pages_last_7_days = (words_7 as f64 / WORDS_PER_PAGE).round() as u32;
+    stats.pages_last_30_days = (words_30 as f64 / WORDS_PER_PAGE).round() as u32;
+    stats.percent_last_7_days = stats.percent_last_7_days.round();
+    stats.percent_last_30_days = stats.percent_last_30_days.round();
+    let days = reading_days(&state, &all);
+    stats.longest_streak_days = longest_streak(&days);
+    stats.current_streak_days = current_streak(&days, Local::now().date_naive());
+    Ok(stats)
+}
+
+/// The current and longest daily reading streaks, in local days, with the
+/// days read over the last 90
+#[tauri::command]
+pub fn get_streak(
+    state: State<'_, AppState>,
+    session_manager: State<'_, SessionManager>,
+) -> Result<ReadingStreak, String> {
+    let mut all = sessions::load_sessions(&state.paths()?.sessions);
+    all.extend(sessions::open_session(&session_manager));
+    let days = reading_days(&state, &all);
+
+    let today = Local::now().date_naive();
+    let since = today - Duration::days(ACTIVE_DAYS_WINDOW - 1);
+    Ok(ReadingStreak {
+        current_days: current_streak(&days, today),
+        longest_days: longest_streak(&days),
+        active_days: days.range(since..=today).copied().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::testing::{book, temp_dir};
+    use chrono::TimeZone;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, d).unwrap()
+    }
+
+    #[test]
+    fn longest_streak_is_the_longest_run() {
+        assert_eq!(longest_streak(&BTreeSet::new()), 0);
+        let days = BTreeSet::from([day(1), day(2), day(3), day(5), day(6)]);
+        assert_eq!(longest_streak(&days), 3);
+    }
+
+    #[test]
+    fn current_streak_counts_back_from_today_or_yesterday() {
+        let days = BTreeSet::from([day(1), day(3), day(4), day(5)]);
+        assert_eq!(current_streak(&days, day(5)), 3);
+        // Today not read yet keeps yesterday's streak
+        assert_eq!(current_streak(&days, day(6)), 3);
+        assert_eq!(current_streak(&days, day(7)), 0);
+    }
+
+    #[test]
+    fn a_late_session_counts_on_its_local_day() {
+        let dir = temp_dir("stats_local_day");
+        let state = AppState::in_dir(&dir);
+        let start = Local
+            .with_ymd_and_hms(2026, 3, 1, 23, 30, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+        let session = ReadingSession {
+            book_id: "a".to_string(),
+            start,
+            end: Some(start + Duration::minutes(20)),
+            last_activity: start + Duration::minutes(20),
+            active_seconds: 1200,
+            idle_seconds: 0,
+            start_progress: None,
+            end_progress: None,
+            words: None,
+        };
+        assert_eq!(reading_days(&state, &[session]), BTreeSet::from([day(1)]));
+    }
+
+    #[test]
+    fn books_read_to_the_end_count_as_finished() {
+        let mut read = book("a");
+        read.progress = 1.0;
+        assert!(is_finished(&read));
+        let mut started = book("b");
+        started.progress = 0.5;
+        assert!(!is_finished(&started));
+    }
+}
@@ -0,0 +1,233 @@
+/**
+ * Translate selected text: installed bilingual StarDict dictionaries for
+ * words, an optional online provider for sentences
+ */
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::error::AppError;
+use crate::logging;
+use crate::stardict::{self, DictionaryInfo};
+use crate::state::AppState;
+
+/// Longest selection accepted, in characters
+const MAX_TEXT_CHARS: usize = 1000;
+/// Selections of more words than this are sentences, not dictionary entries
+const MAX_DICTIONARY_WORDS: usize = 3;
+/// Lookups remembered in memory
+const CACHE_CAPACITY: usize = 200;
+const ONLINE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// (source language, target language, lowercased text)
+type CacheKey = (Option<String>, String, String);
+
+static CACHE: Mutex<Option<VecDeque<(CacheKey, Translation)>>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Translation {
+    pub text: String,
+    pub translation: String,
+    /// "dictionary" or "online"
+    pub backend: String,
+    /// Name of the dictionary, or host of the online provider
+    pub provider: String,
+    #[serde(rename = "sourceLang")]
+    pub source_lang: Option<String>,
+    #[serde(rename = "targetLang")]
+    pub target_lang: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OnlineResponse {
+    #[serde(rename = "translatedText")]
+    translated_text: String,
+}
+
+fn cached(key: &CacheKey) -> Option<Translation> {
+    let mut cache = CACHE.lock().ok()?;
+    let cache = cache.as_mut()?;
+    let index = cache.iter().position(|(k, _)| k == key)?;
+    // Most recently used last
+    let entry = cache.remove(index)?;
+    let translation = entry.1.clone();
+    cache.push_back(entry);
+    Some(translation)
+}
+
+fn remember(key: CacheKey, translation: &Translation) {
+    if let Ok(mut cache) = CACHE.lock() {
+        let cache = cache.get_or_insert_with(VecDeque::new);
+        cache.retain(|(k, _)| k != &key);
+        if cache.len() >= CACHE_CAPACITY {
+            cache.pop_front();
+        }
+        cache.push_back((key, translation.clone()));
+    }
+}
+
+/// Dictionaries that translate into `target`, from `source` if given
+fn dictionaries_for(dir: &Path, source: Option<&str>, target: &str) -> Vec<DictionaryInfo> {
+    stardict::installed(dir)
+        .into_iter()
+        .filter(|d| d.target_lang.as_deref() == Some(target))
+        .filter(|d| source.is_none_or(|source| d.source_lang.as_deref() == Some(source)))
+        .collect()
+}
+
+/// The first dictionary with an entry for `text`, trying it as selected and
+/// without surrounding punctuation
+fn lookup(dictionaries: &[DictionaryInfo], text: &str) -> Option<(String, String)> {
+    let bare = text.trim_matches(|c: char| !c.is_alphanumeric());
+    for info in dictionaries {
+        let dictionary = match stardict::load(info) {
+            Ok(dictionary) => dictionary,
+            Err(e) => {
+                logging::warn(&e);
+                continue;
+            }
+        };
+        if let Some(article) = dictionary.lookup(text).or_else(|| dictionary.lookup(bare)) {
+            return Some((info.name.clone(), article));
+        }
+    }
+    None
+}
+
+async fn translate_online(
+    endpoint: &str,
+    text: &str,
+    source: Option<&str>,
+    target: &str,
+) -> Result<(String, String), String> {
+    let url = reqwest::Url::parse(endpoint)
+        .map_err(|e| format!("Invalid translation endpoint: {}", e))?;
+    let client = reqwest::Client::builder()
+        .timeout(ONLINE_TIMEOUT)
+        .user_agent(concat!("Epilogue/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let response = client
+        .post(url.clone())
+        .json(&serde_json::json!({
+            "q": text,
+            "source": source.unwrap_or("auto"),
+            "target": target,
+            "format": "text",
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Translation request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Translation service returned {}",
+            response.status()
+        ));
+    }
+    let body: OnlineResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse translation: {}", e))?;
+    Ok((
+        url.host_str().unwrap_or_default().to_string(),
+        body.translated_text,
+    ))
+}
+
+/// Translate a selection into `target_lang`, or the language set in the
+/// preferences. Words go to the installed dictionaries; sentences to the
+/// online provider when it is enabled.
+#[tauri::command]
+pub async fn translate_text(
+    app: AppHandle,
+    text: String,
+    target_lang: Option<String>,
+    source_lang: Option<String>,
+) -> Result<Translation, AppError> {
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err("Nothing to translate".to_string().into());
+    }
+    let length = text.chars().count();
+    if length > MAX_TEXT_CHARS {
+        return Err(format!(
+            "Text is too long to translate ({} characters, at most {})",
+            length, MAX_TEXT_CHARS
+        )
+        .into());
+    }
+
+    let state = app.state::<AppState>();
+    let prefs = state.preferences()?;
+    let target = target_lang
+        .or(prefs.translation_target_language)
+        .map(|lang| stardict::primary_language(&lang))
+        .filter(|lang| !lang.is_empty())
+        .ok_or_else(|| "No translation language is set".to_string())?;
+    let source = source_lang
+        .map(|lang| stardict::primary_language(&lang))
+        .filter(|lang| !lang.is_empty());
+
+    let key = (source.clone(), target.clone(), text.to_lowercase());
+    if let Some(translation) = cached(&key) {
+        return Ok(translation);
+    }
+
+    let folder = state.paths()?.dictionaries.clone();
+    let dictionaries = dictionaries_for(&folder, source.as_deref(), &target);
+    let is_word = text.split_whitespace().count() <= MAX_DICTIONARY_WORDS;
+
+    let mut found = None;
+    if is_word && !dictionaries.is_empty() {
+        let (dictionaries, text) = (dictionaries.clone(), text.clone());
+        // Indexes are read on first use, which can take a moment
+        found = tauri::async_runtime::spawn_blocking(move || lookup(&dictionaries, &text))
+            .await
+            .map_err(|e| format!("Dictionary lookup failed: {}", e))?
+            .map(|(name, article)| ("dictionary", name, article));
+    }
+
+    let online = prefs
+        .translation_endpoint
+        .filter(|_| prefs.translation_online_enabled);
+    if found.is_none() {
+        if let Some(endpoint) = &online {
+            let (host, translated) =
+                translate_online(endpoint, &text, source.as_deref(), &target).await?;
+            found = Some(("online", host, translated));
+        }
+    }
+
+    let Some((backend, provider, translated)) = found else {
+        if dictionaries.is_empty() {
+            return Err(AppError::NoDictionary {
+                source,
+                target,
+                folder: folder.to_string_lossy().to_string(),
+            });
+        }
+        if !is_word {
+            return Err(
+                "Translating sentences needs the online provider, which is off"
+                    .to_string()
+                    .into(),
+            );
+        }
+        return Err(format!("No translation found for '{}'", text).into());
+    };
+
+    let translation = Translation {
+        text,
+        translation: translated,
+        backend: backend.to_string(),
+        provider,
+        source_lang: source,
+        target_lang: target,
+    };
+    remember(key, &translation);
+    Ok(translation)
+}
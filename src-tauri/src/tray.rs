@@ -0,0 +1,182 @@
+/**
+ * System tray: recent books, music toggle and minimize-to-tray
+ */
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Emitter, Listener, Manager, Window, WindowEvent};
+
+use crate::launch;
+use crate::library;
+use crate::logging;
+use crate::state::AppState;
+
+const TRAY_ID: &str = "main";
+const RECENT_LIMIT: usize = 5;
+const MAX_TITLE_CHARS: usize = 40;
+
+/// False when the desktop has no tray host; the window must then never be
+/// hidden to the tray, or it could not be brought back
+static TRAY_AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_available() -> bool {
+    TRAY_AVAILABLE.load(Ordering::SeqCst)
+}
+
+/// Create the tray icon. Failure (e.g. no StatusNotifier host or missing
+/// appindicator library on Linux) is logged and the app runs without it.
+pub fn init(app: &AppHandle) {
+    // libappindicator is loaded at runtime on Linux and panics when absent
+    match panic::catch_unwind(AssertUnwindSafe(|| create_tray(app))) {
+        Ok(Ok(())) => {
+            TRAY_AVAILABLE.store(true, Ordering::SeqCst);
+            let handle = app.clone();
+            app.listen_any("library-changed", move |_| refresh(&handle));
+        }
+        Ok(Err(e)) => logging::warn(&format!("System tray unavailable: {}", e)),
+        Err(_) => logging::warn("System tray unavailable: no tray host found"),
+    }
+}
+
+fn create_tray(app: &AppHandle) -> tauri::Result<()> {
+    let menu = build_menu(app)?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("Epilogue")
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(|app, event| handle_menu_event(app, event.id().as_ref()))
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                launch::focus_main_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+
+    builder.build(app)?;
+    Ok(())
+}
+
+fn build_menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
+    let menu = Menu::new(app)?;
+
+    let recent =
+        library::recent_books(&app.state::<AppState>(), RECENT_LIMIT, false).unwrap_or_default();
+    if recent.is_empty() {
+        menu.append(&MenuItem::with_id(
+            app,
+            "recent-none",
+            "No recent books",
+            false,
+            None::<&str>,
+        )?)?;
+    }
+    for book in recent {
+        let label = truncate_title(&book.title);
+        menu.append(&MenuItem::with_id(
+            app,
+            format!("recent:{}", book.id),
+            label,
+            true,
+            None::<&str>,
+        )?)?;
+    }
+
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(
+        app,
+        "toggle-music",
+        "Play/Pause Music",
+        true,
+        None::<&str>,
+    )?)?;
+    menu.append(&MenuItem::with_id(
+        app,
+        "show",
+        "Show Epilogue",
+        true,
+        None::<&str>,
+    )?)?;
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?)?;
+
+    Ok(menu)
+}
+
+pub fn truncate_title(title: &str) -> String {
+    if title.chars().count() <= MAX_TITLE_CHARS {
+        return title.to_string();
+    }
+    let short: String = title.chars().take(MAX_TITLE_CHARS - 1).collect();
+    format!("{}…", short.trim_end())
+}
+
+/// Rebuild the menu so the recents section matches the library
+pub fn refresh(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    match build_menu(app) {
+        Ok(menu) => {
+            let _ = tray.set_menu(Some(menu));
+        }
+        Err(e) => logging::warn(&format!("Failed to refresh tray menu: {}", e)),
+    }
+}
+
+fn handle_menu_event(app: &AppHandle, id: &str) {
+    match id {
+        "toggle-music" => {
+            let _ = app.emit("tray-toggle-music", ());
+        }
+        "show" => launch::focus_main_window(app),
+        "quit" => app.exit(0),
+        _ => {
+            if let Some(book_id) = id.strip_prefix("recent:") {
+                open_recent(app, book_id);
+            }
+        }
+    }
+}
+
+pub fn open_recent(app: &AppHandle, book_id: &str) {
+    let book = app
+        .state::<AppState>()
+        .with_library(|lib| lib.books.iter().find(|b| b.id == book_id).cloned())
+        .ok()
+        .flatten();
+
+    launch::focus_main_window(app);
+    match book {
+        Some(book) => launch::emit_when_ready(app, "open-file", book),
+        None => refresh(app), // Removed since the menu was built
+    }
+}
+
+/// Hide instead of closing when the user chose to minimize to the tray
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    let WindowEvent::CloseRequested { api, .. } = event else {
+        return;
+    };
+    if window.label() != "main" || !is_available() {
+        return;
+    }
+
+    let to_tray = window
+        .state::<AppState>()
+        .preferences()
+        .map(|p| p.close_behavior == "tray")
+        .unwrap_or(false);
+    if to_tray {
+        api.prevent_close();
+        let _ = window.hide();
+    }
+}
@@ -0,0 +1,472 @@
+/**
+ * Text-to-speech through the system engine (SAPI, AVSpeechSynthesizer or
+ * speech-dispatcher), spoken a sentence at a time on a dedicated thread
+ */
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tts::Tts;
+
+use crate::logging;
+use crate::state::AppState;
+
+/// How often the speech thread checks whether a sentence has finished, on
+/// engines that can't report it themselves
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Engines may not report speaking right after a sentence is queued
+const START_GRACE: Duration = Duration::from_millis(300);
+/// Longest we wait for the engine to list its voices
+const VOICES_TIMEOUT: Duration = Duration::from_secs(3);
+/// Allowed range for the speaking rate, as a multiple of the normal rate
+pub const MIN_TTS_RATE: f32 = 0.5;
+pub const MAX_TTS_RATE: f32 = 3.0;
+
+enum Command {
+    Speak {
+        id: u64,
+        text: String,
+        voice: Option<String>,
+        rate: f32,
+    },
+    Pause,
+    Resume,
+    Stop,
+    /// The engine finished speaking a sentence
+    Finished,
+    Voices(Sender<Result<Vec<TtsVoice>, String>>),
+    Shutdown,
+}
+
+/// The speech thread, started on first use
+#[derive(Default)]
+pub struct TtsPlayer {
+    sender: Mutex<Option<Sender<Command>>>,
+    next_id: AtomicU64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TtsVoice {
+    pub id: String,
+    pub name: String,
+    pub language: String,
+}
+
+/// Emitted as "tts-sentence" when a sentence starts being spoken
+#[derive(Debug, Serialize, Clone)]
+struct SentenceEvent {
+    #[serde(rename = "utteranceId")]
+    utterance_id: u64,
+    index: usize,
+    /// UTF-16 offsets into the text, as JavaScript strings count them
+    start: usize,
+    end: usize,
+}
+
+/// Emitted as "tts-end" when an utterance is done or cancelled
+#[derive(Debug, Serialize, Clone)]
+struct EndEvent {
+    #[serde(rename = "utteranceId")]
+    utterance_id: u64,
+    completed: bool,
+}
+
+struct Sentence {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+struct Utterance {
+    id: u64,
+    sentences: Vec<Sentence>,
+    index: usize,
+    paused: bool,
+}
+
+/// Split text into sentences, with their UTF-16 offsets. A sentence ends
+/// at a line break, or at whitespace after terminal punctuation and any
+/// closing quotes or brackets.
+fn split_sentences(text: &str) -> Vec<Sentence> {
+    let mut chars = Vec::new();
+    let mut offset = 0;
+    for c in text.chars() {
+        chars.push((offset, c));
+        offset += c.len_utf16();
+    }
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut terminated = false;
+    for (i, &(_, c)) in chars.iter().enumerate() {
+        if c == '\n' || (terminated && c.is_whitespace()) {
+            segments.push(&chars[start..i]);
+            start = i + 1;
+            terminated = false;
+        } else if matches!(c, '.' | '!' | '?' | '…' | '。' | '！' | '？') {
+            terminated = true;
+        } else if !matches!(c, '"' | '\'' | '”' | '’' | '»' | ')' | ']') {
+            terminated = false;
+        }
+    }
+    segments.push(&chars[start..]);
+
+    segments
+        .into_iter()
+        .filter_map(|segment| {
+            let first = segment.iter().position(|(_, c)| !c.is_whitespace())?;
+            let last = segment.iter().rposition(|(_, c)| !c.is_whitespace())?;
+            let (end_offset, end_char) = segment[last];
+            Some(Sentence {
+                text: segment[first..=last].iter().map(|(_, c)| c).collect(),
+                start: segment[first].0,
+                end: end_offset + end_char.len_utf16(),
+            })
+        })
+        .collect()
+}
+
+fn list_voices(tts: &Tts) -> Result<Vec<TtsVoice>, String> {
+    let voices = tts
+        .voices()
+        .map_err(|e| format!("Failed to list voices: {}", e))?;
+    Ok(voices
+        .iter()
+        .map(|voice| TtsVoice {
+            id: voice.id(),
+            name: voice.name(),
+            language: voice.language().as_str().to_string(),
+        })
+        .collect())
+}
+
+fn configure(tts: &mut Tts, voice: Option<&str>, rate: f32) {
+    let features = tts.supported_features();
+    if let (Some(id), true) = (voice, features.voice) {
+        match tts
+            .voices()
+            .map(|voices| voices.into_iter().find(|v| v.id() == id))
+        {
+            Ok(Some(voice)) => {
+                if let Err(e) = tts.set_voice(&voice) {
+                    logging::warn(&format!("Failed to set voice {}: {}", id, e));
+                }
+            }
+            Ok(None) => logging::warn(&format!("Voice not found: {}", id)),
+            Err(e) => logging::warn(&format!("Failed to list voices: {}", e)),
+        }
+    }
+    if features.rate {
+        let rate = (tts.normal_rate() * rate).clamp(tts.min_rate(), tts.max_rate());
+        if let Err(e) = tts.set_rate(rate) {
+            logging::warn(&format!("Failed to set speaking rate: {}", e));
+        }
+    }
+}
+
+struct Speaker {
+    app: AppHandle,
+    tts: Tts,
+    /// Whether the engine tells us, one way or another, when a sentence ends
+    tracks_progress: bool,
+    current: Option<Utterance>,
+    sentence_started: Instant,
+}
+
+impl Speaker {
+    fn end(&mut self, completed: bool) {
+        if let Some(utterance) = self.current.take() {
+            let _ = self.app.emit(
+                "tts-end",
+                EndEvent {
+                    utterance_id: utterance.id,
+                    completed,
+                },
+            );
+        }
+    }
+
+    fn speak_current(&mut self) {
+        let Some(utterance) = self.current.as_ref() else {
+            return;
+        };
+
+        // Engines that can't report progress get the rest in one go
+        let result = if self.tracks_progress {
+            let sentence = &utterance.sentences[utterance.index];
+            let _ = self.app.emit(
+                "tts-sentence",
+                SentenceEvent {
+                    utterance_id: utterance.id,
+                    index: utterance.index,
+                    start: sentence.start,
+                    end: sentence.end,
+                },
+            );
+            self.sentence_started = Instant::now();
+            self.tts.speak(sentence.text.as_str(), true)
+        } else {
+            let rest: Vec<&str> = utterance.sentences[utterance.index..]
+                .iter()
+                .map(|s| s.text.as_str())
+                .collect();
+            self.tts.speak(rest.join(" "), true)
+        };
+
+        if let Err(e) = result {
+            logging::error(&format!("Text-to-speech failed: {}", e));
+            self.end(false);
+        } else if !self.tracks_progress {
+            self.current = None;
+        }
+    }
+
+    fn advance(&mut self) {
+        let Some(utterance) = self.current.as_mut() else {
+            return;
+        };
+        if utterance.paused {
+            return;
+        }
+        utterance.index += 1;
+        if utterance.index >= utterance.sentences.len() {
+            self.end(true);
+        } else {
+            self.speak_current();
+        }
+    }
+
+    fn stop_engine(&mut self) {
+        if let Err(e) = self.tts.stop() {
+            logging::warn(&format!("Failed to stop speech: {}", e));
+        }
+    }
+}
+
+fn run(app: AppHandle, commands: Receiver<Command>, sender: Sender<Command>, tts: Tts) {
+    let features = tts.supported_features();
+    let callbacks = features.utterance_callbacks
+        && tts
+            .on_utterance_end(Some(Box::new(move |_| {
+                let _ = sender.send(Command::Finished);
+            })))
+            .is_ok();
+    let mut speaker = Speaker {
+        app,
+        tts,
+        tracks_progress: callbacks || features.is_speaking,
+        current: None,
+        sentence_started: Instant::now(),
+    };
+
+    loop {
+        let command = match commands.recv_timeout(POLL_INTERVAL) {
+            Ok(command) => Some(command),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match command {
+            Some(Command::Speak {
+                id,
+                text,
+                voice,
+                rate,
+            }) => {
+                // A new utterance replaces whatever was being spoken
+                if speaker.current.is_some() {
+                    speaker.stop_engine();
+                    speaker.end(false);
+                }
+                configure(&mut speaker.tts, voice.as_deref(), rate);
+                let sentences = split_sentences(&text);
+                speaker.current = Some(Utterance {
+                    id,
+                    sentences,
+                    index: 0,
+                    paused: false,
+                });
+                if speaker
+                    .current
+                    .as_ref()
+                    .is_some_and(|u| u.sentences.is_empty())
+                {
+                    speaker.end(true);
+                } else {
+                    speaker.speak_current();
+                }
+            }
+            // The engines can't pause mid-sentence everywhere, so pausing
+            // stops and resuming starts the sentence over
+            Some(Command::Pause) => {
+                if let Some(utterance) = speaker.current.as_mut() {
+                    if !utterance.paused {
+                        utterance.paused = true;
+                        speaker.stop_engine();
+                    }
+                }
+            }
+            Some(Command::Resume) => {
+                if let Some(utterance) = speaker.current.as_mut() {
+                    if utterance.paused {
+                        utterance.paused = false;
+                        speaker.speak_current();
+                    }
+                }
+            }
+            Some(Command::Stop) => {
+                if speaker.current.is_some() {
+                    speaker.stop_engine();
+                    speaker.end(false);
+                }
+            }
+            Some(Command::Finished) => speaker.advance(),
+            Some(Command::Voices(reply)) => {
+                let _ = reply.send(list_voices(&speaker.tts));
+            }
+            Some(Command::Shutdown) => {
+                speaker.stop_engine();
+                speaker.end(false);
+                break;
+            }
+            None => {
+                let finished = !callbacks
+                    && speaker.current.as_ref().is_some_and(|u| !u.paused)
+                    && speaker.sentence_started.elapsed() > START_GRACE
+                    && !speaker.tts.is_speaking().unwrap_or(true);
+                if finished {
+                    speaker.advance();
+                }
+            }
+        }
+    }
+}
+
+impl TtsPlayer {
+    /// The speech thread's channel, starting the thread if needed
+    fn sender(&self, app: &AppHandle) -> Result<Sender<Command>, String> {
+        let mut sender = self.sender.lock().map_err(|e| e.to_string())?;
+        if let Some(sender) = sender.as_ref() {
+            return Ok(sender.clone());
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread_app = app.clone();
+        let thread_tx = tx.clone();
+        std::thread::Builder::new()
+            .name("tts".to_string())
+            .spawn(move || {
+                // The engine is created and used on this thread only
+                match Tts::default() {
+                    Ok(tts) => {
+                        let _ = ready_tx.send(Ok(()));
+                        run(thread_app, rx, thread_tx, tts);
+                    }
+                    Err(e) => {
+                        let _ =
+                            ready_tx.send(Err(format!("Text-to-speech isn't available: {}", e)));
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to start text-to-speech: {}", e))?;
+        ready_rx
+            .recv()
+            .map_err(|_| "Text-to-speech failed to start".to_string())??;
+
+        *sender = Some(tx.clone());
+        Ok(tx)
+    }
+
+    /// Send a command if the speech thread is running; nothing to control
+    /// otherwise
+    fn send(&self, command: Command) -> Result<(), String> {
+        let mut sender = self.sender.lock().map_err(|e| e.to_string())?;
+        if let Some(tx) = sender.as_ref() {
+            if tx.send(command).is_err() {
+                *sender = None;
+                return Err("Text-to-speech stopped unexpectedly".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Speak `text`, cancelling anything being spoken. `voice` and `rate`
+/// default to the TTS preferences. Returns the utterance id carried by the
+/// "tts-sentence" and "tts-end" events.
+#[tauri::command]
+pub fn speak_text(
+    app: AppHandle,
+    player: State<'_, TtsPlayer>,
+    state: State<'_, AppState>,
+    text: String,
+    voice: Option<String>,
+    rate: Option<f32>,
+) -> Result<u64, String> {
+    let prefs = state.preferences()?.tts;
+    let rate = rate.unwrap_or(prefs.rate);
+    if !(MIN_TTS_RATE..=MAX_TTS_RATE).contains(&rate) {
+        return Err(format!(
+            "Speaking rate must be between {} and {}, got {}",
+            MIN_TTS_RATE, MAX_TTS_RATE, rate
+        ));
+    }
+
+    let id = player.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+    player
+        .sender(&app)?
+        .send(Command::Speak {
+            id,
+            text,
+            voice: voice.or(prefs.voice),
+            rate,
+        })
+        .map_err(|_| "Text-to-speech stopped unexpectedly".to_string())?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn pause_tts(player: State<'_, TtsPlayer>) -> Result<(), String> {
+    player.send(Command::Pause)
+}
+
+#[tauri::command]
+pub fn resume_tts(player: State<'_, TtsPlayer>) -> Result<(), String> {
+    player.send(Command::Resume)
+}
+
+#[tauri::command]
+pub fn stop_tts(player: State<'_, TtsPlayer>) -> Result<(), String> {
+    player.send(Command::Stop)
+}
+
+/// Voices the system engine offers, for the TTS preferences
+#[tauri::command]
+pub fn get_tts_voices(
+    app: AppHandle,
+    player: State<'_, TtsPlayer>,
+) -> Result<Vec<TtsVoice>, String> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    player
+        .sender(&app)?
+        .send(Command::Voices(reply_tx))
+        .map_err(|_| "Text-to-speech stopped unexpectedly".to_string())?;
+    reply_rx
+        .recv_timeout(VOICES_TIMEOUT)
+        .map_err(|_| "Timed out listing voices".to_string())?
+}
+
+/// Stop speaking and end the speech thread
+pub fn shutdown(app: &AppHandle) {
+    let sender = app
+        .state::<TtsPlayer>()
+        .sender
+        .lock()
+        .ok()
+        .and_then(|mut sender| sender.take());
+    if let Some(sender) = sender {
+        let _ = sender.send(Command::Shutdown);
+    }
+}
@@ -0,0 +1,156 @@
+/**
+ * Checking GitHub releases for a newer version (never downloads anything)
+ */
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::launch;
+use crate::logging;
+use crate::meta;
+use crate::state::AppState;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/Greyash-Dave/Epilogue/releases/latest";
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum UpdateStatus {
+    Available,
+    UpToDate,
+    /// No network connection or the request timed out
+    Offline,
+    /// Reached the network but got an unexpected response
+    Unreachable,
+    /// Built without update checks (distro packages)
+    Disabled,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UpdateInfo {
+    pub status: UpdateStatus,
+    pub current: String,
+    pub latest: Option<String>,
+    pub url: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl UpdateInfo {
+    fn without_release(status: UpdateStatus) -> Self {
+        Self {
+            status,
+            current: env!("CARGO_PKG_VERSION").to_string(),
+            latest: None,
+            url: None,
+            notes: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    body: Option<String>,
+}
+
+async fn fetch_latest_release() -> Result<Release, UpdateStatus> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("Epilogue/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|_| UpdateStatus::Unreachable)?;
+
+    let response = client
+        .get(RELEASES_URL)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await
+        .map_err(|e| {
+            logging::debug(&format!("Update check failed: {}", e));
+            if e.is_connect() || e.is_timeout() {
+                UpdateStatus::Offline
+            } else {
+                UpdateStatus::Unreachable
+            }
+        })?;
+
+    if !response.status().is_success() {
+        logging::debug(&format!("Update check got HTTP {}", response.status()));
+        return Err(UpdateStatus::Unreachable);
+    }
+
+    response.json().await.map_err(|_| UpdateStatus::Unreachable)
+}
+
+/// "v1.2.0" -> 1.2.0
+fn parse_version(tag: &str) -> Option<semver::Version> {
+    semver::Version::parse(tag.trim().trim_start_matches(['v', 'V'])).ok()
+}
+
+async fn run_check() -> UpdateInfo {
+    if cfg!(feature = "disable-update-check") {
+        return UpdateInfo::without_release(UpdateStatus::Disabled);
+    }
+
+    let release = match fetch_latest_release().await {
+        Ok(release) => release,
+        Err(status) => return UpdateInfo::without_release(status),
+    };
+
+    let (Some(current), Some(latest)) = (
+        parse_version(env!("CARGO_PKG_VERSION")),
+        parse_version(&release.tag_name),
+    ) else {
+        return UpdateInfo::without_release(UpdateStatus::Unreachable);
+    };
+
+    UpdateInfo {
+        status: if latest > current {
+            UpdateStatus::Available
+        } else {
+            UpdateStatus::UpToDate
+        },
+        current: current.to_string(),
+        latest: Some(latest.to_string()),
+        url: Some(release.html_url),
+        notes: release.body,
+    }
+}
+
+/// Check whether a newer release exists. Network problems are reported in
+/// the result's status rather than as an error.
+#[tauri::command]
+pub async fn check_for_updates() -> Result<UpdateInfo, String> {
+    let info = run_check().await;
+    let _ = meta::update_meta(|m| m.last_update_check = Some(Utc::now()));
+    Ok(info)
+}
+
+/// The daily maintenance check, when enabled and not already done by hand
+/// in the last day. The result is emitted as "update-check-result".
+pub fn maintenance_check(app: &AppHandle) -> Result<String, String> {
+    if cfg!(feature = "disable-update-check") {
+        return Ok("Update checks are disabled in this build".to_string());
+    }
+
+    let enabled = app
+        .state::<AppState>()
+        .preferences()
+        .map(|p| p.update_check_enabled)
+        .unwrap_or(true);
+    if !enabled {
+        return Ok("Update checks are turned off".to_string());
+    }
+    let recent = meta::load_meta()
+        .last_update_check
+        .is_some_and(|last| Utc::now() - last < Duration::days(1));
+    if recent {
+        return Ok("Already checked today".to_string());
+    }
+
+    let info = tauri::async_runtime::block_on(check_for_updates())?;
+    let summary = format!("{:?}", info.status);
+    launch::emit_when_ready(app, "update-check-result", info);
+    Ok(summary)
+}
@@ -0,0 +1,201 @@
+/**
+ * Per-book view state (zoom, sidebar, search, chapter list scroll), kept
+ * apart from the reading position and written a moment after it settles
+ */
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use crate::advanced::AdvancedConfig;
+use crate::config;
+use crate::logging;
+use crate::state::AppState;
+
+/// Largest state blob accepted, in bytes of JSON
+const MAX_VIEW_STATE_BYTES: usize = 4 * 1024;
+const MAX_SEARCH_QUERY_CHARS: usize = 500;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 5.0;
+
+/// States saved but not written yet, by book id
+static PENDING: Mutex<Option<HashMap<String, ViewState>>> = Mutex::new(None);
+static SAVE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ViewState {
+    #[serde(default)]
+    pub zoom: Option<f32>,
+    #[serde(rename = "tocOpen", default)]
+    pub toc_open: Option<bool>,
+    #[serde(rename = "searchQuery", default)]
+    pub search_query: Option<String>,
+    /// Scroll offset of the chapter list, in pixels
+    #[serde(rename = "tocScroll", default)]
+    pub toc_scroll: Option<f64>,
+}
+
+impl ViewState {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(zoom) = self.zoom {
+            if !(MIN_ZOOM..=MAX_ZOOM).contains(&zoom) {
+                return Err(format!(
+                    "Zoom must be between {} and {}, got {}",
+                    MIN_ZOOM, MAX_ZOOM, zoom
+                ));
+            }
+        }
+        if self
+            .search_query
+            .as_ref()
+            .is_some_and(|q| q.chars().count() > MAX_SEARCH_QUERY_CHARS)
+        {
+            return Err(format!(
+                "Search query is longer than {} characters",
+                MAX_SEARCH_QUERY_CHARS
+            ));
+        }
+        if self
+            .toc_scroll
+            .is_some_and(|scroll| !scroll.is_finite() || scroll < 0.0)
+        {
+            return Err("Chapter list scroll must be a positive number".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn state_path(state: &AppState, book_id: &str) -> Result<PathBuf, String> {
+    // Book ids are hex digests, anything else must not reach the filesystem
+    if book_id.is_empty() || !book_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid book id: {}", book_id));
+    }
+    Ok(state.paths()?.view_state.join(format!("{}.json", book_id)))
+}
+
+/// Write every pending state
+fn write_pending(state: &AppState) {
+    let pending = match PENDING.lock() {
+        Ok(mut pending) => pending.take().unwrap_or_default(),
+        Err(_) => return,
+    };
+    if pending.is_empty() || config::is_safe_mode() {
+        return;
+    }
+    for (book_id, view) in pending {
+        let result = state_path(state, &book_id).and_then(|path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .map_err(|e| format!("Failed to create view state directory: {}", e))?;
+            }
+            let json = serde_json::to_string(&view)
+                .map_err(|e| format!("Failed to serialize view state: {}", e))?;
+            fs::write(&path, json).map_err(|e| format!("Failed to write view state: {}", e))
+        });
+        if let Err(e) = result {
+            logging::error(&e);
+        }
+    }
+}
+
+/// Write pending states before the app goes away
+pub fn flush_pending(app: &AppHandle) {
+    SAVE_GENERATION.fetch_add(1, Ordering::SeqCst);
+    write_pending(&app.state::<AppState>());
+}
+
+/// Forget a removed book's view state
+pub fn remove(state: &AppState, book_id: &str) {
+    if let Ok(mut pending) = PENDING.lock() {
+        if let Some(pending) = pending.as_mut() {
+            pending.remove(book_id);
+        }
+    }
+    if let Ok(path) = state_path(state, book_id) {
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                logging::warn(&format!(
+                    "Failed to remove view state of {}: {}",
+                    book_id, e
+                ));
+            }
+        }
+    }
+}
+
+/// Save a book's view state, given as JSON. Calls in quick succession end
+/// in a single write after the autosave debounce.
+#[tauri::command]
+pub fn save_view_state(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    advanced: State<'_, AdvancedConfig>,
+    book_id: String,
+    state_json: String,
+) -> Result<(), String> {
+    if state_json.len() > MAX_VIEW_STATE_BYTES {
+        return Err(format!(
+            "View state is larger than {} bytes",
+            MAX_VIEW_STATE_BYTES
+        ));
+    }
+    let view: ViewState =
+        serde_json::from_str(&state_json).map_err(|e| format!("Invalid view state: {}", e))?;
+    view.validate()?;
+    let book_id = state.resolve_book_id(&book_id);
+    state_path(&state, &book_id)?;
+
+    PENDING
+        .lock()
+        .map_err(|_| "View state lock poisoned".to_string())?
+        .get_or_insert_with(HashMap::new)
+        .insert(book_id, view);
+
+    let generation = SAVE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let delay = Duration::from_millis(advanced.autosave_debounce_ms);
+    std::thread::spawn(move || {
+        std::thread::sleep(delay);
+        if SAVE_GENERATION.load(Ordering::SeqCst) != generation {
+            return; // A later save rescheduled the write
+        }
+        write_pending(&app.state::<AppState>());
+    });
+    Ok(())
+}
+
+/// A book's view state, if one was saved
+#[tauri::command]
+pub fn get_view_state(
+    state: State<'_, AppState>,
+    book_id: String,
+) -> Result<Option<ViewState>, String> {
+    let book_id = state.resolve_book_id(&book_id);
+    let pending = PENDING
+        .lock()
+        .map_err(|_| "View state lock poisoned".to_string())?
+        .as_ref()
+        .and_then(|pending| pending.get(&book_id).cloned());
+    if pending.is_some() {
+        return Ok(pending);
+    }
+
+    let path = state_path(&state, &book_id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read view state: {}", e))?;
+    // A state that no longer parses is dropped rather than failing the open
+    match serde_json::from_str(&content) {
+        Ok(view) => Ok(Some(view)),
+        Err(e) => {
+            logging::warn(&format!("Ignoring view state of {}: {}", book_id, e));
+            Ok(None)
+        }
+    }
+}
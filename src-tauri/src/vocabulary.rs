@@ -0,0 +1,264 @@
+/**
+ * Vocabulary collected from dictionary lookups and translations, with the
+ * sentences the words were met in, kept in vocabulary.json
+ */
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::config;
+use crate::goodreads::escape_csv;
+use crate::state::AppState;
+
+/// Longest word or phrase collected, in characters
+const MAX_WORD_CHARS: usize = 100;
+const MAX_SENTENCE_CHARS: usize = 1000;
+const MAX_DEFINITION_CHARS: usize = 5000;
+
+/// Held while vocabulary.json is read and written back
+static VOCABULARY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VocabularyExample {
+    pub sentence: String,
+    #[serde(rename = "bookId", default)]
+    pub book_id: Option<String>,
+    /// The title when the word was collected, kept if the book is removed
+    #[serde(rename = "bookTitle", default)]
+    pub book_title: Option<String>,
+    #[serde(default)]
+    pub cfi: Option<String>,
+    #[serde(rename = "addedAt")]
+    pub added_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VocabularyEntry {
+    pub word: String,
+    #[serde(default)]
+    pub definition: Option<String>,
+    #[serde(default)]
+    pub examples: Vec<VocabularyExample>,
+    #[serde(rename = "addedAt")]
+    pub added_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct VocabularyFilter {
+    /// Part of the word or its definition, ignoring case
+    #[serde(default)]
+    pub query: Option<String>,
+    /// Words met in this book
+    #[serde(rename = "bookId", default)]
+    pub book_id: Option<String>,
+}
+
+fn word_key(word: &str) -> String {
+    word.trim().to_lowercase()
+}
+
+pub fn load_vocabulary(path: &Path) -> Vec<VocabularyEntry> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_vocabulary(path: &Path, entries: &[VocabularyEntry]) -> Result<(), String> {
+    if config::is_safe_mode() {
+        return Err(config::safe_mode_error().into());
+    }
+    let json = serde_json::to_string_pretty(entries)
+        .map_err(|e| format!("Failed to serialize vocabulary: {}", e))?;
+    fs::write(path, json).map_err(|e| format!("Failed to write vocabulary: {}", e))
+}
+
+/// Add `entry` to `entries`, merging it into the same word when it is
+/// there: new example sentences are added and a new definition replaces
+/// the old one
+pub fn merge_entry(entries: &mut Vec<VocabularyEntry>, entry: VocabularyEntry) {
+    let key = word_key(&entry.word);
+    let Some(existing) = entries.iter_mut().find(|e| word_key(&e.word) == key) else {
+        entries.push(entry);
+        return;
+    };
+    for example in entry.examples {
+        let known = existing
+            .examples
+            .iter()
+            .any(|e| e.sentence == example.sentence && e.book_id == example.book_id);
+        if !known {
+            existing.examples.push(example);
+        }
+    }
+    if entry.definition.is_some() {
+        existing.definition = entry.definition;
+    }
+    existing.added_at = existing.added_at.min(entry.added_at);
+    existing.updated_at = existing.updated_at.max(entry.updated_at);
+}
+
+fn too_long(value: Option<&str>, max: usize, what: &str) -> Result<(), String> {
+    if value.is_some_and(|v| v.chars().count() > max) {
+        return Err(format!("The {} is longer than {} characters", what, max));
+    }
+    Ok(())
+}
+
+/// Collect a word, with the sentence and book it was looked up in. A word
+/// already collected gets the sentence added to its examples.
+#[tauri::command]
+pub fn add_vocabulary_entry(
+    state: State<'_, AppState>,
+    word: String,
+    definition: Option<String>,
+    sentence: Option<String>,
+    book_id: Option<String>,
+    cfi: Option<String>,
+) -> Result<VocabularyEntry, String> {
+    let word = word.trim().to_string();
+    if word.is_empty() {
+        return Err("No word to add".to_string());
+    }
+    too_long(Some(&word), MAX_WORD_CHARS, "word")?;
+    let definition = definition
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty());
+    too_long(definition.as_deref(), MAX_DEFINITION_CHARS, "definition")?;
+    let sentence = sentence
+        .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|s| !s.is_empty());
+    too_long(sentence.as_deref(), MAX_SENTENCE_CHARS, "sentence")?;
+
+    let book_title = match &book_id {
+        Some(id) => state.with_library(|library| {
+            library
+                .books
+                .iter()
+                .find(|b| b.id == *id)
+                .map(|b| b.title.clone())
+        })?,
+        None => None,
+    };
+    let now = Utc::now();
+    let entry = VocabularyEntry {
+        word: word.clone(),
+        definition,
+        examples: sentence
+            .map(|sentence| VocabularyExample {
+                sentence,
+                book_id,
+                book_title,
+                cfi,
+                added_at: now,
+            })
+            .into_iter()
+            .collect(),
+        added_at: now,
+        updated_at: now,
+    };
+
+    let path = state.paths()?.vocabulary.clone();
+    let _lock = VOCABULARY_LOCK
+        .lock()
+        .map_err(|_| "Vocabulary lock poisoned")?;
+    let mut entries = load_vocabulary(&path);
+    merge_entry(&mut entries, entry);
+    save_vocabulary(&path, &entries)?;
+    entries
+        .into_iter()
+        .find(|e| word_key(&e.word) == word_key(&word))
+        .ok_or_else(|| "Failed to add the word".to_string())
+}
+
+/// Collected words, newest first
+#[tauri::command]
+pub fn list_vocabulary(
+    state: State<'_, AppState>,
+    filter: Option<VocabularyFilter>,
+) -> Result<Vec<VocabularyEntry>, String> {
+    let filter = filter.unwrap_or_default();
+    let query = filter
+        .query
+        .map(|q| q.trim().to_lowercase())
+        .filter(|q| !q.is_empty());
+    let mut entries: Vec<VocabularyEntry> = load_vocabulary(&state.paths()?.vocabulary)
+        .into_iter()
+        .filter(|entry| {
+            query.as_ref().is_none_or(|q| {
+                entry.word.to_lowercase().contains(q)
+                    || entry
+                        .definition
+                        .as_ref()
+                        .is_some_and(|d| d.to_lowercase().contains(q))
+            })
+        })
+        .filter(|entry| {
+            filter.book_id.as_ref().is_none_or(|id| {
+                entry
+                    .examples
+                    .iter()
+                    .any(|e| e.book_id.as_ref() == Some(id))
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    Ok(entries)
+}
+
+#[tauri::command]
+pub fn remove_vocabulary_entry(state: State<'_, AppState>, word: String) -> Result<(), String> {
+    let path = state.paths()?.vocabulary.clone();
+    let _lock = VOCABULARY_LOCK
+        .lock()
+        .map_err(|_| "Vocabulary lock poisoned")?;
+    let mut entries = load_vocabulary(&path);
+    let before = entries.len();
+    entries.retain(|e| word_key(&e.word) != word_key(&word));
+    if entries.len() == before {
+        return Err(format!("'{}' is not in the vocabulary", word.trim()));
+    }
+    save_vocabulary(&path, &entries)
+}
+
+/// Write the vocabulary as CSV for importing into Anki: word, definition,
+/// example sentences and the books they are from. Several examples share a
+/// field, separated by line breaks Anki shows. Returns how many words were
+/// written.
+#[tauri::command]
+pub fn export_vocabulary_csv(state: State<'_, AppState>, dest: String) -> Result<usize, String> {
+    let entries = load_vocabulary(&state.paths()?.vocabulary);
+    let mut csv = String::new();
+    for entry in &entries {
+        let sentences: Vec<&str> = entry.examples.iter().map(|e| e.sentence.as_str()).collect();
+        let mut books: Vec<&str> = Vec::new();
+        for title in entry
+            .examples
+            .iter()
+            .filter_map(|e| e.book_title.as_deref())
+        {
+            if !books.contains(&title) {
+                books.push(title);
+            }
+        }
+        let row = [
+            entry.word.as_str(),
+            entry.definition.as_deref().unwrap_or_default(),
+            &sentences.join("<br>"),
+            &books.join("; "),
+        ]
+        .iter()
+        .map(|field| escape_csv(field))
+        .collect::<Vec<_>>()
+        .join(",");
+        csv.push_str(&row);
+        csv.push('\n');
+    }
+    fs::write(&dest, csv).map_err(|e| format!("Failed to write vocabulary export: {}", e))?;
+    Ok(entries.len())
+}
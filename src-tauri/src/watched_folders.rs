@@ -0,0 +1,140 @@
+/**
+ * Watched folders: EPUBs dropped into them are imported on their own.
+ * Books whose file leaves the folder stay in the library.
+ */
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Manager, State};
+
+use crate::advanced::AdvancedConfig;
+use crate::config;
+use crate::file_access;
+use crate::folder_import;
+use crate::library::{LibraryEvent, LibraryEventBatch};
+use crate::logging;
+use crate::meta;
+use crate::preferences;
+use crate::state::AppState;
+
+/// How often the folders are looked through; a poll works the same on
+/// every platform and network drives
+const SCAN_INTERVAL: Duration = Duration::from_secs(60);
+/// Files changed more recently than this may still be being copied and
+/// wait for the next scan
+const SETTLE_TIME: Duration = Duration::from_secs(10);
+
+fn is_settled(path: &Path) -> bool {
+    path.metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= SETTLE_TIME)
+}
+
+/// Import the EPUBs of the watched folders not taken in before
+fn scan(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let limits = app.state::<AdvancedConfig>();
+    let folders = state.preferences()?.watched_folders;
+    if folders.is_empty() {
+        return Ok(());
+    }
+    let mut files = Vec::new();
+    for folder in &folders {
+        folder_import::find_epubs(Path::new(folder), true, &mut files);
+    }
+    let seen = meta::load_meta().watched_files;
+    let known: Vec<String> = state
+        .with_library(|library| library.books.iter().map(|b| b.file_path.clone()).collect())?;
+
+    let mut taken = Vec::new();
+    let mut events = LibraryEventBatch::new(app);
+    for path in files {
+        let display = path.to_string_lossy().to_string();
+        if seen.contains(&display) || !is_settled(&path) {
+            continue;
+        }
+        if !known.contains(&display) {
+            match folder_import::import_file(&state, &limits, &path) {
+                Ok(event) => {
+                    if let LibraryEvent::Added(book) = &event {
+                        logging::info(&format!("Imported '{}' from a watched folder", book.title));
+                    }
+                    events.push(event);
+                }
+                // Not tried again, a broken file would fail every scan
+                Err(e) => logging::warn(&format!("Failed to import {}: {}", display, e)),
+            }
+        }
+        taken.push(display);
+    }
+
+    if !taken.is_empty() {
+        meta::update_meta(|m| m.watched_files.extend(taken))?;
+    }
+    Ok(())
+}
+
+/// Look through the watched folders at startup and every minute after
+pub fn watch(app: &AppHandle) {
+    if config::is_safe_mode() {
+        return;
+    }
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        if let Err(e) = scan(&app) {
+            logging::error(&format!("Failed to scan watched folders: {}", e));
+        }
+        std::thread::sleep(SCAN_INTERVAL);
+    });
+}
+
+fn save_folders(state: &AppState, folders: Vec<String>) -> Result<Vec<String>, String> {
+    let mut prefs = state.preferences()?;
+    prefs.watched_folders = folders.clone();
+    preferences::save_preferences(state, prefs)?;
+    Ok(folders)
+}
+
+/// Watch a folder picked with open_folder_dialog. EPUBs already in it are
+/// imported on the next scan.
+#[tauri::command]
+pub fn add_watched_folder(state: State<'_, AppState>, path: String) -> Result<Vec<String>, String> {
+    let folder: PathBuf = file_access::check_read_access(&state, &path)?;
+    if !folder.is_dir() {
+        return Err(format!("Not a folder: {}", path));
+    }
+    let folder = folder.to_string_lossy().to_string();
+    let mut folders = state.preferences()?.watched_folders;
+    if folders.contains(&folder) {
+        return Ok(folders);
+    }
+    folders.push(folder);
+    save_folders(&state, folders)
+}
+
+/// Stop watching a folder; its books stay in the library
+#[tauri::command]
+pub fn remove_watched_folder(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<Vec<String>, String> {
+    let mut folders = state.preferences()?.watched_folders;
+    let before = folders.len();
+    folders.retain(|f| f != &path);
+    if folders.len() == before {
+        return Err(format!("'{}' is not a watched folder", path));
+    }
+    let folders = save_folders(&state, folders)?;
+    // Files there count as new should the folder be watched again
+    meta::update_meta(|m| {
+        m.watched_files
+            .retain(|file| !Path::new(file).starts_with(&path))
+    })?;
+    Ok(folders)
+}
+
+#[tauri::command]
+pub fn list_watched_folders(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    Ok(state.preferences()?.watched_folders)
+}
@@ -0,0 +1,694 @@
+/**
+ * Library sync through a WebDAV folder (Nextcloud, ownCloud, ...).
+ * Only library state is synced, never the book files.
+ */
+use chrono::{DateTime, Utc};
+use reqwest::header::{ETAG, IF_MATCH, IF_NONE_MATCH};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::annotations::{self, BookAnnotations};
+use crate::config;
+use crate::error::AppError;
+use crate::library::{self, Library, LibraryEvent};
+use crate::logging;
+use crate::sessions::{self, ReadingSession};
+use crate::state::AppState;
+
+/// Passwords live in the OS keychain under this service
+const KEYRING_SERVICE: &str = "Epilogue WebDAV sync";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// Conditional writes retried this often when another machine wins the race
+const MAX_PUT_ATTEMPTS: usize = 3;
+/// Waits between retries while the server is unreachable
+const RETRY_DELAYS: &[Duration] = &[
+    Duration::from_secs(60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(15 * 60),
+    Duration::from_secs(60 * 60),
+];
+
+const LIBRARY_OBJECT: &str = "library.json";
+const SESSIONS_OBJECT: &str = "stats/sessions.jsonl";
+const COLLECTIONS: &[&str] = &["annotations", "stats"];
+
+static SYNCING: AtomicBool = AtomicBool::new(false);
+static RETRY_SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SyncSettings {
+    /// The folder to sync into, e.g. https://cloud.example.com/remote.php/dav/files/me/Epilogue
+    pub url: String,
+    pub username: String,
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// What we last wrote to or read from the server for one object
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SyncedObject {
+    etag: String,
+    hash: String,
+}
+
+/// sync.json: settings (without the password) and sync bookkeeping
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct SyncFile {
+    #[serde(default)]
+    settings: Option<SyncSettings>,
+    #[serde(default)]
+    objects: HashMap<String, SyncedObject>,
+    /// Local changes waiting for the server to come back
+    #[serde(rename = "pendingSince", default)]
+    pending_since: Option<DateTime<Utc>>,
+    #[serde(rename = "lastSync", default)]
+    last_sync: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SyncStatus {
+    pub settings: Option<SyncSettings>,
+    #[serde(rename = "hasPassword")]
+    pub has_password: bool,
+    #[serde(rename = "pendingSince")]
+    pub pending_since: Option<DateTime<Utc>>,
+    #[serde(rename = "lastSync")]
+    pub last_sync: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SyncSummary {
+    /// Objects that had changes from another machine merged in
+    pub pulled: Vec<String>,
+    /// Objects written to the server
+    pub pushed: Vec<String>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SyncValidation {
+    pub reachable: bool,
+    pub authenticated: bool,
+    pub message: String,
+}
+
+enum SyncError {
+    /// No connection or timed out; changes are kept and retried
+    Offline(String),
+    Unauthorized,
+    Failed(String),
+}
+
+impl From<String> for SyncError {
+    fn from(e: String) -> Self {
+        SyncError::Failed(e)
+    }
+}
+
+impl From<AppError> for SyncError {
+    fn from(e: AppError) -> Self {
+        SyncError::Failed(e.into())
+    }
+}
+
+impl From<SyncError> for String {
+    fn from(e: SyncError) -> Self {
+        match e {
+            SyncError::Offline(e) => format!(
+                "Sync server unreachable ({}); changes will be synced when it is back",
+                e
+            ),
+            SyncError::Unauthorized => {
+                "The sync server rejected the username or password".to_string()
+            }
+            SyncError::Failed(e) => e,
+        }
+    }
+}
+
+fn load_sync_file(state: &AppState) -> SyncFile {
+    let Ok(paths) = state.paths() else {
+        return SyncFile::default();
+    };
+    match fs::read_to_string(&paths.sync) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            logging::warn(&format!("Failed to parse sync.json, starting over: {}", e));
+            SyncFile::default()
+        }),
+        Err(_) => SyncFile::default(),
+    }
+}
+
+fn save_sync_file(state: &AppState, file: &SyncFile) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(file)
+        .map_err(|e| format!("Failed to serialize sync state: {}", e))?;
+    fs::write(&state.paths()?.sync, json).map_err(|e| format!("Failed to write sync state: {}", e))
+}
+
+fn keyring_entry(settings: &SyncSettings) -> Result<keyring::Entry, String> {
+    let account = format!("{}@{}", settings.username, settings.url);
+    keyring::Entry::new(KEYRING_SERVICE, &account)
+        .map_err(|e| format!("Failed to open the system keychain: {}", e))
+}
+
+fn stored_password(settings: &SyncSettings) -> Option<String> {
+    keyring_entry(settings).ok()?.get_password().ok()
+}
+
+fn validate_url(url: &str) -> Result<(), String> {
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(format!(
+            "Sync URL must start with https:// or http://, got {}",
+            url
+        ));
+    }
+    Ok(())
+}
+
+fn hash(content: &[u8]) -> String {
+    format!("{:x}", md5::compute(content))
+}
+
+fn send_error(e: reqwest::Error) -> SyncError {
+    if e.is_connect() || e.is_timeout() {
+        SyncError::Offline(e.to_string())
+    } else {
+        SyncError::Failed(format!("Sync request failed: {}", e))
+    }
+}
+
+fn etag_of(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// A WebDAV folder and the credentials to reach it
+struct Remote {
+    client: Client,
+    base: String,
+    username: String,
+    password: String,
+}
+
+impl Remote {
+    fn new(settings: &SyncSettings, password: String) -> Result<Self, String> {
+        validate_url(&settings.url)?;
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("Epilogue/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        Ok(Self {
+            client,
+            base: settings.url.trim_end_matches('/').to_string(),
+            username: settings.username.clone(),
+            password,
+        })
+    }
+
+    fn request(&self, method: Method, name: &str) -> RequestBuilder {
+        let url = if name.is_empty() {
+            format!("{}/", self.base)
+        } else {
+            format!("{}/{}", self.base, name)
+        };
+        self.client
+            .request(method, url)
+            .basic_auth(&self.username, Some(&self.password))
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, SyncError> {
+        let response = request.send().await.map_err(send_error)?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(SyncError::Unauthorized),
+            _ => Ok(response),
+        }
+    }
+
+    /// The object's content and ETag, or None if it doesn't exist yet
+    async fn get(&self, name: &str) -> Result<Option<(Vec<u8>, String)>, SyncError> {
+        let response = self.send(self.request(Method::GET, name)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(SyncError::Failed(format!(
+                "Failed to download {}: HTTP {}",
+                name,
+                response.status()
+            )));
+        }
+
+        let etag = etag_of(&response).unwrap_or_default();
+        let body = response.bytes().await.map_err(send_error)?;
+        Ok(Some((body.to_vec(), etag)))
+    }
+
+    /// Write an object only if the server still has the version we merged
+    /// with (`etag`), or has none at all. Returns the new ETag, or None when
+    /// someone else wrote it first.
+    async fn put(
+        &self,
+        name: &str,
+        content: Vec<u8>,
+        etag: Option<&str>,
+    ) -> Result<Option<String>, SyncError> {
+        let request = match etag {
+            Some(etag) => self.request(Method::PUT, name).header(IF_MATCH, etag),
+            None => self.request(Method::PUT, name).header(IF_NONE_MATCH, "*"),
+        };
+        let response = self.send(request.body(content)).await?;
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(SyncError::Failed(format!(
+                "Failed to upload {}: HTTP {}",
+                name,
+                response.status()
+            )));
+        }
+
+        // Not every server returns the ETag of a PUT
+        match etag_of(&response) {
+            Some(etag) => Ok(Some(etag)),
+            None => {
+                let head = self.send(self.request(Method::HEAD, name)).await?;
+                Ok(Some(etag_of(&head).unwrap_or_default()))
+            }
+        }
+    }
+
+    /// Create the sub-folders we write into; existing ones are fine
+    async fn ensure_collections(&self) -> Result<(), SyncError> {
+        for name in COLLECTIONS {
+            let mkcol = Method::from_bytes(b"MKCOL").expect("valid method");
+            let response = self.send(self.request(mkcol, name)).await?;
+            let status = response.status();
+            if !status.is_success() && status != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(SyncError::Failed(format!(
+                    "Failed to create {} on the server: HTTP {}",
+                    name, status
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bring one object in sync: merge in the server's copy if it changed since
+/// we last saw it, then write the result back guarded by its ETag. Returns
+/// (pulled, pushed).
+async fn sync_object(
+    remote: &Remote,
+    objects: &mut HashMap<String, SyncedObject>,
+    name: &str,
+    local: impl Fn() -> Result<Option<Vec<u8>>, String>,
+    mut merge_remote: impl FnMut(&[u8]) -> Result<Vec<u8>, String>,
+) -> Result<(bool, bool), SyncError> {
+    let mut pulled = false;
+
+    for _ in 0..MAX_PUT_ATTEMPTS {
+        let server = remote.get(name).await?;
+        let known = objects.get(name);
+
+        let (content, server_etag, server_hash) = match server {
+            Some((bytes, etag)) if known.is_none_or(|k| k.etag != etag) => {
+                pulled = true;
+                (Some(merge_remote(&bytes)?), Some(etag), Some(hash(&bytes)))
+            }
+            Some((bytes, etag)) => (local()?, Some(etag), Some(hash(&bytes))),
+            None => (local()?, None, None),
+        };
+        let Some(content) = content else {
+            return Ok((pulled, false));
+        };
+
+        let content_hash = hash(&content);
+        if let Some(etag) = &server_etag {
+            // The server already has exactly this
+            if server_hash.as_deref() == Some(content_hash.as_str()) {
+                objects.insert(
+                    name.to_string(),
+                    SyncedObject {
+                        etag: etag.clone(),
+                        hash: content_hash,
+                    },
+                );
+                return Ok((pulled, false));
+            }
+        }
+
+        if let Some(etag) = remote.put(name, content, server_etag.as_deref()).await? {
+            objects.insert(
+                name.to_string(),
+                SyncedObject {
+                    etag,
+                    hash: content_hash,
+                },
+            );
+            return Ok((pulled, true));
+        }
+        logging::debug(&format!(
+            "{} changed on the server while syncing, merging again",
+            name
+        ));
+    }
+
+    Err(SyncError::Failed(format!(
+        "{} kept changing on the server, try again later",
+        name
+    )))
+}
+
+fn read_local(path: &std::path::Path) -> Result<Option<Vec<u8>>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read(path)
+        .map(Some)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+fn record(summary: &mut SyncSummary, name: &str, (pulled, pushed): (bool, bool)) {
+    if pulled {
+        summary.pulled.push(name.to_string());
+    }
+    if pushed {
+        summary.pushed.push(name.to_string());
+    }
+}
+
+async fn sync_all(
+    app: &AppHandle,
+    remote: &Remote,
+    objects: &mut HashMap<String, SyncedObject>,
+) -> Result<SyncSummary, SyncError> {
+    let state = app.state::<AppState>();
+    let mut summary = SyncSummary::default();
+
+    remote.ensure_collections().await?;
+
+    // Library: book entries are merged with the same rules as a backup restore
+    state.flush_library()?;
+    let mut library_changed = false;
+    let result = sync_object(
+        remote,
+        objects,
+        LIBRARY_OBJECT,
+        || {
+            state
+                .with_library(serde_json::to_vec_pretty)?
+                .map(Some)
+                .map_err(|e| format!("Failed to serialize library: {}", e))
+        },
+        |bytes| {
+            let incoming: Library = serde_json::from_slice(bytes)
+                .map_err(|e| format!("Failed to parse the synced library: {}", e))?;
+            state.update_library(|library| {
+                let merged = library::merge_libraries(library, incoming);
+                library_changed |= merged.added + merged.updated > 0;
+                serde_json::to_vec_pretty(library)
+                    .map_err(|e| format!("Failed to serialize library: {}", e))
+            })
+        },
+    )
+    .await?;
+    record(&mut summary, LIBRARY_OBJECT, result);
+    if library_changed {
+        library::emit_library_event(app, LibraryEvent::Reloaded);
+    }
+
+    // Annotations of every book we know of after the merge
+    let book_ids = state.with_library(|library| {
+        library
+            .books
+            .iter()
+            .map(|b| b.id.clone())
+            .collect::<Vec<_>>()
+    })?;
+    let annotations_dir = state.paths()?.annotations.clone();
+    for book_id in book_ids {
+        let name = format!("annotations/{}.json", book_id);
+        let path = annotations_dir.join(format!("{}.json", book_id));
+        let result = sync_object(
+            remote,
+            objects,
+            &name,
+            || read_local(&path),
+            |bytes| {
+                let incoming: BookAnnotations = serde_json::from_slice(bytes)
+                    .map_err(|e| format!("Failed to parse synced annotations: {}", e))?;
+                let mut local = annotations::load_annotations(&state, &book_id)?;
+                annotations::merge_annotations(&mut local, incoming);
+                annotations::save_annotations(&state, &local)?;
+                serde_json::to_vec_pretty(&local)
+                    .map_err(|e| format!("Failed to serialize annotations: {}", e))
+            },
+        )
+        .await?;
+        record(&mut summary, &name, result);
+    }
+
+    // Reading stats
+    let sessions_path = state.paths()?.sessions.clone();
+    let result = sync_object(
+        remote,
+        objects,
+        SESSIONS_OBJECT,
+        || read_local(&sessions_path),
+        |bytes| {
+            let incoming: Vec<ReadingSession> = String::from_utf8_lossy(bytes)
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect();
+            sessions::merge_sessions(&state, incoming)?;
+            Ok(read_local(&sessions_path)?.unwrap_or_default())
+        },
+    )
+    .await?;
+    record(&mut summary, SESSIONS_OBJECT, result);
+
+    summary.finished_at = Some(Utc::now());
+    Ok(summary)
+}
+
+async fn run_sync(app: &AppHandle) -> Result<SyncSummary, SyncError> {
+    // Safe mode must not write anything pulled from the server
+    if config::is_safe_mode() {
+        return Err(SyncError::Failed(config::safe_mode_error().to_string()));
+    }
+    if SYNCING.swap(true, Ordering::SeqCst) {
+        return Err(SyncError::Failed("A sync is already running".to_string()));
+    }
+
+    let result = async {
+        let state = app.state::<AppState>();
+        let mut file = load_sync_file(&state);
+        let settings = file
+            .settings
+            .clone()
+            .filter(|s| s.enabled)
+            .ok_or_else(|| SyncError::Failed("Sync is not set up".to_string()))?;
+        let password = stored_password(&settings).ok_or(SyncError::Unauthorized)?;
+        let remote = Remote::new(&settings, password)?;
+
+        let result = sync_all(app, &remote, &mut file.objects).await;
+        match &result {
+            Ok(_) => {
+                file.pending_since = None;
+                file.last_sync = Some(Utc::now());
+            }
+            Err(SyncError::Offline(_)) => {
+                file.pending_since.get_or_insert_with(Utc::now);
+            }
+            Err(_) => {}
+        }
+        save_sync_file(&state, &file)?;
+        result
+    }
+    .await;
+
+    SYNCING.store(false, Ordering::SeqCst);
+    result
+}
+
+/// Keep retrying in the background while the server is unreachable
+fn schedule_retry(app: &AppHandle) {
+    if RETRY_SCHEDULED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        for delay in RETRY_DELAYS.iter().cycle() {
+            std::thread::sleep(*delay);
+            match tauri::async_runtime::block_on(run_sync(&app)) {
+                Ok(summary) => {
+                    logging::info("Synced queued changes");
+                    let _ = app.emit("sync-completed", summary);
+                    break;
+                }
+                Err(SyncError::Offline(_)) => continue,
+                Err(e) => {
+                    logging::warn(&format!("Sync retry failed: {}", String::from(e)));
+                    break;
+                }
+            }
+        }
+        RETRY_SCHEDULED.store(false, Ordering::SeqCst);
+    });
+}
+
+async fn sync_and_report(app: &AppHandle) -> Result<SyncSummary, String> {
+    match run_sync(app).await {
+        Ok(summary) => {
+            let _ = app.emit("sync-completed", summary.clone());
+            Ok(summary)
+        }
+        Err(e) => {
+            if matches!(e, SyncError::Offline(_)) {
+                schedule_retry(app);
+            }
+            Err(e.into())
+        }
+    }
+}
+
+/// Pull and merge once at startup when sync is set up
+pub fn sync_on_startup(app: &AppHandle) {
+    let file = load_sync_file(&app.state::<AppState>());
+    if config::is_safe_mode() || !file.settings.is_some_and(|s| s.enabled) {
+        return;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = sync_and_report(&app).await {
+            logging::warn(&format!("Startup sync failed: {}", e));
+        }
+    });
+}
+
+/// Current sync settings and state
+#[tauri::command]
+pub fn get_sync_settings(state: State<'_, AppState>) -> Result<SyncStatus, String> {
+    let file = load_sync_file(&state);
+    Ok(SyncStatus {
+        has_password: file
+            .settings
+            .as_ref()
+            .is_some_and(|s| stored_password(s).is_some()),
+        settings: file.settings,
+        pending_since: file.pending_since,
+        last_sync: file.last_sync,
+    })
+}
+
+/// Save sync settings. The password goes to the system keychain; leave it
+/// out to keep the stored one. An empty URL turns sync off and forgets
+/// the password.
+#[tauri::command]
+pub fn set_sync_settings(
+    state: State<'_, AppState>,
+    settings: SyncSettings,
+    password: Option<String>,
+) -> Result<SyncStatus, String> {
+    let mut file = load_sync_file(&state);
+
+    if settings.url.trim().is_empty() {
+        if let Some(old) = file.settings.take() {
+            if let Err(e) = keyring_entry(&old).and_then(|entry| {
+                entry
+                    .delete_credential()
+                    .map_err(|e| format!("Failed to remove sync password: {}", e))
+            }) {
+                logging::warn(&e);
+            }
+        }
+        file.objects.clear();
+        file.pending_since = None;
+    } else {
+        validate_url(&settings.url)?;
+        if let Some(password) = password {
+            keyring_entry(&settings)?
+                .set_password(&password)
+                .map_err(|e| format!("Failed to store sync password: {}", e))?;
+        }
+        // A different folder starts from scratch
+        if file
+            .settings
+            .as_ref()
+            .is_some_and(|old| old.url != settings.url)
+        {
+            file.objects.clear();
+        }
+        file.settings = Some(settings);
+    }
+
+    save_sync_file(&state, &file)?;
+    get_sync_settings(state)
+}
+
+/// Check that the server answers and accepts the credentials. Uses the
+/// stored password when none is given.
+#[tauri::command]
+pub async fn validate_sync_settings(
+    settings: SyncSettings,
+    password: Option<String>,
+) -> Result<SyncValidation, String> {
+    let Some(password) = password.or_else(|| stored_password(&settings)) else {
+        return Ok(SyncValidation {
+            reachable: false,
+            authenticated: false,
+            message: "No password set".to_string(),
+        });
+    };
+    let remote = Remote::new(&settings, password)?;
+
+    let propfind = Method::from_bytes(b"PROPFIND").expect("valid method");
+    let request = remote.request(propfind, "").header("Depth", "0");
+    let validation = match remote.send(request).await {
+        Ok(response) if response.status() == StatusCode::MULTI_STATUS => SyncValidation {
+            reachable: true,
+            authenticated: true,
+            message: "Connected".to_string(),
+        },
+        Ok(response) if response.status() == StatusCode::NOT_FOUND => SyncValidation {
+            reachable: true,
+            authenticated: true,
+            message: "The folder doesn't exist on the server".to_string(),
+        },
+        Ok(response) => SyncValidation {
+            reachable: true,
+            authenticated: false,
+            message: format!("Not a WebDAV folder (HTTP {})", response.status()),
+        },
+        Err(SyncError::Unauthorized) => SyncValidation {
+            reachable: true,
+            authenticated: false,
+            message: String::from(SyncError::Unauthorized),
+        },
+        Err(e) => SyncValidation {
+            reachable: false,
+            authenticated: false,
+            message: e.into(),
+        },
+    };
+    Ok(validation)
+}
+
+/// Pull, merge and push now
+#[tauri::command]
+pub async fn sync_now(app: AppHandle) -> Result<SyncSummary, String> {
+    sync_and_report(&app).await
+}
@@ -0,0 +1,365 @@
+/**
+ * Remember each window's size, position and maximized/fullscreen state
+ */
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tauri::{Monitor, PhysicalPosition, PhysicalSize, WebviewWindow, Window, WindowEvent};
+
+use crate::config;
+use crate::fullscreen;
+use crate::logging;
+
+/// Moves and resizes arrive in bursts; only save once they settle
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How much of the window, across and down, must be on a monitor for it to
+/// count as reachable
+const MIN_VISIBLE_PX: i64 = 100;
+
+static SAVE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Geometry in physical pixels. Size and position are the last *normal*
+/// (non-maximized, non-fullscreen) bounds so un-maximizing restores them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WindowState {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub maximized: bool,
+    #[serde(default)]
+    pub fullscreen: bool,
+    /// Window decorations hidden for distraction-free reading
+    #[serde(default)]
+    pub immersive: bool,
+    /// Name of the monitor the window was on
+    #[serde(default)]
+    pub monitor: Option<String>,
+}
+
+/// A monitor's bounds, in physical pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorArea {
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<&Monitor> for MonitorArea {
+    fn from(monitor: &Monitor) -> Self {
+        Self {
+            name: monitor.name().cloned(),
+            x: monitor.position().x,
+            y: monitor.position().y,
+            width: monitor.size().width,
+            height: monitor.size().height,
+        }
+    }
+}
+
+fn window_state_path() -> Result<PathBuf, String> {
+    Ok(config::get_app_dir_path()?.join("window-state.json"))
+}
+
+/// Saved state of every window, by label. Older versions stored only the
+/// main window's state at the top level.
+fn load_all() -> HashMap<String, WindowState> {
+    let Some(content) = window_state_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+    else {
+        return HashMap::new();
+    };
+
+    serde_json::from_str(&content)
+        .or_else(|_| {
+            serde_json::from_str::<WindowState>(&content)
+                .map(|main| HashMap::from([("main".to_string(), main)]))
+        })
+        .unwrap_or_default()
+}
+
+fn load_window_state(label: &str) -> Option<WindowState> {
+    load_all().remove(label)
+}
+
+fn save_window_state(label: &str, state: &WindowState) -> Result<(), String> {
+    let mut all = load_all();
+    all.insert(label.to_string(), state.clone());
+
+    let json = serde_json::to_string_pretty(&all)
+        .map_err(|e| format!("Failed to serialize window state: {}", e))?;
+    fs::write(window_state_path()?, json).map_err(|e| format!("Failed to save window state: {}", e))
+}
+
+/// Width and height of the part of the window on a monitor
+fn overlap(state: &WindowState, monitor: &MonitorArea) -> (i64, i64) {
+    let left = (state.x as i64).max(monitor.x as i64);
+    let top = (state.y as i64).max(monitor.y as i64);
+    let right = (state.x as i64 + state.width as i64).min(monitor.x as i64 + monitor.width as i64);
+    let bottom =
+        (state.y as i64 + state.height as i64).min(monitor.y as i64 + monitor.height as i64);
+
+    ((right - left).max(0), (bottom - top).max(0))
+}
+
+/// Keep a saved window reachable on the monitors that exist now. If it is
+/// no longer meaningfully visible on any of them (e.g. its display was
+/// disconnected), it is shrunk to fit and centered on its old monitor if
+/// that is still connected, otherwise on the first (primary) monitor.
+pub fn clamp_to_monitors(state: &WindowState, monitors: &[MonitorArea]) -> WindowState {
+    let mut clamped = state.clone();
+
+    let min_width = MIN_VISIBLE_PX.min(state.width as i64);
+    let min_height = MIN_VISIBLE_PX.min(state.height as i64);
+    let reachable = |m: &MonitorArea| {
+        let (width, height) = overlap(state, m);
+        width >= min_width && height >= min_height
+    };
+    if monitors.is_empty() || monitors.iter().any(reachable) {
+        return clamped;
+    }
+
+    let target = monitors
+        .iter()
+        .find(|m| m.name.is_some() && m.name == state.monitor)
+        .unwrap_or(&monitors[0]);
+
+    clamped.width = state.width.min(target.width);
+    clamped.height = state.height.min(target.height);
+    clamped.x = target.x + ((target.width - clamped.width) / 2) as i32;
+    clamped.y = target.y + ((target.height - clamped.height) / 2) as i32;
+    clamped.monitor = target.name.clone();
+
+    clamped
+}
+
+/// Apply the saved geometry to a window that is still hidden
+pub fn restore(window: &WebviewWindow) {
+    let Some(saved) = load_window_state(window.label()) else {
+        return;
+    };
+
+    let mut monitors: Vec<MonitorArea> = Vec::new();
+    if let Ok(Some(primary)) = window.primary_monitor() {
+        monitors.push(MonitorArea::from(&primary));
+    }
+    for monitor in window.available_monitors().unwrap_or_default() {
+        let area = MonitorArea::from(&monitor);
+        if !monitors.contains(&area) {
+            monitors.push(area);
+        }
+    }
+
+    let state = clamp_to_monitors(&saved, &monitors);
+
+    let _ = window.set_size(PhysicalSize::new(state.width, state.height));
+    let _ = window.set_position(PhysicalPosition::new(state.x, state.y));
+    if state.maximized {
+        let _ = window.maximize();
+    }
+    if state.fullscreen || state.immersive {
+        let _ = fullscreen::apply(window, state.fullscreen, state.immersive);
+    }
+}
+
+/// Whether the window was last put in immersive mode
+pub fn is_immersive(label: &str) -> bool {
+    load_window_state(label).is_some_and(|s| s.immersive)
+}
+
+/// Remember a window's fullscreen and immersive flags, keeping its bounds
+pub fn save_fullscreen(window: &WebviewWindow, fullscreen: bool, immersive: bool) {
+    let state = load_window_state(window.label()).or_else(|| {
+        let position = window.outer_position().ok()?;
+        let size = window.inner_size().ok()?;
+        Some(WindowState {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+            maximized: false,
+            fullscreen: false,
+            immersive: false,
+            monitor: None,
+        })
+    });
+
+    if let Some(mut state) = state {
+        state.fullscreen = fullscreen;
+        state.immersive = immersive;
+        if let Err(e) = save_window_state(window.label(), &state) {
+            logging::warn(&e);
+        }
+    }
+}
+
+/// Read the window's current geometry, keeping the previous normal bounds
+/// while it is maximized, fullscreen or minimized
+fn capture(window: &Window) -> Option<WindowState> {
+    let maximized = window.is_maximized().unwrap_or(false);
+    let fullscreen = window.is_fullscreen().unwrap_or(false);
+    let minimized = window.is_minimized().unwrap_or(false);
+    let monitor = window
+        .current_monitor()
+        .ok()
+        .flatten()
+        .and_then(|m| m.name().cloned());
+
+    let previous = load_window_state(window.label());
+    let immersive = previous.as_ref().is_some_and(|p| p.immersive);
+
+    let mut state = match previous {
+        Some(previous) if maximized || fullscreen || minimized => previous,
+        _ => {
+            if minimized {
+                return None;
+            }
+            let position = window.outer_position().ok()?;
+            let size = window.inner_size().ok()?;
+            WindowState {
+                x: position.x,
+                y: position.y,
+                width: size.width,
+                height: size.height,
+                maximized: false,
+                fullscreen: false,
+                immersive,
+                monitor: None,
+            }
+        }
+    };
+
+    if !minimized {
+        state.maximized = maximized;
+        state.fullscreen = fullscreen;
+        state.monitor = monitor.or(state.monitor);
+    }
+
+    Some(state)
+}
+
+fn save_now(window: &Window) {
+    if let Some(state) = capture(window) {
+        if let Err(e) = save_window_state(window.label(), &state) {
+            logging::warn(&e);
+        }
+    }
+}
+
+/// Window event hook: debounced save on move/resize, immediate save on close
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if window.label() != "main" {
+        return;
+    }
+
+    match event {
+        WindowEvent::Moved(_) | WindowEvent::Resized(_) => {
+            let generation = SAVE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+            let window = window.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(SAVE_DEBOUNCE);
+                if SAVE_GENERATION.load(Ordering::SeqCst) == generation {
+                    save_now(&window);
+                }
+            });
+        }
+        WindowEvent::CloseRequested { .. } => {
+            // Cancel any pending debounced save; this one is final
+            SAVE_GENERATION.fetch_add(1, Ordering::SeqCst);
+            save_now(window);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32, y: i32, width: u32, height: u32) -> MonitorArea {
+        MonitorArea {
+            name: Some(name.to_string()),
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn window(x: i32, y: i32, width: u32, height: u32, monitor: Option<&str>) -> WindowState {
+        WindowState {
+            x,
+            y,
+            width,
+            height,
+            maximized: false,
+            fullscreen: false,
+            immersive: false,
+            monitor: monitor.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn visible_window_is_left_alone() {
+        let monitors = [monitor("A", 0, 0, 1920, 1080)];
+        let saved = window(100, 100, 800, 600, Some("A"));
+        assert_eq!(clamp_to_monitors(&saved, &monitors), saved);
+    }
+
+    #[test]
+    fn partially_off_screen_window_stays_while_reachable() {
+        let monitors = [monitor("A", 0, 0, 1920, 1080)];
+
+        // 200x200 of it still on screen
+        let reachable = window(1720, 880, 800, 600, Some("A"));
+        assert_eq!(clamp_to_monitors(&reachable, &monitors), reachable);
+
+        // Only a 50px sliver left: moved back to the middle of the monitor
+        let sliver = window(1870, 100, 800, 600, Some("A"));
+        let clamped = clamp_to_monitors(&sliver, &monitors);
+        assert_eq!((clamped.x, clamped.y), (560, 240));
+        assert_eq!((clamped.width, clamped.height), (800, 600));
+    }
+
+    #[test]
+    fn window_on_disconnected_monitor_moves_to_primary() {
+        let monitors = [
+            monitor("Primary", 0, 0, 1280, 720),
+            monitor("Side", -1920, 0, 1920, 1080),
+        ];
+        let saved = window(3000, 200, 1600, 900, Some("External"));
+        let clamped = clamp_to_monitors(&saved, &monitors);
+
+        assert_eq!((clamped.width, clamped.height), (1280, 720));
+        assert_eq!((clamped.x, clamped.y), (0, 0));
+        assert_eq!(clamped.monitor.as_deref(), Some("Primary"));
+    }
+
+    #[test]
+    fn unreachable_window_prefers_its_old_monitor() {
+        let monitors = [
+            monitor("Primary", 0, 0, 1920, 1080),
+            monitor("Side", 1920, 0, 1280, 1024),
+        ];
+        // The side monitor's resolution dropped and left the window off it
+        let saved = window(3300, 1100, 640, 480, Some("Side"));
+        let clamped = clamp_to_monitors(&saved, &monitors);
+
+        assert_eq!((clamped.x, clamped.y), (1920 + 320, 272));
+        assert_eq!(clamped.monitor.as_deref(), Some("Side"));
+    }
+
+    #[test]
+    fn no_monitors_keeps_saved_geometry() {
+        let saved = window(-5000, -5000, 800, 600, None);
+        assert_eq!(clamp_to_monitors(&saved, &[]), saved);
+    }
+}
@@ -81,15 +81,17 @@ export class LibraryManager {
      * @param {string} bookId - Book ID
      * @param {string} cfi - Current location CFI
      * @param {number} percentage - Progress percentage (0-1)
+     * @param {string} [mode] - Reading mode the position was recorded in
      */
-    async updateProgress(bookId, cfi, percentage) {
+    async updateProgress(bookId, cfi, percentage, mode) {
         if (!isTauri || !bookId) return;
 
         try {
             await invoke('update_progress', {
                 bookId: bookId,
                 progress: percentage,
-                cfi: cfi
+                cfi: cfi,
+                view: mode ? { mode } : null
             });
         } catch (error) {
             console.error('Failed to update progress:', error);
@@ -99,19 +101,53 @@ export class LibraryManager {
     /**
      * Get saved progress for a book
      * @param {string} bookId - Book ID
+     * @param {string} [mode] - Reading mode to resume in
      * @returns {Promise<string|null>} Saved CFI
      */
-    async getBookProgress(bookId) {
+    async getBookProgress(bookId, mode) {
         if (!isTauri) return null;
 
         try {
-            return await invoke('get_book_progress', { bookId });
+            const position = await invoke('get_book_progress', { bookId, mode: mode || null });
+            return position ? position.cfi : null;
         } catch (error) {
             console.error('Failed to get progress:', error);
             return null;
         }
     }
 
+    /**
+     * Save a book's view state (zoom, sidebar, search, chapter list scroll).
+     * Writes are debounced on the Rust side, so this can follow navigation.
+     * @param {string} bookId - Book ID
+     * @param {object} state - { zoom, tocOpen, searchQuery, tocScroll }
+     */
+    async saveViewState(bookId, state) {
+        if (!isTauri || !bookId) return;
+
+        try {
+            await invoke('save_view_state', { bookId, stateJson: JSON.stringify(state) });
+        } catch (error) {
+            console.error('Failed to save view state:', error);
+        }
+    }
+
+    /**
+     * Get a book's saved view state
+     * @param {string} bookId - Book ID
+     * @returns {Promise<object|null>} Saved view state
+     */
+    async getViewState(bookId) {
+        if (!isTauri) return null;
+
+        try {
+            return await invoke('get_view_state', { bookId });
+        } catch (error) {
+            console.error('Failed to get view state:', error);
+            return null;
+        }
+    }
+
     /**
      * Remove a book from the library
      * @param {string} bookId - Book ID to remove
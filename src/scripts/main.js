@@ -12,6 +12,7 @@ import { readFile } from '@tauri-apps/plugin-fs';
 
 // Tauri API imports
 let invoke;
+let listen;
 let appWindow;
 let isTauri = false;
 
@@ -19,6 +20,9 @@ try {
     const tauriCore = await import('@tauri-apps/api/core');
     invoke = tauriCore.invoke;
 
+    const tauriEvent = await import('@tauri-apps/api/event');
+    listen = tauriEvent.listen;
+
     const tauriWindow = await import('@tauri-apps/api/window');
     appWindow = tauriWindow.getCurrentWindow();
 
@@ -198,9 +202,110 @@ async function init() {
         await openBookFromFile(path);
     });
 
+    if (isTauri) {
+        await setupBackendListeners();
+    }
+
     showToast('Epilogue ready', 'info');
 }
 
+/**
+ * Listen for events sent by the backend, e.g. books opened from outside the app
+ */
+async function setupBackendListeners() {
+    // A book passed on the command line or opened with Epilogue
+    await listen('open-file', async (event) => {
+        await openBookFromFile(event.payload.filePath);
+    });
+
+    await listen('open-file-error', (event) => {
+        const name = event.payload.path.split(/[\\/]/).pop();
+        showToast(`Cannot open ${name}: ${event.payload.reason}`, 'error');
+    });
+
+    // epilogue:// links, validated by the backend
+    await listen('deep-link', async (event) => {
+        const link = event.payload;
+        if (link.kind === 'openBook') {
+            await openBookFromLink(link.bookId, link.cfi);
+        } else if (link.kind === 'importPreset') {
+            await importPresetCode(link.code);
+        }
+    });
+
+    await listen('deep-link-error', (event) => {
+        showToast(`Cannot open link: ${event.payload.reason}`, 'error');
+    });
+
+    // The data folder couldn't be used; in safe mode nothing is kept
+    await listen('app-dir-unavailable', (event) => {
+        const { path, reason, safeMode } = event.payload;
+        if (safeMode) {
+            showToast(`Data folder unusable (${reason}). Running from a temporary folder; changes won't be kept.`, 'error');
+        } else {
+            showToast(`The data folder ${path} is not usable: ${reason}`, 'error');
+        }
+    });
+
+    // Problems in advanced.toml; the settings fall back to their defaults
+    await listen('advanced-config-warnings', (event) => {
+        for (const warning of event.payload) {
+            console.warn(warning);
+        }
+        showToast(`advanced.toml: ${event.payload.length} setting(s) ignored, see the log`, 'error');
+    });
+
+
+    await listen('builtin-assets-updated', async (event) => {
+        renderPresetList(await presetManager.loadPresets());
+        const { preserved } = event.payload;
+        if (preserved.length > 0) {
+            showToast(`Kept your changes to ${preserved.length} built-in preset(s); the new versions were saved alongside`, 'info');
+        }
+    });
+}
+
+/**
+ * Open a library book named by a deep link, at the linked position if any
+ */
+async function openBookFromLink(bookId, cfi) {
+    try {
+        const books = await invoke('get_books');
+        const book = books.find(b => b.id === bookId);
+        if (!book) {
+            showToast('Book not found in library', 'error');
+            return;
+        }
+        await openBookFromFile(book.filePath);
+        if (cfi && reader.rendition) {
+            await reader.rendition.display(cfi);
+        }
+    } catch (error) {
+        console.error('Failed to open linked book:', error);
+        showToast('Failed to open linked book', 'error');
+    }
+}
+
+/**
+ * Save a preset shared as a code (URL-safe base64 of the preset JSON)
+ */
+async function importPresetCode(code) {
+    try {
+        const base64 = code.replace(/-/g, '+').replace(/_/g, '/');
+        const bytes = Uint8Array.from(atob(base64), c => c.charCodeAt(0));
+        const preset = JSON.parse(new TextDecoder().decode(bytes));
+        await invoke('save_custom_preset', {
+            name: preset.name,
+            presetJson: JSON.stringify(preset)
+        });
+        renderPresetList(await presetManager.loadPresets());
+        showToast(`Imported preset: ${preset.name}`, 'success');
+    } catch (error) {
+        console.error('Failed to import preset:', error);
+        showToast('Failed to import preset', 'error');
+    }
+}
+
 /**
  * Render preset list in panel
  */
@@ -343,7 +448,10 @@ async function openBookFromFile(filePath) {
             currentBookId = book.id;
 
             // Check for saved progress
-            const savedCfi = await libraryManager.getBookProgress(book.id);
+            const savedCfi = await libraryManager.getBookProgress(
+                book.id,
+                currentPrefs.readingMode || 'paginated'
+            );
             if (savedCfi) {
                 try {
                     await reader.rendition.display(savedCfi);
@@ -363,7 +471,8 @@ async function openBookFromFile(filePath) {
                         await libraryManager.updateProgress(
                             currentBookId,
                             location.start.cfi,
-                            progress || 0
+                            progress || 0,
+                            currentPrefs.readingMode || 'paginated'
                         );
                     }
                 }, 2000);
@@ -1059,5 +1168,21 @@ window.addEventListener('beforeunload', () => {
     presetManager.saveLastSession(currentPrefs);
 });
 
-// Start the application
-init();
+/**
+ * Tell the backend the first screen is up: the main window starts hidden and
+ * is shown by this call, and events from the backend are held until it
+ */
+async function signalReady() {
+    if (!isTauri) return;
+    try {
+        await invoke('frontend_ready');
+    } catch (error) {
+        console.error('Failed to signal frontend ready:', error);
+    }
+}
+
+// Start the application. The window is shown even if init fails part way,
+// rather than staying hidden until the backend's timeout.
+init()
+    .catch(error => console.error('Failed to initialize:', error))
+    .finally(signalReady);